@@ -0,0 +1,209 @@
+//! Benchmarks for the statistics and aggregation run on every sample, and
+//! for discarding downloaded bodies.
+//!
+//! Only the hot paths are exercised, through the library the
+//! `cloud-speed` binary wraps.
+//!
+//! ```bash
+//! cargo bench --bench hot_paths
+//! cargo bench --bench hot_paths -- --save-baseline before
+//! cargo bench --bench hot_paths -- --baseline before
+//! ```
+
+use cloud_speed::measurements::{
+    aggregate_bandwidth, jitter_f64, BandwidthMeasurement,
+};
+use cloud_speed::stats::percentile_f64;
+use cloud_speed::tui::progress::{
+    BandwidthDirection, ProgressEvent, TestPhase,
+};
+use cloud_speed::tui::state::TuiState;
+use cloud_speed::DiscardSink;
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId,
+    Criterion, Throughput,
+};
+use std::io::{self, Read, Write};
+
+/// Size of the buffer downloaded bodies are drained through.
+const DRAIN_BUFFER_BYTES: usize = 64 * 1024;
+
+/// Body size discarded per iteration.
+const BODY_BYTES: usize = 64 * 1024 * 1024;
+
+/// Sample counts seen in practice: a default run, a `--detailed` run
+/// and a long `--repeat` session.
+const SAMPLE_COUNTS: [usize; 3] = [20, 200, 2_000];
+
+/// Deterministic, unsorted values around `center`, spread like real
+/// speed or latency samples.
+fn samples(count: usize, center: f64) -> Vec<f64> {
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    (0..count)
+        .map(|_| {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let unit = (state >> 11) as f64 / (1_u64 << 53) as f64;
+            center * (0.5 + unit)
+        })
+        .collect()
+}
+
+/// Bandwidth measurements, about a tenth of them too short to count.
+fn bandwidth_measurements(count: usize) -> Vec<BandwidthMeasurement> {
+    samples(count, 100_000_000.0)
+        .into_iter()
+        .zip(samples(count, 40.0))
+        .map(|(bandwidth_bps, duration_ms)| BandwidthMeasurement {
+            bytes: 1_000_000,
+            bandwidth_bps,
+            duration_ms,
+            server_time_ms: 1.0,
+            ttfb_ms: 10.0,
+        })
+        .collect()
+}
+
+fn bench_percentile(c: &mut Criterion) {
+    let mut group = c.benchmark_group("percentile_f64");
+    for count in SAMPLE_COUNTS {
+        let values = samples(count, 100.0);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(count),
+            &values,
+            |b, values| {
+                b.iter_batched_ref(
+                    || values.clone(),
+                    |values| percentile_f64(black_box(values), 0.9),
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_aggregate_bandwidth(c: &mut Criterion) {
+    let mut group = c.benchmark_group("aggregate_bandwidth");
+    for count in SAMPLE_COUNTS {
+        let measurements = bandwidth_measurements(count);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(count),
+            &measurements,
+            |b, measurements| {
+                b.iter(|| {
+                    aggregate_bandwidth(black_box(measurements), 0.9, 10.0)
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_jitter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("jitter_f64");
+    for count in SAMPLE_COUNTS {
+        let values = samples(count, 15.0);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(count),
+            &values,
+            |b, values| b.iter(|| jitter_f64(black_box(values))),
+        );
+    }
+    group.finish();
+}
+
+/// Progress events of a run with `count` latency probes and `count`
+/// measurements in each direction.
+fn progress_events(count: usize) -> Vec<ProgressEvent> {
+    let mut events = vec![ProgressEvent::PhaseChange(TestPhase::Latency)];
+    events.extend(samples(count, 15.0).into_iter().enumerate().map(
+        |(i, value_ms)| ProgressEvent::LatencyMeasurement {
+            value_ms,
+            current: i + 1,
+            total: count,
+        },
+    ));
+    for (phase, direction) in [
+        (TestPhase::Download, BandwidthDirection::Download),
+        (TestPhase::Upload, BandwidthDirection::Upload),
+    ] {
+        events.push(ProgressEvent::PhaseChange(phase));
+        events.extend(samples(count, 100.0).into_iter().enumerate().map(
+            |(i, speed_mbps)| ProgressEvent::BandwidthMeasurement {
+                direction,
+                speed_mbps,
+                bytes: 1_000_000,
+                current: i + 1,
+                total: count,
+            },
+        ));
+        events.push(ProgressEvent::PhaseComplete(phase));
+    }
+    events
+}
+
+fn bench_progress_state(c: &mut Criterion) {
+    let mut group = c.benchmark_group("progress_state");
+    for count in SAMPLE_COUNTS {
+        let events = progress_events(count);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(count),
+            &events,
+            |b, events| {
+                b.iter_batched_ref(
+                    TuiState::new,
+                    |state| {
+                        for event in events {
+                            state.update_from_event(black_box(event));
+                        }
+                    },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Discarding a downloaded body must outpace a 10 Gbps link (1.25 GB/s),
+/// so the CPU never caps a measurement; hashing backs `--detailed`.
+fn bench_discard_sink(c: &mut Criterion) {
+    let mut group = c.benchmark_group("discard_sink");
+    group.throughput(Throughput::Bytes(BODY_BYTES as u64));
+    for hash in [false, true] {
+        let name = if hash { "hashing" } else { "counting" };
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            &hash,
+            |b, &hash| {
+                b.iter(|| {
+                    // Read through a stack buffer, as bodies are drained
+                    let mut body = io::repeat(b'0').take(BODY_BYTES as u64);
+                    let mut buffer = [0_u8; DRAIN_BUFFER_BYTES];
+                    let mut sink = DiscardSink::new().with_hash(hash);
+                    loop {
+                        match body.read(&mut buffer).unwrap() {
+                            0 => break,
+                            n => sink.write_all(&buffer[..n]).unwrap(),
+                        }
+                    }
+                    (sink.bytes(), sink.digest())
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_percentile,
+    bench_aggregate_bandwidth,
+    bench_jitter,
+    bench_progress_state,
+    bench_discard_sink
+);
+criterion_main!(benches);
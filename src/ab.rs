@@ -0,0 +1,505 @@
+//! A/B comparison of two test configurations.
+//!
+//! Runs each configuration several times, interleaved by default so slow
+//! drift in network conditions affects both equally, and compares the
+//! medians of each metric. Differences are tested with the Mann-Whitney U
+//! test, since a handful of speed test samples is neither large nor
+//! normally distributed.
+
+use crate::cloudflare::tests::engine::{
+    SpeedTestOutput, TestConfig, TestEngine,
+};
+use crate::config::AbHooks;
+use crate::reporters::CommandReport;
+use crate::stats::{mann_whitney_p, median_f64};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::error::Error;
+use std::io::{self, Write};
+use std::process::Command;
+
+/// p-value below which a difference is reported as significant.
+pub const SIGNIFICANCE: f64 = 0.05;
+
+/// Runs of each configuration unless told otherwise.
+pub const DEFAULT_RUNS: usize = 5;
+
+/// One side of the comparison.
+pub struct Arm {
+    /// Short name used in reports, e.g. `A`
+    pub label: String,
+    /// Where the configuration came from, e.g. the config file path
+    pub source: String,
+    /// Engine configuration for this side's runs
+    pub test_config: TestConfig,
+    /// Commands run around each of this side's runs
+    pub hooks: AbHooks,
+}
+
+/// How many runs to make and in what order.
+#[derive(Debug, Clone, Copy)]
+pub struct AbOptions {
+    /// Runs of each configuration
+    pub runs: usize,
+    /// Run every A before every B instead of alternating
+    pub sequential: bool,
+}
+
+/// Result of an A/B comparison.
+#[derive(Debug, Clone, Serialize)]
+pub struct AbReport {
+    /// When the comparison finished
+    pub timestamp: DateTime<Utc>,
+    /// `interleaved` or `sequential`
+    pub order: &'static str,
+    /// The two configurations, A first
+    pub arms: Vec<ArmSummary>,
+    /// Per-metric comparison of B against A
+    pub metrics: Vec<MetricComparison>,
+}
+
+impl AbReport {
+    /// Runs that failed across both configurations.
+    pub fn failures(&self) -> usize {
+        self.arms.iter().map(|arm| arm.failures).sum()
+    }
+
+    /// Whether either configuration has no successful runs to compare.
+    pub fn incomplete(&self) -> bool {
+        self.arms.iter().any(|arm| arm.runs == 0)
+    }
+}
+
+/// How one configuration's runs went.
+#[derive(Debug, Clone, Serialize)]
+pub struct ArmSummary {
+    pub label: String,
+    pub source: String,
+    /// Successful runs
+    pub runs: usize,
+    /// Failed runs, excluded from the comparison
+    pub failures: usize,
+}
+
+/// Comparison of one metric between the two configurations.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricComparison {
+    pub metric: &'static str,
+    pub unit: &'static str,
+    pub median_a: f64,
+    pub median_b: f64,
+    /// `median_b - median_a`
+    pub difference: f64,
+    /// Difference relative to A, unless A's median is zero
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_percent: Option<f64>,
+    /// Mann-Whitney U two-sided p-value
+    pub p_value: f64,
+    /// Whether `p_value` is below [`SIGNIFICANCE`]
+    pub significant: bool,
+    /// Label of the better configuration, when the difference is
+    /// significant
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub better: Option<String>,
+}
+
+/// The metrics one run contributes to the comparison.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunSample {
+    pub download_mbps: f64,
+    pub upload_mbps: f64,
+    pub idle_latency_ms: f64,
+    pub idle_jitter_ms: Option<f64>,
+    pub loaded_down_ms: Option<f64>,
+    pub loaded_up_ms: Option<f64>,
+}
+
+impl RunSample {
+    pub fn from_output(output: &SpeedTestOutput) -> Self {
+        Self {
+            download_mbps: output.download.speed_mbps,
+            upload_mbps: output.upload.speed_mbps,
+            idle_latency_ms: output.latency.idle_ms,
+            idle_jitter_ms: output.latency.idle_jitter_ms,
+            loaded_down_ms: output.latency.loaded_down_ms,
+            loaded_up_ms: output.latency.loaded_up_ms,
+        }
+    }
+}
+
+/// A compared metric: name, unit, whether higher is better, and how to
+/// read it from a run.
+pub struct Metric {
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub higher_is_better: bool,
+    pub value: fn(&RunSample) -> Option<f64>,
+}
+
+pub const METRICS: [Metric; 6] = [
+    Metric {
+        name: "download",
+        unit: "Mbps",
+        higher_is_better: true,
+        value: |s| Some(s.download_mbps),
+    },
+    Metric {
+        name: "upload",
+        unit: "Mbps",
+        higher_is_better: true,
+        value: |s| Some(s.upload_mbps),
+    },
+    Metric {
+        name: "idle_latency",
+        unit: "ms",
+        higher_is_better: false,
+        value: |s| Some(s.idle_latency_ms),
+    },
+    Metric {
+        name: "idle_jitter",
+        unit: "ms",
+        higher_is_better: false,
+        value: |s| s.idle_jitter_ms,
+    },
+    Metric {
+        name: "loaded_latency_down",
+        unit: "ms",
+        higher_is_better: false,
+        value: |s| s.loaded_down_ms,
+    },
+    Metric {
+        name: "loaded_latency_up",
+        unit: "ms",
+        higher_is_better: false,
+        value: |s| s.loaded_up_ms,
+    },
+];
+
+/// Run both configurations and compare them.
+///
+/// Progress is written to stderr. A failed run is logged and left out
+/// of the comparison, but a failing `before` hook aborts it, since the
+/// runs that follow would not test what they claim to.
+pub async fn run(
+    arms: [Arm; 2],
+    options: AbOptions,
+) -> Result<AbReport, Box<dyn Error>> {
+    let schedule: Vec<usize> = if options.sequential {
+        [0, 1].iter().flat_map(|&arm| vec![arm; options.runs]).collect()
+    } else {
+        (0..options.runs).flat_map(|_| [0, 1]).collect()
+    };
+
+    let mut samples: [Vec<RunSample>; 2] = Default::default();
+    let mut failures = [0usize; 2];
+    let engines = arms
+        .each_ref()
+        .map(|arm| TestEngine::new(arm.test_config.clone(), None));
+
+    for (i, &index) in schedule.iter().enumerate() {
+        let arm = &arms[index];
+        let position = format!("[{}/{}] {}", i + 1, schedule.len(), arm.label);
+
+        if let Some(ref before) = arm.hooks.before {
+            run_hook(before).await.map_err(|e| {
+                format!("{} before hook `{}` failed: {}", arm.label, before, e)
+            })?;
+        }
+
+        let result = engines[index].run().await;
+
+        if let Some(ref after) = arm.hooks.after {
+            if let Err(e) = run_hook(after).await {
+                log::warn!(
+                    "{} after hook `{}` failed: {}",
+                    arm.label,
+                    after,
+                    e
+                );
+            }
+        }
+
+        match result {
+            Ok(output) => {
+                let sample = RunSample::from_output(&output);
+                eprintln!(
+                    "{}: {:.2} Mbps down, {:.2} Mbps up, {:.1} ms idle",
+                    position,
+                    sample.download_mbps,
+                    sample.upload_mbps,
+                    sample.idle_latency_ms
+                );
+                samples[index].push(sample);
+            }
+            Err(e) => {
+                eprintln!("{}: failed: {}", position, e);
+                failures[index] += 1;
+            }
+        }
+    }
+
+    let labels = [arms[0].label.as_str(), arms[1].label.as_str()];
+    let metrics = compare(labels, &samples[0], &samples[1]);
+
+    Ok(AbReport {
+        timestamp: Utc::now(),
+        order: if options.sequential { "sequential" } else { "interleaved" },
+        arms: arms
+            .iter()
+            .zip(samples.iter().zip(failures))
+            .map(|(arm, (samples, failures))| ArmSummary {
+                label: arm.label.clone(),
+                source: arm.source.clone(),
+                runs: samples.len(),
+                failures,
+            })
+            .collect(),
+        metrics,
+    })
+}
+
+/// Compare each metric between the runs of `a` and `b`.
+///
+/// Metrics missing from every run of either side are left out.
+pub fn compare(
+    labels: [&str; 2],
+    a: &[RunSample],
+    b: &[RunSample],
+) -> Vec<MetricComparison> {
+    METRICS
+        .iter()
+        .filter_map(|metric| {
+            let values = |samples: &[RunSample]| -> Vec<f64> {
+                samples.iter().filter_map(metric.value).collect()
+            };
+            let (mut values_a, mut values_b) = (values(a), values(b));
+            let p_value = mann_whitney_p(&values_a, &values_b)?;
+            let median_a = median_f64(&mut values_a)?;
+            let median_b = median_f64(&mut values_b)?;
+
+            let difference = median_b - median_a;
+            let significant = p_value < SIGNIFICANCE;
+            let better = (significant && difference != 0.0).then(|| {
+                let b_higher = difference > 0.0;
+                let label = if b_higher == metric.higher_is_better {
+                    labels[1]
+                } else {
+                    labels[0]
+                };
+                label.to_string()
+            });
+
+            Some(MetricComparison {
+                metric: metric.name,
+                unit: metric.unit,
+                median_a,
+                median_b,
+                difference,
+                change_percent: (median_a != 0.0)
+                    .then(|| difference / median_a * 100.0),
+                p_value,
+                significant,
+                better,
+            })
+        })
+        .collect()
+}
+
+/// Print a human-readable comparison table.
+pub fn print_report<W: Write + ?Sized>(
+    report: &AbReport,
+    out: &mut W,
+) -> io::Result<()> {
+    writeln!(out, "A/B comparison ({})", report.order)?;
+    for arm in &report.arms {
+        write!(out, "  {}: {} ({} runs", arm.label, arm.source, arm.runs)?;
+        if arm.failures > 0 {
+            write!(out, ", {} failed", arm.failures)?;
+        }
+        writeln!(out, ")")?;
+    }
+    writeln!(out)?;
+
+    if report.metrics.is_empty() {
+        writeln!(out, "No successful runs to compare")?;
+        return Ok(());
+    }
+
+    let (a, b) = (&report.arms[0].label, &report.arms[1].label);
+    writeln!(
+        out,
+        "{:<20} {:>10} {:>10} {:>9} {:>7}",
+        "Metric", a, b, "Change", "p"
+    )?;
+    for metric in &report.metrics {
+        let change = metric
+            .change_percent
+            .map(|c| format!("{:+.1}%", c))
+            .unwrap_or_else(|| "-".to_string());
+        write!(
+            out,
+            "{:<20} {:>10.2} {:>10.2} {:>9} {:>7.3}",
+            format!("{} ({})", metric.metric, metric.unit),
+            metric.median_a,
+            metric.median_b,
+            change,
+            metric.p_value
+        )?;
+        match &metric.better {
+            Some(better) => writeln!(out, "  {} better", better)?,
+            None => writeln!(out)?,
+        }
+    }
+    writeln!(out)?;
+    writeln!(
+        out,
+        "Medians shown; differences with p < {} are significant",
+        SIGNIFICANCE
+    )
+}
+
+/// Run a hook command through the shell.
+async fn run_hook(command: &str) -> Result<(), Box<dyn Error>> {
+    let command = command.to_string();
+    let status = tokio::task::spawn_blocking(move || {
+        let mut shell = if cfg!(windows) {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        } else {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        };
+        shell.arg(&command).status()
+    })
+    .await??;
+
+    if !status.success() {
+        return Err(format!("exited with {}", status).into());
+    }
+    Ok(())
+}
+
+impl CommandReport for AbReport {
+    fn write_human(&self, out: &mut dyn Write) -> io::Result<()> {
+        print_report(self, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(download_mbps: f64, idle_latency_ms: f64) -> RunSample {
+        RunSample {
+            download_mbps,
+            upload_mbps: 20.0,
+            idle_latency_ms,
+            ..RunSample::default()
+        }
+    }
+
+    #[test]
+    fn test_compare_detects_significant_difference() {
+        let a: Vec<_> = [90.0, 92.0, 91.0, 93.0, 94.0]
+            .iter()
+            .map(|&d| sample(d, 12.0))
+            .collect();
+        let b: Vec<_> = [60.0, 62.0, 61.0, 63.0, 64.0]
+            .iter()
+            .map(|&d| sample(d, 30.0))
+            .collect();
+
+        let metrics = compare(["A", "B"], &a, &b);
+        let download = &metrics[0];
+        assert_eq!(download.metric, "download");
+        assert_eq!(download.median_a, 92.0);
+        assert_eq!(download.median_b, 62.0);
+        assert_eq!(download.difference, -30.0);
+        assert!(download.significant);
+        assert_eq!(download.better.as_deref(), Some("A"));
+
+        // Lower latency is better, so A wins here too
+        let latency = metrics.iter().find(|m| m.metric == "idle_latency");
+        assert_eq!(latency.unwrap().better.as_deref(), Some("A"));
+
+        // Identical uploads are not a difference
+        let upload = &metrics[1];
+        assert!(!upload.significant);
+        assert_eq!(upload.better, None);
+    }
+
+    #[test]
+    fn test_compare_overlapping_samples_not_significant() {
+        let a: Vec<_> =
+            [90.0, 60.0, 75.0].iter().map(|&d| sample(d, 12.0)).collect();
+        let b: Vec<_> =
+            [70.0, 85.0, 65.0].iter().map(|&d| sample(d, 12.0)).collect();
+
+        let download = &compare(["A", "B"], &a, &b)[0];
+        assert!(!download.significant);
+        assert_eq!(download.better, None);
+    }
+
+    #[test]
+    fn test_compare_skips_missing_metrics() {
+        let a = vec![sample(90.0, 12.0)];
+        let b = vec![sample(80.0, 12.0)];
+        let metrics = compare(["A", "B"], &a, &b);
+        let names: Vec<_> = metrics.iter().map(|m| m.metric).collect();
+        assert_eq!(names, ["download", "upload", "idle_latency"]);
+
+        assert!(compare(["A", "B"], &a, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_print_report_table() {
+        let a: Vec<_> = [90.0, 92.0, 91.0, 93.0, 94.0]
+            .iter()
+            .map(|&d| sample(d, 12.0))
+            .collect();
+        let b: Vec<_> = [60.0, 62.0, 61.0, 63.0, 64.0]
+            .iter()
+            .map(|&d| sample(d, 12.0))
+            .collect();
+        let report = AbReport {
+            timestamp: Utc::now(),
+            order: "interleaved",
+            arms: vec![
+                ArmSummary {
+                    label: "A".to_string(),
+                    source: "vpn-off.toml".to_string(),
+                    runs: 5,
+                    failures: 0,
+                },
+                ArmSummary {
+                    label: "B".to_string(),
+                    source: "vpn-on.toml".to_string(),
+                    runs: 5,
+                    failures: 1,
+                },
+            ],
+            metrics: compare(["A", "B"], &a, &b),
+        };
+
+        let mut out = Vec::new();
+        print_report(&report, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains("B: vpn-on.toml (5 runs, 1 failed)"));
+        assert!(text.contains("-32.6%"));
+        assert!(text.contains("A better"));
+        assert_eq!(report.failures(), 1);
+        assert!(!report.incomplete());
+    }
+
+    #[tokio::test]
+    async fn test_run_hook_reports_failure() {
+        if cfg!(windows) {
+            return;
+        }
+        assert!(run_hook("true").await.is_ok());
+        assert!(run_hook("exit 3").await.is_err());
+    }
+}
@@ -0,0 +1,4017 @@
+//! The `cloud-speed` command line: flags, subcommands and the output
+//! modes (TUI/JSON/Silent) of a test run.
+
+use crate::clock::{describe_skew, skew_secs, CLOCK_SKEW_WARN_SECS};
+use crate::cloudflare::certs::{use_certificates, TlsCertificates};
+use crate::cloudflare::client::Client;
+use crate::cloudflare::portal::check_interception;
+use crate::cloudflare::requests::{locations::Locations, meta::MetaRequest};
+use crate::cloudflare::tests::connection::{
+    use_name_server, use_tls_backend, use_tls_cipher, Dscp, SocketOptions,
+    TlsBackend, TlsCipher,
+};
+use crate::cloudflare::tests::engine::{
+    LatencyProbe, LatencySchedule, Pacing, PacingJitter, PhaseSelection,
+    SizeBlocks, TestConfig, TestEngine, TestOrder,
+    DEFAULT_LATENCY_PARALLEL_GAP_MS, DEFAULT_LATENCY_TICK_MS,
+};
+use crate::cloudflare::tests::packet_loss::{
+    run_packet_loss_test_safe, LossProvider, PacketLossConfig,
+};
+use crate::cloudflare::tests::stall::DEFAULT_STALL_TIMEOUT;
+use crate::cloudflare::tests::upload::{PayloadPattern, UploadChunkSize};
+use crate::config::{
+    AbHooks, Config, HistorySettings, RegionalSettings, ShareSettings,
+    TestSettings,
+};
+use crate::errors::{
+    classify_error, exit_codes, format_error_for_display, ErrorKind,
+    SpeedTestError,
+};
+use crate::history::{
+    detect_regressions, ExportFormat, HistoryEntry, HistoryStore,
+    RegressionResults, Retention,
+};
+use crate::regional::{RegionScope, RegionalComparison};
+use crate::reporters::{
+    summary_line, PhaseStream, Reporter, ReporterRegistry,
+};
+use crate::results::{
+    connection_metrics, AimScoresOutput, AssertionResult, BandwidthResults,
+    ConnectionMeta, DnsResults, DualStackResults, DuplexResults,
+    EgressResults, HandshakeResults, LatencyResults, PacingResults,
+    PacketLossResults, PathMtuResults, ProfileResults, ResponsivenessResults,
+    RunMeta, ServerLocation, SpeedTestResults, TlsResults, JSON_SCHEMA,
+};
+use crate::scoring::{
+    ConnectionMetrics, ScoringThresholds, WorkingLatencyCheck,
+};
+use crate::share::ShareSummary;
+use crate::signing::SigningKey;
+use crate::simulate::{Simulation, SpeedProfile, DEFAULT_SIMULATION_SECS};
+use crate::tui::state::{ConnectionInfo, ServerInfo};
+use crate::tui::{DisplayMode, TerminalCapabilities, TuiController};
+use crate::{
+    ab, diff, doctor, exit, history, locations, ping, regional, repeat,
+    reporters, serve, share, signing, tui, vpn,
+};
+use clap::{Parser, Subcommand};
+use clap_verbosity_flag::Verbosity;
+use colored::Colorize;
+use http::header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::ops::ControlFlow;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const LONG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("CLOUDSPEED_BUILD_GIT_HASH"),
+    ")"
+);
+
+/// Flags that need both directions measured, so can't be combined with
+/// `--latency-only`, `--download-only` or `--upload-only`.
+const PARTIAL_RUN_CONFLICTS: [&str; 6] = [
+    "save_history",
+    "detect_regression",
+    "assert_working_latency",
+    "share",
+    "repeat",
+    "compare_vpn",
+];
+
+/// Flags that record, publish or compare real measurements, so can't be
+/// combined with `--simulate`.
+const SIMULATE_CONFLICTS: [&str; 7] = [
+    "save_history",
+    "detect_regression",
+    "share",
+    "compare_region",
+    "sign_key",
+    "repeat",
+    "compare_vpn",
+];
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None, long_version = LONG_VERSION)]
+struct Cli {
+    /// Print results in json format (shorthand for `--format json`)
+    #[arg(short, long, default_value_t = false)]
+    json: bool,
+
+    /// Output format for results (human, json, markdown, html, junit)
+    #[arg(long, value_name = "FORMAT", conflicts_with = "json")]
+    format: Option<String>,
+
+    /// Only applies when json is active.
+    /// Pretty prints JSON on output
+    #[arg(short, long, default_value_t = false)]
+    pretty: bool,
+
+    /// Only applies when json is active.
+    /// Print a JSON line as each phase completes, ahead of the final
+    /// results (NDJSON; implies compact output)
+    #[arg(long, default_value_t = false)]
+    stream: bool,
+
+    /// Server to measure packet loss against (e.g., turn:example.com:3478)
+    #[arg(long, alias = "turn-server", value_name = "URI")]
+    loss_server: Option<String>,
+
+    /// Kind of packet loss server: turn (STUN Binding requests), udp-echo,
+    /// or quic (version negotiation; Cloudflare's edge unless
+    /// --loss-server is given) [default: turn]
+    #[arg(long, value_name = "PROVIDER")]
+    loss_provider: Option<LossProvider>,
+
+    /// Extra HTTP header to send with every request (repeatable),
+    /// e.g. -H "CF-Access-Client-Id: <id>"
+    #[arg(short = 'H', long = "header", value_name = "KEY:VALUE", value_parser = parse_header)]
+    headers: Vec<(HeaderName, HeaderValue)>,
+
+    /// User-Agent for speed test requests, in case a filter on the way
+    /// treats unknown clients differently [default: cloud-speed/<version>]
+    #[arg(long, value_name = "UA", value_parser = parse_header_value)]
+    user_agent: Option<HeaderValue>,
+
+    /// Only measure idle latency, without loading the link; scores and
+    /// history need both directions and are left out
+    #[arg(
+        long,
+        group = "phases",
+        conflicts_with_all = PARTIAL_RUN_CONFLICTS
+    )]
+    latency_only: bool,
+
+    /// Only measure latency and download speed
+    #[arg(
+        long,
+        group = "phases",
+        conflicts_with_all = PARTIAL_RUN_CONFLICTS
+    )]
+    download_only: bool,
+
+    /// Only measure latency and upload speed
+    #[arg(
+        long,
+        group = "phases",
+        conflicts_with_all = PARTIAL_RUN_CONFLICTS
+    )]
+    upload_only: bool,
+
+    /// Background streams saturating the link while loaded latency is
+    /// measured (0-32)
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(0..=32)
+    )]
+    load_streams: Option<u32>,
+
+    /// Measure idle latency with ICMP echo instead of HTTP, falling back
+    /// to HTTP when ICMP sockets are unavailable
+    #[arg(long)]
+    icmp: bool,
+
+    /// Spacing between idle latency probes in ms [default: 0, 100 with
+    /// --latency-tick, or 20 with --latency-parallel]
+    #[arg(long, value_name = "MS")]
+    latency_interval: Option<u64>,
+
+    /// Send idle latency probes on a fixed tick, like ping, instead of
+    /// waiting for each probe to finish
+    #[arg(long)]
+    latency_tick: bool,
+
+    /// Keep up to N idle latency probes in flight at once, for a shorter
+    /// latency phase on high-RTT links [default N: 4]
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        default_missing_value = "4",
+        value_parser = clap::value_parser!(u32).range(2..=16),
+        conflicts_with = "latency_tick"
+    )]
+    latency_parallel: Option<u32>,
+
+    /// Byte pattern for upload payloads: random (incompressible) or zeros
+    #[arg(long, value_name = "PATTERN", default_value_t = PayloadPattern::Random)]
+    upload_payload: PayloadPattern,
+
+    /// Bytes per upload write, or auto to size writes from the measured
+    /// upload bandwidth
+    #[arg(long, value_name = "BYTES", default_value_t = UploadChunkSize::Auto)]
+    upload_chunk_size: UploadChunkSize,
+
+    /// Download sizes and how many times to fetch each, smallest first
+    #[arg(
+        long,
+        value_name = "SIZE:COUNT,...",
+        default_value_t = SizeBlocks(TestConfig::default().download_sizes)
+    )]
+    download_sizes: SizeBlocks,
+
+    /// Upload sizes and how many times to send each, smallest first
+    #[arg(
+        long,
+        value_name = "SIZE:COUNT,...",
+        default_value_t = SizeBlocks(TestConfig::default().upload_sizes)
+    )]
+    upload_sizes: SizeBlocks,
+
+    /// SO_SNDBUF to request for measurement sockets [default: OS default]
+    #[arg(long, value_name = "BYTES")]
+    send_buffer: Option<usize>,
+
+    /// SO_RCVBUF to request for measurement sockets [default: OS default]
+    #[arg(long, value_name = "BYTES")]
+    recv_buffer: Option<usize>,
+
+    /// DSCP class to mark measurement traffic with (ef, af11-af43,
+    /// cs0-cs7, be, or 0-63)
+    #[arg(long, value_name = "CLASS")]
+    dscp: Option<Dscp>,
+
+    /// DNS server to resolve every hostname with instead of the system's
+    /// resolvers, e.g. 1.1.1.1 or [2606:4700:4700::1111]:53
+    #[arg(long, value_name = "IP[:PORT]", value_parser = parse_resolver)]
+    resolver: Option<SocketAddr>,
+
+    /// Complete a TLS session before measuring and resume it for every
+    /// test connection
+    #[arg(long)]
+    prewarm_tls: bool,
+
+    /// TLS cipher suites to offer: auto, aes-gcm (fastest with AES
+    /// instructions) or chacha20 (fastest without); rustls only
+    #[arg(long, value_name = "CIPHER", default_value_t = TlsCipher::Auto)]
+    tls_cipher: TlsCipher,
+
+    /// TLS implementation for measurement connections: rustls, or native
+    /// for the platform's own, which trusts what the OS trusts
+    #[arg(long, value_name = "BACKEND", default_value_t = TlsBackend::Rustls)]
+    tls_backend: TlsBackend,
+
+    /// PEM bundle of extra root certificates to trust, e.g. a
+    /// TLS-inspecting proxy's
+    #[arg(long, value_name = "FILE")]
+    cacert: Option<std::path::PathBuf>,
+
+    /// PEM client certificate chain to present, for networks that require
+    /// mutual TLS
+    #[arg(long, value_name = "FILE", requires = "client_key")]
+    client_cert: Option<std::path::PathBuf>,
+
+    /// PKCS#8 PEM private key of --client-cert
+    #[arg(long, value_name = "FILE", requires = "client_cert")]
+    client_key: Option<std::path::PathBuf>,
+
+    /// Threads running async work such as latency probes [default: one
+    /// per core]; transfers run on threads of their own
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    worker_threads: Option<u16>,
+
+    /// Be polite on shared networks: pause between measurements so the
+    /// link is idle at least half the time, and add no background load
+    #[arg(long)]
+    nice: bool,
+
+    /// Add random gaps between bandwidth requests and vary their sizes
+    /// by up to 10%, for CPE or ISP traffic shaping that spots fixed test
+    /// patterns
+    #[arg(long)]
+    pacing_jitter: bool,
+
+    /// Probe the path MTU toward the test server with don't-fragment
+    /// pings before measuring (Linux; needs ICMP access like --icmp)
+    #[arg(long)]
+    mtu: bool,
+
+    /// Time a cold connection setup (DNS, TCP and TLS) against warm ones
+    /// with the lookup cached and the TLS session resumed
+    #[arg(long)]
+    handshake: bool,
+
+    /// Order of the download and upload blocks: sequential, interleaved,
+    /// or concurrent (both directions at once) [default: interleaved]
+    #[arg(long, value_name = "ORDER")]
+    order: Option<TestOrder>,
+
+    /// After the one-way tests, saturate both directions at once and
+    /// report duplex throughput, latency and efficiency
+    #[arg(long)]
+    duplex: bool,
+
+    /// Record the error kind and attempt count of retried measurements,
+    /// and the Cloudflare headers of each response, in each size's JSON
+    /// output, the loss of each packet loss batch, and the User-Agent
+    /// sent
+    #[arg(long)]
+    detailed: bool,
+
+    /// Skip the rest of a size once the coefficient of variation of its
+    /// recent speeds drops below this, e.g. 0.05 for 5%; 0 disables
+    /// [default: 0.05]
+    #[arg(long, value_name = "CV", value_parser = parse_convergence_cv)]
+    convergence_cv: Option<f64>,
+
+    /// Recent measurements of a size the coefficient of variation is
+    /// taken over (2-32) [default: 4]
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(2..=32)
+    )]
+    convergence_window: Option<u32>,
+
+    /// Warn when a transfer receives no data for this many seconds;
+    /// 0 disables stall detection [default: 10]
+    #[arg(long, value_name = "SECS")]
+    stall_timeout: Option<u64>,
+
+    /// Abort stalled transfers and retry the measurement instead of
+    /// waiting for them to resume
+    #[arg(long)]
+    abort_stalled: bool,
+
+    /// Run latency or a size block again if the network changes while
+    /// it runs (e.g. a Wi-Fi roam or a VPN connecting)
+    #[arg(long)]
+    restart_on_network_change: bool,
+
+    /// If a VPN is detected, compare tests with and without it using the
+    /// config file's [vpn] up/down commands
+    #[arg(long)]
+    compare_vpn: bool,
+
+    /// Abort instead of testing when traffic leaves through Cloudflare
+    /// WARP, which would be measured rather than your ISP
+    #[arg(long)]
+    require_direct: bool,
+
+    /// Run the complete test N times and report each run along with the
+    /// median and spread of every metric across runs
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(2..=100),
+        conflicts_with_all = ["compare_vpn", "stream"]
+    )]
+    repeat: Option<u32>,
+
+    /// Run a simulated test of a fiber, cable, dsl or mobile connection
+    /// through the TUI without touching the network, for UI work and
+    /// demos [default: cable]
+    #[arg(
+        long,
+        value_name = "PROFILE",
+        num_args = 0..=1,
+        default_missing_value = "cable",
+        conflicts_with_all = SIMULATE_CONFLICTS
+    )]
+    simulate: Option<SpeedProfile>,
+
+    /// Seconds a simulated test takes
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = DEFAULT_SIMULATION_SECS,
+        value_parser = clap::value_parser!(u64).range(1..=600),
+        requires = "simulate"
+    )]
+    simulate_duration: u64,
+
+    /// Config file to use instead of the platform default
+    #[arg(long, value_name = "PATH")]
+    config: Option<std::path::PathBuf>,
+
+    /// Tag to attach to the results (repeatable), e.g. --tag site=nyc
+    #[arg(long = "tag", value_name = "KEY=VALUE", value_parser = parse_tag)]
+    tags: Vec<(String, String)>,
+
+    /// Record this run in the local history
+    #[arg(long, default_value_t = false)]
+    save_history: bool,
+
+    /// Compare this run against the last 30 days of history and exit
+    /// with code 5 if any metric regressed (implies --save-history)
+    #[arg(long, default_value_t = false)]
+    detect_regression: bool,
+
+    /// Exit with code 7 unless loaded latency stays under the working
+    /// latency targets (100 ms by default, per BITAG)
+    #[arg(long)]
+    assert_working_latency: bool,
+
+    /// Exit with code 9 unless the download speed reaches MBPS
+    #[arg(long, value_name = "MBPS")]
+    assert_download: Option<f64>,
+
+    /// Exit with code 9 unless the upload speed reaches MBPS
+    #[arg(long, value_name = "MBPS")]
+    assert_upload: Option<f64>,
+
+    /// Exit with code 9 unless idle latency stays at or under MS
+    #[arg(long, value_name = "MS")]
+    assert_latency: Option<f64>,
+
+    /// History file to use instead of the platform default
+    #[arg(long, value_name = "PATH")]
+    history_file: Option<std::path::PathBuf>,
+
+    /// Publish-safe output: truncate the client IP to its /24 or /48
+    /// network and omit the ISP, ASN, proxy and hostname
+    #[arg(long)]
+    anonymize: bool,
+
+    /// Print a shareable summary of the anonymized results, or a link to
+    /// it if the config file sets a [share] endpoint
+    #[arg(long)]
+    share: bool,
+
+    /// Compare the results with the median speeds Cloudflare Radar
+    /// publishes for your country or network (country, asn)
+    /// [default: country]
+    #[arg(
+        long,
+        value_name = "SCOPE",
+        num_args = 0..=1,
+        default_missing_value = "country"
+    )]
+    compare_region: Option<RegionScope>,
+
+    /// Don't show the MB/s throughput and download time estimates in the
+    /// human report
+    #[arg(long)]
+    no_estimates: bool,
+
+    /// Add a sparkline of per-size speeds to the Markdown report
+    #[arg(long)]
+    sparkline: bool,
+
+    /// Ring the terminal bell when the test finishes or fails
+    #[arg(long)]
+    bell: bool,
+
+    /// Draw the interactive display with ASCII symbols and basic colors
+    /// only, for terminals that show Unicode as garbage
+    #[arg(long)]
+    ascii: bool,
+
+    /// Sign the results with this Ed25519 private key (PKCS#8 PEM or DER)
+    #[arg(long, value_name = "PATH")]
+    sign_key: Option<std::path::PathBuf>,
+
+    #[command(flatten)]
+    verbose: Verbosity,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check DNS, connectivity, proxy, IPv6, clock and terminal setup
+    Doctor,
+    /// Serve a local HTTP API for triggering tests and reading results
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+        /// Register as an agent with the fleet coordinator at this URL
+        #[arg(long, value_name = "URL")]
+        coordinator: Option<String>,
+        /// URL the coordinator should use to reach this agent (defaults
+        /// to the address registration came from)
+        #[arg(long, value_name = "URL", requires = "coordinator")]
+        advertise: Option<String>,
+        /// Also log result summaries and errors, with structured fields,
+        /// to syslog, journald or eventlog
+        #[arg(long, value_name = "SINK")]
+        log_sink: Option<serve::sink::LogSink>,
+    },
+    /// Check the signature of a JSON result file
+    Verify {
+        /// Result file to check, or `-` for stdin
+        file: std::path::PathBuf,
+        /// Also require the results to be signed by this public key
+        /// (base64, as embedded in the signature)
+        #[arg(long, value_name = "KEY")]
+        public_key: Option<String>,
+    },
+    /// Coordinate simultaneous tests across registered agents and print a
+    /// merged report
+    Fleet {
+        /// Address agents register with
+        #[arg(long, default_value = "0.0.0.0:9090")]
+        listen: SocketAddr,
+        /// Number of agents to wait for
+        #[arg(long, default_value_t = 1)]
+        agents: usize,
+        /// Seconds to wait for agents to register
+        #[arg(long, value_name = "SECS", default_value_t = 300)]
+        wait: u64,
+        /// Seconds each agent's test may take
+        #[arg(long, value_name = "SECS", default_value_t = 300)]
+        timeout: u64,
+    },
+    /// Run two configurations several times each and compare them
+    Ab {
+        /// Config file for configuration A
+        #[arg(long, value_name = "PATH")]
+        config_a: std::path::PathBuf,
+        /// Config file for configuration B
+        #[arg(long, value_name = "PATH")]
+        config_b: std::path::PathBuf,
+        /// Runs of each configuration
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = ab::DEFAULT_RUNS as u32,
+            value_parser = clap::value_parser!(u32).range(2..=50)
+        )]
+        runs: u32,
+        /// Run every A before every B instead of alternating A and B
+        #[arg(long)]
+        sequential: bool,
+    },
+    /// Run the API server as a Windows service
+    #[cfg(all(windows, feature = "service"))]
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+    /// Print the client IP, ISP and serving location without testing
+    Meta,
+    /// Print the JSON Schema for `--json` results
+    Schema,
+    /// Recalculate the scores and grade of saved results, or of metrics
+    /// given as flags, with the config file's thresholds
+    Score {
+        /// Results JSON to rescore, or `-` for stdin
+        #[arg(
+            long,
+            value_name = "PATH",
+            required_unless_present_all = ["download", "upload", "latency"],
+            conflicts_with_all = [
+                "download", "upload", "latency", "jitter", "loss",
+                "loaded_down", "loaded_up",
+            ]
+        )]
+        input: Option<std::path::PathBuf>,
+        /// Download speed in Mbps
+        #[arg(long, value_name = "MBPS")]
+        download: Option<f64>,
+        /// Upload speed in Mbps
+        #[arg(long, value_name = "MBPS")]
+        upload: Option<f64>,
+        /// Idle latency in ms
+        #[arg(long, value_name = "MS")]
+        latency: Option<f64>,
+        /// Idle jitter in ms
+        #[arg(long, value_name = "MS", default_value_t = 0.0)]
+        jitter: f64,
+        /// Packet loss ratio, from 0 to 1
+        #[arg(long, value_name = "RATIO")]
+        loss: Option<f64>,
+        /// Latency while downloading in ms
+        #[arg(long, value_name = "MS")]
+        loaded_down: Option<f64>,
+        /// Latency while uploading in ms
+        #[arg(long, value_name = "MS")]
+        loaded_up: Option<f64>,
+    },
+    /// Show the results in a snippet printed by `--share`
+    Decode {
+        /// The snippet, or a link or message containing it
+        snippet: String,
+    },
+    /// Compare two JSON result files metric by metric
+    Diff {
+        /// Results from before the change, or `-` for stdin
+        before: std::path::PathBuf,
+        /// Results from after the change
+        after: std::path::PathBuf,
+    },
+    /// Measure latency to the Cloudflare edge continuously until
+    /// stopped, then print a summary
+    Ping {
+        /// Seconds between probes
+        #[arg(
+            long,
+            short = 'i',
+            value_name = "SECS",
+            default_value = "1",
+            value_parser = parse_interval
+        )]
+        interval: Duration,
+        /// Stop after this many probes
+        #[arg(long, short = 'c', value_name = "N")]
+        count: Option<usize>,
+    },
+    /// Prune or export the run history
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+    /// List Cloudflare's test locations
+    Locations {
+        /// Only list locations in this country (ISO code, e.g. US)
+        #[arg(long, value_name = "CODE")]
+        country: Option<String>,
+        /// Estimate the round trip to each location and list the nearest
+        #[arg(long)]
+        nearest: bool,
+        /// Number of locations to list (default: all, or 10 with
+        /// --nearest)
+        #[arg(long, value_name = "N")]
+        limit: Option<usize>,
+    },
+}
+
+/// What to do with the run history.
+#[derive(Subcommand)]
+enum HistoryAction {
+    /// Drop old runs beyond the config file's [history] limits, or the
+    /// limits given here
+    Prune {
+        /// Keep at most this many runs
+        #[arg(long, value_name = "N")]
+        max_entries: Option<usize>,
+        /// Drop runs older than this, e.g. 90d
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        max_age: Option<chrono::TimeDelta>,
+        /// Shrink the history file to at most this many KiB
+        #[arg(long, value_name = "KIB")]
+        max_size_kb: Option<u64>,
+    },
+    /// Serve the history as JSON time series for charting, e.g. in
+    /// Grafana
+    ServeJson {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8081")]
+        listen: SocketAddr,
+        /// Also implement the Grafana JSON datasource API (/search,
+        /// /metrics and /query)
+        #[arg(long)]
+        grafana: bool,
+    },
+    /// Print the history as CSV or JSON
+    Export {
+        /// Only runs from this recently, e.g. 30d, 12h or 2w
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        since: Option<chrono::TimeDelta>,
+        /// Output format: csv or json
+        #[arg(long, value_name = "FORMAT", default_value_t)]
+        format: ExportFormat,
+    },
+}
+
+/// What to do with the Windows service.
+#[cfg(all(windows, feature = "service"))]
+#[derive(Subcommand)]
+enum ServiceAction {
+    /// Register the service to start at boot, serving with the flags given
+    /// here (run as administrator)
+    Install {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+    },
+    /// Stop and remove the service (run as administrator)
+    Uninstall,
+    /// Serve as the service; started by the service control manager
+    Run {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+    },
+}
+
+impl Cli {
+    /// Get the packet loss configuration if there's a server to measure
+    /// against, given or the provider's own.
+    fn packet_loss_config(
+        &self,
+        settings: &TestSettings,
+    ) -> Option<PacketLossConfig> {
+        let provider =
+            self.loss_provider.or(settings.loss_provider).unwrap_or_default();
+        let server = self
+            .loss_server
+            .clone()
+            .or_else(|| settings.loss_server.clone())
+            .or_else(|| provider.default_server().map(str::to_string))?;
+        Some(PacketLossConfig::new(server).with_provider(provider))
+    }
+
+    /// Name of the selected output format.
+    fn format_name(&self) -> &str {
+        match &self.format {
+            Some(format) => format,
+            None if self.json => "json",
+            None => "human",
+        }
+    }
+
+    /// Collect the `--tag` values, later values overriding earlier ones.
+    fn tags(&self) -> BTreeMap<String, String> {
+        self.tags.iter().cloned().collect()
+    }
+
+    /// The idle latency probe schedule and its interval in ms, falling
+    /// back to `settings` for flags that weren't given.
+    fn latency_schedule(
+        &self,
+        settings: &TestSettings,
+    ) -> (LatencySchedule, u64) {
+        let interval = self.latency_interval.or(settings.latency_interval);
+        // A schedule given on the command line replaces the config file's;
+        // each source allows only one of the two
+        let (tick, parallel) = if self.latency_tick {
+            (true, None)
+        } else if let Some(in_flight) = self.latency_parallel {
+            (false, Some(in_flight as usize))
+        } else {
+            (settings.latency_tick == Some(true), settings.latency_parallel)
+        };
+        if tick {
+            (
+                LatencySchedule::FixedTick,
+                interval.unwrap_or(DEFAULT_LATENCY_TICK_MS),
+            )
+        } else if let Some(in_flight) = parallel {
+            (
+                LatencySchedule::Parallel { in_flight },
+                interval.unwrap_or(DEFAULT_LATENCY_PARALLEL_GAP_MS),
+            )
+        } else {
+            (LatencySchedule::Sequential, interval.unwrap_or(0))
+        }
+    }
+
+    /// Headers for speed test requests: the config file's `[test]`
+    /// headers, replaced by `--header` values of the same name, with the
+    /// User-Agent from `--user-agent` or else wherever it was set.
+    fn request_headers(&self, settings: &TestSettings) -> HeaderMap {
+        let mut headers = settings.request_headers();
+        for (name, _) in &self.headers {
+            headers.remove(name);
+        }
+        for (name, value) in &self.headers {
+            headers.append(name.clone(), value.clone());
+        }
+        if let Some(ref user_agent) = self.user_agent {
+            headers.insert(USER_AGENT, user_agent.clone());
+        }
+        headers
+    }
+
+    /// API client sending the headers of [`Cli::request_headers`].
+    fn client(&self, settings: &TestSettings) -> Client {
+        Client::from_headers(self.request_headers(settings))
+    }
+
+    /// What the TUI may draw with: detected from the environment, and
+    /// limited to ASCII and basic colors with `--ascii`.
+    fn terminal_capabilities(&self) -> TerminalCapabilities {
+        let detected = TerminalCapabilities::detect();
+        if self.ascii {
+            detected.ascii()
+        } else {
+            detected
+        }
+    }
+
+    /// Test engine configuration from the measurement flags, falling
+    /// back to the config file's `[test]` settings for flags that weren't
+    /// given.
+    fn test_config(&self, settings: &TestSettings) -> TestConfig {
+        let (latency_schedule, latency_interval_ms) =
+            self.latency_schedule(settings);
+        let enabled = |flag: bool, setting: Option<bool>| {
+            flag || setting.unwrap_or(false)
+        };
+        let load_streams =
+            self.load_streams.or(settings.load_streams).unwrap_or(0);
+        let defaults = TestConfig::default();
+
+        TestConfig {
+            headers: self.request_headers(settings),
+            latency_probe: if enabled(self.icmp, settings.icmp) {
+                LatencyProbe::Icmp
+            } else {
+                LatencyProbe::Http
+            },
+            latency_schedule,
+            latency_interval_ms,
+            load_streams: load_streams as usize,
+            upload_payload: self.upload_payload,
+            upload_chunk_size: self.upload_chunk_size,
+            download_sizes: self.download_sizes.0.clone(),
+            upload_sizes: self.upload_sizes.0.clone(),
+            socket_options: SocketOptions {
+                send_buffer: self.send_buffer.or(settings.send_buffer),
+                recv_buffer: self.recv_buffer.or(settings.recv_buffer),
+                dscp: self.dscp.or(settings.dscp),
+            },
+            prewarm_tls: enabled(self.prewarm_tls, settings.prewarm_tls),
+            pacing: enabled(self.nice, settings.nice).then_some(Pacing::NICE),
+            pacing_jitter: enabled(self.pacing_jitter, settings.pacing_jitter)
+                .then_some(PacingJitter::DEFAULT),
+            path_mtu: enabled(self.mtu, settings.mtu),
+            handshake: enabled(self.handshake, settings.handshake),
+            order: self.order.or(settings.order).unwrap_or_default(),
+            duplex: enabled(self.duplex, settings.duplex),
+            detailed: enabled(self.detailed, settings.detailed),
+            stall_timeout: match self.stall_timeout.or(settings.stall_timeout)
+            {
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => Some(DEFAULT_STALL_TIMEOUT),
+            },
+            abort_stalled: enabled(self.abort_stalled, settings.abort_stalled),
+            restart_on_network_change: enabled(
+                self.restart_on_network_change,
+                settings.restart_on_network_change,
+            ),
+            convergence_cv_threshold: match self
+                .convergence_cv
+                .or(settings.convergence_cv)
+            {
+                Some(cv) if cv > 0.0 => Some(cv),
+                Some(_) => None,
+                None => defaults.convergence_cv_threshold,
+            },
+            convergence_window: self
+                .convergence_window
+                .or(settings.convergence_window)
+                .map_or(defaults.convergence_window, |n| n as usize),
+            phases: self.phases(),
+            ..defaults
+        }
+    }
+
+    /// Phases selected by `--latency-only`, `--download-only` or
+    /// `--upload-only`.
+    fn phases(&self) -> PhaseSelection {
+        if self.latency_only {
+            PhaseSelection::Latency
+        } else if self.download_only {
+            PhaseSelection::Download
+        } else if self.upload_only {
+            PhaseSelection::Upload
+        } else {
+            PhaseSelection::All
+        }
+    }
+
+    /// `serve` configuration from the measurement flags, without a
+    /// coordinator or log sink.
+    fn serve_config(&self, config: &Config) -> serve::ServeConfig {
+        serve::ServeConfig {
+            test_config: self.test_config(&config.test),
+            scoring: config.scoring,
+            tags: self.tags(),
+            history: self.history_store(&config.history),
+            coordinator: None,
+            advertise: None,
+            anonymize: self.anonymize,
+            log_sink: None,
+        }
+    }
+
+    /// The history store selected by `--history-file`, or the platform
+    /// default, keeping as much history as `settings` allow.
+    fn history_store(
+        &self,
+        settings: &HistorySettings,
+    ) -> Option<HistoryStore> {
+        let store = match &self.history_file {
+            Some(path) => Some(HistoryStore::new(path)),
+            None => {
+                let store = HistoryStore::open_default();
+                if store.is_none() {
+                    log::warn!("No data directory found; history is disabled");
+                }
+                store
+            }
+        };
+        store.map(|store| store.with_retention(settings.retention()))
+    }
+}
+
+/// Parse a `KEY=VALUE` tag argument.
+fn parse_tag(arg: &str) -> Result<(String, String), String> {
+    let (key, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", arg))?;
+
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(format!("tag key must not be empty in '{}'", arg));
+    }
+
+    Ok((key.to_string(), value.trim().to_string()))
+}
+
+/// Parse a `--resolver` address, defaulting to port 53.
+fn parse_resolver(arg: &str) -> Result<SocketAddr, String> {
+    arg.parse::<SocketAddr>()
+        .or_else(|_| arg.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+        .map_err(|_| format!("expected IP or IP:PORT, got '{}'", arg))
+}
+
+/// Parse a `--convergence-cv` threshold between 0 and 1.
+fn parse_convergence_cv(arg: &str) -> Result<f64, String> {
+    let cv: f64 =
+        arg.parse().map_err(|_| format!("expected a ratio, got '{}'", arg))?;
+    if !(0.0..=1.0).contains(&cv) {
+        return Err(format!(
+            "convergence threshold must be between 0 and 1, got {}",
+            cv
+        ));
+    }
+    Ok(cv)
+}
+
+/// Parse a `ping --interval` in seconds, at least 0.1.
+fn parse_interval(arg: &str) -> Result<Duration, String> {
+    let secs: f64 =
+        arg.parse().map_err(|_| format!("expected seconds, got '{}'", arg))?;
+    if !(0.1..=3600.0).contains(&secs) {
+        return Err(format!(
+            "interval must be between 0.1 and 3600 seconds, got {}",
+            secs
+        ));
+    }
+    Ok(Duration::from_secs_f64(secs))
+}
+
+/// Parse an age like `30d`, `12h`, `2w` or `45m`.
+fn parse_age(arg: &str) -> Result<chrono::TimeDelta, String> {
+    let invalid = || format!("expected an age like 30d or 12h, got '{}'", arg);
+    let split = arg.len().checked_sub(1).ok_or_else(invalid)?;
+    let (amount, unit) = arg.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    if amount <= 0 {
+        return Err(invalid());
+    }
+    let age = match unit {
+        "m" => chrono::TimeDelta::try_minutes(amount),
+        "h" => chrono::TimeDelta::try_hours(amount),
+        "d" => chrono::TimeDelta::try_days(amount),
+        "w" => chrono::TimeDelta::try_weeks(amount),
+        _ => None,
+    };
+    age.ok_or_else(invalid)
+}
+
+/// Parse a header value argument.
+fn parse_header_value(arg: &str) -> Result<HeaderValue, String> {
+    HeaderValue::from_str(arg.trim())
+        .map_err(|e| format!("invalid header value: {}", e))
+}
+
+/// Parse a `KEY:VALUE` header argument.
+fn parse_header(arg: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = arg
+        .split_once(':')
+        .ok_or_else(|| format!("expected KEY:VALUE, got '{}'", arg))?;
+
+    let name =
+        HeaderName::from_bytes(name.trim().as_bytes()).map_err(|e| {
+            format!("invalid header name '{}': {}", name.trim(), e)
+        })?;
+    let value = HeaderValue::from_str(value.trim())
+        .map_err(|e| format!("invalid header value for '{}': {}", name, e))?;
+
+    Ok((name, value))
+}
+
+/// Parse the command line and run it on a new async runtime.
+pub fn main() {
+    let cli: Cli = Cli::parse();
+    tui::panic_guard::install_panic_hook();
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    if let Some(threads) = cli.worker_threads {
+        runtime.worker_threads(threads.into());
+    }
+    match runtime.enable_all().build() {
+        Ok(runtime) => tui::panic_guard::restore_on_panic(|| {
+            runtime.block_on(run_cli(cli))
+        }),
+        Err(e) => {
+            eprintln!("Failed to start the async runtime: {}", e);
+            process::exit(exit_codes::UNKNOWN_ERROR);
+        }
+    }
+}
+
+async fn run_cli(cli: Cli) {
+    env_logger::Builder::new()
+        .filter_level(cli.verbose.log_level_filter())
+        .init();
+
+    if let Some(resolver) = cli.resolver {
+        use_name_server(resolver);
+    }
+    use_tls_cipher(cli.tls_cipher);
+    use_tls_backend(cli.tls_backend);
+    let client = cli.client_cert.as_deref().zip(cli.client_key.as_deref());
+    match TlsCertificates::load(cli.cacert.as_deref(), client) {
+        Ok(certificates) => use_certificates(certificates),
+        Err(error) => {
+            print_error(&error, cli.format_name() == "json");
+            process::exit(error.exit_code());
+        }
+    }
+    if cli.tls_backend == TlsBackend::Native {
+        if cli.tls_cipher != TlsCipher::Auto {
+            log::warn!("--tls-cipher has no effect with the native backend");
+        }
+        if cli.prewarm_tls {
+            log::warn!("The native TLS backend never resumes sessions");
+        }
+    }
+
+    if let Some(Command::Doctor) = cli.command {
+        let code = match doctor::run(&mut io::stdout()).await {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("Failed to write report: {}", e);
+                exit_codes::UNKNOWN_ERROR
+            }
+        };
+        process::exit(code);
+    }
+
+    if let Some(Command::Schema) = cli.command {
+        let code = match writeln!(io::stdout(), "{}", JSON_SCHEMA.trim_end()) {
+            Ok(()) => exit_codes::SUCCESS,
+            Err(e) => {
+                eprintln!("Failed to write schema: {}", e);
+                exit_codes::UNKNOWN_ERROR
+            }
+        };
+        process::exit(code);
+    }
+
+    if let Some(Command::Verify { ref file, ref public_key }) = cli.command {
+        process::exit(run_verify(file, public_key.as_deref()));
+    }
+
+    // Every streamed object has to fit on one line
+    let registry = ReporterRegistry::builtin(
+        cli.pretty && !cli.stream,
+        !cli.no_estimates,
+        cli.sparkline,
+    );
+    let reporter = match registry.get(cli.format_name()) {
+        Some(reporter) => reporter,
+        None => {
+            let error = SpeedTestError::config(format!(
+                "Unknown output format '{}'",
+                cli.format_name()
+            ))
+            .with_suggestion(format!(
+                "Available formats: {}",
+                registry.names().join(", ")
+            ));
+            print_error(&error, false);
+            process::exit(error.exit_code());
+        }
+    };
+    let json_mode = reporter.name() == "json";
+    if cli.stream && !json_mode {
+        let error = SpeedTestError::config("--stream requires JSON output")
+            .with_suggestion("Add --json");
+        print_error(&error, false);
+        process::exit(error.exit_code());
+    }
+
+    if let Some(Command::Decode { ref snippet }) = cli.command {
+        process::exit(run_decode(snippet, json_mode, cli.pretty));
+    }
+
+    if let Some(Command::Diff { ref before, ref after }) = cli.command {
+        process::exit(run_diff(before, after, json_mode, cli.pretty));
+    }
+
+    let config = match Config::load(cli.config.as_deref()) {
+        Ok(config) => config,
+        Err(error) => {
+            print_error(&error, json_mode);
+            process::exit(error.exit_code());
+        }
+    };
+
+    if let Some(Command::History { ref action }) = cli.command {
+        process::exit(run_history(&cli, &config, action, json_mode).await);
+    }
+
+    if let Some(Command::Score {
+        ref input,
+        download,
+        upload,
+        latency,
+        jitter,
+        loss,
+        loaded_down,
+        loaded_up,
+    }) = cli.command
+    {
+        let source = match (input, download, upload, latency) {
+            (Some(path), ..) => ScoreSource::Results(path),
+            (None, Some(download), Some(upload), Some(latency)) => {
+                let metrics =
+                    ConnectionMetrics::new(download, upload, latency, jitter)
+                        .with_loaded_latency(loaded_down, loaded_up);
+                ScoreSource::Metrics(match loss {
+                    Some(loss) => metrics.with_packet_loss(loss),
+                    None => metrics,
+                })
+            }
+            // clap requires either --input or all three metrics
+            _ => unreachable!("score needs --input or metrics"),
+        };
+        process::exit(run_score(
+            source,
+            &config.scoring,
+            reporter,
+            json_mode,
+            cli.pretty,
+        ));
+    }
+
+    let signing_key = match cli.sign_key {
+        Some(ref path) => match SigningKey::load(path) {
+            Ok(key) => Some(key),
+            Err(error) => {
+                print_error(&error, json_mode);
+                process::exit(error.exit_code());
+            }
+        },
+        None => None,
+    };
+
+    if let Some(Command::Serve {
+        listen,
+        ref coordinator,
+        ref advertise,
+        log_sink,
+    }) = cli.command
+    {
+        let serve_config = serve::ServeConfig {
+            coordinator: coordinator.clone(),
+            advertise: advertise.clone(),
+            log_sink,
+            ..cli.serve_config(&config)
+        };
+        if let Err(e) = serve::run(listen, serve_config).await {
+            let error = SpeedTestError::config(e.to_string());
+            print_error(&error, json_mode);
+            process::exit(error.exit_code());
+        }
+        return;
+    }
+
+    #[cfg(all(windows, feature = "service"))]
+    if let Some(Command::Service { ref action }) = cli.command {
+        process::exit(run_service(&cli, &config, action, json_mode));
+    }
+
+    if let Some(Command::Fleet { listen, agents, wait, timeout }) = cli.command
+    {
+        let fleet_config = serve::fleet::FleetConfig {
+            agents,
+            wait: Duration::from_secs(wait),
+            timeout: Duration::from_secs(timeout),
+        };
+        process::exit(
+            run_fleet(listen, fleet_config, json_mode, cli.pretty).await,
+        );
+    }
+
+    if let Some(Command::Ab { ref config_a, ref config_b, runs, sequential }) =
+        cli.command
+    {
+        let options = ab::AbOptions { runs: runs as usize, sequential };
+        let code = match load_ab_arms(&cli, [config_a, config_b]) {
+            Ok(arms) => run_ab(&cli, arms, options, json_mode).await,
+            Err(error) => {
+                print_error(&error, json_mode);
+                error.exit_code()
+            }
+        };
+        process::exit(code);
+    }
+
+    if let Some(Command::Meta) = cli.command {
+        process::exit(run_meta(&cli, &config, json_mode).await);
+    }
+
+    if let Some(Command::Ping { interval, count }) = cli.command {
+        let options = ping::PingOptions { interval, count };
+        process::exit(run_ping(&cli, &config, options, json_mode).await);
+    }
+
+    if let Some(Command::Locations { ref country, nearest, limit }) =
+        cli.command
+    {
+        let options = locations::LocationsOptions {
+            country: country.clone(),
+            nearest,
+            limit,
+        };
+        process::exit(run_locations(&cli, &config, options, json_mode).await);
+    }
+
+    if cli.compare_vpn {
+        let arms = match vpn_ab_arms(&cli, &config) {
+            Ok(arms) => arms,
+            Err(error) => {
+                print_error(&error, json_mode);
+                process::exit(error.exit_code());
+            }
+        };
+
+        let organization = cli
+            .client(&config.test)
+            .send(MetaRequest {})
+            .await
+            .map(|meta| meta.as_organization)
+            .ok();
+        // A path MTU is only measured with --mtu, which needs ICMP access
+        let test_config = cli.test_config(&config.test);
+        let path_mtu = if test_config.path_mtu {
+            TestEngine::new(test_config, None)
+                .run_path_mtu()
+                .await
+                .map(|path_mtu| path_mtu.bytes)
+                .map_err(|e| {
+                    log::warn!("Path MTU discovery unavailable: {}", e)
+                })
+                .ok()
+        } else {
+            None
+        };
+        let detection = vpn::detect(organization.as_deref(), path_mtu);
+        if detection.suspected {
+            eprintln!(
+                "VPN detected ({}); comparing tests with and without it",
+                detection.signals.join(", ")
+            );
+            let options =
+                ab::AbOptions { runs: ab::DEFAULT_RUNS, sequential: false };
+            process::exit(run_ab(&cli, arms, options, json_mode).await);
+        }
+        log::warn!("No VPN detected; running a single test");
+    }
+
+    if let Some(runs) = cli.repeat {
+        let options =
+            repeat::RepeatOptions { runs: runs as usize, tags: cli.tags() };
+        process::exit(run_repeat(&cli, &config, options, json_mode).await);
+    }
+
+    // Detect display mode based on CLI flags and terminal capabilities
+    let is_tty = io::stdout().is_terminal();
+    let display_mode =
+        DisplayMode::detect(reporter.is_machine_readable(), is_tty);
+
+    // Create shutdown flag for signal handling
+    let shutdown_flag = Arc::new(AtomicBool::new(false));
+
+    // Create TUI controller
+    let mut tui = match TuiController::new(display_mode) {
+        Ok(tui) => tui
+            .with_refresh_interval(config.tui.refresh_interval())
+            .with_capabilities(cli.terminal_capabilities()),
+        Err(e) => {
+            // Fall back to silent mode if TUI initialization fails
+            eprintln!("Warning: TUI initialization failed: {}", e);
+            TuiController::new(DisplayMode::Silent)
+                .expect("Silent mode should always succeed")
+        }
+    };
+
+    // Initialize TUI (enters alternate screen in TUI mode)
+    if let Err(e) = tui.init() {
+        eprintln!("Warning: TUI init failed: {}", e);
+    }
+
+    // Set up SIGINT handler for graceful cleanup
+    let shutdown_flag_clone = Arc::clone(&shutdown_flag);
+    let signal_handler = setup_signal_handler(shutdown_flag_clone);
+
+    // Run speed test with retest loop support
+    let exit_code = loop {
+        match run_speed_test_with_tui(
+            &cli,
+            &config,
+            reporter,
+            signing_key.as_ref(),
+            &mut tui,
+            &shutdown_flag,
+        )
+        .await
+        {
+            Ok(code) => break code,
+            Err(e) => {
+                // Check if this is a retest request
+                if e.to_string() == "__RETEST__" {
+                    // Continue the loop to run another test
+                    continue;
+                }
+
+                // Check if this was a user-initiated shutdown
+                if shutdown_flag.load(Ordering::Relaxed) {
+                    // User pressed Ctrl+C, clean up gracefully
+                    // Get partial results before cleanup
+                    let partial_results = tui.get_partial_results();
+                    let _ = tui.cleanup();
+                    print_interrupted_message(json_mode, partial_results);
+                    break exit_codes::INTERRUPTED;
+                } else {
+                    if cli.bell {
+                        ring_bell();
+                    }
+                    let error = create_user_error(e.as_ref());
+
+                    // In TUI mode, display error in the TUI before cleanup
+                    if tui.mode() == DisplayMode::Tui {
+                        // Set error state in TUI to display with red styling
+                        tui.set_error(
+                            error.message.clone(),
+                            error.suggestion.clone(),
+                        );
+                        // Render the error in TUI
+                        let _ = tui.render();
+                        // Wait a moment for user to see the error
+                        tokio::time::sleep(tokio::time::Duration::from_secs(
+                            2,
+                        ))
+                        .await;
+                    }
+
+                    // Clean up TUI before printing error to terminal
+                    let _ = tui.cleanup();
+                    print_error(&error, json_mode);
+                    break error.exit_code();
+                }
+            }
+        }
+    };
+
+    // Clean up TUI (restores terminal state)
+    let _ = tui.cleanup();
+
+    // Drop the signal handler
+    drop(signal_handler);
+
+    process::exit(exit_code);
+}
+
+/// Set up a signal handler for SIGINT (Ctrl+C).
+///
+/// This function spawns a task that listens for SIGINT and sets the
+/// shutdown flag when received. This allows for graceful cleanup of
+/// the TUI and printing of partial results.
+///
+/// # Arguments
+/// * `shutdown_flag` - An atomic boolean that will be set to true on SIGINT
+///
+/// # Returns
+/// A JoinHandle for the signal handler task.
+///
+/// # Requirements
+/// _Requirements: 8.2, 8.3_
+fn setup_signal_handler(
+    shutdown_flag: Arc<AtomicBool>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        // Wait for SIGINT (Ctrl+C)
+        #[cfg(unix)]
+        {
+            let mut sigint = tokio::signal::unix::signal(
+                tokio::signal::unix::SignalKind::interrupt(),
+            )
+            .expect("Failed to set up SIGINT handler");
+            sigint.recv().await;
+        }
+
+        #[cfg(windows)]
+        {
+            tokio::signal::ctrl_c()
+                .await
+                .expect("Failed to set up Ctrl+C handler");
+        }
+
+        // Set the shutdown flag
+        shutdown_flag.store(true, Ordering::Relaxed);
+    })
+}
+
+/// Print a message indicating the test was interrupted.
+///
+/// If partial results are available, they will be printed as well.
+///
+/// # Arguments
+/// * `json_mode` - Whether to output in JSON format
+/// * `partial_results` - Optional partial results collected before interruption
+fn print_interrupted_message(
+    json_mode: bool,
+    partial_results: Option<crate::tui::PartialResults>,
+) {
+    let mut out = Vec::new();
+    if json_mode {
+        let error_json = if let Some(ref results) = partial_results {
+            serde_json::json!({
+                "error": {
+                    "kind": "Interrupted",
+                    "message": "Speed test interrupted by user",
+                    "suggestion": null,
+                },
+                "partial_results": {
+                    "latency_ms": results.latency_median_ms,
+                    "jitter_ms": results.latency_jitter_ms,
+                    "download_mbps": results.download_speed_mbps,
+                    "upload_mbps": results.upload_speed_mbps,
+                    "phase": format!("{:?}", results.phase),
+                }
+            })
+        } else {
+            serde_json::json!({
+                "error": {
+                    "kind": "Interrupted",
+                    "message": "Speed test interrupted by user",
+                    "suggestion": null,
+                }
+            })
+        };
+        let _ = writeln!(
+            out,
+            "{}",
+            serde_json::to_string(&error_json).unwrap_or_default()
+        );
+    } else {
+        let _ = writeln!(
+            out,
+            "\n{}",
+            "Speed test interrupted by user (Ctrl+C)".yellow()
+        );
+
+        // Print partial results if available
+        if let Some(results) = partial_results {
+            let _ = writeln!(out, "\n{}", "Partial results:".bold().white());
+
+            if let Some(latency) = results.latency_median_ms {
+                let _ = writeln!(
+                    out,
+                    "  {} {}",
+                    "Latency:".white(),
+                    format!("{:.2} ms", latency).bright_red()
+                );
+            }
+
+            if let Some(jitter) = results.latency_jitter_ms {
+                let _ = writeln!(
+                    out,
+                    "  {} {}",
+                    "Jitter:".white(),
+                    format!("{:.2} ms", jitter).bright_red()
+                );
+            }
+
+            if let Some(download) = results.download_speed_mbps {
+                let status = if results.download_completed {
+                    ""
+                } else {
+                    " (incomplete)"
+                };
+                let _ = writeln!(
+                    out,
+                    "  {} {}{}",
+                    "Download:".white(),
+                    format!("{:.2} Mbps", download).bright_cyan(),
+                    status.yellow()
+                );
+            }
+
+            if let Some(upload) = results.upload_speed_mbps {
+                let status = if results.upload_completed {
+                    ""
+                } else {
+                    " (incomplete)"
+                };
+                let _ = writeln!(
+                    out,
+                    "  {} {}{}",
+                    "Upload:".white(),
+                    format!("{:.2} Mbps", upload).bright_cyan(),
+                    status.yellow()
+                );
+            }
+        }
+    }
+
+    let _ = exit::write_stderr(out);
+}
+
+/// Check the signature of a result file and report the outcome.
+///
+/// # Returns
+/// The exit code: success if the signature is valid.
+fn run_verify(file: &std::path::Path, public_key: Option<&str>) -> i32 {
+    let contents = if file.as_os_str() == "-" {
+        io::read_to_string(io::stdin())
+    } else {
+        std::fs::read_to_string(file)
+    };
+    let document = contents
+        .map_err(|e| format!("Failed to read {}: {}", file.display(), e))
+        .and_then(|contents| {
+            serde_json::from_str::<serde_json::Value>(&contents).map_err(|e| {
+                format!("{} is not valid JSON: {}", file.display(), e)
+            })
+        });
+    let document = match document {
+        Ok(document) => document,
+        Err(message) => {
+            let error = SpeedTestError::config(message);
+            print_error(&error, false);
+            return error.exit_code();
+        }
+    };
+
+    match signing::verify(&document, public_key) {
+        Ok(signature) => {
+            println!(
+                "{} Signature valid (key {})",
+                "✓".green(),
+                signature.public_key
+            );
+            match SpeedTestResults::from_value(document) {
+                Ok(results) => {
+                    let speeds: String = [
+                        ("down", results.download.as_ref()),
+                        ("up", results.upload.as_ref()),
+                    ]
+                    .iter()
+                    .filter_map(|(direction, bandwidth)| {
+                        Some(format!(
+                            "{:.2} Mbps {}, ",
+                            (*bandwidth)?.speed_mbps,
+                            direction
+                        ))
+                    })
+                    .collect();
+                    println!(
+                        "  {} via {}: {}{:.2} ms",
+                        results.timestamp.to_rfc3339(),
+                        results.server.iata,
+                        speeds,
+                        results.latency.idle_ms
+                    )
+                }
+                Err(e) => log::warn!("Could not read the results: {}", e),
+            }
+            exit_codes::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{} {}", "✗".red(), e);
+            exit_codes::VERIFICATION_FAILED
+        }
+    }
+}
+
+/// What `score` rescores.
+enum ScoreSource<'a> {
+    /// A saved results document
+    Results(&'a std::path::Path),
+    /// Metrics given as flags
+    Metrics(ConnectionMetrics),
+}
+
+/// Recalculate scores against `thresholds`.
+///
+/// Saved results are written back out, in the selected format, with their
+/// scores replaced; metrics from flags print just the scores.
+fn run_score(
+    source: ScoreSource,
+    thresholds: &ScoringThresholds,
+    reporter: &dyn Reporter,
+    json_mode: bool,
+    pretty: bool,
+) -> i32 {
+    let metrics = match source {
+        ScoreSource::Results(file) => {
+            let results = match read_results(file) {
+                Ok(results) => results,
+                Err(message) => {
+                    let error = SpeedTestError::config(message);
+                    print_error(&error, json_mode);
+                    return error.exit_code();
+                }
+            };
+            if results.signature.is_some() {
+                log::warn!(
+                    "Dropping the signature, which rescoring invalidates"
+                );
+            }
+            let results = results.rescored(thresholds);
+            if let Err(e) = reporter.report(&results, &mut io::stdout()) {
+                eprintln!("Failed to write results: {}", e);
+                return exit_codes::UNKNOWN_ERROR;
+            }
+            return exit_codes::SUCCESS;
+        }
+        ScoreSource::Metrics(metrics) => metrics,
+    };
+
+    let scores = AimScoresOutput::score(&metrics, thresholds);
+    let written =
+        reporters::write_report(&scores, json_mode, pretty, &mut io::stdout());
+    if let Err(e) = written {
+        eprintln!("Failed to write scores: {}", e);
+        return exit_codes::UNKNOWN_ERROR;
+    }
+
+    exit_codes::SUCCESS
+}
+
+/// Read a results document from `file`, or stdin for `-`.
+fn read_results(file: &std::path::Path) -> Result<SpeedTestResults, String> {
+    let contents = if file.as_os_str() == "-" {
+        io::read_to_string(io::stdin())
+    } else {
+        std::fs::read_to_string(file)
+    }
+    .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+    let document = serde_json::from_str::<serde_json::Value>(&contents)
+        .map_err(|e| format!("{} is not valid JSON: {}", file.display(), e))?;
+    SpeedTestResults::from_value(document).map_err(|e| {
+        format!("{} is not a results file: {}", file.display(), e)
+    })
+}
+
+/// Install, remove or run the Windows service.
+///
+/// The service is installed to run with this command line, `install`
+/// replaced by `run`, so the flags given at install time apply to every
+/// test it runs.
+#[cfg(all(windows, feature = "service"))]
+fn run_service(
+    cli: &Cli,
+    config: &Config,
+    action: &ServiceAction,
+    json_mode: bool,
+) -> i32 {
+    use crate::service;
+
+    let outcome = match *action {
+        ServiceAction::Install { .. } => {
+            let mut arguments: Vec<_> = std::env::args_os().skip(1).collect();
+            if let Some(install) =
+                arguments.iter_mut().rev().find(|arg| *arg == "install")
+            {
+                *install = "run".into();
+            }
+            service::install(arguments)
+        }
+        ServiceAction::Uninstall => service::uninstall(),
+        ServiceAction::Run { listen } => {
+            let serve_config = serve::ServeConfig {
+                log_sink: Some(serve::sink::LogSink::EventLog),
+                ..cli.serve_config(config)
+            };
+            service::run(listen, serve_config)
+        }
+    };
+
+    match outcome {
+        Ok(()) => exit_codes::SUCCESS,
+        Err(e) => {
+            let error = SpeedTestError::config(format!("Service: {}", e));
+            print_error(&error, json_mode);
+            error.exit_code()
+        }
+    }
+}
+
+/// Run a fleet coordinator round and print the merged report.
+///
+/// # Returns
+/// The exit code: success if every probe completed, partial failure if
+/// only some did.
+async fn run_fleet(
+    listen: SocketAddr,
+    config: serve::fleet::FleetConfig,
+    json_mode: bool,
+    pretty: bool,
+) -> i32 {
+    let report = match serve::fleet::run(listen, config).await {
+        Ok(report) => report,
+        Err(e) => {
+            let error = SpeedTestError::config(e.to_string());
+            print_error(&error, json_mode);
+            return error.exit_code();
+        }
+    };
+
+    let written =
+        reporters::write_report(&report, json_mode, pretty, &mut io::stdout());
+    if let Err(e) = written {
+        eprintln!("Failed to write report: {}", e);
+        return exit_codes::UNKNOWN_ERROR;
+    }
+
+    match report.failures() {
+        0 => exit_codes::SUCCESS,
+        n if n == report.probes.len() => exit_codes::NETWORK_ERROR,
+        _ => exit_codes::PARTIAL_FAILURE,
+    }
+}
+
+/// Probe latency until stopped or `options.count` probes were sent, then
+/// print the session's statistics.
+///
+/// The TUI shows a live view; otherwise each probe is printed as a line,
+/// or nothing but the summary in JSON mode.
+async fn run_ping(
+    cli: &Cli,
+    config: &Config,
+    options: ping::PingOptions,
+    json_mode: bool,
+) -> i32 {
+    let display_mode =
+        DisplayMode::detect(json_mode, io::stdout().is_terminal());
+    let mut tui = match TuiController::new(display_mode) {
+        Ok(tui) => tui
+            .with_refresh_interval(config.tui.refresh_interval())
+            .with_capabilities(cli.terminal_capabilities()),
+        Err(e) => {
+            eprintln!("Warning: TUI initialization failed: {}", e);
+            TuiController::new(DisplayMode::Silent)
+                .expect("Silent mode should always succeed")
+        }
+    };
+
+    // Only to label the view; probing works without it
+    let client = cli.client(&config.test);
+    if let Ok(meta) = client.send(MetaRequest {}).await {
+        tui.set_metadata(
+            ServerInfo { city: meta.colo.city, iata: meta.colo.iata },
+            ConnectionInfo {
+                ip: meta.client_ip,
+                country: meta.country,
+                isp: meta.as_organization,
+                asn: meta.asn,
+            },
+        );
+    }
+
+    if let Err(e) = tui.init() {
+        eprintln!("Warning: TUI init failed: {}", e);
+    }
+    tui.start_ping(options.interval);
+
+    let shutdown_flag = Arc::new(AtomicBool::new(false));
+    let signal_handler = setup_signal_handler(Arc::clone(&shutdown_flag));
+
+    let engine = TestEngine::new(cli.test_config(&config.test), None);
+    let stats = ping::run(&engine, &options, |tick, stats| {
+        if let ping::PingTick::Probe(latency_ms) = tick {
+            tui.set_ping_stats(stats);
+            if display_mode == DisplayMode::Silent {
+                let _ = ping::print_probe(
+                    stats.sent(),
+                    latency_ms,
+                    &mut io::stdout(),
+                );
+            }
+        }
+        // Rendering also picks up key presses
+        let _ = tui.render();
+        if shutdown_flag.load(Ordering::Relaxed) || tui.stop_requested() {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    })
+    .await;
+
+    let _ = tui.cleanup();
+    signal_handler.abort();
+
+    let summary = stats.summary();
+    let written = reporters::write_report(
+        &summary,
+        json_mode,
+        cli.pretty,
+        &mut io::stdout(),
+    );
+    if let Err(e) = written {
+        eprintln!("Failed to write summary: {}", e);
+        return exit_codes::UNKNOWN_ERROR;
+    }
+
+    if summary.received == 0 && summary.sent > 0 {
+        exit_codes::NETWORK_ERROR
+    } else {
+        exit_codes::SUCCESS
+    }
+}
+
+/// Print where the client egresses from, without running any tests.
+async fn run_meta(cli: &Cli, config: &Config, json_mode: bool) -> i32 {
+    let client = cli.client(&config.test);
+    let meta = match client.send(MetaRequest {}).await {
+        Ok(meta) => meta,
+        Err(e) => {
+            let error = create_user_error(e.as_ref());
+            print_error(&error, json_mode);
+            return error.exit_code();
+        }
+    };
+
+    let mut egress = EgressResults {
+        connection: ConnectionMeta::new(
+            meta.client_ip,
+            meta.country,
+            meta.as_organization,
+            meta.asn,
+        )
+        .with_proxy(client.active_proxy()),
+        city: meta.city,
+        region: meta.region,
+        server: ServerLocation::new(meta.colo.city, meta.colo.iata),
+    };
+    if cli.anonymize {
+        egress.connection.anonymize();
+    }
+
+    let written = reporters::write_report(
+        &egress,
+        json_mode,
+        cli.pretty,
+        &mut io::stdout(),
+    );
+    if let Err(e) = written {
+        eprintln!("Failed to write connection info: {}", e);
+        return exit_codes::UNKNOWN_ERROR;
+    }
+
+    exit_codes::SUCCESS
+}
+
+/// Show the results in a `--share` snippet.
+fn run_decode(snippet: &str, json_mode: bool, pretty: bool) -> i32 {
+    let summary = match ShareSummary::decode(snippet) {
+        Ok(summary) => summary,
+        Err(e) => {
+            let error = SpeedTestError::config(e.to_string());
+            print_error(&error, json_mode);
+            return error.exit_code();
+        }
+    };
+
+    let written = reporters::write_report(
+        &summary,
+        json_mode,
+        pretty,
+        &mut io::stdout(),
+    );
+    if let Err(e) = written {
+        eprintln!("Failed to write summary: {}", e);
+        return exit_codes::UNKNOWN_ERROR;
+    }
+
+    exit_codes::SUCCESS
+}
+
+/// Compare the results in `before` and `after`.
+fn run_diff(
+    before: &std::path::Path,
+    after: &std::path::Path,
+    json_mode: bool,
+    pretty: bool,
+) -> i32 {
+    let read = |file: &std::path::Path| {
+        read_results(file).map(|results| {
+            let side = diff::DiffSide {
+                file: file.display().to_string(),
+                timestamp: results.timestamp,
+            };
+            (side, results)
+        })
+    };
+    let ((before, a), (after, b)) = match read(before)
+        .and_then(|before| read(after).map(|after| (before, after)))
+    {
+        Ok(runs) => runs,
+        Err(message) => {
+            let error = SpeedTestError::config(message);
+            print_error(&error, json_mode);
+            return error.exit_code();
+        }
+    };
+    let diff = diff::ResultsDiff::new(before, after, [&a, &b]);
+
+    let written =
+        reporters::write_report(&diff, json_mode, pretty, &mut io::stdout());
+    if let Err(e) = written {
+        eprintln!("Failed to write comparison: {}", e);
+        return exit_codes::UNKNOWN_ERROR;
+    }
+
+    exit_codes::SUCCESS
+}
+
+/// Prune, export or serve the run history.
+async fn run_history(
+    cli: &Cli,
+    config: &Config,
+    action: &HistoryAction,
+    json_mode: bool,
+) -> i32 {
+    let Some(store) = cli.history_store(&config.history) else {
+        let error = SpeedTestError::config(
+            "No data directory found; pass --history-file",
+        );
+        print_error(&error, json_mode);
+        return error.exit_code();
+    };
+
+    let written = match *action {
+        HistoryAction::Prune { max_entries, max_age, max_size_kb } => {
+            let configured = config.history.retention();
+            let retention = Retention {
+                max_entries: max_entries.or(configured.max_entries),
+                max_age: max_age.or(configured.max_age),
+                max_bytes: max_size_kb
+                    .map(|kb| kb * 1024)
+                    .or(configured.max_bytes),
+            };
+            if retention.is_unlimited() {
+                let error = SpeedTestError::config(
+                    "No retention limits set; pass --max-entries, \
+                     --max-age or --max-size-kb, or set them in the config \
+                     file's [history] section",
+                );
+                print_error(&error, json_mode);
+                return error.exit_code();
+            }
+            store.prune(retention).and_then(|removed| {
+                if json_mode {
+                    writeln!(
+                        io::stdout(),
+                        "{}",
+                        serde_json::json!({ "removed": removed })
+                    )
+                } else {
+                    writeln!(
+                        io::stdout(),
+                        "Removed {} runs from {}",
+                        removed,
+                        store.path().display()
+                    )
+                }
+            })
+        }
+        HistoryAction::ServeJson { listen, grafana } => {
+            return match serve::datasource::run(listen, store, grafana).await {
+                Ok(()) => exit_codes::SUCCESS,
+                Err(e) => {
+                    let error = create_user_error(e.as_ref());
+                    print_error(&error, json_mode);
+                    error.exit_code()
+                }
+            };
+        }
+        HistoryAction::Export { since, format } => {
+            let entries = match since {
+                Some(age) => store.load_since(chrono::Utc::now() - age),
+                None => store.load(),
+            };
+            entries.and_then(|entries| {
+                history::export(&entries, format, &mut io::stdout())
+            })
+        }
+    };
+
+    if let Err(e) = written {
+        eprintln!(
+            "Failed to access history {}: {}",
+            store.path().display(),
+            e
+        );
+        return exit_codes::UNKNOWN_ERROR;
+    }
+    exit_codes::SUCCESS
+}
+
+/// Print a `--share` snippet for `results`, or the link to it when a
+/// paste endpoint is configured. Goes to stderr in JSON mode so the
+/// results document stays the only thing on stdout.
+async fn share_results(
+    results: &SpeedTestResults,
+    settings: &ShareSettings,
+    json_mode: bool,
+) {
+    let Some(summary) = ShareSummary::from_results(results) else {
+        log::warn!("Nothing to share from a run that skipped a direction");
+        return;
+    };
+    let snippet = summary.encode();
+    let shared = match settings.endpoint.as_deref() {
+        Some(endpoint) => {
+            match share::upload(endpoint, &snippet, settings.request_headers())
+                .await
+            {
+                Ok(link) => link,
+                Err(e) => {
+                    log::warn!("Failed to share via {}: {}", endpoint, e);
+                    snippet
+                }
+            }
+        }
+        None => snippet,
+    };
+
+    if json_mode {
+        eprintln!("Share: {}", shared);
+    } else {
+        // A closed stdout has already failed the report
+        let _ =
+            writeln!(io::stdout(), "{} {}", "Share:".bold().white(), shared);
+    }
+}
+
+/// Compare `results` with the medians of their region for
+/// `--compare-region`. Failing to get the medians only warns, since the
+/// run itself succeeded.
+async fn compare_region(
+    results: &SpeedTestResults,
+    scope: RegionScope,
+    settings: &RegionalSettings,
+) -> Option<RegionalComparison> {
+    let Some(region) = regional::region_of(results, scope) else {
+        log::warn!("The results don't record the client's {}", scope);
+        return None;
+    };
+    let token = settings.token.clone().or_else(|| {
+        std::env::var(regional::TOKEN_ENV).ok().filter(|t| !t.is_empty())
+    });
+    if token.is_none() && settings.endpoint.is_none() {
+        log::warn!(
+            "Comparing with regional medians needs a Cloudflare API token \
+             with Radar read access: set {} or [regional] token",
+            regional::TOKEN_ENV
+        );
+        return None;
+    }
+
+    let endpoint =
+        settings.endpoint.as_deref().unwrap_or(regional::RADAR_ENDPOINT);
+    let source = url::Url::parse(endpoint)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| endpoint.to_string());
+    let headers = settings.request_headers();
+    match regional::fetch(endpoint, token.as_deref(), scope, &region, headers)
+        .await
+    {
+        Ok(medians) => Some(RegionalComparison::new(
+            scope, region, source, results, &medians,
+        )),
+        Err(e) => {
+            log::warn!(
+                "Failed to get regional medians for {} from {}: {}",
+                region,
+                source,
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Print Cloudflare's test locations.
+async fn run_locations(
+    cli: &Cli,
+    config: &Config,
+    options: locations::LocationsOptions,
+    json_mode: bool,
+) -> i32 {
+    let client = cli.client(&config.test);
+    let colos = match locations::list(&client, &options).await {
+        Ok(colos) => colos,
+        Err(e) => {
+            let error = create_user_error(e.as_ref());
+            print_error(&error, json_mode);
+            return error.exit_code();
+        }
+    };
+
+    let written = reporters::write_report(
+        &colos,
+        json_mode,
+        cli.pretty,
+        &mut io::stdout(),
+    );
+    if let Err(e) = written {
+        eprintln!("Failed to write locations: {}", e);
+        return exit_codes::UNKNOWN_ERROR;
+    }
+
+    exit_codes::SUCCESS
+}
+
+/// Build the two sides of an A/B comparison from config files.
+fn load_ab_arms(
+    cli: &Cli,
+    paths: [&std::path::PathBuf; 2],
+) -> Result<[ab::Arm; 2], SpeedTestError> {
+    let arm = |label: &str, path: &std::path::PathBuf| {
+        let config = Config::load(Some(path))?;
+        Ok(ab::Arm {
+            label: label.to_string(),
+            source: path.display().to_string(),
+            test_config: cli.test_config(&config.test),
+            hooks: config.ab,
+        })
+    };
+    Ok([arm("A", paths[0])?, arm("B", paths[1])?])
+}
+
+/// Build a comparison of testing through the VPN against testing without
+/// it, switching with the config file's `[vpn]` commands.
+///
+/// The VPN is brought back up after each direct run.
+fn vpn_ab_arms(
+    cli: &Cli,
+    config: &Config,
+) -> Result<[ab::Arm; 2], SpeedTestError> {
+    let (Some(up), Some(down)) = (&config.vpn.up, &config.vpn.down) else {
+        return Err(SpeedTestError::config(
+            "--compare-vpn needs commands to bring the VPN up and down",
+        )
+        .with_suggestion(
+            "Add a [vpn] section with `up` and `down` to the config file",
+        ));
+    };
+
+    let test_config = cli.test_config(&config.test);
+    Ok([
+        ab::Arm {
+            label: "vpn".to_string(),
+            source: up.clone(),
+            test_config: test_config.clone(),
+            hooks: AbHooks { before: Some(up.clone()), after: None },
+        },
+        ab::Arm {
+            label: "direct".to_string(),
+            source: down.clone(),
+            test_config,
+            hooks: AbHooks {
+                before: Some(down.clone()),
+                after: Some(up.clone()),
+            },
+        },
+    ])
+}
+
+/// Run an A/B comparison and print the report.
+///
+/// # Returns
+/// The exit code: success if every run completed, partial failure if
+/// some failed, and a network error if either configuration never ran.
+async fn run_ab(
+    cli: &Cli,
+    arms: [ab::Arm; 2],
+    options: ab::AbOptions,
+    json_mode: bool,
+) -> i32 {
+    let report = match ab::run(arms, options).await {
+        Ok(report) => report,
+        Err(e) => {
+            let error = SpeedTestError::config(e.to_string());
+            print_error(&error, json_mode);
+            return error.exit_code();
+        }
+    };
+
+    let written = reporters::write_report(
+        &report,
+        json_mode,
+        cli.pretty,
+        &mut io::stdout(),
+    );
+    if let Err(e) = written {
+        eprintln!("Failed to write report: {}", e);
+        return exit_codes::UNKNOWN_ERROR;
+    }
+
+    if report.incomplete() {
+        exit_codes::NETWORK_ERROR
+    } else if report.failures() > 0 {
+        exit_codes::PARTIAL_FAILURE
+    } else {
+        exit_codes::SUCCESS
+    }
+}
+
+/// Run the complete test several times and print every run with the
+/// aggregate.
+///
+/// Each run is recorded in the history like a single test would be.
+///
+/// # Returns
+/// The exit code: success if every run completed, partial failure if
+/// some failed, and a network error if none did.
+async fn run_repeat(
+    cli: &Cli,
+    config: &Config,
+    options: repeat::RepeatOptions,
+    json_mode: bool,
+) -> i32 {
+    let test_config = cli.test_config(&config.test);
+    let mut report =
+        match repeat::run(test_config, &config.scoring, options).await {
+            Ok(report) => report,
+            Err(e) => {
+                let error = create_user_error(e.as_ref());
+                print_error(&error, json_mode);
+                return error.exit_code();
+            }
+        };
+
+    for results in &mut report.runs {
+        if let Some(entry) = HistoryEntry::from_results(results) {
+            record_history(cli, config, &entry);
+        }
+        if cli.anonymize {
+            *results = results.clone().anonymized();
+        }
+    }
+
+    let written = reporters::write_report(
+        &report,
+        json_mode,
+        cli.pretty,
+        &mut io::stdout(),
+    );
+    if let Err(e) = written {
+        eprintln!("Failed to write report: {}", e);
+        return exit_codes::UNKNOWN_ERROR;
+    }
+
+    if cli.bell {
+        ring_bell();
+    }
+
+    if report.incomplete() {
+        exit_codes::NETWORK_ERROR
+    } else if report.failures > 0 {
+        exit_codes::PARTIAL_FAILURE
+    } else {
+        exit_codes::SUCCESS
+    }
+}
+
+/// Create a user-friendly error from a generic error.
+fn create_user_error(
+    error: &(dyn std::error::Error + 'static),
+) -> SpeedTestError {
+    let kind = classify_error(error);
+    let message = error.to_string();
+
+    match kind {
+        ErrorKind::Network => SpeedTestError::network(format!(
+            "Failed to connect to speed.cloudflare.com: {}",
+            message
+        )),
+        ErrorKind::Dns => SpeedTestError::dns(format!(
+            "Failed to resolve speed.cloudflare.com: {}",
+            message
+        )),
+        ErrorKind::Timeout => SpeedTestError::timeout(format!(
+            "Connection timed out: {}",
+            message
+        )),
+        ErrorKind::Tls => SpeedTestError::tls(format!(
+            "TLS/SSL connection failed: {}",
+            message
+        )),
+        ErrorKind::UntrustedCertificate => {
+            SpeedTestError::untrusted_certificate(format!(
+                "TLS/SSL connection failed: {}",
+                message
+            ))
+        }
+        ErrorKind::Intercepted => SpeedTestError::intercepted(message),
+        ErrorKind::RateLimited => SpeedTestError::rate_limited(message),
+        ErrorKind::Warp => SpeedTestError::warp(message),
+        ErrorKind::Api => {
+            SpeedTestError::api(format!("Cloudflare API error: {}", message))
+        }
+        _ => SpeedTestError::new(kind, message),
+    }
+}
+
+/// Print an error message to stderr.
+fn print_error(error: &SpeedTestError, json_mode: bool) {
+    let mut out = Vec::new();
+    if json_mode {
+        // Output error as JSON
+        let error_json = serde_json::json!({
+            "error": {
+                "kind": format!("{:?}", error.kind),
+                "message": error.message,
+                "suggestion": error.suggestion,
+            }
+        });
+        let _ = writeln!(
+            out,
+            "{}",
+            serde_json::to_string(&error_json).unwrap_or_default()
+        );
+    } else {
+        // Output human-readable error
+        let _ = writeln!(out, "{}", format_error_for_display(error).red());
+    }
+
+    let _ = exit::write_stderr(out);
+}
+
+/// Run the speed test with TUI integration.
+///
+/// This function integrates the TuiController for real-time progress display.
+/// In TUI mode, it shows live updates during the test. In JSON mode, it
+/// suppresses all output until the final JSON result.
+///
+/// # Arguments
+/// * `cli` - Command line arguments
+/// * `config` - Settings from the config file
+/// * `reporter` - Reporter for the final results
+/// * `signing_key` - Key to sign the results with, if any
+/// * `tui` - TUI controller for display
+/// * `shutdown_flag` - Atomic flag to check for user interruption
+///
+/// # Returns
+/// The exit code for a completed run.
+///
+/// # Requirements
+/// _Requirements: 1.1, 1.2, 1.3, 2.1, 2.2, 2.3_
+async fn run_speed_test_with_tui(
+    cli: &Cli,
+    config: &Config,
+    reporter: &dyn Reporter,
+    signing_key: Option<&SigningKey>,
+    tui: &mut TuiController,
+    shutdown_flag: &Arc<AtomicBool>,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    // Check for shutdown before starting
+    if shutdown_flag.load(Ordering::Relaxed) {
+        return Err("Interrupted by user".into());
+    }
+
+    if let Some(profile) = cli.simulate {
+        let simulation = Simulation::new(
+            profile,
+            Duration::from_secs(cli.simulate_duration),
+        )
+        .with_sizes(cli.download_sizes.0.clone(), cli.upload_sizes.0.clone());
+        return run_simulation_with_tui(
+            cli,
+            config,
+            reporter,
+            &simulation,
+            tui,
+            shutdown_flag,
+        )
+        .await;
+    }
+
+    let client = cli.client(&config.test);
+    let proxy = client.active_proxy();
+    if let Some(ref proxy) = proxy {
+        log::info!("Using proxy {}", proxy);
+    }
+    let user_agent =
+        String::from_utf8_lossy(client.user_agent().as_bytes()).into_owned();
+    log::info!("User-Agent: {}", user_agent);
+
+    // Look for a captive portal or intercepting proxy before testing
+    let interception = check_interception().await;
+    if let Some(ref reason) = interception.reason {
+        tui.set_warning(format!("Captive portal suspected: {}", reason));
+    }
+
+    // Fetch connection metadata
+    let (meta, server_date) =
+        client.send_dated(MetaRequest {}).await.map_err(|e| {
+            interception.attribute(format!(
+                "Failed to fetch connection metadata: {}",
+                e
+            ))
+        })?;
+
+    // Through WARP, the test measures Cloudflare's network, not the ISP
+    let warp = vpn::is_warp(meta.asn);
+    if warp {
+        if cli.require_direct {
+            return Err(SpeedTestError::warp(format!(
+                "Traffic leaves through Cloudflare WARP (AS{}), so the test \
+                 would measure WARP rather than your ISP",
+                meta.asn
+            ))
+            .into());
+        }
+        tui.set_warning(
+            "Connected through Cloudflare WARP; results measure WARP, not \
+             your ISP"
+                .to_string(),
+        );
+    }
+
+    // A wrong clock skews result timestamps and can break TLS
+    let clock_skew =
+        server_date.map(|date| skew_secs(chrono::Utc::now(), date));
+    if let Some(skew) = clock_skew.filter(|s| s.abs() >= CLOCK_SKEW_WARN_SECS)
+    {
+        tui.set_warning(format!(
+            "{}; enable time synchronisation (NTP)",
+            describe_skew(skew)
+        ));
+    }
+
+    let location = client
+        .send(Locations {})
+        .await
+        .map_err(|e| {
+            interception
+                .attribute(format!("Failed to fetch server locations: {}", e))
+        })?
+        .get(&meta.colo.iata);
+
+    // Set metadata in TUI
+    let server_info = ServerInfo {
+        city: location.city.clone(),
+        iata: location.iata.clone(),
+    };
+    let connection_info = ConnectionInfo {
+        ip: meta.client_ip.clone(),
+        country: meta.country.clone(),
+        isp: meta.as_organization.clone(),
+        asn: meta.asn,
+    };
+    tui.set_metadata(server_info, connection_info);
+
+    // Initial render to show metadata
+    tui.render()?;
+
+    // Get progress callback for the test engine
+    let mut progress_callback = tui.progress_callback();
+    if cli.stream {
+        progress_callback = Arc::new(PhaseStream::new(progress_callback));
+    }
+
+    // Run the test engine with progress callback
+    let test_config = cli.test_config(&config.test);
+    let dscp = test_config.socket_options.dscp;
+    let order = test_config.bandwidth_order();
+    let pacing_jitter = test_config.pacing_jitter.is_some();
+    let detailed = test_config.detailed;
+    tui.set_phases(test_config.phases);
+    let engine = TestEngine::new(test_config, Some(progress_callback))
+        .with_pause(tui.pause_flag());
+
+    // Create a render loop that updates the TUI during test execution
+    let output =
+        run_with_render_loop(engine.run(), tui, Arc::clone(shutdown_flag))
+            .await
+            .map_err(|e| interception.attribute(e))?;
+
+    // Check for shutdown after test completes
+    if shutdown_flag.load(Ordering::Relaxed) {
+        return Err("Interrupted by user".into());
+    }
+
+    // Run packet loss test if configured
+    let packet_loss_config = cli.packet_loss_config(&config.test);
+    let loss_provider = packet_loss_config.as_ref().map(|c| c.provider);
+    let packet_loss_result =
+        run_packet_loss_test_safe(packet_loss_config).await;
+
+    // Build result structures
+    let server =
+        ServerLocation::new(location.city.clone(), location.iata.clone())
+            .with_ip(output.endpoint_ip().map(|ip| ip.to_string()));
+    let connection = ConnectionMeta::new(
+        meta.client_ip.clone(),
+        meta.country.clone(),
+        meta.as_organization.clone(),
+        meta.asn,
+    )
+    .with_proxy(proxy);
+
+    let latency = LatencyResults::new(
+        output.latency.idle_ms,
+        output.latency.idle_jitter_ms,
+        output.latency.loaded_down_ms,
+        output.latency.loaded_down_jitter_ms,
+        output.latency.loaded_up_ms,
+        output.latency.loaded_up_jitter_ms,
+    )
+    .with_probe(output.latency.probe)
+    .with_load_streams(output.latency.load_streams);
+
+    let download = BandwidthResults::from_engine(&output.download)
+        .with_load_streams(output.latency.load_streams);
+    let upload = BandwidthResults::from_engine(&output.upload)
+        .with_load_streams(output.latency.load_streams);
+
+    let packet_loss = if packet_loss_result.is_available() {
+        Some(
+            PacketLossResults::new(
+                packet_loss_result.packet_loss_ratio,
+                packet_loss_result.packets_sent,
+                packet_loss_result.packets_lost,
+                packet_loss_result.packets_received,
+                packet_loss_result.avg_rtt_ms,
+            )
+            .with_provider(loss_provider)
+            .with_order(
+                packet_loss_result.reordered,
+                packet_loss_result.duplicates,
+            )
+            .with_batches(if detailed {
+                &packet_loss_result.batches
+            } else {
+                &[]
+            }),
+        )
+    } else {
+        None
+    };
+
+    // Calculate AIM scores
+    let metrics =
+        connection_metrics(&latency, &download, &upload, packet_loss.as_ref());
+
+    let scores = AimScoresOutput::score(&metrics, &config.scoring);
+
+    // Set quality scores and loaded latency in TUI before creating results
+    if output.phases == PhaseSelection::All {
+        tui.set_quality_scores(
+            &scores.streaming,
+            &scores.gaming,
+            &scores.video_conferencing,
+        );
+        if let Some(details) = scores.details {
+            tui.set_score_details(details);
+        }
+        if let Some(grade) = scores.grade.clone() {
+            tui.set_grade(grade);
+        }
+    }
+    tui.set_loaded_latency(
+        latency.loaded_down_ms,
+        latency.loaded_down_jitter_ms,
+        latency.loaded_up_ms,
+        latency.loaded_up_jitter_ms,
+    );
+    tui.set_ttfb(
+        output.ttfb.map(|ttfb| ttfb.median_ms),
+        output.ttfb.map(|ttfb| ttfb.p95_ms),
+    );
+
+    let results = SpeedTestResults::new(
+        server,
+        connection,
+        latency.clone(),
+        download.clone(),
+        upload.clone(),
+        packet_loss.clone(),
+        scores,
+    )
+    .with_phases(output.phases)
+    .with_meas_id(output.meas_id.clone())
+    .with_dns(output.dns.as_ref().map(DnsResults::from_engine))
+    .with_dual_stack(
+        output.dual_stack.as_ref().map(DualStackResults::from_engine),
+    )
+    .with_tls(output.tls.as_ref().map(TlsResults::from_engine))
+    .with_pacing(output.pacing.as_ref().map(PacingResults::from_engine))
+    .with_profile(output.profile.as_ref().map(ProfileResults::from_engine))
+    .with_path_mtu(output.path_mtu.as_ref().map(PathMtuResults::from_engine))
+    .with_handshake(
+        output.handshake.as_ref().map(HandshakeResults::from_engine),
+    )
+    .with_duplex(output.duplex.as_ref().map(DuplexResults::from_engine))
+    .with_responsiveness(
+        output.ttfb.as_ref().map(ResponsivenessResults::from_engine),
+    )
+    .with_meta(Some(
+        RunMeta::detect()
+            .with_tags(cli.tags())
+            .with_captive_portal_suspected(interception.is_suspected())
+            .with_vpn(vpn::detect(
+                Some(&meta.as_organization),
+                output.path_mtu.map(|path_mtu| path_mtu.bytes),
+            ))
+            .with_warp(warp)
+            .with_dscp(dscp)
+            .with_order(order)
+            .with_pacing_jitter(pacing_jitter)
+            .with_clock(clock_skew, output.clock_step)
+            .with_rate_limit(output.rate_limit.as_ref())
+            .with_network_changes(&output.network_changes)
+            .with_user_agent(detailed.then_some(user_agent)),
+    ));
+    let assertions = AssertionResult::check_all(
+        &results,
+        cli.assert_download,
+        cli.assert_upload,
+        cli.assert_latency,
+    );
+    let results = results.with_assertions(assertions);
+
+    let regression = HistoryEntry::from_results(&results)
+        .and_then(|entry| record_history(cli, config, &entry));
+    let exit_code = run_exit_code(cli, regression.as_ref(), &results);
+    let mut results = results.with_regression(regression);
+    if let Some(scope) = cli.compare_region {
+        let regional = compare_region(&results, scope, &config.regional).await;
+        results = results.with_regional(regional);
+    }
+    if cli.anonymize {
+        results = results.anonymized();
+    }
+    let results = match signing_key {
+        Some(key) => results.signed(key)?,
+        None => results,
+    };
+
+    present_results(cli, reporter, &results, tui, shutdown_flag)?;
+
+    if cli.share {
+        let json_mode = tui.mode() == DisplayMode::Json;
+        share_results(&results, &config.share, json_mode).await;
+    }
+
+    // Scripts may close stderr; the run itself still succeeded
+    let summary = format!("{}\n", summary_line(&results));
+    let _ = exit::write_stderr(summary.into_bytes());
+
+    Ok(exit_code)
+}
+
+/// Run a simulated test: the same TUI and reports as a real run, fed by
+/// made-up progress events and results instead of the network.
+///
+/// # Returns
+/// The exit code for a completed run, which honours the assertion flags.
+async fn run_simulation_with_tui(
+    cli: &Cli,
+    config: &Config,
+    reporter: &dyn Reporter,
+    simulation: &Simulation,
+    tui: &mut TuiController,
+    shutdown_flag: &Arc<AtomicBool>,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let server = simulation.server();
+    let connection = simulation.connection();
+    tui.set_metadata(
+        ServerInfo { city: server.city.clone(), iata: server.iata.clone() },
+        ConnectionInfo {
+            ip: connection.ip.clone(),
+            country: connection.country.clone(),
+            isp: connection.isp.clone().unwrap_or_default(),
+            asn: connection.asn.unwrap_or_default(),
+        },
+    );
+    tui.render()?;
+
+    let progress = tui.progress_callback();
+    let run = run_with_render_loop(
+        async { Ok(simulation.run(progress.as_ref()).await) },
+        tui,
+        Arc::clone(shutdown_flag),
+    )
+    .await?;
+    if shutdown_flag.load(Ordering::Relaxed) {
+        return Err("Interrupted by user".into());
+    }
+
+    let results = simulation.results(&run, &config.scoring).with_meta(Some(
+        RunMeta::detect().with_tags(cli.tags()).with_simulated(true),
+    ));
+    if let Some(scores) = &results.scores {
+        tui.set_quality_scores(
+            &scores.streaming,
+            &scores.gaming,
+            &scores.video_conferencing,
+        );
+        if let Some(details) = scores.details {
+            tui.set_score_details(details);
+        }
+        if let Some(grade) = scores.grade.clone() {
+            tui.set_grade(grade);
+        }
+    }
+    let latency = &results.latency;
+    tui.set_loaded_latency(
+        latency.loaded_down_ms,
+        latency.loaded_down_jitter_ms,
+        latency.loaded_up_ms,
+        latency.loaded_up_jitter_ms,
+    );
+
+    let assertions = AssertionResult::check_all(
+        &results,
+        cli.assert_download,
+        cli.assert_upload,
+        cli.assert_latency,
+    );
+    let mut results = results.with_assertions(assertions);
+    let exit_code = run_exit_code(cli, None, &results);
+    if cli.anonymize {
+        results = results.anonymized();
+    }
+
+    present_results(cli, reporter, &results, tui, shutdown_flag)?;
+
+    let summary = format!("{}\n", summary_line(&results));
+    let _ = exit::write_stderr(summary.into_bytes());
+
+    Ok(exit_code)
+}
+
+/// The exit code for a completed run: a regression against history
+/// first, then missed `--assert-*` thresholds, then the working latency
+/// check.
+fn run_exit_code(
+    cli: &Cli,
+    regression: Option<&RegressionResults>,
+    results: &SpeedTestResults,
+) -> i32 {
+    match regression {
+        Some(r) if r.has_anomalies() => {
+            log::warn!(
+                "Regression detected in: {}",
+                r.anomalous_metrics().join(", ")
+            );
+            exit_codes::REGRESSION_DETECTED
+        }
+        _ if results.assertions.iter().any(|a| !a.pass) => {
+            for assertion in results.assertions.iter().filter(|a| !a.pass) {
+                log::warn!("Threshold not met: {}", assertion.describe());
+            }
+            exit_codes::THRESHOLD_NOT_MET
+        }
+        _ if cli.assert_working_latency => working_latency_exit_code(
+            results
+                .scores
+                .as_ref()
+                .and_then(|scores| scores.working_latency.as_ref()),
+        ),
+        _ => exit_codes::SUCCESS,
+    }
+}
+
+/// Show the final results the way the display mode calls for: in the
+/// TUI until the user exits (then as a report), or straight as a report.
+///
+/// # Returns
+/// The `__RETEST__` error when the user asks the TUI for another run.
+fn present_results(
+    cli: &Cli,
+    reporter: &dyn Reporter,
+    results: &SpeedTestResults,
+    tui: &mut TuiController,
+    shutdown_flag: &Arc<AtomicBool>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if cli.bell {
+        ring_bell();
+    }
+
+    // Output results based on display mode
+    match tui.mode() {
+        DisplayMode::Json => {
+            // Clean up TUI before machine-readable output
+            tui.cleanup()?;
+            write_report(reporter, results)?;
+        }
+        DisplayMode::Tui => {
+            // Show final results in TUI
+            tui.show_results(results)?;
+
+            // Wait for user input - they can exit or request retest
+            match tui.wait_for_exit(shutdown_flag)? {
+                crate::tui::WaitResult::Retest => {
+                    // Don't cleanup - return special error to trigger retest
+                    return Err("__RETEST__".into());
+                }
+                crate::tui::WaitResult::Exit => {
+                    tui.cleanup()?;
+                    // Print summary after TUI cleanup
+                    write_report(reporter, results)?;
+                }
+            }
+        }
+        DisplayMode::Silent => {
+            // Silent mode: just print the report
+            write_report(reporter, results)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the final report to stdout, giving up when stdout doesn't take
+/// it in time.
+fn write_report(
+    reporter: &dyn Reporter,
+    results: &SpeedTestResults,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut out = Vec::new();
+    reporter.report(results, &mut out)?;
+    exit::write_stdout(out)?;
+    Ok(())
+}
+
+/// The exit code for `--assert-working-latency`: success only if loaded
+/// latency was measured and stayed under the targets.
+fn working_latency_exit_code(check: Option<&WorkingLatencyCheck>) -> i32 {
+    match check {
+        Some(check) if check.pass => exit_codes::SUCCESS,
+        Some(_) => {
+            log::warn!("Loaded latency exceeded the working latency targets");
+            exit_codes::WORKING_LATENCY_EXCEEDED
+        }
+        None => {
+            log::warn!("Loaded latency was not measured; can't check it");
+            exit_codes::WORKING_LATENCY_EXCEEDED
+        }
+    }
+}
+
+/// Ring the terminal bell.
+fn ring_bell() {
+    let _ = exit::write_stderr(b"\x07".to_vec());
+}
+
+/// Record a run in the history store and compare it with the baseline.
+///
+/// History problems are logged rather than failing the run, since the
+/// speed test itself succeeded.
+///
+/// # Returns
+/// The baseline comparison when `--detect-regression` is set.
+fn record_history(
+    cli: &Cli,
+    config: &Config,
+    entry: &HistoryEntry,
+) -> Option<RegressionResults> {
+    if !cli.save_history && !cli.detect_regression {
+        return None;
+    }
+
+    let store = cli.history_store(&config.history)?;
+
+    // Load the baseline before appending so the run isn't compared
+    // against itself.
+    let regression = if cli.detect_regression {
+        let cutoff = entry.timestamp
+            - chrono::Duration::days(
+                history::regression::BASELINE_WINDOW_DAYS,
+            );
+        match store.load_since(cutoff) {
+            Ok(baseline) => Some(detect_regressions(entry, &baseline)),
+            Err(e) => {
+                log::warn!(
+                    "Failed to read history {}: {}",
+                    store.path().display(),
+                    e
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    if let Err(e) = store.append(entry) {
+        log::warn!(
+            "Failed to write history {}: {}",
+            store.path().display(),
+            e
+        );
+    }
+
+    regression
+}
+
+/// Run a test with a render loop for TUI updates.
+///
+/// This function runs the test (the engine, or a simulation) while
+/// periodically rendering the TUI to show progress updates. It also
+/// checks for user interruption via the shutdown flag.
+///
+/// # Arguments
+/// * `test` - The test to run
+/// * `tui` - TUI controller for display
+/// * `shutdown_flag` - Atomic flag to check for user interruption
+///
+/// # Returns
+/// The test output, or an error if the test fails or is interrupted.
+///
+/// # Requirements
+/// _Requirements: 8.2, 8.3_
+async fn run_with_render_loop<T>(
+    test: impl std::future::Future<Output = Result<T, Box<dyn std::error::Error>>>,
+    tui: &mut TuiController,
+    shutdown_flag: Arc<AtomicBool>,
+) -> Result<T, Box<dyn std::error::Error>> {
+    use tokio::select;
+    use tokio::time::interval;
+
+    // Only run render loop in TUI mode
+    if tui.mode() != DisplayMode::Tui {
+        return test.await;
+    }
+
+    // Unchanged frames are skipped, so ticks are cheap when idle
+    let mut render_interval = interval(tui.refresh_interval());
+
+    tokio::pin!(test);
+
+    loop {
+        // Check for shutdown
+        if shutdown_flag.load(Ordering::Relaxed) {
+            return Err("Interrupted by user".into());
+        }
+
+        select! {
+            // Test engine completed
+            result = &mut test => {
+                // Final render
+                let _ = tui.render();
+                return result;
+            }
+            // Render tick
+            _ = render_interval.tick() => {
+                let _ = tui.render();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cloudflare::client::DEFAULT_USER_AGENT;
+    use proptest::prelude::*;
+
+    // Helper function to create test SpeedTestResults
+    fn create_test_results(
+        download_speed: f64,
+        upload_speed: f64,
+        latency_ms: f64,
+        jitter_ms: Option<f64>,
+    ) -> SpeedTestResults {
+        let server =
+            ServerLocation::new("Test City".to_string(), "TST".to_string());
+        let connection = ConnectionMeta::new(
+            "192.168.1.1".to_string(),
+            "US".to_string(),
+            "Test ISP".to_string(),
+            12345,
+        );
+        let latency =
+            LatencyResults::new(latency_ms, jitter_ms, None, None, None, None);
+        let download = BandwidthResults::new(download_speed, vec![], false);
+        let upload = BandwidthResults::new(upload_speed, vec![], false);
+        let scores = AimScoresOutput {
+            streaming: "good".to_string(),
+            gaming: "good".to_string(),
+            video_conferencing: "good".to_string(),
+            overall: "good".to_string(),
+            details: None,
+            working_latency: None,
+            grade: None,
+        };
+
+        SpeedTestResults::new(
+            server, connection, latency, download, upload, None, scores,
+        )
+    }
+
+    // Helper to check for TUI escape sequences
+    fn contains_escape_sequences(s: &str) -> bool {
+        // Common ANSI escape sequences used by TUI libraries
+        s.contains("\x1b[") || // CSI sequences
+        s.contains("\x1b]") || // OSC sequences
+        s.contains("\x1bP") || // DCS sequences
+        s.contains("\x1b\\") || // ST sequences
+        s.contains("\x1b(") || // Character set selection
+        s.contains("\x1b)") || // Character set selection
+        s.contains("\x1b*") || // Character set selection
+        s.contains("\x1b+") // Character set selection
+    }
+
+    // **Feature: tui-progress-display, Property 13: JSON Mode Output Correctness**
+    // **Validates: Requirements 10.1, 10.2, 10.3, 10.4**
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(100))]
+
+        /// Property: For any valid SpeedTestResults, when serialized to JSON:
+        /// - The output SHALL be valid JSON
+        /// - The JSON SHALL contain all required fields
+        /// - No TUI escape sequences SHALL appear in the output
+        #[test]
+        fn json_output_is_valid_and_complete(
+            download_speed in 0.0f64..1000.0,
+            upload_speed in 0.0f64..1000.0,
+            latency_ms in 0.1f64..500.0,
+            jitter_ms in proptest::option::of(0.1f64..100.0)
+        ) {
+            let results = create_test_results(
+                download_speed,
+                upload_speed,
+                latency_ms,
+                jitter_ms,
+            );
+
+            // Serialize to JSON (non-pretty)
+            let json = serde_json::to_string(&results);
+            prop_assert!(
+                json.is_ok(),
+                "Serialization should succeed"
+            );
+            let json_str = json.unwrap();
+
+            // Verify it's valid JSON by parsing it
+            let parsed: Result<serde_json::Value, _> =
+                serde_json::from_str(&json_str);
+            prop_assert!(
+                parsed.is_ok(),
+                "Output should be valid JSON: {}",
+                json_str
+            );
+
+            // Verify required fields are present
+            let value = parsed.unwrap();
+            prop_assert!(
+                value.get("timestamp").is_some(),
+                "JSON should contain timestamp field"
+            );
+            prop_assert!(
+                value.get("server").is_some(),
+                "JSON should contain server field"
+            );
+            prop_assert!(
+                value.get("connection").is_some(),
+                "JSON should contain connection field"
+            );
+            prop_assert!(
+                value.get("latency").is_some(),
+                "JSON should contain latency field"
+            );
+            prop_assert!(
+                value.get("download").is_some(),
+                "JSON should contain download field"
+            );
+            prop_assert!(
+                value.get("upload").is_some(),
+                "JSON should contain upload field"
+            );
+            prop_assert!(
+                value.get("scores").is_some(),
+                "JSON should contain scores field"
+            );
+
+            // Verify no TUI escape sequences
+            prop_assert!(
+                !contains_escape_sequences(&json_str),
+                "JSON output should not contain TUI escape sequences"
+            );
+        }
+
+        /// Property: Pretty-printed JSON is also valid and deserializable
+        #[test]
+        fn pretty_json_output_is_valid(
+            download_speed in 0.0f64..1000.0,
+            upload_speed in 0.0f64..1000.0,
+            latency_ms in 0.1f64..500.0
+        ) {
+            let results = create_test_results(
+                download_speed,
+                upload_speed,
+                latency_ms,
+                Some(latency_ms * 0.1),
+            );
+
+            // Serialize to pretty JSON
+            let json = serde_json::to_string_pretty(&results);
+            prop_assert!(
+                json.is_ok(),
+                "Pretty serialization should succeed"
+            );
+            let json_str = json.unwrap();
+
+            // Verify it's valid JSON
+            let parsed: Result<serde_json::Value, _> =
+                serde_json::from_str(&json_str);
+            prop_assert!(
+                parsed.is_ok(),
+                "Pretty output should be valid JSON"
+            );
+
+            // Verify no TUI escape sequences
+            prop_assert!(
+                !contains_escape_sequences(&json_str),
+                "Pretty JSON should not contain TUI escape sequences"
+            );
+        }
+
+        /// Property: JSON error output is valid JSON
+        #[test]
+        fn json_error_output_is_valid(
+            error_message in "[a-zA-Z0-9 ]{1,100}",
+            suggestion in proptest::option::of("[a-zA-Z0-9 ]{1,50}")
+        ) {
+            let error = SpeedTestError::new(
+                ErrorKind::Network,
+                error_message.clone(),
+            );
+
+            // Create error JSON as print_error does
+            let error_json = serde_json::json!({
+                "error": {
+                    "kind": format!("{:?}", error.kind),
+                    "message": error.message,
+                    "suggestion": suggestion,
+                }
+            });
+
+            let json_str = serde_json::to_string(&error_json);
+            prop_assert!(
+                json_str.is_ok(),
+                "Error JSON serialization should succeed"
+            );
+            let json_str = json_str.unwrap();
+
+            // Verify it's valid JSON
+            let parsed: Result<serde_json::Value, _> =
+                serde_json::from_str(&json_str);
+            prop_assert!(
+                parsed.is_ok(),
+                "Error output should be valid JSON"
+            );
+
+            // Verify no TUI escape sequences
+            prop_assert!(
+                !contains_escape_sequences(&json_str),
+                "Error JSON should not contain TUI escape sequences"
+            );
+        }
+    }
+
+    // Unit tests for JSON output
+    #[test]
+    fn test_json_output_contains_required_fields() {
+        let results = create_test_results(100.0, 50.0, 15.0, Some(2.0));
+        let json_str = serde_json::to_string(&results).unwrap();
+
+        // Verify required fields are present
+        assert!(json_str.contains("\"timestamp\""));
+        assert!(json_str.contains("\"server\""));
+        assert!(json_str.contains("\"connection\""));
+        assert!(json_str.contains("\"latency\""));
+        assert!(json_str.contains("\"download\""));
+        assert!(json_str.contains("\"upload\""));
+        assert!(json_str.contains("\"scores\""));
+    }
+
+    #[test]
+    fn test_json_output_no_escape_sequences() {
+        let results = create_test_results(100.0, 50.0, 15.0, Some(2.0));
+        let json_str = serde_json::to_string(&results).unwrap();
+
+        assert!(
+            !contains_escape_sequences(&json_str),
+            "JSON should not contain escape sequences"
+        );
+    }
+
+    #[test]
+    fn test_display_mode_json_suppresses_tui() {
+        // When json_flag is true, DisplayMode should be Json
+        let mode = DisplayMode::detect(true, true);
+        assert_eq!(mode, DisplayMode::Json);
+
+        let mode = DisplayMode::detect(true, false);
+        assert_eq!(mode, DisplayMode::Json);
+    }
+
+    #[test]
+    fn test_parse_header_valid() {
+        let (name, value) =
+            parse_header("CF-Access-Client-Id: abc.access").unwrap();
+        assert_eq!(name, "cf-access-client-id");
+        assert_eq!(value, "abc.access");
+    }
+
+    #[test]
+    fn test_parse_header_value_may_contain_colon() {
+        let (name, value) = parse_header("X-Forwarded:a:b").unwrap();
+        assert_eq!(name, "x-forwarded");
+        assert_eq!(value, "a:b");
+    }
+
+    #[test]
+    fn test_parse_header_invalid() {
+        assert!(parse_header("no-separator").is_err());
+        assert!(parse_header("bad name: value").is_err());
+        assert!(parse_header(": value").is_err());
+    }
+
+    #[test]
+    fn test_parse_resolver() {
+        assert_eq!(
+            parse_resolver("1.1.1.1"),
+            Ok("1.1.1.1:53".parse().unwrap())
+        );
+        assert_eq!(
+            parse_resolver("9.9.9.9:5353"),
+            Ok("9.9.9.9:5353".parse().unwrap())
+        );
+        assert_eq!(
+            parse_resolver("2606:4700:4700::1111"),
+            Ok("[2606:4700:4700::1111]:53".parse().unwrap())
+        );
+        assert_eq!(
+            parse_resolver("[::1]:8053"),
+            Ok("[::1]:8053".parse().unwrap())
+        );
+        assert!(parse_resolver("dns.example").is_err());
+        assert!(parse_resolver("1.1.1.1:dns").is_err());
+    }
+
+    #[test]
+    fn test_cli_collects_repeated_headers() {
+        let cli = Cli::parse_from([
+            "cloud-speed",
+            "-H",
+            "X-One: 1",
+            "--header",
+            "X-Two: 2",
+        ]);
+        let headers = cli.request_headers(&TestSettings::default());
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers.get("x-one").unwrap(), "1");
+        assert_eq!(headers.get("x-two").unwrap(), "2");
+        assert_eq!(headers.get(USER_AGENT).unwrap(), DEFAULT_USER_AGENT);
+    }
+
+    #[test]
+    fn test_cli_user_agent() {
+        let settings = TestSettings {
+            user_agent: Some(HeaderValue::from_static("config/1.0")),
+            headers: HeaderMap::from_iter([(
+                HeaderName::from_static("x-one"),
+                HeaderValue::from_static("config"),
+            )]),
+            ..TestSettings::default()
+        };
+
+        let cli = Cli::parse_from(["cloud-speed", "-H", "X-One: flag"]);
+        let headers = cli.request_headers(&settings);
+        assert_eq!(headers.get("x-one").unwrap(), "flag");
+        assert_eq!(headers.get(USER_AGENT).unwrap(), "config/1.0");
+
+        let cli =
+            Cli::parse_from(["cloud-speed", "--user-agent", "Mozilla/5.0"]);
+        let headers = cli.request_headers(&settings);
+        assert_eq!(headers.get(USER_AGENT).unwrap(), "Mozilla/5.0");
+        assert_eq!(cli.client(&settings).user_agent(), "Mozilla/5.0");
+    }
+
+    #[test]
+    fn test_cli_diff() {
+        let cli = Cli::parse_from(["cloud-speed", "diff", "a.json", "b.json"]);
+        let Some(Command::Diff { before, after }) = cli.command else {
+            panic!("expected the diff subcommand");
+        };
+        assert_eq!(before, std::path::Path::new("a.json"));
+        assert_eq!(after, std::path::Path::new("b.json"));
+
+        assert!(
+            Cli::try_parse_from(["cloud-speed", "diff", "a.json"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_cli_load_streams() {
+        let cli = Cli::parse_from(["cloud-speed"]);
+        assert_eq!(cli.load_streams, None);
+
+        let cli = Cli::parse_from(["cloud-speed", "--load-streams", "4"]);
+        assert_eq!(cli.load_streams, Some(4));
+
+        assert!(Cli::try_parse_from(["cloud-speed", "--load-streams", "33"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_cli_repeat() {
+        let cli = Cli::parse_from(["cloud-speed"]);
+        assert_eq!(cli.repeat, None);
+
+        let cli = Cli::parse_from(["cloud-speed", "--repeat", "5"]);
+        assert_eq!(cli.repeat, Some(5));
+
+        assert!(Cli::try_parse_from(["cloud-speed", "--repeat", "1"]).is_err());
+        assert!(Cli::try_parse_from([
+            "cloud-speed",
+            "--repeat",
+            "3",
+            "--json",
+            "--stream"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_working_latency_exit_code() {
+        let check = |pass| WorkingLatencyCheck {
+            download_target_ms: 100.0,
+            upload_target_ms: 100.0,
+            download_pass: Some(pass),
+            upload_pass: None,
+            pass,
+        };
+        assert_eq!(
+            working_latency_exit_code(Some(&check(true))),
+            exit_codes::SUCCESS
+        );
+        assert_eq!(
+            working_latency_exit_code(Some(&check(false))),
+            exit_codes::WORKING_LATENCY_EXCEEDED
+        );
+        assert_eq!(
+            working_latency_exit_code(None),
+            exit_codes::WORKING_LATENCY_EXCEEDED
+        );
+    }
+
+    #[test]
+    fn test_cli_restart_on_network_change() {
+        let none = TestSettings::default();
+        let config = Cli::parse_from(["cloud-speed"]).test_config(&none);
+        assert!(!config.restart_on_network_change);
+
+        let cli =
+            Cli::parse_from(["cloud-speed", "--restart-on-network-change"]);
+        assert!(cli.test_config(&none).restart_on_network_change);
+
+        let settings = TestSettings {
+            restart_on_network_change: Some(true),
+            ..TestSettings::default()
+        };
+        let config = Cli::parse_from(["cloud-speed"]).test_config(&settings);
+        assert!(config.restart_on_network_change);
+    }
+
+    #[test]
+    fn test_cli_tls_cipher_and_worker_threads() {
+        let cli = Cli::parse_from(["cloud-speed"]);
+        assert_eq!(cli.tls_cipher, TlsCipher::Auto);
+        assert_eq!(cli.tls_backend, TlsBackend::Rustls);
+        assert_eq!(cli.worker_threads, None);
+
+        let cli = Cli::parse_from([
+            "cloud-speed",
+            "--tls-cipher",
+            "chacha20",
+            "--worker-threads",
+            "4",
+        ]);
+        assert_eq!(cli.tls_cipher, TlsCipher::Chacha20);
+        assert_eq!(cli.worker_threads, Some(4));
+
+        let native =
+            Cli::try_parse_from(["cloud-speed", "--tls-backend", "native"]);
+        assert_eq!(
+            native.map(|cli| cli.tls_backend).ok(),
+            cfg!(feature = "native-tls").then_some(TlsBackend::Native)
+        );
+
+        assert!(Cli::try_parse_from(["cloud-speed", "--tls-cipher", "rc4"])
+            .is_err());
+        assert!(Cli::try_parse_from(["cloud-speed", "--worker-threads", "0"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_cli_client_cert_needs_key() {
+        let cli = Cli::parse_from([
+            "cloud-speed",
+            "--cacert",
+            "corp.pem",
+            "--client-cert",
+            "me.pem",
+            "--client-key",
+            "me.key",
+        ]);
+        assert_eq!(
+            cli.cacert.as_deref(),
+            Some(std::path::Path::new("corp.pem"))
+        );
+        assert_eq!(
+            cli.client_key.as_deref(),
+            Some(std::path::Path::new("me.key"))
+        );
+
+        assert!(Cli::try_parse_from([
+            "cloud-speed",
+            "--client-cert",
+            "me.pem"
+        ])
+        .is_err());
+        assert!(Cli::try_parse_from([
+            "cloud-speed",
+            "--client-key",
+            "me.key"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_cli_require_direct() {
+        assert!(!Cli::parse_from(["cloud-speed"]).require_direct);
+        assert!(
+            Cli::parse_from(["cloud-speed", "--require-direct"])
+                .require_direct
+        );
+
+        let error: Box<dyn std::error::Error> = SpeedTestError::warp(
+            "Traffic leaves through Cloudflare WARP (AS13335)",
+        )
+        .into();
+        let error = create_user_error(error.as_ref());
+        assert_eq!(error.kind, ErrorKind::Warp);
+        assert_eq!(error.exit_code(), exit_codes::NOT_DIRECT);
+        assert!(error.suggestion.is_some());
+    }
+
+    #[test]
+    fn test_cli_packet_loss_config() {
+        let none = TestSettings::default();
+        assert!(Cli::parse_from(["cloud-speed"])
+            .packet_loss_config(&none)
+            .is_none());
+
+        // The old flag name still works
+        let cli = Cli::parse_from(["cloud-speed", "--turn-server", "turn:a"]);
+        let config = cli.packet_loss_config(&none).unwrap();
+        assert_eq!(config.server_uri, "turn:a");
+        assert_eq!(config.provider, LossProvider::Turn);
+
+        // QUIC falls back to Cloudflare's edge
+        let settings = TestSettings {
+            loss_provider: Some(LossProvider::Quic),
+            ..TestSettings::default()
+        };
+        let config = Cli::parse_from(["cloud-speed"])
+            .packet_loss_config(&settings)
+            .unwrap();
+        assert_eq!(config.server_uri, "speed.cloudflare.com");
+
+        let cli = Cli::parse_from([
+            "cloud-speed",
+            "--loss-provider",
+            "udp-echo",
+            "--loss-server",
+            "echo.example.com",
+        ]);
+        let config = cli.packet_loss_config(&settings).unwrap();
+        assert_eq!(config.provider, LossProvider::UdpEcho);
+        assert_eq!(config.server_uri, "echo.example.com");
+    }
+
+    #[test]
+    fn test_cli_compare_region() {
+        assert_eq!(Cli::parse_from(["cloud-speed"]).compare_region, None);
+        let cli = Cli::parse_from(["cloud-speed", "--compare-region"]);
+        assert_eq!(cli.compare_region, Some(RegionScope::Country));
+        let cli = Cli::parse_from(["cloud-speed", "--compare-region", "ASN"]);
+        assert_eq!(cli.compare_region, Some(RegionScope::Asn));
+        assert!(Cli::try_parse_from([
+            "cloud-speed",
+            "--compare-region",
+            "city"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_cli_stall_timeout() {
+        let none = TestSettings::default();
+        let config = Cli::parse_from(["cloud-speed"]).test_config(&none);
+        assert_eq!(config.stall_timeout, Some(DEFAULT_STALL_TIMEOUT));
+        assert!(!config.abort_stalled);
+
+        let cli = Cli::parse_from([
+            "cloud-speed",
+            "--stall-timeout",
+            "5",
+            "--abort-stalled",
+        ]);
+        let config = cli.test_config(&none);
+        assert_eq!(config.stall_timeout, Some(Duration::from_secs(5)));
+        assert!(config.abort_stalled);
+
+        let settings =
+            TestSettings { stall_timeout: Some(0), ..TestSettings::default() };
+        let config = Cli::parse_from(["cloud-speed"]).test_config(&settings);
+        assert_eq!(config.stall_timeout, None);
+    }
+
+    #[test]
+    fn test_cli_convergence() {
+        let none = TestSettings::default();
+        let config = Cli::parse_from(["cloud-speed"]).test_config(&none);
+        assert_eq!(config.convergence_cv_threshold, Some(0.05));
+        assert_eq!(config.convergence_window, 4);
+
+        let cli = Cli::parse_from([
+            "cloud-speed",
+            "--convergence-cv",
+            "0.1",
+            "--convergence-window",
+            "6",
+        ]);
+        let config = cli.test_config(&none);
+        assert_eq!(config.convergence_cv_threshold, Some(0.1));
+        assert_eq!(config.convergence_window, 6);
+
+        let settings = TestSettings {
+            convergence_cv: Some(0.0),
+            ..TestSettings::default()
+        };
+        let config = Cli::parse_from(["cloud-speed"]).test_config(&settings);
+        assert_eq!(config.convergence_cv_threshold, None);
+
+        let cli =
+            Cli::try_parse_from(["cloud-speed", "--convergence-cv", "2"]);
+        assert!(cli.is_err());
+        let cli =
+            Cli::try_parse_from(["cloud-speed", "--convergence-window", "1"]);
+        assert!(cli.is_err());
+    }
+
+    #[test]
+    fn test_cli_latency_schedule() {
+        let none = TestSettings::default();
+        let cli = Cli::parse_from(["cloud-speed"]);
+        assert_eq!(
+            cli.latency_schedule(&none),
+            (LatencySchedule::Sequential, 0)
+        );
+
+        let cli = Cli::parse_from(["cloud-speed", "--latency-interval", "50"]);
+        assert_eq!(
+            cli.latency_schedule(&none),
+            (LatencySchedule::Sequential, 50)
+        );
+
+        let cli = Cli::parse_from(["cloud-speed", "--latency-tick"]);
+        assert_eq!(
+            cli.latency_schedule(&none),
+            (LatencySchedule::FixedTick, DEFAULT_LATENCY_TICK_MS)
+        );
+
+        let cli = Cli::parse_from([
+            "cloud-speed",
+            "--latency-tick",
+            "--latency-interval",
+            "250",
+        ]);
+        assert_eq!(
+            cli.latency_schedule(&none),
+            (LatencySchedule::FixedTick, 250)
+        );
+
+        let cli = Cli::parse_from(["cloud-speed", "--latency-parallel"]);
+        assert_eq!(
+            cli.latency_schedule(&none),
+            (
+                LatencySchedule::Parallel { in_flight: 4 },
+                DEFAULT_LATENCY_PARALLEL_GAP_MS
+            )
+        );
+
+        let settings = TestSettings {
+            latency_parallel: Some(8),
+            ..TestSettings::default()
+        };
+        let cli = Cli::parse_from(["cloud-speed", "--latency-interval", "0"]);
+        assert_eq!(
+            cli.latency_schedule(&settings),
+            (LatencySchedule::Parallel { in_flight: 8 }, 0)
+        );
+        let cli = Cli::parse_from(["cloud-speed", "--latency-tick"]);
+        assert_eq!(
+            cli.latency_schedule(&settings).0,
+            LatencySchedule::FixedTick
+        );
+
+        let settings = TestSettings {
+            latency_tick: Some(true),
+            ..TestSettings::default()
+        };
+        let cli = Cli::parse_from(["cloud-speed", "--latency-parallel", "2"]);
+        assert_eq!(
+            cli.latency_schedule(&settings).0,
+            LatencySchedule::Parallel { in_flight: 2 }
+        );
+
+        assert!(Cli::try_parse_from([
+            "cloud-speed",
+            "--latency-parallel",
+            "1"
+        ])
+        .is_err());
+        assert!(Cli::try_parse_from([
+            "cloud-speed",
+            "--latency-parallel",
+            "--latency-tick"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_cli_test_config_falls_back_to_settings() {
+        let settings = TestSettings {
+            load_streams: Some(4),
+            dscp: Some(Dscp::EF),
+            icmp: Some(true),
+            latency_tick: Some(true),
+            order: Some(TestOrder::Sequential),
+            ..TestSettings::default()
+        };
+
+        let config = Cli::parse_from(["cloud-speed"]).test_config(&settings);
+        assert_eq!(config.load_streams, 4);
+        assert_eq!(config.socket_options.dscp, Some(Dscp::EF));
+        assert_eq!(config.latency_probe, LatencyProbe::Icmp);
+        assert_eq!(config.latency_schedule, LatencySchedule::FixedTick);
+        assert_eq!(config.latency_interval_ms, DEFAULT_LATENCY_TICK_MS);
+        assert_eq!(config.order, TestOrder::Sequential);
+
+        // Flags given on the command line win
+        let config = Cli::parse_from([
+            "cloud-speed",
+            "--load-streams",
+            "2",
+            "--dscp",
+            "cs1",
+            "--order",
+            "concurrent",
+        ])
+        .test_config(&settings);
+        assert_eq!(config.load_streams, 2);
+        assert_eq!(config.order, TestOrder::Concurrent);
+        assert_eq!(config.socket_options.dscp, Some("cs1".parse().unwrap()));
+
+        // Including 0, which turns off the configured load streams
+        let config = Cli::parse_from(["cloud-speed", "--load-streams", "0"])
+            .test_config(&settings);
+        assert_eq!(config.load_streams, 0);
+    }
+
+    #[test]
+    fn test_vpn_ab_arms_switch_vpn_around_direct_runs() {
+        let cli = Cli::parse_from(["cloud-speed", "--compare-vpn"]);
+        let err = vpn_ab_arms(&cli, &Config::default())
+            .err()
+            .expect("up and down commands are required");
+        assert_eq!(err.exit_code(), exit_codes::CONFIG_ERROR);
+
+        let config =
+            Config::from_toml("[vpn]\nup = \"vpn-up\"\ndown = \"vpn-down\"\n")
+                .unwrap();
+        let [vpn, direct] = vpn_ab_arms(&cli, &config).unwrap();
+        assert_eq!(vpn.hooks.before.as_deref(), Some("vpn-up"));
+        assert_eq!(direct.hooks.before.as_deref(), Some("vpn-down"));
+        assert_eq!(direct.hooks.after.as_deref(), Some("vpn-up"));
+    }
+
+    #[test]
+    fn test_cli_phase_selection() {
+        let cli = Cli::try_parse_from(["cloud-speed"]).unwrap();
+        assert_eq!(cli.phases(), PhaseSelection::All);
+
+        let cli =
+            Cli::try_parse_from(["cloud-speed", "--latency-only"]).unwrap();
+        assert_eq!(
+            cli.test_config(&TestSettings::default()).phases,
+            PhaseSelection::Latency
+        );
+        let cli =
+            Cli::try_parse_from(["cloud-speed", "--upload-only"]).unwrap();
+        assert_eq!(cli.phases(), PhaseSelection::Upload);
+
+        assert!(Cli::try_parse_from([
+            "cloud-speed",
+            "--download-only",
+            "--upload-only"
+        ])
+        .is_err());
+        assert!(Cli::try_parse_from([
+            "cloud-speed",
+            "--latency-only",
+            "--save-history"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_cli_history() {
+        let cli = Cli::try_parse_from([
+            "cloud-speed",
+            "history",
+            "export",
+            "--since",
+            "30d",
+            "--format",
+            "csv",
+        ])
+        .unwrap();
+        let Some(Command::History {
+            action: HistoryAction::Export { since, format },
+        }) = cli.command
+        else {
+            panic!("expected history export");
+        };
+        assert_eq!(since, Some(chrono::TimeDelta::days(30)));
+        assert_eq!(format, ExportFormat::Csv);
+
+        let cli = Cli::try_parse_from([
+            "cloud-speed",
+            "history",
+            "prune",
+            "--max-entries",
+            "100",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::History {
+                action: HistoryAction::Prune { max_entries: Some(100), .. }
+            })
+        ));
+
+        assert_eq!(parse_age("12h"), Ok(chrono::TimeDelta::hours(12)));
+        assert_eq!(parse_age("2w"), Ok(chrono::TimeDelta::weeks(2)));
+        for bad in ["", "d", "30", "0d", "-1d", "5y"] {
+            assert!(parse_age(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_cli_ping() {
+        let cli = Cli::try_parse_from(["cloud-speed", "ping"]).unwrap();
+        let Some(Command::Ping { interval, count }) = cli.command else {
+            panic!("expected ping");
+        };
+        assert_eq!(interval, Duration::from_secs(1));
+        assert_eq!(count, None);
+
+        let cli = Cli::try_parse_from([
+            "cloud-speed",
+            "ping",
+            "-i",
+            "0.5",
+            "-c",
+            "10",
+        ])
+        .unwrap();
+        let Some(Command::Ping { interval, count }) = cli.command else {
+            panic!("expected ping");
+        };
+        assert_eq!(interval, Duration::from_millis(500));
+        assert_eq!(count, Some(10));
+
+        for bad in ["0", "0.05", "-1", "abc"] {
+            assert!(
+                Cli::try_parse_from(["cloud-speed", "ping", "-i", bad])
+                    .is_err(),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_cli_score_needs_input_or_metrics() {
+        let cli = Cli::try_parse_from([
+            "cloud-speed",
+            "score",
+            "--input",
+            "results.json",
+        ])
+        .unwrap();
+        assert!(matches!(cli.command, Some(Command::Score { .. })));
+
+        let cli = Cli::try_parse_from([
+            "cloud-speed",
+            "score",
+            "--download",
+            "100",
+            "--upload",
+            "20",
+            "--latency",
+            "15",
+        ])
+        .unwrap();
+        let Some(Command::Score { download, jitter, loss, .. }) = cli.command
+        else {
+            panic!("expected score");
+        };
+        assert_eq!(download, Some(100.0));
+        assert_eq!(jitter, 0.0);
+        assert_eq!(loss, None);
+
+        assert!(Cli::try_parse_from(["cloud-speed", "score"]).is_err());
+        assert!(Cli::try_parse_from([
+            "cloud-speed",
+            "score",
+            "--download",
+            "100"
+        ])
+        .is_err());
+        assert!(Cli::try_parse_from([
+            "cloud-speed",
+            "score",
+            "--input",
+            "results.json",
+            "--download",
+            "100",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_cli_upload_payload() {
+        let cli = Cli::parse_from(["cloud-speed"]);
+        assert_eq!(cli.upload_payload, PayloadPattern::Random);
+
+        let cli =
+            Cli::parse_from(["cloud-speed", "--upload-payload", "zeros"]);
+        assert_eq!(cli.upload_payload, PayloadPattern::Zeros);
+
+        assert!(Cli::try_parse_from([
+            "cloud-speed",
+            "--upload-payload",
+            "ones"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_tag() {
+        assert_eq!(
+            parse_tag("site=nyc").unwrap(),
+            ("site".to_string(), "nyc".to_string())
+        );
+        assert_eq!(
+            parse_tag("url=a=b").unwrap(),
+            ("url".to_string(), "a=b".to_string())
+        );
+        assert!(parse_tag("site").is_err());
+        assert!(parse_tag("=nyc").is_err());
+    }
+
+    #[test]
+    fn test_cli_tags_last_value_wins() {
+        let cli = Cli::parse_from([
+            "cloud-speed",
+            "--tag",
+            "site=nyc",
+            "--tag",
+            "rack=4",
+            "--tag",
+            "site=sfo",
+        ]);
+        let tags = cli.tags();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags["site"], "sfo");
+        assert_eq!(tags["rack"], "4");
+    }
+
+    #[test]
+    fn test_cli_format_name() {
+        let cli = Cli::parse_from(["cloud-speed"]);
+        assert_eq!(cli.format_name(), "human");
+
+        let cli = Cli::parse_from(["cloud-speed", "--json"]);
+        assert_eq!(cli.format_name(), "json");
+
+        let cli = Cli::parse_from(["cloud-speed", "--format", "json"]);
+        assert_eq!(cli.format_name(), "json");
+    }
+
+    #[test]
+    fn test_cli_size_blocks() {
+        let cli = Cli::parse_from(["cloud-speed"]);
+        let config = cli.test_config(&TestSettings::default());
+        assert_eq!(
+            config.download_sizes,
+            TestConfig::default().download_sizes
+        );
+
+        let cli = Cli::parse_from([
+            "cloud-speed",
+            "--download-sizes",
+            "100kB:10,1MB:8,25MB:4",
+        ]);
+        let config = cli.test_config(&TestSettings::default());
+        assert_eq!(cli.download_sizes.to_string(), "100kB:10,1MB:8,25MB:4");
+        assert_eq!(config.download_sizes.len(), 3);
+        assert_eq!(config.upload_sizes, TestConfig::default().upload_sizes);
+
+        let result = Cli::try_parse_from([
+            "cloud-speed",
+            "--upload-sizes",
+            "1MB:8,1kB:2",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_cli_ascii() {
+        let caps = Cli::parse_from(["cloud-speed", "--ascii"])
+            .terminal_capabilities();
+        assert!(!caps.unicode);
+        assert!(!caps.mouse);
+    }
+
+    #[test]
+    fn test_cli_simulate() {
+        let cli = Cli::parse_from(["cloud-speed"]);
+        assert_eq!(cli.simulate, None);
+
+        let cli = Cli::parse_from(["cloud-speed", "--simulate"]);
+        assert_eq!(cli.simulate, Some(SpeedProfile::Cable));
+        assert_eq!(cli.simulate_duration, DEFAULT_SIMULATION_SECS);
+
+        let cli = Cli::parse_from([
+            "cloud-speed",
+            "--simulate",
+            "fiber",
+            "--simulate-duration",
+            "5",
+        ]);
+        assert_eq!(cli.simulate, Some(SpeedProfile::Fiber));
+        assert_eq!(cli.simulate_duration, 5);
+
+        for args in [
+            ["cloud-speed", "--simulate-duration", "5"].as_slice(),
+            &["cloud-speed", "--simulate", "dialup"],
+            &["cloud-speed", "--simulate", "--save-history"],
+            &["cloud-speed", "--simulate", "--share"],
+        ] {
+            assert!(Cli::try_parse_from(args).is_err(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn test_cli_format_conflicts_with_json() {
+        let result = Cli::try_parse_from([
+            "cloud-speed",
+            "--json",
+            "--format",
+            "human",
+        ]);
+        assert!(result.is_err());
+    }
+}
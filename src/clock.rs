@@ -0,0 +1,108 @@
+//! Wall clock checks.
+//!
+//! Measurements time themselves with monotonic clocks (`Instant`), which
+//! NTP can't step, so durations stay correct however the system clock
+//! moves. The wall clock still stamps results and history, and TLS
+//! certificate validation depends on it, so a run compares it with the
+//! server's `Date` header before testing and records when it was stepped
+//! while the test ran.
+
+use chrono::{DateTime, TimeDelta, Utc};
+use std::time::Instant;
+
+/// Clock skew (seconds) above which the clock is worth a warning.
+pub const CLOCK_SKEW_WARN_SECS: i64 = 5;
+
+/// Clock skew (seconds) above which the clock counts as wrong.
+/// Certificate validation and history timestamps become unreliable
+/// beyond this.
+pub const CLOCK_SKEW_FAIL_SECS: i64 = 60;
+
+/// Smallest wall clock step during a run worth recording, in ms; well
+/// above how far the two clocks drift apart over a test.
+const STEP_THRESHOLD_MS: i64 = 1000;
+
+/// Parse the value of an HTTP `Date` header.
+pub fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value.trim())
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// Whole seconds `local` is ahead of `server`; negative when behind.
+///
+/// `Date` headers only have one second resolution, so ±1 is noise.
+pub fn skew_secs(local: DateTime<Utc>, server: DateTime<Utc>) -> i64 {
+    (local - server).num_seconds()
+}
+
+/// Describe a skew from [`skew_secs`] for people.
+pub fn describe_skew(skew_secs: i64) -> String {
+    let direction = if skew_secs >= 0 { "ahead of" } else { "behind" };
+    format!("Local clock is {}s {} the server", skew_secs.abs(), direction)
+}
+
+/// Compares the wall clock with the monotonic clock over a run, to
+/// notice the wall clock being stepped (e.g. by NTP) in between.
+#[derive(Debug, Clone, Copy)]
+pub struct ClockWatch {
+    wall: DateTime<Utc>,
+    monotonic: Instant,
+}
+
+impl ClockWatch {
+    /// Start watching from now.
+    pub fn start() -> Self {
+        Self { wall: Utc::now(), monotonic: Instant::now() }
+    }
+
+    /// How far the wall clock was stepped since [`ClockWatch::start`]
+    /// (positive when it jumped forward), if by at least a second.
+    pub fn step(&self) -> Option<TimeDelta> {
+        self.step_at(Utc::now(), Instant::now())
+    }
+
+    fn step_at(
+        &self,
+        wall: DateTime<Utc>,
+        monotonic: Instant,
+    ) -> Option<TimeDelta> {
+        let monotonic_elapsed =
+            TimeDelta::from_std(monotonic - self.monotonic).ok()?;
+        let step = (wall - self.wall) - monotonic_elapsed;
+        (step.num_milliseconds().abs() >= STEP_THRESHOLD_MS).then_some(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_describe_skew() {
+        assert_eq!(
+            describe_skew(120),
+            "Local clock is 120s ahead of the server"
+        );
+        assert_eq!(describe_skew(-7), "Local clock is 7s behind the server");
+    }
+
+    #[test]
+    fn test_clock_watch_notices_steps() {
+        let watch = ClockWatch::start();
+        let later = watch.monotonic + Duration::from_secs(30);
+        let at = |secs| watch.wall + TimeDelta::seconds(secs);
+
+        assert_eq!(watch.step_at(at(30), later), None);
+        assert_eq!(
+            watch.step_at(at(30) + TimeDelta::milliseconds(400), later),
+            None
+        );
+        assert_eq!(watch.step_at(at(90), later), Some(TimeDelta::seconds(60)));
+        assert_eq!(
+            watch.step_at(at(-10), later),
+            Some(TimeDelta::seconds(-40))
+        );
+    }
+}
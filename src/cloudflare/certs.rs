@@ -0,0 +1,210 @@
+//! Certificates given on the command line.
+//!
+//! `--cacert` adds roots to trust, for networks whose TLS inspection
+//! re-signs traffic with a corporate root the system doesn't know about.
+//! `--client-cert` and `--client-key` present a client certificate, for
+//! egress that requires mutual TLS. Both are read once at startup and
+//! apply to the API client and to every measurement connection.
+
+use crate::errors::SpeedTestError;
+use rustls_connector::rustls::pki_types::pem::PemObject;
+use rustls_connector::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::fmt;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Certificates loaded from `--cacert` and `--client-cert`/`--client-key`.
+#[derive(Debug, Default)]
+pub struct TlsCertificates {
+    /// Roots to trust on top of the system's
+    pub roots: Vec<CertificateDer<'static>>,
+    /// Client certificate to present, if any
+    pub identity: Option<ClientIdentity>,
+}
+
+/// A client certificate chain and its private key.
+pub struct ClientIdentity {
+    /// Certificate chain, leaf first
+    pub chain: Vec<CertificateDer<'static>>,
+    /// PKCS#8 private key of the leaf
+    pub key: PrivateKeyDer<'static>,
+    /// The chain as read, PEM-encoded
+    pub chain_pem: Vec<u8>,
+    /// The key as read, PEM-encoded
+    pub key_pem: Vec<u8>,
+}
+
+impl fmt::Debug for ClientIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientIdentity")
+            .field("chain", &self.chain.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TlsCertificates {
+    /// Read the CA bundle at `cacert` and the client certificate and key
+    /// at `client`, where given.
+    pub fn load(
+        cacert: Option<&Path>,
+        client: Option<(&Path, &Path)>,
+    ) -> Result<Self, SpeedTestError> {
+        let roots = match cacert {
+            Some(path) => {
+                let roots = read_certificates(path, &read(path)?)?;
+                log::debug!(
+                    "Trusting {} extra root(s) from {}",
+                    roots.len(),
+                    path.display()
+                );
+                roots
+            }
+            None => Vec::new(),
+        };
+        let identity = client
+            .map(|(cert, key)| ClientIdentity::load(cert, key))
+            .transpose()?;
+
+        Ok(Self { roots, identity })
+    }
+}
+
+impl ClientIdentity {
+    /// Read a PEM certificate chain from `cert` and its PKCS#8 key from
+    /// `key`.
+    fn load(cert: &Path, key: &Path) -> Result<Self, SpeedTestError> {
+        let chain_pem = read(cert)?;
+        let chain = read_certificates(cert, &chain_pem)?;
+
+        let key_pem = read(key)?;
+        let invalid = |reason: String| {
+            SpeedTestError::config(format!(
+                "Invalid client key {}: {}",
+                key.display(),
+                reason
+            ))
+        };
+        let parsed = PrivateKeyDer::from_pem_slice(&key_pem)
+            .map_err(|e| invalid(e.to_string()))?;
+        // The platform TLS libraries only take PKCS#8 keys
+        if !matches!(parsed, PrivateKeyDer::Pkcs8(_)) {
+            return Err(invalid("not a PKCS#8 key".to_string())
+                .with_suggestion(format!(
+                    "Convert it with: openssl pkcs8 -topk8 -nocrypt -in {} \
+                     -out key.pem",
+                    key.display()
+                )));
+        }
+        reqwest::Identity::from_pkcs8_pem(&chain_pem, &key_pem)
+            .map_err(|e| invalid(e.to_string()))?;
+
+        Ok(Self { chain, key: parsed, chain_pem, key_pem })
+    }
+}
+
+/// Read the file at `path`.
+fn read(path: &Path) -> Result<Vec<u8>, SpeedTestError> {
+    std::fs::read(path).map_err(|e| {
+        SpeedTestError::config(format!(
+            "Failed to read {}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+/// Every certificate in the PEM file `pem`, read from `path`; at least
+/// one.
+fn read_certificates(
+    path: &Path,
+    pem: &[u8],
+) -> Result<Vec<CertificateDer<'static>>, SpeedTestError> {
+    let certs = CertificateDer::pem_slice_iter(pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            SpeedTestError::config(format!(
+                "Invalid certificate in {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+    if certs.is_empty() {
+        return Err(SpeedTestError::config(format!(
+            "No PEM certificates found in {}",
+            path.display()
+        ))
+        .with_suggestion(
+            "Certificates must be PEM-encoded (-----BEGIN CERTIFICATE-----)",
+        ));
+    }
+    Ok(certs)
+}
+
+/// Certificates every later TLS connection uses, once loaded.
+static CERTIFICATES: OnceLock<TlsCertificates> = OnceLock::new();
+
+/// Use `certificates` for every later TLS connection.
+///
+/// Only the first call takes effect.
+pub fn use_certificates(certificates: TlsCertificates) {
+    let _ = CERTIFICATES.set(certificates);
+}
+
+/// The certificates chosen with [`use_certificates`], if any.
+pub fn certificates() -> Option<&'static TlsCertificates> {
+    CERTIFICATES.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorKind;
+    use std::path::PathBuf;
+
+    /// Path of `name` in the fixtures directory.
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/cloudflare/tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn test_load_ca_bundle() {
+        let certificates =
+            TlsCertificates::load(Some(&fixture("client.pem")), None).unwrap();
+        assert_eq!(certificates.roots.len(), 1);
+        assert!(certificates.identity.is_none());
+
+        let error =
+            TlsCertificates::load(Some(&fixture("download.http")), None)
+                .unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Config);
+        assert!(error.message.contains("No PEM certificates"));
+
+        let error = TlsCertificates::load(Some(&fixture("missing.pem")), None)
+            .unwrap_err();
+        assert!(error.message.starts_with("Failed to read"));
+    }
+
+    #[test]
+    fn test_load_client_identity() {
+        let cert = fixture("client.pem");
+        let identity = TlsCertificates::load(
+            None,
+            Some((&cert, &fixture("client_key.pem"))),
+        )
+        .unwrap()
+        .identity
+        .unwrap();
+        assert_eq!(identity.chain.len(), 1);
+        assert!(matches!(identity.key, PrivateKeyDer::Pkcs8(_)));
+
+        let error = TlsCertificates::load(
+            None,
+            Some((&cert, &fixture("client_key_sec1.pem"))),
+        )
+        .unwrap_err();
+        assert!(error.message.contains("not a PKCS#8 key"));
+        assert!(error.suggestion.unwrap().contains("openssl pkcs8"));
+    }
+}
@@ -1,48 +1,224 @@
+use crate::clock::parse_http_date;
+use crate::cloudflare::certs::certificates;
+use crate::cloudflare::proxy::{env_proxy, redact};
 use crate::cloudflare::requests::{Request, RequestBody};
-use reqwest::{Body, Client as ReqwestClient, RequestBuilder};
+use crate::cloudflare::tests::connection::{lookup_host, name_server};
+use chrono::{DateTime, Utc};
+use http::header::{DATE, USER_AGENT};
+use http::{HeaderMap, HeaderValue};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{Body, Client as ReqwestClient, Proxy, RequestBuilder};
 use std::error::Error;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
 
 static BASE_URL: &str = "https://speed.cloudflare.com";
 
-#[derive(Debug, Clone)]
+/// User-Agent sent unless one is configured.
+pub const DEFAULT_USER_AGENT: &str = concat!(
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("CARGO_PKG_REPOSITORY"),
+    ")"
+);
+
+/// A hook that can inspect and modify the headers of every request sent
+/// through a [`Client`], e.g. to attach Zero Trust service tokens.
+pub type Middleware = Arc<dyn Fn(&mut HeaderMap) + Send + Sync>;
+
+/// Resolves API hosts through the name server chosen with `--resolver`.
+struct NameServerResolve;
+
+impl Resolve for NameServerResolve {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let lookup = lookup_host(&host).await?;
+            let addrs: Addrs = Box::new(
+                lookup.addresses.into_iter().map(|ip| SocketAddr::new(ip, 0)),
+            );
+            Ok(addrs)
+        })
+    }
+}
+
+/// An HTTP client builder with the proxy, name server and certificates
+/// every request to Cloudflare should use.
+pub fn http_client_builder() -> reqwest::ClientBuilder {
+    // Route through our own environment proxy detection rather than
+    // reqwest's, so NO_PROXY follows the same rules as the raw tests.
+    let mut builder = ReqwestClient::builder()
+        .no_proxy()
+        .proxy(Proxy::custom(|url| env_proxy().proxy_for(url).cloned()));
+    if name_server().is_some() {
+        builder = builder.dns_resolver(NameServerResolve);
+    }
+    if let Some(certificates) = certificates() {
+        for root in &certificates.roots {
+            if let Ok(root) = reqwest::Certificate::from_der(root) {
+                builder = builder.add_root_certificate(root);
+            }
+        }
+        // Already checked to parse when loaded
+        if let Some(identity) = &certificates.identity {
+            if let Ok(identity) = reqwest::Identity::from_pkcs8_pem(
+                &identity.chain_pem,
+                &identity.key_pem,
+            ) {
+                builder = builder.identity(identity);
+            }
+        }
+    }
+    builder
+}
+
+#[derive(Clone)]
 pub struct Client {
     client: ReqwestClient,
+    user_agent: HeaderValue,
+    middleware: Vec<Middleware>,
 }
 
 impl Client {
     pub fn new() -> Self {
-        Client { client: ReqwestClient::new() }
+        let client = http_client_builder()
+            .build()
+            .expect("failed to build HTTP client");
+
+        Client {
+            client,
+            user_agent: HeaderValue::from_static(DEFAULT_USER_AGENT),
+            middleware: Vec::new(),
+        }
+    }
+
+    /// A client sending `headers` with every request, taking its
+    /// User-Agent from them if they set one.
+    pub fn from_headers(mut headers: HeaderMap) -> Self {
+        let client = match headers.remove(USER_AGENT) {
+            Some(user_agent) => Client::new().with_user_agent(user_agent),
+            None => Client::new(),
+        };
+        client.with_headers(headers)
+    }
+
+    /// Send `user_agent` instead of [`DEFAULT_USER_AGENT`]. Middleware
+    /// setting the header still take precedence.
+    pub fn with_user_agent(mut self, user_agent: HeaderValue) -> Self {
+        self.user_agent = user_agent;
+        self
+    }
+
+    /// The User-Agent requests are sent with, before middleware.
+    pub fn user_agent(&self) -> &HeaderValue {
+        &self.user_agent
+    }
+
+    /// The proxy requests to the speed test API go through, if any, with
+    /// credentials removed.
+    pub fn active_proxy(&self) -> Option<String> {
+        let url = url::Url::parse(BASE_URL).ok()?;
+        env_proxy().proxy_for(&url).map(redact)
+    }
+
+    /// Register a middleware that runs after the request's own headers are
+    /// built. Middleware run in the order they were added, so later ones
+    /// can override headers set by earlier ones.
+    pub fn with_middleware<F>(mut self, middleware: F) -> Self
+    where
+        F: Fn(&mut HeaderMap) + Send + Sync + 'static,
+    {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Convenience middleware that sets each of `headers` on every request,
+    /// replacing any default value for the same header name.
+    pub fn with_headers(self, headers: HeaderMap) -> Self {
+        if headers.is_empty() {
+            return self;
+        }
+
+        self.with_middleware(move |request_headers| {
+            for name in headers.keys() {
+                request_headers.remove(name);
+            }
+            for (name, value) in &headers {
+                request_headers.append(name, value.clone());
+            }
+        })
+    }
+
+    /// Build the headers for `request`, running them through every
+    /// registered middleware.
+    fn request_headers<R: Request>(&self, request: &R) -> HeaderMap {
+        let mut headers = request.headers();
+        headers.insert(USER_AGENT, self.user_agent.clone());
+        for middleware in &self.middleware {
+            middleware(&mut headers);
+        }
+
+        headers
     }
 
     pub async fn send<R: Request>(
         &self,
         request: R,
     ) -> Result<R::Response, Box<dyn Error>> {
+        self.send_dated(request).await.map(|(response, _)| response)
+    }
+
+    /// Like [`Client::send`], also returning the server's clock from the
+    /// response's `Date` header, when it has one.
+    pub async fn send_dated<R: Request>(
+        &self,
+        request: R,
+    ) -> Result<(R::Response, Option<DateTime<Utc>>), Box<dyn Error>> {
         let endpoint = request.endpoint();
         let endpoint = endpoint.trim_matches('/');
         let url = format!("{}/{}", BASE_URL, endpoint);
 
+        let headers = self.request_headers(&request);
+
         let response = self
             .client
             .request(R::METHOD, &url)
-            .headers(request.headers())
+            .headers(headers)
             .cloudflare_body(request.body())?
             .send()
             .await?
             .error_for_status()?;
+        let date = response
+            .headers()
+            .get(DATE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_http_date);
 
         // Get the response text
         let text = response.text().await?;
 
         // Try JSON deserialization first (Cloudflare often returns JSON with text/plain content-type)
         if let Ok(parsed) = serde_json::from_str::<R::Response>(&text) {
-            return Ok(parsed);
+            return Ok((parsed, date));
         }
 
         // Fall back to plain text deserialization for simple responses (e.g., locations endpoint)
         let deserialized = serde_plain::from_str(&text)?;
 
-        Ok(deserialized)
+        Ok((deserialized, date))
+    }
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("client", &self.client)
+            .field("user_agent", &self.user_agent)
+            .field("middleware", &self.middleware.len())
+            .finish()
     }
 }
 
@@ -70,3 +246,77 @@ impl RequestBuilderExt for RequestBuilder {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cloudflare::requests::meta::MetaRequest;
+
+    #[test]
+    fn test_request_headers_without_middleware_uses_defaults() {
+        let client = Client::new();
+        let headers = client.request_headers(&MetaRequest {});
+
+        assert_eq!(headers.get(USER_AGENT).unwrap(), DEFAULT_USER_AGENT);
+    }
+
+    #[test]
+    fn test_with_user_agent() {
+        let client = Client::new()
+            .with_user_agent(HeaderValue::from_static("Mozilla/5.0"));
+        let headers = client.request_headers(&MetaRequest {});
+
+        assert_eq!(headers.get(USER_AGENT).unwrap(), "Mozilla/5.0");
+        assert_eq!(client.user_agent(), "Mozilla/5.0");
+    }
+
+    #[test]
+    fn test_with_headers_overrides_and_adds() {
+        let mut extra = HeaderMap::new();
+        extra.insert(USER_AGENT, HeaderValue::from_static("custom/1.0"));
+        extra.insert(
+            "cf-access-client-id",
+            HeaderValue::from_static("service-token"),
+        );
+
+        let client = Client::new().with_headers(extra);
+        let headers = client.request_headers(&MetaRequest {});
+
+        assert_eq!(headers.get(USER_AGENT).unwrap(), "custom/1.0");
+        assert_eq!(
+            headers.get("cf-access-client-id").unwrap(),
+            "service-token"
+        );
+        assert!(headers.contains_key(http::header::REFERER));
+    }
+
+    #[test]
+    fn test_from_headers_sets_user_agent() {
+        let mut extra = HeaderMap::new();
+        extra.insert(USER_AGENT, HeaderValue::from_static("custom/1.0"));
+        extra.insert("x-one", HeaderValue::from_static("one"));
+
+        let client = Client::from_headers(extra);
+        let headers = client.request_headers(&MetaRequest {});
+
+        assert_eq!(client.user_agent(), "custom/1.0");
+        assert_eq!(headers.get(USER_AGENT).unwrap(), "custom/1.0");
+        assert_eq!(headers.get("x-one").unwrap(), "one");
+        let client = Client::from_headers(HeaderMap::new());
+        assert_eq!(client.user_agent(), DEFAULT_USER_AGENT);
+    }
+
+    #[test]
+    fn test_middleware_runs_in_order() {
+        let client = Client::new()
+            .with_middleware(|h| {
+                h.insert("x-order", HeaderValue::from_static("first"));
+            })
+            .with_middleware(|h| {
+                h.insert("x-order", HeaderValue::from_static("second"));
+            });
+        let headers = client.request_headers(&MetaRequest {});
+
+        assert_eq!(headers.get("x-order").unwrap(), "second");
+    }
+}
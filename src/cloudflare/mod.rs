@@ -1,3 +1,6 @@
+pub mod certs;
 pub mod client;
+pub mod portal;
+pub mod proxy;
 pub mod requests;
 pub mod tests;
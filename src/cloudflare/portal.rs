@@ -0,0 +1,265 @@
+//! Detection of captive portals and intercepting middleboxes.
+//!
+//! Before a test starts, two resources with known responses are fetched:
+//! a plain HTTP endpoint that always answers `204 No Content`, and a small
+//! fixed-size download from the speed test server over HTTPS. A captive
+//! portal usually answers the first with a redirect or login page, while a
+//! TLS-intercepting proxy fails certificate validation on the second or
+//! rewrites its body.
+
+use crate::cloudflare::client::http_client_builder;
+use reqwest::{redirect, Client as ReqwestClient, StatusCode};
+use std::error::Error;
+use std::time::Duration;
+
+/// Endpoint that returns an empty `204 No Content` when not intercepted.
+const PORTAL_PROBE_URL: &str = "http://cp.cloudflare.com/generate_204";
+
+/// Size of the HTTPS content probe.
+const CONTENT_PROBE_BYTES: usize = 32;
+
+/// How long each probe may take before it is treated as inconclusive.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of the pre-test interception check.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InterceptionCheck {
+    /// Why interception is suspected, if it is
+    pub reason: Option<String>,
+}
+
+impl InterceptionCheck {
+    /// Whether a captive portal or intercepting middlebox is suspected.
+    pub fn is_suspected(&self) -> bool {
+        self.reason.is_some()
+    }
+
+    /// Attribute `error` to interception when it is suspected.
+    ///
+    /// The message is rewritten so the failure classifies as
+    /// [`ErrorKind::Intercepted`](crate::errors::ErrorKind::Intercepted);
+    /// otherwise the error is returned unchanged.
+    pub fn attribute<E: Into<Box<dyn Error>>>(
+        &self,
+        error: E,
+    ) -> Box<dyn Error> {
+        let error = error.into();
+        match &self.reason {
+            Some(reason) => {
+                format!("Connection intercepted ({}): {}", reason, error)
+                    .into()
+            }
+            None => error,
+        }
+    }
+}
+
+/// Probe for captive portals and intercepting middleboxes.
+///
+/// Probes that fail for unrelated reasons (e.g. no connectivity at all)
+/// are inconclusive and do not raise suspicion; the test itself will
+/// report those failures. The probes go through the same proxy, name
+/// server and certificates as the test, so a TLS-inspecting proxy trusted
+/// with `--cacert` doesn't count as interception.
+pub async fn check_interception() -> InterceptionCheck {
+    let client = match http_client_builder()
+        .redirect(redirect::Policy::none())
+        .timeout(PROBE_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            log::debug!("Skipping interception check: {}", e);
+            return InterceptionCheck::default();
+        }
+    };
+
+    let content_url = format!(
+        "https://speed.cloudflare.com/__down?bytes={}",
+        CONTENT_PROBE_BYTES
+    );
+    let (portal, content) = tokio::join!(
+        probe(&client, PORTAL_PROBE_URL),
+        probe(&client, &content_url)
+    );
+
+    let portal_reason = match portal {
+        Ok((status, body)) => evaluate_portal_probe(status, &body),
+        Err(e) => {
+            log::debug!("Captive portal probe inconclusive: {}", e);
+            None
+        }
+    };
+
+    let content_reason = match content {
+        Ok((status, body)) => evaluate_content_probe(status, &body),
+        Err(e) if is_certificate_error(&e) => Some(format!(
+            "certificate for speed.cloudflare.com failed validation: {}",
+            root_cause(&e)
+        )),
+        Err(e) => {
+            log::debug!("Content probe inconclusive: {}", e);
+            None
+        }
+    };
+
+    let reason = portal_reason.or(content_reason);
+    if let Some(ref reason) = reason {
+        log::warn!(
+            "Captive portal or intercepting proxy suspected: {}",
+            reason
+        );
+    }
+
+    InterceptionCheck { reason }
+}
+
+async fn probe(
+    client: &ReqwestClient,
+    url: &str,
+) -> Result<(StatusCode, Vec<u8>), reqwest::Error> {
+    let response = client.get(url).send().await?;
+    let status = response.status();
+    let body = response.bytes().await?;
+
+    Ok((status, body.to_vec()))
+}
+
+/// Check the plain HTTP probe, which must be an empty `204`.
+fn evaluate_portal_probe(status: StatusCode, body: &[u8]) -> Option<String> {
+    if status.is_redirection() {
+        return Some(format!("HTTP probe was redirected ({})", status));
+    }
+
+    if status != StatusCode::NO_CONTENT {
+        return Some(format!("HTTP probe returned {} instead of 204", status));
+    }
+
+    if !body.is_empty() {
+        return Some(format!(
+            "HTTP probe returned {} unexpected bytes",
+            body.len()
+        ));
+    }
+
+    None
+}
+
+/// Check the HTTPS probe, which must return exactly the bytes requested.
+fn evaluate_content_probe(status: StatusCode, body: &[u8]) -> Option<String> {
+    if status != StatusCode::OK {
+        return Some(format!(
+            "HTTPS probe returned {} instead of 200",
+            status
+        ));
+    }
+
+    if body.len() != CONTENT_PROBE_BYTES {
+        return Some(format!(
+            "HTTPS probe returned {} bytes instead of {}",
+            body.len(),
+            CONTENT_PROBE_BYTES
+        ));
+    }
+
+    None
+}
+
+/// Whether any error in the source chain is a certificate failure.
+fn is_certificate_error(error: &(dyn Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(e) = current {
+        if e.to_string().to_lowercase().contains("certificate") {
+            return true;
+        }
+        current = e.source();
+    }
+
+    false
+}
+
+/// The innermost error in the source chain.
+fn root_cause(error: &(dyn Error + 'static)) -> String {
+    let mut current = error;
+    while let Some(source) = current.source() {
+        current = source;
+    }
+
+    current.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_portal_probe_accepts_empty_204() {
+        assert_eq!(evaluate_portal_probe(StatusCode::NO_CONTENT, b""), None);
+    }
+
+    #[test]
+    fn test_portal_probe_flags_redirect() {
+        let reason = evaluate_portal_probe(StatusCode::FOUND, b"").unwrap();
+        assert!(reason.contains("redirected"));
+    }
+
+    #[test]
+    fn test_portal_probe_flags_login_page() {
+        assert!(evaluate_portal_probe(
+            StatusCode::OK,
+            b"<html>Please log in</html>"
+        )
+        .is_some());
+        assert!(
+            evaluate_portal_probe(StatusCode::NO_CONTENT, b"<html>").is_some()
+        );
+    }
+
+    #[test]
+    fn test_content_probe() {
+        let body = vec![b'0'; CONTENT_PROBE_BYTES];
+        assert_eq!(evaluate_content_probe(StatusCode::OK, &body), None);
+        assert!(evaluate_content_probe(StatusCode::OK, b"<html>").is_some());
+        assert!(evaluate_content_probe(StatusCode::FORBIDDEN, &body).is_some());
+    }
+
+    #[test]
+    fn test_is_certificate_error_walks_sources() {
+        let inner =
+            std::io::Error::other("invalid peer certificate: UnknownIssuer");
+        let outer = std::io::Error::other(SourceError(inner));
+        assert!(is_certificate_error(&outer));
+        assert!(!is_certificate_error(&std::io::Error::other(
+            "connection refused"
+        )));
+    }
+
+    #[test]
+    fn test_attribute() {
+        let check = InterceptionCheck::default();
+        assert_eq!(check.attribute("boom").to_string(), "boom");
+
+        let check = InterceptionCheck {
+            reason: Some("HTTP probe was redirected".to_string()),
+        };
+        assert!(check.is_suspected());
+        let message = check.attribute("boom").to_string();
+        assert!(message.starts_with("Connection intercepted"));
+        assert!(message.ends_with("boom"));
+    }
+
+    #[derive(Debug)]
+    struct SourceError(std::io::Error);
+
+    impl std::fmt::Display for SourceError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "request failed")
+        }
+    }
+
+    impl Error for SourceError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+}
@@ -10,13 +10,13 @@ pub struct LocationsResponse(Vec<Location>);
 #[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct Location {
     pub iata: String,
-    #[serde(rename(serialize = "lat", deserialize = "lat"))]
-    pub _lat: f64,
-    #[serde(rename(serialize = "lon", deserialize = "lon"))]
-    pub _lon: f64,
+    pub lat: f64,
+    pub lon: f64,
     pub city: String,
-    #[serde(rename(serialize = "region", deserialize = "region"))]
-    pub _region: String,
+    pub region: String,
+    /// ISO 3166-1 alpha-2 country code
+    #[serde(default)]
+    pub cca2: String,
 }
 
 pub(crate) struct Locations {}
@@ -32,6 +32,11 @@ impl Request for Locations {
 }
 
 impl LocationsResponse {
+    /// All locations, in the order Cloudflare lists them.
+    pub(crate) fn into_vec(self) -> Vec<Location> {
+        self.0
+    }
+
     pub(crate) fn get(self, iata: &str) -> Location {
         self.0
             .into_iter()
@@ -5,22 +5,10 @@ pub mod meta;
 
 use http::header::REFERER;
 use http::HeaderValue;
-use reqwest::{
-    header::{HeaderMap, USER_AGENT},
-    Body, Method,
-};
+use reqwest::{header::HeaderMap, Body, Method};
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 
-pub const UA: &str = concat!(
-    env!("CARGO_PKG_NAME"),
-    "/",
-    env!("CARGO_PKG_VERSION"),
-    " (",
-    env!("CARGO_PKG_REPOSITORY"),
-    ")"
-);
-
 #[derive(
     Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default,
 )]
@@ -42,7 +30,6 @@ pub trait Request {
     fn headers(&self) -> HeaderMap {
         let mut headers = HeaderMap::new();
 
-        headers.insert(USER_AGENT, UA.parse().unwrap());
         headers.insert(
             REFERER,
             HeaderValue::from_static("https://speed.cloudflare.com/"),
@@ -3,39 +3,532 @@
 //! This module provides common connection establishment functions used by
 //! both download and upload tests.
 
-use super::IoReadAndWrite;
-use hickory_resolver::TokioResolver;
-use rustls_connector::RustlsConnector;
+use super::{extract_http_status, IoReadAndWrite};
+use crate::cloudflare::certs::certificates;
+use crate::cloudflare::proxy::{env_proxy, redact};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig};
+use hickory_resolver::name_server::TokioConnectionProvider;
+use hickory_resolver::{ResolveError, TokioResolver};
+use log::debug;
+use percent_encoding::percent_decode_str;
+use rustls_connector::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use rustls_connector::rustls::client::{
+    ClientSessionMemoryCache, Resumption, WebPkiServerVerifier,
+};
+use rustls_connector::rustls::pki_types::{
+    CertificateDer, ServerName, UnixTime,
+};
+use rustls_connector::rustls::{
+    self, ClientConfig, DigitallySignedStruct, HandshakeKind, ProtocolVersion,
+    RootCertStore, SignatureScheme,
+};
+use rustls_connector::{rustls_native_certs, webpki_roots, RustlsConnector};
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::collections::HashMap;
 use std::error::Error;
-use std::io::Write;
-use std::net::{IpAddr, TcpStream};
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex, Once, OnceLock};
 use std::time::Duration;
 use tokio::time::Instant;
 use url::Url;
 
+/// Number of TLS sessions kept for resumption once it is enabled.
+const SESSION_CACHE_SIZE: usize = 32;
+
+/// Hosts pinned to a single address for the rest of the process.
+fn pinned_hosts() -> &'static Mutex<HashMap<String, IpAddr>> {
+    static PINNED: OnceLock<Mutex<HashMap<String, IpAddr>>> = OnceLock::new();
+    PINNED.get_or_init(Default::default)
+}
+
+/// Resolve `url`'s host and pin it to the preferred answer, so later
+/// connections skip DNS and can't be spread across addresses by varying
+/// answers.
+///
+/// Any earlier pin for the host is replaced. Returns the full lookup.
+pub async fn pin_dns(url: &Url) -> Result<DnsLookup, Box<dyn Error>> {
+    let lookup = lookup_url(url).await?;
+    let ip = lookup.preferred();
+    debug!(
+        "Pinned {} to {} of {:?} (lookup {:.1} ms)",
+        lookup.host,
+        ip,
+        lookup.addresses,
+        lookup.duration.as_secs_f64() * 1000.0
+    );
+    pin_address(&lookup.host, ip);
+
+    Ok(lookup)
+}
+
+/// Pin `host` to `ip`, replacing any earlier pin.
+pub fn pin_address(host: &str, ip: IpAddr) {
+    if let Ok(mut pinned) = pinned_hosts().lock() {
+        pinned.insert(host.to_string(), ip);
+    }
+}
+
+/// Name server every hostname lookup goes to, once one is chosen with
+/// `--resolver`.
+static NAME_SERVER: OnceLock<SocketAddr> = OnceLock::new();
+
+/// Send every later hostname lookup to `server` instead of the system's
+/// configured resolvers.
+///
+/// Only the first call takes effect.
+pub fn use_name_server(server: SocketAddr) {
+    let _ = NAME_SERVER.set(server);
+}
+
+/// The name server chosen with [`use_name_server`], if any.
+pub fn name_server() -> Option<SocketAddr> {
+    NAME_SERVER.get().copied()
+}
+
+/// A resolver for the chosen name server, or for the system's
+/// configuration when none was chosen.
+fn resolver() -> Result<TokioResolver, ResolveError> {
+    let builder = match name_server() {
+        Some(server) => TokioResolver::builder_with_config(
+            ResolverConfig::from_parts(
+                None,
+                vec![],
+                NameServerConfigGroup::from_ips_clear(
+                    &[server.ip()],
+                    server.port(),
+                    true,
+                ),
+            ),
+            TokioConnectionProvider::default(),
+        ),
+        None => TokioResolver::builder_tokio()?,
+    };
+
+    Ok(builder.build())
+}
+
+/// The answer to a hostname lookup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnsLookup {
+    /// Hostname that was looked up
+    pub host: String,
+    /// Every address it resolved to, in answer order (never empty)
+    pub addresses: Vec<IpAddr>,
+    /// Time the lookup took
+    pub duration: Duration,
+    /// Name server that answered, when one was chosen with `--resolver`
+    pub name_server: Option<SocketAddr>,
+}
+
+impl DnsLookup {
+    /// The address connections use: the first IPv4 answer, or the first
+    /// answer when there is no IPv4 one.
+    pub fn preferred(&self) -> IpAddr {
+        self.addresses
+            .iter()
+            .find(|ip| ip.is_ipv4())
+            .unwrap_or(&self.addresses[0])
+            .to_owned()
+    }
+}
+
+/// A resolver that keeps the answers it gets, so a repeated lookup of a
+/// host is answered from its cache.
+pub struct HostResolver(TokioResolver);
+
+impl HostResolver {
+    /// A resolver for the chosen name server, with an empty cache.
+    pub fn new() -> Result<Self, ResolveError> {
+        resolver().map(Self)
+    }
+
+    /// Look up every address of `host`.
+    pub async fn lookup(
+        &self,
+        host: &str,
+    ) -> Result<DnsLookup, Box<dyn Error + Send + Sync>> {
+        let begin = Instant::now();
+        let response = self.0.lookup_ip(host).await?;
+        let duration = begin.elapsed();
+
+        let addresses: Vec<_> = response.iter().collect();
+        if addresses.is_empty() {
+            return Err(format!("No addresses found for {}", host).into());
+        }
+
+        Ok(DnsLookup {
+            host: host.to_string(),
+            addresses,
+            duration,
+            name_server: name_server(),
+        })
+    }
+}
+
+/// Look up every address of `host` through the chosen name server.
+pub async fn lookup_host(
+    host: &str,
+) -> Result<DnsLookup, Box<dyn Error + Send + Sync>> {
+    HostResolver::new()?.lookup(host).await
+}
+
+/// Shared TLS session store, present once resumption is enabled.
+static SESSION_STORE: OnceLock<Arc<ClientSessionMemoryCache>> =
+    OnceLock::new();
+
+/// Let later TLS handshakes resume sessions from earlier ones.
+///
+/// Each handshake otherwise uses a fresh configuration and performs a
+/// full handshake.
+pub fn enable_session_resumption() {
+    SESSION_STORE.get_or_init(session_store);
+}
+
+/// A new, empty TLS session store.
+pub fn session_store() -> Arc<ClientSessionMemoryCache> {
+    Arc::new(ClientSessionMemoryCache::new(SESSION_CACHE_SIZE))
+}
+
+/// Cipher suites TLS connections may negotiate.
+///
+/// At multi-gigabit speeds record encryption can cost more CPU than the
+/// rest of a transfer. AES-GCM is fastest on CPUs with AES instructions
+/// (AES-NI, ARMv8 crypto extensions), ChaCha20-Poly1305 on those without.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TlsCipher {
+    /// Offer every suite, in rustls's order of preference
+    #[default]
+    Auto,
+    /// Offer AES-GCM suites only
+    AesGcm,
+    /// Offer ChaCha20-Poly1305 suites only
+    Chacha20,
+}
+
+impl TlsCipher {
+    /// Whether `suite` may be offered.
+    fn allows(self, suite: rustls::CipherSuite) -> bool {
+        let name = suite.as_str().unwrap_or_default();
+        match self {
+            TlsCipher::Auto => true,
+            TlsCipher::AesGcm => {
+                name.contains("_AES_") && name.contains("GCM")
+            }
+            TlsCipher::Chacha20 => name.contains("CHACHA20_POLY1305"),
+        }
+    }
+}
+
+impl std::str::FromStr for TlsCipher {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(TlsCipher::Auto),
+            "aes-gcm" | "aes" => Ok(TlsCipher::AesGcm),
+            "chacha20" | "chacha" => Ok(TlsCipher::Chacha20),
+            _ => Err(format!(
+                "invalid TLS cipher '{}' (expected auto, aes-gcm or chacha20)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for TlsCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsCipher::Auto => write!(f, "auto"),
+            TlsCipher::AesGcm => write!(f, "aes-gcm"),
+            TlsCipher::Chacha20 => write!(f, "chacha20"),
+        }
+    }
+}
+
+/// Cipher suites every later TLS handshake offers, once chosen with
+/// `--tls-cipher`.
+static TLS_CIPHER: OnceLock<TlsCipher> = OnceLock::new();
+
+/// Offer only the suites `cipher` allows in every later TLS handshake.
+///
+/// Only the first call takes effect.
+pub fn use_tls_cipher(cipher: TlsCipher) {
+    let _ = TLS_CIPHER.set(cipher);
+}
+
+/// The ring crypto provider, limited to the suites chosen with
+/// [`use_tls_cipher`].
+fn crypto_provider() -> Arc<rustls::crypto::CryptoProvider> {
+    let cipher = TLS_CIPHER.get().copied().unwrap_or_default();
+    let mut provider = rustls::crypto::ring::default_provider();
+    provider.cipher_suites.retain(|suite| cipher.allows(suite.suite()));
+    Arc::new(provider)
+}
+
 /// Resolve DNS for a URL, preferring IPv4 addresses.
 ///
+/// Hosts pinned with [`pin_dns`] resolve to their pinned address without
+/// a lookup.
+///
 /// Returns the resolved IP address and the time taken for DNS resolution.
-pub async fn resolve_dns(url: &Url) -> Result<(IpAddr, Duration), Box<dyn Error>> {
-    let resolver = TokioResolver::builder_tokio()?.build();
+pub async fn resolve_dns(
+    url: &Url,
+) -> Result<(IpAddr, Duration), Box<dyn Error>> {
+    let pinned = url.host_str().and_then(|host| {
+        pinned_hosts().lock().ok().and_then(|p| p.get(host).copied())
+    });
+    if let Some(ip) = pinned {
+        return Ok((ip, Duration::ZERO));
+    }
+
+    lookup_dns(url).await
+}
+
+/// Look up `url`'s host, preferring IPv4 addresses.
+async fn lookup_dns(url: &Url) -> Result<(IpAddr, Duration), Box<dyn Error>> {
+    let lookup = lookup_url(url).await?;
+    Ok((lookup.preferred(), lookup.duration))
+}
+
+/// Look up every address of `url`'s host.
+async fn lookup_url(url: &Url) -> Result<DnsLookup, Box<dyn Error>> {
+    let host = url.host_str().ok_or("URL has no host")?;
+
+    // IP literals (e.g. a proxy given by address) need no lookup
+    let literal = match url.host() {
+        Some(url::Host::Ipv4(ip)) => Some(IpAddr::from(ip)),
+        Some(url::Host::Ipv6(ip)) => Some(IpAddr::from(ip)),
+        _ => None,
+    };
+    if let Some(ip) = literal {
+        return Ok(DnsLookup {
+            host: host.to_string(),
+            addresses: vec![ip],
+            duration: Duration::ZERO,
+            name_server: None,
+        });
+    }
+
+    lookup_host(host).await.map_err(|e| e as Box<dyn Error>)
+}
+
+/// A Differentiated Services code point (RFC 2474), 0 through 63.
+///
+/// Parsed from a class name (`ef`, `af41`, `cs1`, `be`) or a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dscp(u8);
+
+impl Dscp {
+    /// Expedited Forwarding, as used for voice
+    pub const EF: Dscp = Dscp(46);
+
+    /// The code point, 0 through 63.
+    pub fn value(self) -> u8 {
+        self.0
+    }
+
+    /// The IPv4 TOS / IPv6 traffic class byte carrying this code point.
+    ///
+    /// The ECN bits are left clear.
+    pub fn traffic_class(self) -> u32 {
+        u32::from(self.0) << 2
+    }
+}
+
+impl std::str::FromStr for Dscp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+        let invalid = || {
+            format!(
+                "invalid DSCP '{}' (expected ef, af11-af43, cs0-cs7, be, \
+                 or 0-63)",
+                s
+            )
+        };
+
+        let value = match name.as_str() {
+            "ef" => Dscp::EF.0,
+            "be" | "default" => 0,
+            _ if name.starts_with("af") => {
+                let digits = &name.as_bytes()[2..];
+                match digits {
+                    [class @ b'1'..=b'4', drop @ b'1'..=b'3'] => {
+                        (class - b'0') * 8 + (drop - b'0') * 2
+                    }
+                    _ => return Err(invalid()),
+                }
+            }
+            _ if name.starts_with("cs") => match name[2..].parse::<u8>() {
+                Ok(class @ 0..=7) => class * 8,
+                _ => return Err(invalid()),
+            },
+            _ => match name.parse::<u8>() {
+                Ok(value @ 0..=63) => value,
+                _ => return Err(invalid()),
+            },
+        };
+
+        Ok(Dscp(value))
+    }
+}
 
-    let begin = Instant::now();
+impl<'de> serde::Deserialize<'de> for Dscp {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl std::fmt::Display for Dscp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            0 => write!(f, "be"),
+            46 => write!(f, "ef"),
+            value if value % 8 == 0 => write!(f, "cs{}", value / 8),
+            value
+                if (1..=4).contains(&(value / 8))
+                    && (1..=3).contains(&(value % 8 / 2))
+                    && value % 2 == 0 =>
+            {
+                write!(f, "af{}{}", value / 8, value % 8 / 2)
+            }
+            value => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Socket settings requested for measurement connections.
+///
+/// `None` leaves the operating system's default (and, on Linux, its
+/// buffer autotuning) in place.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// Requested `SO_SNDBUF` in bytes
+    pub send_buffer: Option<usize>,
+    /// Requested `SO_RCVBUF` in bytes
+    pub recv_buffer: Option<usize>,
+    /// DSCP marking for outgoing packets
+    pub dscp: Option<Dscp>,
+}
+
+impl SocketOptions {
+    /// Apply the requested settings to `socket`, which will connect to
+    /// `address`.
+    ///
+    /// Must run before connecting, since the receive buffer determines
+    /// the window scale negotiated in the handshake.
+    fn apply(
+        &self,
+        socket: &Socket,
+        address: &SocketAddr,
+    ) -> std::io::Result<()> {
+        if let Some(dscp) = self.dscp {
+            // Marking is best effort: an unmarked test is still a test
+            static WARN_DSCP: Once = Once::new();
+            if let Err(e) = set_traffic_class(socket, address, dscp) {
+                WARN_DSCP.call_once(|| {
+                    log::warn!("Could not set DSCP {}: {}", dscp, e)
+                });
+            }
+        }
+
+        if let Some(bytes) = self.send_buffer {
+            socket.set_send_buffer_size(bytes)?;
+        }
+        if let Some(bytes) = self.recv_buffer {
+            socket.set_recv_buffer_size(bytes)?;
+        }
+
+        // The kernel silently caps requests (net.core.wmem_max/rmem_max on
+        // Linux), so say so once rather than report misleading settings
+        static WARN_CLAMPED: Once = Once::new();
+        if let Some(effective) = SocketBuffers::read(socket) {
+            let clamped = |requested: Option<usize>, effective: usize| {
+                requested.is_some_and(|requested| effective < requested)
+            };
+            if clamped(self.send_buffer, effective.send_bytes)
+                || clamped(self.recv_buffer, effective.recv_bytes)
+            {
+                WARN_CLAMPED.call_once(|| {
+                    log::warn!(
+                        "Socket buffers limited by the OS: requested \
+                         send={:?} recv={:?}, got send={} recv={}",
+                        self.send_buffer,
+                        self.recv_buffer,
+                        effective.send_bytes,
+                        effective.recv_bytes
+                    )
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
 
-    let response = resolver.lookup_ip(url.host_str().unwrap()).await?;
+/// Set the IPv4 TOS or IPv6 traffic class byte for `dscp`.
+fn set_traffic_class(
+    socket: &Socket,
+    address: &SocketAddr,
+    dscp: Dscp,
+) -> std::io::Result<()> {
+    if address.is_ipv4() {
+        return socket.set_tos_v4(dscp.traffic_class());
+    }
 
-    let duration = begin.elapsed();
+    #[cfg(any(
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "linux",
+        target_os = "macos",
+    ))]
+    return socket.set_tclass_v6(dscp.traffic_class());
 
-    let ipv4_addresses: Vec<_> =
-        response.iter().filter(|addr| addr.is_ipv4()).collect();
+    #[cfg(not(any(
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "linux",
+        target_os = "macos",
+    )))]
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "IPv6 traffic class is not supported on this platform",
+    ))
+}
 
-    let ipv6_addresses: Vec<_> =
-        response.iter().filter(|addr| addr.is_ipv6()).collect();
+/// Effective socket buffer sizes reported by the operating system.
+///
+/// Linux doubles requested sizes to leave room for bookkeeping, and grows
+/// unset buffers as a transfer proceeds, so these can differ from what
+/// was requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketBuffers {
+    /// `SO_SNDBUF` in bytes
+    pub send_bytes: usize,
+    /// `SO_RCVBUF` in bytes
+    pub recv_bytes: usize,
+}
 
-    if !ipv4_addresses.is_empty() {
-        return Ok((ipv4_addresses[0], duration));
+impl SocketBuffers {
+    fn read(socket: &Socket) -> Option<Self> {
+        Some(Self {
+            send_bytes: socket.send_buffer_size().ok()?,
+            recv_bytes: socket.recv_buffer_size().ok()?,
+        })
     }
+}
 
-    Ok((ipv6_addresses[0], duration))
+/// Read the effective socket buffer sizes of `stream`.
+pub fn socket_buffers(stream: &TcpStream) -> Option<SocketBuffers> {
+    SocketBuffers::read(&SockRef::from(stream))
 }
 
 /// Establish a TCP connection to the given address and port.
@@ -47,10 +540,33 @@ pub async fn resolve_dns(url: &Url) -> Result<(IpAddr, Duration), Box<dyn Error>
 pub async fn tcp_connect(
     address: IpAddr,
     port: u16,
+    options: SocketOptions,
+) -> Result<(TcpStream, Duration), Box<dyn Error>> {
+    tcp_connect_within(SocketAddr::new(address, port), options, None).await
+}
+
+/// Like [`tcp_connect`], giving up after `timeout` when one is given.
+pub async fn tcp_connect_within(
+    address: SocketAddr,
+    options: SocketOptions,
+    timeout: Option<Duration>,
 ) -> Result<(TcpStream, Duration), Box<dyn Error>> {
     tokio::task::spawn_blocking(move || {
+        let socket = Socket::new(
+            Domain::for_address(address),
+            Type::STREAM,
+            Some(Protocol::TCP),
+        )?;
+        options.apply(&socket, &address)?;
+
         let now = Instant::now();
-        let mut stream = TcpStream::connect((address, port))?;
+        match timeout {
+            Some(timeout) => {
+                socket.connect_timeout(&address.into(), timeout)?
+            }
+            None => socket.connect(&address.into())?,
+        }
+        let mut stream = TcpStream::from(socket);
         stream.flush()?;
         let tcp_connect_duration = now.elapsed();
         Ok::<_, std::io::Error>((stream, tcp_connect_duration))
@@ -59,38 +575,508 @@ pub async fn tcp_connect(
     .map_err(|e| e.into())
 }
 
-/// Perform TLS handshake on an established TCP connection.
+/// Open a TCP connection for `url`, tunnelling through the environment
+/// proxy when one applies.
+///
+/// When a proxy is used, the returned address and port are the proxy's,
+/// since that is the first hop loaded latency probes can reach.
+///
+/// Returns the connected stream, the address and port connected to, and
+/// the time taken to establish the TCP connection.
+pub async fn connect(
+    url: &Url,
+) -> Result<(TcpStream, IpAddr, u16, Duration), Box<dyn Error>> {
+    connect_with(url, SocketOptions::default()).await
+}
+
+/// Like [`connect`], applying `options` to the socket.
+pub async fn connect_with(
+    url: &Url,
+    options: SocketOptions,
+) -> Result<(TcpStream, IpAddr, u16, Duration), Box<dyn Error>> {
+    let Some(proxy) = env_proxy().proxy_for(url) else {
+        let (ip_address, _dns_duration) = resolve_dns(url).await?;
+        let port = url.port_or_known_default().unwrap();
+        let (stream, duration) =
+            tcp_connect(ip_address, port, options).await?;
+        return Ok((stream, ip_address, port, duration));
+    };
+
+    if proxy.scheme() != "http" {
+        return Err(format!(
+            "Unsupported proxy scheme '{}' (only http:// proxies are \
+            supported)",
+            proxy.scheme()
+        )
+        .into());
+    }
+
+    debug!("Connecting to {} via proxy {}", url, redact(proxy));
+
+    let (ip_address, _dns_duration) = resolve_dns(proxy).await?;
+    let port = proxy.port_or_known_default().unwrap();
+    let (stream, duration) = tcp_connect(ip_address, port, options).await?;
+    let stream = connect_tunnel(stream, url, proxy).await?;
+
+    Ok((stream, ip_address, port, duration))
+}
+
+/// Ask an HTTP proxy to open a tunnel to `target` with `CONNECT`.
 ///
 /// Runs on a blocking thread pool via `spawn_blocking` to avoid
 /// starving the tokio async runtime.
-///
-/// Returns a TLS-wrapped stream and the time taken for the handshake.
-pub async fn tls_handshake_duration(
-    tcp: TcpStream,
-    host: String,
-) -> Result<(Box<dyn IoReadAndWrite>, Duration), Box<dyn Error>> {
+async fn connect_tunnel(
+    mut stream: TcpStream,
+    target: &Url,
+    proxy: &Url,
+) -> Result<TcpStream, Box<dyn Error>> {
+    let authority = format!(
+        "{}:{}",
+        target.host_str().unwrap_or(""),
+        target.port_or_known_default().unwrap()
+    );
+    let mut request =
+        format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n",);
+    if !proxy.username().is_empty() {
+        let decode =
+            |s: &str| percent_decode_str(s).decode_utf8_lossy().into_owned();
+        let credentials = format!(
+            "{}:{}",
+            decode(proxy.username()),
+            decode(proxy.password().unwrap_or(""))
+        );
+        request.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            BASE64_STANDARD.encode(credentials)
+        ));
+    }
+    request.push_str("\r\n");
+
     let result: Result<_, Box<dyn Error + Send + Sync>> =
         tokio::task::spawn_blocking(move || {
-            let connector: RustlsConnector =
-                RustlsConnector::new_with_native_certs()
-                    .unwrap_or_else(|_| {
-                        RustlsConnector::new_with_webpki_roots_certs()
-                    });
-            let now = Instant::now();
-
-            let mut stream = connector.connect(&host, tcp)?;
+            stream.write_all(request.as_bytes())?;
             stream.flush()?;
-            let tls_handshake_duration = now.elapsed();
-            Ok((
-                Box::new(stream) as Box<dyn IoReadAndWrite>,
-                tls_handshake_duration,
-            ))
+
+            // Read the proxy's response headers one byte at a time so no
+            // bytes belonging to the tunnelled stream are consumed.
+            let mut response = Vec::new();
+            let mut byte = [0_u8];
+            while !response.ends_with(b"\r\n\r\n") {
+                if stream.read(&mut byte)? == 0 {
+                    return Err(
+                        "Proxy closed connection during CONNECT".into()
+                    );
+                }
+                response.push(byte[0]);
+            }
+
+            let response = String::from_utf8_lossy(&response);
+            match extract_http_status(&response) {
+                Some(200) => Ok(stream),
+                Some(status) => {
+                    Err(format!("Proxy CONNECT failed: HTTP {status}").into())
+                }
+                None => Err("Malformed response from proxy".into()),
+            }
         })
         .await?;
 
     result.map_err(|e| e as Box<dyn Error>)
 }
 
+/// Details of a negotiated TLS session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsInfo {
+    /// Negotiated protocol version (e.g. "TLSv1.3")
+    pub version: String,
+    /// Negotiated cipher suite (e.g. "TLS13_AES_128_GCM_SHA256")
+    pub cipher_suite: String,
+    /// Protocol selected via ALPN, if the server chose one
+    pub alpn: Option<String>,
+    /// Number of certificates the server presented
+    pub cert_chain_len: usize,
+    /// Time spent validating the server's certificate chain
+    pub cert_verify_duration: Option<Duration>,
+    /// Total time for the TLS handshake
+    pub handshake_duration: Duration,
+    /// Whether an earlier session was resumed
+    pub resumed: bool,
+    /// TLS implementation the session ran over
+    pub backend: TlsBackend,
+    /// Where the certificates the server was checked against came from
+    pub trust: TrustSource,
+}
+
+/// TLS implementation used for measurement connections.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum TlsBackend {
+    /// rustls, the same on every platform
+    #[default]
+    Rustls,
+    /// The platform's own library (SChannel, Secure Transport, OpenSSL),
+    /// verifying with the OS, for networks whose TLS inspection only the
+    /// OS is set up to trust
+    Native,
+}
+
+impl std::str::FromStr for TlsBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rustls" => Ok(TlsBackend::Rustls),
+            "native" if cfg!(feature = "native-tls") => Ok(TlsBackend::Native),
+            "native" => Err("this build has no native TLS support (enable \
+                             the native-tls feature)"
+                .to_string()),
+            _ => Err(format!(
+                "invalid TLS backend '{}' (expected rustls or native)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for TlsBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsBackend::Rustls => write!(f, "rustls"),
+            TlsBackend::Native => write!(f, "native"),
+        }
+    }
+}
+
+/// Where trusted root certificates came from.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum TrustSource {
+    /// The operating system's trust store, including any roots an
+    /// administrator added
+    #[default]
+    System,
+    /// The Mozilla roots bundled into the binary, used when the system
+    /// store could not be loaded
+    Bundled,
+    /// Roots from `--cacert`, on top of the system's or bundled ones
+    Custom,
+}
+
+impl std::fmt::Display for TrustSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrustSource::System => write!(f, "system"),
+            TrustSource::Bundled => write!(f, "bundled"),
+            TrustSource::Custom => write!(f, "custom"),
+        }
+    }
+}
+
+/// TLS backend every later handshake uses, once chosen with
+/// `--tls-backend`.
+static TLS_BACKEND: OnceLock<TlsBackend> = OnceLock::new();
+
+/// Make every later TLS handshake use `backend`.
+///
+/// Only the first call takes effect.
+pub fn use_tls_backend(backend: TlsBackend) {
+    let _ = TLS_BACKEND.set(backend);
+}
+
+/// The backend chosen with [`use_tls_backend`], rustls by default.
+pub fn tls_backend() -> TlsBackend {
+    TLS_BACKEND.get().copied().unwrap_or_default()
+}
+
+/// Certificate verifier that records how long chain validation takes.
+#[derive(Debug)]
+struct TimedVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    elapsed: Arc<Mutex<Option<Duration>>>,
+}
+
+impl ServerCertVerifier for TimedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let start = std::time::Instant::now();
+        let result = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        );
+        if let Ok(mut elapsed) = self.elapsed.lock() {
+            *elapsed = Some(start.elapsed());
+        }
+        result
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Trusted root certificates for rustls, loaded once per process, and
+/// where they came from.
+///
+/// Uses the platform's native store, falling back to the bundled
+/// webpki roots if it cannot be loaded, plus any roots from `--cacert`.
+fn root_store() -> (Arc<RootCertStore>, TrustSource) {
+    static ROOTS: OnceLock<(Arc<RootCertStore>, TrustSource)> =
+        OnceLock::new();
+    ROOTS
+        .get_or_init(|| {
+            let native = rustls_native_certs::load_native_certs();
+            let mut roots = RootCertStore::empty();
+            if native.errors.is_empty() {
+                roots.add_parsable_certificates(native.certs);
+            }
+            let mut trust = TrustSource::System;
+            if roots.is_empty() {
+                debug!("System trust store unavailable, using bundled roots");
+                roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+                trust = TrustSource::Bundled;
+            }
+            if let Some(extra) = certificates().filter(|c| !c.roots.is_empty())
+            {
+                roots.add_parsable_certificates(extra.roots.iter().cloned());
+                trust = TrustSource::Custom;
+            }
+            (Arc::new(roots), trust)
+        })
+        .clone()
+}
+
+/// Format a protocol version the way it is usually written.
+fn format_tls_version(version: ProtocolVersion) -> String {
+    match version {
+        ProtocolVersion::TLSv1_2 => "TLSv1.2".to_string(),
+        ProtocolVersion::TLSv1_3 => "TLSv1.3".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Perform TLS handshake on an established TCP connection.
+///
+/// Runs on a blocking thread pool via `spawn_blocking` to avoid
+/// starving the tokio async runtime.
+///
+/// Returns a TLS-wrapped stream and details of the negotiated session,
+/// including the time taken for the handshake.
+pub async fn tls_handshake(
+    tcp: TcpStream,
+    host: String,
+) -> Result<(Box<dyn IoReadAndWrite>, TlsInfo), Box<dyn Error>> {
+    tls_handshake_with(tcp, host, SESSION_STORE.get().cloned()).await
+}
+
+/// Like [`tls_handshake`], resuming sessions from `store` instead of the
+/// shared session store, or never resuming without one.
+///
+/// Runs over the backend chosen with [`use_tls_backend`]; the native
+/// backend never resumes sessions.
+pub async fn tls_handshake_with(
+    tcp: TcpStream,
+    host: String,
+    store: Option<Arc<ClientSessionMemoryCache>>,
+) -> Result<(Box<dyn IoReadAndWrite>, TlsInfo), Box<dyn Error>> {
+    let backend = tls_backend();
+    let result = tokio::task::spawn_blocking(move || match backend {
+        TlsBackend::Rustls => rustls_handshake(tcp, &host, store),
+        TlsBackend::Native => native_handshake(tcp, &host),
+    })
+    .await?;
+
+    result.map_err(|e| e as Box<dyn Error>)
+}
+
+/// Perform a TLS handshake over `tcp` with rustls.
+fn rustls_handshake(
+    tcp: TcpStream,
+    host: &str,
+    store: Option<Arc<ClientSessionMemoryCache>>,
+) -> Result<(Box<dyn IoReadAndWrite>, TlsInfo), Box<dyn Error + Send + Sync>> {
+    let (roots, trust) = root_store();
+    let cert_verify_duration = Arc::new(Mutex::new(None));
+    let verifier = TimedVerifier {
+        inner: WebPkiServerVerifier::builder(roots).build()?,
+        elapsed: cert_verify_duration.clone(),
+    };
+    let builder = ClientConfig::builder_with_provider(crypto_provider())
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+    let mut config = match certificates().and_then(|c| c.identity.as_ref()) {
+        Some(identity) => builder.with_client_auth_cert(
+            identity.chain.clone(),
+            identity.key.clone_key(),
+        )?,
+        None => builder.with_no_client_auth(),
+    };
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    if let Some(store) = store {
+        config.resumption = Resumption::store(store);
+    }
+    let connector = RustlsConnector::from(config);
+
+    let now = Instant::now();
+
+    let mut stream = connector.connect(host, tcp)?;
+    stream.flush()?;
+    let handshake_duration = now.elapsed();
+
+    let conn = &stream.conn;
+    let info = TlsInfo {
+        version: conn
+            .protocol_version()
+            .map(format_tls_version)
+            .unwrap_or_default(),
+        cipher_suite: conn
+            .negotiated_cipher_suite()
+            .map(|suite| {
+                let suite = suite.suite();
+                suite
+                    .as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("{:?}", suite))
+            })
+            .unwrap_or_default(),
+        alpn: conn
+            .alpn_protocol()
+            .map(|p| String::from_utf8_lossy(p).into_owned()),
+        cert_chain_len: conn
+            .peer_certificates()
+            .map_or(0, |certs| certs.len()),
+        cert_verify_duration: cert_verify_duration
+            .lock()
+            .ok()
+            .and_then(|d| *d),
+        handshake_duration,
+        resumed: conn.handshake_kind() == Some(HandshakeKind::Resumed),
+        backend: TlsBackend::Rustls,
+        trust,
+    };
+
+    Ok((Box::new(stream) as Box<dyn IoReadAndWrite>, info))
+}
+
+/// Perform a TLS handshake over `tcp` with the platform's TLS library,
+/// which checks the server against the OS trust store and any roots from
+/// `--cacert`.
+///
+/// The platform libraries don't report the negotiated version or cipher
+/// suite, or the time spent on the certificate chain, and only show the
+/// server's leaf certificate.
+#[cfg(feature = "native-tls")]
+fn native_handshake(
+    tcp: TcpStream,
+    host: &str,
+) -> Result<(Box<dyn IoReadAndWrite>, TlsInfo), Box<dyn Error + Send + Sync>> {
+    let mut builder = native_tls::TlsConnector::builder();
+    builder.request_alpns(&["http/1.1"]);
+    let mut trust = TrustSource::System;
+    if let Some(certificates) = certificates() {
+        for root in &certificates.roots {
+            builder.add_root_certificate(native_tls::Certificate::from_der(
+                root,
+            )?);
+            trust = TrustSource::Custom;
+        }
+        if let Some(identity) = &certificates.identity {
+            builder.identity(native_tls::Identity::from_pkcs8(
+                &identity.chain_pem,
+                &identity.key_pem,
+            )?);
+        }
+    }
+    let connector = builder.build()?;
+
+    let now = Instant::now();
+
+    // The handshake error holds the stream mid-handshake, which may not
+    // be Sync
+    let mut stream = connector
+        .connect(host, tcp)
+        .map_err(|e| format!("TLS handshake failed: {}", e))?;
+    stream.flush()?;
+    let handshake_duration = now.elapsed();
+
+    let info = TlsInfo {
+        version: String::new(),
+        cipher_suite: String::new(),
+        alpn: stream
+            .negotiated_alpn()
+            .ok()
+            .flatten()
+            .map(|p| String::from_utf8_lossy(&p).into_owned()),
+        cert_chain_len: stream
+            .peer_certificate()
+            .ok()
+            .flatten()
+            .map_or(0, |_| 1),
+        cert_verify_duration: None,
+        handshake_duration,
+        resumed: false,
+        backend: TlsBackend::Native,
+        trust,
+    };
+
+    Ok((Box::new(stream) as Box<dyn IoReadAndWrite>, info))
+}
+
+/// Without the `native-tls` feature there is no native backend to
+/// choose; [`TlsBackend`] refuses to parse it.
+#[cfg(not(feature = "native-tls"))]
+fn native_handshake(
+    _tcp: TcpStream,
+    _host: &str,
+) -> Result<(Box<dyn IoReadAndWrite>, TlsInfo), Box<dyn Error + Send + Sync>> {
+    Err("this build has no native TLS support".into())
+}
+
 /// Measure TCP latency by performing a TCP handshake.
 ///
 /// Runs on a blocking thread pool via `spawn_blocking` to avoid
@@ -117,3 +1103,122 @@ pub async fn measure_tcp_latency(
     })
     .await?
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, TcpListener};
+
+    #[tokio::test]
+    async fn test_tcp_connect_applies_socket_options() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let options = SocketOptions {
+            send_buffer: Some(64 * 1024),
+            recv_buffer: Some(64 * 1024),
+            dscp: Some(Dscp::EF),
+        };
+
+        let (stream, _) =
+            tcp_connect(Ipv4Addr::LOCALHOST.into(), port, options)
+                .await
+                .unwrap();
+        let buffers = socket_buffers(&stream).unwrap();
+
+        // Linux doubles the request; other platforms report it as set
+        assert!(buffers.send_bytes >= 64 * 1024);
+        assert!(buffers.recv_bytes >= 64 * 1024);
+        assert_eq!(
+            SockRef::from(&stream).tos_v4().unwrap(),
+            Dscp::EF.traffic_class()
+        );
+    }
+
+    #[test]
+    fn test_dns_lookup_prefers_ipv4() {
+        let lookup = |addresses: &[&str]| DnsLookup {
+            host: "speed.cloudflare.com".to_string(),
+            addresses: addresses.iter().map(|a| a.parse().unwrap()).collect(),
+            duration: Duration::ZERO,
+            name_server: None,
+        };
+
+        let mixed = lookup(&["2606:4700::6810:1", "104.16.0.1", "104.16.0.2"]);
+        assert_eq!(mixed.preferred(), "104.16.0.1".parse::<IpAddr>().unwrap());
+        let ipv6 = lookup(&["2606:4700::6810:1", "2606:4700::6810:2"]);
+        assert_eq!(
+            ipv6.preferred(),
+            "2606:4700::6810:1".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_dscp_parses_class_names() {
+        let parse = |s: &str| s.parse::<Dscp>().map(Dscp::value);
+        assert_eq!(parse("ef"), Ok(46));
+        assert_eq!(parse("EF"), Ok(46));
+        assert_eq!(parse("af11"), Ok(10));
+        assert_eq!(parse("af41"), Ok(34));
+        assert_eq!(parse("af43"), Ok(38));
+        assert_eq!(parse("cs1"), Ok(8));
+        assert_eq!(parse("cs7"), Ok(56));
+        assert_eq!(parse("be"), Ok(0));
+        assert_eq!(parse("26"), Ok(26));
+
+        for invalid in ["af51", "af14", "cs8", "64", "voice", ""] {
+            assert!(invalid.parse::<Dscp>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_tls_backend_parses() {
+        assert_eq!("rustls".parse(), Ok(TlsBackend::Rustls));
+        assert_eq!("RUSTLS".parse(), Ok(TlsBackend::Rustls));
+        assert_eq!(
+            "native".parse::<TlsBackend>().is_ok(),
+            cfg!(feature = "native-tls")
+        );
+        assert!("openssl".parse::<TlsBackend>().is_err());
+        assert_eq!(TlsBackend::Native.to_string(), "native");
+        assert_eq!(TrustSource::Bundled.to_string(), "bundled");
+    }
+
+    #[test]
+    fn test_tls_cipher_limits_suites() {
+        for cipher in [TlsCipher::Auto, TlsCipher::AesGcm, TlsCipher::Chacha20]
+        {
+            assert_eq!(cipher.to_string().parse(), Ok(cipher));
+        }
+        assert!("rc4".parse::<TlsCipher>().is_err());
+
+        let offered = |cipher: TlsCipher| {
+            rustls::crypto::ring::default_provider()
+                .cipher_suites
+                .iter()
+                .map(|suite| suite.suite())
+                .filter(|&suite| cipher.allows(suite))
+                .count()
+        };
+        let all = offered(TlsCipher::Auto);
+        let aes = offered(TlsCipher::AesGcm);
+        let chacha = offered(TlsCipher::Chacha20);
+        assert!(aes > 0 && chacha > 0);
+        assert_eq!(aes + chacha, all);
+
+        assert!(TlsCipher::AesGcm
+            .allows(rustls::CipherSuite::TLS13_AES_128_GCM_SHA256));
+        assert!(!TlsCipher::Chacha20
+            .allows(rustls::CipherSuite::TLS13_AES_128_GCM_SHA256));
+    }
+
+    #[test]
+    fn test_dscp_display_roundtrips() {
+        for value in 0..=63u8 {
+            let dscp = Dscp(value);
+            assert_eq!(dscp.to_string().parse::<Dscp>(), Ok(dscp));
+        }
+        assert_eq!(Dscp(34).to_string(), "af41");
+        assert_eq!(Dscp(8).to_string(), "cs1");
+        assert_eq!(Dscp(46).traffic_class(), 0xb8);
+    }
+}
@@ -0,0 +1,86 @@
+//! How much of a core a transfer kept busy.
+//!
+//! Each transfer runs start to finish on one blocking thread, TLS record
+//! encryption and decryption included, so the thread's CPU time over the
+//! wall time of the transfer is the share of one core it used. A transfer
+//! using nearly a whole core was limited by the CPU rather than the
+//! network, and its speed understates the link. Only Linux reports
+//! per-thread CPU time; elsewhere nothing is measured.
+
+use std::time::{Duration, Instant};
+
+/// Share of one core above which a transfer counts as CPU bound.
+pub const CPU_BOUND_UTILIZATION: f64 = 0.9;
+
+/// CPU time used by the calling thread so far.
+///
+/// Returns `None` on platforms without per-thread CPU clocks or if the
+/// call fails.
+#[cfg(target_os = "linux")]
+pub fn thread_cpu_time() -> Option<Duration> {
+    let mut now = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // SAFETY: `now` is a valid timespec for the kernel to write into.
+    let ret = unsafe {
+        libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut now)
+    };
+    if ret != 0 {
+        return None;
+    }
+    Some(Duration::new(now.tv_sec as u64, now.tv_nsec as u32))
+}
+
+/// CPU time used by the calling thread so far.
+///
+/// Returns `None` on platforms without per-thread CPU clocks or if the
+/// call fails.
+#[cfg(not(target_os = "linux"))]
+pub fn thread_cpu_time() -> Option<Duration> {
+    None
+}
+
+/// Measures the share of one core the calling thread uses while it runs
+/// a transfer.
+#[derive(Debug, Clone, Copy)]
+pub struct CoreMeter {
+    wall: Instant,
+    cpu: Duration,
+}
+
+impl CoreMeter {
+    /// Start measuring, where the platform allows.
+    pub fn start() -> Option<Self> {
+        Some(Self { cpu: thread_cpu_time()?, wall: Instant::now() })
+    }
+
+    /// Share of one core used since [`CoreMeter::start`], from 0.0 to
+    /// 1.0. Must be called on the thread that started the meter.
+    pub fn utilization(&self) -> Option<f64> {
+        let cpu = thread_cpu_time()?.saturating_sub(self.cpu);
+        let wall = self.wall.elapsed();
+        if wall.is_zero() {
+            return None;
+        }
+        Some((cpu.as_secs_f64() / wall.as_secs_f64()).min(1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_busy_thread_uses_a_core() {
+        let meter = CoreMeter::start().unwrap();
+        let until = Instant::now() + Duration::from_millis(50);
+        let mut spins = 0_u64;
+        while Instant::now() < until {
+            spins = std::hint::black_box(spins + 1);
+        }
+        assert!(meter.utilization().unwrap() > 0.5);
+
+        let meter = CoreMeter::start().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(meter.utilization().unwrap() < 0.5);
+    }
+}
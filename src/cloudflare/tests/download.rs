@@ -1,25 +1,71 @@
-use crate::cloudflare::requests::UA;
-use crate::cloudflare::tests::connection::{
-    measure_tcp_latency, resolve_dns, tcp_connect, tls_handshake_duration,
+use crate::cloudflare::tests::connection::SocketOptions;
+use crate::cloudflare::tests::response::{read_body, read_head};
+use crate::cloudflare::tests::sink::DiscardSink;
+use crate::cloudflare::tests::stall::TransferProgress;
+use crate::cloudflare::tests::transfer::{
+    read_first_byte, run_transfer, Exchange, LoadedLatency, Transfer,
+    TransferOptions,
+};
+use crate::cloudflare::tests::{
+    endpoint_url, format_extra_headers, request_target, user_agent,
+    EdgeHeaders, IoReadAndWrite, Test, TestResults,
 };
-use crate::cloudflare::tests::{extract_http_status, IoReadAndWrite, Test, TestResults, BASE_URL};
 use crate::measurements::parse_server_timing;
-use http::header::{HeaderMap, HeaderName, HeaderValue};
-use log::{debug, info};
+use http::header::{HeaderMap, HeaderName};
+use log::info;
 use std::borrow::Cow;
 use std::error::Error;
-use std::io::{Read, Write};
-use std::net::IpAddr;
-use std::str::FromStr;
-use std::sync::Arc;
+use std::io::Write;
+use std::net::TcpStream;
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::time::Instant;
 use url::Url;
 
-pub(crate) struct Download {}
+#[derive(Default)]
+pub(crate) struct Download {
+    options: TransferOptions,
+    /// Hash each response body, for checking what the server sent
+    hash_body: bool,
+}
 
 impl Download {
+    /// Attach additional headers to every request made by this test.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.options.headers = headers;
+        self
+    }
+
+    /// Apply `options` to the sockets this test opens.
+    pub fn with_socket_options(mut self, options: SocketOptions) -> Self {
+        self.options.socket_options = options;
+        self
+    }
+
+    /// Record the transfer's progress in `progress`.
+    pub fn with_progress(mut self, progress: TransferProgress) -> Self {
+        self.options.progress = progress;
+        self
+    }
+
+    /// Abort the transfer once no data has arrived for `timeout`.
+    pub fn with_stall_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.options.stall_timeout = timeout;
+        self
+    }
+
+    /// Tag every request with the run's measurement ID.
+    pub fn with_meas_id(mut self, meas_id: Option<String>) -> Self {
+        self.options.meas_id = meas_id;
+        self
+    }
+
+    /// Hash each response body with SHA-256 as it's discarded.
+    pub fn with_body_hash(mut self, hash: bool) -> Self {
+        self.hash_body = hash;
+        self
+    }
+
     /// Run the download test with concurrent loaded latency measurements.
     ///
     /// This method performs a download test while simultaneously measuring
@@ -42,37 +88,12 @@ impl Download {
         min_request_duration_ms: u64,
     ) -> Result<TestResults, Box<dyn Error>> {
         info!("Beginning Download Test with loaded latency: {}", bytes);
-        let mut url =
-            Url::parse(format!("{}/{}", BASE_URL, self.endpoint()).as_str())?;
-        url.set_query(Some(format!("bytes={}", bytes).as_str()));
-
-        let (ip_address, _dns_duration) = resolve_dns(&url).await?;
-        let port = url.port_or_known_default().unwrap();
-        let (stream, tcp_connect_duration) = tcp_connect(ip_address, port).await?;
-        let host = url.host_str().unwrap_or("").to_string();
-        let (stream, _tls_handshake_duration) =
-            tls_handshake_duration(stream, host).await?;
-
-        // Execute HTTP GET with concurrent latency measurements
-        let (_connect_duration, ttfb_duration, server_time, end_duration) =
-            execute_http_get_with_latency(
-                stream,
-                &url,
-                ip_address,
-                port,
-                latency_tx,
-                throttle_ms,
-                min_request_duration_ms,
-            )
-            .await?;
-
-        Ok(TestResults::new(
-            tcp_connect_duration,
-            ttfb_duration,
-            server_time,
-            end_duration,
-            bytes,
-        ))
+        let loaded = LoadedLatency::new(
+            latency_tx,
+            throttle_ms,
+            min_request_duration_ms,
+        );
+        run_transfer(self, bytes, Some(loaded)).await
     }
 }
 
@@ -83,73 +104,47 @@ impl Test for Download {
 
     async fn run(&self, bytes: u64) -> Result<TestResults, Box<dyn Error>> {
         info!("Beginning Download Test: {}", bytes);
-        let mut url =
-            Url::parse(format!("{}/{}", BASE_URL, self.endpoint()).as_str())?;
-        // Add query param or body based on test method
-        url.set_query(Some(format!("bytes={}", bytes).as_str()));
-
-        let (_ip_address, _dns_duration) = resolve_dns(&url).await?;
-        let port = url.port_or_known_default().unwrap();
-        let (stream, tcp_connect_duration) = tcp_connect(_ip_address, port).await?;
-        let host = url.host_str().unwrap_or("").to_string();
-        let (stream, _tls_handshake_duration) =
-            tls_handshake_duration(stream, host).await?;
-        let (_connect_duration, ttfb_duration, server_time, end_duration) =
-            execute_http_get(stream, url).await?;
-
-        Ok(TestResults::new(
-            tcp_connect_duration,
-            ttfb_duration,
-            server_time,
-            end_duration,
-            bytes,
-        ))
+        run_transfer(self, bytes, None).await
     }
 }
 
-async fn execute_http_get(
-    mut tcp: Box<dyn IoReadAndWrite>,
-    url: Url,
-) -> Result<(Duration, Duration, Duration, Duration), Box<dyn Error>> {
-    let header = build_http_header(&url);
-    debug!("\r\n{}", header);
-
-    tokio::task::spawn_blocking(move || {
-        let now = Instant::now();
-
-        tcp.write_all(header.as_bytes())?;
-        tcp.flush()?;
-
-        let connect_duration = now.elapsed();
+impl Transfer for Download {
+    /// The number of bytes requested, and the sink the body goes to
+    type Body = (u64, DiscardSink);
 
-        let mut one_byte_buffer = [0_u8];
-        let now = Instant::now();
-        tcp.read_exact(&mut one_byte_buffer)?;
-        let ttfb_duration = now.elapsed();
+    fn options(&self) -> &TransferOptions {
+        &self.options
+    }
 
-        let mut headers: Vec<u8> = Vec::new();
-        headers.push(one_byte_buffer[0]);
+    fn url(&self, bytes: u64) -> Result<Url, url::ParseError> {
+        endpoint_url(
+            &self.endpoint(),
+            self.options.meas_id.as_deref(),
+            Some(bytes),
+        )
+    }
 
-        while tcp.read(&mut one_byte_buffer)? > 0 {
-            headers.push(one_byte_buffer[0]);
-            if headers.len() >= 4
-                && headers[headers.len() - 4..] == [b'\r', b'\n', b'\r', b'\n']
-            {
-                break;
-            }
-        }
+    fn request_head(&self, url: &Url, _bytes: u64) -> String {
+        build_http_header(url, &self.options.headers)
+    }
 
-        let headers_str = String::from_utf8(headers)
-            .map_err(|e| format!("Invalid UTF-8 in HTTP headers: {}", e))?;
+    fn body(&self, bytes: u64) -> Self::Body {
+        (bytes, DiscardSink::new().with_hash(self.hash_body))
+    }
 
-        // Check HTTP status code before processing body
-        let status = extract_http_status(&headers_str)
-            .ok_or("Malformed HTTP response from speed test server")?;
-        if status != 200 {
-            return Err(format!("HTTP {status} from speed test server").into());
-        }
+    fn exchange(
+        tcp: &mut Box<dyn IoReadAndWrite>,
+        head: &str,
+        (requested, mut sink): Self::Body,
+        _socket: Option<&TcpStream>,
+        progress: &TransferProgress,
+    ) -> Result<Exchange, Box<dyn Error + Send + Sync>> {
+        tcp.write_all(head.as_bytes())?;
+        tcp.flush()?;
 
-        let headers = extract_http_headers(&headers_str);
+        let ttfb_start = Instant::now();
+        let (first_byte, ttfb) = read_first_byte(tcp, progress)?;
+        let headers = read_head(tcp, first_byte)?;
 
         // Extract server processing time from server-timing header
         let server_time = headers
@@ -157,181 +152,38 @@ async fn execute_http_get(
             .and_then(|h| h.to_str().ok())
             .and_then(parse_server_timing)
             .unwrap_or(Duration::ZERO);
+        let edge = EdgeHeaders::from_headers(&headers);
 
-        let mut buff = Vec::new();
-
-        tcp.read_to_end(&mut buff)?;
-
-        let end_duration = now.elapsed();
+        // Read body - the long blocking operation
+        read_body(tcp, &headers, Some(requested), &mut sink, progress)?;
 
-        Ok::<_, Box<dyn Error + Send + Sync>>((connect_duration, ttfb_duration, server_time, end_duration))
-    })
-    .await?
-    .map_err(|e| e as Box<dyn Error>)
+        Ok(Exchange {
+            ttfb,
+            server_time,
+            end: ttfb_start.elapsed(),
+            edge,
+            body_sha256: sink.digest(),
+        })
+    }
 }
 
-fn build_http_header(url: &Url) -> String {
+/// Build the request head for a download of `url`.
+pub(crate) fn build_http_header(
+    url: &Url,
+    extra_headers: &HeaderMap,
+) -> String {
     format!(
-        "GET {}?{} HTTP/1.1\r\n\
+        "GET {} HTTP/1.1\r\n\
         Host: {}\r\n\
         User-Agent: {}\r\n\
         Accept: */*\r\n\
         Accept-Encoding: identity\r\n\
+        {}\
         Connection: close\r\n\
         \r\n",
-        url.path(),
-        url.query().unwrap(),
+        request_target(url),
         url.host_str().unwrap(),
-        UA
+        user_agent(extra_headers),
+        format_extra_headers(extra_headers)
     )
 }
-
-fn extract_http_headers(raw_headers: &str) -> HeaderMap {
-    let mut headers = HeaderMap::new();
-
-    for line in raw_headers.lines() {
-        let line = line.trim();
-
-        if line.is_empty() || !line.contains(':') {
-            continue;
-        }
-
-        let parts: Vec<&str> = line.splitn(2, ':').collect();
-        if parts.len() != 2 {
-            continue;
-        }
-
-        // Skip malformed header names/values instead of panicking
-        let name = match HeaderName::from_str(parts[0].trim()) {
-            Ok(n) => n,
-            Err(_) => continue,
-        };
-        let value = match HeaderValue::from_str(parts[1].trim()) {
-            Ok(v) => v,
-            Err(_) => continue,
-        };
-
-        headers.append(name, value);
-    }
-
-    headers
-}
-
-/// Execute HTTP GET with concurrent latency measurements.
-///
-/// This function performs the HTTP GET request while spawning a background
-/// task that measures latency at regular intervals. Latency measurements
-/// are only included if the request duration exceeds the minimum threshold.
-async fn execute_http_get_with_latency(
-    mut tcp: Box<dyn IoReadAndWrite>,
-    url: &Url,
-    ip_address: IpAddr,
-    port: u16,
-    latency_tx: mpsc::Sender<f64>,
-    throttle_ms: u64,
-    min_request_duration_ms: u64,
-) -> Result<(Duration, Duration, Duration, Duration), Box<dyn Error>> {
-    let header = build_http_header(url);
-    debug!("\r\n{}", header);
-
-    let throttle_duration = Duration::from_millis(throttle_ms);
-    let min_duration = Duration::from_millis(min_request_duration_ms);
-    let request_start = Instant::now();
-
-    // Use Arc to share the stop flag between tasks
-    let stop_flag = Arc::new(std::sync::atomic::AtomicBool::new(false));
-    let stop_flag_clone = stop_flag.clone();
-
-    // Spawn latency measurement task
-    let latency_handle = tokio::spawn(async move {
-        let mut last_measurement = Instant::now();
-
-        loop {
-            // Check if we should stop (Acquire pairs with Release in main thread)
-            if stop_flag_clone.load(std::sync::atomic::Ordering::Acquire) {
-                break;
-            }
-
-            // Wait for throttle interval
-            let elapsed_since_last = last_measurement.elapsed();
-            if elapsed_since_last < throttle_duration {
-                tokio::time::sleep(throttle_duration - elapsed_since_last)
-                    .await;
-            }
-
-            // Check again after sleep (Acquire pairs with Release in main thread)
-            if stop_flag_clone.load(std::sync::atomic::Ordering::Acquire) {
-                break;
-            }
-
-            // Only measure if request has been running long enough
-            let request_duration = request_start.elapsed();
-            if request_duration >= min_duration {
-                // Measure latency using TCP handshake time
-                if let Ok(latency_ms) = measure_tcp_latency(ip_address, port).await {
-                    let _ = latency_tx.send(latency_ms).await;
-                }
-            }
-
-            last_measurement = Instant::now();
-        }
-    });
-
-    let result = tokio::task::spawn_blocking(move || {
-        let request_start = Instant::now();
-
-        tcp.write_all(header.as_bytes())?;
-        tcp.flush()?;
-        let connect_duration = request_start.elapsed();
-
-        // Read TTFB
-        let mut one_byte_buffer = [0_u8];
-        let ttfb_start = Instant::now();
-        tcp.read_exact(&mut one_byte_buffer)?;
-        let ttfb_duration = ttfb_start.elapsed();
-
-        // Read headers
-        let mut headers: Vec<u8> = Vec::new();
-        headers.push(one_byte_buffer[0]);
-        while tcp.read(&mut one_byte_buffer)? > 0 {
-            headers.push(one_byte_buffer[0]);
-            if headers.len() >= 4
-                && headers[headers.len() - 4..] == [b'\r', b'\n', b'\r', b'\n']
-            {
-                break;
-            }
-        }
-
-        let headers_str = String::from_utf8(headers)
-            .map_err(|e| format!("Invalid UTF-8 in HTTP headers: {}", e))?;
-
-        // Check HTTP status code before processing body
-        let status = extract_http_status(&headers_str)
-            .ok_or("Malformed HTTP response from speed test server")?;
-        if status != 200 {
-            return Err(format!("HTTP {status} from speed test server").into());
-        }
-
-        let headers = extract_http_headers(&headers_str);
-        let server_time = headers
-            .get(HeaderName::from_static("server-timing"))
-            .and_then(|h| h.to_str().ok())
-            .and_then(parse_server_timing)
-            .unwrap_or(Duration::ZERO);
-
-        // Read body - the long blocking operation
-        let mut buff = Vec::new();
-        tcp.read_to_end(&mut buff)?;
-        let end_duration = ttfb_start.elapsed();
-
-        Ok::<_, Box<dyn Error + Send + Sync>>((connect_duration, ttfb_duration, server_time, end_duration))
-    })
-    .await?
-    .map_err(|e| e as Box<dyn Error>)?;
-
-    // Signal latency task to stop
-    stop_flag.store(true, std::sync::atomic::Ordering::Release);
-    let _ = tokio::time::timeout(Duration::from_millis(100), latency_handle).await;
-
-    Ok(result)
-}
@@ -0,0 +1,245 @@
+//! Full-duplex saturation phase.
+//!
+//! Downloads and uploads run back-to-back at the same time for a fixed
+//! window, measuring what each direction achieves, and the latency, while
+//! the other is busy. Links that share capacity between directions (Wi-Fi,
+//! DOCSIS, powerline) or whose upstream queue delays the downstream's
+//! ACKs deliver well below the sum of their one-way speeds.
+
+use crate::cloudflare::tests::download::Download;
+use crate::cloudflare::tests::engine::TestConfig;
+use crate::cloudflare::tests::upload::Upload;
+use crate::measurements::{
+    aggregate_bandwidth, calculate_speed_mbps, jitter_f64,
+    BandwidthMeasurement,
+};
+use crate::stats::median_f64;
+use log::{debug, info, warn};
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// How long both directions are saturated.
+pub const WINDOW: Duration = Duration::from_secs(8);
+
+/// Transfers are sized to take about this long at the one-way speed, so
+/// each spans several loaded latency probes.
+const REQUEST_SECS: f64 = 1.0;
+
+/// Smallest transfer, so slow links still make measurable requests.
+const MIN_REQUEST_BYTES: u64 = 100_000;
+
+/// Largest download transfer, matching the largest download block.
+const MAX_DOWNLOAD_BYTES: u64 = 100_000_000;
+
+/// Largest upload transfer, matching the largest upload block.
+const MAX_UPLOAD_BYTES: u64 = 50_000_000;
+
+/// Pause before retrying a failed transfer.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Throughput and latency with both directions saturated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DuplexResult {
+    /// Download speed achieved during duplex load
+    pub download_mbps: f64,
+    /// Upload speed achieved during duplex load
+    pub upload_mbps: f64,
+    /// Median latency during duplex load
+    pub latency_ms: Option<f64>,
+    /// Jitter during duplex load
+    pub jitter_ms: Option<f64>,
+    /// Duplex speeds summed, over the one-way speeds summed
+    pub efficiency: f64,
+}
+
+/// Saturate both directions for [`WINDOW`].
+///
+/// `download_mbps` and `upload_mbps` are the one-way speeds already
+/// measured; they size the transfers and are what efficiency is relative
+/// to. Fails if either is zero or a direction completes no transfer.
+/// Requests are tagged with `meas_id`, the run's measurement ID.
+pub async fn run(
+    config: &TestConfig,
+    meas_id: Option<&str>,
+    download_mbps: f64,
+    upload_mbps: f64,
+) -> Result<DuplexResult, Box<dyn Error>> {
+    if download_mbps <= 0.0 || upload_mbps <= 0.0 {
+        return Err("one-way download and upload speeds are needed".into());
+    }
+
+    let (latency_tx, mut latency_rx) = mpsc::channel::<f64>(100);
+    let deadline = Instant::now() + WINDOW;
+    let collect_latencies = async {
+        let mut latencies = Vec::new();
+        // Ends once both directions finish and drop their senders
+        while let Some(latency_ms) = latency_rx.recv().await {
+            latencies.push(latency_ms);
+        }
+        latencies
+    };
+
+    let (downloads, uploads, latencies) = tokio::join!(
+        saturate(
+            config,
+            meas_id,
+            true,
+            request_bytes(download_mbps, MAX_DOWNLOAD_BYTES),
+            deadline,
+            latency_tx.clone(),
+        ),
+        saturate(
+            config,
+            meas_id,
+            false,
+            request_bytes(upload_mbps, MAX_UPLOAD_BYTES),
+            deadline,
+            latency_tx,
+        ),
+        collect_latencies,
+    );
+
+    let speed = |measurements: &[BandwidthMeasurement], direction: &str| {
+        aggregate_bandwidth(
+            measurements,
+            config.bandwidth_percentile,
+            config.bandwidth_min_duration_ms,
+        )
+        .map(calculate_speed_mbps)
+        .ok_or_else(|| format!("no duplex {} transfer completed", direction))
+    };
+    let duplex_download_mbps = speed(&downloads, "download")?;
+    let duplex_upload_mbps = speed(&uploads, "upload")?;
+
+    let result = DuplexResult {
+        download_mbps: duplex_download_mbps,
+        upload_mbps: duplex_upload_mbps,
+        latency_ms: median_f64(&mut latencies.clone()),
+        jitter_ms: jitter_f64(&latencies),
+        efficiency: efficiency(
+            (duplex_download_mbps, duplex_upload_mbps),
+            (download_mbps, upload_mbps),
+        ),
+    };
+    info!(
+        "Duplex: {:.2} Mbps down, {:.2} Mbps up ({:.0}% efficiency), \
+         latency {:?} ms",
+        result.download_mbps,
+        result.upload_mbps,
+        result.efficiency * 100.0,
+        result.latency_ms
+    );
+
+    Ok(result)
+}
+
+/// Transfer back-to-back in one direction until `deadline`.
+async fn saturate(
+    config: &TestConfig,
+    meas_id: Option<&str>,
+    is_download: bool,
+    bytes: u64,
+    deadline: Instant,
+    latency_tx: mpsc::Sender<f64>,
+) -> Vec<BandwidthMeasurement> {
+    let test_type = if is_download { "download" } else { "upload" };
+    let throttle_ms = config.loaded_latency_throttle_ms;
+    let min_duration_ms = config.loaded_request_min_duration_ms as u64;
+    let mut measurements: Vec<BandwidthMeasurement> = Vec::new();
+
+    debug!("Duplex {} of {} bytes per request", test_type, bytes);
+    while Instant::now() < deadline {
+        let result = if is_download {
+            Download::default()
+                .with_headers(config.headers.clone())
+                .with_socket_options(config.socket_options)
+                .with_meas_id(meas_id.map(str::to_string))
+                .run_with_loaded_latency(
+                    bytes,
+                    latency_tx.clone(),
+                    throttle_ms,
+                    min_duration_ms,
+                )
+                .await
+                .map_err(|e| e.to_string())
+        } else {
+            let estimate_bps = aggregate_bandwidth(
+                &measurements,
+                config.bandwidth_percentile,
+                config.bandwidth_min_duration_ms,
+            );
+            Upload::with_pattern(bytes, config.upload_payload)
+                .with_headers(config.headers.clone())
+                .with_chunk_size(
+                    config.upload_chunk_size.resolve(estimate_bps),
+                )
+                .with_socket_options(config.socket_options)
+                .with_meas_id(meas_id.map(str::to_string))
+                .run_with_loaded_latency(
+                    latency_tx.clone(),
+                    throttle_ms,
+                    min_duration_ms,
+                )
+                .await
+                .map_err(|e| e.to_string())
+        };
+
+        match result {
+            Ok(result) => measurements.push(result.to_bandwidth_measurement()),
+            Err(e) => {
+                warn!("Duplex {} request failed: {}", test_type, e);
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+
+    measurements
+}
+
+/// Bytes per transfer for a link measured at `speed_mbps` one way.
+fn request_bytes(speed_mbps: f64, max_bytes: u64) -> u64 {
+    let bytes = speed_mbps * 1_000_000.0 / 8.0 * REQUEST_SECS;
+    (bytes as u64).clamp(MIN_REQUEST_BYTES, max_bytes)
+}
+
+/// Share of the one-way speeds kept when both directions are busy.
+fn efficiency(duplex: (f64, f64), one_way: (f64, f64)) -> f64 {
+    let one_way_total = one_way.0 + one_way.1;
+    if one_way_total <= 0.0 {
+        return 0.0;
+    }
+    (duplex.0 + duplex.1) / one_way_total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_bytes() {
+        // 100 Mbps for a second is 12.5 MB
+        assert_eq!(request_bytes(100.0, MAX_DOWNLOAD_BYTES), 12_500_000);
+        assert_eq!(request_bytes(0.1, MAX_DOWNLOAD_BYTES), MIN_REQUEST_BYTES);
+        assert_eq!(
+            request_bytes(10_000.0, MAX_UPLOAD_BYTES),
+            MAX_UPLOAD_BYTES
+        );
+    }
+
+    #[test]
+    fn test_efficiency() {
+        // A full-duplex link keeps both directions
+        assert_eq!(efficiency((900.0, 100.0), (900.0, 100.0)), 1.0);
+        // A half-duplex one shares its capacity between them
+        assert_eq!(efficiency((450.0, 50.0), (900.0, 100.0)), 0.5);
+        assert_eq!(efficiency((0.0, 0.0), (0.0, 0.0)), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_run_needs_one_way_speeds() {
+        let config = TestConfig::default();
+        assert!(run(&config, None, 0.0, 10.0).await.is_err());
+        assert!(run(&config, None, 10.0, 0.0).await.is_err());
+    }
+}
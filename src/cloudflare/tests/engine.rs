@@ -1,25 +1,66 @@
+use crate::clock::ClockWatch;
+use crate::cloudflare::proxy::env_proxy;
+use crate::cloudflare::tests::connection::{
+    enable_session_resumption, pin_address, pin_dns, resolve_dns, DnsLookup,
+    SocketBuffers, SocketOptions, TlsInfo,
+};
 use crate::cloudflare::tests::download::Download;
-use crate::cloudflare::tests::upload::Upload;
-use crate::cloudflare::tests::{Test, TestResults};
+use crate::cloudflare::tests::duplex::{self, DuplexResult};
+use crate::cloudflare::tests::handshake::{self, HandshakeComparison};
+use crate::cloudflare::tests::happy_eyeballs::{self, Advisory, DualStack};
+use crate::cloudflare::tests::icmp::IcmpPinger;
+use crate::cloudflare::tests::load::LoadGenerator;
+use crate::cloudflare::tests::mtu::{self, PathMtu};
+use crate::cloudflare::tests::response::BodyMismatch;
+use crate::cloudflare::tests::stall::{
+    TransferProgress, DEFAULT_STALL_TIMEOUT,
+};
+use crate::cloudflare::tests::tcp_info::TcpStats;
+use crate::cloudflare::tests::upload::{
+    PayloadPattern, Upload, UploadChunkSize,
+};
+use crate::cloudflare::tests::{
+    new_meas_id, EdgeHeaders, Test, TestResults, BASE_URL,
+};
+use crate::errors::ErrorKind;
 use crate::measurements::{
-    aggregate_bandwidth, calculate_speed_mbps, jitter_f64, latency_f64,
-    BandwidthMeasurement, LatencyDirection, LoadedLatencyCollector,
+    aggregate_bandwidth, calculate_speed_mbps, coefficient_of_variation,
+    jitter_f64, latency_f64, mean_and_max_bandwidth, BandwidthMeasurement,
+    LatencyDirection, LoadedLatencyCollector,
+};
+use crate::netwatch::NetworkWatch;
+use crate::profiling::{PhaseProfiler, RunProfile};
+use crate::retry::{
+    retry_async, retry_async_recording, FailedAttempt, RetryConfig,
+    RetryResult,
 };
-use crate::retry::{retry_async, RetryConfig, RetryResult};
 use crate::stats::{median_f64, percentile_f64};
 use crate::tui::{
     BandwidthDirection, ProgressCallback, ProgressEvent, TestPhase,
 };
+use chrono::TimeDelta;
+use http::HeaderMap;
 use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
 use std::error::Error;
-use std::sync::Arc;
+use std::fmt;
+use std::future::Future;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::{Instant, MissedTickBehavior};
+use url::Url;
 
 /// A data block configuration for bandwidth tests.
 ///
 /// Defines the size and number of measurements for a specific
-/// file size in the download or upload test sequence.
-#[derive(Debug, Clone)]
+/// file size in the download or upload test sequence. Written as
+/// `SIZE:COUNT`, e.g. `25MB:4`, with decimal `kB`, `MB` and `GB` units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct DataBlock {
     /// Size of the data block in bytes
     pub bytes: u64,
@@ -34,6 +75,104 @@ impl DataBlock {
     }
 }
 
+/// Size units accepted in size blocks, largest first.
+const SIZE_UNITS: [(&str, u64); 4] =
+    [("GB", 1_000_000_000), ("MB", 1_000_000), ("kB", 1_000), ("B", 1)];
+
+impl fmt::Display for DataBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The largest unit the size is a whole number of, so the block
+        // parses back to the same size
+        let (unit, scale) = SIZE_UNITS
+            .into_iter()
+            .find(|(_, scale)| self.bytes % scale == 0 && self.bytes > 0)
+            .unwrap_or(("B", 1));
+        write!(f, "{}{}:{}", self.bytes / scale, unit, self.count)
+    }
+}
+
+impl FromStr for DataBlock {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (size, count) = s.trim().split_once(':').ok_or_else(|| {
+            format!("expected SIZE:COUNT, e.g. 1MB:8, got '{}'", s)
+        })?;
+
+        let digits =
+            size.find(|c: char| !c.is_ascii_digit()).unwrap_or(size.len());
+        let (number, unit) = size.split_at(digits);
+        let scale = match unit.trim() {
+            "" => 1,
+            unit => SIZE_UNITS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(unit))
+                .map(|(_, scale)| *scale)
+                .ok_or_else(|| {
+                    format!(
+                        "unknown size unit '{}' in '{}' (expected B, kB, MB \
+                         or GB)",
+                        unit, s
+                    )
+                })?,
+        };
+        let bytes = number
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(scale))
+            .filter(|&bytes| bytes > 0)
+            .ok_or_else(|| format!("invalid size '{}' in '{}'", size, s))?;
+        let count = count
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|&count| count > 0)
+            .ok_or_else(|| {
+                format!(
+                    "invalid count '{}' in '{}' (expected 1 or more)",
+                    count, s
+                )
+            })?;
+
+        Ok(Self { bytes, count })
+    }
+}
+
+/// The size blocks one direction runs, smallest first, written as a
+/// comma-separated list such as `100kB:10,1MB:8,25MB:4`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeBlocks(pub Vec<DataBlock>);
+
+impl fmt::Display for SizeBlocks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let blocks: Vec<String> =
+            self.0.iter().map(ToString::to_string).collect();
+        write!(f, "{}", blocks.join(","))
+    }
+}
+
+impl FromStr for SizeBlocks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let blocks = s
+            .split(',')
+            .map(DataBlock::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        // Larger sizes are skipped once one size takes long enough, which
+        // only works smallest first
+        if let Some(pair) =
+            blocks.windows(2).find(|pair| pair[0].bytes >= pair[1].bytes)
+        {
+            return Err(format!(
+                "sizes must increase, but {} comes before {}",
+                pair[0], pair[1]
+            ));
+        }
+        Ok(Self(blocks))
+    }
+}
+
 /// Configuration for the test engine.
 ///
 /// This struct contains all configurable parameters for the speed test,
@@ -52,6 +191,22 @@ pub struct TestConfig {
     /// Default: 20
     pub latency_packets: usize,
 
+    /// Probe used for idle latency. ICMP falls back to HTTP when no ICMP
+    /// socket can be opened.
+    /// Default: HTTP
+    pub latency_probe: LatencyProbe,
+
+    /// How idle latency probes are scheduled.
+    /// Default: sequential
+    pub latency_schedule: LatencySchedule,
+
+    /// Spacing between idle latency probes in ms. For a sequential
+    /// schedule this is the gap after each probe completes; for a fixed
+    /// tick it is the tick period; for parallel probes it is the least
+    /// time between sending two probes.
+    /// Default: 0ms (back-to-back)
+    pub latency_interval_ms: u64,
+
     /// Minimum interval between loaded latency measurements in ms.
     /// Default: 400ms
     pub loaded_latency_throttle_ms: u64,
@@ -61,6 +216,17 @@ pub struct TestConfig {
     /// Default: 1000ms
     pub bandwidth_finish_duration_ms: f64,
 
+    /// Coefficient of variation below which a size block is considered
+    /// converged and its remaining iterations are skipped.
+    /// `None` disables convergence-based termination.
+    /// Default: 0.05 (5%)
+    pub convergence_cv_threshold: Option<f64>,
+
+    /// Number of most recent measurements the coefficient of variation
+    /// is calculated over.
+    /// Default: 4
+    pub convergence_window: usize,
+
     /// Minimum duration for a measurement to be included in
     /// bandwidth calculations (in ms).
     /// Default: 10ms
@@ -78,6 +244,90 @@ pub struct TestConfig {
     /// Retry configuration for failed measurements.
     /// Default: 3 retries with exponential backoff
     pub retry_config: RetryConfig,
+
+    /// Additional headers sent with every test request.
+    /// Default: none
+    pub headers: HeaderMap,
+
+    /// Byte pattern used to fill upload payloads.
+    /// Default: random (incompressible)
+    pub upload_payload: PayloadPattern,
+
+    /// Size of the writes upload bodies are sent in. Auto mode sizes them
+    /// from the upload bandwidth measured so far, since small writes
+    /// throttle fast uplinks with syscall overhead.
+    /// Default: auto
+    pub upload_chunk_size: UploadChunkSize,
+
+    /// Send and receive buffer sizes applied to measurement sockets.
+    /// Default: OS defaults
+    pub socket_options: SocketOptions,
+
+    /// Complete a TLS session during setup and resume it for later
+    /// connections, taking full handshakes out of the measurements.
+    /// Default: false
+    pub prewarm_tls: bool,
+
+    /// Number of background streams saturating the link during each
+    /// bandwidth block, independent of the measured requests.
+    /// Default: 0 (no synthetic load)
+    pub load_streams: usize,
+
+    /// Politeness settings that leave room on the link for other users.
+    /// Default: none (measurements run back-to-back)
+    pub pacing: Option<Pacing>,
+
+    /// Randomize the gap before each bandwidth measurement and its size,
+    /// so the test doesn't look like a fixed sequence of fixed-size
+    /// transfers to CPE or ISP traffic shaping.
+    /// Default: none (fixed sizes, no added gaps)
+    pub pacing_jitter: Option<PacingJitter>,
+
+    /// Probe the path MTU toward the test server with don't-fragment
+    /// ICMP echo requests before measuring.
+    /// Default: false
+    pub path_mtu: bool,
+
+    /// Time a cold connection setup (DNS, TCP, full TLS handshake)
+    /// against warm ones with cached DNS and resumed TLS sessions before
+    /// measuring.
+    /// Default: false
+    pub handshake: bool,
+
+    /// Order the download and upload size blocks run in.
+    /// Default: interleaved
+    pub order: TestOrder,
+
+    /// Saturate both directions at once after the one-way tests to
+    /// measure full-duplex throughput and latency.
+    /// Default: false
+    pub duplex: bool,
+
+    /// Keep the error kind and attempt count of every retried
+    /// measurement so intermittent failures show up in the results, and
+    /// the Cloudflare response headers of every measurement.
+    /// Default: false
+    pub detailed: bool,
+
+    /// How long a transfer may go without moving data before a stall is
+    /// reported. `None` disables stall detection.
+    /// Default: 10s
+    pub stall_timeout: Option<Duration>,
+
+    /// Abort stalled transfers so the measurement is retried, instead of
+    /// waiting for them to resume.
+    /// Default: false
+    pub abort_stalled: bool,
+
+    /// Phases to run. Idle latency is always measured; skipped bandwidth
+    /// directions come back empty.
+    /// Default: all
+    pub phases: PhaseSelection,
+
+    /// Run idle latency or a size block again when the network changed
+    /// while it ran, so its measurements come from one network.
+    /// Default: false
+    pub restart_on_network_change: bool,
 }
 
 impl Default for TestConfig {
@@ -110,16 +360,566 @@ impl Default for TestConfig {
                 DataBlock::new(50_000_000, 3), // 50MB
             ],
             latency_packets: 20,
+            latency_probe: LatencyProbe::Http,
+            latency_schedule: LatencySchedule::Sequential,
+            latency_interval_ms: 0,
             loaded_latency_throttle_ms: 400,
             bandwidth_finish_duration_ms: 1000.0,
+            convergence_cv_threshold: Some(0.05),
+            convergence_window: 4,
             bandwidth_min_duration_ms: 10.0,
             loaded_request_min_duration_ms: 250.0,
             bandwidth_percentile: 0.9,
             retry_config: RetryConfig::default(),
+            headers: HeaderMap::new(),
+            upload_payload: PayloadPattern::default(),
+            upload_chunk_size: UploadChunkSize::default(),
+            socket_options: SocketOptions::default(),
+            prewarm_tls: false,
+            load_streams: 0,
+            pacing: None,
+            pacing_jitter: None,
+            path_mtu: false,
+            handshake: false,
+            order: TestOrder::default(),
+            duplex: false,
+            detailed: false,
+            stall_timeout: Some(DEFAULT_STALL_TIMEOUT),
+            abort_stalled: false,
+            phases: PhaseSelection::default(),
+            restart_on_network_change: false,
+        }
+    }
+}
+
+impl TestConfig {
+    /// The order the bandwidth tests actually run in: running both
+    /// directions at once can't be paced, so paced tests interleave them
+    /// instead.
+    pub fn bandwidth_order(&self) -> TestOrder {
+        match self.order {
+            TestOrder::Concurrent if self.pacing.is_some() => {
+                TestOrder::Interleaved
+            }
+            order => order,
+        }
+    }
+}
+
+/// Pauses and concurrency limits for testing on shared networks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pacing {
+    /// Shortest pause between bandwidth measurements
+    pub min_pause: Duration,
+    /// Pause as a multiple of the preceding measurement's duration; 1.0
+    /// keeps the link busy at most half the time
+    pub pause_ratio: f64,
+    /// Most background load streams allowed
+    pub max_load_streams: usize,
+}
+
+impl Pacing {
+    /// Settings for `--nice`: the link is idle at least as long as it is
+    /// busy, and no synthetic load is added.
+    pub const NICE: Pacing = Pacing {
+        min_pause: Duration::from_millis(500),
+        pause_ratio: 1.0,
+        max_load_streams: 0,
+    };
+
+    /// Pause to take after a measurement lasting `duration_ms`.
+    pub fn pause_after(&self, duration_ms: f64) -> Duration {
+        let scaled = Duration::from_secs_f64(
+            (duration_ms.max(0.0) * self.pause_ratio.max(0.0)) / 1000.0,
+        );
+        scaled.max(self.min_pause)
+    }
+}
+
+/// Random variation in request timing and size, for `--pacing-jitter`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PacingJitter {
+    /// Longest random gap added before a bandwidth measurement
+    pub max_gap: Duration,
+    /// Largest fraction a request's size is varied by, either way
+    pub size_fraction: f64,
+}
+
+impl PacingJitter {
+    /// Gaps of up to 250 ms and sizes within ±10%: enough to break up a
+    /// regular pattern without moving a measurement to another size
+    /// block.
+    pub const DEFAULT: PacingJitter = PacingJitter {
+        max_gap: Duration::from_millis(250),
+        size_fraction: 0.1,
+    };
+
+    /// Gap before a measurement, given a uniform random `unit` in [0, 1).
+    pub fn gap(&self, unit: f64) -> Duration {
+        self.max_gap.mul_f64(unit.clamp(0.0, 1.0))
+    }
+
+    /// Size of a request for `bytes`, given a uniform random `unit` in
+    /// [0, 1).
+    pub fn size(&self, bytes: u64, unit: f64) -> u64 {
+        let offset = (unit.clamp(0.0, 1.0) * 2.0 - 1.0) * self.size_fraction;
+        ((bytes as f64 * (1.0 + offset)).round() as u64).max(1)
+    }
+}
+
+/// A uniform random number in [0, 1), or 0.5 if the system's random
+/// source fails.
+fn random_unit() -> f64 {
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    let mut bytes = [0_u8; 8];
+    if SystemRandom::new().fill(&mut bytes).is_err() {
+        return 0.5;
+    }
+    // The top 53 bits fill an f64 mantissa exactly
+    (u64::from_le_bytes(bytes) >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+/// Time spent pausing under [`Pacing`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacingSummary {
+    /// Number of pauses taken
+    pub pauses: usize,
+    /// Total time spent paused, which the test took longer by
+    pub paused: Duration,
+}
+
+/// Requests the server turned away with HTTP 429 during a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimitSummary {
+    /// Rate limited responses, counting each attempt
+    pub responses: usize,
+    /// Total time spent waiting for the limit to lift, which the test
+    /// took longer by
+    pub waited: Duration,
+}
+
+/// The local address used to reach the test server changing during a
+/// run, e.g. from a Wi-Fi roam or a VPN connecting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkChange {
+    /// Phase that was running when the change was noticed
+    pub phase: TestPhase,
+    /// Local address before, `None` if there was no route
+    pub from: Option<IpAddr>,
+    /// Local address after, `None` if the route was lost
+    pub to: Option<IpAddr>,
+    /// Whether the affected measurements were run again
+    pub restarted: bool,
+}
+
+/// Rate limiting seen so far in a run.
+#[derive(Debug, Default)]
+struct RateLimitState {
+    /// When the next measurement may start, if the last attempt of a
+    /// measurement was rate limited
+    until: Option<Instant>,
+    summary: RateLimitSummary,
+}
+
+/// Pacing progress through a run.
+#[derive(Debug, Default)]
+struct PacingState {
+    /// Duration of the most recent bandwidth measurement
+    last_duration_ms: Option<f64>,
+    summary: PacingSummary,
+}
+
+/// State collected over one run of [`TestEngine::run`].
+///
+/// Kept out of the engine so that one engine can be reused for many runs,
+/// including runs in flight at the same time.
+#[derive(Debug, Default)]
+struct RunState {
+    /// Measurement ID every request of the run is tagged with
+    meas_id: Option<String>,
+    /// Pauses taken so far under `config.pacing`
+    pacing: Mutex<PacingState>,
+    /// TTFB of each download request so far, in ms
+    ttfb_samples: Mutex<Vec<f64>>,
+    /// TTFB and server processing time of each latency probe that
+    /// reported one, in ms
+    probe_timings: Mutex<Vec<(f64, f64)>>,
+    /// Rate limited responses so far
+    rate_limit: Mutex<RateLimitState>,
+    /// Route to the pinned endpoint, once it is pinned
+    network: Mutex<Option<NetworkWatch>>,
+    /// Network changes noticed so far
+    network_changes: Mutex<Vec<NetworkChange>>,
+    /// Allocations of each phase so far, with the `profiling` feature
+    profile: PhaseProfiler,
+}
+
+impl RunState {
+    /// Record a bandwidth measurement's duration for pacing.
+    fn record_for_pacing(&self, duration_ms: f64) {
+        if let Ok(mut state) = self.pacing.lock() {
+            state.last_duration_ms = Some(duration_ms);
+        }
+    }
+
+    /// Record a download request's time to first byte.
+    fn record_ttfb(&self, ttfb_ms: f64) {
+        if let Ok(mut samples) = self.ttfb_samples.lock() {
+            samples.push(ttfb_ms);
+        }
+    }
+
+    /// Record a latency probe's TTFB and, when the server reported it,
+    /// its processing time.
+    fn record_probe(&self, timing: &ProbeTiming) {
+        self.record_ttfb(timing.ttfb_ms);
+        if let (Some(server_ms), Ok(mut timings)) =
+            (timing.server_ms, self.probe_timings.lock())
+        {
+            timings.push((timing.ttfb_ms, server_ms));
+        }
+    }
+
+    /// Record a measurement's rate limited attempts.
+    ///
+    /// Retries already waited before trying again, as long as
+    /// `retry_config` said; when the last attempt was rate limited too,
+    /// the next measurement has to wait instead.
+    fn record_rate_limits(
+        &self,
+        retry_config: &RetryConfig,
+        errors: &[FailedAttempt],
+        retried_last: bool,
+    ) {
+        let Ok(mut state) = self.rate_limit.lock() else {
+            return;
+        };
+        for (i, failure) in errors.iter().enumerate() {
+            if failure.kind != ErrorKind::RateLimited {
+                continue;
+            }
+            state.summary.responses += 1;
+            let delay = retry_config.delay_after(failure.attempt - 1, failure);
+            if i + 1 < errors.len() || retried_last {
+                state.summary.waited += delay;
+            } else {
+                state.until = Some(Instant::now() + delay);
+            }
+        }
+    }
+
+    /// Take the wait the server's rate limit imposes on the next
+    /// measurement, if there is one.
+    fn take_rate_limit_wait(&self) -> Option<Duration> {
+        let mut state = self.rate_limit.lock().ok()?;
+        let wait = state.until.take()?.checked_duration_since(Instant::now());
+        if let Some(wait) = wait {
+            state.summary.waited += wait;
+        }
+        wait
+    }
+
+    /// Rate limiting so far, if the server limited any request.
+    fn rate_limit_summary(&self) -> Option<RateLimitSummary> {
+        self.rate_limit
+            .lock()
+            .ok()
+            .map(|state| state.summary)
+            .filter(|summary| summary.responses > 0)
+    }
+
+    /// Record whether the network changed since the last check,
+    /// attributing a change to `phase`. `restarting` says whether the
+    /// caller runs the phase again when it did.
+    fn check_network(&self, phase: TestPhase, restarting: bool) -> bool {
+        let change = self.network.lock().ok().and_then(|mut watch| {
+            watch.as_mut().and_then(NetworkWatch::check)
+        });
+        let Some(change) = change else {
+            return false;
+        };
+
+        warn!(
+            "Network changed during {:?}: local address {} -> {}{}",
+            phase,
+            describe_address(change.from),
+            describe_address(change.to),
+            if restarting { "; running it again" } else { "" }
+        );
+        if let Ok(mut changes) = self.network_changes.lock() {
+            changes.push(NetworkChange {
+                phase,
+                from: change.from,
+                to: change.to,
+                restarted: restarting,
+            });
+        }
+        true
+    }
+
+    /// Network changes noticed so far.
+    fn network_changes(&self) -> Vec<NetworkChange> {
+        self.network_changes
+            .lock()
+            .map(|changes| changes.clone())
+            .unwrap_or_default()
+    }
+
+    /// Pauses taken so far.
+    fn pacing_summary(&self) -> PacingSummary {
+        self.pacing.lock().map(|state| state.summary).unwrap_or_default()
+    }
+
+    /// Summary of the TTFB samples so far, if there are any.
+    fn ttfb_summary(&self) -> Option<TtfbSummary> {
+        let breakdown = self
+            .probe_timings
+            .lock()
+            .ok()
+            .and_then(|timings| RttBreakdown::from_probes(&timings));
+        self.ttfb_samples
+            .lock()
+            .ok()
+            .and_then(|samples| TtfbSummary::from_samples(&samples))
+            .map(|summary| summary.with_breakdown(breakdown))
+    }
+}
+
+/// A local address for logs, or "none" when there was no route.
+fn describe_address(address: Option<IpAddr>) -> String {
+    address.map_or_else(|| "none".to_string(), |ip| ip.to_string())
+}
+
+/// How long to wait for each ICMP echo reply.
+const ICMP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often a running transfer is checked for stalls.
+const STALL_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often a paused engine checks whether it has been resumed.
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Switch shared with a [`TestEngine`] that holds the test between
+/// measurements while it is on.
+#[derive(Debug, Clone, Default)]
+pub struct PauseFlag(Arc<AtomicBool>);
+
+impl PauseFlag {
+    /// Flip the switch, returning whether it is now on.
+    pub fn toggle(&self) -> bool {
+        !self.0.fetch_xor(true, Ordering::AcqRel)
+    }
+
+    /// Turn the switch off.
+    pub fn clear(&self) {
+        self.0.store(false, Ordering::Release);
+    }
+
+    /// Whether the switch is on.
+    pub fn is_paused(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Probe used to measure idle latency.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum LatencyProbe {
+    /// TCP handshake time of a small HTTP download
+    #[default]
+    Http,
+    /// ICMP echo round trip
+    Icmp,
+}
+
+/// Tick period used for a fixed-tick latency schedule when no interval is
+/// given, matching ping's typical spacing.
+pub const DEFAULT_LATENCY_TICK_MS: u64 = 100;
+
+/// Least time between sending two parallel latency probes when no
+/// interval is given, so they don't all leave in one burst.
+pub const DEFAULT_LATENCY_PARALLEL_GAP_MS: u64 = 20;
+
+/// How idle latency probes are scheduled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LatencySchedule {
+    /// Each probe is sent after the previous one completes.
+    #[default]
+    Sequential,
+    /// Probes are sent on a fixed tick whether or not earlier probes
+    /// have completed, like ping.
+    FixedTick,
+    /// Up to `in_flight` probes are outstanding at once, each sent as
+    /// soon as a slot frees up, which shortens the phase on high-RTT
+    /// links.
+    Parallel {
+        /// Most probes in flight at the same time
+        in_flight: usize,
+    },
+}
+
+/// Order the download and upload size blocks run in.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum TestOrder {
+    /// Every download block, then every upload block.
+    Sequential,
+    /// Download and upload blocks of each size alternate.
+    #[default]
+    Interleaved,
+    /// Downloads and uploads run at the same time, loading the link in
+    /// both directions as a video call or backup would.
+    Concurrent,
+}
+
+impl fmt::Display for TestOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TestOrder::Sequential => write!(f, "sequential"),
+            TestOrder::Interleaved => write!(f, "interleaved"),
+            TestOrder::Concurrent => write!(f, "concurrent"),
         }
     }
 }
 
+impl FromStr for TestOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "sequential" => Ok(TestOrder::Sequential),
+            "interleaved" => Ok(TestOrder::Interleaved),
+            "concurrent" => Ok(TestOrder::Concurrent),
+            other => Err(format!(
+                "unknown test order '{}' (expected sequential, \
+                 interleaved or concurrent)",
+                other
+            )),
+        }
+    }
+}
+
+/// Which phases of the test run.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PhaseSelection {
+    /// Latency, downloads and uploads.
+    #[default]
+    All,
+    /// Idle latency only, for monitoring without loading the link.
+    Latency,
+    /// Latency and downloads.
+    Download,
+    /// Latency and uploads.
+    Upload,
+}
+
+impl PhaseSelection {
+    /// Whether the download blocks run.
+    pub fn downloads(self) -> bool {
+        matches!(self, PhaseSelection::All | PhaseSelection::Download)
+    }
+
+    /// Whether the upload blocks run.
+    pub fn uploads(self) -> bool {
+        matches!(self, PhaseSelection::All | PhaseSelection::Upload)
+    }
+}
+
+impl fmt::Display for PhaseSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PhaseSelection::All => write!(f, "all"),
+            PhaseSelection::Latency => write!(f, "latency"),
+            PhaseSelection::Download => write!(f, "download"),
+            PhaseSelection::Upload => write!(f, "upload"),
+        }
+    }
+}
+
+/// One direction's progress through its size blocks.
+struct DirectionRun<'a> {
+    blocks: &'a [DataBlock],
+    is_download: bool,
+    measurements: Vec<BandwidthMeasurement>,
+    size_results: Vec<SizeMeasurement>,
+    early_terminated: bool,
+    phase_started: bool,
+    phase_completed: bool,
+    /// Measurements taken so far, for progress events
+    measurement_count: usize,
+    total_measurements: usize,
+    loaded_latency: LoadedLatencyCollector,
+}
+
+impl<'a> DirectionRun<'a> {
+    fn new(blocks: &'a [DataBlock], is_download: bool) -> Self {
+        Self {
+            blocks,
+            is_download,
+            measurements: Vec::new(),
+            size_results: Vec::new(),
+            early_terminated: false,
+            phase_started: false,
+            phase_completed: false,
+            measurement_count: 0,
+            total_measurements: blocks.iter().map(|b| b.count).sum(),
+            loaded_latency: LoadedLatencyCollector::new(),
+        }
+    }
+
+    fn test_type(&self) -> &'static str {
+        if self.is_download {
+            "download"
+        } else {
+            "upload"
+        }
+    }
+
+    fn phase(&self) -> TestPhase {
+        if self.is_download {
+            TestPhase::Download
+        } else {
+            TestPhase::Upload
+        }
+    }
+
+    fn direction(&self) -> BandwidthDirection {
+        if self.is_download {
+            BandwidthDirection::Download
+        } else {
+            BandwidthDirection::Upload
+        }
+    }
+
+    fn latency_direction(&self) -> LatencyDirection {
+        if self.is_download {
+            LatencyDirection::Download
+        } else {
+            LatencyDirection::Upload
+        }
+    }
+}
+
+/// Rule that ended a size block early.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TerminationRule {
+    /// A measurement reached `bandwidth_finish_duration_ms`; larger sizes
+    /// are skipped.
+    Duration,
+    /// Recent measurements were stable enough that the remaining
+    /// iterations of this size were skipped.
+    Convergence,
+}
+
 /// Results from a single bandwidth measurement set (one file size).
 #[derive(Debug, Clone)]
 pub struct SizeMeasurement {
@@ -133,6 +933,51 @@ pub struct SizeMeasurement {
     pub measurements: Vec<BandwidthMeasurement>,
     /// Whether early termination was triggered after this size
     pub triggered_early_termination: bool,
+    /// Which rule, if any, ended this size block early
+    pub terminated_by: Option<TerminationRule>,
+    /// Kernel TCP statistics for each measurement, where supported
+    pub tcp_stats: Vec<TcpStats>,
+    /// Effective socket buffer sizes for each measurement
+    pub socket_buffers: Vec<SocketBuffers>,
+    /// Iterations that needed retries, when `detailed` is enabled
+    pub failures: Vec<MeasurementFailure>,
+    /// Cloudflare headers of each response, when `detailed` is enabled
+    pub edge_headers: Vec<EdgeHeaders>,
+    /// Loaded latency samples in ms taken while this size block ran
+    pub loaded_latencies: Vec<f64>,
+    /// Redirects the measurements followed
+    pub redirects: u32,
+    /// Time spent following redirects in ms, left out of the speeds
+    pub redirect_ms: f64,
+    /// Share of one core each measurement kept busy, where measurable
+    pub core_utilization: Vec<f64>,
+}
+
+/// Retries needed by one iteration of a size block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MeasurementFailure {
+    /// Iteration within the size block, starting at 1
+    pub iteration: usize,
+    /// Total attempts made, including the final one
+    pub attempts: u32,
+    /// Whether a retry eventually succeeded
+    pub recovered: bool,
+    /// Each failed attempt, in order
+    pub errors: Vec<FailedAttempt>,
+}
+
+/// Everything one size block produced.
+struct BlockOutcome {
+    measurements: Vec<BandwidthMeasurement>,
+    tcp_stats: Vec<TcpStats>,
+    socket_buffers: Vec<SocketBuffers>,
+    failures: Vec<MeasurementFailure>,
+    edge_headers: Vec<EdgeHeaders>,
+    loaded_latencies: Vec<f64>,
+    redirects: u32,
+    redirect_ms: f64,
+    core_utilization: Vec<f64>,
+    terminated_by: Option<TerminationRule>,
 }
 
 /// Results from latency measurements.
@@ -150,17 +995,29 @@ pub struct LatencyResults {
     pub loaded_up_ms: Option<f64>,
     /// Loaded jitter during uploads in milliseconds
     pub loaded_up_jitter_ms: Option<f64>,
+    /// Probe that measured idle latency
+    pub probe: LatencyProbe,
+    /// Background load streams running while loaded latency was measured
+    pub load_streams: usize,
 }
 
 /// Results from bandwidth measurements (download or upload).
 #[derive(Debug, Clone)]
 pub struct BandwidthResults {
-    /// Final speed in Mbps (90th percentile of all measurements)
+    /// Final speed in Mbps (`percentile` of all measurements)
     pub speed_mbps: f64,
+    /// Mean speed in Mbps across all measurements
+    pub mean_mbps: Option<f64>,
+    /// Fastest single measurement in Mbps
+    pub max_mbps: Option<f64>,
+    /// Percentile `speed_mbps` was taken at (0.0 to 1.0)
+    pub percentile: f64,
     /// Per-size measurement results
     pub measurements: Vec<SizeMeasurement>,
     /// Whether early termination was applied
     pub early_terminated: bool,
+    /// Payload pattern used (uploads only)
+    pub payload: Option<PayloadPattern>,
 }
 
 /// Complete results from a speed test run.
@@ -172,6 +1029,123 @@ pub struct SpeedTestOutput {
     pub download: BandwidthResults,
     /// Upload bandwidth results
     pub upload: BandwidthResults,
+    /// TLS session details from the initial download estimation, recorded
+    /// with `detailed`
+    pub tls: Option<TlsInfo>,
+    /// Lookup of the endpoint during setup, unless the test ran through
+    /// a proxy
+    pub dns: Option<DnsLookup>,
+    /// IPv6 and IPv4 connection races, when the endpoint resolved to both
+    pub dual_stack: Option<DualStack>,
+    /// How far the wall clock was stepped while the test ran, if at all;
+    /// measurements use monotonic time and are unaffected
+    pub clock_step: Option<TimeDelta>,
+    /// Time spent pausing, when pacing was configured
+    pub pacing: Option<PacingSummary>,
+    /// Path MTU toward the endpoint, when probed and ICMP got through
+    pub path_mtu: Option<PathMtu>,
+    /// Cold and warm connection setup times, when compared
+    pub handshake: Option<HandshakeComparison>,
+    /// Throughput and latency with both directions saturated, when the
+    /// duplex phase ran
+    pub duplex: Option<DuplexResult>,
+    /// Time to first byte across the run's downloads, when any completed
+    pub ttfb: Option<TtfbSummary>,
+    /// Requests the server rate limited, when it limited any
+    pub rate_limit: Option<RateLimitSummary>,
+    /// Changes of the network the test ran on, in the order noticed
+    pub network_changes: Vec<NetworkChange>,
+    /// Measurement ID the run's requests were tagged with
+    pub meas_id: Option<String>,
+    /// Phases that ran; the bandwidth results of skipped directions are
+    /// empty
+    pub phases: PhaseSelection,
+    /// Allocations per phase and peak memory, recorded with `detailed`
+    /// in builds with the `profiling` feature
+    pub profile: Option<RunProfile>,
+}
+
+impl SpeedTestOutput {
+    /// Address the endpoint was pinned to during setup: the winner of the
+    /// dual-stack races, or else the lookup's preferred address.
+    pub fn endpoint_ip(&self) -> Option<IpAddr> {
+        match &self.dual_stack {
+            Some(dual_stack) => Some(dual_stack.winner()),
+            None => self.dns.as_ref().map(DnsLookup::preferred),
+        }
+    }
+}
+
+/// Time to first byte across every download request of a run.
+///
+/// TTFB covers the request's trip to the server, the server's processing
+/// and the first byte's trip back, on an already-open connection. High
+/// TTFB alongside good bandwidth points at the server or CDN rather than
+/// the access link.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TtfbSummary {
+    /// Median TTFB in ms
+    pub median_ms: f64,
+    /// 95th percentile TTFB in ms
+    pub p95_ms: f64,
+    /// Requests the summary covers
+    pub samples: usize,
+    /// Latency probes' TTFB split into network and server time, when the
+    /// server reported its processing time
+    pub breakdown: Option<RttBreakdown>,
+}
+
+impl TtfbSummary {
+    /// Summarize TTFB samples in ms, or `None` if there are none.
+    pub fn from_samples(samples: &[f64]) -> Option<Self> {
+        let mut sorted = samples.to_vec();
+        Some(Self {
+            median_ms: median_f64(&mut sorted)?,
+            p95_ms: percentile_f64(&mut sorted, 0.95)?,
+            samples: samples.len(),
+            breakdown: None,
+        })
+    }
+
+    /// Attach the latency probes' TTFB breakdown.
+    pub fn with_breakdown(mut self, breakdown: Option<RttBreakdown>) -> Self {
+        self.breakdown = breakdown;
+        self
+    }
+}
+
+/// Latency probes' TTFB split into the round trip between client and
+/// edge and the edge's own processing time.
+///
+/// The edge reports how long it took to answer in the `Server-Timing`
+/// header; whatever is left of the TTFB was spent on the network. A slow
+/// edge and a slow path both raise TTFB, and only this tells them apart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RttBreakdown {
+    /// Median of each probe's TTFB less its processing time, in ms
+    pub network_median_ms: f64,
+    /// Median processing time the edge reported, in ms
+    pub server_median_ms: f64,
+    /// Probes that reported a processing time
+    pub samples: usize,
+}
+
+impl RttBreakdown {
+    /// Summarize probes' `(ttfb_ms, server_ms)` pairs, or `None` if there
+    /// are none.
+    pub fn from_probes(probes: &[(f64, f64)]) -> Option<Self> {
+        let mut network: Vec<f64> = probes
+            .iter()
+            .map(|&(ttfb_ms, server_ms)| (ttfb_ms - server_ms).max(0.0))
+            .collect();
+        let mut server: Vec<f64> =
+            probes.iter().map(|&(_, server_ms)| server_ms).collect();
+        Some(Self {
+            network_median_ms: median_f64(&mut network)?,
+            server_median_ms: median_f64(&mut server)?,
+            samples: probes.len(),
+        })
+    }
 }
 
 /// The test engine that orchestrates all network measurements.
@@ -197,6 +1171,8 @@ pub struct TestEngine {
     /// Optional progress callback for TUI updates.
     /// When provided, the engine emits progress events during test execution.
     progress_callback: Option<Arc<dyn ProgressCallback>>,
+    /// Holds the test between measurements while on
+    pause: PauseFlag,
 }
 
 impl TestEngine {
@@ -209,7 +1185,96 @@ impl TestEngine {
         config: TestConfig,
         progress_callback: Option<Arc<dyn ProgressCallback>>,
     ) -> Self {
-        Self { config, progress_callback }
+        Self { config, progress_callback, pause: PauseFlag::default() }
+    }
+
+    /// Hold the test between measurements whenever `pause` is on.
+    pub fn with_pause(mut self, pause: PauseFlag) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    /// Socket options for latency probes: the DSCP marking without the
+    /// buffer sizes, which only matter for bulk transfers.
+    fn probe_socket_options(&self) -> SocketOptions {
+        SocketOptions {
+            dscp: self.config.socket_options.dscp,
+            ..SocketOptions::default()
+        }
+    }
+
+    /// Background load streams to run, after any pacing cap.
+    fn load_streams(&self) -> usize {
+        match self.config.pacing {
+            Some(pacing) => {
+                self.config.load_streams.min(pacing.max_load_streams)
+            }
+            None => self.config.load_streams,
+        }
+    }
+
+    /// Pause before a bandwidth measurement if pacing is configured,
+    /// based on how long the previous measurement took.
+    async fn pace(&self, state: &RunState) {
+        let Some(pacing) = self.config.pacing else {
+            return;
+        };
+        let last =
+            state.pacing.lock().ok().and_then(|state| state.last_duration_ms);
+        let Some(last_duration_ms) = last else {
+            return;
+        };
+
+        let pause = pacing.pause_after(last_duration_ms);
+        debug!("Pacing: pausing {:?} before the next measurement", pause);
+        tokio::time::sleep(pause).await;
+
+        if let Ok(mut pacing) = state.pacing.lock() {
+            pacing.summary.pauses += 1;
+            pacing.summary.paused += pause;
+        }
+    }
+
+    /// Wait a random gap before the next measurement under
+    /// `config.pacing_jitter`, returning the number of bytes it should
+    /// transfer in place of `bytes`.
+    async fn jitter(&self, bytes: u64) -> u64 {
+        let Some(jitter) = self.config.pacing_jitter else {
+            return bytes;
+        };
+
+        tokio::time::sleep(jitter.gap(random_unit())).await;
+        let jittered = jitter.size(bytes, random_unit());
+        debug!("Pacing jitter: requesting {} bytes for {}", jittered, bytes);
+        jittered
+    }
+
+    /// Wait before the next measurement while the server is rate
+    /// limiting requests.
+    async fn wait_out_rate_limit(&self, state: &RunState) {
+        if let Some(wait) = state.take_rate_limit_wait() {
+            info!(
+                "Rate limited: waiting {:?} before the next measurement",
+                wait
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Wait for the pause flag to clear, if it is on, emitting events
+    /// when the test pauses and resumes.
+    async fn wait_while_paused(&self) {
+        if !self.pause.is_paused() {
+            return;
+        }
+
+        info!("Test paused");
+        self.emit_progress(ProgressEvent::Paused);
+        while self.pause.is_paused() {
+            tokio::time::sleep(PAUSE_POLL_INTERVAL).await;
+        }
+        info!("Test resumed");
+        self.emit_progress(ProgressEvent::Resumed);
     }
 
     /// Emit a progress event if a callback is registered.
@@ -219,35 +1284,109 @@ impl TestEngine {
         }
     }
 
+    /// Move the run on to `phase`, noting it for profiling.
+    fn change_phase(&self, state: &RunState, phase: TestPhase) {
+        state.profile.enter(phase);
+        self.emit_progress(ProgressEvent::PhaseChange(phase));
+    }
+
     /// Run the complete speed test sequence.
     ///
     /// Executes measurements in the following order:
+    /// 0. Setup: resolve and pin the endpoint, optionally pre-warm TLS,
+    ///    probe the path MTU and compare cold and warm connection setup
     /// 1. Initial latency estimation (1 packet)
     /// 2. Initial download estimation (100KB, 1 request)
     /// 3. Full latency measurement (20 packets)
-    /// 4. Download and upload tests (interleaved by similar sizes)
+    /// 4. Download and upload tests, in the configured [`TestOrder`]
+    /// 5. Duplex saturation, if configured
+    ///
+    /// Directions left out of [`TestConfig::phases`] are skipped, along
+    /// with the download estimation when downloads are, and duplex
+    /// saturation unless every phase runs.
     ///
-    /// Download and upload tests are interleaved to provide a more
-    /// realistic measurement of connection performance under varying
+    /// By default download and upload tests are interleaved to provide a
+    /// more realistic measurement of connection performance under varying
     /// conditions.
     ///
+    /// The engine keeps no state between runs, so it can be reused for
+    /// any number of runs, concurrently or one after another.
+    ///
     /// # Returns
     /// Complete speed test results including latency, download, and upload
     pub async fn run(&self) -> Result<SpeedTestOutput, Box<dyn Error>> {
-        info!("Starting speed test sequence");
+        let state =
+            RunState { meas_id: Some(new_meas_id()), ..RunState::default() };
+        info!(
+            "Starting speed test sequence (measId {})",
+            state.meas_id.as_deref().unwrap_or_default()
+        );
 
         // Emit initializing phase
-        self.emit_progress(ProgressEvent::PhaseChange(
-            TestPhase::Initializing,
-        ));
+        self.change_phase(&state, TestPhase::Initializing);
+
+        // Step 0: Pin the endpoint so every measurement uses one address
+        let clock = ClockWatch::start();
+        let (dns, dual_stack) = self.run_setup().await?;
+        // Through a proxy the route to the endpoint isn't ours to watch
+        if let Some(endpoint) = dual_stack
+            .as_ref()
+            .map(DualStack::winner)
+            .or_else(|| dns.as_ref().map(DnsLookup::preferred))
+        {
+            if let Ok(mut network) = state.network.lock() {
+                *network = Some(NetworkWatch::start(endpoint));
+            }
+        }
+
+        let path_mtu = if self.config.path_mtu {
+            match self.run_path_mtu().await {
+                Ok(path_mtu) => {
+                    info!(
+                        "Path MTU: {} bytes ({} probes)",
+                        path_mtu.bytes, path_mtu.probes
+                    );
+                    Some(path_mtu)
+                }
+                Err(e) => {
+                    warn!("Path MTU discovery unavailable: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let handshake = if self.config.handshake {
+            self.run_handshake().await
+        } else {
+            None
+        };
 
         // Step 1: Initial latency estimation (1 packet)
         debug!("Running initial latency estimation");
-        let _ = self.run_latency_internal(1, false).await?;
+        let _ = self.run_latency_internal(1, false, &state).await?;
 
         // Step 2: Initial download estimation (100KB, 1 request)
-        debug!("Running initial download estimation");
-        let _ = self.run_download_single(100_000).await?;
+        let tls = if self.config.phases.downloads() {
+            debug!("Running initial download estimation");
+            let estimation = self
+                .run_download_single(100_000, state.meas_id.clone())
+                .await?;
+            state.record_ttfb(estimation.ttfb_duration.as_secs_f64() * 1000.0);
+            estimation.tls
+        } else {
+            None
+        };
+        if let Some(ref tls) = tls {
+            info!(
+                "TLS: {} {} (ALPN: {}), handshake {:.2} ms",
+                tls.version,
+                tls.cipher_suite,
+                tls.alpn.as_deref().unwrap_or("none"),
+                tls.handshake_duration.as_secs_f64() * 1000.0
+            );
+        }
 
         // Step 3: Full latency measurement
         debug!(
@@ -256,11 +1395,15 @@ impl TestEngine {
         );
 
         // Emit latency phase
-        self.emit_progress(ProgressEvent::PhaseChange(TestPhase::Latency));
-
-        let idle_latencies = self
-            .run_latency_internal(self.config.latency_packets, true)
-            .await?;
+        self.change_phase(&state, TestPhase::Latency);
+
+        let (mut idle_latencies, mut probe) =
+            self.run_idle_latency(&state).await?;
+        let restart = self.config.restart_on_network_change;
+        if state.check_network(TestPhase::Latency, restart) && restart {
+            (idle_latencies, probe) = self.run_idle_latency(&state).await?;
+            state.check_network(TestPhase::Latency, false);
+        }
 
         // run_latency_internal guarantees non-empty vec on success
         let idle_ms = latency_f64(&idle_latencies)
@@ -268,22 +1411,30 @@ impl TestEngine {
         let idle_jitter_ms = jitter_f64(&idle_latencies);
 
         info!("Idle latency: {:.2} ms, jitter: {:?}", idle_ms, idle_jitter_ms);
+        self.emit_progress(ProgressEvent::LatencyComplete {
+            idle_ms,
+            jitter_ms: idle_jitter_ms,
+        });
 
         // Emit latency phase complete
         self.emit_progress(ProgressEvent::PhaseComplete(TestPhase::Latency));
 
-        // Step 4: Interleaved download and upload tests with loaded latency
-        let mut loaded_latency_collector = LoadedLatencyCollector::new();
-
-        let (download, upload) = self
-            .run_interleaved_bandwidth_tests(&mut loaded_latency_collector)
-            .await?;
+        // Step 4: Download and upload tests with loaded latency
+        let (download_run, upload_run) =
+            self.run_bandwidth_tests(&state).await?;
 
         // Calculate loaded latency results
-        let loaded_down_latencies =
-            loaded_latency_collector.get_latencies(LatencyDirection::Download);
+        let loaded_down_latencies = download_run
+            .loaded_latency
+            .get_latencies(LatencyDirection::Download);
         let loaded_up_latencies =
-            loaded_latency_collector.get_latencies(LatencyDirection::Upload);
+            upload_run.loaded_latency.get_latencies(LatencyDirection::Upload);
+        // Uploads don't time the first response byte
+        for measurement in &download_run.measurements {
+            state.record_ttfb(measurement.ttfb_ms);
+        }
+        let download = self.bandwidth_results(download_run);
+        let upload = self.bandwidth_results(upload_run);
 
         let loaded_down_ms = if !loaded_down_latencies.is_empty() {
             let mut latencies = loaded_down_latencies.clone();
@@ -318,217 +1469,481 @@ impl TestEngine {
             loaded_down_jitter_ms,
             loaded_up_ms,
             loaded_up_jitter_ms,
+            probe,
+            load_streams: self.load_streams(),
         };
 
+        // Step 5: Saturate both directions at once
+        let duplex = if self.config.duplex
+            && self.config.phases == PhaseSelection::All
+        {
+            self.run_duplex(&state, download.speed_mbps, upload.speed_mbps)
+                .await
+        } else {
+            None
+        };
+        if duplex.is_some() {
+            state.check_network(TestPhase::Duplex, false);
+        }
+
         info!(
             "Speed test complete: download={:.2} Mbps, upload={:.2} Mbps",
             download.speed_mbps, upload.speed_mbps
         );
 
         // Emit complete phase
-        self.emit_progress(ProgressEvent::PhaseChange(TestPhase::Complete));
+        self.change_phase(&state, TestPhase::Complete);
+
+        let pacing = self.config.pacing.map(|_| state.pacing_summary());
+        if let Some(ref pacing) = pacing {
+            info!(
+                "Pacing added {:.1}s over {} pauses",
+                pacing.paused.as_secs_f64(),
+                pacing.pauses
+            );
+        }
+
+        let ttfb = state.ttfb_summary();
+        if let Some(ref ttfb) = ttfb {
+            info!(
+                "TTFB: median {:.2} ms, p95 {:.2} ms over {} requests",
+                ttfb.median_ms, ttfb.p95_ms, ttfb.samples
+            );
+            if let Some(breakdown) = ttfb.breakdown {
+                info!(
+                    "Probe TTFB: network {:.2} ms, edge processing {:.2} ms \
+                     (medians over {} probes)",
+                    breakdown.network_median_ms,
+                    breakdown.server_median_ms,
+                    breakdown.samples
+                );
+            }
+        }
+
+        let rate_limit = state.rate_limit_summary();
+        if let Some(ref rate_limit) = rate_limit {
+            warn!(
+                "Server rate limited {} requests; waited {:.1}s for it",
+                rate_limit.responses,
+                rate_limit.waited.as_secs_f64()
+            );
+        }
+
+        let clock_step = clock.step();
+        if let Some(step) = clock_step {
+            warn!(
+                "System clock was stepped by {:.1}s during the test",
+                step.num_milliseconds() as f64 / 1000.0
+            );
+        }
 
-        Ok(SpeedTestOutput { latency, download, upload })
+        Ok(SpeedTestOutput {
+            latency,
+            download,
+            upload,
+            tls: tls.filter(|_| self.config.detailed),
+            dns,
+            dual_stack,
+            clock_step,
+            pacing,
+            path_mtu,
+            handshake,
+            duplex,
+            ttfb,
+            rate_limit,
+            network_changes: state.network_changes(),
+            meas_id: state.meas_id.clone(),
+            phases: self.config.phases,
+            profile: self
+                .config
+                .detailed
+                .then(|| state.profile.finish())
+                .flatten(),
+        })
     }
 
-    /// Run interleaved download and upload bandwidth tests.
-    ///
-    /// This method interleaves download and upload tests of similar sizes
-    /// to provide more realistic measurements. Tests are paired by size
-    /// and executed alternately (download then upload for each size).
-    ///
-    /// Early termination is tracked separately for each direction.
-    async fn run_interleaved_bandwidth_tests(
+    /// Run the duplex saturation phase; failures are logged and leave the
+    /// rest of the results intact.
+    async fn run_duplex(
         &self,
-        loaded_latency_collector: &mut LoadedLatencyCollector,
-    ) -> Result<(BandwidthResults, BandwidthResults), Box<dyn Error>> {
-        let mut download_measurements: Vec<BandwidthMeasurement> = Vec::new();
-        let mut upload_measurements: Vec<BandwidthMeasurement> = Vec::new();
-        let mut download_size_results: Vec<SizeMeasurement> = Vec::new();
-        let mut upload_size_results: Vec<SizeMeasurement> = Vec::new();
-        let mut download_early_terminated = false;
-        let mut upload_early_terminated = false;
-
-        // Track phase state for progress events
-        let mut download_phase_started = false;
-        let mut upload_phase_started = false;
-
-        // Calculate total measurements for progress tracking
-        let total_download_measurements: usize =
-            self.config.download_sizes.iter().map(|b| b.count).sum();
-        let total_upload_measurements: usize =
-            self.config.upload_sizes.iter().map(|b| b.count).sum();
-        let mut download_measurement_count = 0usize;
-        let mut upload_measurement_count = 0usize;
-
-        // Get the maximum number of size blocks between download and upload
-        let max_blocks = self
-            .config
-            .download_sizes
-            .len()
-            .max(self.config.upload_sizes.len());
-
-        for i in 0..max_blocks {
-            // Run download test for this size (if available and not terminated)
-            if let Some(block) = self.config.download_sizes.get(i) {
-                if !download_early_terminated {
-                    // Emit download phase start on first download block
-                    if !download_phase_started {
-                        self.emit_progress(ProgressEvent::PhaseChange(
-                            TestPhase::Download,
-                        ));
-                        download_phase_started = true;
-                    }
+        state: &RunState,
+        download_mbps: f64,
+        upload_mbps: f64,
+    ) -> Option<DuplexResult> {
+        if self.config.pacing.is_some() {
+            warn!("Skipping duplex saturation: it can't be paced");
+            return None;
+        }
 
-                    info!(
-                        "Running download test: {} bytes x {} iterations",
-                        block.bytes, block.count
-                    );
+        self.change_phase(state, TestPhase::Duplex);
+        let result = duplex::run(
+            &self.config,
+            state.meas_id.as_deref(),
+            download_mbps,
+            upload_mbps,
+        )
+        .await;
+        self.emit_progress(ProgressEvent::PhaseComplete(TestPhase::Duplex));
 
-                    let (measurements, triggered) = self
-                        .run_bandwidth_block_with_progress(
-                            block,
-                            true, // is_download
-                            LatencyDirection::Download,
-                            loaded_latency_collector,
-                            &mut download_measurement_count,
-                            total_download_measurements,
-                        )
-                        .await?;
-
-                    let speed_mbps = self.calculate_block_speed(&measurements);
-                    info!("Download {}B: {:.2} Mbps", block.bytes, speed_mbps);
-
-                    download_size_results.push(SizeMeasurement {
-                        bytes: block.bytes,
-                        speed_mbps,
-                        count: measurements.len(),
-                        measurements: measurements.clone(),
-                        triggered_early_termination: triggered,
-                    });
+        result.map_err(|e| warn!("Duplex saturation failed: {}", e)).ok()
+    }
 
-                    download_measurements.extend(measurements);
+    /// Search for the path MTU toward the test server.
+    ///
+    /// Fails if no ICMP socket can be opened, don't-fragment can't be
+    /// set, or the server doesn't answer pings.
+    pub async fn run_path_mtu(&self) -> Result<PathMtu, Box<dyn Error>> {
+        let (target, _) = resolve_dns(&Url::parse(BASE_URL)?).await?;
+        let path_mtu = tokio::task::spawn_blocking(move || {
+            let pinger = IcmpPinger::open(target)?;
+            mtu::discover(&pinger, ICMP_TIMEOUT)
+        })
+        .await??;
+        Ok(path_mtu)
+    }
 
-                    if triggered {
-                        download_early_terminated = true;
-                        info!(
-                            "Early termination triggered for download at {} bytes",
-                            block.bytes
-                        );
-                    }
-                } else {
-                    debug!(
-                        "Skipping download {}B due to early termination",
-                        block.bytes
-                    );
-                }
+    /// Time cold and warm connection setups to the test endpoint; failures
+    /// are logged and leave the rest of the test to run.
+    async fn run_handshake(&self) -> Option<HandshakeComparison> {
+        let url = Url::parse(&format!("{}/__down?bytes=0", BASE_URL)).ok()?;
+        if env_proxy().proxy_for(&url).is_some() {
+            warn!(
+                "Skipping the handshake comparison: it can't go through a \
+                   proxy"
+            );
+            return None;
+        }
+
+        match handshake::compare(
+            &url,
+            &self.config.headers,
+            self.config.socket_options,
+        )
+        .await
+        {
+            Ok(comparison) => {
+                info!(
+                    "Connection setup: {:.2} ms cold, {:.2} ms warm{}",
+                    comparison.cold.total().as_secs_f64() * 1000.0,
+                    comparison.warm.total().as_secs_f64() * 1000.0,
+                    if comparison.warm.resumed { " (resumed)" } else { "" }
+                );
+                Some(comparison)
+            }
+            Err(e) => {
+                warn!("Handshake comparison failed: {}", e);
+                None
             }
+        }
+    }
 
-            // Run upload test for this size (if available and not terminated)
-            if let Some(block) = self.config.upload_sizes.get(i) {
-                if !upload_early_terminated {
-                    // Emit upload phase start on first upload block
-                    // Also emit download phase complete if download was started
-                    if !upload_phase_started {
-                        if download_phase_started {
-                            self.emit_progress(ProgressEvent::PhaseComplete(
-                                TestPhase::Download,
-                            ));
-                        }
-                        self.emit_progress(ProgressEvent::PhaseChange(
-                            TestPhase::Upload,
-                        ));
-                        upload_phase_started = true;
-                    }
+    /// Resolve and pin the test endpoint, and pre-warm a TLS session if
+    /// configured.
+    ///
+    /// When the endpoint resolves to both IPv6 and IPv4 addresses, the
+    /// families are raced and the endpoint is pinned to the winner.
+    ///
+    /// # Returns
+    /// The endpoint's lookup and dual-stack races, or `None`s when a proxy
+    /// resolves the endpoint
+    async fn run_setup(
+        &self,
+    ) -> Result<(Option<DnsLookup>, Option<DualStack>), Box<dyn Error>> {
+        let url = Url::parse(BASE_URL)?;
 
-                    info!(
-                        "Running upload test: {} bytes x {} iterations",
-                        block.bytes, block.count
-                    );
+        if env_proxy().proxy_for(&url).is_some() {
+            debug!("Not pinning endpoint; the proxy resolves it");
+            self.prewarm_tls().await;
+            return Ok((None, None));
+        }
 
-                    let (measurements, triggered) = self
-                        .run_bandwidth_block_with_progress(
-                            block,
-                            false, // is_download
-                            LatencyDirection::Upload,
-                            loaded_latency_collector,
-                            &mut upload_measurement_count,
-                            total_upload_measurements,
-                        )
-                        .await?;
-
-                    let speed_mbps = self.calculate_block_speed(&measurements);
-                    info!("Upload {}B: {:.2} Mbps", block.bytes, speed_mbps);
-
-                    upload_size_results.push(SizeMeasurement {
-                        bytes: block.bytes,
-                        speed_mbps,
-                        count: measurements.len(),
-                        measurements: measurements.clone(),
-                        triggered_early_termination: triggered,
-                    });
+        let lookup = pin_dns(&url).await?;
+        let port = url.port_or_known_default().unwrap_or(443);
+        let dual_stack =
+            happy_eyeballs::run(&lookup, port, self.config.socket_options)
+                .await;
+        let ip = match &dual_stack {
+            Some(dual_stack) => {
+                let ip = dual_stack.winner();
+                pin_address(&lookup.host, ip);
+                match dual_stack.advisory() {
+                    Some(Advisory::Ipv6Broken) => {
+                        warn!("IPv6 didn't connect; testing over IPv4")
+                    }
+                    Some(Advisory::Ipv6Slower) => {
+                        warn!("IPv6 connected more slowly than IPv4")
+                    }
+                    None => {}
+                }
+                ip
+            }
+            None => lookup.preferred(),
+        };
+        info!("Endpoint {} pinned to {}", BASE_URL, ip);
 
-                    upload_measurements.extend(measurements);
+        self.prewarm_tls().await;
+        Ok((Some(lookup), dual_stack))
+    }
 
-                    if triggered {
-                        upload_early_terminated = true;
-                        info!(
-                            "Early termination triggered for upload at {} bytes",
-                            block.bytes
-                        );
-                    }
-                } else {
-                    debug!(
-                        "Skipping upload {}B due to early termination",
-                        block.bytes
-                    );
+    /// Pre-warm a TLS session when configured.
+    async fn prewarm_tls(&self) {
+        if self.config.prewarm_tls {
+            enable_session_resumption();
+            // A complete request gives the server a chance to send
+            // session tickets; failures just mean a full handshake later
+            match self.run_download_single(1, None).await {
+                Ok(result) => debug!(
+                    "Pre-warmed TLS session ({:?})",
+                    result.tls.map(|tls| tls.handshake_duration)
+                ),
+                Err(e) => warn!("TLS pre-warm failed: {}", e),
+            }
+        }
+    }
+
+    /// Run the download and upload bandwidth tests in the configured
+    /// [`TestOrder`].
+    ///
+    /// Early termination is tracked separately for each direction.
+    async fn run_bandwidth_tests(
+        &self,
+        state: &RunState,
+    ) -> Result<(DirectionRun<'_>, DirectionRun<'_>), Box<dyn Error>> {
+        let phases = self.config.phases;
+        let download_sizes: &[DataBlock] =
+            if phases.downloads() { &self.config.download_sizes } else { &[] };
+        let upload_sizes: &[DataBlock] =
+            if phases.uploads() { &self.config.upload_sizes } else { &[] };
+        let mut download = DirectionRun::new(download_sizes, true);
+        let mut upload = DirectionRun::new(upload_sizes, false);
+
+        let order = self.config.bandwidth_order();
+        if order != self.config.order {
+            warn!("Interleaving downloads and uploads: concurrent tests can't be paced");
+        }
+        match order {
+            TestOrder::Interleaved => {
+                let max_blocks =
+                    download.blocks.len().max(upload.blocks.len());
+                for i in 0..max_blocks {
+                    self.run_size_block(
+                        state,
+                        &mut download,
+                        i,
+                        Some(&mut upload),
+                    )
+                    .await?;
+                    self.run_size_block(
+                        state,
+                        &mut upload,
+                        i,
+                        Some(&mut download),
+                    )
+                    .await?;
                 }
             }
+            TestOrder::Sequential => {
+                for i in 0..download.blocks.len() {
+                    self.run_size_block(state, &mut download, i, None).await?;
+                }
+                for i in 0..upload.blocks.len() {
+                    self.run_size_block(
+                        state,
+                        &mut upload,
+                        i,
+                        Some(&mut download),
+                    )
+                    .await?;
+                }
+            }
+            TestOrder::Concurrent => {
+                // Each direction loads the link while the other measures
+                tokio::try_join!(
+                    self.run_all_size_blocks(state, &mut download),
+                    self.run_all_size_blocks(state, &mut upload),
+                )?;
+            }
         }
 
         // Emit phase complete events for any phases that were started
         // but not yet completed (handles case where upload didn't start)
-        if download_phase_started && !upload_phase_started {
-            self.emit_progress(ProgressEvent::PhaseComplete(
-                TestPhase::Download,
-            ));
+        for run in [&mut download, &mut upload] {
+            self.complete_phase(run);
         }
-        if upload_phase_started {
-            self.emit_progress(ProgressEvent::PhaseComplete(
-                TestPhase::Upload,
-            ));
+
+        Ok((download, upload))
+    }
+
+    /// Run every size block of one direction in order.
+    async fn run_all_size_blocks(
+        &self,
+        state: &RunState,
+        run: &mut DirectionRun<'_>,
+    ) -> Result<(), Box<dyn Error>> {
+        for i in 0..run.blocks.len() {
+            self.run_size_block(state, run, i, None).await?;
         }
+        self.complete_phase(run);
+        Ok(())
+    }
 
-        // Calculate final speeds using 90th percentile of all measurements
-        let download_speed_mbps = aggregate_bandwidth(
-            &download_measurements,
+    /// Run size block `i` of one direction, unless it doesn't exist or the
+    /// direction already terminated early.
+    ///
+    /// The first block run emits the direction's phase change; `previous`
+    /// is a direction whose phase this one takes over from.
+    async fn run_size_block(
+        &self,
+        state: &RunState,
+        run: &mut DirectionRun<'_>,
+        i: usize,
+        previous: Option<&mut DirectionRun<'_>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let blocks = run.blocks;
+        let Some(block) = blocks.get(i) else {
+            return Ok(());
+        };
+        let test_type = run.test_type();
+        if run.early_terminated {
+            debug!(
+                "Skipping {} {}B due to early termination",
+                test_type, block.bytes
+            );
+            return Ok(());
+        }
+
+        if !run.phase_started {
+            if let Some(previous) = previous {
+                self.complete_phase(previous);
+            }
+            self.change_phase(state, run.phase());
+            run.phase_started = true;
+        }
+
+        info!(
+            "Running {} test: {} bytes x {} iterations",
+            test_type, block.bytes, block.count
+        );
+
+        // Earlier blocks seed the upload chunk size estimate
+        let estimate_bps = aggregate_bandwidth(
+            &run.measurements,
             self.config.bandwidth_percentile,
             self.config.bandwidth_min_duration_ms,
-        )
-        .map(calculate_speed_mbps)
-        .unwrap_or(0.0);
+        );
+        // A block the network changed under runs once more, dropping the
+        // loaded latency it sampled along with its measurements
+        let mut restart = self.config.restart_on_network_change;
+        let outcome = loop {
+            let loaded_before = restart.then(|| run.loaded_latency.clone());
+            let outcome = self
+                .run_bandwidth_block_with_progress(
+                    state,
+                    block,
+                    run.is_download,
+                    run.latency_direction(),
+                    &mut run.loaded_latency,
+                    &mut run.measurement_count,
+                    run.total_measurements,
+                    estimate_bps,
+                )
+                .await?;
+            if !(state.check_network(run.phase(), restart) && restart) {
+                break outcome;
+            }
+            if let Some(loaded) = loaded_before {
+                run.loaded_latency = loaded;
+            }
+            run.total_measurements += block.count;
+            restart = false;
+        };
+        let BlockOutcome {
+            measurements,
+            tcp_stats,
+            socket_buffers,
+            failures,
+            edge_headers,
+            loaded_latencies,
+            redirects,
+            redirect_ms,
+            core_utilization,
+            terminated_by,
+        } = outcome;
+
+        let speed_mbps = self.calculate_block_speed(&measurements);
+        info!("{} {}B: {:.2} Mbps", test_type, block.bytes, speed_mbps);
+        self.emit_progress(ProgressEvent::SizeBlockComplete {
+            direction: run.direction(),
+            bytes: block.bytes,
+            speed_mbps,
+            count: measurements.len(),
+        });
+
+        let triggered = terminated_by == Some(TerminationRule::Duration);
+        run.size_results.push(SizeMeasurement {
+            bytes: block.bytes,
+            speed_mbps,
+            count: measurements.len(),
+            measurements: measurements.clone(),
+            triggered_early_termination: triggered,
+            terminated_by,
+            tcp_stats,
+            socket_buffers,
+            failures,
+            edge_headers,
+            loaded_latencies,
+            redirects,
+            redirect_ms,
+            core_utilization,
+        });
+
+        run.measurements.extend(measurements);
+
+        if triggered {
+            run.early_terminated = true;
+            info!(
+                "Early termination triggered for {} at {} bytes",
+                test_type, block.bytes
+            );
+        }
 
-        let upload_speed_mbps = aggregate_bandwidth(
-            &upload_measurements,
+        Ok(())
+    }
+
+    /// Emit the phase complete event for a direction that started and
+    /// hasn't completed yet.
+    fn complete_phase(&self, run: &mut DirectionRun<'_>) {
+        if run.phase_started && !run.phase_completed {
+            self.emit_progress(ProgressEvent::PhaseComplete(run.phase()));
+            run.phase_completed = true;
+        }
+    }
+
+    /// Aggregate one direction's measurements into its final results.
+    fn bandwidth_results(&self, run: DirectionRun<'_>) -> BandwidthResults {
+        // Calculate final speeds using 90th percentile of all measurements
+        let speed_mbps = aggregate_bandwidth(
+            &run.measurements,
             self.config.bandwidth_percentile,
             self.config.bandwidth_min_duration_ms,
         )
         .map(calculate_speed_mbps)
         .unwrap_or(0.0);
+        let mean_and_max = mean_and_max_bandwidth(
+            &run.measurements,
+            self.config.bandwidth_min_duration_ms,
+        );
 
-        let download = BandwidthResults {
-            speed_mbps: download_speed_mbps,
-            measurements: download_size_results,
-            early_terminated: download_early_terminated,
-        };
-
-        let upload = BandwidthResults {
-            speed_mbps: upload_speed_mbps,
-            measurements: upload_size_results,
-            early_terminated: upload_early_terminated,
-        };
-
-        Ok((download, upload))
+        BandwidthResults {
+            speed_mbps,
+            mean_mbps: mean_and_max
+                .map(|(mean, _)| calculate_speed_mbps(mean)),
+            max_mbps: mean_and_max.map(|(_, max)| calculate_speed_mbps(max)),
+            percentile: self.config.bandwidth_percentile,
+            measurements: run.size_results,
+            early_terminated: run.early_terminated,
+            payload: (!run.is_download).then_some(self.config.upload_payload),
+        }
     }
 
     /// Calculate the speed in Mbps for a block of measurements.
@@ -554,6 +1969,33 @@ impl TestEngine {
         }
     }
 
+    /// Check whether a block's measurements have stabilised.
+    ///
+    /// Looks at the most recent `convergence_window` measurements that
+    /// meet the minimum duration and compares their coefficient of
+    /// variation against `convergence_cv_threshold`.
+    fn has_converged(&self, measurements: &[BandwidthMeasurement]) -> bool {
+        let Some(threshold) = self.config.convergence_cv_threshold else {
+            return false;
+        };
+        let window = self.config.convergence_window;
+        if window < 2 {
+            return false;
+        }
+
+        let recent: Vec<f64> = measurements
+            .iter()
+            .rev()
+            .filter(|m| m.duration_ms >= self.config.bandwidth_min_duration_ms)
+            .take(window)
+            .map(|m| m.bandwidth_bps)
+            .collect();
+
+        recent.len() == window
+            && coefficient_of_variation(&recent)
+                .is_some_and(|cv| cv < threshold)
+    }
+
     /// Run latency measurements.
     ///
     /// # Arguments
@@ -566,7 +2008,103 @@ impl TestEngine {
         &self,
         num_packets: usize,
     ) -> Result<Vec<f64>, Box<dyn Error>> {
-        self.run_latency_internal(num_packets, false).await
+        self.run_latency_internal(num_packets, false, &RunState::default())
+            .await
+    }
+
+    /// Send a single HTTP latency probe, without retries, for continuous
+    /// monitoring where a failed probe counts as lost.
+    ///
+    /// # Returns
+    /// TCP handshake time of the probe in milliseconds
+    pub async fn probe_latency(&self) -> Result<f64, Box<dyn Error>> {
+        let download = Download::default()
+            .with_headers(self.config.headers.clone())
+            .with_socket_options(self.probe_socket_options());
+        let result = download.run(1000).await?;
+        Ok(result.tcp_duration.as_secs_f64() * 1000.0)
+    }
+
+    /// Measure idle latency with the configured probe.
+    ///
+    /// # Returns
+    /// Latency values in milliseconds and the probe that produced them
+    async fn run_idle_latency(
+        &self,
+        state: &RunState,
+    ) -> Result<(Vec<f64>, LatencyProbe), Box<dyn Error>> {
+        let num_packets = self.config.latency_packets;
+
+        if self.config.latency_probe == LatencyProbe::Icmp {
+            match self.run_icmp_latency(num_packets).await {
+                Ok(latencies) => return Ok((latencies, LatencyProbe::Icmp)),
+                Err(e) => warn!(
+                    "ICMP latency unavailable ({}), falling back to HTTP probes",
+                    e
+                ),
+            }
+        }
+
+        let latencies =
+            self.run_latency_internal(num_packets, true, state).await?;
+        Ok((latencies, LatencyProbe::Http))
+    }
+
+    /// Measure idle latency with ICMP echo requests to the test server,
+    /// spaced by `latency_interval_ms`.
+    ///
+    /// Fails if no ICMP socket can be opened or every ping fails.
+    async fn run_icmp_latency(
+        &self,
+        num_packets: usize,
+    ) -> Result<Vec<f64>, Box<dyn Error>> {
+        let (target, _) = resolve_dns(&Url::parse(BASE_URL)?).await?;
+        let pinger = Arc::new(
+            tokio::task::spawn_blocking(move || IcmpPinger::open(target))
+                .await??,
+        );
+        debug!("Pinging {} with a {:?} ICMP socket", target, pinger.kind());
+
+        let spacing = Duration::from_millis(self.config.latency_interval_ms);
+        let mut latencies = Vec::with_capacity(num_packets);
+
+        for i in 0..num_packets {
+            if i > 0 && !spacing.is_zero() {
+                tokio::time::sleep(spacing).await;
+            }
+            self.wait_while_paused().await;
+
+            let ping = Arc::clone(&pinger);
+            let sequence = i as u16;
+            let result = tokio::task::spawn_blocking(move || {
+                ping.ping(sequence, ICMP_TIMEOUT)
+            })
+            .await?;
+
+            match result {
+                Ok(rtt) => {
+                    let latency_ms = rtt.as_secs_f64() * 1000.0;
+                    debug!("ICMP latency: {:.2} ms", latency_ms);
+                    latencies.push(latency_ms);
+                    self.emit_progress(ProgressEvent::LatencyMeasurement {
+                        value_ms: latency_ms,
+                        current: i + 1,
+                        total: num_packets,
+                    });
+                }
+                Err(e) => {
+                    warn!("ICMP ping {}/{} failed: {}", i + 1, num_packets, e)
+                }
+            }
+        }
+
+        if latencies.is_empty() {
+            return Err(
+                format!("All {} ICMP pings failed", num_packets).into()
+            );
+        }
+
+        Ok(latencies)
     }
 
     /// Internal latency measurement with optional progress events.
@@ -574,6 +2112,7 @@ impl TestEngine {
     /// # Arguments
     /// * `num_packets` - Number of latency measurements to perform
     /// * `emit_progress` - Whether to emit progress events
+    /// * `state` - State of the run, which probe TTFBs are recorded in
     ///
     /// # Returns
     /// Vector of latency values in milliseconds
@@ -581,83 +2120,245 @@ impl TestEngine {
         &self,
         num_packets: usize,
         emit_events: bool,
+        state: &RunState,
     ) -> Result<Vec<f64>, Box<dyn Error>> {
-        let download = Download {};
+        let latencies = match self.config.latency_schedule {
+            LatencySchedule::Sequential => {
+                self.run_latency_sequential(num_packets, emit_events, state)
+                    .await
+            }
+            LatencySchedule::FixedTick => {
+                self.run_latency_ticked(num_packets, emit_events, state).await
+            }
+            LatencySchedule::Parallel { in_flight } => {
+                self.run_latency_parallel(
+                    num_packets,
+                    in_flight,
+                    emit_events,
+                    state,
+                )
+                .await
+            }
+        };
+
+        if latencies.is_empty() {
+            return Err(format!(
+                "All {} latency measurements failed",
+                num_packets
+            )
+            .into());
+        }
+
+        let failed_count = num_packets - latencies.len();
+        if failed_count > 0 {
+            warn!(
+                "{} of {} latency measurements failed, continuing with {} successful",
+                failed_count, num_packets, latencies.len()
+            );
+        }
+
+        Ok(latencies)
+    }
+
+    /// Send latency probes one after another, waiting
+    /// `latency_interval_ms` after each probe completes.
+    async fn run_latency_sequential(
+        &self,
+        num_packets: usize,
+        emit_events: bool,
+        state: &RunState,
+    ) -> Vec<f64> {
+        let download = Download::default()
+            .with_headers(self.config.headers.clone())
+            .with_socket_options(self.probe_socket_options())
+            .with_meas_id(state.meas_id.clone());
+        let spacing = Duration::from_millis(self.config.latency_interval_ms);
         let mut latencies = Vec::with_capacity(num_packets);
-        let mut failed_count = 0;
 
         for i in 0..num_packets {
-            debug!("Latency measurement {}/{}", i + 1, num_packets);
+            if i > 0 && !spacing.is_zero() {
+                tokio::time::sleep(spacing).await;
+            }
+            self.wait_while_paused().await;
 
-            let operation_name =
-                format!("latency measurement {}/{}", i + 1, num_packets);
-            let result = retry_async(
+            let timing = measure_latency_probe(
+                &download,
                 &self.config.retry_config,
-                &operation_name,
-                || async {
-                    // Use small download (1000 bytes) to measure latency
-                    download
-                        .run(1000)
-                        .await
-                        .map_err(|e| std::io::Error::other(e.to_string()))
-                },
+                i,
+                num_packets,
             )
             .await;
 
-            match result {
-                RetryResult::Success(test_result) => {
-                    // Use TCP handshake time as latency measurement
-                    let latency_ms =
-                        test_result.tcp_duration.as_secs_f64() * 1000.0;
-                    latencies.push(latency_ms);
-                    debug!("Latency: {:.2} ms", latency_ms);
+            if let Some(timing) = timing {
+                state.record_probe(&timing);
+                latencies.push(timing.latency_ms);
+                if emit_events {
+                    self.emit_progress(ProgressEvent::LatencyMeasurement {
+                        value_ms: timing.latency_ms,
+                        current: i + 1,
+                        total: num_packets,
+                    });
+                }
+            }
+        }
+
+        latencies
+    }
 
-                    // Emit progress event if enabled
+    /// Send latency probes on a fixed tick of `latency_interval_ms`, like
+    /// ping. A slow probe does not delay the next one, so probes may be in
+    /// flight at the same time.
+    async fn run_latency_ticked(
+        &self,
+        num_packets: usize,
+        emit_events: bool,
+        state: &RunState,
+    ) -> Vec<f64> {
+        let period = Duration::from_millis(self.config.latency_interval_ms)
+            .max(Duration::from_millis(1));
+        let mut ticker = tokio::time::interval(period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let mut probes = JoinSet::new();
+        let mut results = Vec::with_capacity(num_packets);
+        let mut sent = 0;
+        let mut completed = 0;
+
+        while completed < num_packets {
+            tokio::select! {
+                _ = ticker.tick(), if sent < num_packets => {
+                    self.spawn_latency_probe(
+                        &mut probes,
+                        sent,
+                        num_packets,
+                        state,
+                    );
+                    sent += 1;
+                }
+                Some(joined) = probes.join_next() => {
+                    completed += 1;
+                    let Ok((index, Some(timing))) = joined else {
+                        continue;
+                    };
+                    state.record_probe(&timing);
+                    results.push((index, timing.latency_ms));
                     if emit_events {
                         self.emit_progress(
                             ProgressEvent::LatencyMeasurement {
-                                value_ms: latency_ms,
-                                current: i + 1,
+                                value_ms: timing.latency_ms,
+                                current: completed,
                                 total: num_packets,
                             },
                         );
                     }
                 }
-                RetryResult::Failed { last_error, attempts } => {
-                    failed_count += 1;
-                    warn!(
-                        "Latency measurement {}/{} failed after {} attempts: {}",
-                        i + 1, num_packets, attempts, last_error
-                    );
-                    // Continue with remaining measurements
-                }
             }
         }
 
-        if latencies.is_empty() {
-            return Err(format!(
-                "All {} latency measurements failed",
-                num_packets
-            )
-            .into());
-        }
+        // Jitter compares consecutive probes, so restore send order
+        results.sort_by_key(|&(index, _)| index);
+        results.into_iter().map(|(_, latency_ms)| latency_ms).collect()
+    }
 
-        if failed_count > 0 {
-            warn!(
-                "{} of {} latency measurements failed, continuing with {} successful",
-                failed_count, num_packets, latencies.len()
-            );
+    /// Send latency probes with up to `in_flight` outstanding at once,
+    /// at least `latency_interval_ms` apart.
+    ///
+    /// Probes sent together overlap, so their send order says little
+    /// about how latency moved from one to the next; latencies are kept
+    /// in the order probes arrived, which jitter is computed over.
+    async fn run_latency_parallel(
+        &self,
+        num_packets: usize,
+        in_flight: usize,
+        emit_events: bool,
+        state: &RunState,
+    ) -> Vec<f64> {
+        let in_flight = in_flight.max(1);
+        let gap = Duration::from_millis(self.config.latency_interval_ms);
+
+        let mut probes = JoinSet::new();
+        let mut latencies = Vec::with_capacity(num_packets);
+        let mut next_send = Instant::now();
+        let mut sent = 0;
+        let mut completed = 0;
+
+        while completed < num_packets {
+            let slot_free = sent < num_packets && probes.len() < in_flight;
+            tokio::select! {
+                _ = tokio::time::sleep_until(next_send), if slot_free => {
+                    self.wait_while_paused().await;
+                    self.spawn_latency_probe(
+                        &mut probes,
+                        sent,
+                        num_packets,
+                        state,
+                    );
+                    sent += 1;
+                    next_send = Instant::now() + gap;
+                }
+                Some(joined) = probes.join_next() => {
+                    completed += 1;
+                    let Ok((_, Some(timing))) = joined else {
+                        continue;
+                    };
+                    state.record_probe(&timing);
+                    latencies.push(timing.latency_ms);
+                    if emit_events {
+                        self.emit_progress(
+                            ProgressEvent::LatencyMeasurement {
+                                value_ms: timing.latency_ms,
+                                current: completed,
+                                total: num_packets,
+                            },
+                        );
+                    }
+                }
+            }
         }
 
-        Ok(latencies)
+        latencies
+    }
+
+    /// Start latency probe `index` of `num_packets` in `probes`, on its
+    /// own connection.
+    fn spawn_latency_probe(
+        &self,
+        probes: &mut JoinSet<(usize, Option<ProbeTiming>)>,
+        index: usize,
+        num_packets: usize,
+        state: &RunState,
+    ) {
+        let headers = self.config.headers.clone();
+        let retry_config = self.config.retry_config.clone();
+        let socket_options = self.probe_socket_options();
+        let meas_id = state.meas_id.clone();
+        probes.spawn(async move {
+            let download = Download::default()
+                .with_headers(headers)
+                .with_socket_options(socket_options)
+                .with_meas_id(meas_id);
+            let timing = measure_latency_probe(
+                &download,
+                &retry_config,
+                index,
+                num_packets,
+            )
+            .await;
+            (index, timing)
+        });
     }
 
-    /// Run a single download measurement with retry logic.
+    /// Run a single download measurement with retry logic, tagged with
+    /// `meas_id`.
     async fn run_download_single(
         &self,
         bytes: u64,
+        meas_id: Option<String>,
     ) -> Result<TestResults, Box<dyn Error>> {
-        let download = Download {};
+        let download = Download::default()
+            .with_headers(self.config.headers.clone())
+            .with_socket_options(self.config.socket_options)
+            .with_meas_id(meas_id);
         let operation_name = format!("download estimation ({}B)", bytes);
 
         let result = retry_async(
@@ -682,175 +2383,113 @@ impl TestEngine {
         }
     }
 
-    /// Run a single bandwidth block (one file size, multiple iterations).
-    ///
-    /// Returns the measurements and whether early termination was triggered.
-    /// Individual measurement failures are retried, and if all retries fail,
-    /// the measurement is skipped and the test continues with remaining iterations.
-    #[allow(dead_code)]
-    async fn run_bandwidth_block(
+    /// Drive `transfer` to completion, emitting a stall event each second
+    /// it goes without moving data past `stall_timeout`.
+    async fn watch_for_stalls<F: Future>(
         &self,
-        block: &DataBlock,
-        is_download: bool,
-        latency_direction: LatencyDirection,
-        loaded_latency_collector: &mut LoadedLatencyCollector,
-    ) -> Result<(Vec<BandwidthMeasurement>, bool), Box<dyn Error>> {
-        let mut measurements = Vec::with_capacity(block.count);
-        let mut triggered_early_termination = false;
-        let mut failed_count = 0;
-
-        // Create channel for loaded latency measurements
-        let (latency_tx, mut latency_rx) = mpsc::channel::<f64>(100);
-
-        let test_type = if is_download { "download" } else { "upload" };
-
-        for i in 0..block.count {
-            debug!(
-                "  Iteration {}/{} for {} bytes",
-                i + 1,
-                block.count,
-                block.bytes
-            );
-
-            let operation_name = format!(
-                "{} {}B iteration {}/{}",
-                test_type,
-                block.bytes,
-                i + 1,
-                block.count
-            );
-
-            let latency_tx_clone = latency_tx.clone();
-            let throttle_ms = self.config.loaded_latency_throttle_ms;
-            let min_duration_ms =
-                self.config.loaded_request_min_duration_ms as u64;
-            let bytes = block.bytes;
-
-            let result = if is_download {
-                retry_async(&self.config.retry_config, &operation_name, || {
-                    let latency_tx = latency_tx_clone.clone();
-                    async move {
-                        let download = Download {};
-                        download
-                            .run_with_loaded_latency(
-                                bytes,
-                                latency_tx,
-                                throttle_ms,
-                                min_duration_ms,
-                            )
-                            .await
-                            .map_err(|e| std::io::Error::other(e.to_string()))
+        direction: BandwidthDirection,
+        bytes: u64,
+        progress: &TransferProgress,
+        transfer: F,
+    ) -> F::Output {
+        let Some(stall_timeout) = self.config.stall_timeout else {
+            return transfer.await;
+        };
+        tokio::pin!(transfer);
+        let mut poll = tokio::time::interval(STALL_POLL_INTERVAL);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut reported_secs = 0;
+
+        loop {
+            tokio::select! {
+                output = &mut transfer => return output,
+                _ = poll.tick() => {
+                    let idle = progress.idle();
+                    if idle < stall_timeout {
+                        reported_secs = 0;
+                        continue;
                     }
-                })
-                .await
-            } else {
-                retry_async(&self.config.retry_config, &operation_name, || {
-                    let latency_tx = latency_tx_clone.clone();
-                    async move {
-                        let upload = Upload::new(bytes);
-                        upload
-                            .run_with_loaded_latency(
-                                latency_tx,
-                                throttle_ms,
-                                min_duration_ms,
-                            )
-                            .await
-                            .map_err(|e| std::io::Error::other(e.to_string()))
+                    let idle_secs = idle.as_secs();
+                    if idle_secs <= reported_secs {
+                        continue;
                     }
-                })
-                .await
-            };
-
-            match result {
-                RetryResult::Success(test_result) => {
-                    let measurement = test_result.to_bandwidth_measurement();
-                    let duration_ms = measurement.duration_ms;
-
-                    measurements.push(measurement);
-
-                    // Check for early termination
-                    if duration_ms >= self.config.bandwidth_finish_duration_ms
-                    {
-                        triggered_early_termination = true;
-                        debug!(
-                            "Duration {:.2}ms >= threshold {:.2}ms, triggering early termination",
-                            duration_ms, self.config.bandwidth_finish_duration_ms
+                    if reported_secs == 0 {
+                        warn!(
+                            "{:?} of {}B stalled: no data for {}s",
+                            direction, bytes, idle_secs
                         );
                     }
-                }
-                RetryResult::Failed { last_error, attempts } => {
-                    failed_count += 1;
-                    warn!(
-                        "{} failed after {} attempts: {}. Continuing with remaining iterations.",
-                        operation_name, attempts, last_error
-                    );
-                    // Continue with remaining iterations
+                    reported_secs = idle_secs;
+                    self.emit_progress(ProgressEvent::Stalled {
+                        direction,
+                        bytes,
+                        idle_secs,
+                    });
                 }
             }
         }
-
-        // Drop the sender to close the channel
-        drop(latency_tx);
-
-        // Collect loaded latency measurements from channel
-        while let Ok(latency_ms) = latency_rx.try_recv() {
-            // Get the duration of the most recent measurement
-            let request_duration_ms =
-                measurements.last().map(|m| m.duration_ms).unwrap_or(0.0);
-
-            loaded_latency_collector.add(
-                latency_direction,
-                latency_ms,
-                request_duration_ms,
-            );
-        }
-
-        if failed_count > 0 {
-            warn!(
-                "{} {}B: {} of {} measurements failed, {} successful",
-                test_type,
-                block.bytes,
-                failed_count,
-                block.count,
-                measurements.len()
-            );
-        }
-
-        Ok((measurements, triggered_early_termination))
     }
 
-    /// Run a single bandwidth block with progress event emission.
-    ///
-    /// Similar to `run_bandwidth_block` but emits progress events after each
-    /// successful measurement.
+    /// Run a single bandwidth block (one file size, multiple iterations),
+    /// emitting progress events after each successful measurement.
+    /// Failed iterations are retried, and skipped once retries run out.
     ///
     /// # Arguments
+    /// * `state` - State of the run the block is part of
     /// * `block` - The data block configuration
     /// * `is_download` - Whether this is a download test
     /// * `latency_direction` - Direction for loaded latency collection
     /// * `loaded_latency_collector` - Collector for loaded latency measurements
     /// * `measurement_count` - Running count of measurements (updated in place)
     /// * `total_measurements` - Total expected measurements for this direction
+    /// * `estimate_bps` - Bandwidth measured before this block, used to size
+    ///   upload writes until the block has measurements of its own
     ///
     /// # Returns
-    /// Tuple of (measurements, triggered_early_termination)
+    /// The block's measurements, kernel TCP statistics, effective socket
+    /// buffer sizes, retried iterations and the rule that ended it early
+    #[allow(clippy::too_many_arguments)]
     async fn run_bandwidth_block_with_progress(
         &self,
+        state: &RunState,
         block: &DataBlock,
         is_download: bool,
         latency_direction: LatencyDirection,
         loaded_latency_collector: &mut LoadedLatencyCollector,
         measurement_count: &mut usize,
         total_measurements: usize,
-    ) -> Result<(Vec<BandwidthMeasurement>, bool), Box<dyn Error>> {
+        estimate_bps: Option<f64>,
+    ) -> Result<BlockOutcome, Box<dyn Error>> {
         let mut measurements = Vec::with_capacity(block.count);
-        let mut triggered_early_termination = false;
+        let mut tcp_stats = Vec::new();
+        let mut socket_buffers = Vec::new();
+        let mut failures = Vec::new();
+        let mut edge_headers = Vec::new();
+        let mut redirects = 0;
+        let mut redirect_ms = 0.0;
+        let mut core_utilization = Vec::new();
+        let mut terminated_by = None;
         let mut failed_count = 0;
 
         // Create channel for loaded latency measurements
         let (latency_tx, mut latency_rx) = mpsc::channel::<f64>(100);
 
         let test_type = if is_download { "download" } else { "upload" };
+
+        // Saturate the link for the duration of the block; streams stop
+        // when `load` is dropped.
+        let load = LoadGenerator::start(
+            self.load_streams(),
+            is_download,
+            &self.config.headers,
+        );
+        if load.streams() > 0 {
+            debug!(
+                "Started {} background {} load streams",
+                load.streams(),
+                test_type
+            );
+        }
         let direction = if is_download {
             BandwidthDirection::Download
         } else {
@@ -858,6 +2497,11 @@ impl TestEngine {
         };
 
         for i in 0..block.count {
+            self.wait_while_paused().await;
+            self.pace(state).await;
+            self.wait_out_rate_limit(state).await;
+            let bytes = self.jitter(block.bytes).await;
+
             debug!(
                 "  Iteration {}/{} for {} bytes",
                 i + 1,
@@ -877,51 +2521,144 @@ impl TestEngine {
             let throttle_ms = self.config.loaded_latency_throttle_ms;
             let min_duration_ms =
                 self.config.loaded_request_min_duration_ms as u64;
-            let bytes = block.bytes;
-
-            let result = if is_download {
-                retry_async(&self.config.retry_config, &operation_name, || {
-                    let latency_tx = latency_tx_clone.clone();
-                    async move {
-                        let download = Download {};
-                        download
-                            .run_with_loaded_latency(
-                                bytes,
-                                latency_tx,
-                                throttle_ms,
-                                min_duration_ms,
-                            )
-                            .await
-                            .map_err(|e| std::io::Error::other(e.to_string()))
-                    }
-                })
-                .await
+            let headers = &self.config.headers;
+            let payload = self.config.upload_payload;
+            let socket_options = self.config.socket_options;
+            let detailed = self.config.detailed;
+            let chunk_size = self.config.upload_chunk_size.resolve(
+                aggregate_bandwidth(
+                    &measurements,
+                    self.config.bandwidth_percentile,
+                    self.config.bandwidth_min_duration_ms,
+                )
+                .or(estimate_bps),
+            );
+
+            let progress = TransferProgress::default();
+            let abort_after = self
+                .config
+                .stall_timeout
+                .filter(|_| self.config.abort_stalled);
+            let (result, errors) = if is_download {
+                let transfer = retry_async_recording(
+                    &self.config.retry_config,
+                    &operation_name,
+                    || {
+                        let latency_tx = latency_tx_clone.clone();
+                        let headers = headers.clone();
+                        let meas_id = state.meas_id.clone();
+                        let progress = progress.clone();
+                        progress.touch();
+                        async move {
+                            let download = Download::default()
+                                .with_headers(headers)
+                                .with_socket_options(socket_options)
+                                .with_progress(progress)
+                                .with_stall_timeout(abort_after)
+                                .with_meas_id(meas_id)
+                                .with_body_hash(detailed);
+                            download
+                                .run_with_loaded_latency(
+                                    bytes,
+                                    latency_tx,
+                                    throttle_ms,
+                                    min_duration_ms,
+                                )
+                                .await
+                                .map_err(transfer_error)
+                        }
+                    },
+                );
+                self.watch_for_stalls(direction, bytes, &progress, transfer)
+                    .await
             } else {
-                retry_async(&self.config.retry_config, &operation_name, || {
-                    let latency_tx = latency_tx_clone.clone();
-                    async move {
-                        let upload = Upload::new(bytes);
-                        upload
-                            .run_with_loaded_latency(
-                                latency_tx,
-                                throttle_ms,
-                                min_duration_ms,
-                            )
-                            .await
-                            .map_err(|e| std::io::Error::other(e.to_string()))
-                    }
-                })
-                .await
+                let transfer = retry_async_recording(
+                    &self.config.retry_config,
+                    &operation_name,
+                    || {
+                        let latency_tx = latency_tx_clone.clone();
+                        let headers = headers.clone();
+                        let meas_id = state.meas_id.clone();
+                        let progress = progress.clone();
+                        progress.touch();
+                        async move {
+                            let upload = Upload::with_pattern(bytes, payload)
+                                .with_headers(headers)
+                                .with_chunk_size(chunk_size)
+                                .with_socket_options(socket_options)
+                                .with_progress(progress)
+                                .with_stall_timeout(abort_after)
+                                .with_meas_id(meas_id);
+                            upload
+                                .run_with_loaded_latency(
+                                    latency_tx,
+                                    throttle_ms,
+                                    min_duration_ms,
+                                )
+                                .await
+                                .map_err(transfer_error)
+                        }
+                    },
+                );
+                self.watch_for_stalls(direction, bytes, &progress, transfer)
+                    .await
             };
 
+            state.record_rate_limits(
+                &self.config.retry_config,
+                &errors,
+                matches!(result, RetryResult::Success(_)),
+            );
+
+            if self.config.detailed && !errors.is_empty() {
+                let (attempts, recovered) = match &result {
+                    RetryResult::Success(_) => (errors.len() as u32 + 1, true),
+                    RetryResult::Failed { attempts, .. } => (*attempts, false),
+                };
+                failures.push(MeasurementFailure {
+                    iteration: i + 1,
+                    attempts,
+                    recovered,
+                    errors,
+                });
+            }
+
             match result {
                 RetryResult::Success(test_result) => {
                     let measurement = test_result.to_bandwidth_measurement();
                     let duration_ms = measurement.duration_ms;
                     let speed_mbps =
                         calculate_speed_mbps(measurement.bandwidth_bps);
+                    state.record_for_pacing(duration_ms);
 
                     measurements.push(measurement);
+                    tcp_stats.extend(test_result.tcp);
+                    socket_buffers.extend(test_result.socket_buffers);
+                    core_utilization.extend(test_result.core_utilization);
+                    if test_result.redirects > 0 {
+                        redirects += test_result.redirects;
+                        redirect_ms +=
+                            test_result.redirect_duration.as_secs_f64()
+                                * 1000.0;
+                        static WARN_REDIRECTED: Once = Once::new();
+                        WARN_REDIRECTED.call_once(|| {
+                            warn!(
+                                "Measurement requests are being redirected; \
+                                 redirect time is left out of the speeds"
+                            )
+                        });
+                    }
+                    if let Some(ref digest) = test_result.body_sha256 {
+                        debug!("  body sha256: {}", digest);
+                    }
+                    if let Some(edge) = test_result.edge {
+                        if let Some(ref ray) = edge.ray {
+                            debug!("  cf-ray: {}", ray);
+                        }
+                        if self.config.detailed {
+                            edge_headers.push(edge);
+                        }
+                    }
                     *measurement_count += 1;
 
                     // Emit progress event
@@ -936,7 +2673,7 @@ impl TestEngine {
                     // Check for early termination
                     if duration_ms >= self.config.bandwidth_finish_duration_ms
                     {
-                        triggered_early_termination = true;
+                        terminated_by = Some(TerminationRule::Duration);
                         debug!(
                             "Duration {:.2}ms >= threshold {:.2}ms, \
                              triggering early termination",
@@ -944,6 +2681,19 @@ impl TestEngine {
                             self.config.bandwidth_finish_duration_ms
                         );
                     }
+
+                    if self.has_converged(&measurements) {
+                        terminated_by
+                            .get_or_insert(TerminationRule::Convergence);
+                        debug!(
+                            "{} {}B converged after {} measurements, \
+                             skipping remaining iterations",
+                            test_type,
+                            block.bytes,
+                            measurements.len()
+                        );
+                        break;
+                    }
                 }
                 RetryResult::Failed { last_error, attempts } => {
                     failed_count += 1;
@@ -957,20 +2707,26 @@ impl TestEngine {
             }
         }
 
+        drop(load);
+
         // Drop the sender to close the channel
         drop(latency_tx);
 
-        // Collect loaded latency measurements from channel
+        // Collect loaded latency measurements from channel, keeping this
+        // block's own samples too
+        let mut loaded_latencies = Vec::new();
         while let Ok(latency_ms) = latency_rx.try_recv() {
             // Get the duration of the most recent measurement
             let request_duration_ms =
                 measurements.last().map(|m| m.duration_ms).unwrap_or(0.0);
 
-            loaded_latency_collector.add(
+            if loaded_latency_collector.add(
                 latency_direction,
                 latency_ms,
                 request_duration_ms,
-            );
+            ) {
+                loaded_latencies.push(latency_ms);
+            }
         }
 
         if failed_count > 0 {
@@ -984,7 +2740,88 @@ impl TestEngine {
             );
         }
 
-        Ok((measurements, triggered_early_termination))
+        Ok(BlockOutcome {
+            measurements,
+            tcp_stats,
+            socket_buffers,
+            failures,
+            edge_headers,
+            loaded_latencies,
+            redirects,
+            redirect_ms,
+            core_utilization,
+            terminated_by,
+        })
+    }
+}
+
+/// A transfer error as an I/O error the retry logic can hold, keeping a
+/// body mismatch intact for its diagnostic.
+fn transfer_error(error: Box<dyn Error>) -> std::io::Error {
+    match error.downcast::<BodyMismatch>() {
+        Ok(mismatch) => std::io::Error::other(*mismatch),
+        Err(error) => std::io::Error::other(error.to_string()),
+    }
+}
+
+/// Timings from one latency probe.
+#[derive(Debug, Clone, Copy)]
+struct ProbeTiming {
+    /// TCP handshake time in ms
+    latency_ms: f64,
+    /// Time to first byte in ms
+    ttfb_ms: f64,
+    /// Processing time the server reported in ms, if it did
+    server_ms: Option<f64>,
+}
+
+/// Measure a single latency probe with retry logic.
+///
+/// Uses the TCP handshake time of a small download. Returns `None` when
+/// every attempt fails.
+async fn measure_latency_probe(
+    download: &Download,
+    retry_config: &RetryConfig,
+    index: usize,
+    total: usize,
+) -> Option<ProbeTiming> {
+    debug!("Latency measurement {}/{}", index + 1, total);
+
+    let operation_name =
+        format!("latency measurement {}/{}", index + 1, total);
+    let result = retry_async(retry_config, &operation_name, || async {
+        // Use small download (1000 bytes) to measure latency
+        download
+            .run(1000)
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))
+    })
+    .await;
+
+    match result {
+        RetryResult::Success(test_result) => {
+            // Use TCP handshake time as latency measurement
+            let latency_ms = test_result.tcp_duration.as_secs_f64() * 1000.0;
+            debug!("Latency: {:.2} ms", latency_ms);
+            Some(ProbeTiming {
+                latency_ms,
+                ttfb_ms: test_result.ttfb_duration.as_secs_f64() * 1000.0,
+                // Zero when the Server-Timing header was missing
+                server_ms: Some(test_result.server_time)
+                    .filter(|server_time| !server_time.is_zero())
+                    .map(|server_time| server_time.as_secs_f64() * 1000.0),
+            })
+        }
+        RetryResult::Failed { last_error, attempts } => {
+            warn!(
+                "Latency measurement {}/{} failed after {} attempts: {}",
+                index + 1,
+                total,
+                attempts,
+                last_error
+            );
+            None
+        }
     }
 }
 
@@ -992,6 +2829,30 @@ impl TestEngine {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_ttfb_summary_from_samples() {
+        assert_eq!(TtfbSummary::from_samples(&[]), None);
+
+        let samples: Vec<f64> = (1..=20).map(f64::from).collect();
+        let summary = TtfbSummary::from_samples(&samples).unwrap();
+        assert_eq!(summary.samples, 20);
+        assert_eq!(summary.median_ms, 10.5);
+        assert!(summary.p95_ms > 19.0 && summary.p95_ms <= 20.0);
+        assert_eq!(summary.breakdown, None);
+    }
+
+    #[test]
+    fn test_rtt_breakdown_from_probes() {
+        assert_eq!(RttBreakdown::from_probes(&[]), None);
+
+        // A reported time above the TTFB counts as no network time
+        let probes = [(30.0, 10.0), (25.0, 4.0), (40.0, 12.0), (5.0, 8.0)];
+        let breakdown = RttBreakdown::from_probes(&probes).unwrap();
+        assert_eq!(breakdown.samples, 4);
+        assert_eq!(breakdown.network_median_ms, 20.5);
+        assert_eq!(breakdown.server_median_ms, 9.0);
+    }
+
     // Unit tests for TestConfig
     #[test]
     fn test_config_default() {
@@ -1002,6 +2863,13 @@ mod tests {
         assert!((config.bandwidth_min_duration_ms - 10.0).abs() < 0.001);
         assert!((config.loaded_request_min_duration_ms - 250.0).abs() < 0.001);
         assert!((config.bandwidth_percentile - 0.9).abs() < 0.001);
+        assert_eq!(config.convergence_cv_threshold, Some(0.05));
+        assert_eq!(config.convergence_window, 4);
+        assert_eq!(config.load_streams, 0);
+        assert_eq!(config.upload_payload, PayloadPattern::Random);
+        assert_eq!(config.latency_probe, LatencyProbe::Http);
+        assert_eq!(config.latency_schedule, LatencySchedule::Sequential);
+        assert_eq!(config.latency_interval_ms, 0);
         assert_eq!(config.download_sizes.len(), 5);
         assert_eq!(config.upload_sizes.len(), 5);
     }
@@ -1013,6 +2881,193 @@ mod tests {
         assert_eq!(block.count, 10);
     }
 
+    #[test]
+    fn test_data_block_parse() {
+        assert_eq!("100kB:10".parse(), Ok(DataBlock::new(100_000, 10)));
+        assert_eq!("25mb:4".parse(), Ok(DataBlock::new(25_000_000, 4)));
+        assert_eq!("1GB:1".parse(), Ok(DataBlock::new(1_000_000_000, 1)));
+        assert_eq!("512:3".parse(), Ok(DataBlock::new(512, 3)));
+        assert_eq!(" 2 MB : 5 ".parse(), Ok(DataBlock::new(2_000_000, 5)));
+
+        for invalid in ["1MB", "1TB:2", "0kB:2", "1MB:0", "MB:2", "1.5MB:2"] {
+            assert!(invalid.parse::<DataBlock>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_size_blocks_round_trip() {
+        let blocks: SizeBlocks =
+            "512:1,100kB:10, 1MB:8,1500kB:6,25MB:4".parse().unwrap();
+        assert_eq!(blocks.0.len(), 5);
+        assert_eq!(
+            blocks.to_string(),
+            "512B:1,100kB:10,1MB:8,1500kB:6,25MB:4"
+        );
+        assert_eq!(blocks.to_string().parse(), Ok(blocks.clone()));
+
+        let error = "1MB:8,100kB:10".parse::<SizeBlocks>().unwrap_err();
+        assert!(error.contains("1MB:8 comes before 100kB:10"), "{}", error);
+        assert!("1MB:8,1MB:2".parse::<SizeBlocks>().is_err());
+        assert!("".parse::<SizeBlocks>().is_err());
+    }
+
+    #[test]
+    fn test_pacing_pause_after() {
+        let pacing = Pacing::NICE;
+        // Short measurements get the minimum pause
+        assert_eq!(pacing.pause_after(20.0), Duration::from_millis(500));
+        // Longer ones are matched, leaving the link idle half the time
+        assert_eq!(pacing.pause_after(1500.0), Duration::from_millis(1500));
+
+        let pacing = Pacing { pause_ratio: 0.5, ..Pacing::NICE };
+        assert_eq!(pacing.pause_after(4000.0), Duration::from_millis(2000));
+    }
+
+    #[test]
+    fn test_pacing_jitter() {
+        let jitter = PacingJitter::DEFAULT;
+        assert_eq!(jitter.gap(0.0), Duration::ZERO);
+        assert_eq!(jitter.gap(0.5), Duration::from_millis(125));
+
+        assert_eq!(jitter.size(1_000_000, 0.0), 900_000);
+        assert_eq!(jitter.size(1_000_000, 0.5), 1_000_000);
+        assert_eq!(jitter.size(1_000_000, 0.75), 1_050_000);
+        assert_eq!(jitter.size(1, 0.0), 1);
+
+        for _ in 0..100 {
+            let unit = random_unit();
+            assert!((0.0..1.0).contains(&unit));
+        }
+    }
+
+    #[test]
+    fn test_pacing_caps_load_streams() {
+        let config = TestConfig { load_streams: 4, ..TestConfig::default() };
+        assert_eq!(TestEngine::new(config.clone(), None).load_streams(), 4);
+
+        let config = TestConfig { pacing: Some(Pacing::NICE), ..config };
+        assert_eq!(TestEngine::new(config, None).load_streams(), 0);
+    }
+
+    #[test]
+    fn test_pacing_interleaves_concurrent_order() {
+        let config = TestConfig {
+            order: TestOrder::Concurrent,
+            ..TestConfig::default()
+        };
+        assert_eq!(config.bandwidth_order(), TestOrder::Concurrent);
+
+        let config = TestConfig { pacing: Some(Pacing::NICE), ..config };
+        assert_eq!(config.bandwidth_order(), TestOrder::Interleaved);
+        let config = TestConfig { order: TestOrder::Sequential, ..config };
+        assert_eq!(config.bandwidth_order(), TestOrder::Sequential);
+    }
+
+    #[tokio::test]
+    async fn test_pace_records_pauses() {
+        let pacing = Pacing {
+            min_pause: Duration::from_millis(1),
+            pause_ratio: 0.001,
+            max_load_streams: 0,
+        };
+        let config =
+            TestConfig { pacing: Some(pacing), ..TestConfig::default() };
+        let engine = TestEngine::new(config, None);
+
+        let state = RunState::default();
+
+        // Nothing to pace before the first measurement
+        engine.pace(&state).await;
+        state.record_for_pacing(2000.0);
+        engine.pace(&state).await;
+        state.record_for_pacing(10.0);
+        engine.pace(&state).await;
+
+        let summary = state.pacing_summary();
+        assert_eq!(summary.pauses, 2);
+        assert_eq!(summary.paused, Duration::from_millis(3));
+    }
+
+    #[test]
+    fn test_rate_limits_delay_the_next_measurement() {
+        let config = RetryConfig::new(1, 100, 5000);
+        let state = RunState::default();
+        let limited = |attempt| FailedAttempt {
+            attempt,
+            kind: ErrorKind::RateLimited,
+            status: Some(429),
+            error: "HTTP 429 from speed test server (retry after 2s)".into(),
+            diagnostic: None,
+        };
+        assert_eq!(state.rate_limit_summary(), None);
+
+        // The retry waited, so the next measurement doesn't have to
+        state.record_rate_limits(&config, &[limited(1)], true);
+        assert_eq!(state.take_rate_limit_wait(), None);
+
+        // Every attempt was limited, so the next measurement waits
+        state.record_rate_limits(&config, &[limited(1), limited(2)], false);
+        let wait = state.take_rate_limit_wait().unwrap();
+        assert!(wait > Duration::from_secs(1));
+        assert!(wait <= Duration::from_secs(2));
+        assert_eq!(state.take_rate_limit_wait(), None);
+
+        let summary = state.rate_limit_summary().unwrap();
+        assert_eq!(summary.responses, 3);
+        assert_eq!(summary.waited, Duration::from_secs(4) + wait);
+    }
+
+    #[test]
+    fn test_engine_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<TestEngine>();
+    }
+
+    #[tokio::test]
+    async fn test_runs_keep_separate_state() {
+        let pacing = Pacing {
+            min_pause: Duration::from_millis(1),
+            pause_ratio: 0.001,
+            max_load_streams: 0,
+        };
+        let config =
+            TestConfig { pacing: Some(pacing), ..TestConfig::default() };
+        let engine = TestEngine::new(config, None);
+        let first = RunState::default();
+        let second = RunState::default();
+
+        first.record_for_pacing(2000.0);
+        first.record_ttfb(12.0);
+        tokio::join!(engine.pace(&first), engine.pace(&second));
+
+        assert_eq!(first.pacing_summary().pauses, 1);
+        assert_eq!(second.pacing_summary(), PacingSummary::default());
+        assert_eq!(first.ttfb_summary().map(|ttfb| ttfb.samples), Some(1));
+        assert!(second.ttfb_summary().is_none());
+    }
+
+    #[test]
+    fn test_run_state_probe_breakdown() {
+        let state = RunState::default();
+        state.record_probe(&ProbeTiming {
+            latency_ms: 10.0,
+            ttfb_ms: 30.0,
+            server_ms: Some(6.0),
+        });
+        state.record_probe(&ProbeTiming {
+            latency_ms: 10.0,
+            ttfb_ms: 50.0,
+            server_ms: None,
+        });
+
+        let ttfb = state.ttfb_summary().unwrap();
+        assert_eq!(ttfb.samples, 2);
+        let breakdown = ttfb.breakdown.unwrap();
+        assert_eq!(breakdown.samples, 1);
+        assert_eq!(breakdown.network_median_ms, 24.0);
+        assert_eq!(breakdown.server_median_ms, 6.0);
+    }
+
     // Unit tests for calculate_block_speed
     #[test]
     fn test_calculate_block_speed_empty() {
@@ -1051,6 +3106,76 @@ mod tests {
         assert!((speed - 10.0).abs() < 0.001);
     }
 
+    // Unit tests for has_converged
+    fn measurement(
+        bandwidth_bps: f64,
+        duration_ms: f64,
+    ) -> BandwidthMeasurement {
+        BandwidthMeasurement {
+            bytes: 1_000_000,
+            bandwidth_bps,
+            duration_ms,
+            server_time_ms: 1.0,
+            ttfb_ms: 5.0,
+        }
+    }
+
+    #[test]
+    fn test_has_converged_stable_measurements() {
+        let engine = TestEngine::new(TestConfig::default(), None);
+        let measurements: Vec<_> = [100.0, 101.0, 99.0, 100.5]
+            .iter()
+            .map(|mbps| measurement(mbps * 1_000_000.0, 80.0))
+            .collect();
+        assert!(engine.has_converged(&measurements));
+    }
+
+    #[test]
+    fn test_has_converged_needs_full_window() {
+        let engine = TestEngine::new(TestConfig::default(), None);
+        let measurements: Vec<_> = [100.0, 100.0, 100.0]
+            .iter()
+            .map(|mbps| measurement(mbps * 1_000_000.0, 80.0))
+            .collect();
+        assert!(!engine.has_converged(&measurements));
+    }
+
+    #[test]
+    fn test_has_converged_unstable_measurements() {
+        let engine = TestEngine::new(TestConfig::default(), None);
+        let measurements: Vec<_> = [50.0, 100.0, 150.0, 100.0]
+            .iter()
+            .map(|mbps| measurement(mbps * 1_000_000.0, 80.0))
+            .collect();
+        assert!(!engine.has_converged(&measurements));
+    }
+
+    #[test]
+    fn test_has_converged_ignores_short_measurements() {
+        let engine = TestEngine::new(TestConfig::default(), None);
+        let mut measurements: Vec<_> = [100.0, 100.0, 100.0]
+            .iter()
+            .map(|mbps| measurement(mbps * 1_000_000.0, 80.0))
+            .collect();
+        // Below bandwidth_min_duration_ms, so it doesn't fill the window
+        measurements.push(measurement(100_000_000.0, 5.0));
+        assert!(!engine.has_converged(&measurements));
+    }
+
+    #[test]
+    fn test_has_converged_disabled() {
+        let config = TestConfig {
+            convergence_cv_threshold: None,
+            ..TestConfig::default()
+        };
+        let engine = TestEngine::new(config, None);
+        let measurements: Vec<_> = [100.0, 100.0, 100.0, 100.0]
+            .iter()
+            .map(|mbps| measurement(mbps * 1_000_000.0, 80.0))
+            .collect();
+        assert!(!engine.has_converged(&measurements));
+    }
+
     // Property-based tests for progress event emission
     // Feature: tui-progress-display, Property 12: Progress Event Emission
     // Validates: Requirements 9.2, 9.3, 9.4
@@ -1234,20 +3359,20 @@ mod tests {
                 .collect();
 
             // Verify monotonically increasing current values
-            for i in 0..latency_events.len() {
+            for (i, &(current, total)) in latency_events.iter().enumerate() {
                 prop_assert_eq!(
-                    latency_events[i].0,
+                    current,
                     i + 1,
                     "Current value should be {} but was {}",
                     i + 1,
-                    latency_events[i].0
+                    current
                 );
                 prop_assert_eq!(
-                    latency_events[i].1,
+                    total,
                     num_measurements,
                     "Total should be {} but was {}",
                     num_measurements,
-                    latency_events[i].1
+                    total
                 );
             }
         }
@@ -1307,13 +3432,13 @@ mod tests {
                 })
                 .collect();
 
-            for i in 0..download_events.len() {
+            for (i, &(current, _)) in download_events.iter().enumerate() {
                 prop_assert_eq!(
-                    download_events[i].0,
+                    current,
                     i + 1,
                     "Download current should be {} but was {}",
                     i + 1,
-                    download_events[i].0
+                    current
                 );
             }
 
@@ -1335,13 +3460,13 @@ mod tests {
                 })
                 .collect();
 
-            for i in 0..upload_events.len() {
+            for (i, &(current, _)) in upload_events.iter().enumerate() {
                 prop_assert_eq!(
-                    upload_events[i].0,
+                    current,
                     i + 1,
                     "Upload current should be {} but was {}",
                     i + 1,
-                    upload_events[i].0
+                    current
                 );
             }
         }
@@ -1368,6 +3493,132 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_test_order_from_str() {
+        for order in [
+            TestOrder::Sequential,
+            TestOrder::Interleaved,
+            TestOrder::Concurrent,
+        ] {
+            assert_eq!(order.to_string().parse::<TestOrder>(), Ok(order));
+        }
+        assert_eq!("Concurrent".parse(), Ok(TestOrder::Concurrent));
+        assert!("parallel".parse::<TestOrder>().is_err());
+    }
+
+    #[test]
+    fn test_phase_selection_directions() {
+        assert!(PhaseSelection::All.downloads());
+        assert!(PhaseSelection::All.uploads());
+        assert!(!PhaseSelection::Latency.downloads());
+        assert!(!PhaseSelection::Latency.uploads());
+        assert!(PhaseSelection::Download.downloads());
+        assert!(!PhaseSelection::Download.uploads());
+        assert!(!PhaseSelection::Upload.downloads());
+        assert!(PhaseSelection::Upload.uploads());
+        assert_eq!(TestConfig::default().phases, PhaseSelection::All);
+    }
+
+    #[test]
+    fn test_complete_phase_emits_once() {
+        let callback = Arc::new(TestProgressCallback::new());
+        let engine =
+            TestEngine::new(TestConfig::default(), Some(callback.clone()));
+        let blocks = [DataBlock::new(100_000, 2)];
+        let mut run = DirectionRun::new(&blocks, false);
+        assert_eq!(run.total_measurements, 2);
+
+        // Never started, so never completed
+        engine.complete_phase(&mut run);
+        assert!(callback.events().is_empty());
+
+        run.phase_started = true;
+        engine.complete_phase(&mut run);
+        engine.complete_phase(&mut run);
+        let events = callback.events();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            ProgressEvent::PhaseComplete(TestPhase::Upload)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_run_size_block_skips_terminated_direction() {
+        let callback = Arc::new(TestProgressCallback::new());
+        let engine =
+            TestEngine::new(TestConfig::default(), Some(callback.clone()));
+        let blocks = [DataBlock::new(100_000, 2)];
+        let mut run = DirectionRun::new(&blocks, true);
+        run.early_terminated = true;
+
+        engine
+            .run_size_block(&RunState::default(), &mut run, 0, None)
+            .await
+            .unwrap();
+        // Past the last block
+        run.early_terminated = false;
+        engine
+            .run_size_block(&RunState::default(), &mut run, 1, None)
+            .await
+            .unwrap();
+
+        assert!(!run.phase_started);
+        assert!(run.size_results.is_empty());
+        assert!(callback.events().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_wait_while_paused() {
+        let callback = Arc::new(TestProgressCallback::new());
+        let pause = PauseFlag::default();
+        let engine =
+            TestEngine::new(TestConfig::default(), Some(callback.clone()))
+                .with_pause(pause.clone());
+
+        // Not paused: no wait and no events
+        engine.wait_while_paused().await;
+        assert!(callback.events().is_empty());
+
+        assert!(pause.toggle());
+        tokio::join!(engine.wait_while_paused(), async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert!(!pause.toggle());
+        });
+
+        let events = callback.events();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], ProgressEvent::Paused));
+        assert!(matches!(events[1], ProgressEvent::Resumed));
+    }
+
+    #[tokio::test]
+    async fn test_run_bandwidth_tests_without_blocks() {
+        for order in [
+            TestOrder::Sequential,
+            TestOrder::Interleaved,
+            TestOrder::Concurrent,
+        ] {
+            let config = TestConfig {
+                download_sizes: Vec::new(),
+                upload_sizes: Vec::new(),
+                order,
+                ..TestConfig::default()
+            };
+            let engine = TestEngine::new(config, None);
+            let (download, upload) = engine
+                .run_bandwidth_tests(&RunState::default())
+                .await
+                .unwrap();
+            let download = engine.bandwidth_results(download);
+            let upload = engine.bandwidth_results(upload);
+            assert_eq!(download.speed_mbps, 0.0);
+            assert_eq!(download.payload, None);
+            assert!(upload.measurements.is_empty());
+            assert_eq!(upload.payload, Some(PayloadPattern::Random));
+        }
+    }
+
     // Unit tests for progress event emission helpers
     #[test]
     fn test_count_phase_changes() {
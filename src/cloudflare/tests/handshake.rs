@@ -0,0 +1,211 @@
+//! Connection setup with cold and warm caches.
+//!
+//! The first connection to a host pays for a DNS lookup, a TCP handshake
+//! and a full TLS handshake with certificate validation. Later ones can
+//! answer the lookup from cache and resume the TLS session, skipping a
+//! round trip and the certificate work. One cold setup and a few warm
+//! ones are timed stage by stage, each on its own connection, to show how
+//! much the handshakes cost on the path.
+//!
+//! "Cold" is cold for cloud-speed only: the operating system's or the
+//! network's resolvers may still answer the first lookup from their own
+//! caches.
+
+use crate::cloudflare::tests::connection::{
+    session_store, tcp_connect, tls_handshake_with, HostResolver,
+    SocketOptions,
+};
+use crate::cloudflare::tests::download::build_http_header;
+use crate::cloudflare::tests::response::{read_body, read_head};
+use crate::cloudflare::tests::sink::DiscardSink;
+use crate::cloudflare::tests::stall::TransferProgress;
+use http::HeaderMap;
+use log::debug;
+use rustls_connector::rustls::client::ClientSessionMemoryCache;
+use std::error::Error;
+use std::io::{Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+
+/// Warm setups timed after the cold one.
+const WARM_ROUNDS: usize = 3;
+
+/// Time each stage of one connection setup took.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetupTiming {
+    /// DNS lookup
+    pub dns: Duration,
+    /// TCP handshake
+    pub tcp: Duration,
+    /// TLS handshake, including certificate validation when it wasn't
+    /// resumed
+    pub tls: Duration,
+    /// Whether the TLS session was resumed
+    pub resumed: bool,
+}
+
+impl SetupTiming {
+    /// Time from starting the lookup to a usable TLS connection.
+    pub fn total(&self) -> Duration {
+        self.dns + self.tcp + self.tls
+    }
+}
+
+/// A cold connection setup and the warm ones after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandshakeComparison {
+    /// Setup with an empty DNS cache and no TLS session to resume
+    pub cold: SetupTiming,
+    /// Median of each stage across the warm setups; `resumed` only if
+    /// every one of them resumed
+    pub warm: SetupTiming,
+    /// Warm setups that completed
+    pub warm_rounds: usize,
+}
+
+impl HandshakeComparison {
+    /// Time a warm setup saved over the cold one.
+    pub fn saved(&self) -> Duration {
+        self.cold.total().saturating_sub(self.warm.total())
+    }
+}
+
+/// Time a cold connection setup for `url` and then a few warm ones.
+///
+/// Every setup finishes with a request for `url`, so the server sends
+/// the session tickets the next setup resumes from.
+pub async fn compare(
+    url: &Url,
+    headers: &HeaderMap,
+    options: SocketOptions,
+) -> Result<HandshakeComparison, Box<dyn Error>> {
+    let resolver = HostResolver::new()?;
+    let store = session_store();
+
+    let cold = setup(&resolver, &store, url, headers, options).await?;
+    debug!("Cold setup: {:?}", cold);
+
+    let mut warm = Vec::with_capacity(WARM_ROUNDS);
+    for round in 1..=WARM_ROUNDS {
+        match setup(&resolver, &store, url, headers, options).await {
+            Ok(timing) => {
+                debug!("Warm setup {}: {:?}", round, timing);
+                warm.push(timing);
+            }
+            Err(e) => debug!("Warm setup {} failed: {}", round, e),
+        }
+    }
+
+    Ok(HandshakeComparison {
+        cold,
+        warm: median(&warm).ok_or("No warm connection setup completed")?,
+        warm_rounds: warm.len(),
+    })
+}
+
+/// Set up one connection for `url` and make its request.
+async fn setup(
+    resolver: &HostResolver,
+    store: &Arc<ClientSessionMemoryCache>,
+    url: &Url,
+    headers: &HeaderMap,
+    options: SocketOptions,
+) -> Result<SetupTiming, Box<dyn Error>> {
+    let host = url.host_str().ok_or("URL has no host")?;
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let lookup =
+        resolver.lookup(host).await.map_err(|e| e as Box<dyn Error>)?;
+    let (tcp, tcp_duration) =
+        tcp_connect(lookup.preferred(), port, options).await?;
+    let (mut stream, tls) =
+        tls_handshake_with(tcp, host.to_string(), Some(store.clone())).await?;
+
+    let request = build_http_header(url, headers);
+    tokio::task::spawn_blocking(move || {
+        stream.write_all(request.as_bytes())?;
+        let mut first_byte = [0_u8];
+        stream.read_exact(&mut first_byte)?;
+        let headers = read_head(&mut stream, first_byte[0])?;
+        read_body(
+            &mut stream,
+            &headers,
+            None,
+            &mut DiscardSink::new(),
+            &TransferProgress::default(),
+        )?;
+        Ok::<_, Box<dyn Error + Send + Sync>>(())
+    })
+    .await?
+    .map_err(|e| e as Box<dyn Error>)?;
+
+    Ok(SetupTiming {
+        dns: lookup.duration,
+        tcp: tcp_duration,
+        tls: tls.handshake_duration,
+        resumed: tls.resumed,
+    })
+}
+
+/// Median of each stage across `setups`, or `None` if there are none.
+fn median(setups: &[SetupTiming]) -> Option<SetupTiming> {
+    let stage = |time: fn(&SetupTiming) -> Duration| {
+        let mut times: Vec<_> = setups.iter().map(time).collect();
+        times.sort();
+        times.get(times.len() / 2).copied()
+    };
+
+    Some(SetupTiming {
+        dns: stage(|setup| setup.dns)?,
+        tcp: stage(|setup| setup.tcp)?,
+        tls: stage(|setup| setup.tls)?,
+        resumed: setups.iter().all(|setup| setup.resumed),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(dns_ms: u64, tcp_ms: u64, tls_ms: u64) -> SetupTiming {
+        SetupTiming {
+            dns: Duration::from_millis(dns_ms),
+            tcp: Duration::from_millis(tcp_ms),
+            tls: Duration::from_millis(tls_ms),
+            resumed: true,
+        }
+    }
+
+    #[test]
+    fn test_median_takes_each_stage_separately() {
+        let warm = median(&[
+            timing(1, 14, 30),
+            timing(0, 40, 12),
+            SetupTiming { resumed: false, ..timing(2, 15, 13) },
+        ])
+        .unwrap();
+        assert_eq!(warm.dns, Duration::from_millis(1));
+        assert_eq!(warm.tcp, Duration::from_millis(15));
+        assert_eq!(warm.tls, Duration::from_millis(13));
+        assert!(!warm.resumed);
+
+        assert_eq!(median(&[]), None);
+    }
+
+    #[test]
+    fn test_saved() {
+        let comparison = HandshakeComparison {
+            cold: SetupTiming { resumed: false, ..timing(20, 15, 45) },
+            warm: timing(0, 15, 15),
+            warm_rounds: 3,
+        };
+        assert_eq!(comparison.cold.total(), Duration::from_millis(80));
+        assert_eq!(comparison.saved(), Duration::from_millis(50));
+
+        // A slow warm setup saves nothing rather than a negative amount
+        let comparison =
+            HandshakeComparison { warm: timing(0, 90, 15), ..comparison };
+        assert_eq!(comparison.saved(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,361 @@
+//! Dual-stack connection racing ("Happy Eyeballs", RFC 8305).
+//!
+//! When the endpoint resolves to both IPv6 and IPv4 addresses, setup
+//! races a TCP connection to each: IPv6 starts first and IPv4 joins after
+//! a short head start, or as soon as IPv6 fails. The first to connect
+//! wins and the endpoint is pinned to it. The loser still gets to finish,
+//! so a family that is merely slower can be told apart from one that
+//! doesn't connect at all. A few rounds are raced, so one unlucky
+//! handshake doesn't decide the family or trigger an advisory.
+
+use crate::cloudflare::tests::connection::{
+    tcp_connect_within, DnsLookup, SocketOptions,
+};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+/// Head start IPv6 gets before IPv4 joins the race (RFC 8305 section 5
+/// recommends 250 ms).
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// How long each attempt may take before its family counts as failed.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Races run during setup.
+const ROUNDS: usize = 3;
+
+/// How much longer IPv6 has to take than IPv4 to count as slower.
+const SLOWER_MARGIN: Duration = Duration::from_millis(10);
+
+/// Outcome of one race between two addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Race {
+    /// Address that connected first
+    pub winner: SocketAddr,
+    /// Time the winner took to connect
+    pub winner_connect: Duration,
+    /// Address that connected second, or not at all
+    pub loser: SocketAddr,
+    /// Time the loser took to connect, or `None` if it failed or timed
+    /// out
+    pub loser_connect: Option<Duration>,
+}
+
+impl Race {
+    /// Time the attempt of the given family took, or `None` if it
+    /// failed.
+    fn connect_time(&self, ipv6: bool) -> Option<Duration> {
+        if self.winner.is_ipv6() == ipv6 {
+            Some(self.winner_connect)
+        } else {
+            self.loser_connect
+        }
+    }
+}
+
+/// What the races say about IPv6 compared with IPv4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Advisory {
+    /// IPv6 didn't connect in any race
+    Ipv6Broken,
+    /// IPv6 connected more slowly than IPv4 in every race
+    Ipv6Slower,
+}
+
+/// Races between the endpoint's first IPv6 and first IPv4 address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DualStack {
+    /// Completed races; rounds where neither family connected are left
+    /// out
+    pub races: Vec<Race>,
+}
+
+impl DualStack {
+    /// The address that won the most races, IPv6 on a tie.
+    ///
+    /// # Panics
+    /// If no race completed.
+    pub fn winner(&self) -> IpAddr {
+        let ipv6_wins = self.ipv6_wins();
+        let wanted_ipv6 = ipv6_wins * 2 >= self.races.len();
+        self.races
+            .iter()
+            .map(|race| race.winner.ip())
+            .find(|ip| ip.is_ipv6() == wanted_ipv6)
+            .unwrap_or_else(|| self.races[0].winner.ip())
+    }
+
+    /// Races IPv6 won.
+    pub fn ipv6_wins(&self) -> usize {
+        self.races.iter().filter(|race| race.winner.is_ipv6()).count()
+    }
+
+    /// Races in which the given family didn't connect.
+    pub fn failures(&self, ipv6: bool) -> usize {
+        self.races
+            .iter()
+            .filter(|race| race.connect_time(ipv6).is_none())
+            .count()
+    }
+
+    /// Median connect time of the given family, when it connected at
+    /// least once.
+    pub fn median_connect(&self, ipv6: bool) -> Option<Duration> {
+        let mut times: Vec<_> = self
+            .races
+            .iter()
+            .filter_map(|race| race.connect_time(ipv6))
+            .collect();
+        times.sort();
+        times.get(times.len() / 2).copied()
+    }
+
+    /// Whether IPv6 was consistently broken or slower than IPv4.
+    pub fn advisory(&self) -> Option<Advisory> {
+        if self.races.is_empty() {
+            return None;
+        }
+        if self.failures(true) == self.races.len() {
+            return Some(Advisory::Ipv6Broken);
+        }
+
+        let slower = self.races.iter().all(|race| {
+            match (race.connect_time(true), race.connect_time(false)) {
+                (Some(ipv6), Some(ipv4)) => ipv6 > ipv4 + SLOWER_MARGIN,
+                (None, Some(_)) => true,
+                _ => false,
+            }
+        });
+        slower.then_some(Advisory::Ipv6Slower)
+    }
+}
+
+/// Race the first IPv6 and first IPv4 address of `lookup` on `port` a
+/// few times.
+///
+/// Returns `None` if the lookup didn't return both families, or if
+/// neither family connected in any round.
+pub async fn run(
+    lookup: &DnsLookup,
+    port: u16,
+    options: SocketOptions,
+) -> Option<DualStack> {
+    let ipv6 = lookup.addresses.iter().find(|ip| ip.is_ipv6())?;
+    let ipv4 = lookup.addresses.iter().find(|ip| ip.is_ipv4())?;
+    let (ipv6, ipv4) =
+        (SocketAddr::new(*ipv6, port), SocketAddr::new(*ipv4, port));
+
+    let mut races = Vec::with_capacity(ROUNDS);
+    for round in 1..=ROUNDS {
+        match race(ipv6, ipv4, options, CONNECTION_ATTEMPT_DELAY).await {
+            Ok(race) => {
+                debug!(
+                    "Race {}: {} won in {:?}, {} took {:?}",
+                    round,
+                    race.winner,
+                    race.winner_connect,
+                    race.loser,
+                    race.loser_connect
+                );
+                races.push(race);
+            }
+            Err(e) => debug!("Race {}: {}", round, e),
+        }
+    }
+
+    (!races.is_empty()).then_some(DualStack { races })
+}
+
+/// Race a connection to `first` against one to `second`, which starts
+/// after `delay` or as soon as `first` fails.
+pub async fn race(
+    first: SocketAddr,
+    second: SocketAddr,
+    options: SocketOptions,
+    delay: Duration,
+) -> Result<Race, Box<dyn Error>> {
+    let start = Instant::now();
+    let first_failed = Notify::new();
+
+    let first_attempt = async {
+        let connect = attempt(first, options).await;
+        if connect.is_none() {
+            first_failed.notify_one();
+        }
+        connect.map(|connect| (connect, start.elapsed()))
+    };
+    let second_attempt = async {
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = first_failed.notified() => {}
+        }
+        attempt(second, options)
+            .await
+            .map(|connect| (connect, start.elapsed()))
+    };
+    let (first_result, second_result) =
+        tokio::join!(first_attempt, second_attempt);
+
+    match (first_result, second_result) {
+        (Some((first_connect, first_done)), Some((second_connect, done)))
+            if done < first_done =>
+        {
+            Ok(Race {
+                winner: second,
+                winner_connect: second_connect,
+                loser: first,
+                loser_connect: Some(first_connect),
+            })
+        }
+        (Some((first_connect, _)), second_result) => Ok(Race {
+            winner: first,
+            winner_connect: first_connect,
+            loser: second,
+            loser_connect: second_result.map(|(connect, _)| connect),
+        }),
+        (None, Some((second_connect, _))) => Ok(Race {
+            winner: second,
+            winner_connect: second_connect,
+            loser: first,
+            loser_connect: None,
+        }),
+        (None, None) => {
+            Err(format!("Neither {} nor {} connected", first, second).into())
+        }
+    }
+}
+
+/// Connect to `address`, returning the connect time or `None` on failure.
+async fn attempt(
+    address: SocketAddr,
+    options: SocketOptions,
+) -> Option<Duration> {
+    match tcp_connect_within(address, options, Some(ATTEMPT_TIMEOUT)).await {
+        Ok((_, connect)) => Some(connect),
+        Err(e) => {
+            debug!("Connecting to {} failed: {}", address, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    fn race_of(ipv6_ms: Option<u64>, ipv4_ms: Option<u64>) -> Race {
+        let (ipv6, ipv4) = (addr("[2606:4700::1]:443"), addr("1.1.1.1:443"));
+        let ms = |ms: Option<u64>| ms.map(Duration::from_millis);
+        // IPv4 starts 250 ms behind
+        let ipv6_wins = match (ipv6_ms, ipv4_ms) {
+            (Some(v6), Some(v4)) => v6 <= v4 + 250,
+            (v6, _) => v6.is_some(),
+        };
+        if ipv6_wins {
+            Race {
+                winner: ipv6,
+                winner_connect: ms(ipv6_ms).unwrap(),
+                loser: ipv4,
+                loser_connect: ms(ipv4_ms),
+            }
+        } else {
+            Race {
+                winner: ipv4,
+                winner_connect: ms(ipv4_ms).unwrap(),
+                loser: ipv6,
+                loser_connect: ms(ipv6_ms),
+            }
+        }
+    }
+
+    #[test]
+    fn test_advisory() {
+        let healthy =
+            DualStack { races: vec![race_of(Some(12), Some(14)); 3] };
+        assert_eq!(healthy.advisory(), None);
+        assert!(healthy.winner().is_ipv6());
+        assert_eq!(
+            healthy.median_connect(false),
+            Some(Duration::from_millis(14))
+        );
+
+        let broken = DualStack { races: vec![race_of(None, Some(14)); 3] };
+        assert_eq!(broken.advisory(), Some(Advisory::Ipv6Broken));
+        assert!(broken.winner().is_ipv4());
+        assert_eq!(broken.failures(true), 3);
+        assert_eq!(broken.median_connect(true), None);
+
+        // Slower in every race, even when the head start let it win
+        let slower = DualStack {
+            races: vec![
+                race_of(Some(80), Some(14)),
+                race_of(Some(400), Some(15)),
+                race_of(None, Some(14)),
+            ],
+        };
+        assert_eq!(slower.advisory(), Some(Advisory::Ipv6Slower));
+
+        // One slow race isn't consistent
+        let mixed = DualStack {
+            races: vec![
+                race_of(Some(80), Some(14)),
+                race_of(Some(12), Some(14)),
+                race_of(Some(13), Some(14)),
+            ],
+        };
+        assert_eq!(mixed.advisory(), None);
+    }
+
+    #[tokio::test]
+    async fn test_race_prefers_first_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let race = race(
+            address,
+            address,
+            SocketOptions::default(),
+            Duration::from_millis(250),
+        )
+        .await
+        .unwrap();
+        assert_eq!(race.winner, address);
+        assert!(race.loser_connect.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_race_falls_back_when_first_fails() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let closed = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+
+        let begin = Instant::now();
+        let race = race(
+            closed,
+            address,
+            SocketOptions::default(),
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        assert_eq!(race.winner, address);
+        assert_eq!(race.loser, closed);
+        assert_eq!(race.loser_connect, None);
+        // The second attempt started on the failure, not after the delay
+        assert!(begin.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,340 @@
+//! ICMP echo ("ping") latency probing.
+//!
+//! HTTP probes include TCP (and on the first request TLS) overhead, so
+//! they read higher than the network round trip. ICMP echo measures the
+//! round trip directly, but needs either an unprivileged ping socket
+//! (Linux `net.ipv4.ping_group_range`, macOS) or a raw socket
+//! (`CAP_NET_RAW` / root). [`IcmpPinger::open`] tries both in that order;
+//! callers fall back to HTTP probing when neither is available.
+
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// ICMPv4 echo request type.
+const ICMPV4_ECHO_REQUEST: u8 = 8;
+/// ICMPv4 echo reply type.
+const ICMPV4_ECHO_REPLY: u8 = 0;
+/// ICMPv6 echo request type.
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+/// ICMPv6 echo reply type.
+const ICMPV6_ECHO_REPLY: u8 = 129;
+
+/// Bytes of payload carried by each echo request, as ping sends by default.
+const PAYLOAD_BYTES: usize = 56;
+
+/// Length of the ICMP echo header (type, code, checksum, ident, sequence).
+pub(crate) const HEADER_BYTES: usize = 8;
+
+/// How an ICMP socket was opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketKind {
+    /// Unprivileged datagram ping socket; the kernel owns the identifier
+    Datagram,
+    /// Raw socket; IPv4 replies include the IP header
+    Raw,
+}
+
+/// A socket that sends ICMP echo requests to a single host.
+pub struct IcmpPinger {
+    socket: Socket,
+    kind: SocketKind,
+    ipv6: bool,
+    ident: u16,
+}
+
+impl IcmpPinger {
+    /// Open an ICMP socket connected to `target`.
+    ///
+    /// Tries an unprivileged datagram socket first, then a raw socket.
+    /// Fails when the process may open neither.
+    pub fn open(target: IpAddr) -> io::Result<Self> {
+        let (domain, protocol) = match target {
+            IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+            IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+        };
+
+        let (socket, kind) =
+            match Socket::new(domain, Type::DGRAM, Some(protocol)) {
+                Ok(socket) => (socket, SocketKind::Datagram),
+                Err(dgram_err) => {
+                    match Socket::new(domain, Type::RAW, Some(protocol)) {
+                        Ok(socket) => (socket, SocketKind::Raw),
+                        Err(raw_err) => {
+                            return Err(io::Error::new(
+                                raw_err.kind(),
+                                format!(
+                                    "no ICMP socket available (datagram: \
+                                     {}; raw: {})",
+                                    dgram_err, raw_err
+                                ),
+                            ))
+                        }
+                    }
+                }
+            };
+
+        socket.connect(&SockAddr::from(SocketAddr::new(target, 0)))?;
+
+        Ok(Self {
+            socket,
+            kind,
+            ipv6: target.is_ipv6(),
+            ident: std::process::id() as u16,
+        })
+    }
+
+    /// How the socket was opened.
+    pub fn kind(&self) -> SocketKind {
+        self.kind
+    }
+
+    /// Whether the socket sends to an IPv6 target.
+    pub fn is_ipv6(&self) -> bool {
+        self.ipv6
+    }
+
+    /// Set the don't-fragment bit on outgoing requests, ignoring any path
+    /// MTU the kernel has cached, so oversized requests are dropped (or
+    /// refused locally) instead of fragmented.
+    #[cfg(target_os = "linux")]
+    pub fn set_dont_fragment(&self) -> io::Result<()> {
+        use std::os::fd::AsRawFd;
+
+        let (level, name, value) = if self.ipv6 {
+            (
+                libc::IPPROTO_IPV6,
+                libc::IPV6_MTU_DISCOVER,
+                libc::IPV6_PMTUDISC_PROBE,
+            )
+        } else {
+            (libc::IPPROTO_IP, libc::IP_MTU_DISCOVER, libc::IP_PMTUDISC_PROBE)
+        };
+
+        // SAFETY: `value` is a c_int and its exact size is passed
+        let ret = unsafe {
+            libc::setsockopt(
+                self.socket.as_raw_fd(),
+                level,
+                name,
+                (&value as *const libc::c_int).cast(),
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Set the don't-fragment bit on outgoing requests.
+    ///
+    /// Only supported on Linux.
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_dont_fragment(&self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "setting don't-fragment is only supported on Linux",
+        ))
+    }
+
+    /// Send one echo request and wait up to `timeout` for its reply.
+    ///
+    /// This blocks the calling thread; run it via `spawn_blocking`.
+    ///
+    /// # Returns
+    /// The round-trip time.
+    pub fn ping(
+        &self,
+        sequence: u16,
+        timeout: Duration,
+    ) -> io::Result<Duration> {
+        self.ping_sized(sequence, PAYLOAD_BYTES, timeout)
+    }
+
+    /// Like [`IcmpPinger::ping`], carrying `payload_bytes` of payload.
+    pub fn ping_sized(
+        &self,
+        sequence: u16,
+        payload_bytes: usize,
+        timeout: Duration,
+    ) -> io::Result<Duration> {
+        let request =
+            echo_request(self.ipv6, self.ident, sequence, payload_bytes);
+        let begin = Instant::now();
+        self.socket.send(&request)?;
+
+        // Room for the reply plus the largest IPv4 header
+        let mut buf = vec![0u8; request.len() + 60];
+        loop {
+            let remaining = timeout.saturating_sub(begin.elapsed());
+            if remaining.is_zero() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("no echo reply for sequence {}", sequence),
+                ));
+            }
+            self.socket.set_read_timeout(Some(remaining))?;
+
+            let len = match (&self.socket).read(&mut buf) {
+                Ok(len) => len,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e),
+            };
+            let rtt = begin.elapsed();
+
+            if self.is_reply(&buf[..len], sequence) {
+                return Ok(rtt);
+            }
+        }
+    }
+
+    /// Whether `packet` is the echo reply to `sequence`.
+    fn is_reply(&self, packet: &[u8], sequence: u16) -> bool {
+        // Raw IPv4 sockets deliver the IP header too
+        let packet = if self.kind == SocketKind::Raw && !self.ipv6 {
+            match packet.first() {
+                Some(&b) => packet.get(((b & 0x0f) as usize) * 4..),
+                None => None,
+            }
+        } else {
+            Some(packet)
+        };
+
+        // Datagram sockets rewrite the identifier, and the kernel only
+        // delivers replies for this socket, so it is not compared
+        let ident = (self.kind == SocketKind::Raw).then_some(self.ident);
+
+        match packet {
+            Some(packet) => {
+                parse_echo_reply(packet, self.ipv6, ident, sequence)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Build an ICMP echo request carrying `payload_bytes` of payload.
+fn echo_request(
+    ipv6: bool,
+    ident: u16,
+    sequence: u16,
+    payload_bytes: usize,
+) -> Vec<u8> {
+    let mut packet = vec![0u8; HEADER_BYTES + payload_bytes];
+    packet[0] = if ipv6 { ICMPV6_ECHO_REQUEST } else { ICMPV4_ECHO_REQUEST };
+    packet[4..6].copy_from_slice(&ident.to_be_bytes());
+    packet[6..8].copy_from_slice(&sequence.to_be_bytes());
+    for (i, byte) in packet[HEADER_BYTES..].iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+
+    // The kernel fills in the ICMPv6 checksum, which covers a
+    // pseudo-header we don't have
+    if !ipv6 {
+        let checksum = internet_checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    packet
+}
+
+/// Whether `packet` (starting at the ICMP header) is an echo reply to
+/// `sequence`, optionally also checking the identifier.
+fn parse_echo_reply(
+    packet: &[u8],
+    ipv6: bool,
+    ident: Option<u16>,
+    sequence: u16,
+) -> bool {
+    if packet.len() < HEADER_BYTES {
+        return false;
+    }
+
+    let reply_type = if ipv6 { ICMPV6_ECHO_REPLY } else { ICMPV4_ECHO_REPLY };
+    let reply_ident = u16::from_be_bytes([packet[4], packet[5]]);
+    let reply_sequence = u16::from_be_bytes([packet[6], packet[7]]);
+
+    packet[0] == reply_type
+        && reply_sequence == sequence
+        && ident.is_none_or(|ident| ident == reply_ident)
+}
+
+/// RFC 1071 one's complement checksum.
+fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| match pair {
+            [hi, lo] => u16::from_be_bytes([*hi, *lo]) as u32,
+            [hi] => u16::from_be_bytes([*hi, 0]) as u32,
+            _ => 0,
+        })
+        .sum();
+
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_internet_checksum_validates() {
+        let packet = echo_request(false, 0x1234, 7, PAYLOAD_BYTES);
+        // A packet including its own checksum sums to zero
+        assert_eq!(internet_checksum(&packet), 0);
+    }
+
+    #[test]
+    fn test_internet_checksum_odd_length() {
+        assert_eq!(internet_checksum(&[0xff]), !0xff00);
+    }
+
+    #[test]
+    fn test_echo_request_layout() {
+        let packet = echo_request(false, 0xabcd, 42, PAYLOAD_BYTES);
+        assert_eq!(packet.len(), HEADER_BYTES + PAYLOAD_BYTES);
+        assert_eq!(packet[0], ICMPV4_ECHO_REQUEST);
+        assert_eq!(packet[1], 0);
+        assert_eq!(&packet[4..6], &[0xab, 0xcd]);
+        assert_eq!(&packet[6..8], &[0, 42]);
+
+        let packet = echo_request(true, 1, 1, PAYLOAD_BYTES);
+        assert_eq!(packet[0], ICMPV6_ECHO_REQUEST);
+        assert_eq!(&packet[2..4], &[0, 0]);
+
+        let packet = echo_request(false, 1, 1, 1472);
+        assert_eq!(packet.len(), 1480);
+        assert_eq!(internet_checksum(&packet), 0);
+    }
+
+    #[test]
+    fn test_parse_echo_reply() {
+        let mut reply = echo_request(false, 0x1234, 9, PAYLOAD_BYTES);
+        reply[0] = ICMPV4_ECHO_REPLY;
+
+        assert!(parse_echo_reply(&reply, false, Some(0x1234), 9));
+        assert!(parse_echo_reply(&reply, false, None, 9));
+        assert!(!parse_echo_reply(&reply, false, Some(0x4321), 9));
+        assert!(!parse_echo_reply(&reply, false, None, 10));
+        assert!(!parse_echo_reply(&reply, true, None, 9));
+        assert!(!parse_echo_reply(&reply[..4], false, None, 9));
+
+        // Our own request echoed back is not a reply
+        let request = echo_request(false, 0x1234, 9, PAYLOAD_BYTES);
+        assert!(!parse_echo_reply(&request, false, None, 9));
+    }
+}
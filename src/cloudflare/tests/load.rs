@@ -0,0 +1,98 @@
+//! Background load generation for loaded latency measurements.
+//!
+//! The measurement requests alone may not saturate a fast link, so
+//! loaded latency can look better than it would under real congestion.
+//! A [`LoadGenerator`] runs extra transfer streams alongside a bandwidth
+//! block, independent of the measured requests, to keep the link busy.
+
+use crate::cloudflare::tests::download::Download;
+use crate::cloudflare::tests::upload::Upload;
+use crate::cloudflare::tests::Test;
+use http::HeaderMap;
+use log::debug;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Bytes transferred by each request on a load stream.
+///
+/// Kept moderate so that a stream stops soon after the block ends: a
+/// request already in flight runs to completion.
+const LOAD_REQUEST_BYTES: u64 = 10_000_000;
+
+/// Pause before a failed load stream tries again.
+const LOAD_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Saturating transfer streams running in the background.
+///
+/// Streams are stopped when the generator is dropped.
+pub(crate) struct LoadGenerator {
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl LoadGenerator {
+    /// Start `streams` background streams in the given direction.
+    ///
+    /// # Arguments
+    /// * `streams` - Number of streams; `0` starts nothing
+    /// * `is_download` - Whether the streams download or upload
+    /// * `headers` - Additional headers sent with every load request
+    pub fn start(
+        streams: usize,
+        is_download: bool,
+        headers: &HeaderMap,
+    ) -> Self {
+        let handles = (0..streams)
+            .map(|stream| {
+                let headers = headers.clone();
+                tokio::spawn(async move {
+                    run_stream(stream, is_download, headers).await
+                })
+            })
+            .collect();
+
+        Self { handles }
+    }
+
+    /// Number of streams running.
+    pub fn streams(&self) -> usize {
+        self.handles.len()
+    }
+}
+
+impl Drop for LoadGenerator {
+    fn drop(&mut self) {
+        for handle in &self.handles {
+            handle.abort();
+        }
+    }
+}
+
+/// Transfer data back-to-back until the task is aborted.
+async fn run_stream(stream: usize, is_download: bool, headers: HeaderMap) {
+    let download = Download::default().with_headers(headers.clone());
+    let upload = Upload::new(LOAD_REQUEST_BYTES).with_headers(headers);
+
+    loop {
+        let result = if is_download {
+            download.run(LOAD_REQUEST_BYTES).await
+        } else {
+            upload.run(LOAD_REQUEST_BYTES).await
+        };
+
+        if let Err(e) = result.map_err(|e| e.to_string()) {
+            debug!("Load stream {} request failed: {}", stream, e);
+            tokio::time::sleep(LOAD_RETRY_DELAY).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_zero_streams_starts_nothing() {
+        let load = LoadGenerator::start(0, true, &HeaderMap::new());
+        assert_eq!(load.streams(), 0);
+    }
+}
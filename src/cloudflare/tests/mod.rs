@@ -1,16 +1,81 @@
+use crate::cloudflare::client::DEFAULT_USER_AGENT;
+use http::header::{
+    HeaderMap, HeaderName, HeaderValue, CONNECTION, CONTENT_LENGTH, HOST,
+    SERVER, TRANSFER_ENCODING, USER_AGENT,
+};
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::io::{Read, Write};
+use std::str::FromStr;
 use std::time::Duration;
 
 pub(crate) mod connection;
+pub(crate) mod cpu;
 pub(crate) mod download;
+pub mod duplex;
 pub mod engine;
+pub mod handshake;
+pub mod happy_eyeballs;
+pub(crate) mod icmp;
+pub(crate) mod load;
+pub mod mtu;
 pub mod packet_loss;
+pub(crate) mod response;
+pub(crate) mod sink;
+pub(crate) mod stall;
+pub mod tcp_info;
+pub(crate) mod transfer;
 pub(crate) mod upload;
 
 pub(crate) static BASE_URL: &str = "https://speed.cloudflare.com";
 
+/// Query parameter tagging every request of a run with its measurement
+/// ID, as the official client does.
+const MEAS_ID_PARAM: &str = "measId";
+
+/// Generate a measurement ID for a run: a random 16-digit number, the
+/// form the official client uses, so Cloudflare can find a run's requests
+/// in their logs.
+pub fn new_meas_id() -> String {
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    let mut bytes = [0_u8; 8];
+    if SystemRandom::new().fill(&mut bytes).is_err() {
+        // Only used to correlate requests; uniqueness is enough
+        let nanos =
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        bytes = nanos.to_le_bytes();
+    }
+    format!("{:016}", u64::from_le_bytes(bytes) % 10_u64.pow(16))
+}
+
+/// URL of a test endpoint, with the run's measurement ID and, for
+/// downloads, the byte count as query parameters.
+pub(crate) fn endpoint_url(
+    endpoint: &str,
+    meas_id: Option<&str>,
+    bytes: Option<u64>,
+) -> Result<url::Url, url::ParseError> {
+    let mut url = url::Url::parse(&format!("{}/{}", BASE_URL, endpoint))?;
+    if meas_id.is_some() || bytes.is_some() {
+        let mut query = url.query_pairs_mut();
+        if let Some(meas_id) = meas_id {
+            query.append_pair(MEAS_ID_PARAM, meas_id);
+        }
+        if let Some(bytes) = bytes {
+            query.append_pair("bytes", &bytes.to_string());
+        }
+    }
+    Ok(url)
+}
+
+/// Path and query of `url`, as sent in a request line.
+pub(crate) fn request_target(url: &url::Url) -> &str {
+    &url[url::Position::BeforePath..url::Position::AfterQuery]
+}
+
 /// Extract HTTP status code from a raw HTTP response status line.
 ///
 /// Parses "HTTP/1.1 200 OK\r\n..." and returns the numeric status code.
@@ -22,6 +87,119 @@ pub(crate) fn extract_http_status(raw_headers: &str) -> Option<u16> {
         .and_then(|code| code.parse().ok())
 }
 
+/// Parse the header lines of a raw HTTP response, skipping the status line
+/// and any malformed headers.
+pub(crate) fn extract_http_headers(raw_headers: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    for line in raw_headers.lines() {
+        let line = line.trim();
+
+        if line.is_empty() || !line.contains(':') {
+            continue;
+        }
+
+        let parts: Vec<&str> = line.splitn(2, ':').collect();
+        if parts.len() != 2 {
+            continue;
+        }
+
+        // Skip malformed header names/values instead of panicking
+        let name = match HeaderName::from_str(parts[0].trim()) {
+            Ok(n) => n,
+            Err(_) => continue,
+        };
+        let value = match HeaderValue::from_str(parts[1].trim()) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        headers.append(name, value);
+    }
+
+    headers
+}
+
+/// Prefix of Cloudflare's `cf-meta-*` response headers.
+const CF_META_PREFIX: &str = "cf-meta-";
+
+/// Cloudflare response headers identifying the edge and request that
+/// served a measurement, for tracking down odd results.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EdgeHeaders {
+    /// `cf-ray`: the request ID, suffixed with the serving location
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ray: Option<String>,
+    /// `cf-cache-status`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_status: Option<String>,
+    /// `server`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    /// `cf-meta-*` headers, keyed by name without the prefix
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub meta: BTreeMap<String, String>,
+}
+
+impl EdgeHeaders {
+    /// Pick the edge headers out of a response's `headers`, or `None` if
+    /// it has none of them.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let get = |name: &str| {
+            headers
+                .get(name)
+                .map(|value| String::from_utf8_lossy(value.as_bytes()))
+                .map(String::from)
+        };
+        let meta = headers
+            .iter()
+            .filter_map(|(name, value)| {
+                let key = name.as_str().strip_prefix(CF_META_PREFIX)?;
+                let value = String::from_utf8_lossy(value.as_bytes());
+                Some((key.to_string(), value.into_owned()))
+            })
+            .collect();
+
+        let edge = Self {
+            ray: get("cf-ray"),
+            cache_status: get("cf-cache-status"),
+            server: get(SERVER.as_str()),
+            meta,
+        };
+        (edge != Self::default()).then_some(edge)
+    }
+}
+
+/// User-Agent to send on raw test requests, honouring any override in
+/// the caller-supplied `headers`.
+pub(crate) fn user_agent(headers: &HeaderMap) -> Cow<'_, str> {
+    headers
+        .get(USER_AGENT)
+        .map(|value| String::from_utf8_lossy(value.as_bytes()))
+        .unwrap_or(Cow::Borrowed(DEFAULT_USER_AGENT))
+}
+
+/// Render caller-supplied headers as raw HTTP/1.1 header lines.
+///
+/// `User-Agent` is handled by [`user_agent`], and headers that control
+/// message framing are owned by the test itself, so both are skipped.
+pub(crate) fn format_extra_headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .filter(|(name, _)| {
+            ![USER_AGENT, HOST, CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING]
+                .contains(name)
+        })
+        .map(|(name, value)| {
+            format!(
+                "{}: {}\r\n",
+                name,
+                String::from_utf8_lossy(value.as_bytes())
+            )
+        })
+        .collect()
+}
+
 pub trait IoReadAndWrite: Read + Write + Send {}
 
 impl<T: Read + Write + Send> IoReadAndWrite for T {}
@@ -69,6 +247,22 @@ pub(crate) struct TestResults {
     pub end_duration: Duration,
     /// Number of bytes transferred
     pub bytes: u64,
+    /// Details of the TLS session the test ran over
+    pub tls: Option<connection::TlsInfo>,
+    /// Kernel TCP statistics after the transfer, where supported
+    pub tcp: Option<tcp_info::TcpStats>,
+    /// Effective socket buffer sizes after the transfer
+    pub socket_buffers: Option<connection::SocketBuffers>,
+    /// Cloudflare headers of the response
+    pub edge: Option<EdgeHeaders>,
+    /// Number of redirects followed before the transfer
+    pub redirects: u32,
+    /// Time spent following redirects, not part of the transfer
+    pub redirect_duration: Duration,
+    /// SHA-256 of the response body, when it was hashed
+    pub body_sha256: Option<String>,
+    /// Share of one core the transfer kept busy, where measurable
+    pub core_utilization: Option<f64>,
 }
 
 impl TestResults {
@@ -85,9 +279,77 @@ impl TestResults {
             server_time,
             end_duration,
             bytes,
+            tls: None,
+            tcp: None,
+            socket_buffers: None,
+            edge: None,
+            redirects: 0,
+            redirect_duration: Duration::ZERO,
+            body_sha256: None,
+            core_utilization: None,
         }
     }
 
+    /// Attach details of the TLS session the test ran over.
+    pub(crate) fn with_tls(mut self, tls: connection::TlsInfo) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Attach kernel TCP statistics for the test connection.
+    pub(crate) fn with_tcp_stats(
+        mut self,
+        tcp: Option<tcp_info::TcpStats>,
+    ) -> Self {
+        self.tcp = tcp;
+        self
+    }
+
+    /// Attach the effective socket buffer sizes of the test connection.
+    pub(crate) fn with_socket_buffers(
+        mut self,
+        buffers: Option<connection::SocketBuffers>,
+    ) -> Self {
+        self.socket_buffers = buffers;
+        self
+    }
+
+    /// Attach the Cloudflare headers of the response.
+    pub(crate) fn with_edge_headers(
+        mut self,
+        edge: Option<EdgeHeaders>,
+    ) -> Self {
+        self.edge = edge;
+        self
+    }
+
+    /// Attach the SHA-256 of the response body.
+    pub(crate) fn with_body_sha256(mut self, digest: Option<String>) -> Self {
+        self.body_sha256 = digest;
+        self
+    }
+
+    /// Record the share of one core the transfer kept busy.
+    pub(crate) fn with_core_utilization(
+        mut self,
+        utilization: Option<f64>,
+    ) -> Self {
+        self.core_utilization = utilization;
+        self
+    }
+
+    /// Record the redirects followed before the transfer, and the time
+    /// they took.
+    pub(crate) fn with_redirects(
+        mut self,
+        redirects: u32,
+        duration: Duration,
+    ) -> Self {
+        self.redirects = redirects;
+        self.redirect_duration = duration;
+        self
+    }
+
     /// Calculate the transfer duration (time to download/upload data).
     ///
     /// This is the time from first byte to last byte, which represents
@@ -124,3 +386,89 @@ impl TestResults {
         }
     }
 }
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use http::HeaderValue;
+
+    #[test]
+    fn test_new_meas_id() {
+        let meas_id = new_meas_id();
+        assert_eq!(meas_id.len(), 16);
+        assert!(meas_id.bytes().all(|b| b.is_ascii_digit()));
+        assert_ne!(meas_id, new_meas_id());
+    }
+
+    #[test]
+    fn test_endpoint_url() {
+        assert_eq!(
+            endpoint_url("__down", Some("1234"), Some(100_000))
+                .unwrap()
+                .as_str(),
+            "https://speed.cloudflare.com/__down?measId=1234&bytes=100000"
+        );
+        assert_eq!(
+            endpoint_url("__down", None, Some(0)).unwrap().as_str(),
+            "https://speed.cloudflare.com/__down?bytes=0"
+        );
+        assert_eq!(
+            endpoint_url("__up", None, None).unwrap().as_str(),
+            "https://speed.cloudflare.com/__up"
+        );
+    }
+
+    #[test]
+    fn test_request_target() {
+        let url = endpoint_url("__down", Some("1234"), Some(0)).unwrap();
+        assert_eq!(request_target(&url), "/__down?measId=1234&bytes=0");
+
+        let url = endpoint_url("__up", None, None).unwrap();
+        assert_eq!(request_target(&url), "/__up");
+    }
+
+    #[test]
+    fn test_user_agent_defaults_and_overrides() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(user_agent(&headers), DEFAULT_USER_AGENT);
+
+        headers.insert(USER_AGENT, HeaderValue::from_static("custom/1.0"));
+        assert_eq!(user_agent(&headers), "custom/1.0");
+    }
+
+    #[test]
+    fn test_format_extra_headers_skips_reserved() {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static("custom/1.0"));
+        headers.insert(HOST, HeaderValue::from_static("example.com"));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("1"));
+        headers.insert("x-token", HeaderValue::from_static("secret"));
+
+        assert_eq!(format_extra_headers(&headers), "x-token: secret\r\n");
+    }
+
+    #[test]
+    fn test_edge_headers_from_response() {
+        let headers = extract_http_headers(
+            "HTTP/1.1 200 OK\r\n\
+            Server: cloudflare\r\n\
+            CF-RAY: 8a1b2c3d4e5f6789-DFW\r\n\
+            cf-cache-status: DYNAMIC\r\n\
+            cf-meta-colo: DFW\r\n\
+            cf-meta-request-time: 1790000000000\r\n\
+            Content-Type: application/octet-stream\r\n\r\n",
+        );
+
+        let edge = EdgeHeaders::from_headers(&headers).unwrap();
+        assert_eq!(edge.ray.as_deref(), Some("8a1b2c3d4e5f6789-DFW"));
+        assert_eq!(edge.cache_status.as_deref(), Some("DYNAMIC"));
+        assert_eq!(edge.server.as_deref(), Some("cloudflare"));
+        assert_eq!(edge.meta.len(), 2);
+        assert_eq!(edge.meta["colo"], "DFW");
+
+        let plain = extract_http_headers(
+            "HTTP/1.1 200 OK\r\nServer-Timing: x\r\n\r\n",
+        );
+        assert_eq!(EdgeHeaders::from_headers(&plain), None);
+    }
+}
@@ -0,0 +1,159 @@
+//! Path MTU discovery toward the test server.
+//!
+//! Sends ICMP echo requests with the don't-fragment bit set and binary
+//! searches for the largest packet that gets a reply. A path MTU below
+//! the usual 1500 bytes is harmless while routers report it, but when
+//! their "fragmentation needed" messages are filtered (a PMTU black
+//! hole), small requests work and large transfers stall, which looks
+//! like a bandwidth problem rather than a routing one.
+
+use crate::cloudflare::tests::icmp::{IcmpPinger, HEADER_BYTES};
+use log::debug;
+use std::io;
+use std::time::Duration;
+
+/// The Ethernet MTU, where the search starts.
+pub const ETHERNET_MTU: usize = 1500;
+
+/// Smallest MTU every IPv4 host must accept (RFC 791).
+const MIN_MTU_V4: usize = 576;
+
+/// Smallest MTU every IPv6 link must support (RFC 8200).
+const MIN_MTU_V6: usize = 1280;
+
+/// Probes sent at each size before concluding it doesn't fit, so a
+/// single lost packet doesn't shrink the result.
+const ATTEMPTS: usize = 2;
+
+/// Result of a path MTU search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathMtu {
+    /// Largest IP packet, in bytes, that reached the server and back
+    pub bytes: usize,
+    /// Echo requests sent during the search
+    pub probes: usize,
+}
+
+/// Find the path MTU to the host `pinger` is connected to.
+///
+/// This blocks the calling thread; run it via `spawn_blocking`.
+///
+/// Fails if don't-fragment can't be set, or if not even a minimum-size
+/// packet gets a reply (the server or path drops ICMP).
+pub fn discover(
+    pinger: &IcmpPinger,
+    timeout: Duration,
+) -> io::Result<PathMtu> {
+    pinger.set_dont_fragment()?;
+
+    let ip_header = if pinger.is_ipv6() { 40 } else { 20 };
+    let floor = if pinger.is_ipv6() { MIN_MTU_V6 } else { MIN_MTU_V4 };
+    let mut probes = 0;
+    let mut last_error = None;
+
+    let bytes = search(floor, ETHERNET_MTU, |mtu| {
+        let payload = mtu - ip_header - HEADER_BYTES;
+        (0..ATTEMPTS).any(|_| {
+            let sequence = probes as u16;
+            probes += 1;
+            match pinger.ping_sized(sequence, payload, timeout) {
+                Ok(rtt) => {
+                    debug!("PMTU probe of {} bytes: {:?}", mtu, rtt);
+                    true
+                }
+                Err(e) => {
+                    debug!("PMTU probe of {} bytes failed: {}", mtu, e);
+                    last_error = Some(e);
+                    false
+                }
+            }
+        })
+    });
+
+    match bytes {
+        Some(bytes) => Ok(PathMtu { bytes, probes }),
+        None => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!(
+                "no reply to a {} byte probe ({})",
+                floor,
+                last_error.map(|e| e.to_string()).unwrap_or_default()
+            ),
+        )),
+    }
+}
+
+/// Largest size in `floor..=ceiling` for which `fits` holds, assuming
+/// every smaller size fits too.
+///
+/// `ceiling` is tried first, since most paths carry full frames, then
+/// `floor`; if neither settles it the range between them is bisected.
+fn search(
+    floor: usize,
+    ceiling: usize,
+    mut fits: impl FnMut(usize) -> bool,
+) -> Option<usize> {
+    if fits(ceiling) {
+        return Some(ceiling);
+    }
+    if !fits(floor) {
+        return None;
+    }
+
+    // Invariant: `low` fits and `high` does not
+    let (mut low, mut high) = (floor, ceiling);
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if fits(mid) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    Some(low)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_search_full_frames_take_one_probe() {
+        let mut tried = Vec::new();
+        let mtu = search(576, 1500, |size| {
+            tried.push(size);
+            true
+        });
+        assert_eq!(mtu, Some(1500));
+        assert_eq!(tried, [1500]);
+    }
+
+    #[test]
+    fn test_search_nothing_fits() {
+        assert_eq!(search(576, 1500, |_| false), None);
+    }
+
+    #[test]
+    fn test_search_finds_pppoe_mtu() {
+        let mut probes = 0;
+        let mtu = search(576, 1500, |size| {
+            probes += 1;
+            size <= 1492
+        });
+        assert_eq!(mtu, Some(1492));
+        // Two bounds plus a bisection of 924 sizes
+        assert!(probes <= 12, "{} probes", probes);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_search_finds_largest_fitting_size(actual in 576usize..=1500) {
+            prop_assert_eq!(
+                search(576, 1500, |size| size <= actual),
+                Some(actual)
+            );
+        }
+    }
+}
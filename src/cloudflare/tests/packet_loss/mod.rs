@@ -1,38 +1,180 @@
-//! Packet loss measurement using UDP via TURN server.
+//! Packet loss measurement over UDP.
 //!
-//! This module implements packet loss measurement by sending UDP packets
-//! through a TURN (Traversal Using Relays around NAT) server and measuring
-//! how many packets are lost in transit.
+//! A run sends numbered probes to a server that answers each one, and
+//! counts the probes that go unanswered. What the probes look like
+//! depends on the infrastructure that's reachable, so each
+//! [`LossProvider`] supplies a [`LossProbe`] and the sending, timing and
+//! counting is shared:
 //!
-//! # Requirements
-//! - Requires TURN server credentials to be configured
-//! - Sends UDP packets and waits for responses
-//! - Calculates packet loss ratio as lost/sent
-
+//! - `turn` sends STUN Binding requests to a TURN server, which answers
+//!   them without credentials
+//! - `udp-echo` sends to a plain UDP echo server (RFC 862)
+//! - `quic` asks a QUIC server to negotiate a version, which needs no
+//!   handshake; Cloudflare's edge answers when no server is given
+
+mod quic;
+mod turn;
+mod udp_echo;
+
+use crate::cloudflare::tests::connection::{self, name_server};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::error::Error;
 use std::fmt;
-use std::net::SocketAddr;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
 use std::time::Duration;
+use tokio::net::UdpSocket;
+
+pub use quic::QuicProbe;
+pub use turn::TurnProbe;
+pub use udp_echo::UdpEchoProbe;
+
+/// Largest answer a probe expects; anything longer is truncated.
+const MAX_RESPONSE: usize = 2048;
+
+/// Kind of server packet loss is measured against.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum LossProvider {
+    /// STUN Binding requests to a TURN server
+    #[default]
+    Turn,
+    /// Datagrams a UDP echo server sends back
+    UdpEcho,
+    /// QUIC version negotiation
+    Quic,
+}
 
-/// Configuration for packet loss measurement via TURN server.
-///
-/// This struct contains all the parameters needed to connect to a TURN
-/// server and perform packet loss measurements.
+impl LossProvider {
+    /// Port used when the server URI doesn't give one.
+    pub fn default_port(self) -> u16 {
+        match self {
+            LossProvider::Turn => 3478,
+            LossProvider::UdpEcho => 7,
+            LossProvider::Quic => 443,
+        }
+    }
+
+    /// Server measured against when none is configured, if the provider
+    /// has one.
+    pub fn default_server(self) -> Option<&'static str> {
+        match self {
+            LossProvider::Quic => Some("speed.cloudflare.com"),
+            LossProvider::Turn | LossProvider::UdpEcho => None,
+        }
+    }
+
+    /// URI schemes accepted in front of the server address.
+    fn schemes(self) -> &'static [&'static str] {
+        match self {
+            LossProvider::Turn => &["turn:", "turns:", "stun:", "stuns:"],
+            LossProvider::UdpEcho => &["udp:"],
+            LossProvider::Quic => &["quic:", "https://"],
+        }
+    }
+
+    /// A fresh probe of this kind.
+    fn probe(self) -> Box<dyn LossProbe + Send + Sync> {
+        match self {
+            LossProvider::Turn => Box::new(TurnProbe::new()),
+            LossProvider::UdpEcho => Box::new(UdpEchoProbe),
+            LossProvider::Quic => Box::new(QuicProbe::new()),
+        }
+    }
+}
+
+impl fmt::Display for LossProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LossProvider::Turn => "turn",
+            LossProvider::UdpEcho => "udp-echo",
+            LossProvider::Quic => "quic",
+        })
+    }
+}
+
+impl FromStr for LossProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "turn" => Ok(LossProvider::Turn),
+            "udp-echo" | "echo" => Ok(LossProvider::UdpEcho),
+            "quic" => Ok(LossProvider::Quic),
+            other => Err(format!(
+                "unknown loss provider '{}' (expected turn, udp-echo or quic)",
+                other
+            )),
+        }
+    }
+}
+
+/// The request and answer of one kind of probe. Answers can arrive in
+/// any order, so each has to say which probe it's for.
+pub trait LossProbe {
+    /// Datagram for probe `seq`.
+    fn request(&self, seq: u32) -> Vec<u8>;
+
+    /// Sequence number of the probe `response` answers, or `None` if
+    /// it's not an answer to one of ours.
+    fn sequence(&self, response: &[u8]) -> Option<u32>;
+}
+
+/// Which probes were answered, and in what order.
+#[derive(Debug, Default)]
+struct Arrivals {
+    answered: HashSet<u32>,
+    highest: Option<u32>,
+    /// Answers that arrived after an answer to a later probe
+    reordered: usize,
+    /// Extra copies of answers
+    duplicates: usize,
+}
+
+impl Arrivals {
+    /// Record an answer to probe `seq`, returning whether it's the first.
+    fn record(&mut self, seq: u32) -> bool {
+        if !self.answered.insert(seq) {
+            self.duplicates += 1;
+            return false;
+        }
+        if self.highest.is_some_and(|highest| seq < highest) {
+            self.reordered += 1;
+        }
+        self.highest = self.highest.max(Some(seq));
+        true
+    }
+
+    fn contains(&self, seq: u32) -> bool {
+        self.answered.contains(&seq)
+    }
+
+    /// Probes answered at least once.
+    fn received(&self) -> usize {
+        self.answered.len()
+    }
+}
+
+/// Configuration for packet loss measurement.
 ///
 /// # Example
 /// ```
-/// use cloud_speed::cloudflare::tests::packet_loss::PacketLossConfig;
+/// use cloud_speed::cloudflare::tests::packet_loss::{
+///     LossProvider, PacketLossConfig,
+/// };
 ///
-/// let config = PacketLossConfig::new(
-///     "turn:turn.example.com:3478".to_string(),
-///     "username".to_string(),
-///     "password".to_string(),
-/// );
+/// let config = PacketLossConfig::new("turn:turn.example.com:3478".to_string())
+///     .with_provider(LossProvider::Turn);
 /// ```
 #[derive(Debug, Clone)]
 pub struct PacketLossConfig {
-    /// TURN server URI (e.g., "turn:turn.example.com:3478")
-    pub turn_server_uri: String,
+    /// Server URI (e.g., "turn:turn.example.com:3478")
+    pub server_uri: String,
+    /// Kind of server and probe
+    pub provider: LossProvider,
     /// Number of UDP packets to send for measurement
     /// Default: 1000
     pub num_packets: usize,
@@ -60,19 +202,26 @@ impl PacketLossConfig {
     /// Default packet timeout in milliseconds.
     pub const DEFAULT_PACKET_TIMEOUT_MS: u64 = 1000;
 
-    /// Create a new PacketLossConfig with required parameters and defaults.
+    /// Create a new PacketLossConfig for a TURN server with defaults.
     ///
     /// # Arguments
-    /// * `turn_server_uri` - TURN server URI
-    pub fn new(turn_server_uri: String) -> Self {
+    /// * `server_uri` - Server URI
+    pub fn new(server_uri: String) -> Self {
         Self {
-            turn_server_uri,
+            server_uri,
+            provider: LossProvider::default(),
             num_packets: Self::DEFAULT_NUM_PACKETS,
             batch_size: Self::DEFAULT_BATCH_SIZE,
             batch_wait_time_ms: Self::DEFAULT_BATCH_WAIT_TIME_MS,
             packet_timeout_ms: Self::DEFAULT_PACKET_TIMEOUT_MS,
         }
     }
+
+    /// Measure against a different kind of server.
+    pub fn with_provider(mut self, provider: LossProvider) -> Self {
+        self.provider = provider;
+        self
+    }
 }
 
 /// Result of a packet loss measurement.
@@ -94,6 +243,37 @@ pub struct PacketLossResult {
     pub packets_received: usize,
     /// Average round-trip time for received packets (in ms)
     pub avg_rtt_ms: Option<f64>,
+    /// Packets sent and received in each batch, in order, showing
+    /// whether loss came in bursts or spread evenly
+    pub batches: Vec<BatchLoss>,
+    /// Packets answered after a later packet's answer
+    pub reordered: usize,
+    /// Extra copies of answers received
+    pub duplicates: usize,
+}
+
+/// Packets sent and answered in one batch of a measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchLoss {
+    /// Packets sent in the batch
+    pub sent: usize,
+    /// Packets of the batch that received responses
+    pub received: usize,
+}
+
+impl BatchLoss {
+    /// Packets of the batch that were lost.
+    pub fn lost(&self) -> usize {
+        self.sent.saturating_sub(self.received)
+    }
+
+    /// Loss ratio of the batch (0.0 to 1.0), 0.0 if nothing was sent.
+    pub fn ratio(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        self.lost() as f64 / self.sent as f64
+    }
 }
 
 impl PacketLossResult {
@@ -132,12 +312,36 @@ impl PacketLossResult {
             packets_lost,
             packets_received,
             avg_rtt_ms,
+            batches: Vec::new(),
+            reordered: 0,
+            duplicates: 0,
         }
     }
 
+    /// Attach the per-batch counts the totals were summed from.
+    pub fn with_batches(mut self, batches: Vec<BatchLoss>) -> Self {
+        self.batches = batches;
+        self
+    }
+
+    /// Record how many answers arrived out of order or more than once.
+    pub fn with_order(mut self, reordered: usize, duplicates: usize) -> Self {
+        self.reordered = reordered;
+        self.duplicates = duplicates;
+        self
+    }
+
+    /// Share of received packets that arrived out of order (0.0 to 1.0).
+    pub fn reorder_ratio(&self) -> f64 {
+        if self.packets_received == 0 {
+            return 0.0;
+        }
+        self.reordered as f64 / self.packets_received as f64
+    }
+
     /// Create a result indicating packet loss measurement is unavailable.
     ///
-    /// Used when TURN server is not configured or connection fails.
+    /// Used when no server is configured or the measurement fails.
     pub fn unavailable() -> Self {
         Self {
             packet_loss_ratio: 0.0,
@@ -146,6 +350,9 @@ impl PacketLossResult {
             packets_lost: 0,
             packets_received: 0,
             avg_rtt_ms: None,
+            batches: Vec::new(),
+            reordered: 0,
+            duplicates: 0,
         }
     }
 
@@ -163,9 +370,9 @@ impl PacketLossResult {
 /// Error type for packet loss measurement failures.
 #[derive(Debug)]
 pub enum PacketLossError {
-    /// Failed to connect to TURN server
+    /// Failed to reach the server
     ConnectionFailed(String),
-    /// Invalid TURN server URI
+    /// Invalid server URI
     InvalidUri(String),
 }
 
@@ -173,10 +380,10 @@ impl fmt::Display for PacketLossError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             PacketLossError::ConnectionFailed(msg) => {
-                write!(f, "Failed to connect to TURN server: {}", msg)
+                write!(f, "Failed to reach packet loss server: {}", msg)
             }
             PacketLossError::InvalidUri(uri) => {
-                write!(f, "Invalid TURN server URI: {}", uri)
+                write!(f, "Invalid packet loss server URI: {}", uri)
             }
         }
     }
@@ -186,62 +393,70 @@ impl Error for PacketLossError {}
 
 /// Packet loss test implementation.
 ///
-/// This struct handles the execution of packet loss measurements
-/// using UDP packets via a TURN server.
-///
-/// # Note
-/// This is a simplified implementation. A full TURN client implementation
-/// would require the STUN/TURN protocol stack. For now, this provides
-/// the interface and basic structure, with the actual UDP measurement
-/// being a placeholder that can be extended.
+/// Sends the probes of the configured provider one at a time and waits
+/// for each answer, in batches with a pause in between.
 pub struct PacketLossTest {
     config: PacketLossConfig,
+    probe: Box<dyn LossProbe + Send + Sync>,
 }
 
 impl PacketLossTest {
     /// Create a new packet loss test with the given configuration.
     pub fn new(config: PacketLossConfig) -> Self {
-        Self { config }
+        let probe = config.provider.probe();
+        Self { config, probe }
     }
 
     /// Run the packet loss measurement.
     ///
-    /// This method sends UDP packets through the configured TURN server
-    /// and measures how many packets are lost.
-    ///
     /// # Returns
     /// * `Ok(PacketLossResult)` - Measurement results
-    /// * `Err(PacketLossError)` - If measurement fails
-    ///
-    /// # Note
-    /// This is a simplified implementation. A production implementation
-    /// would need a full STUN/TURN client library.
+    /// * `Err(PacketLossError)` - If the server can't be reached
     pub async fn run(&self) -> Result<PacketLossResult, PacketLossError> {
         use log::{debug, info, warn};
-        use std::time::Instant;
+        use std::io::ErrorKind;
+        use tokio::time::Instant;
 
         info!(
-            "Starting packet loss measurement: {} packets to {}",
-            self.config.num_packets, self.config.turn_server_uri
+            "Starting packet loss measurement: {} {} probes to {}",
+            self.config.num_packets,
+            self.config.provider,
+            self.config.server_uri
         );
 
-        // Parse the TURN URI to extract host and port
-        let (host, port) = self.parse_turn_uri()?;
-        debug!("Parsed TURN server: {}:{}", host, port);
+        let (host, port) =
+            parse_server_uri(&self.config.server_uri, self.config.provider)?;
+        debug!("Parsed packet loss server: {}:{}", host, port);
 
-        // Resolve the TURN server address
         let addr = self.resolve_address(&host, port).await?;
-        debug!("Resolved TURN server address: {}", addr);
+        debug!("Resolved packet loss server address: {}", addr);
 
-        // Create UDP socket
-        let socket = self.create_socket().await?;
+        let socket = self.connect_socket(addr).await?;
         debug!("Created UDP socket");
 
         // Send packets and track responses
         let start_time = Instant::now();
+        let timeout = Duration::from_millis(self.config.packet_timeout_ms);
         let mut packets_sent = 0usize;
-        let mut packets_received = 0usize;
         let mut total_rtt_ms = 0.0f64;
+        let mut arrivals = Arrivals::default();
+        let mut batches = Vec::new();
+        let mut buf = [0u8; MAX_RESPONSE];
+
+        // An ICMP port unreachable for an earlier probe fails the next
+        // send or receive; Windows reports it as a reset
+        let unreachable = |e: &std::io::Error, arrivals: &Arrivals| {
+            matches!(
+                e.kind(),
+                ErrorKind::ConnectionRefused | ErrorKind::ConnectionReset
+            ) && arrivals.received() == 0
+        };
+        let nothing_listening = || {
+            PacketLossError::ConnectionFailed(format!(
+                "nothing is listening on {}",
+                addr
+            ))
+        };
 
         // Send packets in batches
         let num_batches =
@@ -251,6 +466,7 @@ impl PacketLossTest {
             let batch_start = batch * self.config.batch_size;
             let batch_end = (batch_start + self.config.batch_size)
                 .min(self.config.num_packets);
+            let batch_seqs = batch_start as u32..batch_end as u32;
 
             debug!(
                 "Sending batch {}/{}: packets {}-{}",
@@ -260,55 +476,82 @@ impl PacketLossTest {
                 batch_end - 1
             );
 
-            for seq in batch_start..batch_end {
-                // Create a simple packet with sequence number
-                let packet = self.create_packet(seq as u32);
-
-                // Send the packet
-                let send_time = Instant::now();
-                match socket.send_to(&packet, addr).await {
+            // Send the whole batch, then collect its answers, so answers
+            // can overtake each other or arrive twice as media would
+            let mut sent_at = vec![None; batch_end - batch_start];
+            for seq in batch_seqs.clone() {
+                let packet = self.probe.request(seq);
+                match socket.send(&packet).await {
                     Ok(_) => {
-                        packets_sent += 1;
-
-                        // Try to receive response with timeout
-                        let timeout = Duration::from_millis(
-                            self.config.packet_timeout_ms,
+                        sent_at[(seq - batch_seqs.start) as usize] =
+                            Some(Instant::now());
+                    }
+                    Err(e) if unreachable(&e, &arrivals) => {
+                        return Err(nothing_listening())
+                    }
+                    Err(e) => warn!("Failed to send packet {}: {}", seq, e),
+                }
+            }
+            let batch_sent = sent_at.iter().flatten().count();
+            packets_sent += batch_sent;
+
+            let deadline = Instant::now() + timeout;
+            let mut batch_received = 0usize;
+            while batch_received < batch_sent {
+                let len = match tokio::time::timeout_at(
+                    deadline,
+                    socket.recv(&mut buf),
+                )
+                .await
+                {
+                    Ok(Ok(len)) => len,
+                    Ok(Err(e)) if unreachable(&e, &arrivals) => {
+                        return Err(nothing_listening());
+                    }
+                    Ok(Err(e)) => {
+                        debug!("Receive error in batch {}: {}", batch + 1, e);
+                        continue;
+                    }
+                    Err(_) => {
+                        // Timeout - the rest of the batch is lost
+                        debug!(
+                            "Timeout with {} of batch {} unanswered",
+                            batch_sent - batch_received,
+                            batch + 1
                         );
-                        let mut buf = [0u8; 1024];
-
-                        match tokio::time::timeout(
-                            timeout,
-                            socket.recv_from(&mut buf),
-                        )
-                        .await
-                        {
-                            Ok(Ok((len, _from))) => {
-                                if self
-                                    .validate_response(&buf[..len], seq as u32)
-                                {
-                                    packets_received += 1;
-                                    let rtt = send_time.elapsed();
-                                    total_rtt_ms += rtt.as_secs_f64() * 1000.0;
-                                }
-                            }
-                            Ok(Err(e)) => {
-                                debug!(
-                                    "Receive error for packet {}: {}",
-                                    seq, e
-                                );
-                            }
-                            Err(_) => {
-                                // Timeout - packet lost
-                                debug!("Timeout for packet {}", seq);
-                            }
+                        break;
+                    }
+                };
+
+                let Some(seq) = self.probe.sequence(&buf[..len]) else {
+                    continue;
+                };
+                let sent = batch_seqs
+                    .contains(&seq)
+                    .then(|| sent_at[(seq - batch_seqs.start) as usize])
+                    .flatten();
+                match sent {
+                    Some(send_time) => {
+                        if arrivals.record(seq) {
+                            batch_received += 1;
+                            total_rtt_ms +=
+                                send_time.elapsed().as_secs_f64() * 1000.0;
                         }
                     }
-                    Err(e) => {
-                        warn!("Failed to send packet {}: {}", seq, e);
+                    // Another copy of an answer already counted
+                    None if arrivals.contains(seq) => {
+                        arrivals.record(seq);
                     }
+                    // Answers to earlier batches come too late to count
+                    None => debug!("Late answer to packet {}", seq),
                 }
             }
 
+            batches.push(BatchLoss {
+                sent: batch_sent,
+                received: batch_received,
+            });
+
             // Wait between batches (except for the last batch)
             if batch < num_batches - 1 && self.config.batch_wait_time_ms > 0 {
                 tokio::time::sleep(Duration::from_millis(
@@ -318,13 +561,16 @@ impl PacketLossTest {
             }
         }
 
+        let packets_received = arrivals.received();
         let elapsed = start_time.elapsed();
         info!(
-            "Packet loss measurement complete in {:.2}s: sent={}, received={}, lost={}",
+            "Packet loss measurement complete in {:.2}s: sent={}, received={}, lost={}, reordered={}, duplicates={}",
             elapsed.as_secs_f64(),
             packets_sent,
             packets_received,
-            packets_sent.saturating_sub(packets_received)
+            packets_sent.saturating_sub(packets_received),
+            arrivals.reordered,
+            arrivals.duplicates
         );
 
         let avg_rtt_ms = if packets_received > 0 {
@@ -333,46 +579,12 @@ impl PacketLossTest {
             None
         };
 
-        Ok(PacketLossResult::new(packets_sent, packets_received, avg_rtt_ms))
-    }
-
-    /// Parse the TURN URI to extract host and port.
-    fn parse_turn_uri(&self) -> Result<(String, u16), PacketLossError> {
-        let uri = &self.config.turn_server_uri;
-
-        // Remove the "turn:" or "turns:" prefix
-        let without_scheme = uri
-            .strip_prefix("turn:")
-            .or_else(|| uri.strip_prefix("turns:"))
-            .or_else(|| uri.strip_prefix("//"))
-            .unwrap_or(uri);
-
-        // Split host and port
-        let parts: Vec<&str> = without_scheme.split(':').collect();
-
-        match parts.len() {
-            1 => {
-                // No port specified, use default TURN port
-                Ok((parts[0].to_string(), 3478))
-            }
-            2 => {
-                let host = parts[0].to_string();
-                let port = parts[1].parse::<u16>().map_err(|_| {
-                    PacketLossError::InvalidUri(format!(
-                        "Invalid port in URI: {}",
-                        uri
-                    ))
-                })?;
-                Ok((host, port))
-            }
-            _ => Err(PacketLossError::InvalidUri(format!(
-                "Cannot parse TURN URI: {}",
-                uri
-            ))),
-        }
+        Ok(PacketLossResult::new(packets_sent, packets_received, avg_rtt_ms)
+            .with_batches(batches)
+            .with_order(arrivals.reordered, arrivals.duplicates))
     }
 
-    /// Resolve the TURN server hostname to a socket address.
+    /// Resolve the server hostname to a socket address.
     async fn resolve_address(
         &self,
         host: &str,
@@ -380,9 +592,20 @@ impl PacketLossTest {
     ) -> Result<SocketAddr, PacketLossError> {
         use tokio::net::lookup_host;
 
+        // A name server chosen with --resolver answers this lookup too
+        if name_server().is_some() {
+            let lookup = connection::lookup_host(host).await.map_err(|e| {
+                PacketLossError::ConnectionFailed(format!(
+                    "Failed to resolve {}: {}",
+                    host, e
+                ))
+            })?;
+            return Ok(SocketAddr::new(lookup.preferred(), port));
+        }
+
         let addr_str = format!("{}:{}", host, port);
 
-        let mut addrs = lookup_host(&addr_str).await.map_err(|e| {
+        let mut addrs = lookup_host((host, port)).await.map_err(|e| {
             PacketLossError::ConnectionFailed(format!(
                 "Failed to resolve {}: {}",
                 addr_str, e
@@ -397,69 +620,76 @@ impl PacketLossTest {
         })
     }
 
-    /// Create a UDP socket for packet loss measurement.
-    async fn create_socket(
+    /// Create a UDP socket connected to `addr`, so only the server's
+    /// datagrams are received.
+    async fn connect_socket(
         &self,
-    ) -> Result<tokio::net::UdpSocket, PacketLossError> {
-        // Bind to any available port
-        tokio::net::UdpSocket::bind("0.0.0.0:0").await.map_err(|e| {
+        addr: SocketAddr,
+    ) -> Result<UdpSocket, PacketLossError> {
+        let failed = |e: std::io::Error| {
             PacketLossError::ConnectionFailed(format!(
                 "Failed to create UDP socket: {}",
                 e
             ))
-        })
-    }
-
-    /// Create a packet with the given sequence number.
-    ///
-    /// The packet format is simple:
-    /// - 4 bytes: sequence number (big-endian)
-    /// - 8 bytes: timestamp (big-endian, microseconds since epoch)
-    /// - 4 bytes: padding
-    fn create_packet(&self, seq: u32) -> Vec<u8> {
-        use std::time::{SystemTime, UNIX_EPOCH};
-
-        let mut packet = Vec::with_capacity(16);
-
-        // Sequence number (4 bytes, big-endian)
-        packet.extend_from_slice(&seq.to_be_bytes());
-
-        // Timestamp (8 bytes, big-endian)
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_micros() as u64;
-        packet.extend_from_slice(&timestamp.to_be_bytes());
-
-        // Padding (4 bytes)
-        packet.extend_from_slice(&[0u8; 4]);
-
-        packet
+        };
+        let local: SocketAddr = match addr {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(local).await.map_err(failed)?;
+        socket.connect(addr).await.map_err(failed)?;
+        Ok(socket)
     }
+}
 
-    /// Validate a response packet.
-    ///
-    /// For a simple echo server, we just check if the sequence number matches.
-    fn validate_response(&self, data: &[u8], expected_seq: u32) -> bool {
-        if data.len() < 4 {
-            return false;
+/// Split a server URI into host and port, e.g. "turn:example.com:3478"
+/// or "[2001:db8::1]:7". The provider's schemes and a TURN
+/// `?transport=` suffix are accepted; the port defaults to the
+/// provider's.
+fn parse_server_uri(
+    uri: &str,
+    provider: LossProvider,
+) -> Result<(String, u16), PacketLossError> {
+    let invalid =
+        |why: &str| PacketLossError::InvalidUri(format!("{} ({})", uri, why));
+
+    let rest = provider
+        .schemes()
+        .iter()
+        .find_map(|scheme| uri.strip_prefix(scheme))
+        .unwrap_or(uri)
+        .trim_start_matches("//");
+    let authority = rest.split(['?', '/']).next().unwrap_or_default();
+
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(v6) => {
+            let (host, after) =
+                v6.split_once(']').ok_or_else(|| invalid("unclosed '['"))?;
+            (host, after.strip_prefix(':'))
         }
-
-        let seq_bytes: [u8; 4] = data[0..4].try_into().unwrap_or([0; 4]);
-        let seq = u32::from_be_bytes(seq_bytes);
-
-        seq == expected_seq
+        None => match authority.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    if host.is_empty() {
+        return Err(invalid("no host"));
     }
+    let port = match port {
+        Some(port) => port.parse().map_err(|_| invalid("invalid port"))?,
+        None => provider.default_port(),
+    };
+
+    Ok((host.to_string(), port))
 }
 
 /// Run packet loss measurement with optional configuration.
 ///
-/// This function handles the case where TURN server configuration may not
-/// be available. If no configuration is provided, it returns an unavailable
-/// result instead of failing.
+/// If no configuration is provided, this returns an unavailable result
+/// instead of failing.
 ///
 /// # Arguments
-/// * `config` - Optional TURN server configuration
+/// * `config` - Optional server configuration
 ///
 /// # Returns
 /// * `Ok(PacketLossResult)` - Measurement results (may be unavailable)
@@ -486,7 +716,7 @@ pub async fn run_packet_loss_test(
         }
         None => {
             log::info!(
-                "Packet loss measurement skipped: TURN server not configured"
+                "Packet loss measurement skipped: no server configured"
             );
             Ok(PacketLossResult::unavailable())
         }
@@ -501,7 +731,7 @@ pub async fn run_packet_loss_test(
 /// not cause the overall speed test to fail.
 ///
 /// # Arguments
-/// * `config` - Optional TURN server configuration
+/// * `config` - Optional server configuration
 ///
 /// # Returns
 /// `PacketLossResult` - Always returns a result, never fails
@@ -548,7 +778,8 @@ mod tests {
         let config =
             PacketLossConfig::new("turn:example.com:3478".to_string());
 
-        assert_eq!(config.turn_server_uri, "turn:example.com:3478");
+        assert_eq!(config.server_uri, "turn:example.com:3478");
+        assert_eq!(config.provider, LossProvider::Turn);
         assert_eq!(config.num_packets, PacketLossConfig::DEFAULT_NUM_PACKETS);
         assert_eq!(config.batch_size, PacketLossConfig::DEFAULT_BATCH_SIZE);
         assert_eq!(
@@ -606,6 +837,14 @@ mod tests {
         assert_eq!(result.packets_lost, 0);
     }
 
+    #[test]
+    fn test_batch_loss_ratio() {
+        let batch = BatchLoss { sent: 100, received: 75 };
+        assert_eq!(batch.lost(), 25);
+        assert!((batch.ratio() - 0.25).abs() < 1e-9);
+        assert_eq!(BatchLoss { sent: 0, received: 0 }.ratio(), 0.0);
+    }
+
     #[test]
     fn test_packet_loss_result_zero_packets() {
         let result = PacketLossResult::new(0, 0, None);
@@ -653,106 +892,180 @@ mod tests {
         let _ = calculate_packet_loss_ratio(50, 100);
     }
 
-    // Unit tests for PacketLossTest URI parsing
+    // Unit tests for server URI parsing
+    fn parse(uri: &str) -> Result<(String, u16), PacketLossError> {
+        parse_server_uri(uri, LossProvider::Turn)
+    }
+
     #[test]
     fn test_parse_turn_uri_with_port() {
-        let config =
-            PacketLossConfig::new("turn:example.com:3478".to_string());
-        let test = PacketLossTest::new(config);
-
-        let (host, port) = test.parse_turn_uri().unwrap();
+        let (host, port) = parse("turn:example.com:3478").unwrap();
         assert_eq!(host, "example.com");
         assert_eq!(port, 3478);
     }
 
     #[test]
     fn test_parse_turn_uri_without_port() {
-        let config = PacketLossConfig::new("turn:example.com".to_string());
-        let test = PacketLossTest::new(config);
-
-        let (host, port) = test.parse_turn_uri().unwrap();
+        let (host, port) = parse("turn:example.com").unwrap();
         assert_eq!(host, "example.com");
         assert_eq!(port, 3478); // Default port
     }
 
     #[test]
     fn test_parse_turn_uri_turns_scheme() {
-        let config =
-            PacketLossConfig::new("turns:secure.example.com:5349".to_string());
-        let test = PacketLossTest::new(config);
-
-        let (host, port) = test.parse_turn_uri().unwrap();
+        let (host, port) = parse("turns:secure.example.com:5349").unwrap();
         assert_eq!(host, "secure.example.com");
         assert_eq!(port, 5349);
     }
 
     #[test]
     fn test_parse_turn_uri_no_scheme() {
-        let config = PacketLossConfig::new("example.com:3478".to_string());
-        let test = PacketLossTest::new(config);
-
-        let (host, port) = test.parse_turn_uri().unwrap();
+        let (host, port) = parse("example.com:3478").unwrap();
         assert_eq!(host, "example.com");
         assert_eq!(port, 3478);
     }
 
     #[test]
-    fn test_parse_turn_uri_invalid_port() {
-        let config =
-            PacketLossConfig::new("turn:example.com:invalid".to_string());
-        let test = PacketLossTest::new(config);
+    fn test_parse_turn_uri_transport_suffix() {
+        let (host, port) =
+            parse("turn:example.com:3479?transport=udp").unwrap();
+        assert_eq!(host, "example.com");
+        assert_eq!(port, 3479);
+    }
 
-        let result = test.parse_turn_uri();
-        assert!(result.is_err());
+    #[test]
+    fn test_parse_turn_uri_invalid_port() {
+        assert!(parse("turn:example.com:invalid").is_err());
+        assert!(parse("turn::3478").is_err());
     }
 
-    // Unit tests for packet creation and validation
     #[test]
-    fn test_create_packet() {
-        let config =
-            PacketLossConfig::new("turn:example.com:3478".to_string());
-        let test = PacketLossTest::new(config);
+    fn test_parse_uri_provider_defaults() {
+        let (host, port) =
+            parse_server_uri("[2001:db8::1]", LossProvider::UdpEcho).unwrap();
+        assert_eq!(host, "2001:db8::1");
+        assert_eq!(port, 7);
+
+        let (host, port) =
+            parse_server_uri("quic:speed.cloudflare.com", LossProvider::Quic)
+                .unwrap();
+        assert_eq!(host, "speed.cloudflare.com");
+        assert_eq!(port, 443);
+    }
 
-        let packet = test.create_packet(42);
-        assert_eq!(packet.len(), 16);
+    #[test]
+    fn test_loss_provider_parsing() {
+        assert_eq!("TURN".parse(), Ok(LossProvider::Turn));
+        assert_eq!("udp-echo".parse(), Ok(LossProvider::UdpEcho));
+        assert_eq!("quic".parse(), Ok(LossProvider::Quic));
+        assert!("icmp".parse::<LossProvider>().is_err());
+        assert_eq!(LossProvider::UdpEcho.to_string(), "udp-echo");
+    }
 
-        // Check sequence number
-        let seq_bytes: [u8; 4] = packet[0..4].try_into().unwrap();
-        let seq = u32::from_be_bytes(seq_bytes);
-        assert_eq!(seq, 42);
+    /// Echo every datagram back, dropping each one `drop_every` asks to.
+    async fn echo_server(drop_every: usize) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; MAX_RESPONSE];
+            let mut count = 0;
+            while let Ok((len, from)) = socket.recv_from(&mut buf).await {
+                count += 1;
+                if count % drop_every != 0 {
+                    let _ = socket.send_to(&buf[..len], from).await;
+                }
+            }
+        });
+        addr
     }
 
-    #[test]
-    fn test_validate_response_valid() {
-        let config =
-            PacketLossConfig::new("turn:example.com:3478".to_string());
-        let test = PacketLossTest::new(config);
+    fn echo_config(addr: SocketAddr) -> PacketLossConfig {
+        PacketLossConfig {
+            num_packets: 20,
+            batch_size: 10,
+            batch_wait_time_ms: 0,
+            packet_timeout_ms: 200,
+            ..PacketLossConfig::new(format!("udp:{}", addr))
+                .with_provider(LossProvider::UdpEcho)
+        }
+    }
 
-        let packet = test.create_packet(123);
-        assert!(test.validate_response(&packet, 123));
+    #[tokio::test]
+    async fn test_run_against_udp_echo_server() {
+        let addr = echo_server(5).await;
+        let result =
+            PacketLossTest::new(echo_config(addr)).run().await.unwrap();
+
+        assert_eq!(result.packets_sent, 20);
+        assert_eq!(result.packets_lost, 4);
+        assert!(result.avg_rtt_ms.is_some());
+        assert_eq!(
+            result.batches,
+            vec![BatchLoss { sent: 10, received: 8 }; 2]
+        );
     }
 
-    #[test]
-    fn test_validate_response_wrong_seq() {
-        let config =
-            PacketLossConfig::new("turn:example.com:3478".to_string());
-        let test = PacketLossTest::new(config);
+    #[tokio::test]
+    async fn test_run_counts_reordered_and_duplicate_answers() {
+        // Answer each pair of datagrams swapped, and every fourth twice
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; MAX_RESPONSE];
+            let mut held: Option<Vec<u8>> = None;
+            let mut count = 0;
+            while let Ok((len, from)) = socket.recv_from(&mut buf).await {
+                count += 1;
+                let Some(first) = held.take() else {
+                    held = Some(buf[..len].to_vec());
+                    continue;
+                };
+                let _ = socket.send_to(&buf[..len], from).await;
+                let _ = socket.send_to(&first, from).await;
+                if count % 4 == 0 {
+                    let _ = socket.send_to(&first, from).await;
+                }
+            }
+        });
+
+        let result =
+            PacketLossTest::new(echo_config(addr)).run().await.unwrap();
 
-        let packet = test.create_packet(123);
-        assert!(!test.validate_response(&packet, 456));
+        assert_eq!(result.packets_received, 20);
+        assert_eq!(result.packets_lost, 0);
+        assert_eq!(result.reordered, 10);
+        // The last copy follows the final answer, after collection ends
+        assert_eq!(result.duplicates, 4);
+        assert!((result.reorder_ratio() - 0.5).abs() < 1e-9);
     }
 
     #[test]
-    fn test_validate_response_too_short() {
-        let config =
-            PacketLossConfig::new("turn:example.com:3478".to_string());
-        let test = PacketLossTest::new(config);
+    fn test_arrivals() {
+        let mut arrivals = Arrivals::default();
+        for seq in [0, 2, 1, 3] {
+            assert!(arrivals.record(seq));
+        }
+        assert!(!arrivals.record(2));
+
+        assert_eq!(arrivals.received(), 4);
+        assert_eq!(arrivals.reordered, 1);
+        assert_eq!(arrivals.duplicates, 1);
+    }
 
-        let short_packet = vec![0u8; 3];
-        assert!(!test.validate_response(&short_packet, 0));
+    #[tokio::test]
+    async fn test_run_fails_when_nothing_listens() {
+        // Bind and drop to find a port nothing listens on
+        let addr = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let result = PacketLossTest::new(echo_config(addr)).run().await;
+
+        assert!(matches!(result, Err(PacketLossError::ConnectionFailed(_))));
     }
 
-    // Tests for graceful handling of missing TURN configuration
+    // Tests for graceful handling of missing server configuration
     // Validates: Requirements 7.6
     #[tokio::test]
     async fn test_run_packet_loss_test_no_config() {
@@ -842,7 +1155,7 @@ mod tests {
             let ratio = calculate_packet_loss_ratio(packets_sent, packets_received);
 
             prop_assert!(
-                ratio >= 0.0 && ratio <= 1.0,
+                (0.0..=1.0).contains(&ratio),
                 "Ratio {} should be in [0.0, 1.0] (sent={}, received={})",
                 ratio,
                 packets_sent,
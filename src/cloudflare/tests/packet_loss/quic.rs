@@ -0,0 +1,141 @@
+//! Probes for a QUIC server.
+//!
+//! A PING frame only travels inside an established connection, which
+//! takes a full QUIC and TLS 1.3 stack to set up. A server has to answer
+//! a client Initial packet of a version it doesn't support with a
+//! Version Negotiation packet (RFC 9000, section 6) without keeping any
+//! state, so proposing a reserved version gets one answer per probe from
+//! any QUIC server, Cloudflare's edge included.
+
+use super::LossProbe;
+
+/// A version reserved to force version negotiation (RFC 9000, section
+/// 15), so no server supports it.
+const RESERVED_VERSION: u32 = 0x1a2a_3a4a;
+/// Servers drop client Initial packets shorter than this.
+const MIN_INITIAL_LEN: usize = 1200;
+/// Length of the connection IDs sent.
+const CID_LEN: usize = 8;
+/// Long header form and fixed bits, Initial packet type.
+const INITIAL_FIRST_BYTE: u8 = 0xc0;
+
+/// Probe sending client Initial packets of a reserved version. The
+/// destination connection ID carries the sequence number, and the
+/// server echoes both IDs back swapped.
+#[derive(Debug, Clone)]
+pub struct QuicProbe {
+    /// Source connection ID, and the first half of each destination ID
+    id: [u8; CID_LEN],
+}
+
+impl QuicProbe {
+    pub fn new() -> Self {
+        use ring::rand::{SecureRandom, SystemRandom};
+
+        let mut id = [0_u8; CID_LEN];
+        if SystemRandom::new().fill(&mut id).is_err() {
+            // Only used to tell answers apart; uniqueness is enough
+            let nanos =
+                chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+            id = nanos.to_le_bytes();
+        }
+        Self { id }
+    }
+
+    fn destination_id(&self, seq: u32) -> [u8; CID_LEN] {
+        let mut id = [0_u8; CID_LEN];
+        id[..4].copy_from_slice(&self.id[..4]);
+        id[4..].copy_from_slice(&seq.to_be_bytes());
+        id
+    }
+}
+
+impl Default for QuicProbe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LossProbe for QuicProbe {
+    /// An Initial packet header padded to the minimum size; past the
+    /// version the server reads nothing but the connection IDs.
+    fn request(&self, seq: u32) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(MIN_INITIAL_LEN);
+        packet.push(INITIAL_FIRST_BYTE);
+        packet.extend_from_slice(&RESERVED_VERSION.to_be_bytes());
+        packet.push(CID_LEN as u8);
+        packet.extend_from_slice(&self.destination_id(seq));
+        packet.push(CID_LEN as u8);
+        packet.extend_from_slice(&self.id);
+        packet.resize(MIN_INITIAL_LEN, 0);
+        packet
+    }
+
+    /// A Version Negotiation packet addressed to our source ID from one
+    /// of our destination IDs.
+    fn sequence(&self, response: &[u8]) -> Option<u32> {
+        // Long header form bit, then version 0
+        if response.first()? & 0x80 == 0 || response.get(1..5)? != [0; 4] {
+            return None;
+        }
+        let mut rest = &response[5..];
+        let mut next_id = || {
+            let (&len, tail) = rest.split_first()?;
+            let id = tail.get(..len as usize)?;
+            rest = &tail[len as usize..];
+            Some(id)
+        };
+        let (destination, source) = (next_id()?, next_id()?);
+        if destination != self.id
+            || source.len() != CID_LEN
+            || source[..4] != self.id[..4]
+        {
+            return None;
+        }
+        Some(u32::from_be_bytes(source[4..].try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The Version Negotiation packet a server sends for `request`,
+    /// offering QUIC v1.
+    fn negotiation_for(request: &[u8]) -> Vec<u8> {
+        let destination = &request[6..6 + CID_LEN];
+        let source = &request[7 + CID_LEN..7 + 2 * CID_LEN];
+
+        let mut response = vec![0x80 | 0x3f, 0, 0, 0, 0];
+        response.push(CID_LEN as u8);
+        response.extend_from_slice(source);
+        response.push(CID_LEN as u8);
+        response.extend_from_slice(destination);
+        response.extend_from_slice(&1_u32.to_be_bytes());
+        response
+    }
+
+    #[test]
+    fn test_initial_packet_format() {
+        let probe = QuicProbe::new();
+        let request = probe.request(3);
+
+        assert_eq!(request.len(), MIN_INITIAL_LEN);
+        assert_eq!(request[0], INITIAL_FIRST_BYTE);
+        assert_eq!(&request[1..5], &RESERVED_VERSION.to_be_bytes());
+        assert_eq!(request[5] as usize, CID_LEN);
+        assert_eq!(&request[10..14], &3_u32.to_be_bytes());
+    }
+
+    #[test]
+    fn test_sequence_of_version_negotiation() {
+        let probe = QuicProbe::new();
+        let response = negotiation_for(&probe.request(3));
+
+        assert_eq!(probe.sequence(&response), Some(3));
+        // Not a version negotiation
+        assert_eq!(probe.sequence(&probe.request(3)), None);
+        // Truncated connection IDs
+        assert_eq!(probe.sequence(&response[..10]), None);
+    }
+}
@@ -0,0 +1,122 @@
+//! Probes for a TURN server.
+//!
+//! Relaying needs an allocation and long-term credentials, but every TURN
+//! server is also a STUN server and answers a Binding request (RFC 8489)
+//! from anyone. A Binding round trip crosses the same path a relayed
+//! packet's first leg would, so it's what's measured.
+
+use super::LossProbe;
+
+/// STUN message type of a Binding request.
+const BINDING_REQUEST: u16 = 0x0001;
+/// STUN message types of a Binding success and error response.
+const BINDING_RESPONSES: [u16; 2] = [0x0101, 0x0111];
+/// Fixed value in every STUN header.
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+/// Length of the STUN header.
+const HEADER_LEN: usize = 20;
+
+/// Probe sending STUN Binding requests. The transaction ID of each is a
+/// random per-run prefix followed by the sequence number, so answers to
+/// another client can't be mistaken for ours.
+#[derive(Debug, Clone)]
+pub struct TurnProbe {
+    prefix: [u8; 8],
+}
+
+impl TurnProbe {
+    pub fn new() -> Self {
+        use ring::rand::{SecureRandom, SystemRandom};
+
+        let mut prefix = [0_u8; 8];
+        if SystemRandom::new().fill(&mut prefix).is_err() {
+            // Only used to tell answers apart; uniqueness is enough
+            let nanos =
+                chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+            prefix = nanos.to_le_bytes();
+        }
+        Self { prefix }
+    }
+
+    fn transaction_id(&self, seq: u32) -> [u8; 12] {
+        let mut id = [0_u8; 12];
+        id[..8].copy_from_slice(&self.prefix);
+        id[8..].copy_from_slice(&seq.to_be_bytes());
+        id
+    }
+}
+
+impl Default for TurnProbe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LossProbe for TurnProbe {
+    /// A Binding request without attributes.
+    fn request(&self, seq: u32) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(HEADER_LEN);
+        packet.extend_from_slice(&BINDING_REQUEST.to_be_bytes());
+        packet.extend_from_slice(&0_u16.to_be_bytes());
+        packet.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        packet.extend_from_slice(&self.transaction_id(seq));
+        packet
+    }
+
+    /// Any Binding response to one of our requests counts, an error one
+    /// too: the round trip completed either way.
+    fn sequence(&self, response: &[u8]) -> Option<u32> {
+        let header = response.get(..HEADER_LEN)?;
+        let message_type = u16::from_be_bytes([header[0], header[1]]);
+        if !BINDING_RESPONSES.contains(&message_type)
+            || header[4..8] != MAGIC_COOKIE.to_be_bytes()
+            || header[8..16] != self.prefix
+        {
+            return None;
+        }
+        Some(u32::from_be_bytes(header[16..20].try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Binding success response to `request` with an
+    /// XOR-MAPPED-ADDRESS attribute, as a server would send.
+    fn response_to(request: &[u8]) -> Vec<u8> {
+        let mut response = request.to_vec();
+        response[0..2].copy_from_slice(&0x0101_u16.to_be_bytes());
+        response[2..4].copy_from_slice(&12_u16.to_be_bytes());
+        response.extend_from_slice(&[
+            0x00, 0x20, 0x00, 0x08, 0x00, 0x01, 0xA1, 0x47, 0xE1, 0x12, 0xA6,
+            0x43,
+        ]);
+        response
+    }
+
+    #[test]
+    fn test_binding_request_format() {
+        let probe = TurnProbe::new();
+        let request = probe.request(7);
+
+        assert_eq!(request.len(), HEADER_LEN);
+        assert_eq!(&request[0..2], &[0x00, 0x01]);
+        assert_eq!(&request[2..4], &[0x00, 0x00]);
+        assert_eq!(&request[4..8], &[0x21, 0x12, 0xA4, 0x42]);
+        assert_eq!(&request[16..20], &7_u32.to_be_bytes());
+    }
+
+    #[test]
+    fn test_sequence_of_binding_response() {
+        let probe = TurnProbe::new();
+        let response = response_to(&probe.request(7));
+
+        assert_eq!(probe.sequence(&response), Some(7));
+        // Another client's transaction
+        assert_eq!(TurnProbe { prefix: [1; 8] }.sequence(&response), None);
+        // The request echoed back isn't an answer
+        assert_eq!(probe.sequence(&probe.request(7)), None);
+        assert_eq!(probe.sequence(&response[..HEADER_LEN - 1]), None);
+    }
+}
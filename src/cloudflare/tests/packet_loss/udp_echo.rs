@@ -0,0 +1,73 @@
+//! Probes for a plain UDP echo server (RFC 862), which sends every
+//! datagram back as it came.
+
+use super::LossProbe;
+
+/// Probe whose datagrams carry their sequence number for the echo to
+/// return.
+///
+/// The packet format is simple:
+/// - 4 bytes: sequence number (big-endian)
+/// - 8 bytes: timestamp (big-endian, microseconds since epoch)
+/// - 4 bytes: padding
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UdpEchoProbe;
+
+impl LossProbe for UdpEchoProbe {
+    fn request(&self, seq: u32) -> Vec<u8> {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let mut packet = Vec::with_capacity(16);
+
+        // Sequence number (4 bytes, big-endian)
+        packet.extend_from_slice(&seq.to_be_bytes());
+
+        // Timestamp (8 bytes, big-endian); informational only, RTTs are
+        // timed with Instant
+        #[allow(clippy::disallowed_methods)]
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        packet.extend_from_slice(&timestamp.to_be_bytes());
+
+        // Padding (4 bytes)
+        packet.extend_from_slice(&[0u8; 4]);
+
+        packet
+    }
+
+    /// An echo is the request itself, starting with the sequence number.
+    fn sequence(&self, response: &[u8]) -> Option<u32> {
+        let seq = response.get(0..4)?.try_into().ok()?;
+        Some(u32::from_be_bytes(seq))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_packet() {
+        let packet = UdpEchoProbe.request(42);
+        assert_eq!(packet.len(), 16);
+
+        // Check sequence number
+        let seq_bytes: [u8; 4] = packet[0..4].try_into().unwrap();
+        let seq = u32::from_be_bytes(seq_bytes);
+        assert_eq!(seq, 42);
+    }
+
+    #[test]
+    fn test_validate_response_valid() {
+        let packet = UdpEchoProbe.request(123);
+        assert_eq!(UdpEchoProbe.sequence(&packet), Some(123));
+    }
+
+    #[test]
+    fn test_validate_response_too_short() {
+        let short_packet = vec![0u8; 3];
+        assert_eq!(UdpEchoProbe.sequence(&short_packet), None);
+    }
+}
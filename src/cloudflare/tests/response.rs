@@ -0,0 +1,687 @@
+//! Reading raw HTTP/1.1 responses from measurement connections.
+//!
+//! Downloads and uploads speak HTTP over a plain socket, so the response
+//! is parsed here: the head is read byte by byte up to the blank line, and
+//! the body is drained according to its framing. A body that ends before
+//! its `Content-Length` or final chunk is an error rather than a short
+//! transfer timed as a complete one.
+
+use crate::cloudflare::tests::sink::DiscardSink;
+use crate::cloudflare::tests::stall::{drain, stalled, TransferProgress};
+use crate::cloudflare::tests::{extract_http_headers, extract_http_status};
+use http::header::{
+    HeaderMap, CONTENT_LENGTH, LOCATION, RETRY_AFTER, TRANSFER_ENCODING,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Longest response head accepted, to bound reads from a broken server.
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+
+/// Number of body bytes kept for a [`BodyDiagnostic`].
+const DIAGNOSTIC_BYTES: usize = 64;
+
+/// Statuses of redirects that are followed.
+const REDIRECT_STATUSES: [u16; 5] = [301, 302, 303, 307, 308];
+
+/// A redirect in answer to a measurement request.
+///
+/// Raised as an error, so the transfer can be made again at `location`
+/// over a new connection rather than timing the redirect's body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Redirect {
+    /// Status of the redirect response
+    pub status: u16,
+    /// Target the server gave, possibly relative to the request URL
+    pub location: String,
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP {} redirect to {}", self.status, self.location)
+    }
+}
+
+impl Error for Redirect {}
+
+/// A response head read from the connection.
+#[derive(Debug)]
+pub(crate) enum ResponseHead {
+    /// An interim `1xx` response, such as `100 Continue`, with its status
+    Interim(u16),
+    /// The headers of the final `200 OK` response
+    Final(HeaderMap),
+}
+
+/// Read the rest of a response head whose first byte has already been
+/// read, and check that the response is a `200 OK`.
+///
+/// Any other status is an error naming it (`HTTP 429 from speed test
+/// server`), along with the server's `Retry-After` in seconds when it
+/// gave one, so the retry logic can classify it and back off. A redirect
+/// with a `Location` is a [`Redirect`] error.
+///
+/// # Returns
+/// The response headers
+pub(crate) fn read_head(
+    reader: &mut impl Read,
+    first_byte: u8,
+) -> Result<HeaderMap, Box<dyn Error + Send + Sync>> {
+    match read_response_head(reader, first_byte)? {
+        ResponseHead::Final(headers) => Ok(headers),
+        ResponseHead::Interim(status) => {
+            Err(format!("HTTP {status} from speed test server").into())
+        }
+    }
+}
+
+/// Like [`read_head`], but an interim `1xx` response is returned rather
+/// than treated as an error, for requests that expect one.
+pub(crate) fn read_response_head(
+    reader: &mut impl Read,
+    first_byte: u8,
+) -> Result<ResponseHead, Box<dyn Error + Send + Sync>> {
+    let mut head = vec![first_byte];
+    let mut byte = [0_u8];
+
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_HEAD_BYTES {
+            return Err("HTTP response headers are too long".into());
+        }
+        match reader.read(&mut byte) {
+            Ok(0) => {
+                return Err("Connection closed before the end of the HTTP \
+                            response headers"
+                    .into())
+            }
+            Ok(_) => head.push(byte[0]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(stalled(e).into()),
+        }
+    }
+
+    let head = String::from_utf8(head)
+        .map_err(|e| format!("Invalid UTF-8 in HTTP headers: {}", e))?;
+
+    // Check HTTP status code before processing body
+    let status = extract_http_status(&head)
+        .ok_or("Malformed HTTP response from speed test server")?;
+    if (100..200).contains(&status) {
+        return Ok(ResponseHead::Interim(status));
+    }
+    let headers = extract_http_headers(&head);
+    if let Some(location) = headers
+        .get(LOCATION)
+        .filter(|_| REDIRECT_STATUSES.contains(&status))
+        .and_then(|value| value.to_str().ok())
+    {
+        return Err(Box::new(Redirect {
+            status,
+            location: location.trim().to_string(),
+        }));
+    }
+    if status != 200 {
+        let retry_after = headers
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok());
+        return Err(match retry_after {
+            Some(secs) => format!(
+                "HTTP {status} from speed test server (retry after {secs}s)"
+            ),
+            None => format!("HTTP {status} from speed test server"),
+        }
+        .into());
+    }
+
+    Ok(ResponseHead::Final(headers))
+}
+
+/// Explain a failed request body write by the response the server sent
+/// before closing the connection, if it sent one.
+///
+/// Servers reject an upload they won't accept (rate limited, too large)
+/// without reading the rest of its body, so the write fails with a reset
+/// or broken pipe while the reason is still waiting to be read. An
+/// interim `100 Continue` ahead of the rejection is skipped.
+pub(crate) fn early_response(
+    reader: &mut impl Read,
+) -> Option<Box<dyn Error + Send + Sync>> {
+    let mut first_byte = [0_u8];
+    loop {
+        match reader.read(&mut first_byte) {
+            Ok(1) => {}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            _ => return None,
+        }
+        return Some(match read_response_head(reader, first_byte[0]) {
+            Ok(ResponseHead::Interim(_)) => continue,
+            Ok(ResponseHead::Final(_)) => {
+                "Server responded before the request body was sent".into()
+            }
+            Err(e) => e,
+        });
+    }
+}
+
+/// How the end of a response body is marked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyFraming {
+    /// `Content-Length`
+    ContentLength,
+    /// `Transfer-Encoding: chunked`
+    Chunked,
+    /// The connection closing
+    Close,
+}
+
+/// A response whose body didn't match its declared or requested size,
+/// described well enough to tell what the server actually sent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BodyDiagnostic {
+    /// How the body's end was marked
+    pub framing: BodyFraming,
+    /// Body bytes received, excluding chunk framing
+    pub received: u64,
+    /// Length the response declared, as far as it got: its
+    /// `Content-Length`, or the chunk sizes it announced
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub declared: Option<u64>,
+    /// Bytes the request asked for, when it named a size
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requested: Option<u64>,
+    /// Headers of the response
+    pub headers: BTreeMap<String, String>,
+    /// First bytes of the body as received, chunk framing included, with
+    /// anything but printable ASCII escaped
+    pub first_bytes: String,
+}
+
+/// A body that didn't match its size, as an error carrying its
+/// [`BodyDiagnostic`].
+#[derive(Debug, Clone)]
+pub struct BodyMismatch {
+    message: String,
+    pub diagnostic: BodyDiagnostic,
+}
+
+impl BodyMismatch {
+    /// The diagnostic `error` carries, when it is a body mismatch, or an
+    /// I/O error wrapping one.
+    pub fn diagnostic_of<'a>(
+        error: &'a (dyn Error + 'static),
+    ) -> Option<&'a BodyDiagnostic> {
+        let inner = error
+            .downcast_ref::<io::Error>()
+            .and_then(|e| e.get_ref())
+            .map(|e| e as &(dyn Error + 'static));
+        inner
+            .unwrap_or(error)
+            .downcast_ref::<BodyMismatch>()
+            .map(|mismatch| &mismatch.diagnostic)
+    }
+}
+
+impl fmt::Display for BodyMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for BodyMismatch {}
+
+/// Body bytes read, against what the response declared for them.
+struct Received {
+    bytes: u64,
+    declared: Option<u64>,
+    /// Whether the body reached the end its framing marks
+    complete: bool,
+}
+
+/// Read a response body to its end, discarding it and recording progress.
+///
+/// The body is framed by `Transfer-Encoding: chunked`, `Content-Length`,
+/// or otherwise the connection closing. A body that ends early, or whose
+/// size differs from the `requested` one, is a [`BodyMismatch`]. The body,
+/// without chunk framing, goes to `sink`.
+///
+/// # Returns
+/// The number of body bytes received, excluding chunk framing
+pub(crate) fn read_body(
+    reader: &mut impl Read,
+    headers: &HeaderMap,
+    requested: Option<u64>,
+    sink: &mut DiscardSink,
+    progress: &TransferProgress,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let chunked = headers
+        .get(TRANSFER_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.to_ascii_lowercase().contains("chunked"));
+
+    let length = headers
+        .get(CONTENT_LENGTH)
+        .filter(|_| !chunked)
+        .map(|value| {
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .ok_or("Malformed Content-Length in HTTP response")
+        })
+        .transpose()?;
+
+    let mut reader = FirstBytes { inner: reader, first: Vec::new() };
+    let (framing, received) = match length {
+        _ if chunked => (
+            BodyFraming::Chunked,
+            read_chunked(&mut BufReader::new(&mut reader), sink, progress)?,
+        ),
+        Some(length) => {
+            let bytes =
+                drain(&mut (&mut reader).take(length), sink, progress)?;
+            let complete = bytes == length;
+            let declared = Some(length);
+            (
+                BodyFraming::ContentLength,
+                Received { bytes, declared, complete },
+            )
+        }
+        None => {
+            let bytes = drain(&mut reader, sink, progress)?;
+            let received = Received { bytes, declared: None, complete: true };
+            (BodyFraming::Close, received)
+        }
+    };
+
+    let message = match (received.declared, requested) {
+        _ if !received.complete => match received.declared {
+            Some(declared) => format!(
+                "Connection closed early: received {} of {} response bytes",
+                received.bytes, declared
+            ),
+            None => "Connection closed before the final HTTP chunk".into(),
+        },
+        (_, Some(requested)) if requested != received.bytes => format!(
+            "Response body has {} bytes, but {} were requested",
+            received.bytes, requested
+        ),
+        _ => return Ok(received.bytes),
+    };
+
+    Err(Box::new(BodyMismatch {
+        message,
+        diagnostic: BodyDiagnostic {
+            framing,
+            received: received.bytes,
+            declared: received.declared,
+            requested,
+            headers: headers
+                .iter()
+                .map(|(name, value)| {
+                    let value = String::from_utf8_lossy(value.as_bytes());
+                    (name.to_string(), value.into_owned())
+                })
+                .collect(),
+            first_bytes: reader.first.escape_ascii().to_string(),
+        },
+    }))
+}
+
+/// Read a chunked body through its final zero-length chunk and trailers.
+fn read_chunked(
+    reader: &mut impl BufRead,
+    sink: &mut DiscardSink,
+    progress: &TransferProgress,
+) -> Result<Received, Box<dyn Error + Send + Sync>> {
+    let mut total = 0;
+
+    loop {
+        let Some(line) = read_line(reader)? else {
+            return Ok(Received {
+                bytes: total,
+                declared: None,
+                complete: false,
+            });
+        };
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = u64::from_str_radix(size, 16)
+            .map_err(|_| format!("Malformed HTTP chunk size {:?}", line))?;
+        if size == 0 {
+            break;
+        }
+
+        let received = drain(&mut reader.take(size), sink, progress)?;
+        total += received;
+        if received < size {
+            return Ok(Received {
+                bytes: total,
+                declared: Some(total - received + size),
+                complete: false,
+            });
+        }
+        // Each chunk's data is followed by a CRLF
+        read_line(reader)?;
+    }
+
+    // Trailers, if any, end with a blank line
+    while let Some(line) = read_line(reader)? {
+        if line.is_empty() {
+            break;
+        }
+    }
+
+    Ok(Received { bytes: total, declared: Some(total), complete: true })
+}
+
+/// Read one CRLF-terminated line, without the line ending, or `None` at
+/// the end of the stream.
+fn read_line(
+    reader: &mut impl BufRead,
+) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).map_err(stalled)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// Reader keeping the first [`DIAGNOSTIC_BYTES`] bytes read through it.
+struct FirstBytes<R> {
+    inner: R,
+    first: Vec<u8>,
+}
+
+impl<R: Read> Read for FirstBytes<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let keep = DIAGNOSTIC_BYTES.saturating_sub(self.first.len()).min(n);
+        self.first.extend_from_slice(&buf[..keep]);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Recorded responses in `fixtures/`, replayed through the same
+    //! parsing the downloader and uploader use.
+
+    use super::*;
+    use crate::cloudflare::tests::EdgeHeaders;
+    use crate::errors::{classify_error, ErrorKind};
+
+    const DOWNLOAD: &[u8] = include_bytes!("fixtures/download.http");
+    const DOWNLOAD_CHUNKED: &[u8] =
+        include_bytes!("fixtures/download_chunked.http");
+    const DOWNLOAD_UNTIL_CLOSE: &[u8] =
+        include_bytes!("fixtures/download_until_close.http");
+    const DOWNLOAD_EARLY_CLOSE: &[u8] =
+        include_bytes!("fixtures/download_early_close.http");
+    const DOWNLOAD_CHUNKED_EARLY_CLOSE: &[u8] =
+        include_bytes!("fixtures/download_chunked_early_close.http");
+    const UPLOAD: &[u8] = include_bytes!("fixtures/upload.http");
+    const RATE_LIMITED: &[u8] = include_bytes!("fixtures/rate_limited.http");
+    const REDIRECT: &[u8] = include_bytes!("fixtures/redirect.http");
+    const DOWNLOAD_SHORT_CHUNKED: &[u8] =
+        include_bytes!("fixtures/download_short_chunked.http");
+
+    /// Reader that hands out a recorded response at most `step` bytes at
+    /// a time, interrupting every other read like a slow trickle of
+    /// packets.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        step: usize,
+        interrupt: bool,
+    }
+
+    impl<'a> Trickle<'a> {
+        fn new(data: &'a [u8], step: usize) -> Self {
+            Self { data, step, interrupt: false }
+        }
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt && !self.data.is_empty() {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            let n = self.step.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    /// Replay `fixture` the way a transfer reads it: the first byte for
+    /// TTFB, then the head, then the body.
+    fn replay(
+        fixture: &[u8],
+        step: usize,
+    ) -> Result<(HeaderMap, u64), Box<dyn Error + Send + Sync>> {
+        replay_requesting(fixture, step, None)
+    }
+
+    /// Like [`replay`], for a request of `requested` bytes.
+    fn replay_requesting(
+        fixture: &[u8],
+        step: usize,
+        requested: Option<u64>,
+    ) -> Result<(HeaderMap, u64), Box<dyn Error + Send + Sync>> {
+        let mut reader = Trickle::new(fixture, step);
+        let mut first = [0_u8];
+        reader.read_exact(&mut first)?;
+        let headers = read_head(&mut reader, first[0])?;
+        let body = read_body(
+            &mut reader,
+            &headers,
+            requested,
+            &mut DiscardSink::new(),
+            &Default::default(),
+        )?;
+        Ok((headers, body))
+    }
+
+    /// The diagnostic of a failed replay.
+    fn diagnose(
+        result: Result<(HeaderMap, u64), Box<dyn Error + Send + Sync>>,
+    ) -> BodyDiagnostic {
+        let error = result.unwrap_err();
+        BodyMismatch::diagnostic_of(error.as_ref()).unwrap().clone()
+    }
+
+    /// Replay `fixture` both in one go and a byte at a time.
+    fn replay_all(
+        fixture: &[u8],
+    ) -> Vec<Result<(HeaderMap, u64), Box<dyn Error + Send + Sync>>> {
+        [usize::MAX, 1].map(|step| replay(fixture, step)).into()
+    }
+
+    #[test]
+    fn test_download_with_content_length() {
+        for result in replay_all(DOWNLOAD) {
+            let (headers, body) = result.unwrap();
+            assert_eq!(body, 1000);
+            assert!(headers.contains_key("server-timing"));
+            let edge = EdgeHeaders::from_headers(&headers).unwrap();
+            assert_eq!(edge.ray.as_deref(), Some("8d2f5a0e4b9c1a2e-DFW"));
+        }
+    }
+
+    #[test]
+    fn test_download_chunked() {
+        for result in replay_all(DOWNLOAD_CHUNKED) {
+            assert_eq!(result.unwrap().1, 1000);
+        }
+    }
+
+    #[test]
+    fn test_download_until_close() {
+        for result in replay_all(DOWNLOAD_UNTIL_CLOSE) {
+            assert_eq!(result.unwrap().1, 1000);
+        }
+    }
+
+    #[test]
+    fn test_download_early_close_is_an_error() {
+        for fixture in [DOWNLOAD_EARLY_CLOSE, DOWNLOAD_CHUNKED_EARLY_CLOSE] {
+            for result in replay_all(fixture) {
+                let error = result.unwrap_err().to_string();
+                assert!(
+                    error.contains("closed early")
+                        || error.contains("final HTTP chunk"),
+                    "{}",
+                    error
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_body_matches_requested_size() {
+        for fixture in [DOWNLOAD, DOWNLOAD_CHUNKED, DOWNLOAD_UNTIL_CLOSE] {
+            let (_, body) =
+                replay_requesting(fixture, usize::MAX, Some(1000)).unwrap();
+            assert_eq!(body, 1000);
+        }
+
+        let error = replay_requesting(DOWNLOAD, 1, Some(2000)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Response body has 1000 bytes, but 2000 were requested"
+        );
+    }
+
+    #[test]
+    fn test_short_chunked_body_is_diagnosed() {
+        for step in [usize::MAX, 1] {
+            let result =
+                replay_requesting(DOWNLOAD_SHORT_CHUNKED, step, Some(1000));
+            let diagnostic = diagnose(result);
+            assert_eq!(diagnostic.framing, BodyFraming::Chunked);
+            assert_eq!(diagnostic.received, 1);
+            assert_eq!(diagnostic.declared, Some(1));
+            assert_eq!(diagnostic.requested, Some(1000));
+            assert_eq!(diagnostic.headers["transfer-encoding"], "chunked");
+            assert_eq!(diagnostic.first_bytes, "1\\r\\n0\\r\\n0\\r\\n\\r\\n");
+        }
+    }
+
+    #[test]
+    fn test_early_close_is_diagnosed() {
+        let diagnostic = diagnose(replay(DOWNLOAD_EARLY_CLOSE, usize::MAX));
+        assert_eq!(diagnostic.framing, BodyFraming::ContentLength);
+        assert_eq!(diagnostic.declared, Some(1000));
+        assert!(diagnostic.received < 1000);
+        assert_eq!(diagnostic.first_bytes.len(), DIAGNOSTIC_BYTES);
+
+        let diagnostic = diagnose(replay(DOWNLOAD_CHUNKED_EARLY_CLOSE, 1));
+        assert_eq!(diagnostic.framing, BodyFraming::Chunked);
+
+        // The diagnostic survives being wrapped for the retry logic
+        let error = replay(DOWNLOAD_EARLY_CLOSE, usize::MAX).unwrap_err();
+        let mismatch = error.downcast::<BodyMismatch>().unwrap();
+        let wrapped = io::Error::other(*mismatch);
+        assert!(BodyMismatch::diagnostic_of(&wrapped).is_some());
+        assert!(BodyMismatch::diagnostic_of(&io::Error::other("x")).is_none());
+    }
+
+    #[test]
+    fn test_upload_response() {
+        for result in replay_all(UPLOAD) {
+            let (headers, body) = result.unwrap();
+            assert_eq!(body, 0);
+            let edge = EdgeHeaders::from_headers(&headers).unwrap();
+            assert_eq!(edge.cache_status.as_deref(), Some("DYNAMIC"));
+        }
+    }
+
+    #[test]
+    fn test_rate_limited_response() {
+        for result in replay_all(RATE_LIMITED) {
+            let error = result.unwrap_err();
+            assert_eq!(error.to_string(), "HTTP 429 from speed test server");
+        }
+    }
+
+    #[test]
+    fn test_redirect_response() {
+        for result in replay_all(REDIRECT) {
+            let error = result.unwrap_err();
+            let redirect = error.downcast_ref::<Redirect>().unwrap();
+            assert_eq!(redirect.status, 301);
+            assert_eq!(
+                redirect.location,
+                "https://speed.cloudflare.com/v2/__down?bytes=1000"
+            );
+        }
+
+        // Without a target there's nothing to follow
+        let fixture = String::from_utf8_lossy(REDIRECT)
+            .replace("Location", "X-Location");
+        let error = replay(fixture.as_bytes(), usize::MAX).unwrap_err();
+        assert_eq!(error.to_string(), "HTTP 301 from speed test server");
+    }
+
+    #[test]
+    fn test_retry_after_is_reported() {
+        let fixture = String::from_utf8_lossy(RATE_LIMITED)
+            .replace("Connection: close", "Retry-After: 30");
+        let error = replay(fixture.as_bytes(), 1).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "HTTP 429 from speed test server (retry after 30s)"
+        );
+    }
+
+    #[test]
+    fn test_early_response_explains_failed_write() {
+        let mut reader = Trickle::new(RATE_LIMITED, usize::MAX);
+        let error = early_response(&mut reader).unwrap();
+        assert_eq!(error.to_string(), "HTTP 429 from speed test server");
+
+        assert!(early_response(&mut Trickle::new(&[], 1)).is_none());
+    }
+
+    #[test]
+    fn test_head_cut_short() {
+        let head = &DOWNLOAD[..40];
+        let error = replay(head, usize::MAX).unwrap_err().to_string();
+        assert!(error.contains("end of the HTTP response headers"));
+    }
+
+    #[test]
+    fn test_stalled_body_is_a_timeout() {
+        let head_len = DOWNLOAD
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .unwrap()
+            + 4;
+        let mut reader = Trickle::new(&DOWNLOAD[1..head_len], usize::MAX)
+            .chain(&DOWNLOAD[head_len..head_len + 10])
+            .chain(WouldBlock);
+        let headers = read_head(&mut reader, DOWNLOAD[0]).unwrap();
+        let error = read_body(
+            &mut reader,
+            &headers,
+            None,
+            &mut DiscardSink::new(),
+            &Default::default(),
+        )
+        .unwrap_err();
+        assert_eq!(classify_error(error.as_ref()), ErrorKind::Timeout);
+    }
+
+    /// Reader whose socket timed out.
+    struct WouldBlock;
+
+    impl Read for WouldBlock {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Where downloaded payloads go.
+//!
+//! A measurement only needs to know how many bytes arrived and when, so
+//! response bodies are read through one fixed buffer on the stack and
+//! dropped there: they never reach disk, and nothing allocated grows with
+//! the payload. A [`DiscardSink`] counts what passes through it, and can
+//! hash it to check what the server actually sent.
+
+use ring::digest::{Context, SHA256};
+use std::fmt;
+use std::io::{self, Write};
+
+/// Writer that counts the bytes written to it, optionally hashing them,
+/// and discards them.
+#[derive(Clone, Default)]
+pub struct DiscardSink {
+    bytes: u64,
+    hash: Option<Context>,
+}
+
+impl DiscardSink {
+    /// A sink that only counts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also hash everything written, with SHA-256, when `hash` is set.
+    pub fn with_hash(mut self, hash: bool) -> Self {
+        self.hash = hash.then(|| Context::new(&SHA256));
+        self
+    }
+
+    /// Number of bytes written so far.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Hex SHA-256 of everything written so far, when hashing.
+    pub fn digest(&self) -> Option<String> {
+        let digest = self.hash.clone()?.finish();
+        Some(digest.as_ref().iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+impl Write for DiscardSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes += buf.len() as u64;
+        if let Some(ref mut hash) = self.hash {
+            hash.update(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for DiscardSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DiscardSink")
+            .field("bytes", &self.bytes)
+            .field("hashing", &self.hash.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_without_hashing() {
+        let mut sink = DiscardSink::new();
+        sink.write_all(&[b'0'; 1000]).unwrap();
+        sink.write_all(b"").unwrap();
+        assert_eq!(sink.bytes(), 1000);
+        assert_eq!(sink.digest(), None);
+    }
+
+    #[test]
+    fn test_hashes_across_writes() {
+        let mut sink = DiscardSink::new().with_hash(true);
+        sink.write_all(b"ab").unwrap();
+        sink.write_all(b"c").unwrap();
+        assert_eq!(sink.bytes(), 3);
+        assert_eq!(
+            sink.digest().as_deref(),
+            Some(
+                "ba7816bf8f01cfea414140de5dae2223\
+                 b00361a396177a9cb410ff61f20015ad"
+            )
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Stall detection for bandwidth transfers.
+//!
+//! A transfer that stops receiving data sits in a blocking read until the
+//! server or the OS gives up, which can take minutes. Transfers record when
+//! they last moved bytes in a [`TransferProgress`], so the engine can warn
+//! about a stall while it happens and, if asked to, abort the transfer so
+//! the measurement is retried.
+
+use crate::cloudflare::tests::sink::DiscardSink;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long a transfer may go without moving data before it counts as
+/// stalled.
+pub const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Size of the buffer response bodies are drained through.
+const DRAIN_BUFFER_BYTES: usize = 64 * 1024;
+
+/// When a transfer last moved data, shared between the transfer and
+/// whoever is watching it.
+#[derive(Debug, Clone)]
+pub(crate) struct TransferProgress {
+    start: Instant,
+    /// Milliseconds after `start` of the last progress
+    last_ms: Arc<AtomicU64>,
+}
+
+impl Default for TransferProgress {
+    fn default() -> Self {
+        Self { start: Instant::now(), last_ms: Arc::new(AtomicU64::new(0)) }
+    }
+}
+
+impl TransferProgress {
+    /// Record that the transfer moved data just now.
+    pub fn touch(&self) {
+        let elapsed_ms = self.start.elapsed().as_millis() as u64;
+        self.last_ms.store(elapsed_ms, Ordering::Relaxed);
+    }
+
+    /// Time since the transfer last moved data.
+    pub fn idle(&self) -> Duration {
+        let last = Duration::from_millis(self.last_ms.load(Ordering::Relaxed));
+        self.start.elapsed().saturating_sub(last)
+    }
+}
+
+/// Read `reader` to the end into `sink`, recording progress after every
+/// read. Returns the number of bytes read.
+///
+/// Reads go through a buffer on the stack, so draining allocates nothing.
+pub(crate) fn drain(
+    reader: &mut impl Read,
+    sink: &mut DiscardSink,
+    progress: &TransferProgress,
+) -> io::Result<u64> {
+    let mut buffer = [0_u8; DRAIN_BUFFER_BYTES];
+    let start = sink.bytes();
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(sink.bytes() - start),
+            Ok(n) => {
+                sink.write_all(&buffer[..n])?;
+                progress.touch();
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(stalled(e)),
+        }
+    }
+}
+
+/// Report a socket timeout as a stalled transfer, so that it reads as a
+/// timeout rather than "resource temporarily unavailable".
+pub(crate) fn stalled(error: io::Error) -> io::Error {
+    match error.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => io::Error::new(
+            io::ErrorKind::TimedOut,
+            "transfer stalled: timed out waiting for data",
+        ),
+        _ => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{classify_error, ErrorKind};
+
+    /// Reader that returns its chunks one read at a time.
+    struct Chunks(Vec<io::Result<Vec<u8>>>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Ok(0);
+            }
+            let chunk = self.0.remove(0)?;
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn test_drain_counts_bytes_and_records_progress() {
+        let progress = TransferProgress::default();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(progress.idle() >= Duration::from_millis(20));
+
+        let mut reader = Chunks(vec![Ok(vec![1; 10]), Ok(vec![2; 5])]);
+        let mut sink = DiscardSink::new();
+        assert_eq!(drain(&mut reader, &mut sink, &progress).unwrap(), 15);
+        assert_eq!(sink.bytes(), 15);
+        assert!(progress.idle() < Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_drain_reports_socket_timeout_as_stall() {
+        let progress = TransferProgress::default();
+        let mut reader = Chunks(vec![
+            Ok(vec![1; 10]),
+            Err(io::Error::from(io::ErrorKind::WouldBlock)),
+        ]);
+
+        let error = drain(&mut reader, &mut DiscardSink::new(), &progress)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(error.to_string().contains("stalled"));
+        assert_eq!(classify_error(&error), ErrorKind::Timeout);
+    }
+}
@@ -0,0 +1,208 @@
+//! Kernel TCP statistics for a test connection.
+//!
+//! On Linux the kernel tracks retransmissions, round-trip times and the
+//! congestion window for every socket, readable with `TCP_INFO`. A
+//! snapshot taken after a bulk transfer often explains a slow result
+//! better than the throughput alone. Other platforms report nothing.
+
+use std::net::TcpStream;
+
+/// Snapshot of a socket's kernel TCP statistics.
+///
+/// Retransmissions, congestion window and delivery rate are sender-side
+/// counters, so they describe the upload direction; for downloads the
+/// server is the sender.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TcpStats {
+    /// Segments retransmitted over the connection's lifetime
+    pub retransmits: u32,
+    /// Smoothed round-trip time in milliseconds
+    pub rtt_ms: f64,
+    /// Minimum observed round-trip time in milliseconds, if the kernel
+    /// reports it (Linux 4.6+)
+    pub min_rtt_ms: Option<f64>,
+    /// Congestion window in segments
+    pub cwnd: u32,
+    /// Most recent delivery rate in Mbps, if the kernel reports it
+    /// (Linux 4.9+)
+    pub delivery_rate_mbps: Option<f64>,
+    /// Segments carrying data sent over the connection's lifetime,
+    /// retransmissions included, if the kernel reports it (Linux 4.6+)
+    pub data_segs_out: Option<u32>,
+}
+
+/// Read `TCP_INFO` for `stream`.
+///
+/// Returns `None` on platforms without `TCP_INFO` or if the call fails.
+#[cfg(target_os = "linux")]
+pub fn tcp_stats(stream: &TcpStream) -> Option<TcpStats> {
+    use std::os::fd::AsRawFd;
+
+    let mut info = KernelTcpInfo::default();
+    let mut len = std::mem::size_of::<KernelTcpInfo>() as libc::socklen_t;
+
+    // SAFETY: `info` is a plain-old-data struct at least `len` bytes long,
+    // and the kernel writes at most `len` bytes into it.
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            (&mut info as *mut KernelTcpInfo).cast(),
+            &mut len,
+        )
+    };
+    if ret != 0 {
+        log::debug!(
+            "TCP_INFO unavailable: {}",
+            std::io::Error::last_os_error()
+        );
+        return None;
+    }
+
+    Some(info.to_stats(len as usize))
+}
+
+/// Read `TCP_INFO` for `stream`.
+///
+/// Returns `None` on platforms without `TCP_INFO` or if the call fails.
+#[cfg(not(target_os = "linux"))]
+pub fn tcp_stats(_stream: &TcpStream) -> Option<TcpStats> {
+    None
+}
+
+/// Prefix of the kernel's `struct tcp_info` (`linux/tcp.h`) up to
+/// `tcpi_delivery_rate`.
+///
+/// Declared here because the libc crate's glibc definition stops at
+/// `tcpi_total_retrans`. Older kernels fill in fewer bytes, which
+/// [`KernelTcpInfo::to_stats`] checks before reading the later fields.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default)]
+struct KernelTcpInfo {
+    tcpi_state: u8,
+    tcpi_ca_state: u8,
+    tcpi_retransmits: u8,
+    tcpi_probes: u8,
+    tcpi_backoff: u8,
+    tcpi_options: u8,
+    tcpi_wscale: u8,
+    tcpi_flags: u8,
+    tcpi_rto: u32,
+    tcpi_ato: u32,
+    tcpi_snd_mss: u32,
+    tcpi_rcv_mss: u32,
+    tcpi_unacked: u32,
+    tcpi_sacked: u32,
+    tcpi_lost: u32,
+    tcpi_retrans: u32,
+    tcpi_fackets: u32,
+    tcpi_last_data_sent: u32,
+    tcpi_last_ack_sent: u32,
+    tcpi_last_data_recv: u32,
+    tcpi_last_ack_recv: u32,
+    tcpi_pmtu: u32,
+    tcpi_rcv_ssthresh: u32,
+    tcpi_rtt: u32,
+    tcpi_rttvar: u32,
+    tcpi_snd_ssthresh: u32,
+    tcpi_snd_cwnd: u32,
+    tcpi_advmss: u32,
+    tcpi_reordering: u32,
+    tcpi_rcv_rtt: u32,
+    tcpi_rcv_space: u32,
+    tcpi_total_retrans: u32,
+    tcpi_pacing_rate: u64,
+    tcpi_max_pacing_rate: u64,
+    tcpi_bytes_acked: u64,
+    tcpi_bytes_received: u64,
+    tcpi_segs_out: u32,
+    tcpi_segs_in: u32,
+    tcpi_notsent_bytes: u32,
+    tcpi_min_rtt: u32,
+    tcpi_data_segs_in: u32,
+    tcpi_data_segs_out: u32,
+    tcpi_delivery_rate: u64,
+}
+
+#[cfg(target_os = "linux")]
+impl KernelTcpInfo {
+    /// Convert to [`TcpStats`], given how many bytes the kernel filled in.
+    fn to_stats(&self, filled: usize) -> TcpStats {
+        let has = |offset: usize, size: usize| filled >= offset + size;
+
+        let min_rtt_offset = std::mem::offset_of!(Self, tcpi_min_rtt);
+        let data_segs_out_offset =
+            std::mem::offset_of!(Self, tcpi_data_segs_out);
+        let delivery_rate_offset =
+            std::mem::offset_of!(Self, tcpi_delivery_rate);
+
+        TcpStats {
+            retransmits: self.tcpi_total_retrans,
+            rtt_ms: self.tcpi_rtt as f64 / 1000.0,
+            min_rtt_ms: (has(min_rtt_offset, 4) && self.tcpi_min_rtt > 0)
+                .then(|| self.tcpi_min_rtt as f64 / 1000.0),
+            cwnd: self.tcpi_snd_cwnd,
+            delivery_rate_mbps: (has(delivery_rate_offset, 8)
+                && self.tcpi_delivery_rate > 0)
+                .then(|| self.tcpi_delivery_rate as f64 * 8.0 / 1_000_000.0),
+            data_segs_out: has(data_segs_out_offset, 4)
+                .then_some(self.tcpi_data_segs_out),
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_to_stats_converts_units() {
+        let info = KernelTcpInfo {
+            tcpi_total_retrans: 3,
+            tcpi_rtt: 12_500,
+            tcpi_min_rtt: 9_000,
+            tcpi_snd_cwnd: 10,
+            tcpi_data_segs_out: 900,
+            tcpi_delivery_rate: 12_500_000,
+            ..Default::default()
+        };
+        let stats = info.to_stats(std::mem::size_of::<KernelTcpInfo>());
+
+        assert_eq!(stats.retransmits, 3);
+        assert!((stats.rtt_ms - 12.5).abs() < 1e-9);
+        assert_eq!(stats.min_rtt_ms, Some(9.0));
+        assert_eq!(stats.cwnd, 10);
+        assert_eq!(stats.delivery_rate_mbps, Some(100.0));
+        assert_eq!(stats.data_segs_out, Some(900));
+    }
+
+    #[test]
+    fn test_to_stats_older_kernel() {
+        let info = KernelTcpInfo {
+            tcpi_min_rtt: 9_000,
+            tcpi_delivery_rate: 12_500_000,
+            ..Default::default()
+        };
+        // Kernels before 4.6 stop after the pacing/byte counters
+        let filled = std::mem::offset_of!(KernelTcpInfo, tcpi_segs_out);
+        let stats = info.to_stats(filled);
+
+        assert_eq!(stats.min_rtt_ms, None);
+        assert_eq!(stats.delivery_rate_mbps, None);
+        assert_eq!(stats.data_segs_out, None);
+    }
+
+    #[test]
+    fn test_tcp_stats_on_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream =
+            TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let _accepted = listener.accept().unwrap();
+
+        let stats = tcp_stats(&stream).unwrap();
+        assert!(stats.cwnd > 0);
+    }
+}
@@ -0,0 +1,342 @@
+//! Shared plumbing for bandwidth transfers.
+//!
+//! Downloads and uploads differ only in the request they send and in how
+//! the body moves; connecting, the TLS handshake, loaded latency sampling
+//! and collecting the results are the same in both directions. A
+//! [`Transfer`] supplies the direction-specific parts and [`run_transfer`]
+//! does the rest.
+
+use crate::cloudflare::tests::connection::{
+    connect_with, measure_tcp_latency, socket_buffers, tls_handshake,
+    SocketOptions,
+};
+use crate::cloudflare::tests::cpu::CoreMeter;
+use crate::cloudflare::tests::response::Redirect;
+use crate::cloudflare::tests::stall::{stalled, TransferProgress};
+use crate::cloudflare::tests::tcp_info::tcp_stats;
+use crate::cloudflare::tests::{
+    EdgeHeaders, IoReadAndWrite, Test, TestResults,
+};
+use http::HeaderMap;
+use log::debug;
+use std::error::Error;
+use std::io::Read;
+use std::net::{IpAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use url::Url;
+
+/// How long to wait for the latency sampler to stop after a transfer.
+const SAMPLER_STOP_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Most redirects followed for one transfer.
+const MAX_REDIRECTS: u32 = 5;
+
+/// Settings shared by transfers in both directions.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TransferOptions {
+    /// Additional headers sent with every request (e.g. from `--header`)
+    pub headers: HeaderMap,
+    /// Socket buffer sizes applied to the test connection
+    pub socket_options: SocketOptions,
+    /// Updated whenever the transfer sends or receives data
+    pub progress: TransferProgress,
+    /// Abort the transfer after this long without progress
+    pub stall_timeout: Option<Duration>,
+    /// Measurement ID of the run, sent with every request
+    pub meas_id: Option<String>,
+}
+
+/// Loaded latency sampling to run alongside a transfer.
+#[derive(Clone)]
+pub(crate) struct LoadedLatency {
+    /// Channel sender for latency measurements (in milliseconds)
+    pub tx: mpsc::Sender<f64>,
+    /// Minimum interval between latency measurements
+    pub throttle: Duration,
+    /// Minimum request duration before latency is measured
+    pub min_request_duration: Duration,
+}
+
+impl LoadedLatency {
+    pub fn new(
+        tx: mpsc::Sender<f64>,
+        throttle_ms: u64,
+        min_request_duration_ms: u64,
+    ) -> Self {
+        Self {
+            tx,
+            throttle: Duration::from_millis(throttle_ms),
+            min_request_duration: Duration::from_millis(
+                min_request_duration_ms,
+            ),
+        }
+    }
+}
+
+/// Timing of a request/response exchange over an established connection.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Exchange {
+    /// Time to first byte of the response
+    pub ttfb: Duration,
+    /// Server processing time reported by the server
+    pub server_time: Duration,
+    /// Time until the transfer finished
+    pub end: Duration,
+    /// Cloudflare headers of the response
+    pub edge: Option<EdgeHeaders>,
+    /// SHA-256 of the response body, when it was hashed
+    pub body_sha256: Option<String>,
+}
+
+/// A bandwidth transfer in one direction.
+pub(crate) trait Transfer: Test {
+    /// Direction-specific request body, moved onto the blocking thread
+    /// that runs the exchange.
+    type Body: Send + 'static;
+
+    /// Settings shared with the other direction.
+    fn options(&self) -> &TransferOptions;
+
+    /// URL of the request transferring `bytes`.
+    fn url(&self, bytes: u64) -> Result<Url, url::ParseError>;
+
+    /// Raw request head for a transfer of `bytes` to or from `url`.
+    fn request_head(&self, url: &Url, bytes: u64) -> String;
+
+    /// The body to hand to [`Transfer::exchange`] for a transfer of
+    /// `bytes`.
+    fn body(&self, bytes: u64) -> Self::Body;
+
+    /// Send `head` and `body` over `tcp` and read the response, recording
+    /// progress in `progress`. `socket` is the connection's TCP socket,
+    /// when there is a handle on it, for adjusting its timeouts. Runs on
+    /// a blocking thread.
+    fn exchange(
+        tcp: &mut Box<dyn IoReadAndWrite>,
+        head: &str,
+        body: Self::Body,
+        socket: Option<&TcpStream>,
+        progress: &TransferProgress,
+    ) -> Result<Exchange, Box<dyn Error + Send + Sync>>;
+}
+
+/// Run one transfer of `bytes`, sampling loaded latency alongside it when
+/// `loaded` is given.
+///
+/// Redirects are followed, up to [`MAX_REDIRECTS`] of them, by making the
+/// transfer again at their target over a new connection. Only the final
+/// transfer is timed; the time spent on redirects is reported apart.
+pub(crate) async fn run_transfer<T: Transfer>(
+    transfer: &T,
+    bytes: u64,
+    loaded: Option<LoadedLatency>,
+) -> Result<TestResults, Box<dyn Error>> {
+    let start = Instant::now();
+    let mut url = transfer.url(bytes)?;
+    let mut redirects = 0;
+    let mut redirect_time = Duration::ZERO;
+
+    loop {
+        let error =
+            match transfer_to(transfer, &url, bytes, loaded.clone()).await {
+                Ok(results) => {
+                    return Ok(results.with_redirects(redirects, redirect_time))
+                }
+                Err(error) => error,
+            };
+        let redirect = error.downcast::<Redirect>()?;
+
+        redirects += 1;
+        if redirects > MAX_REDIRECTS {
+            return Err(format!(
+                "Too many redirects from speed test server (more than {})",
+                MAX_REDIRECTS
+            )
+            .into());
+        }
+        url = redirect_target(&url, &redirect)?;
+        redirect_time = start.elapsed();
+        debug!("Following {} ({} of {})", redirect, redirects, MAX_REDIRECTS);
+    }
+}
+
+/// The URL `redirect` points to from `url`.
+///
+/// Measurements always run over TLS, so a redirect to anything but
+/// `https` is an error.
+fn redirect_target(
+    url: &Url,
+    redirect: &Redirect,
+) -> Result<Url, Box<dyn Error>> {
+    let target = url.join(&redirect.location).map_err(|e| {
+        format!("Invalid redirect target {:?}: {}", redirect.location, e)
+    })?;
+    if target.scheme() != "https" {
+        return Err(format!(
+            "Refusing to follow {} from speed test server: not HTTPS",
+            redirect
+        )
+        .into());
+    }
+    Ok(target)
+}
+
+/// Make the transfer to `url` over a new connection.
+async fn transfer_to<T: Transfer>(
+    transfer: &T,
+    url: &Url,
+    bytes: u64,
+    loaded: Option<LoadedLatency>,
+) -> Result<TestResults, Box<dyn Error>> {
+    let options = transfer.options();
+
+    let (stream, ip_address, port, tcp_connect_duration) =
+        connect_with(url, options.socket_options).await?;
+    stream.set_write_timeout(options.stall_timeout)?;
+    stream.set_read_timeout(options.stall_timeout)?;
+    // Keep a handle on the socket to read TCP_INFO after the transfer
+    let socket = stream.try_clone().ok();
+    let host = url.host_str().unwrap_or("").to_string();
+    let (mut stream, tls) = tls_handshake(stream, host).await?;
+
+    let head = transfer.request_head(url, bytes);
+    debug!("\r\n{}", head);
+
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let sampler = loaded.map(|loaded| {
+        sample_latency(ip_address, port, loaded, stop_flag.clone())
+    });
+
+    let body = transfer.body(bytes);
+    let progress = options.progress.clone();
+    let exchange_socket = socket.as_ref().and_then(|s| s.try_clone().ok());
+    let result = tokio::task::spawn_blocking(move || {
+        let meter = CoreMeter::start();
+        let exchange = T::exchange(
+            &mut stream,
+            &head,
+            body,
+            exchange_socket.as_ref(),
+            &progress,
+        )?;
+        Ok((exchange, meter.and_then(|meter| meter.utilization())))
+    })
+    .await;
+
+    // Stop the sampler whether or not the transfer succeeded
+    // (Release pairs with Acquire in the sampler)
+    stop_flag.store(true, Ordering::Release);
+    if let Some(sampler) = sampler {
+        let _ = tokio::time::timeout(SAMPLER_STOP_TIMEOUT, sampler).await;
+    }
+    let (exchange, core_utilization) = result?
+        .map_err(|e: Box<dyn Error + Send + Sync>| e as Box<dyn Error>)?;
+
+    Ok(TestResults::new(
+        tcp_connect_duration,
+        exchange.ttfb,
+        exchange.server_time,
+        exchange.end,
+        bytes,
+    )
+    .with_tls(tls)
+    .with_tcp_stats(socket.as_ref().and_then(tcp_stats))
+    .with_socket_buffers(socket.as_ref().and_then(socket_buffers))
+    .with_edge_headers(exchange.edge)
+    .with_body_sha256(exchange.body_sha256)
+    .with_core_utilization(core_utilization))
+}
+
+/// Read the first byte of the response, returning it with the time spent
+/// waiting for it.
+pub(crate) fn read_first_byte(
+    tcp: &mut Box<dyn IoReadAndWrite>,
+    progress: &TransferProgress,
+) -> Result<(u8, Duration), Box<dyn Error + Send + Sync>> {
+    let mut one_byte_buffer = [0_u8];
+    let start = Instant::now();
+    tcp.read_exact(&mut one_byte_buffer).map_err(stalled)?;
+    let waited = start.elapsed();
+    progress.touch();
+    Ok((one_byte_buffer[0], waited))
+}
+
+/// Measure latency to `ip_address` at regular intervals until `stop_flag`
+/// is set, once the request has been running long enough.
+fn sample_latency(
+    ip_address: IpAddr,
+    port: u16,
+    loaded: LoadedLatency,
+    stop_flag: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    let request_start = Instant::now();
+
+    tokio::spawn(async move {
+        let mut last_measurement = Instant::now();
+
+        loop {
+            // Acquire pairs with Release once the transfer finishes
+            if stop_flag.load(Ordering::Acquire) {
+                break;
+            }
+
+            // Wait for throttle interval
+            let elapsed_since_last = last_measurement.elapsed();
+            if elapsed_since_last < loaded.throttle {
+                tokio::time::sleep(loaded.throttle - elapsed_since_last).await;
+            }
+
+            // Check again after sleep
+            if stop_flag.load(Ordering::Acquire) {
+                break;
+            }
+
+            // Only measure if request has been running long enough
+            if request_start.elapsed() >= loaded.min_request_duration {
+                // Measure latency using TCP handshake time
+                if let Ok(latency_ms) =
+                    measure_tcp_latency(ip_address, port).await
+                {
+                    let _ = loaded.tx.send(latency_ms).await;
+                }
+            }
+
+            last_measurement = Instant::now();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redirect(location: &str) -> Redirect {
+        Redirect { status: 302, location: location.to_string() }
+    }
+
+    #[test]
+    fn test_redirect_target() {
+        let url = Url::parse("https://speed.cloudflare.com/__up").unwrap();
+
+        let target = redirect_target(&url, &redirect("/v2/__up?x=1")).unwrap();
+        assert_eq!(
+            target.as_str(),
+            "https://speed.cloudflare.com/v2/__up?x=1"
+        );
+
+        let target =
+            redirect_target(&url, &redirect("https://speed.example.net/up"))
+                .unwrap();
+        assert_eq!(target.as_str(), "https://speed.example.net/up");
+
+        let error =
+            redirect_target(&url, &redirect("http://speed.cloudflare.com/"))
+                .unwrap_err();
+        assert!(error.to_string().contains("not HTTPS"), "{}", error);
+    }
+}
@@ -1,19 +1,192 @@
-use crate::cloudflare::requests::UA;
-use crate::cloudflare::tests::connection::{
-    measure_tcp_latency, resolve_dns, tcp_connect, tls_handshake_duration,
+use crate::cloudflare::tests::connection::SocketOptions;
+use crate::cloudflare::tests::response::{
+    early_response, read_body, read_response_head, ResponseHead,
 };
-use crate::cloudflare::tests::{extract_http_status, IoReadAndWrite, Test, TestResults, BASE_URL};
+use crate::cloudflare::tests::sink::DiscardSink;
+use crate::cloudflare::tests::stall::{stalled, TransferProgress};
+use crate::cloudflare::tests::transfer::{
+    read_first_byte, run_transfer, Exchange, LoadedLatency, Transfer,
+    TransferOptions,
+};
+use crate::cloudflare::tests::{
+    endpoint_url, format_extra_headers, request_target, user_agent,
+    EdgeHeaders, IoReadAndWrite, Test, TestResults,
+};
+use http::HeaderMap;
 use log::{debug, info};
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::error::Error;
-use std::io::{Read, Write};
-use std::net::IpAddr;
-use std::sync::Arc;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::time::Instant;
 use url::Url;
 
+/// Seed for the random payload, fixed so every run uploads the same bytes.
+const PAYLOAD_SEED: u64 = 0x5EED_C10D_5BEE_D000;
+
+/// Size of the random block repeated to fill a payload.
+///
+/// Much larger than the windows used by common compressors (32 KiB for
+/// deflate), so repeating it does not make the payload compressible.
+const PAYLOAD_CHUNK_BYTES: usize = 1 << 20;
+
+/// Write size used for a fixed chunk size by default, and by auto mode
+/// before a bandwidth estimate is available.
+pub const DEFAULT_UPLOAD_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Smallest write size auto mode picks.
+const MIN_AUTO_CHUNK_BYTES: usize = 16 * 1024;
+
+/// Largest write size auto mode picks.
+const MAX_AUTO_CHUNK_BYTES: usize = 4 * 1024 * 1024;
+
+/// Auto mode sizes each write to carry roughly this much transfer time,
+/// keeping syscall overhead small on fast links without building large
+/// bursts on slow ones.
+const AUTO_CHUNK_TARGET: Duration = Duration::from_millis(10);
+
+/// How long to wait for the server to accept an upload with `100
+/// Continue` before sending the body anyway, since servers that don't
+/// implement it never answer (RFC 9110, section 10.1.1).
+const CONTINUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Size of the writes an upload body is sent in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UploadChunkSize {
+    /// Pick a size from the current bandwidth estimate
+    #[default]
+    Auto,
+    /// Always write this many bytes at a time
+    Fixed(usize),
+}
+
+impl UploadChunkSize {
+    /// Resolve to a write size in bytes, given the current upload
+    /// bandwidth estimate in bits per second, if there is one.
+    pub fn resolve(self, estimate_bps: Option<f64>) -> usize {
+        match self {
+            UploadChunkSize::Fixed(bytes) => bytes.max(1),
+            UploadChunkSize::Auto => match estimate_bps {
+                Some(bps) if bps > 0.0 => {
+                    let bytes = bps / 8.0 * AUTO_CHUNK_TARGET.as_secs_f64();
+                    (bytes as usize)
+                        .clamp(MIN_AUTO_CHUNK_BYTES, MAX_AUTO_CHUNK_BYTES)
+                        .next_power_of_two()
+                }
+                _ => DEFAULT_UPLOAD_CHUNK_BYTES,
+            },
+        }
+    }
+}
+
+impl fmt::Display for UploadChunkSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadChunkSize::Auto => write!(f, "auto"),
+            UploadChunkSize::Fixed(bytes) => write!(f, "{}", bytes),
+        }
+    }
+}
+
+impl FromStr for UploadChunkSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(UploadChunkSize::Auto);
+        }
+
+        match s.parse::<usize>() {
+            Ok(bytes) if bytes > 0 => Ok(UploadChunkSize::Fixed(bytes)),
+            _ => Err(format!(
+                "invalid chunk size '{}' (expected auto or a byte count)",
+                s
+            )),
+        }
+    }
+}
+
+/// Byte pattern used to fill upload payloads.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadPattern {
+    /// Incompressible pseudo-random bytes
+    #[default]
+    Random,
+    /// ASCII `'0'` bytes, which compress extremely well
+    Zeros,
+}
+
+impl PayloadPattern {
+    /// Generate a payload of `bytes` bytes in this pattern.
+    pub fn generate(self, bytes: usize) -> Vec<u8> {
+        match self {
+            PayloadPattern::Zeros => vec![b'0'; bytes],
+            PayloadPattern::Random => {
+                let chunk = random_chunk();
+                let mut data = Vec::with_capacity(bytes);
+                while data.len() < bytes {
+                    let take = (bytes - data.len()).min(chunk.len());
+                    data.extend_from_slice(&chunk[..take]);
+                }
+                data
+            }
+        }
+    }
+}
+
+impl fmt::Display for PayloadPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayloadPattern::Random => write!(f, "random"),
+            PayloadPattern::Zeros => write!(f, "zeros"),
+        }
+    }
+}
+
+impl FromStr for PayloadPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "random" => Ok(PayloadPattern::Random),
+            "zeros" => Ok(PayloadPattern::Zeros),
+            other => Err(format!(
+                "unknown payload pattern '{}' (expected random or zeros)",
+                other
+            )),
+        }
+    }
+}
+
+/// The shared random block, generated once per process.
+fn random_chunk() -> &'static [u8] {
+    static CHUNK: OnceLock<Vec<u8>> = OnceLock::new();
+    CHUNK.get_or_init(|| {
+        // SplitMix64: fast, and plenty random for defeating compression
+        let mut state = PAYLOAD_SEED;
+        let mut chunk = Vec::with_capacity(PAYLOAD_CHUNK_BYTES);
+        while chunk.len() < PAYLOAD_CHUNK_BYTES {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^= z >> 31;
+            chunk.extend_from_slice(&z.to_le_bytes());
+        }
+        chunk.truncate(PAYLOAD_CHUNK_BYTES);
+        chunk
+    })
+}
+
 /// Upload test implementation for measuring upload bandwidth.
 ///
 /// This struct performs upload tests by POSTing data to Cloudflare's
@@ -21,6 +194,9 @@ use url::Url;
 pub(crate) struct Upload {
     /// Pre-generated payload data to upload (Arc for cheap cloning into spawn_blocking)
     data: Arc<Vec<u8>>,
+    /// Number of bytes written to the connection at a time
+    chunk_size: usize,
+    options: TransferOptions,
 }
 
 impl Upload {
@@ -32,9 +208,53 @@ impl Upload {
     /// # Returns
     /// A new Upload instance with pre-generated payload data
     pub fn new(bytes: u64) -> Self {
-        // Generate payload data (zeros are efficient and compress well)
-        let data = Arc::new(vec![b'0'; bytes as usize]);
-        Self { data }
+        Self::with_pattern(bytes, PayloadPattern::default())
+    }
+
+    /// Create a new upload test whose payload uses `pattern`.
+    pub fn with_pattern(bytes: u64, pattern: PayloadPattern) -> Self {
+        let data = Arc::new(pattern.generate(bytes as usize));
+        Self {
+            data,
+            chunk_size: DEFAULT_UPLOAD_CHUNK_BYTES,
+            options: TransferOptions::default(),
+        }
+    }
+
+    /// Write the body `bytes` bytes at a time.
+    pub fn with_chunk_size(mut self, bytes: usize) -> Self {
+        self.chunk_size = bytes.max(1);
+        self
+    }
+
+    /// Attach additional headers to every request made by this test.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.options.headers = headers;
+        self
+    }
+
+    /// Apply `options` to the sockets this test opens.
+    pub fn with_socket_options(mut self, options: SocketOptions) -> Self {
+        self.options.socket_options = options;
+        self
+    }
+
+    /// Record the transfer's progress in `progress`.
+    pub fn with_progress(mut self, progress: TransferProgress) -> Self {
+        self.options.progress = progress;
+        self
+    }
+
+    /// Abort the transfer once no data has moved for `timeout`.
+    pub fn with_stall_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.options.stall_timeout = timeout;
+        self
+    }
+
+    /// Tag every request with the run's measurement ID.
+    pub fn with_meas_id(mut self, meas_id: Option<String>) -> Self {
+        self.options.meas_id = meas_id;
+        self
     }
 
     /// Get the size of the upload payload in bytes.
@@ -64,38 +284,12 @@ impl Upload {
     ) -> Result<TestResults, Box<dyn Error>> {
         let bytes = self.bytes();
         info!("Beginning Upload Test with loaded latency: {}", bytes);
-
-        let url =
-            Url::parse(format!("{}/{}", BASE_URL, self.endpoint()).as_str())?;
-
-        let (ip_address, _dns_duration) = resolve_dns(&url).await?;
-        let port = url.port_or_known_default().unwrap();
-        let (stream, tcp_connect_duration) = tcp_connect(ip_address, port).await?;
-        let host = url.host_str().unwrap_or("").to_string();
-        let (stream, _tls_handshake_duration) =
-            tls_handshake_duration(stream, host).await?;
-
-        // Execute HTTP POST with concurrent latency measurements
-        let (_connect_duration, ttfb_duration, server_time, end_duration) =
-            execute_http_post_with_latency(
-                stream,
-                &url,
-                self.data.clone(),
-                ip_address,
-                port,
-                latency_tx,
-                throttle_ms,
-                min_request_duration_ms,
-            )
-            .await?;
-
-        Ok(TestResults::new(
-            tcp_connect_duration,
-            ttfb_duration,
-            server_time,
-            end_duration,
-            bytes,
-        ))
+        let loaded = LoadedLatency::new(
+            latency_tx,
+            throttle_ms,
+            min_request_duration_ms,
+        );
+        run_transfer(self, bytes, Some(loaded)).await
     }
 }
 
@@ -108,92 +302,97 @@ impl Test for Upload {
         // Note: bytes parameter is ignored; we use self.data.len() instead
         let bytes = self.bytes();
         info!("Beginning Upload Test: {}", bytes);
-
-        let url =
-            Url::parse(format!("{}/{}", BASE_URL, self.endpoint()).as_str())?;
-
-        let (_ip_address, _dns_duration) = resolve_dns(&url).await?;
-        let port = url.port_or_known_default().unwrap();
-        let (stream, tcp_connect_duration) = tcp_connect(_ip_address, port).await?;
-        let host = url.host_str().unwrap_or("").to_string();
-        let (stream, _tls_handshake_duration) =
-            tls_handshake_duration(stream, host).await?;
-        let (_connect_duration, ttfb_duration, server_time, end_duration) =
-            execute_http_post(stream, url, self.data.clone()).await?;
-
-        Ok(TestResults::new(
-            tcp_connect_duration,
-            ttfb_duration,
-            server_time,
-            end_duration,
-            bytes,
-        ))
+        run_transfer(self, bytes, None).await
     }
 }
 
-async fn execute_http_post(
-    mut tcp: Box<dyn IoReadAndWrite>,
-    url: Url,
-    data: Arc<Vec<u8>>,
-) -> Result<(Duration, Duration, Duration, Duration), Box<dyn Error>> {
-    tokio::task::spawn_blocking(move || {
-        let header = build_http_post_header(&url, data.len());
-        debug!("\r\n{}", header);
-        let upload_start = Instant::now();
+impl Transfer for Upload {
+    /// The payload and the size of the writes it is sent in
+    type Body = (Arc<Vec<u8>>, usize);
+
+    fn options(&self) -> &TransferOptions {
+        &self.options
+    }
 
-        // Write headers
-        tcp.write_all(header.as_bytes())?;
-        // Write body - this is the actual upload
-        tcp.write_all(&data)?;
+    fn url(&self, _bytes: u64) -> Result<Url, url::ParseError> {
+        endpoint_url(&self.endpoint(), self.options.meas_id.as_deref(), None)
+    }
+
+    fn request_head(&self, url: &Url, bytes: u64) -> String {
+        build_http_post_header(url, bytes, &self.options.headers)
+    }
+
+    fn body(&self, _bytes: u64) -> Self::Body {
+        (self.data.clone(), self.chunk_size)
+    }
+
+    fn exchange(
+        tcp: &mut Box<dyn IoReadAndWrite>,
+        head: &str,
+        (data, chunk_size): Self::Body,
+        socket: Option<&TcpStream>,
+        progress: &TransferProgress,
+    ) -> Result<Exchange, Box<dyn Error + Send + Sync>> {
+        // The head goes out alone, so the server accepts or rejects the
+        // upload before any of the body is sent
+        tcp.write_all(head.as_bytes())?;
         tcp.flush()?;
+        let accept_start = Instant::now();
+        if await_continue(tcp, socket)? {
+            debug!(
+                "Upload accepted after {:.1} ms",
+                accept_start.elapsed().as_secs_f64() * 1000.0
+            );
+        } else {
+            debug!("No 100 Continue from the server; sending the body");
+        }
+
+        // Timed from the first body byte, leaving out the time the server
+        // took to accept the request
+        let upload_start = Instant::now();
+        send_body(tcp, &data, chunk_size, progress)?;
 
         // Read first byte (TTFB) - this marks when server received all data
-        // and started responding
-        let mut one_byte_buffer = [0_u8];
-        tcp.read_exact(&mut one_byte_buffer)?;
-
-        // For uploads, the transfer time is from start of write to TTFB
-        // This captures the actual network transfer time
-        let upload_duration = upload_start.elapsed();
-
-        // Read headers
-        let mut headers: Vec<u8> = Vec::new();
-        headers.push(one_byte_buffer[0]);
-
-        while tcp.read(&mut one_byte_buffer)? > 0 {
-            headers.push(one_byte_buffer[0]);
-            if headers.len() >= 4
-                && headers[headers.len() - 4..] == [b'\r', b'\n', b'\r', b'\n']
+        // and started responding. A 100 Continue that arrived after the
+        // wait gave up comes first and is skipped.
+        let (upload_duration, headers) = loop {
+            let (first_byte, _) = read_first_byte(tcp, progress)?;
+
+            // For uploads, the transfer time is from start of write to
+            // TTFB. This captures the actual network transfer time
+            let upload_duration = upload_start.elapsed();
+
+            if let ResponseHead::Final(headers) =
+                read_response_head(tcp, first_byte)?
             {
-                break;
+                break (upload_duration, headers);
             }
-        }
-
-        // Check HTTP status code
-        let headers_str = String::from_utf8(headers)
-            .map_err(|e| format!("Invalid UTF-8 in HTTP headers: {}", e))?;
-        let status = extract_http_status(&headers_str)
-            .ok_or("Malformed HTTP response from speed test server")?;
-        if status != 200 {
-            return Err(format!("HTTP {status} from speed test server").into());
-        }
+        };
+        let edge = EdgeHeaders::from_headers(&headers);
 
         // Read any remaining response body (we don't need server-timing for uploads)
-        let mut buff = Vec::new();
-        tcp.read_to_end(&mut buff)?;
+        read_body(tcp, &headers, None, &mut DiscardSink::new(), progress)?;
 
-        // For uploads: return upload_duration as end_duration and Duration::ZERO
+        // For uploads: report upload_duration as the end and Duration::ZERO
         // for both ttfb and server_time. This way:
         // - transfer_duration() = end_duration - ttfb = upload_duration
         // - bandwidth calculation uses upload_duration directly without subtracting
         //   server_time (which for uploads includes the receive time)
-        Ok::<_, Box<dyn Error + Send + Sync>>((upload_duration, Duration::ZERO, Duration::ZERO, upload_duration))
-    })
-    .await?
-    .map_err(|e| e as Box<dyn Error>)
+        Ok(Exchange {
+            ttfb: Duration::ZERO,
+            server_time: Duration::ZERO,
+            end: upload_duration,
+            edge,
+            body_sha256: None,
+        })
+    }
 }
 
-fn build_http_post_header(url: &Url, content_length: usize) -> String {
+fn build_http_post_header(
+    url: &Url,
+    content_length: u64,
+    extra_headers: &HeaderMap,
+) -> String {
     format!(
         "POST {} HTTP/1.1\r\n\
         Host: {}\r\n\
@@ -201,135 +400,308 @@ fn build_http_post_header(url: &Url, content_length: usize) -> String {
         Accept: */*\r\n\
         Content-Type: text/plain;charset=UTF-8\r\n\
         Content-Length: {}\r\n\
+        Expect: 100-continue\r\n\
+        {}\
         Connection: close\r\n\
         \r\n",
-        url.path(),
+        request_target(url),
         url.host_str().unwrap(),
-        UA,
-        content_length
+        user_agent(extra_headers),
+        content_length,
+        format_extra_headers(extra_headers)
     )
 }
 
-/// Execute HTTP POST with concurrent latency measurements.
+/// Wait for the server's `100 Continue` to a request head sent with
+/// `Expect: 100-continue`, for at most [`CONTINUE_TIMEOUT`].
 ///
-/// This function performs the HTTP POST request while spawning a background
-/// task that measures latency at regular intervals. Latency measurements
-/// are only included if the request duration exceeds the minimum threshold.
-#[allow(clippy::too_many_arguments)]
-async fn execute_http_post_with_latency(
-    mut tcp: Box<dyn IoReadAndWrite>,
-    url: &Url,
-    data: Arc<Vec<u8>>,
-    ip_address: IpAddr,
-    port: u16,
-    latency_tx: mpsc::Sender<f64>,
-    throttle_ms: u64,
-    min_request_duration_ms: u64,
-) -> Result<(Duration, Duration, Duration, Duration), Box<dyn Error>> {
-    let header = build_http_post_header(url, data.len());
-    debug!("\r\n{}", header);
-    let upload_start = Instant::now();
-
-    let throttle_duration = Duration::from_millis(throttle_ms);
-    let min_duration = Duration::from_millis(min_request_duration_ms);
-
-    // Use Arc to share the stop flag between tasks
-    let stop_flag = Arc::new(std::sync::atomic::AtomicBool::new(false));
-    let stop_flag_clone = stop_flag.clone();
-
-    // Spawn latency measurement task
-    let latency_handle = tokio::spawn(async move {
-        let mut last_measurement = Instant::now();
-
-        loop {
-            // Check if we should stop (Acquire pairs with Release in main thread)
-            if stop_flag_clone.load(std::sync::atomic::Ordering::Acquire) {
-                break;
-            }
+/// # Returns
+/// Whether the server accepted the upload. `false` means it didn't answer
+/// in time, or there's no `socket` to bound the wait with, and the body
+/// should be sent anyway. A response rejecting the upload is an error
+/// naming its status, so a large upload fails before it's sent.
+fn await_continue(
+    tcp: &mut Box<dyn IoReadAndWrite>,
+    socket: Option<&TcpStream>,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let Some(socket) = socket else {
+        return Ok(false);
+    };
+
+    let timeout = socket.read_timeout()?;
+    let wait = timeout.map_or(CONTINUE_TIMEOUT, |t| t.min(CONTINUE_TIMEOUT));
+    socket.set_read_timeout(Some(wait))?;
+    let accepted = read_continue(tcp);
+    socket.set_read_timeout(timeout)?;
+    accepted
+}
 
-            // Wait for throttle interval
-            let elapsed_since_last = last_measurement.elapsed();
-            if elapsed_since_last < throttle_duration {
-                tokio::time::sleep(throttle_duration - elapsed_since_last)
-                    .await;
+/// Read interim responses up to a `100 Continue`, or `false` once the
+/// connection's read timeout passes without one.
+fn read_continue(
+    tcp: &mut impl Read,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let mut first_byte = [0_u8];
+    loop {
+        match tcp.read(&mut first_byte) {
+            Ok(1) => {}
+            Ok(_) => {
+                return Err("Connection closed before the server accepted \
+                            the upload"
+                    .into())
             }
-
-            // Check again after sleep (Acquire pairs with Release in main thread)
-            if stop_flag_clone.load(std::sync::atomic::Ordering::Acquire) {
-                break;
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(false)
             }
+            Err(e) => return Err(e.into()),
+        }
 
-            // Only measure if request has been running long enough
-            let request_duration = upload_start.elapsed();
-            if request_duration >= min_duration {
-                // Measure latency using TCP handshake time
-                if let Ok(latency_ms) = measure_tcp_latency(ip_address, port).await {
-                    let _ = latency_tx.send(latency_ms).await;
-                }
+        match read_response_head(tcp, first_byte[0])? {
+            ResponseHead::Interim(100) => return Ok(true),
+            // e.g. 103 Early Hints; the answer is still to come
+            ResponseHead::Interim(_) => {}
+            ResponseHead::Final(_) => {
+                return Err("Server responded before the request body was \
+                            sent"
+                    .into())
             }
+        }
+    }
+}
 
-            last_measurement = Instant::now();
+/// Write the request body, recording progress per chunk.
+///
+/// When the server rejects the upload part way through and closes the
+/// connection, the failed write is replaced by the response it sent, so
+/// the measurement fails with the HTTP status rather than a broken pipe.
+fn send_body(
+    tcp: &mut Box<dyn IoReadAndWrite>,
+    data: &[u8],
+    chunk_size: usize,
+    progress: &TransferProgress,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut write = || -> io::Result<()> {
+        for chunk in data.chunks(chunk_size) {
+            tcp.write_all(chunk).map_err(stalled)?;
+            progress.touch();
         }
-    });
+        tcp.flush()
+    };
 
-    let result = tokio::task::spawn_blocking(move || {
-        // Write headers
-        tcp.write_all(header.as_bytes())?;
-        // Write body - this is the actual upload
-        tcp.write_all(&data)?;
-        tcp.flush()?;
+    match write() {
+        Ok(()) => Ok(()),
+        Err(e) if closed_by_peer(&e) => {
+            Err(early_response(tcp).unwrap_or_else(|| e.into()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
 
-        // Read first byte (TTFB) - this marks when server received all data
-        // and started responding
-        let mut one_byte_buffer = [0_u8];
-        tcp.read_exact(&mut one_byte_buffer)?;
-
-        // For uploads, the transfer time is from start of write to TTFB
-        // This captures the actual network transfer time
-        let upload_duration = upload_start.elapsed();
-
-        // Read headers
-        let mut headers: Vec<u8> = Vec::new();
-        headers.push(one_byte_buffer[0]);
-
-        while tcp.read(&mut one_byte_buffer)? > 0 {
-            headers.push(one_byte_buffer[0]);
-            if headers.len() >= 4
-                && headers[headers.len() - 4..] == [b'\r', b'\n', b'\r', b'\n']
-            {
-                break;
+/// Whether a write failed because the server closed the connection.
+fn closed_by_peer(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_fixed_chunk_size() {
+        assert_eq!(UploadChunkSize::Fixed(8192).resolve(Some(1e9)), 8192);
+        assert_eq!(UploadChunkSize::Fixed(0).resolve(None), 1);
+    }
+
+    #[test]
+    fn test_auto_chunk_size_scales_with_bandwidth() {
+        let auto = UploadChunkSize::Auto;
+        assert_eq!(auto.resolve(None), DEFAULT_UPLOAD_CHUNK_BYTES);
+        assert_eq!(auto.resolve(Some(0.0)), DEFAULT_UPLOAD_CHUNK_BYTES);
+
+        // 10 Mbps: 12.5 KB per 10 ms, raised to the minimum
+        assert_eq!(auto.resolve(Some(10e6)), MIN_AUTO_CHUNK_BYTES);
+        // 1 Gbps: 1.25 MB per 10 ms, rounded up to 2 MiB
+        assert_eq!(auto.resolve(Some(1e9)), 2 * 1024 * 1024);
+        // 100 Gbps is capped
+        assert_eq!(auto.resolve(Some(100e9)), MAX_AUTO_CHUNK_BYTES);
+    }
+
+    #[test]
+    fn test_chunk_size_from_str() {
+        assert_eq!("auto".parse(), Ok(UploadChunkSize::Auto));
+        assert_eq!("AUTO".parse(), Ok(UploadChunkSize::Auto));
+        assert_eq!("65536".parse(), Ok(UploadChunkSize::Fixed(65536)));
+        assert!("0".parse::<UploadChunkSize>().is_err());
+        assert!("64k".parse::<UploadChunkSize>().is_err());
+    }
+
+    #[test]
+    fn test_zeros_payload() {
+        let data = PayloadPattern::Zeros.generate(1000);
+        assert_eq!(data.len(), 1000);
+        assert!(data.iter().all(|&b| b == b'0'));
+    }
+
+    #[test]
+    fn test_random_payload_is_deterministic() {
+        let a = PayloadPattern::Random.generate(4096);
+        let b = PayloadPattern::Random.generate(4096);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_random_payload_spans_chunks() {
+        let bytes = PAYLOAD_CHUNK_BYTES + 100;
+        let data = PayloadPattern::Random.generate(bytes);
+        assert_eq!(data.len(), bytes);
+        assert_eq!(data[PAYLOAD_CHUNK_BYTES..], data[..100]);
+    }
+
+    #[test]
+    fn test_random_payload_uses_all_byte_values() {
+        let data = PayloadPattern::Random.generate(64 * 1024);
+        let mut seen = [false; 256];
+        for &b in &data {
+            seen[b as usize] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+    }
+
+    #[test]
+    fn test_payload_pattern_from_str() {
+        assert_eq!("random".parse(), Ok(PayloadPattern::Random));
+        assert_eq!("ZEROS".parse(), Ok(PayloadPattern::Zeros));
+        assert!("ones".parse::<PayloadPattern>().is_err());
+        assert_eq!(PayloadPattern::Zeros.to_string(), "zeros");
+    }
+
+    #[test]
+    fn test_upload_with_pattern() {
+        let upload = Upload::with_pattern(10, PayloadPattern::Zeros);
+        assert_eq!(upload.bytes(), 10);
+        assert_eq!(upload.data.as_slice(), b"0000000000");
+    }
+
+    /// Connection whose server rejects the request once `accept` bytes
+    /// have been written, having already sent `response`.
+    struct Rejecting {
+        accept: usize,
+        response: &'static [u8],
+    }
+
+    impl Read for Rejecting {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.response.read(buf)
+        }
+    }
+
+    impl Write for Rejecting {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.accept == 0 {
+                return Err(io::ErrorKind::BrokenPipe.into());
             }
+            let n = buf.len().min(self.accept);
+            self.accept -= n;
+            Ok(n)
         }
 
-        // Check HTTP status code
-        let headers_str = String::from_utf8(headers)
-            .map_err(|e| format!("Invalid UTF-8 in HTTP headers: {}", e))?;
-        let status = extract_http_status(&headers_str)
-            .ok_or("Malformed HTTP response from speed test server")?;
-        if status != 200 {
-            return Err(format!("HTTP {status} from speed test server").into());
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
         }
+    }
 
-        // Read any remaining response body (we don't need server-timing for uploads)
-        let mut buff = Vec::new();
-        tcp.read_to_end(&mut buff)?;
+    #[test]
+    fn test_rejected_upload_fails_with_status() {
+        let mut tcp: Box<dyn IoReadAndWrite> = Box::new(Rejecting {
+            accept: 100,
+            response: include_bytes!("fixtures/rate_limited.http"),
+        });
+        let error = send_body(
+            &mut tcp,
+            &[b'0'; 1000],
+            64,
+            &TransferProgress::default(),
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "HTTP 429 from speed test server");
+
+        // Without a response, the write error stands
+        let mut tcp: Box<dyn IoReadAndWrite> =
+            Box::new(Rejecting { accept: 100, response: b"" });
+        let error = send_body(
+            &mut tcp,
+            &[b'0'; 1000],
+            64,
+            &TransferProgress::default(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("pipe"), "{}", error);
+    }
 
-        // For uploads: return upload_duration as end_duration and Duration::ZERO
-        // for both ttfb and server_time. This way:
-        // - transfer_duration() = end_duration - ttfb = upload_duration
-        // - bandwidth calculation uses upload_duration directly without subtracting
-        //   server_time (which for uploads includes the receive time)
-        Ok::<_, Box<dyn Error + Send + Sync>>((upload_duration, Duration::ZERO, Duration::ZERO, upload_duration))
-    })
-    .await?
-    .map_err(|e| e as Box<dyn Error>)?;
+    /// Connection whose socket timed out.
+    struct TimedOut;
 
-    // Signal latency task to stop (Release ensures visibility to other thread)
-    stop_flag.store(true, std::sync::atomic::Ordering::Release);
+    impl Read for TimedOut {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+
+    #[test]
+    fn test_read_continue() {
+        let mut continued = &b"HTTP/1.1 100 Continue\r\n\r\n"[..];
+        assert!(read_continue(&mut continued).unwrap());
+
+        let mut hinted = &b"HTTP/1.1 103 Early Hints\r\n\r\n\
+                            HTTP/1.1 100 Continue\r\n\r\n"[..];
+        assert!(read_continue(&mut hinted).unwrap());
+        assert!(hinted.is_empty());
 
-    // Wait for latency task to finish (with timeout)
-    let _ =
-        tokio::time::timeout(Duration::from_millis(100), latency_handle).await;
+        // No answer in time: send the body anyway
+        assert!(!read_continue(&mut TimedOut).unwrap());
 
-    Ok(result)
+        // Rejected before any of the body is sent
+        let mut rejected = &include_bytes!("fixtures/rate_limited.http")[..];
+        let error = read_continue(&mut rejected).unwrap_err();
+        assert_eq!(error.to_string(), "HTTP 429 from speed test server");
+
+        let mut accepted = &include_bytes!("fixtures/upload.http")[..];
+        assert!(read_continue(&mut accepted).is_err());
+    }
+
+    #[test]
+    fn test_upload_skips_late_continue() {
+        let url = Url::parse("https://speed.cloudflare.com/__up").unwrap();
+        let head = build_http_post_header(&url, 1000, &HeaderMap::new());
+        assert!(head.contains("\r\nExpect: 100-continue\r\n"));
+
+        // Without a socket to bound the wait, the body goes out at once
+        // and the 100 Continue is read ahead of the final response
+        let mut tcp: Box<dyn IoReadAndWrite> = Box::new(Rejecting {
+            accept: usize::MAX,
+            response: b"HTTP/1.1 100 Continue\r\n\r\n\
+                        HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+        });
+        let exchange = Upload::exchange(
+            &mut tcp,
+            &head,
+            (Arc::new(vec![b'0'; 1000]), 64),
+            None,
+            &TransferProgress::default(),
+        )
+        .unwrap();
+        assert_eq!(exchange.ttfb, Duration::ZERO);
+    }
 }
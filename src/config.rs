@@ -0,0 +1,578 @@
+//! Configuration file support.
+//!
+//! Settings are read from a TOML file, by default
+//! `$XDG_CONFIG_HOME/cloud-speed/config.toml` (`%APPDATA%` on Windows).
+//! Every section is optional, and a missing default file is the same as an
+//! empty one.
+//!
+//! ```toml
+//! [scoring.gaming]
+//! latency = { great = 20.0, good = 40.0, average = 80.0 }
+//!
+//! [test]
+//! load_streams = 4
+//! dscp = "ef"
+//!
+//! [test.headers]
+//! "CF-Access-Client-Id" = "<id>"
+//!
+//! [tui]
+//! refresh_ms = 250
+//! ```
+
+use crate::cloudflare::client::DEFAULT_USER_AGENT;
+use crate::cloudflare::tests::connection::Dscp;
+use crate::cloudflare::tests::engine::TestOrder;
+use crate::cloudflare::tests::packet_loss::LossProvider;
+use crate::errors::SpeedTestError;
+use crate::history::Retention;
+use crate::scoring::ScoringThresholds;
+use crate::tui::DEFAULT_REFRESH_INTERVAL;
+use chrono::TimeDelta;
+use http::header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// File name of the config file inside the config directory.
+const CONFIG_FILE: &str = "config.toml";
+
+/// Settings loaded from the config file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// AIM score threshold overrides
+    pub scoring: ScoringThresholds,
+    /// Measurement settings, overridden by command line flags
+    pub test: TestSettings,
+    /// Commands run around each `ab` run
+    pub ab: AbHooks,
+    /// Commands `--compare-vpn` uses to switch the VPN
+    pub vpn: VpnCommands,
+    /// Where `--share` posts result summaries
+    pub share: ShareSettings,
+    /// How much history to keep
+    pub history: HistorySettings,
+    /// Where `--compare-region` gets regional medians
+    pub regional: RegionalSettings,
+    /// How the interactive display is drawn
+    pub tui: TuiSettings,
+}
+
+/// Measurement settings mirroring the command line flags of the same
+/// name. Unset values fall back to the flag defaults.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TestSettings {
+    /// Measure idle latency with ICMP echo
+    pub icmp: Option<bool>,
+    /// Spacing between idle latency probes in ms
+    pub latency_interval: Option<u64>,
+    /// Send idle latency probes on a fixed tick
+    pub latency_tick: Option<bool>,
+    /// Idle latency probes in flight at once (2-16)
+    pub latency_parallel: Option<usize>,
+    /// Background load streams (0-32)
+    pub load_streams: Option<u32>,
+    /// Requested `SO_SNDBUF` in bytes
+    pub send_buffer: Option<usize>,
+    /// Requested `SO_RCVBUF` in bytes
+    pub recv_buffer: Option<usize>,
+    /// DSCP class for measurement traffic
+    pub dscp: Option<Dscp>,
+    /// Pre-warm and resume a TLS session
+    pub prewarm_tls: Option<bool>,
+    /// Pause between measurements on shared networks
+    pub nice: Option<bool>,
+    /// Randomize request gaps and sizes
+    pub pacing_jitter: Option<bool>,
+    /// Probe the path MTU before measuring
+    pub mtu: Option<bool>,
+    /// Compare cold and warm connection setup
+    pub handshake: Option<bool>,
+    /// Order the download and upload blocks run in
+    pub order: Option<TestOrder>,
+    /// Saturate both directions at once after the one-way tests
+    pub duplex: Option<bool>,
+    /// Record retried measurements in the results
+    pub detailed: Option<bool>,
+    /// Coefficient of variation that ends a size block early (0-1, 0
+    /// disables)
+    pub convergence_cv: Option<f64>,
+    /// Measurements the coefficient of variation is taken over (2-32)
+    pub convergence_window: Option<u32>,
+    /// Seconds without data before a transfer counts as stalled
+    pub stall_timeout: Option<u64>,
+    /// Abort and retry stalled transfers
+    pub abort_stalled: Option<bool>,
+    /// Rerun latency or a size block the network changed under
+    pub restart_on_network_change: Option<bool>,
+    /// Kind of server packet loss is measured against
+    pub loss_provider: Option<LossProvider>,
+    /// Server packet loss is measured against
+    pub loss_server: Option<String>,
+    /// User-Agent for speed test requests
+    #[serde(deserialize_with = "header_value")]
+    pub user_agent: Option<HeaderValue>,
+    /// Extra headers for speed test requests
+    #[serde(deserialize_with = "header_map")]
+    pub headers: HeaderMap,
+}
+
+impl TestSettings {
+    /// Headers for speed test requests, User-Agent included.
+    pub fn request_headers(&self) -> HeaderMap {
+        provider_headers(self.user_agent.as_ref(), &self.headers)
+    }
+}
+
+/// Retention limits for the history store, enforced whenever a run is
+/// recorded and by `history prune`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistorySettings {
+    /// Most runs to keep
+    pub max_entries: Option<usize>,
+    /// Drop runs older than this many days
+    pub max_age_days: Option<u32>,
+    /// Largest the history file may grow, in KiB
+    pub max_size_kb: Option<u64>,
+}
+
+impl HistorySettings {
+    /// The retention policy these settings describe.
+    pub fn retention(&self) -> Retention {
+        Retention {
+            max_entries: self.max_entries,
+            max_age: self
+                .max_age_days
+                .map(|days| TimeDelta::days(i64::from(days))),
+            max_bytes: self.max_size_kb.map(|kb| kb * 1024),
+        }
+    }
+}
+
+/// Settings for the interactive display.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TuiSettings {
+    /// Milliseconds between redraws while a test runs (20-1000)
+    pub refresh_ms: Option<u64>,
+}
+
+impl TuiSettings {
+    /// How often to redraw while a test runs.
+    pub fn refresh_interval(&self) -> Duration {
+        self.refresh_ms.map_or(DEFAULT_REFRESH_INTERVAL, Duration::from_millis)
+    }
+}
+
+/// Shell commands that bring the VPN up and down, for `--compare-vpn`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VpnCommands {
+    pub up: Option<String>,
+    pub down: Option<String>,
+}
+
+/// Settings for `--share`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShareSettings {
+    /// Paste endpoint that accepts a snippet as a plain text POST and
+    /// answers with a link to it
+    pub endpoint: Option<String>,
+    /// User-Agent for requests to the endpoint
+    #[serde(deserialize_with = "header_value")]
+    pub user_agent: Option<HeaderValue>,
+    /// Extra headers for requests to the endpoint
+    #[serde(deserialize_with = "header_map")]
+    pub headers: HeaderMap,
+}
+
+impl ShareSettings {
+    /// Headers for requests to the endpoint, User-Agent included.
+    pub fn request_headers(&self) -> HeaderMap {
+        provider_headers(self.user_agent.as_ref(), &self.headers)
+    }
+}
+
+/// Settings for `--compare-region`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegionalSettings {
+    /// Endpoint answering like Cloudflare Radar's speed summary, instead
+    /// of Radar itself
+    pub endpoint: Option<String>,
+    /// Radar API token, instead of `CLOUDFLARE_API_TOKEN`
+    pub token: Option<String>,
+    /// User-Agent for requests to the endpoint
+    #[serde(deserialize_with = "header_value")]
+    pub user_agent: Option<HeaderValue>,
+    /// Extra headers for requests to the endpoint
+    #[serde(deserialize_with = "header_map")]
+    pub headers: HeaderMap,
+}
+
+impl RegionalSettings {
+    /// Headers for requests to the endpoint, User-Agent included.
+    pub fn request_headers(&self) -> HeaderMap {
+        provider_headers(self.user_agent.as_ref(), &self.headers)
+    }
+}
+
+/// A provider's `headers`, with `user_agent` as the User-Agent. Without
+/// one, a User-Agent among `headers` is kept, and [`DEFAULT_USER_AGENT`]
+/// is sent otherwise.
+fn provider_headers(
+    user_agent: Option<&HeaderValue>,
+    headers: &HeaderMap,
+) -> HeaderMap {
+    let mut headers = headers.clone();
+    match user_agent {
+        Some(user_agent) => {
+            headers.insert(USER_AGENT, user_agent.clone());
+        }
+        None if !headers.contains_key(USER_AGENT) => {
+            headers.insert(
+                USER_AGENT,
+                HeaderValue::from_static(DEFAULT_USER_AGENT),
+            );
+        }
+        None => {}
+    }
+    headers
+}
+
+fn header_value<'de, D>(
+    deserializer: D,
+) -> Result<Option<HeaderValue>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    HeaderValue::from_str(&value).map(Some).map_err(|e| {
+        D::Error::custom(format!("invalid header value '{}': {}", value, e))
+    })
+}
+
+/// A table of header names to values.
+fn header_map<'de, D>(deserializer: D) -> Result<HeaderMap, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    BTreeMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(name, value)| {
+            let value = HeaderValue::from_str(&value).map_err(|e| {
+                D::Error::custom(format!(
+                    "invalid value for header '{}': {}",
+                    name, e
+                ))
+            })?;
+            let name =
+                HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+                    D::Error::custom(format!(
+                        "invalid header name '{}': {}",
+                        name, e
+                    ))
+                })?;
+            Ok((name, value))
+        })
+        .collect()
+}
+
+/// Shell commands `ab` runs before and after each run of a
+/// configuration, e.g. to bring a VPN up and down.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AbHooks {
+    /// Run before each test; a failure aborts the comparison
+    pub before: Option<String>,
+    /// Run after each test, even a failed one
+    pub after: Option<String>,
+}
+
+impl Config {
+    /// Parse and validate config file contents.
+    pub fn from_toml(contents: &str) -> Result<Self, String> {
+        let config: Self =
+            toml::from_str(contents).map_err(|e| e.message().to_string())?;
+        config.scoring.validate().map_err(|e| format!("scoring.{}", e))?;
+        if config.test.load_streams.is_some_and(|n| n > 32) {
+            return Err("test.load_streams must be between 0 and 32".into());
+        }
+        if config.test.latency_parallel.is_some_and(|n| !(2..=16).contains(&n))
+        {
+            return Err(
+                "test.latency_parallel must be between 2 and 16".into()
+            );
+        }
+        if config.test.latency_tick == Some(true)
+            && config.test.latency_parallel.is_some()
+        {
+            return Err("test.latency_tick and test.latency_parallel can't \
+                        both be set"
+                .into());
+        }
+        if config
+            .test
+            .convergence_cv
+            .is_some_and(|cv| !(0.0..=1.0).contains(&cv))
+        {
+            return Err("test.convergence_cv must be between 0 and 1".into());
+        }
+        if config
+            .test
+            .convergence_window
+            .is_some_and(|n| !(2..=32).contains(&n))
+        {
+            return Err(
+                "test.convergence_window must be between 2 and 32".into()
+            );
+        }
+        if config.tui.refresh_ms.is_some_and(|ms| !(20..=1000).contains(&ms)) {
+            return Err("tui.refresh_ms must be between 20 and 1000".into());
+        }
+        Ok(config)
+    }
+
+    /// Load the config file.
+    ///
+    /// An explicit `path` must exist. Without one, the default location is
+    /// used if present, and defaults apply otherwise.
+    pub fn load(path: Option<&Path>) -> Result<Self, SpeedTestError> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_config_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(Self::default())
+            }
+            Err(e) => {
+                return Err(SpeedTestError::config(format!(
+                    "Failed to read config file {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+
+        log::debug!("Loading config from {}", path.display());
+
+        Self::from_toml(&contents).map_err(|e| {
+            SpeedTestError::config(format!(
+                "Invalid config file {}: {}",
+                path.display(),
+                e
+            ))
+            .with_suggestion("Check the config file against the README")
+        })
+    }
+}
+
+/// Default config file location.
+fn default_config_path() -> Option<PathBuf> {
+    let env_dir = |key: &str| {
+        std::env::var_os(key).filter(|v| !v.is_empty()).map(PathBuf::from)
+    };
+
+    let base = if cfg!(windows) {
+        env_dir("APPDATA")
+    } else {
+        env_dir("XDG_CONFIG_HOME")
+            .or_else(|| env_dir("HOME").map(|h| h.join(".config")))
+    };
+
+    base.map(|dir| dir.join("cloud-speed").join(CONFIG_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scoring::Tiers;
+
+    #[test]
+    fn test_empty_config_is_default() {
+        assert_eq!(Config::from_toml("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_partial_scoring_override() {
+        let config = Config::from_toml(
+            r#"
+            [scoring.gaming]
+            latency = { great = 20.0, good = 40.0, average = 80.0 }
+            "#,
+        )
+        .unwrap();
+
+        let defaults = ScoringThresholds::default();
+        assert_eq!(
+            config.scoring.gaming.latency,
+            Tiers::new(20.0, 40.0, 80.0)
+        );
+        assert_eq!(config.scoring.gaming.jitter, defaults.gaming.jitter);
+        assert_eq!(config.scoring.streaming, defaults.streaming);
+    }
+
+    #[test]
+    fn test_non_monotonic_thresholds_rejected() {
+        let err = Config::from_toml(
+            r#"
+            [scoring.streaming]
+            download = { great = 5.0, good = 10.0, average = 25.0 }
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.contains("scoring.streaming.download"));
+    }
+
+    #[test]
+    fn test_unknown_keys_rejected() {
+        assert!(Config::from_toml("[scoring.gamming]\n").is_err());
+        assert!(Config::from_toml(
+            "[scoring.gaming]\nlatency = { great = 1.0, good = 2.0 }\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_test_and_ab_sections() {
+        let config = Config::from_toml(
+            r#"
+            [test]
+            load_streams = 4
+            dscp = "af41"
+            icmp = true
+            order = "concurrent"
+            loss_provider = "udp-echo"
+
+            [ab]
+            before = "wg-quick up wg0"
+
+            [vpn]
+            up = "wg-quick up wg0"
+            down = "wg-quick down wg0"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.test.load_streams, Some(4));
+        assert_eq!(config.test.dscp, Some("af41".parse().unwrap()));
+        assert_eq!(config.test.icmp, Some(true));
+        assert_eq!(config.test.nice, None);
+        assert_eq!(config.test.order, Some(TestOrder::Concurrent));
+        assert_eq!(config.test.loss_provider, Some(LossProvider::UdpEcho));
+        assert_eq!(config.ab.before.as_deref(), Some("wg-quick up wg0"));
+        assert_eq!(config.ab.after, None);
+        assert_eq!(config.vpn.down.as_deref(), Some("wg-quick down wg0"));
+
+        assert!(Config::from_toml("[test]\ndscp = \"af51\"\n").is_err());
+        assert!(Config::from_toml("[test]\nload_streams = 33\n").is_err());
+        assert_eq!(
+            Config::from_toml("[test]\nconvergence_cv = 1.5\n").unwrap_err(),
+            "test.convergence_cv must be between 0 and 1"
+        );
+        assert!(Config::from_toml("[test]\nconvergence_window = 1\n").is_err());
+        assert_eq!(
+            Config::from_toml("[test]\nlatency_parallel = 1\n").unwrap_err(),
+            "test.latency_parallel must be between 2 and 16"
+        );
+        assert!(Config::from_toml("[test]\nlatency_parallel = 17\n").is_err());
+        assert!(Config::from_toml(
+            "[test]\nlatency_tick = true\nlatency_parallel = 4\n"
+        )
+        .is_err());
+        assert!(Config::from_toml("[test]\norder = \"random\"\n").is_err());
+    }
+
+    #[test]
+    fn test_provider_headers() {
+        let config = Config::from_toml(
+            r#"
+            [test]
+            user_agent = "Mozilla/5.0"
+
+            [test.headers]
+            "CF-Access-Client-Id" = "service-token"
+            "User-Agent" = "ignored/1.0"
+
+            [share.headers]
+            "User-Agent" = "paste-client/2.0"
+            "#,
+        )
+        .unwrap();
+
+        let test = config.test.request_headers();
+        assert_eq!(test.get(USER_AGENT).unwrap(), "Mozilla/5.0");
+        assert_eq!(test.get("cf-access-client-id").unwrap(), "service-token");
+        assert_eq!(
+            config.share.request_headers().get(USER_AGENT).unwrap(),
+            "paste-client/2.0"
+        );
+        assert_eq!(
+            config.regional.request_headers().get(USER_AGENT).unwrap(),
+            DEFAULT_USER_AGENT
+        );
+
+        assert!(Config::from_toml("[test]\nuser_agent = \"a\\nb\"\n").is_err());
+        assert!(Config::from_toml("[share.headers]\n\"bad name\" = \"x\"\n")
+            .is_err());
+    }
+
+    #[test]
+    fn test_history_section() {
+        let config = Config::from_toml(
+            r#"
+            [history]
+            max_entries = 500
+            max_age_days = 90
+            "#,
+        )
+        .unwrap();
+
+        let retention = config.history.retention();
+        assert_eq!(retention.max_entries, Some(500));
+        assert_eq!(retention.max_age, Some(TimeDelta::days(90)));
+        assert_eq!(retention.max_bytes, None);
+        assert!(Config::default().history.retention().is_unlimited());
+        assert!(Config::from_toml(
+            "[history]
+max_age = 90
+"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_tui_section() {
+        let config = Config::from_toml("[tui]\nrefresh_ms = 250\n").unwrap();
+        assert_eq!(config.tui.refresh_interval(), Duration::from_millis(250));
+        assert_eq!(
+            Config::default().tui.refresh_interval(),
+            DEFAULT_REFRESH_INTERVAL
+        );
+        assert_eq!(
+            Config::from_toml("[tui]\nrefresh_ms = 5\n").unwrap_err(),
+            "tui.refresh_ms must be between 20 and 1000"
+        );
+    }
+
+    #[test]
+    fn test_missing_explicit_file_is_error() {
+        let path = std::env::temp_dir().join("cloud-speed-missing.toml");
+        let err = Config::load(Some(&path)).unwrap_err();
+        assert_eq!(err.exit_code(), crate::errors::exit_codes::CONFIG_ERROR);
+    }
+}
@@ -0,0 +1,428 @@
+//! Comparison of two saved results.
+//!
+//! `cloud-speed diff before.json after.json` compares each metric of two
+//! runs, e.g. from before and after a change to the network. A single run
+//! has no repeat to test a difference against, so each difference is
+//! weighed against the spread the runs recorded themselves: how far the
+//! size blocks that saturated the link disagreed for bandwidth, jitter for
+//! latency, and the sampling error of the packet count for loss.
+
+use crate::reporters::CommandReport;
+use crate::results::{BandwidthResults, SpeedTestResults};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, Write};
+
+/// How a difference compares to the spread of the two runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Significance {
+    /// More than twice the combined spread
+    Significant,
+    /// More than the combined spread, but not twice
+    Marginal,
+    /// Within the combined spread
+    Noise,
+    /// A run recorded no spread for the metric
+    Unknown,
+}
+
+impl Significance {
+    /// Weigh `difference` against the `spread` of the two runs.
+    fn of(difference: f64, spread: Option<f64>) -> Self {
+        match spread {
+            Some(spread) if difference.abs() > 2.0 * spread => {
+                Significance::Significant
+            }
+            Some(spread) if difference.abs() > spread => {
+                Significance::Marginal
+            }
+            Some(_) => Significance::Noise,
+            None => Significance::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for Significance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Significance::Significant => "significant",
+            Significance::Marginal => "marginal",
+            Significance::Noise => "within noise",
+            Significance::Unknown => "spread unknown",
+        })
+    }
+}
+
+/// One side of the comparison.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffSide {
+    /// File the results were read from
+    pub file: String,
+    /// When the run finished
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Difference of one metric between the two runs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricDiff {
+    pub metric: &'static str,
+    pub unit: &'static str,
+    pub before: f64,
+    pub after: f64,
+    /// `after - before`
+    pub difference: f64,
+    /// Difference relative to `before`, unless that is zero
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_percent: Option<f64>,
+    /// Combined spread of the two runs, when both recorded one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spread: Option<f64>,
+    pub significance: Significance,
+    /// Whether the metric got better; `None` when it didn't change
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub improved: Option<bool>,
+}
+
+/// Comparison of two results.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResultsDiff {
+    pub before: DiffSide,
+    pub after: DiffSide,
+    /// Metrics both runs measured
+    pub metrics: Vec<MetricDiff>,
+}
+
+/// A metric's value and spread in one run.
+struct Reading {
+    value: f64,
+    spread: Option<f64>,
+}
+
+/// A compared metric: name, unit, whether higher is better, and how to
+/// read it from results.
+struct Metric {
+    name: &'static str,
+    unit: &'static str,
+    higher_is_better: bool,
+    read: fn(&SpeedTestResults) -> Option<Reading>,
+}
+
+const METRICS: [Metric; 7] = [
+    Metric {
+        name: "download",
+        unit: "Mbps",
+        higher_is_better: true,
+        read: |r| r.download.as_ref().map(bandwidth),
+    },
+    Metric {
+        name: "upload",
+        unit: "Mbps",
+        higher_is_better: true,
+        read: |r| r.upload.as_ref().map(bandwidth),
+    },
+    Metric {
+        name: "idle_latency",
+        unit: "ms",
+        higher_is_better: false,
+        read: |r| {
+            Some(Reading {
+                value: r.latency.idle_ms,
+                spread: r.latency.idle_jitter_ms,
+            })
+        },
+    },
+    Metric {
+        name: "idle_jitter",
+        unit: "ms",
+        higher_is_better: false,
+        read: |r| {
+            r.latency
+                .idle_jitter_ms
+                .map(|value| Reading { value, spread: None })
+        },
+    },
+    Metric {
+        name: "loaded_latency_down",
+        unit: "ms",
+        higher_is_better: false,
+        read: |r| {
+            r.latency.loaded_down_ms.map(|value| Reading {
+                value,
+                spread: r.latency.loaded_down_jitter_ms,
+            })
+        },
+    },
+    Metric {
+        name: "loaded_latency_up",
+        unit: "ms",
+        higher_is_better: false,
+        read: |r| {
+            r.latency.loaded_up_ms.map(|value| Reading {
+                value,
+                spread: r.latency.loaded_up_jitter_ms,
+            })
+        },
+    },
+    Metric {
+        name: "packet_loss",
+        unit: "%",
+        higher_is_better: false,
+        read: |r| {
+            r.packet_loss.as_ref().map(|loss| Reading {
+                value: loss.percent,
+                // Standard error of the loss ratio over the packets sent
+                spread: (loss.packets_sent > 0).then(|| {
+                    let n = loss.packets_sent as f64;
+                    (loss.ratio * (1.0 - loss.ratio) / n).sqrt() * 100.0
+                }),
+            })
+        },
+    },
+];
+
+/// Bandwidth and its spread: half the range of the size blocks that
+/// reached at least half the final speed. Smaller blocks end before TCP
+/// gets up to speed, so their lower speeds aren't noise.
+fn bandwidth(results: &BandwidthResults) -> Reading {
+    let saturated: Vec<f64> = results
+        .measurements
+        .iter()
+        .map(|size| size.speed_mbps)
+        .filter(|&speed| speed >= results.speed_mbps / 2.0)
+        .collect();
+    let spread = (saturated.len() >= 2).then(|| {
+        let max = saturated.iter().copied().fold(f64::MIN, f64::max);
+        let min = saturated.iter().copied().fold(f64::MAX, f64::min);
+        (max - min) / 2.0
+    });
+
+    Reading { value: results.speed_mbps, spread }
+}
+
+impl ResultsDiff {
+    /// Compare the metrics both `before` and `after` measured.
+    pub fn new(
+        before: DiffSide,
+        after: DiffSide,
+        runs: [&SpeedTestResults; 2],
+    ) -> Self {
+        let metrics = METRICS
+            .iter()
+            .filter_map(|metric| {
+                let a = (metric.read)(runs[0])?;
+                let b = (metric.read)(runs[1])?;
+
+                let difference = b.value - a.value;
+                // Independent spreads add in quadrature
+                let spread = a.spread.zip(b.spread).map(|(a, b)| a.hypot(b));
+                Some(MetricDiff {
+                    metric: metric.name,
+                    unit: metric.unit,
+                    before: a.value,
+                    after: b.value,
+                    difference,
+                    change_percent: (a.value != 0.0)
+                        .then(|| difference / a.value * 100.0),
+                    spread,
+                    significance: Significance::of(difference, spread),
+                    improved: (difference != 0.0).then_some(
+                        (difference > 0.0) == metric.higher_is_better,
+                    ),
+                })
+            })
+            .collect();
+
+        Self { before, after, metrics }
+    }
+}
+
+/// Print a human-readable comparison table.
+pub fn print_diff<W: Write + ?Sized>(
+    diff: &ResultsDiff,
+    out: &mut W,
+) -> io::Result<()> {
+    for (label, side) in [("Before", &diff.before), ("After", &diff.after)] {
+        writeln!(
+            out,
+            "{:<7} {} ({})",
+            label,
+            side.file,
+            side.timestamp.format("%Y-%m-%d %H:%M UTC")
+        )?;
+    }
+    writeln!(out)?;
+
+    if diff.metrics.is_empty() {
+        writeln!(out, "No metrics measured by both runs")?;
+        return Ok(());
+    }
+
+    writeln!(
+        out,
+        "{:<26} {:>10} {:>10} {:>9}",
+        "Metric", "Before", "After", "Change"
+    )?;
+    for metric in &diff.metrics {
+        let change = metric
+            .change_percent
+            .map(|c| format!("{:+.1}%", c))
+            .unwrap_or_else(|| "-".to_string());
+        write!(
+            out,
+            "{:<26} {:>10.2} {:>10.2} {:>9}",
+            format!("{} ({})", metric.metric, metric.unit),
+            metric.before,
+            metric.after,
+            change
+        )?;
+        match metric.improved {
+            Some(improved) => writeln!(
+                out,
+                "  {}, {}",
+                if improved { "better" } else { "worse" },
+                metric.significance
+            )?,
+            None => writeln!(out)?,
+        }
+    }
+    writeln!(out)?;
+    writeln!(
+        out,
+        "Differences beyond twice the runs' combined spread are significant"
+    )
+}
+
+impl CommandReport for ResultsDiff {
+    fn write_human(&self, out: &mut dyn Write) -> io::Result<()> {
+        print_diff(self, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::{
+        AimScoresOutput, ConnectionMeta, LatencyResults, ServerLocation,
+        SizeMeasurement,
+    };
+
+    fn size(speed_mbps: f64) -> SizeMeasurement {
+        SizeMeasurement::new(10_000_000, speed_mbps, 4)
+    }
+
+    fn results(download: &[f64], latency_ms: f64) -> SpeedTestResults {
+        SpeedTestResults::new(
+            ServerLocation::new("Dallas".into(), "DFW".into()),
+            ConnectionMeta::new(
+                "203.0.113.7".into(),
+                "US".into(),
+                "Example ISP".into(),
+                64500,
+            ),
+            LatencyResults::new(latency_ms, Some(2.0), None, None, None, None),
+            BandwidthResults::new(
+                download.iter().copied().fold(0.0, f64::max),
+                download.iter().map(|&speed| size(speed)).collect(),
+                false,
+            ),
+            BandwidthResults::new(10.0, vec![], false),
+            None,
+            AimScoresOutput {
+                streaming: "great".to_string(),
+                gaming: "good".to_string(),
+                video_conferencing: "good".to_string(),
+                overall: "good".to_string(),
+                details: None,
+                working_latency: None,
+                grade: None,
+            },
+        )
+    }
+
+    fn side(file: &str) -> DiffSide {
+        DiffSide { file: file.to_string(), timestamp: Utc::now() }
+    }
+
+    fn diff(
+        before: &SpeedTestResults,
+        after: &SpeedTestResults,
+    ) -> ResultsDiff {
+        ResultsDiff::new(side("a.json"), side("b.json"), [before, after])
+    }
+
+    fn metric<'a>(diff: &'a ResultsDiff, name: &str) -> &'a MetricDiff {
+        diff.metrics.iter().find(|m| m.metric == name).unwrap()
+    }
+
+    #[test]
+    fn test_bandwidth_spread_ignores_ramp_up() {
+        // The 20 Mbps block never got up to speed
+        let reading =
+            bandwidth(&results(&[20.0, 96.0, 100.0], 10.0).download.unwrap());
+
+        assert_eq!(reading.value, 100.0);
+        assert_eq!(reading.spread, Some(2.0));
+    }
+
+    #[test]
+    fn test_significance_against_spread() {
+        let before = results(&[96.0, 100.0], 10.0);
+
+        // Spread of 2 Mbps on each side combines to about 2.8
+        let after = results(&[146.0, 150.0], 10.5);
+        let diff = diff(&before, &after);
+        let download = metric(&diff, "download");
+        assert_eq!(download.significance, Significance::Significant);
+        assert_eq!(download.improved, Some(true));
+        assert_eq!(download.change_percent, Some(50.0));
+
+        // Latency moved by less than its jitter
+        let latency = metric(&diff, "idle_latency");
+        assert_eq!(latency.significance, Significance::Noise);
+        assert_eq!(latency.improved, Some(false));
+
+        let after = results(&[99.0, 103.0], 10.0);
+        let diff = self::diff(&before, &after);
+        assert_eq!(
+            metric(&diff, "download").significance,
+            Significance::Marginal
+        );
+        assert_eq!(metric(&diff, "idle_latency").improved, None);
+        // Jitter has no spread of its own
+        assert_eq!(
+            metric(&diff, "idle_jitter").significance,
+            Significance::Unknown
+        );
+    }
+
+    #[test]
+    fn test_metrics_missing_from_either_run_are_skipped() {
+        let before = results(&[100.0], 10.0);
+        let mut after = results(&[100.0], 10.0);
+        after.download = None;
+
+        let diff = diff(&before, &after);
+        assert!(diff.metrics.iter().all(|m| m.metric != "download"));
+        assert!(diff.metrics.iter().all(|m| m.metric != "packet_loss"));
+        assert!(diff.metrics.iter().any(|m| m.metric == "upload"));
+    }
+
+    #[test]
+    fn test_print_diff() {
+        let diff = diff(
+            &results(&[96.0, 100.0], 10.0),
+            &results(&[146.0, 150.0], 10.0),
+        );
+        let mut out = Vec::new();
+        print_diff(&diff, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.starts_with("Before  a.json ("));
+        assert!(text.contains(
+            "download (Mbps)                100.00     150.00    +50.0%  \
+             better, significant"
+        ));
+    }
+}
@@ -0,0 +1,542 @@
+//! Environment diagnostics for `cloud-speed doctor`.
+//!
+//! Runs a series of independent checks covering the things that most
+//! often make a speed test fail or misbehave — DNS, TCP/TLS reachability,
+//! proxy settings, IPv6, the system clock and the terminal — and prints
+//! a pass/fail report. Failures reuse the error classification from
+//! [`crate::errors`] so the suggestions match what a failed test prints.
+
+use crate::clock::{
+    describe_skew, parse_http_date, skew_secs, CLOCK_SKEW_FAIL_SECS,
+    CLOCK_SKEW_WARN_SECS,
+};
+use crate::cloudflare::proxy::{env_proxy, redact, ProxySettings};
+use crate::cloudflare::tests::connection::{
+    connect, name_server, resolve_dns, tls_handshake,
+};
+use crate::cloudflare::tests::BASE_URL;
+use crate::errors::{classify_error, exit_codes, ErrorKind};
+use crate::tui::renderer::is_minimal_mode;
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use std::error::Error;
+use std::io::{self, IsTerminal, Read, Write};
+use std::net::{IpAddr, Ipv6Addr, UdpSocket};
+use url::Url;
+
+/// Public IPv6 address used to look for an IPv6 route. No packets are
+/// sent; connecting a UDP socket only consults the routing table.
+const IPV6_ROUTE_PROBE: Ipv6Addr =
+    Ipv6Addr::new(0x2606, 0x4700, 0x4700, 0, 0, 0, 0, 0x1111);
+
+/// Outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Result of a single diagnostic check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    /// Short name of the check
+    pub name: &'static str,
+    /// Whether the check passed
+    pub status: CheckStatus,
+    /// What was found
+    pub detail: String,
+    /// How to fix a warning or failure
+    pub suggestion: Option<String>,
+    /// Error category of a failure, used for the exit code
+    pub kind: Option<ErrorKind>,
+}
+
+impl CheckResult {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            suggestion: None,
+            kind: None,
+        }
+    }
+
+    fn warn(
+        name: &'static str,
+        detail: impl Into<String>,
+        suggestion: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Warn,
+            detail: detail.into(),
+            suggestion: Some(suggestion.into()),
+            kind: None,
+        }
+    }
+
+    /// A failed check, classified from the error that caused it.
+    fn fail(name: &'static str, error: &dyn Error) -> Self {
+        let kind = classify_error(error);
+        Self {
+            name,
+            status: CheckStatus::Fail,
+            detail: format!("{}: {}", kind.description(), error),
+            suggestion: kind.suggestion().map(str::to_string),
+            kind: Some(kind),
+        }
+    }
+}
+
+/// Run every check and print the report to `out`.
+///
+/// # Returns
+/// The process exit code: success unless a check failed, in which case
+/// the code for the first failure's error kind.
+pub async fn run(out: &mut dyn Write) -> io::Result<i32> {
+    let checks = run_checks().await;
+    print_report(&checks, out)?;
+
+    Ok(checks
+        .iter()
+        .find(|c| c.status == CheckStatus::Fail)
+        .map(|c| c.kind.unwrap_or(ErrorKind::Unknown).exit_code())
+        .unwrap_or(exit_codes::SUCCESS))
+}
+
+/// Run every check in order.
+pub async fn run_checks() -> Vec<CheckResult> {
+    let url = Url::parse(BASE_URL).expect("BASE_URL is a valid URL");
+
+    let mut checks =
+        vec![check_proxy(env_proxy(), &url), check_dns(&url).await];
+    checks.extend(check_connectivity(&url).await);
+    checks.push(check_ipv6());
+    checks.push(check_terminal(
+        io::stdout().is_terminal(),
+        std::env::var("TERM").ok().as_deref(),
+        std::env::var_os("NO_COLOR").is_some(),
+        crossterm::terminal::size().ok(),
+    ));
+
+    checks
+}
+
+/// Print `checks` as a report.
+pub fn print_report(
+    checks: &[CheckResult],
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    writeln!(out, "{}", "cloud-speed doctor".bold().white())?;
+    writeln!(out)?;
+
+    for check in checks {
+        let status = match check.status {
+            CheckStatus::Pass => "PASS".green(),
+            CheckStatus::Warn => "WARN".yellow(),
+            CheckStatus::Fail => "FAIL".red(),
+        };
+        writeln!(
+            out,
+            "  [{}] {:<22} {}",
+            status,
+            check.name.bold(),
+            check.detail
+        )?;
+        if let Some(ref suggestion) = check.suggestion {
+            writeln!(
+                out,
+                "         {} {}",
+                "Suggestion:".yellow(),
+                suggestion
+            )?;
+        }
+    }
+
+    let failed =
+        checks.iter().filter(|c| c.status == CheckStatus::Fail).count();
+    let warned =
+        checks.iter().filter(|c| c.status == CheckStatus::Warn).count();
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{} passed, {} warnings, {} failed",
+        checks.len() - failed - warned,
+        warned,
+        failed
+    )?;
+
+    Ok(())
+}
+
+/// Report the proxy the test would use, rejecting unsupported schemes.
+fn check_proxy(settings: &ProxySettings, url: &Url) -> CheckResult {
+    const NAME: &str = "Proxy";
+
+    match settings.proxy_for(url) {
+        None => CheckResult::pass(NAME, "No proxy configured"),
+        Some(proxy) if proxy.scheme() == "http" => {
+            CheckResult::pass(NAME, format!("Using {}", redact(proxy)))
+        }
+        Some(proxy) => CheckResult {
+            name: NAME,
+            status: CheckStatus::Fail,
+            detail: format!(
+                "Unsupported proxy scheme '{}' in {}",
+                proxy.scheme(),
+                redact(proxy)
+            ),
+            suggestion: Some(
+                "Only http:// proxies are supported. Check https_proxy, \
+                 all_proxy and their uppercase forms."
+                    .to_string(),
+            ),
+            kind: Some(ErrorKind::Config),
+        },
+    }
+}
+
+async fn check_dns(url: &Url) -> CheckResult {
+    const NAME: &str = "DNS resolution";
+
+    match resolve_dns(url).await {
+        Ok((ip, duration)) => CheckResult::pass(
+            NAME,
+            format!(
+                "{} resolves to {} ({:.1} ms{})",
+                url.host_str().unwrap_or_default(),
+                ip,
+                duration.as_secs_f64() * 1000.0,
+                name_server()
+                    .map(|server| format!(" via {}", server))
+                    .unwrap_or_default()
+            ),
+        ),
+        Err(e) => CheckResult::fail(NAME, e.as_ref()),
+    }
+}
+
+/// Check TCP and TLS connectivity, then read the server's clock from the
+/// same connection.
+async fn check_connectivity(url: &Url) -> Vec<CheckResult> {
+    const TCP: &str = "TCP connectivity";
+    const TLS: &str = "TLS handshake";
+    const CLOCK: &str = "Clock skew";
+
+    let (stream, ip, port, duration) = match connect(url).await {
+        Ok(connection) => connection,
+        Err(e) => return vec![CheckResult::fail(TCP, e.as_ref())],
+    };
+    let mut checks = vec![CheckResult::pass(
+        TCP,
+        format!(
+            "Connected to {}:{} in {:.1} ms",
+            ip,
+            port,
+            duration.as_secs_f64() * 1000.0
+        ),
+    )];
+
+    let host = url.host_str().unwrap_or_default().to_string();
+    let (stream, tls) = match tls_handshake(stream, host.clone()).await {
+        Ok(session) => session,
+        Err(e) => {
+            checks.push(CheckResult::fail(TLS, e.as_ref()));
+            return checks;
+        }
+    };
+    checks.push(CheckResult::pass(
+        TLS,
+        format!(
+            "{} {} in {:.1} ms via {} ({} roots)",
+            tls.version,
+            tls.cipher_suite,
+            tls.handshake_duration.as_secs_f64() * 1000.0,
+            tls.backend,
+            tls.trust
+        ),
+    ));
+
+    let local = Utc::now();
+    checks.push(match fetch_server_date(stream, host).await {
+        Ok(Some(server)) => check_clock_skew(local, server),
+        Ok(None) => CheckResult::warn(
+            CLOCK,
+            "Server response had no Date header",
+            "Compare your clock with an NTP server manually.",
+        ),
+        Err(e) => CheckResult::fail(CLOCK, e.as_ref()),
+    });
+
+    checks
+}
+
+/// Send a `HEAD` request over `stream` and parse the `Date` header.
+async fn fetch_server_date(
+    mut stream: Box<dyn crate::cloudflare::tests::IoReadAndWrite>,
+    host: String,
+) -> Result<Option<DateTime<Utc>>, Box<dyn Error>> {
+    let response: Result<String, io::Error> =
+        tokio::task::spawn_blocking(move || {
+            write!(
+                stream,
+                "HEAD / HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+                host
+            )?;
+            stream.flush()?;
+
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response)?;
+            Ok(String::from_utf8_lossy(&response).into_owned())
+        })
+        .await?;
+
+    Ok(parse_date_header(&response?))
+}
+
+/// Extract and parse the `Date` header from raw HTTP response headers.
+fn parse_date_header(response: &str) -> Option<DateTime<Utc>> {
+    response
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim().eq_ignore_ascii_case("date").then_some(value)
+        })
+        .and_then(parse_http_date)
+}
+
+/// Compare the local clock with the server's.
+fn check_clock_skew(
+    local: DateTime<Utc>,
+    server: DateTime<Utc>,
+) -> CheckResult {
+    const NAME: &str = "Clock skew";
+
+    let skew = skew_secs(local, server);
+    let detail = describe_skew(skew);
+
+    if skew.abs() >= CLOCK_SKEW_FAIL_SECS {
+        CheckResult {
+            name: NAME,
+            status: CheckStatus::Fail,
+            detail,
+            suggestion: ErrorKind::Tls.suggestion().map(str::to_string),
+            kind: Some(ErrorKind::Tls),
+        }
+    } else if skew.abs() >= CLOCK_SKEW_WARN_SECS {
+        CheckResult::warn(
+            NAME,
+            detail,
+            "Enable time synchronisation (NTP) on this machine.",
+        )
+    } else {
+        // The Date header only has one second resolution
+        CheckResult::pass(NAME, "Local clock matches the server (±1s)")
+    }
+}
+
+/// Check whether the system has a route to the IPv6 internet.
+fn check_ipv6() -> CheckResult {
+    const NAME: &str = "IPv6";
+
+    let route =
+        UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0)).and_then(|socket| {
+            socket.connect((IPV6_ROUTE_PROBE, 443))?;
+            socket.local_addr()
+        });
+
+    match route {
+        Ok(addr) if is_global_ipv6(addr.ip()) => {
+            CheckResult::pass(NAME, format!("Routable via {}", addr.ip()))
+        }
+        Ok(addr) => CheckResult::warn(
+            NAME,
+            format!("Only a local IPv6 address ({})", addr.ip()),
+            "IPv6 isn't required; tests run over IPv4.",
+        ),
+        Err(e) => CheckResult::warn(
+            NAME,
+            format!("No IPv6 route ({})", e),
+            "IPv6 isn't required; tests run over IPv4.",
+        ),
+    }
+}
+
+/// Whether `ip` is a globally routable IPv6 address (not loopback,
+/// link-local or unique local).
+fn is_global_ipv6(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            !ip.is_loopback()
+                && !ip.is_unspecified()
+                && (first & 0xffc0) != 0xfe80
+                && (first & 0xfe00) != 0xfc00
+        }
+        IpAddr::V4(_) => false,
+    }
+}
+
+/// Check whether the terminal can show the interactive TUI.
+fn check_terminal(
+    is_tty: bool,
+    term: Option<&str>,
+    no_color: bool,
+    size: Option<(u16, u16)>,
+) -> CheckResult {
+    const NAME: &str = "Terminal";
+
+    if !is_tty {
+        return CheckResult::warn(
+            NAME,
+            "Output is not a terminal; the TUI is disabled",
+            "Results will be printed as text. Use --json for scripting.",
+        );
+    }
+
+    if matches!(term, None | Some("") | Some("dumb")) {
+        return CheckResult::warn(
+            NAME,
+            format!("TERM is {}", term.unwrap_or("unset")),
+            "Set TERM (e.g. xterm-256color) so the TUI can render.",
+        );
+    }
+
+    let mut detail = format!("TERM={}", term.unwrap_or_default());
+    if let Some((width, height)) = size {
+        detail.push_str(&format!(", {}x{}", width, height));
+        if is_minimal_mode(width) {
+            return CheckResult::warn(
+                NAME,
+                detail,
+                "The terminal is narrow, so the compact TUI will be used.",
+            );
+        }
+    }
+    if no_color {
+        detail.push_str(", NO_COLOR set");
+    }
+
+    CheckResult::pass(NAME, detail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn proxy_settings(vars: &[(&str, &str)]) -> ProxySettings {
+        ProxySettings::from_lookup(|key| {
+            vars.iter().find(|(k, _)| *k == key).map(|(_, v)| v.to_string())
+        })
+    }
+
+    #[test]
+    fn test_check_proxy() {
+        let url = Url::parse(BASE_URL).unwrap();
+
+        let check = check_proxy(&proxy_settings(&[]), &url);
+        assert_eq!(check.status, CheckStatus::Pass);
+
+        let check = check_proxy(
+            &proxy_settings(&[("https_proxy", "http://proxy:3128")]),
+            &url,
+        );
+        assert_eq!(check.status, CheckStatus::Pass);
+        assert!(check.detail.contains("proxy:3128"));
+
+        let check = check_proxy(
+            &proxy_settings(&[("https_proxy", "socks5://proxy:1080")]),
+            &url,
+        );
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert_eq!(check.kind, Some(ErrorKind::Config));
+    }
+
+    #[test]
+    fn test_parse_date_header() {
+        let response = "HTTP/1.1 200 OK\r\n\
+                        content-type: text/html\r\n\
+                        Date: Fri, 16 Oct 2026 12:00:00 GMT\r\n\
+                        \r\n";
+        assert_eq!(
+            parse_date_header(response),
+            Some(Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap())
+        );
+
+        assert_eq!(parse_date_header("HTTP/1.1 200 OK\r\n\r\n"), None);
+    }
+
+    #[test]
+    fn test_check_clock_skew() {
+        let server = Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+        let at = |secs| server + chrono::Duration::seconds(secs);
+
+        assert_eq!(check_clock_skew(at(1), server).status, CheckStatus::Pass);
+        assert_eq!(
+            check_clock_skew(at(-10), server).status,
+            CheckStatus::Warn
+        );
+
+        let check = check_clock_skew(at(120), server);
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(check.detail.contains("120s ahead of"));
+        assert_eq!(check.kind, Some(ErrorKind::Tls));
+    }
+
+    #[test]
+    fn test_is_global_ipv6() {
+        assert!(is_global_ipv6("2606:4700::1".parse().unwrap()));
+        assert!(!is_global_ipv6("::1".parse().unwrap()));
+        assert!(!is_global_ipv6("fe80::1".parse().unwrap()));
+        assert!(!is_global_ipv6("fd00::1".parse().unwrap()));
+        assert!(!is_global_ipv6("192.0.2.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_check_terminal() {
+        let check = check_terminal(false, Some("xterm"), false, None);
+        assert_eq!(check.status, CheckStatus::Warn);
+
+        let check = check_terminal(true, Some("dumb"), false, None);
+        assert_eq!(check.status, CheckStatus::Warn);
+
+        let check = check_terminal(true, Some("xterm"), false, Some((40, 20)));
+        assert_eq!(check.status, CheckStatus::Warn);
+
+        let check = check_terminal(true, Some("xterm"), true, Some((120, 40)));
+        assert_eq!(check.status, CheckStatus::Pass);
+        assert_eq!(check.detail, "TERM=xterm, 120x40, NO_COLOR set");
+    }
+
+    #[test]
+    fn test_check_result_fail_uses_classification() {
+        let error = io::Error::other("DNS resolution failed: no such host");
+        let check = CheckResult::fail("DNS resolution", &error);
+
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert_eq!(check.kind, Some(ErrorKind::Dns));
+        assert!(check.suggestion.unwrap().contains("DNS settings"));
+    }
+
+    #[test]
+    fn test_print_report() {
+        colored::control::set_override(false);
+
+        let checks = vec![
+            CheckResult::pass("DNS resolution", "ok"),
+            CheckResult::warn("IPv6", "No IPv6 route", "Not required."),
+        ];
+        let mut out = Vec::new();
+        print_report(&checks, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains("[PASS] DNS resolution"));
+        assert!(text.contains("[WARN] IPv6"));
+        assert!(text.contains("Suggestion: Not required."));
+        assert!(text.contains("1 passed, 1 warnings, 0 failed"));
+    }
+}
@@ -3,6 +3,7 @@
 //! This module provides user-friendly error types that wrap underlying
 //! errors with clear, actionable messages.
 
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt;
 
@@ -18,6 +19,18 @@ pub mod exit_codes {
     pub const CONFIG_ERROR: i32 = 3;
     /// Partial failure (some tests failed but others succeeded).
     pub const PARTIAL_FAILURE: i32 = 4;
+    /// The run completed but regressed against the historical baseline.
+    pub const REGRESSION_DETECTED: i32 = 5;
+    /// Result verification found a missing or invalid signature.
+    pub const VERIFICATION_FAILED: i32 = 6;
+    /// Loaded latency exceeded the working latency targets.
+    pub const WORKING_LATENCY_EXCEEDED: i32 = 7;
+    /// The test would run through Cloudflare WARP, with
+    /// `--require-direct`.
+    pub const NOT_DIRECT: i32 = 8;
+    /// A metric missed its `--assert-download`, `--assert-upload` or
+    /// `--assert-latency` threshold.
+    pub const THRESHOLD_NOT_MET: i32 = 9;
     /// User interrupted the operation (Ctrl+C).
     pub const INTERRUPTED: i32 = 130;
     /// Unknown/unexpected error.
@@ -25,7 +38,8 @@ pub mod exit_codes {
 }
 
 /// Categories of errors that can occur during speed testing.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ErrorKind {
     /// Network connectivity issues.
     Network,
@@ -35,8 +49,19 @@ pub enum ErrorKind {
     Timeout,
     /// TLS/SSL handshake failures.
     Tls,
+    /// The server's certificate chains to a root that isn't trusted,
+    /// typically a TLS-inspecting proxy's.
+    #[serde(rename = "untrusted_certificate")]
+    UntrustedCertificate,
+    /// Traffic intercepted by a captive portal or middlebox.
+    Intercepted,
+    /// The server rejected requests with HTTP 429 Too Many Requests.
+    #[serde(rename = "rate_limited")]
+    RateLimited,
     /// API returned an error response.
     Api,
+    /// Traffic leaves through Cloudflare WARP rather than the ISP.
+    Warp,
     /// Invalid configuration or arguments.
     Config,
     /// Measurement calculation errors.
@@ -53,7 +78,11 @@ impl ErrorKind {
             ErrorKind::Dns => exit_codes::NETWORK_ERROR,
             ErrorKind::Timeout => exit_codes::NETWORK_ERROR,
             ErrorKind::Tls => exit_codes::NETWORK_ERROR,
+            ErrorKind::UntrustedCertificate => exit_codes::NETWORK_ERROR,
+            ErrorKind::Intercepted => exit_codes::NETWORK_ERROR,
+            ErrorKind::RateLimited => exit_codes::API_ERROR,
             ErrorKind::Api => exit_codes::API_ERROR,
+            ErrorKind::Warp => exit_codes::NOT_DIRECT,
             ErrorKind::Config => exit_codes::CONFIG_ERROR,
             ErrorKind::Measurement => exit_codes::PARTIAL_FAILURE,
             ErrorKind::Unknown => exit_codes::UNKNOWN_ERROR,
@@ -67,12 +96,53 @@ impl ErrorKind {
             ErrorKind::Dns => "DNS resolution error",
             ErrorKind::Timeout => "Connection timeout",
             ErrorKind::Tls => "TLS/SSL error",
+            ErrorKind::UntrustedCertificate => "Untrusted certificate",
+            ErrorKind::Intercepted => "Connection intercepted",
+            ErrorKind::RateLimited => "Rate limited",
             ErrorKind::Api => "API error",
+            ErrorKind::Warp => "Not a direct connection",
             ErrorKind::Config => "Configuration error",
             ErrorKind::Measurement => "Measurement error",
             ErrorKind::Unknown => "Unknown error",
         }
     }
+
+    /// Get a suggestion for resolving an error of this kind, if there is
+    /// a general one.
+    pub fn suggestion(&self) -> Option<&'static str> {
+        match self {
+            ErrorKind::Network => {
+                Some("Check your internet connection and try again.")
+            }
+            ErrorKind::Dns => Some(
+                "Check your DNS settings or try using a different DNS server.",
+            ),
+            ErrorKind::Timeout => {
+                Some("The server may be slow or unreachable. Try again later.")
+            }
+            ErrorKind::Tls => Some(
+                "There may be a certificate issue. Check your system time.",
+            ),
+            ErrorKind::UntrustedCertificate => Some(UNTRUSTED_SUGGESTION),
+            ErrorKind::Intercepted => Some(
+                "A captive portal or proxy is intercepting traffic. Sign in \
+                 to the network or try a different one.",
+            ),
+            ErrorKind::RateLimited => Some(
+                "The speed test server is limiting requests from your \
+                 address. Wait a few minutes before testing again.",
+            ),
+            ErrorKind::Api => Some(
+                "The Cloudflare API may be experiencing issues. Try again \
+                 later.",
+            ),
+            ErrorKind::Warp => Some(
+                "Disconnect WARP (warp-cli disconnect) to measure your ISP, \
+                 or drop --require-direct.",
+            ),
+            _ => None,
+        }
+    }
 }
 
 /// A user-friendly error type for speed test operations.
@@ -141,6 +211,28 @@ impl SpeedTestError {
         )
     }
 
+    /// Create an error for a certificate signed by an untrusted root.
+    pub fn untrusted_certificate(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::UntrustedCertificate, message)
+            .with_suggestion(UNTRUSTED_SUGGESTION)
+    }
+
+    /// Create an interception error.
+    pub fn intercepted(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Intercepted, message).with_suggestion(
+            "A captive portal or proxy is intercepting traffic. Sign in to \
+             the network or try a different one.",
+        )
+    }
+
+    /// Create a rate limiting error.
+    pub fn rate_limited(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::RateLimited, message).with_suggestion(
+            "The speed test server is limiting requests from your address. \
+             Wait a few minutes before testing again.",
+        )
+    }
+
     /// Create an API error.
     pub fn api(message: impl Into<String>) -> Self {
         Self::new(ErrorKind::Api, message).with_suggestion(
@@ -148,6 +240,14 @@ impl SpeedTestError {
         )
     }
 
+    /// Create an error for traffic leaving through Cloudflare WARP.
+    pub fn warp(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Warp, message).with_suggestion(
+            "Disconnect WARP (warp-cli disconnect) to measure your ISP, or \
+             drop --require-direct.",
+        )
+    }
+
     /// Create a configuration error.
     pub fn config(message: impl Into<String>) -> Self {
         Self::new(ErrorKind::Config, message)
@@ -177,13 +277,44 @@ impl Error for SpeedTestError {
     }
 }
 
-/// Classify an error into an ErrorKind based on its message.
+/// What to do about a certificate signed by an untrusted root.
+const UNTRUSTED_SUGGESTION: &str =
+    "A TLS-inspecting proxy may be re-signing traffic with its own root \
+     certificate. Pass that root with --cacert, or try --tls-backend native \
+     to use the system's TLS library.";
+
+/// Classify an error into an ErrorKind based on its message and the
+/// messages of its sources.
 pub fn classify_error(error: &dyn Error) -> ErrorKind {
-    let error_str = error.to_string().to_lowercase();
+    let mut error_str = error.to_string().to_lowercase();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        error_str.push_str(": ");
+        error_str.push_str(&cause.to_string().to_lowercase());
+        source = cause.source();
+    }
+
+    if error_str.contains("cloudflare warp") {
+        return ErrorKind::Warp;
+    }
+
+    // Checked first: intercepted failures often look like TLS errors
+    if error_str.contains("intercepted")
+        || error_str.contains("captive portal")
+    {
+        return ErrorKind::Intercepted;
+    }
+
+    if http_status(&error_str) == Some(429)
+        || error_str.contains("too many requests")
+    {
+        return ErrorKind::RateLimited;
+    }
 
     if error_str.contains("dns")
         || error_str.contains("resolve")
         || error_str.contains("no such host")
+        || error_str.contains("no records found")
     {
         return ErrorKind::Dns;
     }
@@ -195,6 +326,18 @@ pub fn classify_error(error: &dyn Error) -> ErrorKind {
         return ErrorKind::Timeout;
     }
 
+    // rustls, OpenSSL, SChannel and Secure Transport in turn
+    if error_str.contains("unknownissuer")
+        || error_str.contains("unable to get local issuer certificate")
+        || error_str.contains("self-signed certificate in certificate chain")
+        || error_str.contains("self signed certificate in certificate chain")
+        || error_str.contains("not issued by a trusted authority")
+        || error_str.contains("issued by an authority that is not trusted")
+        || error_str.contains("certificate is not trusted")
+    {
+        return ErrorKind::UntrustedCertificate;
+    }
+
     if error_str.contains("tls")
         || error_str.contains("ssl")
         || error_str.contains("certificate")
@@ -217,6 +360,7 @@ pub fn classify_error(error: &dyn Error) -> ErrorKind {
         || error_str.contains("status: 5")
         || error_str.contains("api")
         || error_str.contains("server error")
+        || http_status(&error_str).is_some()
     {
         return ErrorKind::Api;
     }
@@ -224,6 +368,28 @@ pub fn classify_error(error: &dyn Error) -> ErrorKind {
     ErrorKind::Unknown
 }
 
+/// The HTTP status in an error message for a rejected measurement
+/// request (`HTTP 429 from speed test server`), if there is one.
+pub fn http_status(message: &str) -> Option<u16> {
+    let lower = message.to_lowercase();
+    let start = lower.find("http ")? + "http ".len();
+    let rest = &lower[start..];
+    let status = rest.get(..3)?;
+    if !rest[3..].starts_with(" from ") {
+        return None;
+    }
+    status.parse().ok().filter(|status| (100..600).contains(status))
+}
+
+/// The delay a rate limited server asked for (`retry after 30s`) in an
+/// error message, if it gave one.
+pub fn retry_after(message: &str) -> Option<std::time::Duration> {
+    let lower = message.to_lowercase();
+    let start = lower.find("retry after ")? + "retry after ".len();
+    let secs = lower[start..].split('s').next()?;
+    secs.parse().ok().map(std::time::Duration::from_secs)
+}
+
 /// Convert a generic error into a SpeedTestError with appropriate classification.
 pub fn to_speed_test_error(
     error: Box<dyn Error + Send + Sync>,
@@ -235,24 +401,9 @@ pub fn to_speed_test_error(
     let mut speed_error = SpeedTestError::new(kind, message);
     speed_error.source = Some(error);
 
-    // Add appropriate suggestions based on error kind
-    speed_error = match kind {
-        ErrorKind::Network => speed_error
-            .with_suggestion("Check your internet connection and try again."),
-        ErrorKind::Dns => speed_error.with_suggestion(
-            "Check your DNS settings or try using a different DNS server.",
-        ),
-        ErrorKind::Timeout => speed_error.with_suggestion(
-            "The server may be slow or unreachable. Try again later.",
-        ),
-        ErrorKind::Tls => speed_error.with_suggestion(
-            "There may be a certificate issue. Check your system time.",
-        ),
-        ErrorKind::Api => speed_error.with_suggestion(
-            "The Cloudflare API may be experiencing issues. Try again later.",
-        ),
-        _ => speed_error,
-    };
+    if let Some(suggestion) = kind.suggestion() {
+        speed_error = speed_error.with_suggestion(suggestion);
+    }
 
     speed_error
 }
@@ -280,6 +431,10 @@ mod tests {
         assert_eq!(ErrorKind::Network.exit_code(), exit_codes::NETWORK_ERROR);
         assert_eq!(ErrorKind::Dns.exit_code(), exit_codes::NETWORK_ERROR);
         assert_eq!(ErrorKind::Timeout.exit_code(), exit_codes::NETWORK_ERROR);
+        assert_eq!(
+            ErrorKind::Intercepted.exit_code(),
+            exit_codes::NETWORK_ERROR
+        );
         assert_eq!(ErrorKind::Api.exit_code(), exit_codes::API_ERROR);
         assert_eq!(ErrorKind::Config.exit_code(), exit_codes::CONFIG_ERROR);
     }
@@ -297,9 +452,13 @@ mod tests {
 
     #[test]
     fn test_classify_error_dns() {
-        let error = std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "DNS resolution failed: no such host",
+        let error =
+            std::io::Error::other("DNS resolution failed: no such host");
+        assert_eq!(classify_error(&error), ErrorKind::Dns);
+
+        let error = std::io::Error::other(
+            "proto error: no records found for Query { name: \
+             Name(\"speed.cloudflare.com.\"), query_type: AAAA }",
         );
         assert_eq!(classify_error(&error), ErrorKind::Dns);
     }
@@ -323,11 +482,79 @@ mod tests {
     }
 
     #[test]
-    fn test_classify_error_unknown() {
-        let error = std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "some random error",
+    fn test_classify_error_untrusted_certificate() {
+        let error =
+            std::io::Error::other("invalid peer certificate: UnknownIssuer");
+        assert_eq!(classify_error(&error), ErrorKind::UntrustedCertificate);
+
+        // Found in the source of a wrapping error
+        let error = SpeedTestError::api("error sending request").with_source(
+            std::io::Error::other(
+                "error:0A000086:SSL routines::certificate verify failed: \
+                 unable to get local issuer certificate",
+            ),
+        );
+        assert_eq!(classify_error(&error), ErrorKind::UntrustedCertificate);
+
+        let error = SpeedTestError::untrusted_certificate("handshake failed");
+        assert!(error.suggestion.unwrap().contains("--cacert"));
+        assert_eq!(
+            ErrorKind::UntrustedCertificate.exit_code(),
+            exit_codes::NETWORK_ERROR
+        );
+
+        // Other certificate problems stay TLS errors
+        let error = std::io::Error::other("invalid peer certificate: Expired");
+        assert_eq!(classify_error(&error), ErrorKind::Tls);
+    }
+
+    #[test]
+    fn test_classify_error_intercepted() {
+        let error = std::io::Error::other(
+            "Connection intercepted (HTTP probe was redirected): \
+             invalid peer certificate",
+        );
+        assert_eq!(classify_error(&error), ErrorKind::Intercepted);
+    }
+
+    #[test]
+    fn test_classify_error_warp() {
+        let error = SpeedTestError::warp(
+            "Traffic leaves through Cloudflare WARP (AS13335)",
         );
+        assert_eq!(classify_error(&error), ErrorKind::Warp);
+        assert_eq!(ErrorKind::Warp.exit_code(), exit_codes::NOT_DIRECT);
+    }
+
+    #[test]
+    fn test_classify_error_http_status() {
+        let error = std::io::Error::other("HTTP 429 from speed test server");
+        assert_eq!(classify_error(&error), ErrorKind::RateLimited);
+
+        let error = std::io::Error::other("HTTP 413 from speed test server");
+        assert_eq!(classify_error(&error), ErrorKind::Api);
+    }
+
+    #[test]
+    fn test_http_status_and_retry_after() {
+        assert_eq!(http_status("HTTP 503 from speed test server"), Some(503));
+        assert_eq!(
+            http_status("Upload failed: HTTP 429 from speed test server"),
+            Some(429)
+        );
+        assert_eq!(http_status("Malformed HTTP response from server"), None);
+        assert_eq!(http_status("connection reset"), None);
+
+        assert_eq!(
+            retry_after("HTTP 429 from speed test server (retry after 30s)"),
+            Some(std::time::Duration::from_secs(30))
+        );
+        assert_eq!(retry_after("HTTP 429 from speed test server"), None);
+    }
+
+    #[test]
+    fn test_classify_error_unknown() {
+        let error = std::io::Error::other("some random error");
         assert_eq!(classify_error(&error), ErrorKind::Unknown);
     }
 
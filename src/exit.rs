@@ -0,0 +1,93 @@
+//! Cleanup that can't hold up the exit.
+//!
+//! Restoring the terminal and writing the final report are blocking
+//! writes. When the terminal is gone, or a reader stops reading, they can
+//! block for good and the process never exits. Each runs on a thread of
+//! its own instead, and is abandoned after [`CLEANUP_TIMEOUT`]; the
+//! process exits with its abandoned threads still blocked.
+
+use std::io::{self, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// How long each cleanup step may take.
+pub const CLEANUP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Run `step` on its own thread and wait up to `timeout` for it.
+///
+/// # Returns
+/// What `step` returned, or a `TimedOut` error naming `what` when it took
+/// too long.
+pub fn bounded<T, F>(what: &str, timeout: Duration, step: F) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+{
+    let (done, result) = mpsc::sync_channel(1);
+    thread::Builder::new().name(format!("cleanup: {}", what)).spawn(
+        move || {
+            // The receiver is gone once the wait timed out
+            let _ = done.send(step());
+        },
+    )?;
+
+    result.recv_timeout(timeout).unwrap_or_else(|_| {
+        log::debug!("Gave up on {} after {:?}", what, timeout);
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("timed out {}", what),
+        ))
+    })
+}
+
+/// Write `bytes` to stdout and flush, giving up after
+/// [`CLEANUP_TIMEOUT`].
+pub fn write_stdout(bytes: Vec<u8>) -> io::Result<()> {
+    bounded("writing to stdout", CLEANUP_TIMEOUT, move || {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&bytes)?;
+        stdout.flush()
+    })
+}
+
+/// Write `bytes` to stderr, giving up after [`CLEANUP_TIMEOUT`].
+pub fn write_stderr(bytes: Vec<u8>) -> io::Result<()> {
+    bounded("writing to stderr", CLEANUP_TIMEOUT, move || {
+        let mut stderr = io::stderr().lock();
+        stderr.write_all(&bytes)?;
+        stderr.flush()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_bounded_returns_result() {
+        let value = bounded("adding", CLEANUP_TIMEOUT, || Ok(40 + 2)).unwrap();
+        assert_eq!(value, 42);
+
+        let error = bounded::<(), _>("failing", CLEANUP_TIMEOUT, || {
+            Err(io::Error::other("broken pipe"))
+        })
+        .unwrap_err();
+        assert_eq!(error.to_string(), "broken pipe");
+    }
+
+    #[test]
+    fn test_bounded_gives_up_on_hung_step() {
+        let start = Instant::now();
+        let error = bounded("hanging", Duration::from_millis(50), || {
+            thread::sleep(Duration::from_secs(60));
+            Ok(())
+        })
+        .unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(error.to_string(), "timed out hanging");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
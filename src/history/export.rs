@@ -0,0 +1,136 @@
+//! Exporting the history for spreadsheets and other tools.
+
+use crate::history::HistoryEntry;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// Columns of a CSV export, in order.
+const CSV_HEADER: &str = "timestamp,download_mbps,upload_mbps,latency_ms,\
+                          jitter_ms,loaded_down_ms,loaded_up_ms,\
+                          packet_loss_percent,load_streams,order";
+
+/// Format of `history export`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One row per run with a header row; missing values are empty
+    #[default]
+    Csv,
+    /// A JSON array of entries, as stored
+    Json,
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        })
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!("unknown format '{}', expected csv or json", s)),
+        }
+    }
+}
+
+/// Write `entries` to `out` in `format`.
+pub fn export<W: Write>(
+    entries: &[HistoryEntry],
+    format: ExportFormat,
+    out: &mut W,
+) -> io::Result<()> {
+    match format {
+        ExportFormat::Csv => write_csv(entries, out),
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, entries)?;
+            writeln!(out)
+        }
+    }
+}
+
+fn write_csv<W: Write>(
+    entries: &[HistoryEntry],
+    out: &mut W,
+) -> io::Result<()> {
+    let optional = |value: Option<f64>| {
+        value.map(|value| value.to_string()).unwrap_or_default()
+    };
+
+    writeln!(out, "{}", CSV_HEADER)?;
+    for entry in entries {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{}",
+            entry.timestamp.to_rfc3339(),
+            entry.download_mbps,
+            entry.upload_mbps,
+            entry.latency_ms,
+            optional(entry.jitter_ms),
+            optional(entry.loaded_down_ms),
+            optional(entry.loaded_up_ms),
+            optional(entry.packet_loss_percent),
+            entry.load_streams.map(|n| n.to_string()).unwrap_or_default(),
+            entry.order.map(|order| order.to_string()).unwrap_or_default(),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn entry() -> HistoryEntry {
+        HistoryEntry {
+            timestamp: Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap(),
+            download_mbps: 95.5,
+            upload_mbps: 20.0,
+            latency_ms: 12.25,
+            jitter_ms: Some(1.5),
+            loaded_down_ms: None,
+            loaded_up_ms: None,
+            packet_loss_percent: None,
+            load_streams: None,
+            order: None,
+        }
+    }
+
+    #[test]
+    fn test_csv_export() {
+        let mut out = Vec::new();
+        export(&[entry()], ExportFormat::Csv, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(
+            lines[1],
+            "2024-05-01T12:00:00+00:00,95.5,20,12.25,1.5,,,,,"
+        );
+        assert_eq!(lines[0].split(',').count(), lines[1].split(',').count());
+    }
+
+    #[test]
+    fn test_json_export_round_trips() {
+        let mut out = Vec::new();
+        export(&[entry()], ExportFormat::Json, &mut out).unwrap();
+        let parsed: Vec<HistoryEntry> = serde_json::from_slice(&out).unwrap();
+        assert_eq!(parsed, vec![entry()]);
+    }
+
+    #[test]
+    fn test_format_parsing() {
+        assert_eq!("CSV".parse(), Ok(ExportFormat::Csv));
+        assert_eq!("json".parse(), Ok(ExportFormat::Json));
+        assert!("xml".parse::<ExportFormat>().is_err());
+    }
+}
@@ -0,0 +1,12 @@
+//! Run history and baseline comparison.
+//!
+//! Completed runs are appended to a JSON Lines file so later runs can be
+//! compared against the connection's recent behaviour.
+
+pub mod export;
+pub mod regression;
+pub mod store;
+
+pub use export::{export, ExportFormat};
+pub use regression::{detect_regressions, RegressionResults};
+pub use store::{HistoryEntry, HistoryStore, Retention};
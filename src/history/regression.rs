@@ -0,0 +1,296 @@
+//! Regression detection against the historical baseline.
+//!
+//! Each metric in the current run is compared with the distribution of
+//! the same metric over the trailing window. A value is anomalous when it
+//! falls outside the Tukey fence on the "worse" side: below
+//! `Q1 - 1.5 * IQR` for metrics where higher is better, or above
+//! `Q3 + 1.5 * IQR` where lower is better. Only regressions are flagged;
+//! an unusually fast run is not an anomaly.
+//!
+//! Only past runs measured under the same conditions make up the
+//! baseline: a run with `--load-streams` or `--order concurrent` is
+//! slower by design, and is only judged against others with the same
+//! load and order.
+
+use super::HistoryEntry;
+use crate::stats::{median_f64, percentile_f64};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Days of history that make up the baseline.
+pub const BASELINE_WINDOW_DAYS: i64 = 30;
+
+/// Fewest historical samples needed before a metric is judged.
+pub const MIN_BASELINE_SAMPLES: usize = 5;
+
+/// Multiplier applied to the IQR to place the fences.
+const IQR_FENCE_MULTIPLIER: f64 = 1.5;
+
+/// Smallest fence distance as a fraction of the median.
+///
+/// A very stable connection can have an IQR near zero, which would flag
+/// any tiny dip. This keeps such runs from being reported as anomalous.
+const MIN_FENCE_FRACTION: f64 = 0.1;
+
+/// Which direction of change counts as a regression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Better {
+    Higher,
+    Lower,
+}
+
+/// Baseline comparison for one metric.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MetricBaseline {
+    /// Metric name, matching the field in the history entry
+    pub metric: Cow<'static, str>,
+    /// Value measured in the current run
+    pub value: f64,
+    /// Median over the baseline window
+    pub median: f64,
+    /// Interquartile range over the baseline window
+    pub iqr: f64,
+    /// Number of historical samples for this metric
+    pub samples: usize,
+    /// Whether the current value is a regression against the baseline
+    pub anomalous: bool,
+}
+
+/// Result of comparing a run with its historical baseline.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RegressionResults {
+    /// Length of the baseline window in days
+    pub window_days: i64,
+    /// Number of historical runs in the window
+    pub baseline_runs: usize,
+    /// Per-metric comparisons (metrics with too little history are omitted)
+    pub metrics: Vec<MetricBaseline>,
+}
+
+impl RegressionResults {
+    /// Whether any metric regressed.
+    pub fn has_anomalies(&self) -> bool {
+        self.metrics.iter().any(|m| m.anomalous)
+    }
+
+    /// Names of the metrics that regressed.
+    pub fn anomalous_metrics(&self) -> Vec<&str> {
+        self.metrics
+            .iter()
+            .filter(|m| m.anomalous)
+            .map(|m| m.metric.as_ref())
+            .collect()
+    }
+}
+
+/// Compare `current` against the `history` baseline.
+///
+/// `history` should already be limited to the baseline window and must
+/// not include `current` itself. Runs not comparable with `current` are
+/// left out of the baseline.
+pub fn detect_regressions(
+    current: &HistoryEntry,
+    history: &[HistoryEntry],
+) -> RegressionResults {
+    type Getter = fn(&HistoryEntry) -> Option<f64>;
+    let metrics: [(&'static str, Better, Getter); 7] = [
+        ("download_mbps", Better::Higher, |e| Some(e.download_mbps)),
+        ("upload_mbps", Better::Higher, |e| Some(e.upload_mbps)),
+        ("latency_ms", Better::Lower, |e| Some(e.latency_ms)),
+        ("jitter_ms", Better::Lower, |e| e.jitter_ms),
+        ("loaded_down_ms", Better::Lower, |e| e.loaded_down_ms),
+        ("loaded_up_ms", Better::Lower, |e| e.loaded_up_ms),
+        ("packet_loss_percent", Better::Lower, |e| e.packet_loss_percent),
+    ];
+
+    let history: Vec<&HistoryEntry> =
+        history.iter().filter(|past| past.comparable_with(current)).collect();
+    let metrics = metrics
+        .iter()
+        .filter_map(|(name, better, get)| {
+            let value = get(current)?;
+            let samples: Vec<f64> = history
+                .iter()
+                .copied()
+                .filter_map(get)
+                .filter(|v| v.is_finite())
+                .collect();
+            compare(name, *better, value, samples)
+        })
+        .collect();
+
+    RegressionResults {
+        window_days: BASELINE_WINDOW_DAYS,
+        baseline_runs: history.len(),
+        metrics,
+    }
+}
+
+/// Compare one value with its historical samples.
+fn compare(
+    metric: &'static str,
+    better: Better,
+    value: f64,
+    mut samples: Vec<f64>,
+) -> Option<MetricBaseline> {
+    if samples.len() < MIN_BASELINE_SAMPLES {
+        return None;
+    }
+
+    let q1 = percentile_f64(&mut samples, 0.25)?;
+    let q3 = percentile_f64(&mut samples, 0.75)?;
+    let median = median_f64(&mut samples)?;
+    let iqr = q3 - q1;
+
+    let fence =
+        (IQR_FENCE_MULTIPLIER * iqr).max(MIN_FENCE_FRACTION * median.abs());
+    let anomalous = match better {
+        Better::Higher => value < q1 - fence,
+        Better::Lower => value > q3 + fence,
+    };
+
+    Some(MetricBaseline {
+        metric: Cow::Borrowed(metric),
+        value,
+        median,
+        iqr,
+        samples: samples.len(),
+        anomalous,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cloudflare::tests::engine::TestOrder;
+    use chrono::Utc;
+
+    fn entry(download: f64, upload: f64, latency: f64) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: Utc::now(),
+            download_mbps: download,
+            upload_mbps: upload,
+            latency_ms: latency,
+            jitter_ms: None,
+            loaded_down_ms: None,
+            loaded_up_ms: None,
+            packet_loss_percent: None,
+            load_streams: None,
+            order: None,
+        }
+    }
+
+    fn baseline() -> Vec<HistoryEntry> {
+        vec![
+            entry(95.0, 20.0, 12.0),
+            entry(100.0, 21.0, 11.0),
+            entry(98.0, 19.5, 13.0),
+            entry(102.0, 20.5, 12.5),
+            entry(97.0, 20.0, 11.5),
+            entry(99.0, 20.2, 12.2),
+        ]
+    }
+
+    fn metric<'a>(
+        results: &'a RegressionResults,
+        name: &str,
+    ) -> &'a MetricBaseline {
+        results.metrics.iter().find(|m| m.metric == name).unwrap()
+    }
+
+    #[test]
+    fn test_typical_run_is_not_anomalous() {
+        let results =
+            detect_regressions(&entry(98.5, 20.1, 12.1), &baseline());
+        assert!(!results.has_anomalies());
+        assert_eq!(results.baseline_runs, 6);
+        assert_eq!(results.window_days, BASELINE_WINDOW_DAYS);
+    }
+
+    #[test]
+    fn test_slow_download_is_anomalous() {
+        let results =
+            detect_regressions(&entry(40.0, 20.1, 12.1), &baseline());
+        assert_eq!(results.anomalous_metrics(), vec!["download_mbps"]);
+        let download = metric(&results, "download_mbps");
+        assert!((download.median - 98.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_high_latency_is_anomalous() {
+        let results =
+            detect_regressions(&entry(98.0, 20.0, 60.0), &baseline());
+        assert_eq!(results.anomalous_metrics(), vec!["latency_ms"]);
+    }
+
+    #[test]
+    fn test_improvement_is_not_anomalous() {
+        let results =
+            detect_regressions(&entry(500.0, 80.0, 2.0), &baseline());
+        assert!(!results.has_anomalies());
+    }
+
+    #[test]
+    fn test_insufficient_history_skips_metrics() {
+        let history = &baseline()[..MIN_BASELINE_SAMPLES - 1];
+        let results = detect_regressions(&entry(1.0, 1.0, 500.0), history);
+        assert!(results.metrics.is_empty());
+        assert!(!results.has_anomalies());
+    }
+
+    #[test]
+    fn test_metrics_missing_from_current_run_are_skipped() {
+        let results =
+            detect_regressions(&entry(98.0, 20.0, 12.0), &baseline());
+        assert!(results.metrics.iter().all(|m| m.metric != "jitter_ms"));
+    }
+
+    #[test]
+    fn test_stable_history_tolerates_small_dips() {
+        let history = vec![entry(100.0, 20.0, 10.0); 10];
+        let results = detect_regressions(&entry(95.0, 19.0, 10.5), &history);
+        assert!(!results.has_anomalies());
+
+        let results = detect_regressions(&entry(80.0, 20.0, 10.0), &history);
+        assert_eq!(results.anomalous_metrics(), vec!["download_mbps"]);
+    }
+
+    #[test]
+    fn test_loaded_runs_have_their_own_baseline() {
+        let loaded =
+            HistoryEntry { load_streams: Some(4), ..entry(60.0, 12.0, 10.0) };
+        let results = detect_regressions(&loaded, &baseline());
+        assert_eq!(results.baseline_runs, 0);
+        assert!(results.metrics.is_empty());
+
+        let mut history = baseline();
+        history.extend(vec![loaded.clone(); 5]);
+        let results = detect_regressions(&loaded, &history);
+        assert_eq!(results.baseline_runs, 5);
+        assert!(!results.has_anomalies());
+
+        // Nor do loaded runs drag down the unloaded baseline
+        let results = detect_regressions(&entry(98.5, 20.1, 12.1), &history);
+        assert_eq!(results.baseline_runs, baseline().len());
+    }
+
+    #[test]
+    fn test_concurrent_runs_have_their_own_baseline() {
+        let concurrent = HistoryEntry {
+            order: Some(TestOrder::Concurrent),
+            ..entry(50.0, 10.0, 12.0)
+        };
+        let results = detect_regressions(&concurrent, &baseline());
+        assert_eq!(results.baseline_runs, 0);
+        assert!(!results.has_anomalies());
+
+        let sequential = HistoryEntry {
+            order: Some(TestOrder::Sequential),
+            ..entry(98.5, 20.1, 12.1)
+        };
+        assert_eq!(
+            detect_regressions(&sequential, &baseline()).baseline_runs,
+            0
+        );
+    }
+}
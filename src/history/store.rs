@@ -0,0 +1,410 @@
+//! JSON Lines history store.
+//!
+//! Each line in the history file is one [`HistoryEntry`]. Appending is a
+//! single write, and lines that fail to parse are skipped on load so a
+//! truncated write never makes the whole history unreadable.
+//!
+//! A [`Retention`] policy caps the history by entry count, age and file
+//! size. It is enforced after every append by rewriting the file without
+//! the oldest entries, through a temporary file so an interrupted prune
+//! leaves the previous history in place.
+
+use crate::cloudflare::tests::engine::TestOrder;
+use crate::results::SpeedTestResults;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// File name of the history store inside the data directory.
+const HISTORY_FILE: &str = "history.jsonl";
+
+/// Summary of a single completed run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// When the run completed
+    pub timestamp: DateTime<Utc>,
+    /// Final download speed in Mbps
+    pub download_mbps: f64,
+    /// Final upload speed in Mbps
+    pub upload_mbps: f64,
+    /// Idle latency in milliseconds
+    pub latency_ms: f64,
+    /// Idle jitter in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jitter_ms: Option<f64>,
+    /// Latency under download load in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loaded_down_ms: Option<f64>,
+    /// Latency under upload load in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loaded_up_ms: Option<f64>,
+    /// Packet loss percentage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub packet_loss_percent: Option<f64>,
+    /// Background load streams the run measured under, which lower the
+    /// speeds and raise loaded latency
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub load_streams: Option<usize>,
+    /// Order the bandwidth blocks ran in, when not the default
+    /// interleaved order; concurrent runs share the link between
+    /// directions and come out slower
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<TestOrder>,
+}
+
+impl HistoryEntry {
+    /// Summarize a completed run.
+    ///
+    /// Returns `None` when a direction was skipped, since a partial run
+    /// can't be compared with the baseline.
+    pub fn from_results(results: &SpeedTestResults) -> Option<Self> {
+        Some(Self {
+            timestamp: results.timestamp,
+            download_mbps: results.download.as_ref()?.speed_mbps,
+            upload_mbps: results.upload.as_ref()?.speed_mbps,
+            latency_ms: results.latency.idle_ms,
+            jitter_ms: results.latency.idle_jitter_ms,
+            loaded_down_ms: results.latency.loaded_down_ms,
+            loaded_up_ms: results.latency.loaded_up_ms,
+            packet_loss_percent: results
+                .packet_loss
+                .as_ref()
+                .map(|pl| pl.percent),
+            load_streams: results.latency.load_streams,
+            order: results.meta.as_ref().and_then(|meta| meta.order),
+        })
+    }
+
+    /// Whether this run was measured under the same conditions as
+    /// `other`, so their speeds and latencies can be compared.
+    pub fn comparable_with(&self, other: &HistoryEntry) -> bool {
+        self.load_streams == other.load_streams && self.order == other.order
+    }
+}
+
+/// Limits on how much history is kept; unset limits don't apply.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retention {
+    /// Most entries to keep
+    pub max_entries: Option<usize>,
+    /// Oldest entry to keep, by age
+    pub max_age: Option<TimeDelta>,
+    /// Largest the history file may grow, in bytes
+    pub max_bytes: Option<u64>,
+}
+
+impl Retention {
+    /// Whether no limit is set.
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The newest of `entries` (oldest first) that fit the limits at
+    /// `now`, with how many were dropped.
+    fn apply(
+        &self,
+        mut entries: Vec<HistoryEntry>,
+        now: DateTime<Utc>,
+    ) -> (Vec<HistoryEntry>, usize) {
+        let total = entries.len();
+        if let Some(max_age) = self.max_age {
+            entries.retain(|entry| entry.timestamp >= now - max_age);
+        }
+        if let Some(max_entries) = self.max_entries {
+            let excess = entries.len().saturating_sub(max_entries);
+            entries.drain(..excess);
+        }
+        if let Some(max_bytes) = self.max_bytes {
+            let mut size: u64 = 0;
+            let keep = entries
+                .iter()
+                .rev()
+                .take_while(|entry| {
+                    size += line_len(entry);
+                    size <= max_bytes
+                })
+                .count();
+            let excess = entries.len() - keep;
+            entries.drain(..excess);
+        }
+        let dropped = total - entries.len();
+        (entries, dropped)
+    }
+}
+
+/// Length of `entry`'s line in the history file, newline included.
+fn line_len(entry: &HistoryEntry) -> u64 {
+    serde_json::to_string(entry).map_or(0, |line| line.len() as u64 + 1)
+}
+
+/// Append-only store of past runs.
+#[derive(Debug, Clone)]
+pub struct HistoryStore {
+    path: PathBuf,
+    retention: Retention,
+}
+
+impl HistoryStore {
+    /// Create a store backed by the file at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), retention: Retention::default() }
+    }
+
+    /// Prune the history to `retention` whenever an entry is appended.
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Create a store at the platform's default location.
+    ///
+    /// Returns `None` if no data directory can be determined.
+    pub fn open_default() -> Option<Self> {
+        default_data_dir().map(|dir| Self::new(dir.join(HISTORY_FILE)))
+    }
+
+    /// Path of the backing file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append an entry, creating the file and its directory if needed.
+    pub fn append(&self, entry: &HistoryEntry) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut file =
+            OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+
+        if !self.retention.is_unlimited() {
+            self.prune(self.retention)?;
+        }
+        Ok(())
+    }
+
+    /// Drop the oldest entries beyond `retention`, returning how many
+    /// were dropped.
+    ///
+    /// The file is only rewritten when something is dropped; malformed
+    /// lines don't survive a rewrite.
+    pub fn prune(&self, retention: Retention) -> io::Result<usize> {
+        let (entries, dropped) = retention.apply(self.load()?, Utc::now());
+        if dropped == 0 {
+            return Ok(0);
+        }
+
+        let mut contents = String::new();
+        for entry in &entries {
+            contents.push_str(&serde_json::to_string(entry)?);
+            contents.push('\n');
+        }
+        let temp = self.path.with_extension("jsonl.tmp");
+        fs::write(&temp, contents)?;
+        fs::rename(&temp, &self.path)?;
+        Ok(dropped)
+    }
+
+    /// Load every entry, oldest first.
+    ///
+    /// A missing file is an empty history.
+    pub fn load(&self) -> io::Result<Vec<HistoryEntry>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Vec::new())
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut entries = Vec::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => log::warn!(
+                    "Skipping malformed history line {} in {}: {}",
+                    index + 1,
+                    self.path.display(),
+                    e
+                ),
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Load entries recorded at or after `cutoff`.
+    pub fn load_since(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> io::Result<Vec<HistoryEntry>> {
+        Ok(self
+            .load()?
+            .into_iter()
+            .filter(|entry| entry.timestamp >= cutoff)
+            .collect())
+    }
+}
+
+/// Platform data directory for cloud-speed.
+///
+/// Follows the XDG base directory spec on Unix and uses `%APPDATA%` on
+/// Windows.
+fn default_data_dir() -> Option<PathBuf> {
+    let env_dir = |key: &str| {
+        std::env::var_os(key).filter(|v| !v.is_empty()).map(PathBuf::from)
+    };
+
+    let base = if cfg!(windows) {
+        env_dir("APPDATA")
+    } else {
+        env_dir("XDG_DATA_HOME")
+            .or_else(|| env_dir("HOME").map(|h| h.join(".local/share")))
+    };
+
+    base.map(|dir| dir.join("cloud-speed"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn temp_store(name: &str) -> HistoryStore {
+        let dir = std::env::temp_dir().join(format!(
+            "cloud-speed-history-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        HistoryStore::new(dir.join(HISTORY_FILE))
+    }
+
+    fn entry(timestamp: DateTime<Utc>, download_mbps: f64) -> HistoryEntry {
+        HistoryEntry {
+            timestamp,
+            download_mbps,
+            upload_mbps: 10.0,
+            latency_ms: 20.0,
+            jitter_ms: None,
+            loaded_down_ms: None,
+            loaded_up_ms: None,
+            packet_loss_percent: None,
+            load_streams: None,
+            order: None,
+        }
+    }
+
+    #[test]
+    fn test_missing_file_is_empty_history() {
+        let store = temp_store("missing");
+        assert!(store.load().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_append_and_load_round_trip() {
+        let store = temp_store("round-trip");
+        let first = entry(Utc::now() - Duration::days(1), 90.0);
+        let second = entry(Utc::now(), 95.0);
+
+        store.append(&first).unwrap();
+        store.append(&second).unwrap();
+
+        assert_eq!(store.load().unwrap(), vec![first, second]);
+        let _ = fs::remove_dir_all(store.path().parent().unwrap());
+    }
+
+    #[test]
+    fn test_malformed_lines_are_skipped() {
+        let store = temp_store("malformed");
+        let good = entry(Utc::now(), 50.0);
+        store.append(&good).unwrap();
+
+        let mut file =
+            OpenOptions::new().append(true).open(store.path()).unwrap();
+        file.write_all(b"{\"timestamp\": \n\n").unwrap();
+
+        assert_eq!(store.load().unwrap(), vec![good]);
+        let _ = fs::remove_dir_all(store.path().parent().unwrap());
+    }
+
+    #[test]
+    fn test_retention_keeps_newest_entries() {
+        let now = Utc::now();
+        let entries: Vec<HistoryEntry> = (0..10)
+            .map(|i| entry(now - Duration::days(10 - i), i as f64))
+            .collect();
+
+        let by_count =
+            Retention { max_entries: Some(3), ..Retention::default() };
+        let (kept, dropped) = by_count.apply(entries.clone(), now);
+        assert_eq!(dropped, 7);
+        assert_eq!(kept.first().unwrap().download_mbps, 7.0);
+
+        let by_age = Retention {
+            max_age: Some(Duration::days(5)),
+            ..Retention::default()
+        };
+        let (kept, _) = by_age.apply(entries.clone(), now);
+        assert_eq!(kept.len(), 5);
+
+        let line = line_len(&entries[0]);
+        let by_size = Retention {
+            max_bytes: Some(line * 2 + line / 2),
+            ..Retention::default()
+        };
+        let (kept, dropped) = by_size.apply(entries.clone(), now);
+        assert_eq!((kept.len(), dropped), (2, 8));
+        assert_eq!(kept.last().unwrap().download_mbps, 9.0);
+
+        assert_eq!(Retention::default().apply(entries, now).1, 0);
+    }
+
+    #[test]
+    fn test_append_enforces_retention() {
+        let store = temp_store("retention").with_retention(Retention {
+            max_entries: Some(2),
+            ..Retention::default()
+        });
+        for i in 0..4 {
+            store.append(&entry(Utc::now(), i as f64)).unwrap();
+        }
+
+        let speeds: Vec<f64> =
+            store.load().unwrap().iter().map(|e| e.download_mbps).collect();
+        assert_eq!(speeds, vec![2.0, 3.0]);
+        assert_eq!(
+            store
+                .prune(Retention {
+                    max_entries: Some(5),
+                    ..Retention::default()
+                })
+                .unwrap(),
+            0
+        );
+        let _ = fs::remove_dir_all(store.path().parent().unwrap());
+    }
+
+    #[test]
+    fn test_load_since_filters_old_entries() {
+        let store = temp_store("since");
+        let now = Utc::now();
+        store.append(&entry(now - Duration::days(45), 10.0)).unwrap();
+        store.append(&entry(now - Duration::days(5), 20.0)).unwrap();
+
+        let recent = store.load_since(now - Duration::days(30)).unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].download_mbps, 20.0);
+        let _ = fs::remove_dir_all(store.path().parent().unwrap());
+    }
+}
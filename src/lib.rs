@@ -0,0 +1,39 @@
+//! cloud-speed measures a network's speed and consistency against
+//! Cloudflare's speed test servers.
+//!
+//! The `cloud-speed` binary is a thin wrapper over [`main`]; the modules
+//! and items made public here are the ones the benchmarks measure.
+
+mod ab;
+mod cli;
+mod clock;
+mod cloudflare;
+pub mod config;
+mod diff;
+mod doctor;
+pub mod errors;
+mod exit;
+pub mod history;
+mod locations;
+pub mod measurements;
+mod netwatch;
+mod ping;
+mod profiling;
+pub mod regional;
+mod repeat;
+pub mod reporters;
+pub mod results;
+pub mod retry;
+mod scoring;
+mod serve;
+#[cfg(all(windows, feature = "service"))]
+mod service;
+mod share;
+pub mod signing;
+mod simulate;
+pub mod stats;
+pub mod tui;
+mod vpn;
+
+pub use cli::main;
+pub use cloudflare::tests::sink::DiscardSink;
@@ -0,0 +1,306 @@
+//! The Cloudflare location list behind `cloud-speed locations`.
+//!
+//! `speed.cloudflare.com` is anycast, so only the colo the network routes
+//! to can be probed directly. With `--nearest`, that colo's round trip is
+//! measured with a few TCP handshakes, and the others are estimated from
+//! it: the measured time beyond light-in-fiber propagation is taken as
+//! fixed overhead and added to each colo's own propagation time.
+
+use crate::cloudflare::client::Client;
+use crate::cloudflare::requests::locations::{Location, Locations};
+use crate::cloudflare::requests::meta::MetaRequest;
+use crate::cloudflare::tests::connection::connect;
+use crate::cloudflare::tests::BASE_URL;
+use crate::reporters::CommandReport;
+use serde::Serialize;
+use std::error::Error;
+use std::io::{self, Write};
+use url::Url;
+
+/// Mean Earth radius in km.
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Distance light travels in optical fiber per millisecond, in km.
+const FIBER_KM_PER_MS: f64 = 200.0;
+
+/// TCP handshakes timed to the serving colo; the fastest is used.
+const PROBES: usize = 3;
+
+/// Colos shown with `--nearest` unless a limit is given.
+pub const DEFAULT_NEAREST: usize = 10;
+
+/// What to list.
+#[derive(Debug, Clone, Default)]
+pub struct LocationsOptions {
+    /// Only colos in this ISO 3166-1 alpha-2 country
+    pub country: Option<String>,
+    /// Estimate round trips and sort by them
+    pub nearest: bool,
+    /// Show at most this many colos
+    pub limit: Option<usize>,
+}
+
+/// One Cloudflare colo.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Colo {
+    pub iata: String,
+    pub city: String,
+    pub country: String,
+    pub region: String,
+    pub lat: f64,
+    pub lon: f64,
+    /// Great-circle distance from the client in km (with `--nearest`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance_km: Option<f64>,
+    /// Round trip in ms: measured for the serving colo, estimated for
+    /// the rest (with `--nearest`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rtt_ms: Option<f64>,
+    /// Whether this colo served the request
+    pub serving: bool,
+}
+
+impl From<Location> for Colo {
+    fn from(location: Location) -> Self {
+        Self {
+            iata: location.iata,
+            city: location.city,
+            country: location.cca2,
+            region: location.region,
+            lat: location.lat,
+            lon: location.lon,
+            distance_km: None,
+            rtt_ms: None,
+            serving: false,
+        }
+    }
+}
+
+/// Fetch the colo list, filtered and (with `--nearest`) ranked by
+/// estimated round trip.
+pub async fn list(
+    client: &Client,
+    options: &LocationsOptions,
+) -> Result<Vec<Colo>, Box<dyn Error>> {
+    let meta = client
+        .send(MetaRequest {})
+        .await
+        .map_err(|e| format!("Failed to fetch connection metadata: {}", e))?;
+    let mut colos: Vec<Colo> = client
+        .send(Locations {})
+        .await
+        .map_err(|e| format!("Failed to fetch server locations: {}", e))?
+        .into_vec()
+        .into_iter()
+        .map(Colo::from)
+        .collect();
+
+    for colo in &mut colos {
+        colo.serving = colo.iata == meta.colo.iata;
+    }
+
+    if let Some(ref country) = options.country {
+        colos.retain(|colo| colo.country.eq_ignore_ascii_case(country));
+    }
+
+    if options.nearest {
+        let client_position =
+            match (meta.latitude.parse(), meta.longitude.parse()) {
+                (Ok(lat), Ok(lon)) => (lat, lon),
+                _ => {
+                    return Err(
+                        "Cloudflare did not report your location".into()
+                    )
+                }
+            };
+        let measured_ms = probe_rtt().await?;
+        let serving = (meta.colo.lat, meta.colo.lon);
+        estimate_rtts(&mut colos, client_position, serving, measured_ms);
+    }
+
+    let limit = options
+        .limit
+        .or(options.nearest.then_some(DEFAULT_NEAREST))
+        .unwrap_or(usize::MAX);
+    colos.truncate(limit);
+
+    Ok(colos)
+}
+
+/// Fill in distances and round trips for `colos`, and sort them by round
+/// trip.
+///
+/// `measured_ms` is the round trip to the serving colo at `serving`.
+fn estimate_rtts(
+    colos: &mut [Colo],
+    client: (f64, f64),
+    serving: (f64, f64),
+    measured_ms: f64,
+) {
+    let overhead_ms = (measured_ms
+        - propagation_rtt_ms(distance_km(client, serving)))
+    .max(0.0);
+
+    for colo in colos.iter_mut() {
+        let distance = distance_km(client, (colo.lat, colo.lon));
+        colo.distance_km = Some(distance);
+        colo.rtt_ms = Some(if colo.serving {
+            measured_ms
+        } else {
+            overhead_ms + propagation_rtt_ms(distance)
+        });
+    }
+
+    colos.sort_by(|a, b| {
+        a.rtt_ms.unwrap_or(f64::MAX).total_cmp(&b.rtt_ms.unwrap_or(f64::MAX))
+    });
+}
+
+/// Fastest of a few TCP handshakes to the test server, in ms.
+async fn probe_rtt() -> Result<f64, Box<dyn Error>> {
+    let url = Url::parse(BASE_URL)?;
+    let mut fastest: Option<f64> = None;
+    for _ in 0..PROBES {
+        let (_, _, _, duration) = connect(&url).await?;
+        let ms = duration.as_secs_f64() * 1000.0;
+        fastest = Some(fastest.map_or(ms, |f| f.min(ms)));
+    }
+
+    fastest.ok_or_else(|| "no round trip measured".into())
+}
+
+/// Great-circle distance between two (lat, lon) points in km.
+fn distance_km(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+    let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+/// Round trip over `km` of fiber, ignoring routing detours and queuing.
+fn propagation_rtt_ms(km: f64) -> f64 {
+    2.0 * km / FIBER_KM_PER_MS
+}
+
+/// Print the colos as a table; the serving colo is marked with `*`.
+pub fn print_table<W: Write + ?Sized>(
+    colos: &[Colo],
+    out: &mut W,
+) -> io::Result<()> {
+    if colos.is_empty() {
+        return writeln!(out, "No matching locations");
+    }
+
+    let ranked = colos.iter().any(|colo| colo.rtt_ms.is_some());
+    write!(
+        out,
+        "  {:<5} {:<24} {:<8} {:<16}",
+        "IATA", "City", "Country", "Region"
+    )?;
+    if ranked {
+        write!(out, " {:>9} {:>9}", "Distance", "RTT")?;
+    }
+    writeln!(out)?;
+
+    for colo in colos {
+        write!(
+            out,
+            "{} {:<5} {:<24} {:<8} {:<16}",
+            if colo.serving { "*" } else { " " },
+            colo.iata,
+            colo.city,
+            colo.country,
+            colo.region
+        )?;
+        if let (Some(distance), Some(rtt)) = (colo.distance_km, colo.rtt_ms) {
+            write!(
+                out,
+                " {:>6.0} km {:>6.1} ms{}",
+                distance,
+                rtt,
+                if colo.serving { "" } else { " (est.)" }
+            )?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+impl CommandReport for Vec<Colo> {
+    fn write_human(&self, out: &mut dyn Write) -> io::Result<()> {
+        print_table(self, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colo(iata: &str, lat: f64, lon: f64, serving: bool) -> Colo {
+        Colo {
+            iata: iata.to_string(),
+            city: iata.to_string(),
+            country: "US".to_string(),
+            region: "North America".to_string(),
+            lat,
+            lon,
+            distance_km: None,
+            rtt_ms: None,
+            serving,
+        }
+    }
+
+    #[test]
+    fn test_distance_km() {
+        // New York (JFK) to London (LHR) is about 5,540 km
+        let distance = distance_km((40.64, -73.78), (51.47, -0.45));
+        assert!((distance - 5540.0).abs() < 30.0, "{}", distance);
+        assert_eq!(distance_km((10.0, 20.0), (10.0, 20.0)), 0.0);
+    }
+
+    #[test]
+    fn test_estimate_rtts_adds_measured_overhead() {
+        let client = (40.7, -74.0);
+        let mut colos = vec![
+            colo("LAX", 33.94, -118.41, false),
+            colo("EWR", 40.69, -74.17, true),
+            colo("ORD", 41.98, -87.90, false),
+        ];
+
+        estimate_rtts(&mut colos, client, (40.69, -74.17), 8.0);
+
+        let order: Vec<_> = colos.iter().map(|c| c.iata.as_str()).collect();
+        assert_eq!(order, ["EWR", "ORD", "LAX"]);
+        assert_eq!(colos[0].rtt_ms, Some(8.0));
+
+        // ORD is about 1,150 km away: 11.5 ms of propagation on top of
+        // the ~8 ms of overhead measured to the nearby serving colo
+        let ord = colos[1].rtt_ms.unwrap();
+        assert!((ord - 19.4).abs() < 0.5, "{}", ord);
+    }
+
+    #[test]
+    fn test_print_table_marks_serving_colo() {
+        let mut colos = vec![colo("EWR", 40.69, -74.17, true)];
+        let mut out = Vec::new();
+        print_table(&colos, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("* EWR"));
+        assert!(!text.contains("RTT"));
+
+        estimate_rtts(&mut colos, (40.7, -74.0), (40.69, -74.17), 8.0);
+        colos.push(Colo {
+            rtt_ms: Some(20.0),
+            distance_km: Some(1150.0),
+            ..colo("ORD", 41.98, -87.90, false)
+        });
+        let mut out = Vec::new();
+        print_table(&colos, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("RTT"));
+        assert!(text.contains("  20.0 ms (est.)"));
+        assert!(text.contains("   8.0 ms\n"));
+    }
+}
@@ -313,6 +313,25 @@ pub fn jitter_f64(measurements: &[f64]) -> Option<f64> {
     Some(jitters.iter().sum::<f64>() / jitters.len() as f64)
 }
 
+/// Calculates the coefficient of variation (standard deviation / mean).
+///
+/// Uses the population standard deviation. Returns `None` for fewer than
+/// two values or a non-positive mean.
+pub fn coefficient_of_variation(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if mean <= 0.0 {
+        return None;
+    }
+
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    Some(variance.sqrt() / mean)
+}
+
 /// Aggregates bandwidth measurements by filtering and calculating a percentile.
 ///
 /// Filters out measurements with duration less than the minimum threshold,
@@ -356,6 +375,30 @@ pub fn aggregate_bandwidth(
     percentile_f64(&mut filtered_bandwidths, percentile)
 }
 
+/// Mean and maximum bandwidth in bits per second, over the measurements
+/// lasting at least `min_duration_ms`.
+///
+/// Returns `None` if all measurements are filtered out or the slice is
+/// empty.
+pub fn mean_and_max_bandwidth(
+    measurements: &[BandwidthMeasurement],
+    min_duration_ms: f64,
+) -> Option<(f64, f64)> {
+    let bandwidths: Vec<f64> = measurements
+        .iter()
+        .filter(|m| m.duration_ms >= min_duration_ms)
+        .map(|m| m.bandwidth_bps)
+        .collect();
+
+    if bandwidths.is_empty() {
+        return None;
+    }
+
+    let mean = bandwidths.iter().sum::<f64>() / bandwidths.len() as f64;
+    let max = bandwidths.iter().copied().fold(f64::MIN, f64::max);
+    Some((mean, max))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -576,6 +619,28 @@ mod tests {
         assert!(result >= 0.0);
     }
 
+    // Tests for coefficient_of_variation
+    #[test]
+    fn test_coefficient_of_variation_constant() {
+        let cv = coefficient_of_variation(&[10.0, 10.0, 10.0]).unwrap();
+        assert!(cv.abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_coefficient_of_variation_known_value() {
+        // mean = 5, population std dev = 2
+        let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        let cv = coefficient_of_variation(&values).unwrap();
+        assert!((cv - 0.4).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_coefficient_of_variation_edge_cases() {
+        assert_eq!(coefficient_of_variation(&[]), None);
+        assert_eq!(coefficient_of_variation(&[5.0]), None);
+        assert_eq!(coefficient_of_variation(&[0.0, 0.0]), None);
+    }
+
     // Tests for BandwidthMeasurement and aggregate_bandwidth
     #[test]
     fn test_aggregate_bandwidth_empty() {
@@ -635,6 +700,30 @@ mod tests {
         assert!((result - 11_800_000.0).abs() < 0.001);
     }
 
+    #[test]
+    fn test_mean_and_max_bandwidth() {
+        let measurement = |bandwidth_bps, duration_ms| BandwidthMeasurement {
+            bytes: 100000,
+            bandwidth_bps,
+            duration_ms,
+            server_time_ms: 1.0,
+            ttfb_ms: 2.0,
+        };
+        let measurements = vec![
+            measurement(30_000_000.0, 5.0), // Below threshold
+            measurement(10_000_000.0, 15.0),
+            measurement(12_000_000.0, 20.0),
+            measurement(14_000_000.0, 20.0),
+        ];
+
+        assert_eq!(
+            mean_and_max_bandwidth(&measurements, 10.0),
+            Some((12_000_000.0, 14_000_000.0))
+        );
+        assert_eq!(mean_and_max_bandwidth(&measurements, 100.0), None);
+        assert_eq!(mean_and_max_bandwidth(&[], 10.0), None);
+    }
+
     #[test]
     fn test_aggregate_bandwidth_none_filtered() {
         let measurements = vec![
@@ -1440,10 +1529,10 @@ mod tests {
 
             // Add measurements (all with valid request duration)
             let actual_count = num_measurements.min(latencies.len());
-            for i in 0..actual_count {
+            for &latency in latencies.iter().take(actual_count) {
                 collector.add(
                     LatencyDirection::Download,
-                    latencies[i],
+                    latency,
                     300.0, // Above threshold
                 );
             }
@@ -0,0 +1,94 @@
+//! Network change detection.
+//!
+//! A Wi-Fi roam or a VPN coming up mid-run splits a test across two
+//! networks, and the measurements no longer describe either. The local
+//! address the OS picks to reach the test server changes when the route
+//! does, so a run looks it up between phases: connecting a UDP socket
+//! selects a route without sending anything, which makes the check cheap
+//! enough to repeat after every size block.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, UdpSocket};
+
+/// Port the route lookup connects to; no packet is ever sent to it.
+const LOOKUP_PORT: u16 = 443;
+
+/// A change of the local address used to reach the test server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteChange {
+    /// Address before the change, `None` if there was no route
+    pub from: Option<IpAddr>,
+    /// Address after the change, `None` if the route was lost
+    pub to: Option<IpAddr>,
+}
+
+/// Tracks the local address used to reach a target across a run.
+#[derive(Debug, Clone)]
+pub struct NetworkWatch {
+    target: IpAddr,
+    local: Option<IpAddr>,
+}
+
+impl NetworkWatch {
+    /// Start watching the route to `target` from now.
+    pub fn start(target: IpAddr) -> Self {
+        Self { target, local: local_address(target) }
+    }
+
+    /// The route's change since the last check, if it changed.
+    pub fn check(&mut self) -> Option<RouteChange> {
+        self.check_with(local_address(self.target))
+    }
+
+    fn check_with(&mut self, local: Option<IpAddr>) -> Option<RouteChange> {
+        if local == self.local {
+            return None;
+        }
+        let change = RouteChange { from: self.local, to: local };
+        self.local = local;
+        Some(change)
+    }
+}
+
+/// Local address the OS would send from to reach `target`, or `None` if
+/// there's no route to it.
+pub fn local_address(target: IpAddr) -> Option<IpAddr> {
+    let unspecified = match target {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind((unspecified, 0)).ok()?;
+    socket.connect((target, LOOKUP_PORT)).ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_reports_each_change_once() {
+        let wifi: IpAddr = "192.168.1.20".parse().unwrap();
+        let vpn: IpAddr = "10.8.0.2".parse().unwrap();
+        let mut watch = NetworkWatch {
+            target: "104.16.0.1".parse().unwrap(),
+            local: Some(wifi),
+        };
+
+        assert_eq!(watch.check_with(Some(wifi)), None);
+        assert_eq!(
+            watch.check_with(Some(vpn)),
+            Some(RouteChange { from: Some(wifi), to: Some(vpn) })
+        );
+        assert_eq!(watch.check_with(Some(vpn)), None);
+        assert_eq!(
+            watch.check_with(None),
+            Some(RouteChange { from: Some(vpn), to: None })
+        );
+    }
+
+    #[test]
+    fn test_local_address_of_loopback() {
+        let loopback = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        assert_eq!(local_address(loopback), Some(loopback));
+    }
+}
@@ -0,0 +1,305 @@
+//! Continuous latency monitoring.
+//!
+//! `cloud-speed ping` sends an HTTP latency probe to the Cloudflare edge
+//! at a fixed interval until stopped, like ping but through the same
+//! HTTPS path the speed test uses. A probe that fails counts as lost.
+//! Statistics are kept as running totals plus a window of recent probes,
+//! so memory stays flat however long the monitor runs.
+
+use crate::cloudflare::tests::engine::TestEngine;
+use crate::measurements::{jitter_f64, latency_f64};
+use crate::reporters::CommandReport;
+use log::debug;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::ops::ControlFlow;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+/// Probes kept for the sparkline and rolling statistics.
+pub const WINDOW: usize = 120;
+
+/// How often the caller is polled while waiting for the next probe.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often to probe and when to stop.
+#[derive(Debug, Clone)]
+pub struct PingOptions {
+    /// Time between the starts of consecutive probes
+    pub interval: Duration,
+    /// Stop after this many probes; `None` runs until stopped
+    pub count: Option<usize>,
+}
+
+/// What the monitor reports to its caller.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PingTick {
+    /// A probe finished, with its latency in ms, or `None` if it was lost
+    Probe(Option<f64>),
+    /// Nothing new; the caller may redraw or check for input
+    Idle,
+}
+
+/// Running statistics over every probe sent.
+#[derive(Debug, Clone, Default)]
+pub struct PingStats {
+    sent: usize,
+    received: usize,
+    min_ms: Option<f64>,
+    max_ms: Option<f64>,
+    sum_ms: f64,
+    /// Sum of differences between consecutive received probes
+    jitter_sum_ms: f64,
+    last_ms: Option<f64>,
+    /// Latest received probes, oldest first
+    recent: VecDeque<f64>,
+}
+
+impl PingStats {
+    /// Record a probe's latency in ms, or `None` if it was lost.
+    pub fn record(&mut self, latency_ms: Option<f64>) {
+        self.sent += 1;
+        let Some(latency_ms) = latency_ms else {
+            return;
+        };
+
+        if let Some(last) = self.last_ms {
+            self.jitter_sum_ms += (latency_ms - last).abs();
+        }
+        self.received += 1;
+        self.min_ms =
+            Some(self.min_ms.map_or(latency_ms, |m| m.min(latency_ms)));
+        self.max_ms =
+            Some(self.max_ms.map_or(latency_ms, |m| m.max(latency_ms)));
+        self.sum_ms += latency_ms;
+        self.last_ms = Some(latency_ms);
+
+        if self.recent.len() == WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(latency_ms);
+    }
+
+    /// Probes sent so far.
+    pub fn sent(&self) -> usize {
+        self.sent
+    }
+
+    /// Latency of the latest received probe in ms.
+    pub fn last_ms(&self) -> Option<f64> {
+        self.last_ms
+    }
+
+    /// Latest received probes in ms, oldest first.
+    pub fn recent(&self) -> &VecDeque<f64> {
+        &self.recent
+    }
+
+    /// Median latency over the recent window in ms.
+    pub fn window_median_ms(&self) -> Option<f64> {
+        latency_f64(&self.window())
+    }
+
+    /// Jitter over the recent window in ms.
+    pub fn window_jitter_ms(&self) -> Option<f64> {
+        jitter_f64(&self.window())
+    }
+
+    fn window(&self) -> Vec<f64> {
+        self.recent.iter().copied().collect()
+    }
+
+    /// Share of probes lost, from 0 to 1.
+    pub fn loss_ratio(&self) -> f64 {
+        if self.sent == 0 {
+            0.0
+        } else {
+            (self.sent - self.received) as f64 / self.sent as f64
+        }
+    }
+
+    /// Statistics over every probe sent.
+    pub fn summary(&self) -> PingSummary {
+        PingSummary {
+            sent: self.sent,
+            received: self.received,
+            loss_percent: self.loss_ratio() * 100.0,
+            min_ms: self.min_ms,
+            avg_ms: (self.received > 0)
+                .then(|| self.sum_ms / self.received as f64),
+            max_ms: self.max_ms,
+            jitter_ms: (self.received >= 2)
+                .then(|| self.jitter_sum_ms / (self.received - 1) as f64),
+        }
+    }
+}
+
+/// Latency statistics of a whole monitoring session.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PingSummary {
+    pub sent: usize,
+    pub received: usize,
+    pub loss_percent: f64,
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    /// Mean difference between consecutive received probes
+    pub jitter_ms: Option<f64>,
+}
+
+/// Probe latency every `options.interval` until `options.count` probes
+/// were sent or `on_tick` breaks.
+///
+/// `on_tick` is called after every probe and every 100 ms in between, so
+/// the caller can redraw and react to input.
+pub async fn run<F>(
+    engine: &TestEngine,
+    options: &PingOptions,
+    mut on_tick: F,
+) -> PingStats
+where
+    F: FnMut(PingTick, &PingStats) -> ControlFlow<()>,
+{
+    let mut stats = PingStats::default();
+    let mut ticker = tokio::time::interval(options.interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    while options.count.is_none_or(|count| stats.sent() < count) {
+        let tick = ticker.tick();
+        tokio::pin!(tick);
+        loop {
+            tokio::select! {
+                _ = &mut tick => break,
+                _ = tokio::time::sleep(POLL_INTERVAL) => {
+                    if on_tick(PingTick::Idle, &stats).is_break() {
+                        return stats;
+                    }
+                }
+            }
+        }
+
+        let latency_ms = match engine.probe_latency().await {
+            Ok(latency_ms) => Some(latency_ms),
+            Err(e) => {
+                debug!("Latency probe {} lost: {}", stats.sent() + 1, e);
+                None
+            }
+        };
+        stats.record(latency_ms);
+        if on_tick(PingTick::Probe(latency_ms), &stats).is_break() {
+            break;
+        }
+    }
+
+    stats
+}
+
+/// Print one probe as a line, like ping.
+pub fn print_probe<W: Write>(
+    seq: usize,
+    latency_ms: Option<f64>,
+    out: &mut W,
+) -> io::Result<()> {
+    match latency_ms {
+        Some(ms) => writeln!(out, "seq={} time={:.2} ms", seq, ms),
+        None => writeln!(out, "seq={} lost", seq),
+    }
+}
+
+/// Print the session statistics, like ping does when stopped.
+pub fn print_summary<W: Write + ?Sized>(
+    host: &str,
+    summary: &PingSummary,
+    out: &mut W,
+) -> io::Result<()> {
+    writeln!(out, "--- {} latency statistics ---", host)?;
+    writeln!(
+        out,
+        "{} probes sent, {} received, {:.1}% loss",
+        summary.sent, summary.received, summary.loss_percent
+    )?;
+    if let (Some(min), Some(avg), Some(max)) =
+        (summary.min_ms, summary.avg_ms, summary.max_ms)
+    {
+        write!(out, "min/avg/max = {:.2}/{:.2}/{:.2} ms", min, avg, max)?;
+        if let Some(jitter) = summary.jitter_ms {
+            write!(out, ", jitter {:.2} ms", jitter)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+impl CommandReport for PingSummary {
+    fn write_human(&self, out: &mut dyn Write) -> io::Result<()> {
+        print_summary("speed.cloudflare.com", self, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_track_loss_and_extremes() {
+        let mut stats = PingStats::default();
+        for latency in [Some(10.0), None, Some(14.0), Some(12.0)] {
+            stats.record(latency);
+        }
+
+        let summary = stats.summary();
+        assert_eq!(summary.sent, 4);
+        assert_eq!(summary.received, 3);
+        assert_eq!(summary.loss_percent, 25.0);
+        assert_eq!(summary.min_ms, Some(10.0));
+        assert_eq!(summary.avg_ms, Some(12.0));
+        assert_eq!(summary.max_ms, Some(14.0));
+        // |14 - 10| and |12 - 14|; lost probes don't count
+        assert_eq!(summary.jitter_ms, Some(3.0));
+        assert_eq!(stats.last_ms(), Some(12.0));
+        assert_eq!(stats.window_median_ms(), Some(12.0));
+    }
+
+    #[test]
+    fn test_window_keeps_latest_probes() {
+        let mut stats = PingStats::default();
+        for i in 0..WINDOW + 10 {
+            stats.record(Some(i as f64));
+        }
+
+        assert_eq!(stats.recent().len(), WINDOW);
+        assert_eq!(stats.recent().front(), Some(&10.0));
+        assert_eq!(stats.summary().min_ms, Some(0.0));
+    }
+
+    #[test]
+    fn test_empty_summary() {
+        let summary = PingStats::default().summary();
+        assert_eq!(summary.loss_percent, 0.0);
+        assert_eq!(summary.avg_ms, None);
+        assert_eq!(summary.jitter_ms, None);
+
+        let mut out = Vec::new();
+        print_summary("example.com", &summary, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("0 probes sent, 0 received"));
+        assert!(!text.contains("min/avg/max"));
+    }
+
+    #[test]
+    fn test_print_summary() {
+        let mut stats = PingStats::default();
+        stats.record(Some(10.0));
+        stats.record(Some(20.0));
+
+        let mut out = Vec::new();
+        print_summary("speed.cloudflare.com", &stats.summary(), &mut out)
+            .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("--- speed.cloudflare.com latency"));
+        assert!(text.contains("2 probes sent, 2 received, 0.0% loss"));
+        assert!(text.contains("min/avg/max = 10.00/15.00/20.00 ms"));
+        assert!(text.contains("jitter 10.00 ms"));
+    }
+}
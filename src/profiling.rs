@@ -0,0 +1,235 @@
+//! Memory and allocation profiling, behind the `profiling` feature.
+//!
+//! With the feature enabled, every heap allocation goes through a
+//! counting allocator, and a `--detailed` run reports how many
+//! allocations each phase of the test made and the process's peak
+//! resident set size. Without it nothing is counted and nothing is
+//! reported, so normal builds pay nothing for the hooks.
+//!
+//! Counts are process-wide: allocations made by anything running
+//! alongside the test (the TUI, or other runs under `serve`) count
+//! towards whichever phase was current.
+
+use crate::tui::TestPhase;
+use std::sync::Mutex;
+
+#[cfg(feature = "profiling")]
+mod counting {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// Allocations made since the process started.
+    pub static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+    /// Bytes allocated since the process started, frees not subtracted.
+    pub static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+    /// The system allocator, counting what it hands out.
+    pub struct CountingAllocator;
+
+    // SAFETY: every call is forwarded unchanged to the system allocator.
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+            unsafe { System.alloc_zeroed(layout) }
+        }
+
+        unsafe fn realloc(
+            &self,
+            ptr: *mut u8,
+            layout: Layout,
+            new_size: usize,
+        ) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            ALLOCATED_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+            unsafe { System.realloc(ptr, layout, new_size) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+}
+
+/// Allocations counted up to some point.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocationCount {
+    /// Number of allocations, reallocations included
+    pub allocations: u64,
+    /// Bytes requested by those allocations
+    pub bytes: u64,
+}
+
+impl AllocationCount {
+    /// Allocations so far, when built with the `profiling` feature.
+    #[cfg(feature = "profiling")]
+    pub fn now() -> Option<Self> {
+        use std::sync::atomic::Ordering;
+        Some(Self {
+            allocations: counting::ALLOCATIONS.load(Ordering::Relaxed),
+            bytes: counting::ALLOCATED_BYTES.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Allocations so far, when built with the `profiling` feature.
+    #[cfg(not(feature = "profiling"))]
+    pub fn now() -> Option<Self> {
+        None
+    }
+
+    /// Allocations made between `earlier` and this count.
+    pub fn since(self, earlier: Self) -> Self {
+        Self {
+            allocations: self.allocations.saturating_sub(earlier.allocations),
+            bytes: self.bytes.saturating_sub(earlier.bytes),
+        }
+    }
+}
+
+/// Peak resident set size of the process in bytes, where the platform
+/// reports it.
+#[cfg(target_os = "linux")]
+pub fn peak_rss_bytes() -> Option<u64> {
+    // SAFETY: all-zero is a valid rusage for the kernel to fill in.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    // SAFETY: `usage` is a valid rusage to write into.
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    // Linux reports KiB
+    Some(usage.ru_maxrss as u64 * 1024)
+}
+
+/// Peak resident set size of the process in bytes, where the platform
+/// reports it.
+#[cfg(not(target_os = "linux"))]
+pub fn peak_rss_bytes() -> Option<u64> {
+    None
+}
+
+/// Allocations made while one phase was current.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhaseAllocations {
+    /// The phase
+    pub phase: TestPhase,
+    /// Allocations made during it
+    pub count: AllocationCount,
+}
+
+/// Allocations per phase and peak memory of a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunProfile {
+    /// Allocations of each phase, in the order the phases ran
+    pub phases: Vec<PhaseAllocations>,
+    /// Peak resident set size of the process in bytes, if known
+    pub peak_rss_bytes: Option<u64>,
+}
+
+/// Tracks allocation counts across the phases of a run.
+#[derive(Debug, Default)]
+pub struct PhaseProfiler {
+    /// Each phase entered so far, with the count when it was entered
+    marks: Mutex<Vec<(TestPhase, AllocationCount)>>,
+}
+
+impl PhaseProfiler {
+    /// Note that `phase` starts now. Does nothing unless built with the
+    /// `profiling` feature.
+    pub fn enter(&self, phase: TestPhase) {
+        if let Some(count) = AllocationCount::now() {
+            self.mark(phase, count);
+        }
+    }
+
+    /// Note that `phase` started when the allocation count was `count`.
+    fn mark(&self, phase: TestPhase, count: AllocationCount) {
+        if let Ok(mut marks) = self.marks.lock() {
+            marks.push((phase, count));
+        }
+    }
+
+    /// Allocations of each phase entered so far, the last one ending at
+    /// `end`.
+    fn phases(&self, end: AllocationCount) -> Vec<PhaseAllocations> {
+        let Ok(marks) = self.marks.lock() else {
+            return Vec::new();
+        };
+        let ends = marks.iter().skip(1).map(|(_, count)| *count);
+        marks
+            .iter()
+            .zip(ends.chain(std::iter::once(end)))
+            .map(|(&(phase, start), end)| PhaseAllocations {
+                phase,
+                count: end.since(start),
+            })
+            .collect()
+    }
+
+    /// The profile of the run so far, when built with the `profiling`
+    /// feature.
+    pub fn finish(&self) -> Option<RunProfile> {
+        let end = AllocationCount::now()?;
+        Some(RunProfile {
+            phases: self.phases(end),
+            peak_rss_bytes: peak_rss_bytes(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(allocations: u64, bytes: u64) -> AllocationCount {
+        AllocationCount { allocations, bytes }
+    }
+
+    #[test]
+    fn test_phase_allocations() {
+        let profiler = PhaseProfiler::default();
+        profiler.mark(TestPhase::Latency, count(100, 1_000));
+        profiler.mark(TestPhase::Download, count(150, 5_000));
+
+        let phases = profiler.phases(count(400, 9_000));
+        assert_eq!(
+            phases,
+            vec![
+                PhaseAllocations {
+                    phase: TestPhase::Latency,
+                    count: count(50, 4_000),
+                },
+                PhaseAllocations {
+                    phase: TestPhase::Download,
+                    count: count(250, 4_000),
+                },
+            ]
+        );
+        assert!(PhaseProfiler::default().phases(count(1, 1)).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "profiling")]
+    fn test_counts_allocations() {
+        let before = AllocationCount::now().unwrap();
+        let buffer = std::hint::black_box(vec![0_u8; 4096]);
+        let made = AllocationCount::now().unwrap().since(before);
+        drop(buffer);
+        assert!(made.allocations >= 1);
+        assert!(made.bytes >= 4096);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_peak_rss() {
+        assert!(peak_rss_bytes().unwrap() > 0);
+    }
+}
@@ -0,0 +1,377 @@
+//! Comparison against regional medians.
+//!
+//! `--compare-region` fetches the median speeds Cloudflare Radar
+//! publishes for the client's country or network (ASN) and reports how
+//! the run compares to them. Radar's API needs a token with Radar read
+//! access, taken from `[regional] token` or `CLOUDFLARE_API_TOKEN`; a
+//! `[regional] endpoint` answering the same queries in the same shape
+//! can stand in for it, e.g. a mirror of a maintained dataset.
+
+use crate::cloudflare::proxy::env_proxy;
+use crate::results::SpeedTestResults;
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+use reqwest::{Client as ReqwestClient, Proxy, Response};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use url::Url;
+
+/// Cloudflare Radar's speed test summary, by location or ASN.
+pub const RADAR_ENDPOINT: &str =
+    "https://api.cloudflare.com/client/v4/radar/quality/speed/summary";
+
+/// Environment variable holding the Radar API token.
+pub const TOKEN_ENV: &str = "CLOUDFLARE_API_TOKEN";
+
+/// How long the endpoint has to answer.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the run is compared against.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum RegionScope {
+    /// Every test from the client's country
+    #[default]
+    Country,
+    /// Every test from the client's network
+    Asn,
+}
+
+impl fmt::Display for RegionScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RegionScope::Country => "country",
+            RegionScope::Asn => "asn",
+        })
+    }
+}
+
+impl FromStr for RegionScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "country" => Ok(RegionScope::Country),
+            "asn" => Ok(RegionScope::Asn),
+            other => Err(format!(
+                "unknown region scope '{}' (expected country or asn)",
+                other
+            )),
+        }
+    }
+}
+
+/// Medians published for a region. Radar leaves out what it has too few
+/// tests for.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegionalMedians {
+    #[serde(default, deserialize_with = "number")]
+    pub bandwidth_download: Option<f64>,
+    #[serde(default, deserialize_with = "number")]
+    pub bandwidth_upload: Option<f64>,
+    #[serde(default, deserialize_with = "number")]
+    pub latency_idle: Option<f64>,
+    #[serde(default, deserialize_with = "number")]
+    pub jitter_idle: Option<f64>,
+    #[serde(default, deserialize_with = "number")]
+    pub packet_loss: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct RadarResponse {
+    result: RadarResult,
+}
+
+#[derive(Deserialize)]
+struct RadarResult {
+    summary_0: RegionalMedians,
+}
+
+/// Radar sends its numbers as strings; accept either.
+fn number<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Number(f64),
+        Text(String),
+    }
+
+    Ok(match Option::<Value>::deserialize(deserializer)? {
+        Some(Value::Number(value)) => Some(value),
+        Some(Value::Text(text)) => text.trim().parse().ok(),
+        None => None,
+    })
+}
+
+/// One metric of the run against the regional median.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegionalMetric {
+    /// Metric name, as in the baseline comparison
+    pub metric: String,
+    /// Value measured by this run
+    pub value: f64,
+    /// Regional median
+    pub median: f64,
+    /// The value as a percentage of the median
+    pub percent_of_median: f64,
+    /// Whether the value is at least as good as the median
+    pub better: bool,
+}
+
+/// How the run compares to the medians of its region.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegionalComparison {
+    /// Whether the region is the client's country or network
+    pub scope: RegionScope,
+    /// Country code or "AS<number>" the medians are for
+    pub region: String,
+    /// Host the medians came from
+    pub source: String,
+    pub metrics: Vec<RegionalMetric>,
+}
+
+impl RegionalComparison {
+    /// Compare `results` to `medians`, skipping metrics either side
+    /// lacks.
+    pub fn new(
+        scope: RegionScope,
+        region: String,
+        source: String,
+        results: &SpeedTestResults,
+        medians: &RegionalMedians,
+    ) -> Self {
+        // (name, measured, median, higher is better)
+        let candidates = [
+            (
+                "download_mbps",
+                results.download.as_ref().map(|d| d.speed_mbps),
+                medians.bandwidth_download,
+                true,
+            ),
+            (
+                "upload_mbps",
+                results.upload.as_ref().map(|u| u.speed_mbps),
+                medians.bandwidth_upload,
+                true,
+            ),
+            (
+                "latency_ms",
+                Some(results.latency.idle_ms),
+                medians.latency_idle,
+                false,
+            ),
+            (
+                "jitter_ms",
+                results.latency.idle_jitter_ms,
+                medians.jitter_idle,
+                false,
+            ),
+            (
+                "packet_loss_percent",
+                results.packet_loss.as_ref().map(|loss| loss.percent),
+                medians.packet_loss,
+                false,
+            ),
+        ];
+
+        let metrics = candidates
+            .into_iter()
+            .filter_map(|(metric, value, median, higher_is_better)| {
+                let (value, median) = (value?, median?);
+                if median <= 0.0 {
+                    return None;
+                }
+                Some(RegionalMetric {
+                    metric: metric.to_string(),
+                    value,
+                    median,
+                    percent_of_median: value / median * 100.0,
+                    better: if higher_is_better {
+                        value >= median
+                    } else {
+                        value <= median
+                    },
+                })
+            })
+            .collect();
+
+        Self { scope, region, source, metrics }
+    }
+}
+
+/// The region `results` belong to within `scope`: a country code or
+/// "AS<number>". `None` when the results don't record it, e.g. the ASN
+/// of anonymized results.
+pub fn region_of(
+    results: &SpeedTestResults,
+    scope: RegionScope,
+) -> Option<String> {
+    match scope {
+        RegionScope::Country => Some(results.connection.country.clone())
+            .filter(|country| !country.is_empty()),
+        RegionScope::Asn => {
+            results.connection.asn.map(|asn| format!("AS{}", asn))
+        }
+    }
+}
+
+/// Fetch the medians for `region` from `endpoint`, which answers like
+/// Radar's speed summary, sending `headers`.
+pub async fn fetch(
+    endpoint: &str,
+    token: Option<&str>,
+    scope: RegionScope,
+    region: &str,
+    headers: HeaderMap,
+) -> Result<RegionalMedians, Box<dyn Error>> {
+    let client = ReqwestClient::builder()
+        .no_proxy()
+        .proxy(Proxy::custom(|url| env_proxy().proxy_for(url).cloned()))
+        .timeout(FETCH_TIMEOUT)
+        .build()?;
+    // Radar takes ASNs as bare numbers
+    let query = match scope {
+        RegionScope::Country => ("location", region),
+        RegionScope::Asn => ("asn", region.trim_start_matches("AS")),
+    };
+    let url = Url::parse_with_params(endpoint, [query, ("format", "json")])?;
+    let mut request = client.get(url).headers(headers);
+    if let Some(token) = token {
+        request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+    }
+    let text = request
+        .send()
+        .await
+        .and_then(Response::error_for_status)?
+        .text()
+        .await?;
+
+    parse(&text)
+}
+
+fn parse(text: &str) -> Result<RegionalMedians, Box<dyn Error>> {
+    let response: RadarResponse = serde_json::from_str(text)
+        .map_err(|e| format!("unexpected response: {}", e))?;
+    let medians = response.result.summary_0;
+    if medians == RegionalMedians::default() {
+        return Err("no published medians for this region".into());
+    }
+    Ok(medians)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::{
+        AimScoresOutput, BandwidthResults, ConnectionMeta, LatencyResults,
+        ServerLocation,
+    };
+
+    fn results() -> SpeedTestResults {
+        SpeedTestResults::new(
+            ServerLocation::new("Dallas".into(), "DFW".into()),
+            ConnectionMeta::new(
+                "203.0.113.7".into(),
+                "US".into(),
+                "Example ISP".into(),
+                64500,
+            ),
+            LatencyResults::new(30.0, Some(2.0), None, None, None, None),
+            BandwidthResults::new(120.0, vec![], false),
+            BandwidthResults::new(10.0, vec![], false),
+            None,
+            AimScoresOutput {
+                streaming: "great".to_string(),
+                gaming: "good".to_string(),
+                video_conferencing: "good".to_string(),
+                overall: "good".to_string(),
+                details: None,
+                working_latency: None,
+                grade: None,
+            },
+        )
+    }
+
+    #[test]
+    fn test_parse_radar_summary() {
+        let text = r#"{
+            "success": true,
+            "result": {
+                "meta": {"dateRange": []},
+                "summary_0": {
+                    "bandwidthDownload": "100.5",
+                    "bandwidthUpload": "20",
+                    "latencyIdle": 25,
+                    "jitterIdle": "3.1",
+                    "packetLoss": "0.9"
+                }
+            }
+        }"#;
+        let medians = parse(text).unwrap();
+
+        assert_eq!(medians.bandwidth_download, Some(100.5));
+        assert_eq!(medians.bandwidth_upload, Some(20.0));
+        assert_eq!(medians.latency_idle, Some(25.0));
+        assert_eq!(medians.jitter_idle, Some(3.1));
+        assert_eq!(medians.packet_loss, Some(0.9));
+    }
+
+    #[test]
+    fn test_parse_rejects_empty_summary() {
+        assert!(parse(r#"{"result": {"summary_0": {}}}"#).is_err());
+        assert!(parse(r#"{"success": false, "errors": []}"#).is_err());
+    }
+
+    #[test]
+    fn test_comparison_direction() {
+        let medians = RegionalMedians {
+            bandwidth_download: Some(100.0),
+            bandwidth_upload: Some(20.0),
+            latency_idle: Some(25.0),
+            jitter_idle: None,
+            packet_loss: Some(1.0),
+        };
+        let comparison = RegionalComparison::new(
+            RegionScope::Country,
+            "US".into(),
+            "example.com".into(),
+            &results(),
+            &medians,
+        );
+        let metric = |name: &str| {
+            comparison.metrics.iter().find(|m| m.metric == name).cloned()
+        };
+
+        let download = metric("download_mbps").unwrap();
+        assert!(download.better);
+        assert!((download.percent_of_median - 120.0).abs() < 1e-9);
+        assert!(!metric("upload_mbps").unwrap().better);
+        assert!(!metric("latency_ms").unwrap().better);
+        // No median for jitter, no measurement for packet loss
+        assert!(metric("jitter_ms").is_none());
+        assert!(metric("packet_loss_percent").is_none());
+    }
+
+    #[test]
+    fn test_region_of() {
+        let mut results = results();
+        assert_eq!(
+            region_of(&results, RegionScope::Country).as_deref(),
+            Some("US")
+        );
+        assert_eq!(
+            region_of(&results, RegionScope::Asn).as_deref(),
+            Some("AS64500")
+        );
+        results.connection.anonymize();
+        assert_eq!(region_of(&results, RegionScope::Asn), None);
+    }
+}
@@ -0,0 +1,312 @@
+//! Repeated runs of the complete test.
+//!
+//! One run is a single sample of a noisy network. `--repeat` runs the
+//! test several times back to back and reports every run along with the
+//! median of each metric across runs and how far the runs spread, which
+//! is a firmer basis for a complaint to an ISP than any one result.
+
+use crate::ab::{RunSample, METRICS};
+use crate::clock::skew_secs;
+use crate::cloudflare::client::Client;
+use crate::cloudflare::requests::{locations::Locations, meta::MetaRequest};
+use crate::cloudflare::tests::engine::{TestConfig, TestEngine};
+use crate::reporters::CommandReport;
+use crate::results::{
+    BandwidthResults, ConnectionMeta, RunMeta, ServerLocation,
+    SpeedTestResults,
+};
+use crate::scoring::ScoringThresholds;
+use crate::stats::{median_f64, percentile_f64};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{self, Write};
+
+/// How many runs to make and how to label them.
+#[derive(Debug, Clone)]
+pub struct RepeatOptions {
+    /// Runs of the complete test
+    pub runs: usize,
+    /// Tags attached to every run's metadata
+    pub tags: BTreeMap<String, String>,
+}
+
+/// Every run of a repeated test and their aggregate.
+#[derive(Debug, Clone, Serialize)]
+pub struct RepeatReport {
+    /// When the last run finished
+    pub timestamp: DateTime<Utc>,
+    /// Results of each successful run, in order
+    pub runs: Vec<SpeedTestResults>,
+    /// Runs that failed, excluded from the aggregate
+    pub failures: usize,
+    /// Median and spread of each metric across the successful runs
+    pub aggregate: Vec<MetricSpread>,
+}
+
+impl RepeatReport {
+    /// Whether no run succeeded.
+    pub fn incomplete(&self) -> bool {
+        self.runs.is_empty()
+    }
+}
+
+/// Median and spread of one metric across runs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricSpread {
+    pub metric: &'static str,
+    pub unit: &'static str,
+    /// Runs that reported the metric
+    pub samples: usize,
+    /// Median across runs of each run's own median (or percentile)
+    pub median: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Interquartile range across runs
+    pub iqr: f64,
+}
+
+/// Run the complete test `options.runs` times.
+///
+/// Progress is written to stderr. A failed run is logged and left out of
+/// the aggregate; the connection metadata is fetched once, up front.
+pub async fn run(
+    test_config: TestConfig,
+    thresholds: &ScoringThresholds,
+    options: RepeatOptions,
+) -> Result<RepeatReport, Box<dyn Error>> {
+    let client = Client::from_headers(test_config.headers.clone());
+    let proxy = client.active_proxy();
+
+    let (meta, server_date) = client
+        .send_dated(MetaRequest {})
+        .await
+        .map_err(|e| format!("Failed to fetch connection metadata: {}", e))?;
+    let clock_skew = server_date.map(|date| skew_secs(Utc::now(), date));
+    let location = client
+        .send(Locations {})
+        .await
+        .map_err(|e| format!("Failed to fetch server locations: {}", e))?
+        .get(&meta.colo.iata);
+
+    let dscp = test_config.socket_options.dscp;
+    let order = test_config.bandwidth_order();
+    let pacing_jitter = test_config.pacing_jitter.is_some();
+    let engine = TestEngine::new(test_config, None);
+
+    let mut runs = Vec::with_capacity(options.runs);
+    let mut samples = Vec::with_capacity(options.runs);
+    let mut failures = 0;
+
+    for i in 0..options.runs {
+        let position = format!("[{}/{}]", i + 1, options.runs);
+        let output = match engine.run().await {
+            Ok(output) => output,
+            Err(e) => {
+                eprintln!("{}: failed: {}", position, e);
+                failures += 1;
+                continue;
+            }
+        };
+
+        let sample = RunSample::from_output(&output);
+        eprintln!(
+            "{}: {:.2} Mbps down, {:.2} Mbps up, {:.1} ms idle",
+            position,
+            sample.download_mbps,
+            sample.upload_mbps,
+            sample.idle_latency_ms
+        );
+        samples.push(sample);
+
+        let server =
+            ServerLocation::new(location.city.clone(), location.iata.clone())
+                .with_ip(output.endpoint_ip().map(|ip| ip.to_string()));
+        let connection = ConnectionMeta::new(
+            meta.client_ip.clone(),
+            meta.country.clone(),
+            meta.as_organization.clone(),
+            meta.asn,
+        )
+        .with_proxy(proxy.clone());
+
+        runs.push(
+            SpeedTestResults::from_engine_output(
+                &output, server, connection, None, thresholds,
+            )
+            .with_meta(Some(
+                RunMeta::detect()
+                    .with_tags(options.tags.clone())
+                    .with_vpn(crate::vpn::detect(
+                        Some(&meta.as_organization),
+                        output.path_mtu.map(|path_mtu| path_mtu.bytes),
+                    ))
+                    .with_dscp(dscp)
+                    .with_order(order)
+                    .with_pacing_jitter(pacing_jitter)
+                    .with_clock(clock_skew, output.clock_step)
+                    .with_rate_limit(output.rate_limit.as_ref())
+                    .with_network_changes(&output.network_changes),
+            )),
+        );
+    }
+
+    Ok(RepeatReport {
+        timestamp: Utc::now(),
+        runs,
+        failures,
+        aggregate: aggregate(&samples),
+    })
+}
+
+/// Median and spread of each metric across `samples`.
+///
+/// Metrics missing from every run are left out.
+pub fn aggregate(samples: &[RunSample]) -> Vec<MetricSpread> {
+    METRICS
+        .iter()
+        .filter_map(|metric| {
+            let mut values: Vec<f64> =
+                samples.iter().filter_map(metric.value).collect();
+            let median = median_f64(&mut values)?;
+            let p25 = percentile_f64(&mut values, 0.25)?;
+            let p75 = percentile_f64(&mut values, 0.75)?;
+            let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+            Some(MetricSpread {
+                metric: metric.name,
+                unit: metric.unit,
+                samples: values.len(),
+                median,
+                min,
+                max,
+                iqr: p75 - p25,
+            })
+        })
+        .collect()
+}
+
+/// Print each run and the aggregate as human-readable tables.
+pub fn print_report<W: Write + ?Sized>(
+    report: &RepeatReport,
+    out: &mut W,
+) -> io::Result<()> {
+    write!(out, "Repeated test ({} runs", report.runs.len())?;
+    if report.failures > 0 {
+        write!(out, ", {} failed", report.failures)?;
+    }
+    writeln!(out, ")")?;
+
+    if report.incomplete() {
+        writeln!(out, "No successful runs to aggregate")?;
+        return Ok(());
+    }
+
+    writeln!(
+        out,
+        "{:<5} {:>10} {:>10} {:>10}",
+        "Run", "Down Mbps", "Up Mbps", "Idle ms"
+    )?;
+    for (i, run) in report.runs.iter().enumerate() {
+        writeln!(
+            out,
+            "{:<5} {:>10} {:>10} {:>10.1}",
+            i + 1,
+            mbps(run.download.as_ref()),
+            mbps(run.upload.as_ref()),
+            run.latency.idle_ms
+        )?;
+    }
+    writeln!(out)?;
+
+    writeln!(
+        out,
+        "{:<20} {:>10} {:>10} {:>10} {:>8}",
+        "Metric", "Median", "Min", "Max", "IQR"
+    )?;
+    for metric in &report.aggregate {
+        writeln!(
+            out,
+            "{:<20} {:>10.2} {:>10.2} {:>10.2} {:>8.2}",
+            format!("{} ({})", metric.metric, metric.unit),
+            metric.median,
+            metric.min,
+            metric.max,
+            metric.iqr
+        )?;
+    }
+    Ok(())
+}
+
+/// A run's speed in one direction for the report table, or `-` when
+/// the direction was skipped.
+fn mbps(bandwidth: Option<&BandwidthResults>) -> String {
+    bandwidth.map_or("-".to_string(), |b| format!("{:.2}", b.speed_mbps))
+}
+
+impl CommandReport for RepeatReport {
+    fn write_human(&self, out: &mut dyn Write) -> io::Result<()> {
+        print_report(self, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(download_mbps: f64) -> RunSample {
+        RunSample {
+            download_mbps,
+            upload_mbps: 20.0,
+            idle_latency_ms: 12.0,
+            ..RunSample::default()
+        }
+    }
+
+    #[test]
+    fn test_aggregate_median_and_spread() {
+        let samples: Vec<_> = [90.0, 40.0, 94.0, 92.0, 91.0]
+            .iter()
+            .map(|&d| sample(d))
+            .collect();
+        let metrics = aggregate(&samples);
+
+        let download = &metrics[0];
+        assert_eq!(download.metric, "download");
+        assert_eq!(download.samples, 5);
+        assert_eq!(download.median, 91.0);
+        assert_eq!(download.min, 40.0);
+        assert_eq!(download.max, 94.0);
+        // One bad run widens the range but not the IQR
+        assert_eq!(download.iqr, 2.0);
+
+        let upload = &metrics[1];
+        assert_eq!(upload.median, 20.0);
+        assert_eq!(upload.iqr, 0.0);
+
+        // Loaded latency wasn't measured in any run
+        let names: Vec<_> = metrics.iter().map(|m| m.metric).collect();
+        assert_eq!(names, ["download", "upload", "idle_latency"]);
+        assert!(aggregate(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_print_report_without_runs() {
+        let report = RepeatReport {
+            timestamp: Utc::now(),
+            runs: Vec::new(),
+            failures: 3,
+            aggregate: Vec::new(),
+        };
+
+        let mut out = Vec::new();
+        print_report(&report, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.starts_with("Repeated test (0 runs, 3 failed)"));
+        assert!(text.contains("No successful runs to aggregate"));
+        assert!(report.incomplete());
+    }
+}
@@ -0,0 +1,238 @@
+//! Output of the subcommands that report something other than a speed
+//! test's results, such as `score`, `meta` or `diff`.
+//!
+//! Each report is written as JSON in JSON mode and as text for people
+//! otherwise; implementing [`CommandReport`] supplies the text form.
+
+use crate::results::{AimScoresOutput, EgressResults};
+use crate::share::ShareSummary;
+use colored::Colorize;
+use serde::Serialize;
+use std::io::{self, Write};
+
+/// A subcommand's report.
+pub trait CommandReport: Serialize {
+    /// Write the report for people.
+    fn write_human(&self, out: &mut dyn Write) -> io::Result<()>;
+}
+
+/// Write `report` to `out` as JSON (pretty printed with `pretty`) when
+/// `json` is set, otherwise for people.
+pub fn write_report<R: CommandReport + ?Sized>(
+    report: &R,
+    json: bool,
+    pretty: bool,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    if !json {
+        return report.write_human(out);
+    }
+    let json = if pretty {
+        serde_json::to_string_pretty(report)
+    } else {
+        serde_json::to_string(report)
+    };
+    writeln!(out, "{}", json.map_err(io::Error::from)?)
+}
+
+/// `cloud-speed score` for metrics given as flags.
+impl CommandReport for AimScoresOutput {
+    fn write_human(&self, out: &mut dyn Write) -> io::Result<()> {
+        let label = |name: &str| name.bold().white();
+
+        writeln!(out, "{} {}", label("Streaming:\t"), self.streaming)?;
+        writeln!(out, "{} {}", label("Gaming:\t\t"), self.gaming)?;
+        writeln!(
+            out,
+            "{} {}",
+            label("Video Calls:\t"),
+            self.video_conferencing
+        )?;
+        if let Some(ref grade) = self.grade {
+            writeln!(
+                out,
+                "{} {} ({})",
+                label("Grade:\t\t"),
+                grade.grade,
+                grade.explanation
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `cloud-speed meta`.
+impl CommandReport for EgressResults {
+    fn write_human(&self, out: &mut dyn Write) -> io::Result<()> {
+        let connection = &self.connection;
+        let label = |name: &str| name.bold().white();
+
+        writeln!(out, "{} {}", label("IP:\t\t"), connection.ip)?;
+        if let (Some(isp), Some(asn)) = (&connection.isp, connection.asn) {
+            writeln!(out, "{} {} (AS{})", label("ISP:\t\t"), isp, asn)?;
+        }
+        writeln!(
+            out,
+            "{} {}",
+            label("Location:\t"),
+            [self.city.as_str(), self.region.as_str(), &connection.country]
+                .iter()
+                .filter(|part| !part.is_empty())
+                .copied()
+                .collect::<Vec<_>>()
+                .join(", ")
+        )?;
+        writeln!(
+            out,
+            "{} {} ({})",
+            label("Server:\t"),
+            self.server.city,
+            self.server.iata
+        )?;
+        if let Some(ref proxy) = connection.proxy {
+            writeln!(out, "{} {}", label("Proxy:\t"), proxy)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `cloud-speed decode`.
+impl CommandReport for ShareSummary {
+    fn write_human(&self, out: &mut dyn Write) -> io::Result<()> {
+        let label = |name: &str| name.bold().white();
+
+        writeln!(
+            out,
+            "{} {} via {} ({})",
+            label("Tested:\t\t"),
+            self.timestamp.to_rfc3339(),
+            self.server,
+            self.country
+        )?;
+        writeln!(
+            out,
+            "{} {:.2} Mbps",
+            label("Download:\t"),
+            self.download_mbps
+        )?;
+        writeln!(
+            out,
+            "{} {:.2} Mbps",
+            label("Upload:\t\t"),
+            self.upload_mbps
+        )?;
+        match self.jitter_ms {
+            Some(jitter) => writeln!(
+                out,
+                "{} {:.2} ms (jitter {:.2} ms)",
+                label("Latency:\t"),
+                self.latency_ms,
+                jitter
+            )?,
+            None => writeln!(
+                out,
+                "{} {:.2} ms",
+                label("Latency:\t"),
+                self.latency_ms
+            )?,
+        }
+        if let Some(loss) = self.packet_loss_percent {
+            writeln!(out, "{} {:.2}%", label("Packet loss:\t"), loss)?;
+        }
+        writeln!(out, "{} {}", label("Overall:\t"), self.score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::{ConnectionMeta, ServerLocation};
+    use crate::scoring::{ConnectionMetrics, ScoringThresholds};
+
+    fn human(report: &impl CommandReport) -> String {
+        colored::control::set_override(false);
+        let mut out = Vec::new();
+        write_report(report, false, false, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_write_report_json() {
+        let metrics = ConnectionMetrics::new(500.0, 100.0, 10.0, 1.0);
+        let scores =
+            AimScoresOutput::score(&metrics, &ScoringThresholds::default());
+
+        let mut out = Vec::new();
+        write_report(&scores, true, false, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.ends_with("}\n"));
+        assert_eq!(text.lines().count(), 1);
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(json["streaming"], "great");
+
+        let mut out = Vec::new();
+        write_report(&scores, true, true, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().lines().count() > 1);
+    }
+
+    #[test]
+    fn test_scores_report() {
+        let metrics = ConnectionMetrics::new(500.0, 100.0, 10.0, 1.0);
+        let scores =
+            AimScoresOutput::score(&metrics, &ScoringThresholds::default());
+
+        let text = human(&scores);
+        assert!(text.contains("Streaming:\t great"));
+        assert!(text.contains("Grade:\t\t A ("));
+    }
+
+    #[test]
+    fn test_egress_report() {
+        let egress = EgressResults {
+            connection: ConnectionMeta::new(
+                "203.0.113.7".to_string(),
+                "US".to_string(),
+                "Example ISP".to_string(),
+                64500,
+            ),
+            city: "Austin".to_string(),
+            region: String::new(),
+            server: ServerLocation::new(
+                "Dallas".to_string(),
+                "DFW".to_string(),
+            ),
+        };
+
+        let text = human(&egress);
+        assert!(text.contains("203.0.113.7"));
+        assert!(text.contains("Example ISP (AS64500)"));
+        assert!(text.contains("Austin, US\n"));
+        assert!(text.contains("Dallas (DFW)"));
+        assert!(!text.contains("Proxy"));
+    }
+
+    #[test]
+    fn test_share_summary_report() {
+        let summary = ShareSummary {
+            timestamp: chrono::DateTime::from_timestamp(1_790_000_000, 0)
+                .unwrap(),
+            server: "DFW".to_string(),
+            country: "US".to_string(),
+            download_mbps: 95.5,
+            upload_mbps: 20.25,
+            latency_ms: 12.0,
+            jitter_ms: None,
+            packet_loss_percent: Some(0.5),
+            score: "good".to_string(),
+        };
+
+        let text = human(&summary);
+        assert!(text.contains("via DFW (US)"));
+        assert!(text.contains("95.50 Mbps"));
+        assert!(text.contains("12.00 ms\n"));
+        assert!(text.contains("0.50%"));
+        assert!(text.contains("good"));
+    }
+}
@@ -0,0 +1,405 @@
+//! HTML reporter.
+//!
+//! Writes one self-contained page (styles and scripts inline, no external
+//! assets) that can be attached to a support case as a single file. The
+//! per-size figures are embedded as JSON and drawn by a small inline
+//! script; the same figures are in plain tables for readers without
+//! scripts.
+
+use super::human::format_size_label;
+use super::Reporter;
+use crate::results::{BandwidthResults, SpeedTestResults};
+use serde_json::json;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::io::Write;
+
+const STYLE: &str = "\
+body{font-family:system-ui,sans-serif;max-width:48rem;margin:2rem auto;\
+padding:0 1rem;color:#222;background:#fafafa}\
+h1{font-size:1.4rem}h2{font-size:1.1rem;margin-top:2rem}\
+table{border-collapse:collapse}td,th{padding:.2rem 1rem .2rem 0;\
+text-align:left}td.value{font-weight:600}.muted{color:#777}\
+.cards{display:flex;flex-wrap:wrap;gap:.75rem}\
+.card{border-radius:.5rem;padding:.75rem 1rem;min-width:7rem;color:#fff}\
+.card b{display:block;font-size:1.3rem}\
+.great{background:#1e8449}.good{background:#52be80}\
+.average{background:#d4ac0d}.poor{background:#c0392b}\
+.grade{background:#34495e}canvas{width:100%;height:220px}";
+
+/// Draws the embedded `DATA` into the page's canvases: a bar chart of
+/// speed by size for each direction and a line chart of loaded latency
+/// through the size blocks.
+const SCRIPT: &str = r##"
+function chart(id, series, unit, bars, baseline) {
+  var canvas = document.getElementById(id);
+  if (!canvas) return;
+  var w = canvas.width = canvas.clientWidth, h = canvas.height = 220;
+  var ctx = canvas.getContext("2d"), pad = 40;
+  var points = series.reduce(function (a, s) { return a.concat(s.values); }, []);
+  var max = Math.max.apply(null, points.concat(baseline || 0)) * 1.1 || 1;
+  var labels = series[0].labels, n = labels.length;
+  var step = (w - pad * 2) / Math.max(n, 1);
+  var y = function (v) { return h - pad - v / max * (h - pad * 2); };
+  ctx.font = "11px system-ui"; ctx.fillStyle = "#777";
+  ctx.fillText(max.toFixed(0) + " " + unit, 0, pad - 8);
+  labels.forEach(function (l, i) {
+    ctx.fillText(l, pad + i * step + 2, h - pad + 14);
+  });
+  if (baseline) {
+    ctx.strokeStyle = "#aaa"; ctx.setLineDash([4, 4]); ctx.beginPath();
+    ctx.moveTo(pad, y(baseline)); ctx.lineTo(w - pad, y(baseline));
+    ctx.stroke(); ctx.setLineDash([]);
+    ctx.fillText("idle", w - pad + 4, y(baseline) + 4);
+  }
+  series.forEach(function (s, k) {
+    ctx.fillStyle = ctx.strokeStyle = s.color;
+    ctx.fillText(s.name, pad + k * 90, 12);
+    if (bars) {
+      var bw = step * 0.7 / series.length;
+      s.values.forEach(function (v, i) {
+        ctx.fillRect(pad + i * step + k * bw, y(v), bw - 2, h - pad - y(v));
+      });
+      return;
+    }
+    ctx.lineWidth = 2; ctx.beginPath();
+    s.values.forEach(function (v, i) {
+      var x = pad + (i + 0.5) * step;
+      if (v === null) return;
+      i ? ctx.lineTo(x, y(v)) : ctx.moveTo(x, y(v));
+      ctx.fillRect(x - 2, y(v) - 2, 4, 4);
+    });
+    ctx.stroke();
+  });
+}
+DATA.speed.forEach(function (d) {
+  chart("speed-" + d.name.toLowerCase(), [d], "Mbps", true);
+});
+var loaded = DATA.loaded.filter(function (s) {
+  return s.values.some(function (v) { return v !== null; });
+});
+if (loaded.length) chart("loaded", loaded, "ms", false, DATA.idle_ms);
+"##;
+
+/// Chart colors for download and upload.
+const DOWNLOAD_COLOR: &str = "#0a7ea4";
+const UPLOAD_COLOR: &str = "#7a4fd0";
+
+/// Writes the results as a standalone HTML page with charts of per-size
+/// speed and loaded latency, and cards for the quality scores.
+pub struct HtmlReporter;
+
+impl Reporter for HtmlReporter {
+    fn name(&self) -> &str {
+        "html"
+    }
+
+    fn report(
+        &self,
+        results: &SpeedTestResults,
+        out: &mut dyn Write,
+    ) -> Result<(), Box<dyn Error>> {
+        out.write_all(render(results).as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Render the whole page.
+fn render(results: &SpeedTestResults) -> String {
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\"><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width\">\
+         <title>cloud-speed report</title><style>{}</style></head><body>\
+         <h1>cloud-speed report</h1>\
+         <p class=\"muted\">{} via {} ({})</p>",
+        STYLE,
+        results.timestamp.format("%Y-%m-%d %H:%M UTC"),
+        escape(&results.server.city),
+        escape(&results.server.iata)
+    );
+
+    summary_section(&mut html, results);
+    scores_section(&mut html, results);
+
+    let directions = [
+        ("Download", results.download.as_ref(), DOWNLOAD_COLOR),
+        ("Upload", results.upload.as_ref(), UPLOAD_COLOR),
+    ];
+    for (name, bandwidth, _) in directions {
+        if let Some(bandwidth) = bandwidth {
+            direction_section(&mut html, name, bandwidth);
+        }
+    }
+    let loaded_measured = directions.iter().any(|(_, bandwidth, _)| {
+        bandwidth.is_some_and(|b| {
+            b.measurements.iter().any(|m| m.loaded_latency_ms.is_some())
+        })
+    });
+    if loaded_measured {
+        html.push_str(
+            "<h2>Loaded latency</h2><p class=\"muted\">Median latency \
+             while each size ran, smallest (first) to largest</p>\
+             <canvas id=\"loaded\"></canvas>",
+        );
+    }
+
+    metadata_section(&mut html, results);
+
+    // Figures for the charts; "</" is escaped so no value can end the
+    // script early
+    let data = json!({
+        "idle_ms": results.latency.idle_ms,
+        "speed": directions
+            .iter()
+            .filter_map(|(name, bandwidth, color)| {
+                let measurements = &(*bandwidth)?.measurements;
+                Some(json!({
+                    "name": name,
+                    "color": color,
+                    "labels": labels(measurements),
+                    "values": measurements
+                        .iter()
+                        .map(|m| m.speed_mbps)
+                        .collect::<Vec<_>>(),
+                }))
+            })
+            .collect::<Vec<_>>(),
+        "loaded": directions
+            .iter()
+            .filter_map(|(name, bandwidth, color)| {
+                let measurements = &(*bandwidth)?.measurements;
+                Some(json!({
+                    "name": format!("{} loaded", name),
+                    "color": color,
+                    "labels": labels(measurements),
+                    "values": measurements
+                        .iter()
+                        .map(|m| m.loaded_latency_ms)
+                        .collect::<Vec<_>>(),
+                }))
+            })
+            .collect::<Vec<_>>(),
+    });
+    let _ = writeln!(
+        html,
+        "<script>var DATA = {};{}</script></body></html>",
+        data.to_string().replace("</", "<\\/"),
+        SCRIPT
+    );
+    html
+}
+
+/// Headline numbers.
+fn summary_section(html: &mut String, results: &SpeedTestResults) {
+    let latency = &results.latency;
+    html.push_str("<h2>Summary</h2><table>");
+    if let Some(download) = &results.download {
+        row(html, "Download", format!("{:.2} Mbps", download.speed_mbps));
+    }
+    if let Some(upload) = &results.upload {
+        row(html, "Upload", format!("{:.2} Mbps", upload.speed_mbps));
+    }
+    row(html, "Latency", format!("{:.2} ms", latency.idle_ms));
+    if let Some(jitter) = latency.idle_jitter_ms {
+        row(html, "Jitter", format!("{:.2} ms", jitter));
+    }
+    if let Some(loaded) = latency.loaded_down_ms {
+        row(html, "Loaded latency (down)", format!("{:.2} ms", loaded));
+    }
+    if let Some(loaded) = latency.loaded_up_ms {
+        row(html, "Loaded latency (up)", format!("{:.2} ms", loaded));
+    }
+    if let Some(loss) = &results.packet_loss {
+        row(html, "Packet loss", format!("{:.2}%", loss.percent));
+    }
+    html.push_str("</table>");
+}
+
+/// A card per quality score, colored by the score, and one for the
+/// grade.
+fn scores_section(html: &mut String, results: &SpeedTestResults) {
+    let Some(scores) = &results.scores else {
+        return;
+    };
+    html.push_str("<h2>Quality scores</h2><div class=\"cards\">");
+    if let Some(grade) = &scores.grade {
+        let _ = write!(
+            html,
+            "<div class=\"card grade\" title=\"{}\">Grade<b>{}</b></div>",
+            escape(&grade.explanation),
+            grade.grade
+        );
+    }
+    for (label, score) in [
+        ("Streaming", &scores.streaming),
+        ("Gaming", &scores.gaming),
+        ("Video calls", &scores.video_conferencing),
+        ("Overall", &scores.overall),
+    ] {
+        let score = escape(score);
+        let _ = write!(
+            html,
+            "<div class=\"card {}\">{}<b>{}</b></div>",
+            score, label, score
+        );
+    }
+    html.push_str("</div>");
+}
+
+/// Chart and table of one direction's per-size speeds.
+fn direction_section(
+    html: &mut String,
+    name: &str,
+    bandwidth: &BandwidthResults,
+) {
+    let _ = write!(
+        html,
+        "<h2>{} by size</h2><p class=\"muted\">{:.2} Mbps overall</p>",
+        name, bandwidth.speed_mbps
+    );
+    if bandwidth.measurements.is_empty() {
+        return;
+    }
+    let _ = write!(
+        html,
+        "<canvas id=\"speed-{}\"></canvas><table><tr><th>Size</th>\
+         <th>Speed</th><th>Samples</th><th>Loaded latency</th></tr>",
+        name.to_lowercase()
+    );
+    for measurement in &bandwidth.measurements {
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td class=\"value\">{:.2} Mbps</td><td>{}</td>\
+             <td>{}</td></tr>",
+            format_size_label(measurement.bytes),
+            measurement.speed_mbps,
+            measurement.count,
+            measurement
+                .loaded_latency_ms
+                .map_or_else(|| "-".to_string(), |l| format!("{:.1} ms", l))
+        );
+    }
+    html.push_str("</table>");
+}
+
+/// Where the test ran from, for whoever reads the report.
+fn metadata_section(html: &mut String, results: &SpeedTestResults) {
+    let connection = &results.connection;
+    html.push_str("<h2>Details</h2><table>");
+    if let Some(isp) = &connection.isp {
+        row(html, "ISP", escape(isp));
+    }
+    if let Some(asn) = connection.asn {
+        row(html, "ASN", format!("AS{}", asn));
+    }
+    row(
+        html,
+        "Client",
+        format!(
+            "{} ({})",
+            escape(&connection.ip),
+            escape(&connection.country)
+        ),
+    );
+    if let Some(ip) = &results.server.ip {
+        row(html, "Server address", escape(ip));
+    }
+    if let Some(meta) = &results.meta {
+        row(
+            html,
+            "Host",
+            format!("{} {}", escape(&meta.os), escape(&meta.arch)),
+        );
+    }
+    if let Some(meas_id) = &results.meas_id {
+        row(html, "Measurement ID", escape(meas_id));
+    }
+    html.push_str("</table>");
+}
+
+/// Size labels of `measurements`, e.g. "1MB".
+fn labels(measurements: &[crate::results::SizeMeasurement]) -> Vec<String> {
+    measurements.iter().map(|m| format_size_label(m.bytes)).collect()
+}
+
+/// A table row with a label and an already escaped value.
+fn row(html: &mut String, label: &str, value: String) {
+    let _ = write!(
+        html,
+        "<tr><td>{}</td><td class=\"value\">{}</td></tr>",
+        label, value
+    );
+}
+
+/// Escape text for HTML.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cloudflare::tests::engine::PhaseSelection;
+    use crate::reporters::tests::sample_results;
+
+    #[test]
+    fn test_html_reporter_output() {
+        let mut out = Vec::new();
+        HtmlReporter.report(&sample_results(), &mut out).unwrap();
+        let html = String::from_utf8(out).unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.ends_with("</html>\n"));
+        assert!(!html.contains("src=\"http"));
+        assert!(html.contains("<canvas id=\"speed-download\">"));
+        assert!(html.contains("<div class=\"card poor\">Video calls"));
+        assert!(html.contains("\"labels\":[\"1MB\"]"));
+        assert!(html.contains("\"values\":[94.0]"));
+        assert!(!html.contains("id=\"loaded\""));
+    }
+
+    #[test]
+    fn test_html_reporter_charts_loaded_latency() {
+        let mut results = sample_results();
+        results.download.as_mut().unwrap().measurements[0].loaded_latency_ms =
+            Some(48.0);
+
+        let html = render(&results);
+        assert!(html.contains("<canvas id=\"loaded\">"));
+        assert!(html.contains("\"values\":[48.0]"));
+    }
+
+    #[test]
+    fn test_html_reporter_escapes_text() {
+        let mut results = sample_results();
+        results.connection.isp = Some("<b>ISP</b>".to_string());
+        results.server.city = "</script>".to_string();
+
+        let html = render(&results);
+        assert!(html.contains("&lt;b&gt;ISP&lt;/b&gt;"));
+        assert!(!html.contains("<b>ISP"));
+        assert_eq!(html.matches("</script>").count(), 1);
+    }
+
+    #[test]
+    fn test_html_reporter_skips_unmeasured_phases() {
+        let results = sample_results().with_phases(PhaseSelection::Latency);
+        let html = render(&results);
+        assert!(!html.contains("Download by size"));
+        assert!(!html.contains("Quality scores"));
+        assert!(html.contains("\"speed\":[]"));
+    }
+}
@@ -0,0 +1,1519 @@
+//! Human-readable reporter.
+
+use super::Reporter;
+use crate::cloudflare::tests::happy_eyeballs::Advisory;
+use crate::results::{
+    estimates, megabytes_per_sec, BandwidthResults, SetupResults,
+    SizeMeasurement, SpeedTestResults,
+};
+use crate::scoring::Grade;
+use colored::Colorize;
+use std::error::Error;
+use std::io::Write;
+use std::time::Duration;
+
+/// Writes a colored, human-readable summary of the results.
+pub struct HumanReporter {
+    /// Show transfer-time estimates below the headline speeds
+    estimates: bool,
+}
+
+impl HumanReporter {
+    /// Create a human reporter.
+    pub fn new(estimates: bool) -> Self {
+        Self { estimates }
+    }
+}
+
+impl Reporter for HumanReporter {
+    fn name(&self) -> &str {
+        "human"
+    }
+
+    fn is_machine_readable(&self) -> bool {
+        false
+    }
+
+    fn report(
+        &self,
+        results: &SpeedTestResults,
+        out: &mut dyn Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let latency = &results.latency;
+        let download = results.download.as_ref();
+        let upload = results.upload.as_ref();
+
+        if results.meta.as_ref().is_some_and(|m| m.simulated) {
+            writeln!(
+                out,
+                "{}\n",
+                "Note: these results were simulated (--simulate); nothing was \
+                 measured."
+                    .yellow()
+            )?;
+        }
+
+        if results.meta.as_ref().is_some_and(|m| m.captive_portal_suspected) {
+            writeln!(
+                out,
+                "{}\n",
+                "Warning: a captive portal or intercepting proxy was \
+                 detected; results may be unreliable."
+                    .yellow()
+            )?;
+        }
+
+        if results.meta.as_ref().is_some_and(|m| m.warp) {
+            writeln!(
+                out,
+                "{}\n",
+                "Note: traffic left through Cloudflare WARP; results describe \
+                 WARP, not your ISP."
+                    .yellow()
+            )?;
+        } else if let Some(meta) =
+            results.meta.as_ref().filter(|m| m.vpn_suspected)
+        {
+            writeln!(
+                out,
+                "{}\n",
+                format!(
+                    "Note: a VPN or tunnel appears to be in use ({}); \
+                     results describe the tunnel, not the local link.",
+                    meta.vpn_signals.join(", ")
+                )
+                .yellow()
+            )?;
+        }
+
+        if let Some(step_ms) =
+            results.meta.as_ref().and_then(|m| m.clock_step_ms)
+        {
+            writeln!(
+                out,
+                "{}\n",
+                format!(
+                    "Note: the system clock was stepped by {:.1}s during the \
+                     test; measurements use a monotonic clock, but the \
+                     result timestamp may be off.",
+                    step_ms as f64 / 1000.0
+                )
+                .yellow()
+            )?;
+        }
+
+        if let Some(rate_limit) =
+            results.meta.as_ref().and_then(|m| m.rate_limit.as_ref())
+        {
+            writeln!(
+                out,
+                "{}\n",
+                format!(
+                    "Note: the server rate limited {} requests; the test \
+                     waited {:.1}s for the limit to lift.",
+                    rate_limit.responses,
+                    rate_limit.waited_ms / 1000.0
+                )
+                .yellow()
+            )?;
+        }
+
+        if let Some(meta) = results.meta.as_ref().filter(|m| m.network_changed)
+        {
+            let restarted =
+                meta.network_changes.iter().all(|change| change.restarted);
+            writeln!(
+                out,
+                "{}\n",
+                format!(
+                    "Note: the network changed {} time(s) during the test; {}",
+                    meta.network_changes.len(),
+                    if restarted {
+                        "the affected measurements were run again."
+                    } else {
+                        "results may mix two networks."
+                    }
+                )
+                .yellow()
+            )?;
+        }
+
+        // Latency section
+        writeln!(
+            out,
+            "{} {}",
+            "Latency:\t".bold().white(),
+            format!("{:.2} ms", latency.idle_ms).bright_red()
+        )?;
+
+        writeln!(
+            out,
+            "{} {}",
+            "Jitter:\t\t".bold().white(),
+            match latency.idle_jitter_ms {
+                Some(j) => format!("{:.2} ms", j).bright_red(),
+                None => "N/A".bright_red(),
+            }
+        )?;
+
+        // Loaded latency (if available)
+        if let Some(loaded_down) = latency.loaded_down_ms {
+            writeln!(
+                out,
+                "{} {}",
+                "Loaded (down):\t".bold().white(),
+                format!("{:.2} ms", loaded_down).bright_red()
+            )?;
+        }
+
+        if let Some(loaded_up) = latency.loaded_up_ms {
+            writeln!(
+                out,
+                "{} {}",
+                "Loaded (up):\t".bold().white(),
+                format!("{:.2} ms", loaded_up).bright_red()
+            )?;
+        }
+
+        writeln!(out)?;
+
+        if let Some(download) = download {
+            // Download speeds by size
+            for measurement in &download.measurements {
+                let size_label = format_size_label(measurement.bytes);
+                writeln!(
+                    out,
+                    "{} {}{}{}",
+                    format!("{} speed:\t", size_label).bold().white(),
+                    format!("{:.2} Mbps", measurement.speed_mbps).yellow(),
+                    loaded_note(measurement).dimmed(),
+                    retry_note(measurement).dimmed()
+                )?;
+            }
+
+            // Final download speed
+            writeln!(
+                out,
+                "{} {}{}",
+                "Download speed:\t".bold().white(),
+                format!("{:.2} Mbps", download.speed_mbps).bright_cyan(),
+                speed_breakdown(download)
+            )?;
+
+            if upload.is_some() {
+                writeln!(out)?;
+            }
+        }
+
+        if let Some(upload) = upload {
+            // Upload speeds by size
+            for measurement in &upload.measurements {
+                let size_label = format_size_label(measurement.bytes);
+                writeln!(
+                    out,
+                    "{} {}{}{}",
+                    format!("{} up:\t", size_label).bold().white(),
+                    format!("{:.2} Mbps", measurement.speed_mbps).yellow(),
+                    loaded_note(measurement).dimmed(),
+                    retry_note(measurement).dimmed()
+                )?;
+            }
+
+            // Final upload speed
+            writeln!(
+                out,
+                "{} {}{}",
+                "Upload speed:\t".bold().white(),
+                format!("{:.2} Mbps", upload.speed_mbps).bright_cyan(),
+                speed_breakdown(upload)
+            )?;
+        }
+
+        // Retransmissions behind the measured speeds
+        let directions = [("down", download), ("up", upload)];
+        for (direction, bandwidth) in directions
+            .iter()
+            .filter_map(|(direction, b)| Some((direction, (*b)?)))
+        {
+            if let Some(retransmission) = &bandwidth.retransmission {
+                let ratio = format!(
+                    "{:.2}% {} ({:.2} Mbps on the wire)",
+                    retransmission.percent(),
+                    direction,
+                    retransmission.wire_mbps
+                );
+                writeln!(
+                    out,
+                    "{} {}",
+                    "Retransmits:\t".bold().white(),
+                    if retransmission.high {
+                        ratio.yellow()
+                    } else {
+                        ratio.normal()
+                    }
+                )?;
+                if retransmission.high {
+                    writeln!(
+                        out,
+                        "  A lossy link; calls and games may suffer despite \
+                         the speed"
+                    )?;
+                }
+            }
+            if let Some(cpu) = bandwidth.cpu.filter(|cpu| cpu.bound) {
+                writeln!(
+                    out,
+                    "{} {}",
+                    "CPU:\t\t".bold().white(),
+                    format!("{:.0}% of a core {}", cpu.percent(), direction)
+                        .yellow()
+                )?;
+                writeln!(
+                    out,
+                    "  The CPU, not the link, may have capped this speed; \
+                     see --tls-cipher"
+                )?;
+            }
+        }
+
+        if download.is_some() || upload.is_some() {
+            writeln!(out)?;
+        }
+
+        // What the download speed means in practice
+        let times =
+            download.map_or_else(Vec::new, |d| estimates(d.speed_mbps));
+        if self.estimates && !times.is_empty() {
+            let throughput: Vec<_> = directions
+                .iter()
+                .filter_map(|(direction, b)| {
+                    Some(format!(
+                        "{:.2} MB/s {}",
+                        megabytes_per_sec((*b)?.speed_mbps),
+                        direction
+                    ))
+                })
+                .collect();
+            writeln!(
+                out,
+                "{} {}",
+                "Throughput:\t".bold().white(),
+                throughput.join(", ").normal()
+            )?;
+            for estimate in &times {
+                writeln!(
+                    out,
+                    "  {} {}",
+                    format!("{}:\t", estimate.label).white(),
+                    format_eta(estimate.duration)
+                )?;
+            }
+            writeln!(out)?;
+        }
+
+        // Packet loss (if available)
+        if let Some(pl) = &results.packet_loss {
+            let provider = pl
+                .provider
+                .map(|provider| format!(" (via {})", provider))
+                .unwrap_or_default();
+            writeln!(
+                out,
+                "{} {}{}",
+                "Packet loss:\t".bold().white(),
+                format!("{:.2}%", pl.percent).bright_magenta(),
+                provider.dimmed()
+            )?;
+            if pl.reordered > 0 || pl.duplicates > 0 {
+                writeln!(
+                    out,
+                    "  {} {:.2}% reordered, {} duplicate{}",
+                    "Order:".white(),
+                    pl.reorder_ratio * 100.0,
+                    pl.duplicates,
+                    if pl.duplicates == 1 { "" } else { "s" }
+                )?;
+            }
+            if !pl.batches.is_empty() {
+                let batches: Vec<String> = pl
+                    .batches
+                    .iter()
+                    .map(|batch| format!("{:.0}%", batch.ratio * 100.0))
+                    .collect();
+                writeln!(
+                    out,
+                    "  {} {}",
+                    "Per batch:".white(),
+                    batches.join(" ")
+                )?;
+            }
+            writeln!(out)?;
+        }
+
+        // Server responsiveness
+        if let Some(responsiveness) = &results.responsiveness {
+            let slow = responsiveness.is_slow(latency.idle_ms);
+            let ttfb = format!(
+                "{:.2} ms (p95 {:.2} ms)",
+                responsiveness.ttfb_median_ms, responsiveness.ttfb_p95_ms
+            );
+            writeln!(
+                out,
+                "{} {}",
+                "Server TTFB:\t".bold().white(),
+                if slow { ttfb.yellow() } else { ttfb.normal() }
+            )?;
+            if let Some(breakdown) = &responsiveness.breakdown {
+                writeln!(
+                    out,
+                    "  Network {:.2} ms + edge processing {:.2} ms",
+                    breakdown.network_median_ms, breakdown.server_median_ms
+                )?;
+            }
+            if slow {
+                writeln!(
+                    out,
+                    "  {}; slow responses point at the server or CDN \
+                     rather than your connection",
+                    if responsiveness.breakdown.is_some() {
+                        "The edge is slow to process requests"
+                    } else {
+                        "Well above the idle latency"
+                    }
+                )?;
+            }
+            writeln!(out)?;
+        }
+
+        // Name server and its answer (with --resolver)
+        if let Some((dns, resolver)) = results
+            .dns
+            .as_ref()
+            .and_then(|dns| Some((dns, dns.resolver.as_ref()?)))
+        {
+            let addresses: Vec<_> =
+                dns.addresses.iter().map(|ip| ip.to_string()).collect();
+            writeln!(
+                out,
+                "{} {} ({:.2} ms)",
+                "Resolver:\t".bold().white(),
+                resolver,
+                dns.lookup_ms
+            )?;
+            writeln!(out, "  {} -> {}", dns.host, addresses.join(", "))?;
+            writeln!(out)?;
+        }
+
+        // IPv6 trouble found by the dual-stack races
+        if let Some(dual_stack) = &results.dual_stack {
+            match dual_stack.advisory {
+                Some(Advisory::Ipv6Broken) => {
+                    writeln!(
+                        out,
+                        "{} {}",
+                        "IPv6:\t\t".bold().white(),
+                        "not connecting".yellow()
+                    )?;
+                    writeln!(
+                        out,
+                        "  No IPv6 connection in {} attempts, so the test \
+                         ran over IPv4; apps that try IPv6 first may stall \
+                         before falling back",
+                        dual_stack.races
+                    )?;
+                    writeln!(out)?;
+                }
+                Some(Advisory::Ipv6Slower) => {
+                    let connect = |ms: Option<f64>| match ms {
+                        Some(ms) => format!("{:.1} ms", ms),
+                        None => "N/A".to_string(),
+                    };
+                    writeln!(
+                        out,
+                        "{} {}",
+                        "IPv6:\t\t".bold().white(),
+                        format!(
+                            "slower than IPv4 ({} vs {} to connect)",
+                            connect(dual_stack.ipv6_connect_ms),
+                            connect(dual_stack.ipv4_connect_ms)
+                        )
+                        .yellow()
+                    )?;
+                    writeln!(
+                        out,
+                        "  Apps that prefer IPv6 take the slower path; check \
+                         your router's or ISP's IPv6 routing"
+                    )?;
+                    writeln!(out)?;
+                }
+                None => {}
+            }
+        }
+
+        // Path MTU (with --mtu)
+        if let Some(path_mtu) = &results.path_mtu {
+            let bytes = format!("{} bytes", path_mtu.bytes);
+            writeln!(
+                out,
+                "{} {}",
+                "Path MTU:\t".bold().white(),
+                if path_mtu.is_reduced() {
+                    bytes.yellow()
+                } else {
+                    bytes.normal()
+                }
+            )?;
+            if path_mtu.is_reduced() {
+                writeln!(
+                    out,
+                    "  Below 1500 bytes; if large uploads stall, check \
+                     that ICMP \"fragmentation needed\" is not filtered"
+                )?;
+            }
+            writeln!(out)?;
+        }
+
+        // Cold vs warm connection setup (with --handshake)
+        if let Some(handshake) = &results.handshake {
+            let setup = |setup: &SetupResults| {
+                format!(
+                    "{:.1} ms (DNS {:.1}, TCP {:.1}, TLS {:.1})",
+                    setup.total_ms, setup.dns_ms, setup.tcp_ms, setup.tls_ms
+                )
+            };
+            writeln!(
+                out,
+                "{} {} cold",
+                "Setup:\t\t".bold().white(),
+                setup(&handshake.cold)
+            )?;
+            writeln!(
+                out,
+                "\t\t {} warm, {}",
+                setup(&handshake.warm),
+                if handshake.warm.resumed {
+                    "TLS resumed"
+                } else {
+                    "TLS not resumed"
+                }
+            )?;
+            writeln!(
+                out,
+                "  Caching saves {:.1} ms per new connection",
+                handshake.saved_ms
+            )?;
+            writeln!(out)?;
+        }
+
+        // Both directions at once (with --duplex)
+        if let Some(duplex) = &results.duplex {
+            writeln!(
+                out,
+                "{} {} down, {} up ({:.0}% of one-way)",
+                "Duplex speed:\t".bold().white(),
+                format!("{:.2} Mbps", duplex.download_mbps).bright_cyan(),
+                format!("{:.2} Mbps", duplex.upload_mbps).bright_cyan(),
+                duplex.efficiency * 100.0
+            )?;
+            if let Some(latency_ms) = duplex.latency_ms {
+                writeln!(
+                    out,
+                    "{} {}",
+                    "Loaded (duplex):".bold().white(),
+                    format!("{:.2} ms", latency_ms).bright_red()
+                )?;
+            }
+            writeln!(out)?;
+        }
+
+        // Time added by --nice
+        if let Some(pacing) = &results.pacing {
+            writeln!(
+                out,
+                "{} {}",
+                "Nice mode:\t".bold().white(),
+                format!(
+                    "{:.1} s added by {} pauses",
+                    pacing.paused_ms / 1000.0,
+                    pacing.pauses
+                )
+                .normal()
+            )?;
+            writeln!(out)?;
+        }
+
+        // Allocations per phase (with --detailed and profiling builds)
+        if let Some(profile) = &results.profile {
+            let peak = profile.peak_rss_bytes.map_or_else(
+                || "peak RSS unknown".to_string(),
+                |bytes| format!("peak RSS {:.1} MB", bytes as f64 / 1e6),
+            );
+            writeln!(out, "{} {}", "Memory:\t\t".bold().white(), peak)?;
+            for phase in &profile.phases {
+                writeln!(
+                    out,
+                    "  {:<12} {} allocations ({:.1} MB)",
+                    format!("{:?}:", phase.phase).to_lowercase(),
+                    phase.allocations,
+                    phase.allocated_bytes as f64 / 1e6
+                )?;
+            }
+            writeln!(out)?;
+        }
+
+        // AIM Scores (when both directions were measured)
+        if let Some(aim_scores) = &results.scores {
+            writeln!(out, "{}", "Quality Scores:".bold().white())?;
+            if let Some(grade) = &aim_scores.grade {
+                writeln!(
+                    out,
+                    "  {} {} {}",
+                    "Grade:\t\t".white(),
+                    format_grade(grade.grade),
+                    format!("({})", grade.explanation).dimmed()
+                )?;
+            }
+            writeln!(
+                out,
+                "  {} {}",
+                "Streaming:\t".white(),
+                format_quality_score(&aim_scores.streaming)
+            )?;
+            writeln!(
+                out,
+                "  {} {}",
+                "Gaming:\t\t".white(),
+                format_quality_score(&aim_scores.gaming)
+            )?;
+            writeln!(
+                out,
+                "  {} {}",
+                "Video Calls:\t".white(),
+                format_quality_score(&aim_scores.video_conferencing)
+            )?;
+
+            // Loaded latency against the working latency targets
+            if let Some(check) = &aim_scores.working_latency {
+                let targets = if check.download_target_ms
+                    == check.upload_target_ms
+                {
+                    format!("under {:.0} ms loaded", check.download_target_ms)
+                } else {
+                    format!(
+                        "under {:.0}/{:.0} ms loaded down/up",
+                        check.download_target_ms, check.upload_target_ms
+                    )
+                };
+                writeln!(
+                    out,
+                    "  {} {} {}",
+                    "Working latency:".white(),
+                    if check.pass { "pass".green() } else { "fail".red() },
+                    format!("({})", targets).dimmed()
+                )?;
+            }
+        }
+
+        // Baseline comparison (if requested)
+        if let Some(regression) = &results.regression {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{} {}",
+                "Baseline:\t".bold().white(),
+                format!(
+                    "{} runs over {} days",
+                    regression.baseline_runs, regression.window_days
+                )
+                .normal()
+            )?;
+
+            if regression.metrics.is_empty() {
+                writeln!(out, "  {}", "Not enough history yet".yellow())?;
+            }
+
+            for metric in &regression.metrics {
+                let status = if metric.anomalous {
+                    "ANOMALY".red().bold()
+                } else {
+                    "ok".green()
+                };
+                writeln!(
+                    out,
+                    "  {:<20} {:>10.2} (median {:.2}, IQR {:.2}) {}",
+                    metric.metric,
+                    metric.value,
+                    metric.median,
+                    metric.iqr,
+                    status
+                )?;
+            }
+        }
+
+        // Regional comparison (if requested)
+        if let Some(regional) = &results.regional {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{} {}",
+                "Regional:\t".bold().white(),
+                format!(
+                    "median of {} {} ({})",
+                    regional.scope, regional.region, regional.source
+                )
+                .normal()
+            )?;
+
+            if regional.metrics.is_empty() {
+                writeln!(out, "  {}", "No comparable medians".yellow())?;
+            }
+
+            for metric in &regional.metrics {
+                let status = if metric.better {
+                    "better".green()
+                } else {
+                    "worse".yellow()
+                };
+                writeln!(
+                    out,
+                    "  {:<20} {:>10.2} (median {:.2}, {:.0}%) {}",
+                    metric.metric,
+                    metric.value,
+                    metric.median,
+                    metric.percent_of_median,
+                    status
+                )?;
+            }
+        }
+
+        // For Cloudflare support to find the run's requests
+        if let Some(meas_id) = &results.meas_id {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{}",
+                format!("Measurement ID: {}", meas_id).dimmed()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Format a byte size into a human-readable label.
+pub(super) fn format_size_label(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1_000_000_000 => format!("{}GB", b / 1_000_000_000),
+        b if b >= 1_000_000 => format!("{}MB", b / 1_000_000),
+        b if b >= 1_000 => format!("{}kB", b / 1_000),
+        b => format!("{}B", b),
+    }
+}
+
+/// Format an estimated transfer time, e.g. "1h 6m" or "1m 20s".
+fn format_eta(duration: Duration) -> String {
+    let secs = duration.as_secs_f64().round() as u64;
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s.max(1)),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
+
+/// How a headline speed was aggregated, with the mean and max for
+/// comparison with tools that report those, e.g. " (p90; mean 90.00,
+/// max 99.00)". Empty for results that don't record it.
+fn speed_breakdown(bandwidth: &BandwidthResults) -> String {
+    let Some(aggregation) = bandwidth.aggregation() else {
+        return String::new();
+    };
+    match (bandwidth.mean_mbps, bandwidth.max_mbps) {
+        (Some(mean), Some(max)) => {
+            format!(" ({}; mean {:.2}, max {:.2})", aggregation, mean, max)
+        }
+        _ => format!(" ({})", aggregation),
+    }
+}
+
+/// Latency under load while a size ran, e.g. " at 48.0 ms loaded", so
+/// the size where the queue starts to grow stands out.
+fn loaded_note(measurement: &SizeMeasurement) -> String {
+    match measurement.loaded_latency_ms {
+        Some(latency) => format!(" at {:.1} ms loaded", latency),
+        None => String::new(),
+    }
+}
+
+/// How many iterations of a size needed retries and how many failed
+/// outright, e.g. " (2 retried, 1 failed)". Empty unless `--detailed`
+/// recorded any.
+fn retry_note(measurement: &SizeMeasurement) -> String {
+    if measurement.failures.is_empty() {
+        return String::new();
+    }
+    let failed = measurement.failures.iter().filter(|f| !f.recovered).count();
+    format!(" ({} retried, {} failed)", measurement.failures.len(), failed)
+}
+
+/// Format a quality score with appropriate color.
+///
+/// Scores arrive as the lowercase strings used in [`SpeedTestResults`].
+fn format_quality_score(score: &str) -> colored::ColoredString {
+    match score {
+        "great" => "Great".bright_green(),
+        "good" => "Good".green(),
+        "average" => "Average".yellow(),
+        "poor" => "Poor".red(),
+        other => other.normal(),
+    }
+}
+
+/// Color a letter grade like the quality scores.
+fn format_grade(grade: Grade) -> colored::ColoredString {
+    let letter = grade.to_string();
+    match grade {
+        Grade::A => letter.bright_green().bold(),
+        Grade::B => letter.green().bold(),
+        Grade::C => letter.yellow().bold(),
+        Grade::D | Grade::F => letter.red().bold(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cloudflare::tests::engine::{
+        MeasurementFailure, PhaseSelection,
+    };
+    use crate::reporters::tests::sample_results;
+
+    #[test]
+    fn test_format_size_label() {
+        assert_eq!(format_size_label(100_000), "100kB");
+        assert_eq!(format_size_label(25_000_000), "25MB");
+        assert_eq!(format_size_label(1_000_000_000), "1GB");
+        assert_eq!(format_size_label(512), "512B");
+    }
+
+    #[test]
+    fn test_human_reporter_output() {
+        colored::control::set_override(false);
+
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&sample_results(), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains("Latency:\t 12.50 ms"));
+        assert!(text.contains("Loaded (down):\t 40.00 ms"));
+        assert!(text.contains("1MB speed:\t 94.00 Mbps"));
+        assert!(text.contains("Download speed:\t 95.50 Mbps"));
+        assert!(text.contains("Upload speed:\t 20.25 Mbps"));
+        assert!(text.contains("Streaming:\t Great"));
+        assert!(text.contains("Video Calls:\t Poor"));
+        assert!(!text.contains("Packet loss"));
+        assert!(!text.contains("Baseline"));
+        assert!(!text.contains("captive portal"));
+    }
+
+    #[test]
+    fn test_human_reporter_skips_unmeasured_phases() {
+        colored::control::set_override(false);
+
+        let results = sample_results().with_phases(PhaseSelection::Download);
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Download speed:\t 95.50 Mbps"));
+        assert!(text.contains("Throughput:\t 11.94 MB/s down\n"));
+        assert!(!text.contains("Upload speed"));
+        assert!(!text.contains("Quality Scores"));
+
+        let results = sample_results().with_phases(PhaseSelection::Latency);
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Latency:\t 12.50 ms"));
+        assert!(!text.contains("Download speed"));
+        assert!(!text.contains("Throughput"));
+    }
+
+    #[test]
+    fn test_human_reporter_labels_speed_aggregation() {
+        colored::control::set_override(false);
+
+        let mut results = sample_results();
+        results.download.as_mut().unwrap().percentile = Some(0.9);
+        results.download.as_mut().unwrap().mean_mbps = Some(88.0);
+        results.download.as_mut().unwrap().max_mbps = Some(101.25);
+        results.upload.as_mut().unwrap().percentile = Some(0.95);
+
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains(
+            "Download speed:\t 95.50 Mbps (p90; mean 88.00, max 101.25)"
+        ));
+        assert!(text.contains("Upload speed:\t 20.25 Mbps (p95)\n"));
+    }
+
+    #[test]
+    fn test_human_reporter_notes_retries() {
+        colored::control::set_override(false);
+
+        let mut results = sample_results();
+        let failure = |recovered| MeasurementFailure {
+            iteration: 1,
+            attempts: 2,
+            recovered,
+            errors: Vec::new(),
+        };
+        results.download.as_mut().unwrap().measurements[0].failures =
+            vec![failure(true), failure(false)];
+
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains("1MB speed:\t 94.00 Mbps (2 retried, 1 failed)"));
+    }
+
+    #[test]
+    fn test_human_reporter_shows_loaded_latency_per_size() {
+        colored::control::set_override(false);
+
+        let mut results = sample_results();
+        results.download.as_mut().unwrap().measurements[0].loaded_latency_ms =
+            Some(48.04);
+
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains("1MB speed:\t 94.00 Mbps at 48.0 ms loaded\n"));
+    }
+
+    #[test]
+    fn test_human_reporter_warns_on_captive_portal() {
+        use crate::results::RunMeta;
+
+        colored::control::set_override(false);
+
+        let results = sample_results().with_meta(Some(
+            RunMeta::detect().with_captive_portal_suspected(true),
+        ));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.starts_with("Warning: a captive portal"));
+    }
+
+    #[test]
+    fn test_human_reporter_notes_clock_step() {
+        use crate::results::RunMeta;
+
+        colored::control::set_override(false);
+
+        let results = sample_results().with_meta(Some(
+            RunMeta::detect()
+                .with_clock(Some(0), Some(chrono::TimeDelta::seconds(-90))),
+        ));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.starts_with(
+            "Note: the system clock was stepped by -90.0s during the test"
+        ));
+    }
+
+    #[test]
+    fn test_human_reporter_notes_rate_limit() {
+        use crate::cloudflare::tests::engine::RateLimitSummary;
+        use crate::results::RunMeta;
+
+        colored::control::set_override(false);
+
+        let results = sample_results().with_meta(Some(
+            RunMeta::detect().with_rate_limit(Some(&RateLimitSummary {
+                responses: 3,
+                waited: std::time::Duration::from_millis(12_500),
+            })),
+        ));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.starts_with(
+            "Note: the server rate limited 3 requests; the test waited 12.5s"
+        ));
+    }
+
+    #[test]
+    fn test_human_reporter_notes_network_change() {
+        use crate::cloudflare::tests::engine::NetworkChange;
+        use crate::results::RunMeta;
+        use crate::tui::TestPhase;
+
+        colored::control::set_override(false);
+
+        let change = NetworkChange {
+            phase: TestPhase::Download,
+            from: Some("192.168.1.20".parse().unwrap()),
+            to: Some("10.8.0.2".parse().unwrap()),
+            restarted: false,
+        };
+        let results = sample_results().with_meta(Some(
+            RunMeta::detect().with_network_changes(&[change]),
+        ));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.starts_with(
+            "Note: the network changed 1 time(s) during the test; results \
+             may mix two networks."
+        ));
+    }
+
+    #[test]
+    fn test_human_reporter_notes_vpn() {
+        use crate::results::RunMeta;
+        use crate::vpn::VpnDetection;
+
+        colored::control::set_override(false);
+
+        let results = sample_results().with_meta(Some(
+            RunMeta::detect().with_vpn(VpnDetection {
+                suspected: true,
+                signals: vec![
+                    "default route uses tunnel interface wg0".to_string()
+                ],
+            }),
+        ));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.starts_with(
+            "Note: a VPN or tunnel appears to be in use (default route uses \
+             tunnel interface wg0)"
+        ));
+    }
+
+    #[test]
+    fn test_human_reporter_notes_simulated_results() {
+        use crate::results::RunMeta;
+
+        colored::control::set_override(false);
+
+        let results = sample_results()
+            .with_meta(Some(RunMeta::detect().with_simulated(true)));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.starts_with("Note: these results were simulated"));
+    }
+
+    #[test]
+    fn test_human_reporter_notes_warp_instead_of_vpn() {
+        use crate::results::RunMeta;
+        use crate::vpn::VpnDetection;
+
+        colored::control::set_override(false);
+
+        let results = sample_results().with_meta(Some(
+            RunMeta::detect()
+                .with_vpn(VpnDetection {
+                    suspected: true,
+                    signals: vec!["egress network Cloudflare".to_string()],
+                })
+                .with_warp(true),
+        ));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.starts_with(
+            "Note: traffic left through Cloudflare WARP; results describe \
+             WARP, not your ISP."
+        ));
+        assert!(!text.contains("VPN or tunnel"));
+    }
+
+    #[test]
+    fn test_human_reporter_shows_pacing_cost() {
+        use crate::results::PacingResults;
+
+        colored::control::set_override(false);
+
+        let results = sample_results().with_pacing(Some(PacingResults {
+            pauses: 12,
+            paused_ms: 18_250.0,
+        }));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains("Nice mode:\t 18.2 s added by 12 pauses"));
+    }
+
+    #[test]
+    fn test_human_reporter_shows_profile() {
+        use crate::results::{PhaseAllocationResults, ProfileResults};
+        use crate::tui::TestPhase;
+
+        colored::control::set_override(false);
+
+        let results = sample_results().with_profile(Some(ProfileResults {
+            phases: vec![PhaseAllocationResults {
+                phase: TestPhase::Download,
+                allocations: 5120,
+                allocated_bytes: 2_500_000,
+            }],
+            peak_rss_bytes: Some(24_000_000),
+        }));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains("Memory:\t\t peak RSS 24.0 MB"));
+        assert!(text.contains("  download:    5120 allocations (2.5 MB)"));
+    }
+
+    #[test]
+    fn test_human_reporter_shows_path_mtu() {
+        use crate::results::PathMtuResults;
+
+        colored::control::set_override(false);
+
+        let full = sample_results()
+            .with_path_mtu(Some(PathMtuResults { bytes: 1500, probes: 1 }));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&full, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Path MTU:\t 1500 bytes"));
+        assert!(!text.contains("fragmentation needed"));
+
+        let reduced = sample_results()
+            .with_path_mtu(Some(PathMtuResults { bytes: 1492, probes: 12 }));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&reduced, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Path MTU:\t 1492 bytes"));
+        assert!(text.contains("fragmentation needed"));
+    }
+
+    #[test]
+    fn test_human_reporter_shows_estimates() {
+        colored::control::set_override(false);
+
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&sample_results(), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Throughput:\t 11.94 MB/s down, 2.53 MB/s up"));
+        assert!(text.contains("1 GB file:\t 1m 24s"));
+        assert!(text.contains("50 GB game:\t 1h 9m"));
+
+        let mut out = Vec::new();
+        HumanReporter::new(false).report(&sample_results(), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(!text.contains("Throughput:"));
+        assert!(!text.contains("1 GB file"));
+    }
+
+    #[test]
+    fn test_format_eta() {
+        assert_eq!(format_eta(Duration::from_millis(200)), "1s");
+        assert_eq!(format_eta(Duration::from_secs(80)), "1m 20s");
+        assert_eq!(format_eta(Duration::from_secs(4000)), "1h 6m");
+    }
+
+    #[test]
+    fn test_human_reporter_shows_handshake() {
+        use crate::results::HandshakeResults;
+
+        colored::control::set_override(false);
+
+        let setup = |dns_ms, tls_ms, resumed| SetupResults {
+            dns_ms,
+            tcp_ms: 14.0,
+            tls_ms,
+            total_ms: dns_ms + 14.0 + tls_ms,
+            resumed,
+        };
+        let results =
+            sample_results().with_handshake(Some(HandshakeResults {
+                cold: setup(21.0, 45.0, false),
+                warm: setup(0.1, 15.9, true),
+                warm_rounds: 3,
+                saved_ms: 50.0,
+            }));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains(
+            "Setup:\t\t 80.0 ms (DNS 21.0, TCP 14.0, TLS 45.0) cold"
+        ));
+        assert!(text.contains(
+            "30.0 ms (DNS 0.1, TCP 14.0, TLS 15.9) warm, TLS resumed"
+        ));
+        assert!(text.contains("Caching saves 50.0 ms per new connection"));
+    }
+
+    #[test]
+    fn test_human_reporter_shows_chosen_resolver() {
+        use crate::results::DnsResults;
+
+        colored::control::set_override(false);
+
+        let dns = DnsResults {
+            host: "speed.cloudflare.com".to_string(),
+            addresses: vec![
+                "104.16.0.1".parse().unwrap(),
+                "104.16.0.2".parse().unwrap(),
+            ],
+            resolver: None,
+            lookup_ms: 8.0,
+        };
+        let mut out = Vec::new();
+        HumanReporter::new(true)
+            .report(&sample_results().with_dns(Some(dns.clone())), &mut out)
+            .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(!text.contains("Resolver:"));
+
+        let chosen = DnsResults { resolver: Some("1.1.1.1:53".into()), ..dns };
+        let mut out = Vec::new();
+        HumanReporter::new(true)
+            .report(&sample_results().with_dns(Some(chosen)), &mut out)
+            .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Resolver:\t 1.1.1.1:53 (8.00 ms)"));
+        assert!(
+            text.contains("speed.cloudflare.com -> 104.16.0.1, 104.16.0.2")
+        );
+    }
+
+    #[test]
+    fn test_human_reporter_shows_ipv6_advisory() {
+        use crate::results::DualStackResults;
+
+        colored::control::set_override(false);
+
+        let dual_stack = DualStackResults {
+            winner: "ipv6".to_string(),
+            ipv6_connect_ms: Some(12.0),
+            ipv4_connect_ms: Some(14.0),
+            races: 3,
+            ipv6_wins: 3,
+            ipv6_failures: 0,
+            ipv4_failures: 0,
+            advisory: None,
+        };
+        let report = |dual_stack: DualStackResults| {
+            let mut out = Vec::new();
+            HumanReporter::new(true)
+                .report(
+                    &sample_results().with_dual_stack(Some(dual_stack)),
+                    &mut out,
+                )
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert!(!report(dual_stack.clone()).contains("IPv6:"));
+
+        let slower = report(DualStackResults {
+            winner: "ipv4".to_string(),
+            ipv6_connect_ms: Some(85.0),
+            ipv6_wins: 0,
+            advisory: Some(Advisory::Ipv6Slower),
+            ..dual_stack.clone()
+        });
+        assert!(slower.contains(
+            "IPv6:\t\t slower than IPv4 (85.0 ms vs 14.0 ms to connect)"
+        ));
+
+        let broken = report(DualStackResults {
+            winner: "ipv4".to_string(),
+            ipv6_connect_ms: None,
+            ipv6_wins: 0,
+            ipv6_failures: 3,
+            advisory: Some(Advisory::Ipv6Broken),
+            ..dual_stack
+        });
+        assert!(broken.contains("IPv6:\t\t not connecting"));
+        assert!(broken.contains("No IPv6 connection in 3 attempts"));
+    }
+
+    #[test]
+    fn test_human_reporter_flags_retransmits() {
+        use crate::results::RetransmissionResults;
+
+        colored::control::set_override(false);
+
+        let mut results = sample_results();
+        results.upload.as_mut().unwrap().retransmission =
+            Some(RetransmissionResults {
+                ratio: 0.05,
+                goodput_mbps: 19.0,
+                wire_mbps: 20.0,
+                high: true,
+            });
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text
+            .contains("Retransmits:\t 5.00% up (20.00 Mbps on the wire)\n"));
+        assert!(text.contains("A lossy link"));
+    }
+
+    #[test]
+    fn test_human_reporter_flags_cpu_bound_transfers() {
+        use crate::results::CpuResults;
+
+        colored::control::set_override(false);
+
+        let mut results = sample_results();
+        results.download.as_mut().unwrap().cpu =
+            Some(CpuResults { core_utilization: 0.97, bound: true });
+        results.upload.as_mut().unwrap().cpu =
+            Some(CpuResults { core_utilization: 0.2, bound: false });
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("CPU:\t\t 97% of a core down\n"));
+        assert!(!text.contains("of a core up"));
+        assert!(text.contains("may have capped this speed"));
+    }
+
+    #[test]
+    fn test_human_reporter_shows_working_latency() {
+        use crate::scoring::WorkingLatencyCheck;
+
+        colored::control::set_override(false);
+
+        let mut results = sample_results();
+        results.scores.as_mut().unwrap().working_latency =
+            Some(WorkingLatencyCheck {
+                download_target_ms: 100.0,
+                upload_target_ms: 100.0,
+                download_pass: Some(true),
+                upload_pass: Some(false),
+                pass: false,
+            });
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Working latency: fail (under 100 ms loaded)"));
+    }
+
+    #[test]
+    fn test_human_reporter_shows_grade() {
+        use crate::scoring::GradeSummary;
+
+        colored::control::set_override(false);
+
+        let mut results = sample_results();
+        results.scores.as_mut().unwrap().grade = Some(GradeSummary {
+            grade: Grade::C,
+            explanation: "Held back by video calls (poor)".to_string(),
+        });
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("C (Held back by video calls (poor))"));
+    }
+
+    #[test]
+    fn test_human_reporter_shows_meas_id() {
+        colored::control::set_override(false);
+
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&sample_results(), &mut out).unwrap();
+        assert!(!String::from_utf8(out).unwrap().contains("Measurement ID"));
+
+        let results = sample_results()
+            .with_meas_id(Some("4815162342000001".to_string()));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.ends_with("\nMeasurement ID: 4815162342000001\n"));
+    }
+
+    #[test]
+    fn test_human_reporter_flags_slow_ttfb() {
+        use crate::results::ResponsivenessResults;
+
+        colored::control::set_override(false);
+
+        let ttfb = |median_ms| ResponsivenessResults {
+            ttfb_median_ms: median_ms,
+            ttfb_p95_ms: median_ms * 2.0,
+            samples: 40,
+            breakdown: None,
+        };
+        let idle_ms = sample_results().latency.idle_ms;
+
+        let results =
+            sample_results().with_responsiveness(Some(ttfb(idle_ms + 5.0)));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Server TTFB:\t"));
+        assert!(!text.contains("server or CDN"));
+
+        let results =
+            sample_results().with_responsiveness(Some(ttfb(idle_ms + 250.0)));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("server or CDN"));
+    }
+
+    #[test]
+    fn test_human_reporter_shows_rtt_breakdown() {
+        use crate::results::{ResponsivenessResults, RttBreakdownResults};
+
+        colored::control::set_override(false);
+
+        let ttfb = |network_ms: f64, server_ms: f64| ResponsivenessResults {
+            ttfb_median_ms: network_ms + server_ms,
+            ttfb_p95_ms: (network_ms + server_ms) * 2.0,
+            samples: 40,
+            breakdown: Some(RttBreakdownResults {
+                network_median_ms: network_ms,
+                server_median_ms: server_ms,
+                samples: 20,
+            }),
+        };
+
+        // A slow path isn't blamed on the edge
+        let results =
+            sample_results().with_responsiveness(Some(ttfb(300.0, 4.0)));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("  Network 300.00 ms + edge processing 4.00 ms"));
+        assert!(!text.contains("server or CDN"));
+
+        let results =
+            sample_results().with_responsiveness(Some(ttfb(20.0, 180.0)));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("The edge is slow to process requests"));
+    }
+
+    #[test]
+    fn test_human_reporter_shows_duplex() {
+        use crate::results::DuplexResults;
+
+        colored::control::set_override(false);
+
+        let results = sample_results().with_duplex(Some(DuplexResults {
+            download_mbps: 420.0,
+            upload_mbps: 35.5,
+            latency_ms: Some(88.0),
+            jitter_ms: Some(12.0),
+            efficiency: 0.62,
+        }));
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains(
+            "Duplex speed:\t 420.00 Mbps down, 35.50 Mbps up (62% of one-way)"
+        ));
+        assert!(text.contains("Loaded (duplex): 88.00 ms"));
+    }
+
+    #[test]
+    fn test_human_reporter_marks_anomalies() {
+        use crate::history::regression::MetricBaseline;
+        use crate::history::RegressionResults;
+
+        colored::control::set_override(false);
+
+        let results =
+            sample_results().with_regression(Some(RegressionResults {
+                window_days: 30,
+                baseline_runs: 12,
+                metrics: vec![MetricBaseline {
+                    metric: "download_mbps".into(),
+                    value: 40.0,
+                    median: 95.0,
+                    iqr: 4.0,
+                    samples: 12,
+                    anomalous: true,
+                }],
+            }));
+
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains("12 runs over 30 days"));
+        assert!(text.contains("download_mbps"));
+        assert!(text.contains("ANOMALY"));
+    }
+
+    #[test]
+    fn test_human_reporter_packet_loss_details() {
+        use crate::cloudflare::tests::packet_loss::BatchLoss;
+        use crate::results::PacketLossResults;
+
+        colored::control::set_override(false);
+
+        let mut results = sample_results();
+        results.packet_loss = Some(
+            PacketLossResults::new(0.1, 200, 20, 180, None)
+                .with_order(9, 1)
+                .with_batches(&[
+                    BatchLoss { sent: 100, received: 100 },
+                    BatchLoss { sent: 100, received: 80 },
+                ]),
+        );
+
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains("Packet loss:\t 10.00%"));
+        assert!(text.contains("Per batch: 0% 20%"));
+        assert!(text.contains("Order: 5.00% reordered, 1 duplicate"));
+    }
+
+    #[test]
+    fn test_human_reporter_regional_comparison() {
+        use crate::regional::{
+            RegionScope, RegionalComparison, RegionalMetric,
+        };
+
+        colored::control::set_override(false);
+
+        let results =
+            sample_results().with_regional(Some(RegionalComparison {
+                scope: RegionScope::Country,
+                region: "US".into(),
+                source: "api.cloudflare.com".into(),
+                metrics: vec![RegionalMetric {
+                    metric: "latency_ms".into(),
+                    value: 12.5,
+                    median: 25.0,
+                    percent_of_median: 50.0,
+                    better: true,
+                }],
+            }));
+
+        let mut out = Vec::new();
+        HumanReporter::new(true).report(&results, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains("median of country US (api.cloudflare.com)"));
+        assert!(text.contains("(median 25.00, 50%) better"));
+    }
+}
@@ -0,0 +1,171 @@
+//! JSON reporter.
+
+use super::Reporter;
+use crate::results::SpeedTestResults;
+use crate::tui::progress::{ProgressCallback, ProgressEvent};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+/// Writes results as a single JSON document.
+pub struct JsonReporter {
+    /// Pretty print the JSON output
+    pretty: bool,
+}
+
+impl JsonReporter {
+    /// Create a JSON reporter.
+    pub fn new(pretty: bool) -> Self {
+        Self { pretty }
+    }
+}
+
+impl Reporter for JsonReporter {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn report(
+        &self,
+        results: &SpeedTestResults,
+        out: &mut dyn Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let json = if self.pretty {
+            serde_json::to_string_pretty(results)?
+        } else {
+            serde_json::to_string(results)?
+        };
+
+        writeln!(out, "{}", json)?;
+
+        Ok(())
+    }
+}
+
+/// Writes a JSON line to stdout as each phase completes (`--stream`),
+/// ahead of the final report, then passes the event on.
+///
+/// Phase lines carry a `type` (`latency` or `size_block`); the final
+/// report is written by [`JsonReporter`] unchanged, so the last line is
+/// the same document a non-streaming run prints.
+pub struct PhaseStream {
+    inner: Arc<dyn ProgressCallback>,
+}
+
+impl PhaseStream {
+    /// Stream phases, forwarding every event to `inner`.
+    pub fn new(inner: Arc<dyn ProgressCallback>) -> Self {
+        Self { inner }
+    }
+}
+
+impl ProgressCallback for PhaseStream {
+    fn on_progress(&self, event: ProgressEvent) {
+        if let Some(line) = phase_json(&event) {
+            let mut out = io::stdout().lock();
+            // Nothing to report a broken pipe to; the final report will
+            // fail on it too
+            let _ = writeln!(out, "{}", line).and_then(|_| out.flush());
+        }
+        self.inner.on_progress(event);
+    }
+}
+
+/// JSON line for an event that completes a phase, if it is one.
+fn phase_json(event: &ProgressEvent) -> Option<Value> {
+    match event {
+        ProgressEvent::LatencyComplete { idle_ms, jitter_ms } => Some(json!({
+            "type": "latency",
+            "idle_ms": idle_ms,
+            "jitter_ms": jitter_ms,
+        })),
+        ProgressEvent::SizeBlockComplete {
+            direction,
+            bytes,
+            speed_mbps,
+            count,
+        } => Some(json!({
+            "type": "size_block",
+            "direction": direction,
+            "bytes": bytes,
+            "speed_mbps": speed_mbps,
+            "count": count,
+        })),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reporters::tests::sample_results;
+
+    #[test]
+    fn test_json_reporter_compact() {
+        let mut out = Vec::new();
+        JsonReporter::new(false).report(&sample_results(), &mut out).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().count(), 1);
+        let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(value["download"]["speed_mbps"], 95.5);
+    }
+
+    #[test]
+    fn test_json_reporter_pretty() {
+        let mut out = Vec::new();
+        JsonReporter::new(true).report(&sample_results(), &mut out).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.lines().count() > 1);
+        assert!(serde_json::from_str::<serde_json::Value>(&text).is_ok());
+    }
+
+    #[test]
+    fn test_json_reporter_output_verifies_when_signed() {
+        use crate::signing::{self, SigningKey};
+        use ring::rand::SystemRandom;
+        use ring::signature::Ed25519KeyPair;
+
+        let pkcs8 =
+            Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key = SigningKey::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let results = sample_results().signed(&key).unwrap();
+
+        let mut out = Vec::new();
+        JsonReporter::new(true).report(&results, &mut out).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert!(signing::verify(&value, Some(&key.public_key())).is_ok());
+    }
+
+    #[test]
+    fn test_phase_json() {
+        use crate::tui::progress::{BandwidthDirection, TestPhase};
+
+        let line = phase_json(&ProgressEvent::SizeBlockComplete {
+            direction: BandwidthDirection::Upload,
+            bytes: 1_000_000,
+            speed_mbps: 42.5,
+            count: 8,
+        })
+        .unwrap();
+        assert_eq!(line["type"], "size_block");
+        assert_eq!(line["direction"], "upload");
+        assert_eq!(line["count"], 8);
+        assert!(!line.to_string().contains('\n'));
+
+        let line = phase_json(&ProgressEvent::LatencyComplete {
+            idle_ms: 12.0,
+            jitter_ms: None,
+        })
+        .unwrap();
+        assert_eq!(line["type"], "latency");
+        assert!(line["jitter_ms"].is_null());
+
+        assert!(phase_json(&ProgressEvent::PhaseComplete(
+            TestPhase::Download
+        ))
+        .is_none());
+    }
+}
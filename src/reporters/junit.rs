@@ -0,0 +1,209 @@
+//! JUnit XML reporter, for gating CI pipelines on network health.
+//!
+//! Each headline metric becomes a test case. A metric checked with
+//! `--assert-download`, `--assert-upload` or `--assert-latency` fails its
+//! case when it misses the threshold (or wasn't measured); the others
+//! always pass and only record their value.
+
+use super::{escape, Reporter};
+use crate::results::{AssertionResult, SpeedTestResults};
+use std::error::Error;
+use std::fmt::Write as _;
+use std::io::Write;
+
+/// Writes the results as a JUnit XML report with one test case per
+/// metric.
+pub struct JunitReporter;
+
+impl Reporter for JunitReporter {
+    fn name(&self) -> &str {
+        "junit"
+    }
+
+    fn report(
+        &self,
+        results: &SpeedTestResults,
+        out: &mut dyn Write,
+    ) -> Result<(), Box<dyn Error>> {
+        out.write_all(render(results).as_bytes())?;
+        Ok(())
+    }
+}
+
+/// A test case: the metric, its measured value and unit, and its
+/// assertion if one was made.
+struct Case<'a> {
+    metric: &'static str,
+    value: Option<f64>,
+    unit: &'static str,
+    assertion: Option<&'a AssertionResult>,
+}
+
+/// Render the whole report.
+fn render(results: &SpeedTestResults) -> String {
+    let measured = [
+        (
+            "download_mbps",
+            results.download.as_ref().map(|d| d.speed_mbps),
+            "Mbps",
+        ),
+        ("upload_mbps", results.upload.as_ref().map(|u| u.speed_mbps), "Mbps"),
+        ("latency_ms", Some(results.latency.idle_ms), "ms"),
+    ];
+    let cases: Vec<Case> = measured
+        .into_iter()
+        .map(|(metric, value, unit)| Case {
+            metric,
+            value,
+            unit,
+            assertion: results.assertions.iter().find(|a| a.metric == metric),
+        })
+        .filter(|case| case.value.is_some() || case.assertion.is_some())
+        .collect();
+    let failures = cases
+        .iter()
+        .filter(|case| case.assertion.is_some_and(|a| !a.pass))
+        .count();
+
+    let mut xml = String::new();
+    let _ = writeln!(xml, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+    let _ = writeln!(
+        xml,
+        "<testsuites name=\"cloud-speed\" tests=\"{}\" failures=\"{}\">",
+        cases.len(),
+        failures
+    );
+    let _ = writeln!(
+        xml,
+        "  <testsuite name=\"cloud-speed\" tests=\"{}\" failures=\"{}\" \
+         errors=\"0\" skipped=\"0\" timestamp=\"{}\">",
+        cases.len(),
+        failures,
+        results.timestamp.format("%Y-%m-%dT%H:%M:%S")
+    );
+
+    // Where the test ran, for whoever reads a failing build
+    xml.push_str("    <properties>\n");
+    let mut properties = vec![
+        (
+            "server",
+            format!("{} ({})", results.server.city, results.server.iata),
+        ),
+        ("client", results.connection.ip.clone()),
+    ];
+    if let Some(isp) = &results.connection.isp {
+        properties.push(("isp", isp.clone()));
+    }
+    if let Some(meas_id) = &results.meas_id {
+        properties.push(("meas_id", meas_id.clone()));
+    }
+    for (name, value) in properties {
+        let _ = writeln!(
+            xml,
+            "      <property name=\"{}\" value=\"{}\"/>",
+            name,
+            escape(&value)
+        );
+    }
+    xml.push_str("    </properties>\n");
+
+    for case in &cases {
+        let _ = writeln!(
+            xml,
+            "    <testcase classname=\"cloud-speed\" name=\"{}\">",
+            case.metric
+        );
+        match case.assertion {
+            Some(assertion) if !assertion.pass => {
+                let _ = writeln!(
+                    xml,
+                    "      <failure type=\"threshold\" message=\"{}\"/>",
+                    escape(&assertion.describe())
+                );
+            }
+            Some(assertion) => {
+                let _ = writeln!(
+                    xml,
+                    "      <system-out>{}</system-out>",
+                    escape(&assertion.describe())
+                );
+            }
+            None => {
+                let _ = writeln!(
+                    xml,
+                    "      <system-out>{} {:.2} {}</system-out>",
+                    case.metric,
+                    case.value.unwrap_or_default(),
+                    case.unit
+                );
+            }
+        }
+        xml.push_str("    </testcase>\n");
+    }
+
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cloudflare::tests::engine::PhaseSelection;
+    use crate::reporters::tests::sample_results;
+
+    #[test]
+    fn test_junit_reporter_without_assertions() {
+        let mut out = Vec::new();
+        JunitReporter.report(&sample_results(), &mut out).unwrap();
+        let xml = String::from_utf8(out).unwrap();
+
+        assert!(xml.starts_with("<?xml version=\"1.0\""));
+        assert!(xml.contains("tests=\"3\" failures=\"0\""));
+        assert!(xml.contains(
+            "<testcase classname=\"cloud-speed\" \
+             name=\"download_mbps\">"
+        ));
+        assert!(xml.contains("<system-out>latency_ms 12.50 ms</system-out>"));
+        assert!(xml.contains("name=\"isp\" value=\"Test ISP\""));
+        assert!(!xml.contains("<failure"));
+    }
+
+    #[test]
+    fn test_junit_reporter_fails_missed_thresholds() {
+        let results = sample_results();
+        let assertions = AssertionResult::check_all(
+            &results,
+            Some(100.0),
+            Some(10.0),
+            None,
+        );
+        let xml = render(&results.with_assertions(assertions));
+
+        assert!(xml.contains("tests=\"3\" failures=\"1\""));
+        assert!(xml.contains(
+            "<failure type=\"threshold\" message=\"download_mbps 95.50 Mbps \
+             is below the 100.00 Mbps threshold\"/>"
+        ));
+        assert!(xml.contains("upload_mbps 20.25 Mbps meets the 10.00 Mbps"));
+    }
+
+    #[test]
+    fn test_junit_reporter_fails_unmeasured_assertions() {
+        let results = sample_results().with_phases(PhaseSelection::Latency);
+        let assertions =
+            AssertionResult::check_all(&results, None, Some(10.0), None);
+        let xml = render(&results.with_assertions(assertions));
+
+        assert!(xml.contains("tests=\"2\" failures=\"1\""));
+        assert!(!xml.contains("download_mbps"));
+        assert!(xml.contains("message=\"upload_mbps was not measured\""));
+    }
+
+    #[test]
+    fn test_junit_reporter_escapes_properties() {
+        let mut results = sample_results();
+        results.connection.isp = Some("AT&T \"Fiber\"".to_string());
+        let xml = render(&results);
+        assert!(xml.contains("value=\"AT&amp;T &quot;Fiber&quot;\""));
+    }
+}
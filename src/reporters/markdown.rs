@@ -0,0 +1,297 @@
+//! Markdown reporter, for pasting results into tickets and wikis.
+
+use super::human::format_size_label;
+use super::Reporter;
+use crate::results::{BandwidthResults, SpeedTestResults};
+use std::error::Error;
+use std::io::Write;
+
+/// Bars of a sparkline, lowest to highest.
+const SPARK_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Writes the results as a Markdown report: a metadata table, a summary,
+/// per-size tables for each direction and the quality scores.
+pub struct MarkdownReporter {
+    /// Add a sparkline of per-size speeds under each direction's table
+    sparkline: bool,
+}
+
+impl MarkdownReporter {
+    /// Create a Markdown reporter.
+    pub fn new(sparkline: bool) -> Self {
+        Self { sparkline }
+    }
+
+    /// Per-size table for one direction, with its sparkline if enabled.
+    fn direction(
+        &self,
+        out: &mut dyn Write,
+        title: &str,
+        bandwidth: &BandwidthResults,
+    ) -> Result<(), Box<dyn Error>> {
+        writeln!(out, "## {}\n", title)?;
+        writeln!(out, "**{:.2} Mbps**\n", bandwidth.speed_mbps)?;
+        if bandwidth.measurements.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out, "| Size | Speed | Samples | Loaded latency |")?;
+        writeln!(out, "| ---: | ---: | ---: | ---: |")?;
+        for measurement in &bandwidth.measurements {
+            writeln!(
+                out,
+                "| {} | {:.2} Mbps | {} | {} |",
+                format_size_label(measurement.bytes),
+                measurement.speed_mbps,
+                measurement.count,
+                measurement.loaded_latency_ms.map_or_else(
+                    || "-".to_string(),
+                    |l| format!("{:.1} ms", l)
+                )
+            )?;
+        }
+        writeln!(out)?;
+
+        if self.sparkline {
+            let speeds: Vec<f64> =
+                bandwidth.measurements.iter().map(|m| m.speed_mbps).collect();
+            let (first, last) = (
+                bandwidth.measurements.first().map(|m| m.bytes),
+                bandwidth.measurements.last().map(|m| m.bytes),
+            );
+            writeln!(
+                out,
+                "`{}` {} to {}\n",
+                sparkline(&speeds),
+                format_size_label(first.unwrap_or_default()),
+                format_size_label(last.unwrap_or_default())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Reporter for MarkdownReporter {
+    fn name(&self) -> &str {
+        "markdown"
+    }
+
+    fn report(
+        &self,
+        results: &SpeedTestResults,
+        out: &mut dyn Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let latency = &results.latency;
+        let connection = &results.connection;
+
+        writeln!(out, "# Speed Test Report\n")?;
+
+        // Where and when the test ran
+        writeln!(out, "| | |")?;
+        writeln!(out, "| --- | --- |")?;
+        row(
+            out,
+            "Date",
+            results.timestamp.format("%Y-%m-%d %H:%M UTC").to_string(),
+        )?;
+        row(
+            out,
+            "Server",
+            format!("{} ({})", results.server.city, results.server.iata),
+        )?;
+        let network = match (&connection.isp, connection.asn) {
+            (Some(isp), Some(asn)) => Some(format!("{} (AS{})", isp, asn)),
+            (Some(isp), None) => Some(isp.clone()),
+            (None, Some(asn)) => Some(format!("AS{}", asn)),
+            (None, None) => None,
+        };
+        if let Some(network) = network {
+            row(out, "Network", network)?;
+        }
+        row(
+            out,
+            "Client",
+            format!("{} ({})", connection.ip, connection.country),
+        )?;
+        if let Some(proxy) = &connection.proxy {
+            row(out, "Proxy", proxy.clone())?;
+        }
+        if let Some(meta) = &results.meta {
+            row(out, "Host", format!("{} {}", meta.os, meta.arch))?;
+            for (key, value) in &meta.tags {
+                row(out, &format!("Tag `{}`", key), value.clone())?;
+            }
+        }
+        if let Some(meas_id) = &results.meas_id {
+            row(out, "Measurement ID", format!("`{}`", meas_id))?;
+        }
+        writeln!(out)?;
+
+        // Headline numbers
+        writeln!(out, "## Summary\n")?;
+        writeln!(out, "| Metric | Value |")?;
+        writeln!(out, "| --- | ---: |")?;
+        if let Some(download) = &results.download {
+            row(out, "Download", format!("{:.2} Mbps", download.speed_mbps))?;
+        }
+        if let Some(upload) = &results.upload {
+            row(out, "Upload", format!("{:.2} Mbps", upload.speed_mbps))?;
+        }
+        row(out, "Latency", format!("{:.2} ms", latency.idle_ms))?;
+        if let Some(jitter) = latency.idle_jitter_ms {
+            row(out, "Jitter", format!("{:.2} ms", jitter))?;
+        }
+        if let Some(loaded) = latency.loaded_down_ms {
+            row(out, "Loaded latency (down)", format!("{:.2} ms", loaded))?;
+        }
+        if let Some(loaded) = latency.loaded_up_ms {
+            row(out, "Loaded latency (up)", format!("{:.2} ms", loaded))?;
+        }
+        if let Some(packet_loss) = &results.packet_loss {
+            row(out, "Packet loss", format!("{:.2}%", packet_loss.percent))?;
+        }
+        writeln!(out)?;
+
+        if let Some(download) = &results.download {
+            self.direction(out, "Download", download)?;
+        }
+        if let Some(upload) = &results.upload {
+            self.direction(out, "Upload", upload)?;
+        }
+
+        // AIM Scores (when both directions were measured)
+        if let Some(scores) = &results.scores {
+            writeln!(out, "## Quality Scores\n")?;
+            if let Some(grade) = &scores.grade {
+                writeln!(
+                    out,
+                    "Grade **{}**: {}\n",
+                    grade.grade,
+                    escape(&grade.explanation)
+                )?;
+            }
+            writeln!(out, "| Use | Score |")?;
+            writeln!(out, "| --- | --- |")?;
+            row(out, "Streaming", capitalize(&scores.streaming))?;
+            row(out, "Gaming", capitalize(&scores.gaming))?;
+            row(out, "Video calls", capitalize(&scores.video_conferencing))?;
+            row(out, "Overall", capitalize(&scores.overall))?;
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Write a two-column table row, escaping `value`.
+fn row(
+    out: &mut dyn Write,
+    label: &str,
+    value: String,
+) -> Result<(), Box<dyn Error>> {
+    writeln!(out, "| {} | {} |", label, escape(&value))?;
+    Ok(())
+}
+
+/// Escape the characters that would break a table cell.
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// "great" as "Great".
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// One bar per value, scaled between the smallest and largest; all bars
+/// are mid-height when the values are equal.
+fn sparkline(values: &[f64]) -> String {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let top = (SPARK_BARS.len() - 1) as f64;
+    values
+        .iter()
+        .map(|value| {
+            let level = if max > min {
+                ((value - min) / (max - min) * top).round()
+            } else {
+                top / 2.0
+            };
+            SPARK_BARS[level as usize]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cloudflare::tests::engine::PhaseSelection;
+    use crate::reporters::tests::sample_results;
+    use crate::results::SizeMeasurement;
+
+    /// Render `results` with the sparkline on or off.
+    fn render(results: &SpeedTestResults, sparkline: bool) -> String {
+        let mut out = Vec::new();
+        MarkdownReporter::new(sparkline).report(results, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_markdown_reporter_output() {
+        let text = render(&sample_results(), false);
+
+        assert!(text.starts_with("# Speed Test Report\n"));
+        assert!(text.contains("| Server | Test City (TST) |"));
+        assert!(text.contains("| Network | Test ISP (AS12345) |"));
+        assert!(text.contains("| Download | 95.50 Mbps |"));
+        assert!(text.contains("| Upload | 20.25 Mbps |"));
+        assert!(text.contains("| Loaded latency (down) | 40.00 ms |"));
+        assert!(text.contains("| 1MB | 94.00 Mbps | 8 | - |"));
+        assert!(text.contains("| Video calls | Poor |"));
+        assert!(!text.contains("Packet loss"));
+        assert!(!text.contains('`'));
+    }
+
+    #[test]
+    fn test_markdown_reporter_sparkline() {
+        let mut results = sample_results();
+        results.download.as_mut().unwrap().measurements = vec![
+            SizeMeasurement::new(100_000, 10.0, 10),
+            SizeMeasurement::new(1_000_000, 50.0, 8),
+            SizeMeasurement::new(10_000_000, 90.0, 6),
+        ];
+
+        let text = render(&results, true);
+        assert!(text.contains("`▁▅█` 100kB to 10MB"));
+        assert!(!render(&results, false).contains('▁'));
+    }
+
+    #[test]
+    fn test_markdown_reporter_escapes_cells() {
+        let mut results = sample_results();
+        results.connection.isp = Some("A|B".to_string());
+        results.connection.asn = None;
+
+        let text = render(&results, false);
+        assert!(text.contains("| Network | A\\|B |"));
+    }
+
+    #[test]
+    fn test_markdown_reporter_skips_unmeasured_phases() {
+        let results = sample_results().with_phases(PhaseSelection::Latency);
+        let text = render(&results, true);
+        assert!(text.contains("| Latency | 12.50 ms |"));
+        assert!(!text.contains("## Download"));
+        assert!(!text.contains("Quality Scores"));
+    }
+
+    #[test]
+    fn test_sparkline_of_equal_values() {
+        assert_eq!(sparkline(&[5.0, 5.0]), "▄▄");
+        assert_eq!(sparkline(&[]), "");
+    }
+}
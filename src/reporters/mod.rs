@@ -0,0 +1,233 @@
+//! Output renderers for speed test results.
+//!
+//! Each output format implements [`Reporter`] and is registered in a
+//! [`ReporterRegistry`] under the name used to select it with `--format`.
+//! Adding a new format means adding a reporter here rather than another
+//! branch in `cli.rs`. Subcommands that report something else implement
+//! [`CommandReport`] for their output instead.
+
+mod command;
+mod html;
+mod human;
+mod json;
+mod junit;
+mod markdown;
+
+pub use command::{write_report, CommandReport};
+pub(crate) use html::escape;
+pub use html::HtmlReporter;
+pub use human::HumanReporter;
+pub use json::{JsonReporter, PhaseStream};
+pub use junit::JunitReporter;
+pub use markdown::MarkdownReporter;
+
+use crate::results::SpeedTestResults;
+use std::error::Error;
+use std::io::Write;
+
+/// Renders final speed test results in a particular output format.
+pub trait Reporter {
+    /// Name used to select this reporter with `--format`.
+    fn name(&self) -> &str;
+
+    /// Whether the output is meant for other programs rather than people.
+    ///
+    /// Machine-readable reporters suppress the TUI so that stdout only
+    /// contains the report itself.
+    fn is_machine_readable(&self) -> bool {
+        true
+    }
+
+    /// Write `results` to `out`.
+    fn report(
+        &self,
+        results: &SpeedTestResults,
+        out: &mut dyn Write,
+    ) -> Result<(), Box<dyn Error>>;
+}
+
+/// One-line summary of `results` for shell scripts, in the same form
+/// whatever the output format:
+/// `RESULT down=512.3 up=38.1 lat=12.4 loss=0.2`.
+///
+/// Speeds are in Mbps and latency in ms; `down` and `up` are left out
+/// when that direction was skipped, and `loss` is a percentage only
+/// present when packet loss was measured.
+pub fn summary_line(results: &SpeedTestResults) -> String {
+    let mut line = String::from("RESULT");
+    if let Some(ref download) = results.download {
+        line.push_str(&format!(" down={:.1}", download.speed_mbps));
+    }
+    if let Some(ref upload) = results.upload {
+        line.push_str(&format!(" up={:.1}", upload.speed_mbps));
+    }
+    line.push_str(&format!(" lat={:.1}", results.latency.idle_ms));
+    if let Some(ref loss) = results.packet_loss {
+        line.push_str(&format!(" loss={:.1}", loss.percent));
+    }
+    line
+}
+
+/// Reporters available for selection by name.
+#[derive(Default)]
+pub struct ReporterRegistry {
+    reporters: Vec<Box<dyn Reporter>>,
+}
+
+impl ReporterRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry containing the built-in reporters.
+    ///
+    /// # Arguments
+    /// * `pretty` - Whether the JSON reporter pretty prints its output
+    /// * `estimates` - Whether the human reporter shows transfer-time
+    ///   estimates
+    /// * `sparkline` - Whether the Markdown reporter adds sparklines of
+    ///   per-size speeds
+    pub fn builtin(pretty: bool, estimates: bool, sparkline: bool) -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(HumanReporter::new(estimates)));
+        registry.register(Box::new(JsonReporter::new(pretty)));
+        registry.register(Box::new(MarkdownReporter::new(sparkline)));
+        registry.register(Box::new(HtmlReporter));
+        registry.register(Box::new(JunitReporter));
+        registry
+    }
+
+    /// Add a reporter, replacing any existing reporter with the same name.
+    pub fn register(&mut self, reporter: Box<dyn Reporter>) {
+        self.reporters.retain(|r| r.name() != reporter.name());
+        self.reporters.push(reporter);
+    }
+
+    /// Look up a reporter by name.
+    pub fn get(&self, name: &str) -> Option<&dyn Reporter> {
+        self.reporters.iter().find(|r| r.name() == name).map(|r| r.as_ref())
+    }
+
+    /// Names of all registered reporters, in registration order.
+    pub fn names(&self) -> Vec<&str> {
+        self.reporters.iter().map(|r| r.name()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::{
+        AimScoresOutput, BandwidthResults, ConnectionMeta, LatencyResults,
+        PacketLossResults, ServerLocation, SizeMeasurement,
+    };
+
+    /// Results shared by the reporter tests.
+    pub(super) fn sample_results() -> SpeedTestResults {
+        SpeedTestResults::new(
+            ServerLocation::new("Test City".to_string(), "TST".to_string()),
+            ConnectionMeta::new(
+                "192.168.1.1".to_string(),
+                "US".to_string(),
+                "Test ISP".to_string(),
+                12345,
+            ),
+            LatencyResults::new(
+                12.5,
+                Some(1.25),
+                Some(40.0),
+                None,
+                None,
+                None,
+            ),
+            BandwidthResults::new(
+                95.5,
+                vec![SizeMeasurement::new(1_000_000, 94.0, 8)],
+                false,
+            ),
+            BandwidthResults::new(20.25, vec![], false),
+            None,
+            AimScoresOutput {
+                streaming: "great".to_string(),
+                gaming: "good".to_string(),
+                video_conferencing: "poor".to_string(),
+                overall: "average".to_string(),
+                details: None,
+                working_latency: None,
+                grade: None,
+            },
+        )
+    }
+
+    struct NamedReporter(&'static str, &'static str);
+
+    impl Reporter for NamedReporter {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn report(
+            &self,
+            _results: &SpeedTestResults,
+            out: &mut dyn Write,
+        ) -> Result<(), Box<dyn Error>> {
+            write!(out, "{}", self.1)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_summary_line() {
+        let mut results = sample_results();
+        assert_eq!(
+            summary_line(&results),
+            "RESULT down=95.5 up=20.2 lat=12.5"
+        );
+
+        results.packet_loss =
+            Some(PacketLossResults::new(0.002, 1000, 2, 998, None));
+        assert!(summary_line(&results).ends_with(" lat=12.5 loss=0.2"));
+
+        results.upload = None;
+        assert!(summary_line(&results).starts_with("RESULT down=95.5 lat="));
+    }
+
+    #[test]
+    fn test_builtin_registry_names() {
+        let registry = ReporterRegistry::builtin(false, true, false);
+        assert_eq!(
+            registry.names(),
+            vec!["human", "json", "markdown", "html", "junit"]
+        );
+        assert!(!registry.get("human").unwrap().is_machine_readable());
+        assert!(registry.get("json").unwrap().is_machine_readable());
+        assert!(registry.get("markdown").unwrap().is_machine_readable());
+        assert!(registry.get("html").unwrap().is_machine_readable());
+        assert!(registry.get("csv").is_none());
+    }
+
+    #[test]
+    fn test_register_custom_reporter() {
+        let mut registry = ReporterRegistry::builtin(false, true, false);
+        registry.register(Box::new(NamedReporter("csv", "a,b")));
+        assert_eq!(
+            registry.names(),
+            vec!["human", "json", "markdown", "html", "junit", "csv"]
+        );
+        assert_eq!(registry.get("csv").unwrap().name(), "csv");
+    }
+
+    #[test]
+    fn test_register_replaces_same_name() {
+        let mut registry = ReporterRegistry::new();
+        registry.register(Box::new(NamedReporter("custom", "first")));
+        registry.register(Box::new(NamedReporter("custom", "second")));
+        assert_eq!(registry.names(), vec!["custom"]);
+
+        let results = sample_results();
+        let mut out = Vec::new();
+        registry.get("custom").unwrap().report(&results, &mut out).unwrap();
+        assert_eq!(out, b"second");
+    }
+}
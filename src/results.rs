@@ -5,22 +5,60 @@
 //! packet loss, and AIM scores. All structures implement Serialize
 //! for JSON output.
 
-use chrono::{DateTime, Utc};
-use serde::Serialize;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
 
+use crate::cloudflare::tests::connection::{DnsLookup, Dscp, SocketBuffers};
+use crate::cloudflare::tests::connection::{TlsBackend, TlsInfo, TrustSource};
+use crate::cloudflare::tests::cpu::CPU_BOUND_UTILIZATION;
+use crate::cloudflare::tests::duplex::DuplexResult;
 use crate::cloudflare::tests::engine::{
-    BandwidthResults as EngineBandwidthResults,
-    LatencyResults as EngineLatencyResults,
+    BandwidthResults as EngineBandwidthResults, LatencyProbe,
+    LatencyResults as EngineLatencyResults, MeasurementFailure, NetworkChange,
+    PacingSummary, PhaseSelection, RateLimitSummary, RttBreakdown,
     SizeMeasurement as EngineSizeMeasurement, SpeedTestOutput,
+    TerminationRule, TestOrder, TtfbSummary,
 };
-use crate::cloudflare::tests::packet_loss::PacketLossResult as EnginePacketLossResult;
-use crate::scoring::{AimScores, ConnectionMetrics, QualityScore};
+use crate::cloudflare::tests::handshake::{HandshakeComparison, SetupTiming};
+use crate::cloudflare::tests::happy_eyeballs::{Advisory, DualStack};
+use crate::cloudflare::tests::mtu::{PathMtu, ETHERNET_MTU};
+use crate::cloudflare::tests::packet_loss::{
+    BatchLoss, LossProvider, PacketLossResult as EnginePacketLossResult,
+};
+use crate::cloudflare::tests::tcp_info::TcpStats;
+use crate::cloudflare::tests::upload::PayloadPattern;
+use crate::cloudflare::tests::EdgeHeaders;
+use crate::history::RegressionResults;
+use crate::measurements::jitter_f64;
+use crate::profiling::RunProfile;
+use crate::regional::{RegionScope, RegionalComparison};
+use crate::scoring::{
+    AimScoreDetails, AimScores, ConnectionMetrics, GradeSummary, QualityScore,
+    ScoringThresholds, WorkingLatencyCheck,
+};
+use crate::signing::{ResultSignature, SigningKey};
+use crate::stats::median_f64;
+use crate::tui::TestPhase;
+use crate::vpn::VpnDetection;
+
+/// Version of the JSON results document.
+///
+/// Bumped when a field is removed, renamed or changes meaning; new
+/// optional fields leave it unchanged.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// JSON Schema describing the results document, as printed by
+/// `cloud-speed schema`.
+pub const JSON_SCHEMA: &str = include_str!("../schema/results.schema.json");
 
 /// Complete results from a speed test run.
 ///
 /// This struct contains all measurement results, metadata, and scores
 /// from a complete speed test execution. It implements Serialize for
-/// JSON output.
+/// JSON output, and Deserialize for reading it back.
 ///
 /// # Requirements
 /// - Includes all measurement results, metadata, and scores
@@ -44,25 +82,79 @@ use crate::scoring::{AimScores, ConnectionMetrics, QualityScore};
 /// // Serialize to JSON
 /// let json = serde_json::to_string_pretty(&results)?;
 /// ```
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SpeedTestResults {
+    /// Version of this document's schema ([`SCHEMA_VERSION`])
+    pub schema_version: u32,
     /// Timestamp when the test was completed
     pub timestamp: DateTime<Utc>,
+    /// Measurement ID the run's requests were tagged with, for finding
+    /// them in Cloudflare's logs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meas_id: Option<String>,
     /// Server location information
     pub server: ServerLocation,
     /// Connection metadata (ISP, IP, etc.)
     pub connection: ConnectionMeta,
     /// Latency measurement results
     pub latency: LatencyResults,
-    /// Download bandwidth results
-    pub download: BandwidthResults,
-    /// Upload bandwidth results
-    pub upload: BandwidthResults,
+    /// Download bandwidth results (unless downloads were skipped)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download: Option<BandwidthResults>,
+    /// Upload bandwidth results (unless uploads were skipped)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload: Option<BandwidthResults>,
     /// Packet loss measurement results (if available)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub packet_loss: Option<PacketLossResults>,
-    /// AIM quality scores
-    pub scores: AimScoresOutput,
+    /// How the server's hostname resolved (unless a proxy resolved it)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns: Option<DnsResults>,
+    /// IPv6 and IPv4 connection races (when the server resolved to both)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dual_stack: Option<DualStackResults>,
+    /// TLS session details (with `--detailed`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsResults>,
+    /// Time added by `--nice` pauses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pacing: Option<PacingResults>,
+    /// Allocations per phase and peak memory (with `--detailed`, in
+    /// builds with the `profiling` feature)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<ProfileResults>,
+    /// Path MTU toward the server (with `--mtu`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path_mtu: Option<PathMtuResults>,
+    /// Cold and warm connection setup times (with `--handshake`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handshake: Option<HandshakeResults>,
+    /// Throughput and latency with both directions saturated (with
+    /// `--duplex`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplex: Option<DuplexResults>,
+    /// How quickly the server answered requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub responsiveness: Option<ResponsivenessResults>,
+    /// Details about the machine and run that produced these results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<RunMeta>,
+    /// Comparison against the historical baseline (if requested)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regression: Option<RegressionResults>,
+    /// Comparison against regional medians (with `--compare-region`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regional: Option<RegionalComparison>,
+    /// Thresholds checked with `--assert-download`, `--assert-upload` and
+    /// `--assert-latency`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub assertions: Vec<AssertionResult>,
+    /// AIM quality scores (when both directions were measured)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scores: Option<AimScoresOutput>,
+    /// Signature over the rest of the document (with `--sign-key`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<ResultSignature>,
 }
 
 impl SpeedTestResults {
@@ -77,96 +169,1236 @@ impl SpeedTestResults {
         scores: AimScoresOutput,
     ) -> Self {
         Self {
+            schema_version: SCHEMA_VERSION,
             timestamp: Utc::now(),
+            meas_id: None,
             server,
             connection,
             latency,
-            download,
-            upload,
+            download: Some(download),
+            upload: Some(upload),
             packet_loss,
-            scores,
+            dns: None,
+            dual_stack: None,
+            tls: None,
+            pacing: None,
+            profile: None,
+            path_mtu: None,
+            handshake: None,
+            duplex: None,
+            responsiveness: None,
+            meta: None,
+            regression: None,
+            regional: None,
+            assertions: Vec::new(),
+            scores: Some(scores),
+            signature: None,
         }
     }
 
-    /// Create SpeedTestResults from engine output and additional data.
+    /// Drop the sections of phases that didn't run. The scores need both
+    /// directions, so they go unless every phase ran.
+    pub fn with_phases(mut self, phases: PhaseSelection) -> Self {
+        if !phases.downloads() {
+            self.download = None;
+        }
+        if !phases.uploads() {
+            self.upload = None;
+        }
+        if phases != PhaseSelection::All {
+            self.scores = None;
+        }
+        self
+    }
+
+    /// Attach the server's DNS lookup.
+    pub fn with_dns(mut self, dns: Option<DnsResults>) -> Self {
+        self.dns = dns;
+        self
+    }
+
+    /// Attach the measurement ID the run's requests were tagged with.
+    pub fn with_meas_id(mut self, meas_id: Option<String>) -> Self {
+        self.meas_id = meas_id;
+        self
+    }
+
+    /// Attach the dual-stack connection races.
+    pub fn with_dual_stack(
+        mut self,
+        dual_stack: Option<DualStackResults>,
+    ) -> Self {
+        self.dual_stack = dual_stack;
+        self
+    }
+
+    /// Attach TLS session details.
+    pub fn with_tls(mut self, tls: Option<TlsResults>) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Attach the time added by pacing.
+    pub fn with_pacing(mut self, pacing: Option<PacingResults>) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    /// Attach the allocation profile.
+    pub fn with_profile(mut self, profile: Option<ProfileResults>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Attach the discovered path MTU.
+    pub fn with_path_mtu(mut self, path_mtu: Option<PathMtuResults>) -> Self {
+        self.path_mtu = path_mtu;
+        self
+    }
+
+    /// Attach the cold and warm connection setup times.
+    pub fn with_handshake(
+        mut self,
+        handshake: Option<HandshakeResults>,
+    ) -> Self {
+        self.handshake = handshake;
+        self
+    }
+
+    /// Attach the duplex saturation results.
+    pub fn with_duplex(mut self, duplex: Option<DuplexResults>) -> Self {
+        self.duplex = duplex;
+        self
+    }
+
+    /// Attach the server responsiveness summary.
+    pub fn with_responsiveness(
+        mut self,
+        responsiveness: Option<ResponsivenessResults>,
+    ) -> Self {
+        self.responsiveness = responsiveness;
+        self
+    }
+
+    /// Attach run metadata.
+    pub fn with_meta(mut self, meta: Option<RunMeta>) -> Self {
+        self.meta = meta;
+        self
+    }
+
+    /// Record the thresholds checked against these results.
+    pub fn with_assertions(
+        mut self,
+        assertions: Vec<AssertionResult>,
+    ) -> Self {
+        self.assertions = assertions;
+        self
+    }
+
+    /// Attach the historical baseline comparison.
+    pub fn with_regression(
+        mut self,
+        regression: Option<RegressionResults>,
+    ) -> Self {
+        self.regression = regression;
+        self
+    }
+
+    /// Attach the comparison against regional medians.
+    pub fn with_regional(
+        mut self,
+        regional: Option<RegionalComparison>,
+    ) -> Self {
+        self.regional = regional;
+        self
+    }
+
+    /// Remove what identifies the client, for results that will be
+    /// published: see [`ConnectionMeta::anonymize`]. The hostname and VPN
+    /// signals, which can name the ISP, are dropped too, as is the
+    /// measurement ID, which leads to the client's address in
+    /// Cloudflare's logs, and a comparison against the client's network.
+    pub fn anonymized(mut self) -> Self {
+        self.connection.anonymize();
+        self.meas_id = None;
+        if matches!(&self.regional, Some(r) if r.scope == RegionScope::Asn) {
+            self.regional = None;
+        }
+        if let Some(ref mut meta) = self.meta {
+            meta.hostname = None;
+            meta.vpn_signals.clear();
+            // Local addresses are often the client's public IPv6 address
+            for change in &mut meta.network_changes {
+                change.from = None;
+                change.to = None;
+            }
+        }
+        // `cf-meta-*` headers echo the client's address and location
+        for size in self
+            .download
+            .iter_mut()
+            .chain(self.upload.iter_mut())
+            .flat_map(|bandwidth| bandwidth.measurements.iter_mut())
+        {
+            for edge in &mut size.edge_headers {
+                edge.meta.clear();
+            }
+        }
+        self
+    }
+
+    /// Sign the results with `key`, replacing any existing signature.
+    ///
+    /// Anything attached afterwards invalidates the signature, so this
+    /// should be the last step before output.
+    pub fn signed(
+        mut self,
+        key: &SigningKey,
+    ) -> Result<Self, serde_json::Error> {
+        self.signature = None;
+        let document = serde_json::to_value(&self)?;
+        self.signature = Some(key.sign(&document));
+        Ok(self)
+    }
+
+    /// Read results back from their JSON form.
+    ///
+    /// Documents from older schema versions parse as long as the fields
+    /// they have still mean the same; newer versions are refused, since
+    /// fields may have been renamed or changed meaning.
+    pub fn from_value(
+        value: serde_json::Value,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let version = value["schema_version"].as_u64();
+        if let Some(version) = version.filter(|&v| v > SCHEMA_VERSION as u64) {
+            return Err(format!(
+                "results use schema version {}, newer than the {} this \
+                 version of cloud-speed reads",
+                version, SCHEMA_VERSION
+            )
+            .into());
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Create SpeedTestResults from engine output and additional data,
+    /// scoring against `thresholds`.
     pub fn from_engine_output(
         output: &SpeedTestOutput,
         server: ServerLocation,
         connection: ConnectionMeta,
         packet_loss: Option<&EnginePacketLossResult>,
+        thresholds: &ScoringThresholds,
     ) -> Self {
         let latency = LatencyResults::from_engine(&output.latency);
-        let download = BandwidthResults::from_engine(&output.download);
-        let upload = BandwidthResults::from_engine(&output.upload);
+        let download = BandwidthResults::from_engine(&output.download)
+            .with_load_streams(output.latency.load_streams);
+        let upload = BandwidthResults::from_engine(&output.upload)
+            .with_load_streams(output.latency.load_streams);
 
         let packet_loss_results = packet_loss
             .filter(|p| p.is_available())
             .map(PacketLossResults::from_engine);
 
         // Calculate AIM scores
-        let metrics = ConnectionMetrics::new(
-            download.speed_mbps,
-            upload.speed_mbps,
-            latency.idle_ms,
-            latency.idle_jitter_ms.unwrap_or(0.0),
-        )
-        .with_loaded_latency(latency.loaded_down_ms, latency.loaded_up_ms);
-
-        let metrics = if let Some(ref pl) = packet_loss_results {
-            metrics.with_packet_loss(pl.ratio)
-        } else {
-            metrics
-        };
-
-        let aim_scores = crate::scoring::calculate_aim_scores(&metrics);
-        let scores = AimScoresOutput::from_aim_scores(&aim_scores);
+        let metrics = connection_metrics(
+            &latency,
+            &download,
+            &upload,
+            packet_loss_results.as_ref(),
+        );
+        let scores = AimScoresOutput::score(&metrics, thresholds);
 
         Self {
+            schema_version: SCHEMA_VERSION,
             timestamp: Utc::now(),
+            meas_id: output.meas_id.clone(),
             server,
             connection,
             latency,
-            download,
-            upload,
+            download: Some(download),
+            upload: Some(upload),
             packet_loss: packet_loss_results,
-            scores,
+            dns: output.dns.as_ref().map(DnsResults::from_engine),
+            dual_stack: output
+                .dual_stack
+                .as_ref()
+                .map(DualStackResults::from_engine),
+            tls: output.tls.as_ref().map(TlsResults::from_engine),
+            pacing: output.pacing.as_ref().map(PacingResults::from_engine),
+            profile: output.profile.as_ref().map(ProfileResults::from_engine),
+            path_mtu: output
+                .path_mtu
+                .as_ref()
+                .map(PathMtuResults::from_engine),
+            handshake: output
+                .handshake
+                .as_ref()
+                .map(HandshakeResults::from_engine),
+            duplex: output.duplex.as_ref().map(DuplexResults::from_engine),
+            responsiveness: output
+                .ttfb
+                .as_ref()
+                .map(ResponsivenessResults::from_engine),
+            meta: None,
+            regression: None,
+            regional: None,
+            assertions: Vec::new(),
+            scores: Some(scores),
+            signature: None,
         }
+        .with_phases(output.phases)
+    }
+
+    /// Recalculate the scores from the measurements against
+    /// `thresholds`, e.g. after the scoring tables changed.
+    ///
+    /// Any signature is dropped, since it no longer matches.
+    pub fn rescored(mut self, thresholds: &ScoringThresholds) -> Self {
+        self.scores = self
+            .metrics()
+            .map(|metrics| AimScoresOutput::score(&metrics, thresholds));
+        self.signature = None;
+        self
+    }
+
+    /// Metrics the scores are calculated from, when both directions were
+    /// measured.
+    pub fn metrics(&self) -> Option<ConnectionMetrics> {
+        Some(connection_metrics(
+            &self.latency,
+            self.download.as_ref()?,
+            self.upload.as_ref()?,
+            self.packet_loss.as_ref(),
+        ))
     }
 }
 
+/// Metrics the AIM scores are calculated from.
+pub fn connection_metrics(
+    latency: &LatencyResults,
+    download: &BandwidthResults,
+    upload: &BandwidthResults,
+    packet_loss: Option<&PacketLossResults>,
+) -> ConnectionMetrics {
+    let metrics = ConnectionMetrics::new(
+        download.speed_mbps,
+        upload.speed_mbps,
+        latency.idle_ms,
+        latency.idle_jitter_ms.unwrap_or(0.0),
+    )
+    .with_loaded_latency(latency.loaded_down_ms, latency.loaded_up_ms)
+    .with_retransmit_ratio(
+        [download, upload]
+            .iter()
+            .filter_map(|b| b.retransmission.map(|r| r.ratio))
+            .max_by(f64::total_cmp),
+    );
+
+    match packet_loss {
+        Some(pl) => metrics.with_packet_loss(pl.ratio),
+        None => metrics,
+    }
+}
+
+/// Example downloads the human report estimates transfer times for.
+const ESTIMATE_DOWNLOADS: [(&str, u64); 2] =
+    [("1 GB file", 1_000_000_000), ("50 GB game", 50_000_000_000)];
+
+/// How long an example download would take at a measured speed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferEstimate {
+    pub label: &'static str,
+    pub duration: Duration,
+}
+
+/// Megabytes per second for a speed in megabits per second.
+pub fn megabytes_per_sec(speed_mbps: f64) -> f64 {
+    speed_mbps / 8.0
+}
+
+/// Time to download a 1 GB file and a 50 GB game at `speed_mbps`.
+///
+/// The estimate assumes the measured throughput holds for the whole
+/// transfer. Empty when no usable speed was measured.
+pub fn estimates(speed_mbps: f64) -> Vec<TransferEstimate> {
+    if !speed_mbps.is_finite() || speed_mbps <= 0.0 {
+        return Vec::new();
+    }
+    ESTIMATE_DOWNLOADS
+        .iter()
+        .map(|&(label, bytes)| TransferEstimate {
+            label,
+            duration: Duration::from_secs_f64(
+                bytes as f64 * 8.0 / (speed_mbps * 1_000_000.0),
+            ),
+        })
+        .collect()
+}
+
 /// Server location information.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ServerLocation {
     /// City name
     pub city: String,
     /// IATA airport code (e.g., "SFO", "LAX")
     pub iata: String,
+    /// Address every measurement connected to, pinned during setup
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
 }
 
 impl ServerLocation {
     /// Create a new ServerLocation.
     pub fn new(city: String, iata: String) -> Self {
-        Self { city, iata }
+        Self { city, iata, ip: None }
+    }
+
+    /// Set the address measurements were pinned to.
+    pub fn with_ip(mut self, ip: Option<String>) -> Self {
+        self.ip = ip;
+        self
+    }
+}
+
+/// TLS session details.
+///
+/// Useful for spotting protocol downgrades or TLS-intercepting
+/// middleboxes that can affect measured speeds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TlsResults {
+    /// Negotiated protocol version (e.g. "TLSv1.3")
+    pub version: String,
+    /// Negotiated cipher suite
+    pub cipher_suite: String,
+    /// Protocol selected via ALPN
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpn: Option<String>,
+    /// Number of certificates the server presented
+    pub cert_chain_len: usize,
+    /// Time spent validating the certificate chain in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cert_verify_ms: Option<f64>,
+    /// Total TLS handshake time in milliseconds
+    pub handshake_ms: f64,
+    /// Whether the session was resumed from a pre-warmed one
+    pub resumed: bool,
+    /// TLS implementation the session ran over
+    #[serde(default)]
+    pub backend: TlsBackend,
+    /// Where the trusted root certificates came from
+    #[serde(default)]
+    pub trust: TrustSource,
+}
+
+impl TlsResults {
+    /// Create TlsResults from the session details captured by a test.
+    pub fn from_engine(tls: &TlsInfo) -> Self {
+        Self {
+            version: tls.version.clone(),
+            cipher_suite: tls.cipher_suite.clone(),
+            alpn: tls.alpn.clone(),
+            cert_chain_len: tls.cert_chain_len,
+            cert_verify_ms: tls
+                .cert_verify_duration
+                .map(|d| d.as_secs_f64() * 1000.0),
+            handshake_ms: tls.handshake_duration.as_secs_f64() * 1000.0,
+            resumed: tls.resumed,
+            backend: tls.backend,
+            trust: tls.trust,
+        }
+    }
+}
+
+/// Time a paced (`--nice`) run spent idle between measurements.
+///
+/// This is the opportunity cost of being polite: the test took this
+/// much longer than it would have back-to-back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PacingResults {
+    /// Number of pauses taken
+    pub pauses: usize,
+    /// Total time spent paused in milliseconds
+    pub paused_ms: f64,
+}
+
+impl PacingResults {
+    /// Create PacingResults from the engine's pacing summary.
+    pub fn from_engine(summary: &PacingSummary) -> Self {
+        Self {
+            pauses: summary.pauses,
+            paused_ms: summary.paused.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+/// Allocations made while one phase of the test was current.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhaseAllocationResults {
+    /// The phase
+    pub phase: TestPhase,
+    /// Number of allocations, reallocations included
+    pub allocations: u64,
+    /// Bytes requested by those allocations
+    pub allocated_bytes: u64,
+}
+
+/// Memory use of the run, for catching regressions in the measurement
+/// hot path.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileResults {
+    /// Allocations of each phase, in the order the phases ran
+    pub phases: Vec<PhaseAllocationResults>,
+    /// Peak resident set size of the process in bytes, where known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_rss_bytes: Option<u64>,
+}
+
+impl ProfileResults {
+    /// Create ProfileResults from the engine's run profile.
+    pub fn from_engine(profile: &RunProfile) -> Self {
+        Self {
+            phases: profile
+                .phases
+                .iter()
+                .map(|phase| PhaseAllocationResults {
+                    phase: phase.phase,
+                    allocations: phase.count.allocations,
+                    allocated_bytes: phase.count.bytes,
+                })
+                .collect(),
+            peak_rss_bytes: profile.peak_rss_bytes,
+        }
+    }
+}
+
+/// Requests the server turned away with HTTP 429, and how long the test
+/// waited for the limit to lift.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitResults {
+    /// Rate limited responses, counting each attempt
+    pub responses: usize,
+    /// Total time spent waiting in milliseconds
+    pub waited_ms: f64,
+}
+
+impl RateLimitResults {
+    /// Create RateLimitResults from the engine's rate limit summary.
+    pub fn from_engine(summary: &RateLimitSummary) -> Self {
+        Self {
+            responses: summary.responses,
+            waited_ms: summary.waited.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+/// A change of the local address the test server was reached from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkChangeResults {
+    /// Phase that was running when the change was noticed
+    pub phase: TestPhase,
+    /// Local address before, absent if there was no route or the results
+    /// were anonymized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<IpAddr>,
+    /// Local address after, absent if the route was lost or the results
+    /// were anonymized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<IpAddr>,
+    /// Whether the affected measurements were run again
+    pub restarted: bool,
+}
+
+impl NetworkChangeResults {
+    /// Create NetworkChangeResults from the engine's network change.
+    pub fn from_engine(change: &NetworkChange) -> Self {
+        Self {
+            phase: change.phase,
+            from: change.from,
+            to: change.to,
+            restarted: change.restarted,
+        }
+    }
+}
+
+/// Addresses the server's hostname resolved to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DnsResults {
+    /// Hostname that was looked up
+    pub host: String,
+    /// Every address in the answer; connections used the first IPv4 one
+    pub addresses: Vec<IpAddr>,
+    /// Name server chosen with `--resolver`, absent when the system's
+    /// resolvers answered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolver: Option<String>,
+    /// Time the lookup took in ms
+    pub lookup_ms: f64,
+}
+
+impl DnsResults {
+    /// Create DnsResults from the engine's endpoint lookup.
+    pub fn from_engine(lookup: &DnsLookup) -> Self {
+        Self {
+            host: lookup.host.clone(),
+            addresses: lookup.addresses.clone(),
+            resolver: lookup.name_server.map(|server| server.to_string()),
+            lookup_ms: lookup.duration.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+/// Happy Eyeballs races between the server's IPv6 and IPv4 addresses.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DualStackResults {
+    /// Address family the test ran over (`ipv6` or `ipv4`)
+    pub winner: String,
+    /// Median IPv6 connect time in ms (absent if IPv6 never connected)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv6_connect_ms: Option<f64>,
+    /// Median IPv4 connect time in ms (absent if IPv4 never connected)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv4_connect_ms: Option<f64>,
+    /// Races run
+    pub races: usize,
+    /// Races IPv6 won
+    pub ipv6_wins: usize,
+    /// Races in which IPv6 didn't connect
+    pub ipv6_failures: usize,
+    /// Races in which IPv4 didn't connect
+    pub ipv4_failures: usize,
+    /// Set when IPv6 was broken or slower than IPv4 in every race
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub advisory: Option<Advisory>,
+}
+
+impl DualStackResults {
+    /// Create DualStackResults from the engine's connection races.
+    pub fn from_engine(dual_stack: &DualStack) -> Self {
+        let ms = |ipv6: bool| {
+            dual_stack
+                .median_connect(ipv6)
+                .map(|connect| connect.as_secs_f64() * 1000.0)
+        };
+        Self {
+            winner: if dual_stack.winner().is_ipv6() {
+                "ipv6"
+            } else {
+                "ipv4"
+            }
+            .to_string(),
+            ipv6_connect_ms: ms(true),
+            ipv4_connect_ms: ms(false),
+            races: dual_stack.races.len(),
+            ipv6_wins: dual_stack.ipv6_wins(),
+            ipv6_failures: dual_stack.failures(true),
+            ipv4_failures: dual_stack.failures(false),
+            advisory: dual_stack.advisory(),
+        }
+    }
+}
+
+/// Path MTU found by don't-fragment ICMP probes toward the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PathMtuResults {
+    /// Largest IP packet in bytes that made the round trip
+    pub bytes: usize,
+    /// Echo requests sent during the search
+    pub probes: usize,
+}
+
+impl PathMtuResults {
+    /// Create PathMtuResults from the engine's path MTU search.
+    pub fn from_engine(path_mtu: &PathMtu) -> Self {
+        Self { bytes: path_mtu.bytes, probes: path_mtu.probes }
+    }
+
+    /// Whether the path carries less than a full Ethernet frame.
+    pub fn is_reduced(&self) -> bool {
+        self.bytes < ETHERNET_MTU
+    }
+}
+
+/// Time each stage of a connection setup took, in milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetupResults {
+    pub dns_ms: f64,
+    pub tcp_ms: f64,
+    pub tls_ms: f64,
+    /// Sum of the three stages
+    pub total_ms: f64,
+    /// Whether the TLS session was resumed
+    pub resumed: bool,
+}
+
+impl SetupResults {
+    /// Create SetupResults from the engine's stage timings.
+    pub fn from_engine(setup: &SetupTiming) -> Self {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        Self {
+            dns_ms: ms(setup.dns),
+            tcp_ms: ms(setup.tcp),
+            tls_ms: ms(setup.tls),
+            total_ms: ms(setup.total()),
+            resumed: setup.resumed,
+        }
+    }
+}
+
+/// Connection setup with nothing cached against setups with the DNS
+/// answer cached and the TLS session resumed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HandshakeResults {
+    /// First connection: uncached lookup and a full TLS handshake
+    pub cold: SetupResults,
+    /// Median of the later connections, stage by stage
+    pub warm: SetupResults,
+    /// Warm connections the median covers
+    pub warm_rounds: usize,
+    /// Time a warm setup saved over the cold one in milliseconds
+    pub saved_ms: f64,
+}
+
+impl HandshakeResults {
+    /// Create HandshakeResults from the engine's setup comparison.
+    pub fn from_engine(comparison: &HandshakeComparison) -> Self {
+        Self {
+            cold: SetupResults::from_engine(&comparison.cold),
+            warm: SetupResults::from_engine(&comparison.warm),
+            warm_rounds: comparison.warm_rounds,
+            saved_ms: comparison.saved().as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+/// Throughput and latency with downloads and uploads running at once.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DuplexResults {
+    /// Download speed in Mbps during duplex load
+    pub download_mbps: f64,
+    /// Upload speed in Mbps during duplex load
+    pub upload_mbps: f64,
+    /// Median latency in ms during duplex load
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+    /// Jitter in ms during duplex load
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jitter_ms: Option<f64>,
+    /// Sum of the duplex speeds over the sum of the one-way speeds; 1.0
+    /// for a link that carries both directions at full speed
+    pub efficiency: f64,
+}
+
+impl DuplexResults {
+    /// Create DuplexResults from the engine's duplex phase.
+    pub fn from_engine(duplex: &DuplexResult) -> Self {
+        Self {
+            download_mbps: duplex.download_mbps,
+            upload_mbps: duplex.upload_mbps,
+            latency_ms: duplex.latency_ms,
+            jitter_ms: duplex.jitter_ms,
+            efficiency: duplex.efficiency,
+        }
+    }
+}
+
+/// Server processing time, beyond the round trip, above which TTFB is
+/// flagged as slow.
+pub const SLOW_SERVER_MS: f64 = 100.0;
+
+/// Time to first byte across the run's download requests.
+///
+/// TTFB is measured on an open connection, so it is the round trip plus
+/// however long the server took to start answering. A TTFB well above
+/// idle latency with good bandwidth points at the server or CDN rather
+/// than the access link.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponsivenessResults {
+    /// Median TTFB in ms
+    pub ttfb_median_ms: f64,
+    /// 95th percentile TTFB in ms
+    pub ttfb_p95_ms: f64,
+    /// Requests the TTFB figures cover
+    pub samples: usize,
+    /// Latency probes' TTFB split into network and edge processing time,
+    /// when the edge reported the latter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub breakdown: Option<RttBreakdownResults>,
+}
+
+impl ResponsivenessResults {
+    /// Create ResponsivenessResults from the engine's TTFB summary.
+    pub fn from_engine(ttfb: &TtfbSummary) -> Self {
+        Self {
+            ttfb_median_ms: ttfb.median_ms,
+            ttfb_p95_ms: ttfb.p95_ms,
+            samples: ttfb.samples,
+            breakdown: ttfb
+                .breakdown
+                .as_ref()
+                .map(RttBreakdownResults::from_engine),
+        }
+    }
+
+    /// Whether the server rather than the link is slow to answer: its
+    /// reported processing time exceeds [`SLOW_SERVER_MS`] or, when it
+    /// reported none, the median TTFB exceeds the idle round trip by that
+    /// much.
+    pub fn is_slow(&self, idle_latency_ms: f64) -> bool {
+        match &self.breakdown {
+            Some(breakdown) => breakdown.server_median_ms > SLOW_SERVER_MS,
+            None => self.ttfb_median_ms - idle_latency_ms > SLOW_SERVER_MS,
+        }
+    }
+}
+
+/// Latency probes' TTFB split into the round trip to the edge and the
+/// edge's processing time, from its `Server-Timing` header.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RttBreakdownResults {
+    /// Median network round trip in ms: TTFB less processing time
+    pub network_median_ms: f64,
+    /// Median processing time the edge reported in ms
+    pub server_median_ms: f64,
+    /// Probes that reported a processing time
+    pub samples: usize,
+}
+
+impl RttBreakdownResults {
+    /// Create RttBreakdownResults from the engine's breakdown.
+    pub fn from_engine(breakdown: &RttBreakdown) -> Self {
+        Self {
+            network_median_ms: breakdown.network_median_ms,
+            server_median_ms: breakdown.server_median_ms,
+            samples: breakdown.samples,
+        }
+    }
+}
+
+/// Kernel TCP statistics aggregated over one size block.
+///
+/// Retransmissions and delivery rate are counted by the sender, so they
+/// are most meaningful for uploads.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TcpResults {
+    /// Connections the statistics were read from
+    pub samples: usize,
+    /// Total segments retransmitted across those connections
+    pub retransmits: u64,
+    /// Lowest round-trip time the kernel observed, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_rtt_ms: Option<f64>,
+    /// Median of the final delivery rates, in Mbps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delivery_rate_mbps: Option<f64>,
+    /// Largest congestion window reached, in segments
+    pub max_cwnd: u32,
+    /// Data segments sent across those connections, retransmissions
+    /// included, where the kernel reports it for all of them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_segments: Option<u64>,
+}
+
+impl TcpResults {
+    /// Aggregate per-connection snapshots; `None` when there are none.
+    pub fn from_engine(stats: &[TcpStats]) -> Option<Self> {
+        if stats.is_empty() {
+            return None;
+        }
+
+        let mut delivery_rates: Vec<f64> =
+            stats.iter().filter_map(|s| s.delivery_rate_mbps).collect();
+
+        Some(Self {
+            samples: stats.len(),
+            retransmits: stats.iter().map(|s| s.retransmits as u64).sum(),
+            min_rtt_ms: stats
+                .iter()
+                .filter_map(|s| s.min_rtt_ms)
+                .min_by(f64::total_cmp),
+            delivery_rate_mbps: median_f64(&mut delivery_rates),
+            max_cwnd: stats.iter().map(|s| s.cwnd).max().unwrap_or(0),
+            data_segments: stats
+                .iter()
+                .map(|s| s.data_segs_out.map(u64::from))
+                .sum(),
+        })
+    }
+}
+
+/// Retransmission ratio above which a direction is flagged.
+///
+/// Loss-based congestion control retransmits a fraction of a percent on
+/// a healthy link; several percent means real loss that hurts calls and
+/// games far more than the throughput suggests.
+pub const HIGH_RETRANSMIT_RATIO: f64 = 0.02;
+
+/// Fewest data segments a ratio is computed from.
+///
+/// The client only sends data in the direction it uploads; for downloads
+/// it sends little more than the requests, which says nothing about the
+/// link.
+const MIN_RETRANSMIT_SEGMENTS: u64 = 1000;
+
+/// Goodput against wire throughput for one direction, from the kernel's
+/// TCP statistics.
+///
+/// The measured speed counts application bytes only (goodput). Segments
+/// the kernel had to send again also crossed the link, so the wire
+/// carried more than that.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RetransmissionResults {
+    /// Share of data segments that were retransmissions (0.0 to 1.0)
+    pub ratio: f64,
+    /// Application throughput in Mbps, i.e. the measured speed
+    pub goodput_mbps: f64,
+    /// Throughput on the wire including retransmissions, in Mbps
+    pub wire_mbps: f64,
+    /// Whether `ratio` exceeds [`HIGH_RETRANSMIT_RATIO`]
+    pub high: bool,
+}
+
+impl RetransmissionResults {
+    /// Combine the TCP statistics of every size; `None` when too few data
+    /// segments were sent to tell.
+    pub fn from_measurements(
+        measurements: &[SizeMeasurement],
+        speed_mbps: f64,
+    ) -> Option<Self> {
+        let (retransmits, segments) = measurements
+            .iter()
+            .filter_map(|m| m.tcp.as_ref())
+            .filter_map(|tcp| Some((tcp.retransmits, tcp.data_segments?)))
+            .fold((0, 0), |(r, s), (retransmits, segments)| {
+                (r + retransmits, s + segments)
+            });
+        if segments < MIN_RETRANSMIT_SEGMENTS {
+            return None;
+        }
+
+        let ratio = (retransmits as f64 / segments as f64).min(1.0);
+        let wire_mbps =
+            if ratio < 1.0 { speed_mbps / (1.0 - ratio) } else { speed_mbps };
+        Some(Self {
+            ratio,
+            goodput_mbps: speed_mbps,
+            wire_mbps,
+            high: ratio > HIGH_RETRANSMIT_RATIO,
+        })
+    }
+
+    /// The ratio as a percentage.
+    pub fn percent(&self) -> f64 {
+        self.ratio * 100.0
+    }
+}
+
+/// How busy the CPU kept one direction's transfers.
+///
+/// A transfer runs on a single thread, TLS included, so a transfer that
+/// kept a whole core busy went as fast as the CPU allowed, whatever the
+/// link could carry.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CpuResults {
+    /// Share of one core the largest transfers kept busy (0.0 to 1.0)
+    pub core_utilization: f64,
+    /// Whether `core_utilization` exceeds [`CPU_BOUND_UTILIZATION`], so
+    /// the CPU rather than the link may have capped the speed
+    pub bound: bool,
+}
+
+impl CpuResults {
+    /// Take the utilization of the largest size measured. Smaller
+    /// transfers spend most of their time waiting on round trips, which
+    /// says nothing about the CPU keeping up with the link.
+    pub fn from_measurements(
+        measurements: &[SizeMeasurement],
+    ) -> Option<Self> {
+        let core_utilization = measurements
+            .iter()
+            .filter(|m| m.core_utilization.is_some())
+            .max_by_key(|m| m.bytes)?
+            .core_utilization?;
+        Some(Self {
+            core_utilization,
+            bound: core_utilization > CPU_BOUND_UTILIZATION,
+        })
+    }
+
+    /// The utilization as a percentage of one core.
+    pub fn percent(&self) -> f64 {
+        self.core_utilization * 100.0
+    }
+}
+
+/// Effective socket buffer sizes over one size block.
+///
+/// The largest value seen is reported, since Linux grows buffers that
+/// weren't set explicitly as a transfer proceeds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SocketBufferResults {
+    /// Largest `SO_SNDBUF` in bytes
+    pub send_bytes: usize,
+    /// Largest `SO_RCVBUF` in bytes
+    pub recv_bytes: usize,
+}
+
+impl SocketBufferResults {
+    /// Aggregate per-connection readings; `None` when there are none.
+    pub fn from_engine(buffers: &[SocketBuffers]) -> Option<Self> {
+        Some(Self {
+            send_bytes: buffers.iter().map(|b| b.send_bytes).max()?,
+            recv_bytes: buffers.iter().map(|b| b.recv_bytes).max()?,
+        })
     }
 }
 
 /// Connection metadata.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ConnectionMeta {
-    /// Client IP address
+    /// Client IP address, or its network when anonymized
     pub ip: String,
     /// Country code (e.g., "US", "GB")
     pub country: String,
-    /// ISP/Organization name
-    pub isp: String,
-    /// Autonomous System Number
-    pub asn: i64,
+    /// ISP/Organization name, omitted when anonymized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub isp: Option<String>,
+    /// Autonomous System Number, omitted when anonymized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asn: Option<i64>,
+    /// Proxy the test was routed through (credentials removed)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
 }
 
 impl ConnectionMeta {
     /// Create a new ConnectionMeta.
     pub fn new(ip: String, country: String, isp: String, asn: i64) -> Self {
-        Self { ip, country, isp, asn }
+        Self { ip, country, isp: Some(isp), asn: Some(asn), proxy: None }
+    }
+
+    /// Set the proxy the test was routed through.
+    pub fn with_proxy(mut self, proxy: Option<String>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Remove what identifies the client: the IP is truncated to its
+    /// network, and the ISP, ASN and proxy are dropped.
+    pub fn anonymize(&mut self) {
+        self.ip = anonymize_ip(&self.ip);
+        self.isp = None;
+        self.asn = None;
+        self.proxy = None;
+    }
+}
+
+/// The /24 (IPv4) or /48 (IPv6) network containing `ip`, e.g.
+/// "203.0.113.0/24". Anything that isn't an IP address is dropped.
+fn anonymize_ip(ip: &str) -> String {
+    match ip.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{}/24", Ipv4Addr::new(a, b, c, 0))
+        }
+        Ok(IpAddr::V6(ip)) => {
+            let mut segments = ip.segments();
+            segments[3..].fill(0);
+            format!("{}/48", Ipv6Addr::from(segments))
+        }
+        Err(_) => String::new(),
+    }
+}
+
+/// Where the client egresses from, as printed by `cloud-speed meta`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EgressResults {
+    /// Client IP, ISP and ASN
+    pub connection: ConnectionMeta,
+    /// City the client IP is geolocated to
+    pub city: String,
+    /// Region the client IP is geolocated to
+    pub region: String,
+    /// Cloudflare location that served the request
+    pub server: ServerLocation,
+}
+
+/// Metadata identifying the probe that ran the test.
+///
+/// Lets results aggregated from several machines be told apart.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunMeta {
+    /// Hostname of the machine running the test
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Operating system (e.g., "linux", "macos", "windows")
+    pub os: String,
+    /// CPU architecture (e.g., "x86_64", "aarch64")
+    pub arch: String,
+    /// User-supplied `key=value` tags
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// Whether a captive portal or intercepting middlebox was detected
+    /// before the test
+    pub captive_portal_suspected: bool,
+    /// Whether the test looks to have run through a VPN or tunnel
+    pub vpn_suspected: bool,
+    /// What pointed to (or hinted at) a VPN
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vpn_signals: Vec<String>,
+    /// Whether the test ran through Cloudflare WARP, so it measured WARP
+    /// rather than the ISP
+    #[serde(default)]
+    pub warp: bool,
+    /// DSCP class measurement traffic was marked with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dscp: Option<String>,
+    /// Order the bandwidth blocks ran in, when not the default
+    /// interleaved order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<TestOrder>,
+    /// Whether request gaps and sizes were randomized
+    #[serde(default)]
+    pub pacing_jitter: bool,
+    /// Seconds the local clock was ahead of the server's before the test
+    /// (negative when behind)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_skew_secs: Option<i64>,
+    /// How far the local clock was stepped during the test in ms, when it
+    /// was; durations are measured with a monotonic clock regardless
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_step_ms: Option<i64>,
+    /// Requests the server rate limited, when it limited any; the test
+    /// took longer by the time spent waiting
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitResults>,
+    /// Whether the network changed while the test ran, so measurements
+    /// may span two networks
+    #[serde(default)]
+    pub network_changed: bool,
+    /// Each change noticed, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub network_changes: Vec<NetworkChangeResults>,
+    /// User-Agent the speed test requests were sent with (with
+    /// `--detailed`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// Whether the results were made up by `--simulate` rather than
+    /// measured
+    #[serde(default)]
+    pub simulated: bool,
+}
+
+impl RunMeta {
+    /// Capture metadata for the current machine.
+    pub fn detect() -> Self {
+        Self {
+            hostname: hostname::get()
+                .ok()
+                .and_then(|h| h.into_string().ok())
+                .filter(|h| !h.is_empty()),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            tags: BTreeMap::new(),
+            captive_portal_suspected: false,
+            vpn_suspected: false,
+            vpn_signals: Vec::new(),
+            warp: false,
+            dscp: None,
+            order: None,
+            pacing_jitter: false,
+            clock_skew_secs: None,
+            clock_step_ms: None,
+            rate_limit: None,
+            network_changed: false,
+            network_changes: Vec::new(),
+            user_agent: None,
+            simulated: false,
+        }
+    }
+
+    /// Record the clock skew found before the test and any clock step
+    /// during it.
+    pub fn with_clock(
+        mut self,
+        skew_secs: Option<i64>,
+        step: Option<TimeDelta>,
+    ) -> Self {
+        self.clock_skew_secs = skew_secs;
+        self.clock_step_ms = step.map(|step| step.num_milliseconds());
+        self
+    }
+
+    /// Record the rate limiting the test ran into.
+    pub fn with_rate_limit(
+        mut self,
+        rate_limit: Option<&RateLimitSummary>,
+    ) -> Self {
+        self.rate_limit = rate_limit.map(RateLimitResults::from_engine);
+        self
+    }
+
+    /// Record the network changes noticed during the test.
+    pub fn with_network_changes(mut self, changes: &[NetworkChange]) -> Self {
+        self.network_changed = !changes.is_empty();
+        self.network_changes =
+            changes.iter().map(NetworkChangeResults::from_engine).collect();
+        self
+    }
+
+    /// Set the user-supplied tags.
+    pub fn with_tags(mut self, tags: BTreeMap<String, String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Record whether interception was suspected before the test.
+    pub fn with_captive_portal_suspected(mut self, suspected: bool) -> Self {
+        self.captive_portal_suspected = suspected;
+        self
+    }
+
+    /// Record the outcome of VPN detection.
+    pub fn with_vpn(mut self, detection: VpnDetection) -> Self {
+        self.vpn_suspected = detection.suspected;
+        self.vpn_signals = detection.signals;
+        self
+    }
+
+    /// Record whether the test ran through Cloudflare WARP.
+    pub fn with_warp(mut self, warp: bool) -> Self {
+        self.warp = warp;
+        self
+    }
+
+    /// Record the DSCP class measurement traffic was marked with.
+    pub fn with_dscp(mut self, dscp: Option<Dscp>) -> Self {
+        self.dscp = dscp.map(|dscp| dscp.to_string());
+        self
+    }
+
+    /// Set the order the bandwidth blocks ran in.
+    pub fn with_order(mut self, order: TestOrder) -> Self {
+        self.order = (order != TestOrder::default()).then_some(order);
+        self
+    }
+
+    /// Record whether request gaps and sizes were randomized.
+    pub fn with_pacing_jitter(mut self, enabled: bool) -> Self {
+        self.pacing_jitter = enabled;
+        self
+    }
+
+    /// Record the User-Agent the speed test requests were sent with.
+    pub fn with_user_agent(mut self, user_agent: Option<String>) -> Self {
+        self.user_agent = user_agent;
+        self
+    }
+
+    /// Record whether the results were simulated.
+    pub fn with_simulated(mut self, simulated: bool) -> Self {
+        self.simulated = simulated;
+        self
     }
 }
 
@@ -178,7 +1410,7 @@ impl ConnectionMeta {
 /// # Requirements
 /// - Include idle and loaded latency/jitter for both directions
 /// - _Requirements: 2.4, 3.1, 6.6, 6.7_
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LatencyResults {
     /// Idle latency (median) in milliseconds
     pub idle_ms: f64,
@@ -197,6 +1429,11 @@ pub struct LatencyResults {
     /// Loaded jitter during uploads in milliseconds
     #[serde(skip_serializing_if = "Option::is_none")]
     pub loaded_up_jitter_ms: Option<f64>,
+    /// Probe that measured idle latency ("http" or "icmp")
+    pub probe: LatencyProbe,
+    /// Background load streams running during loaded latency measurement
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_streams: Option<usize>,
 }
 
 impl LatencyResults {
@@ -216,9 +1453,23 @@ impl LatencyResults {
             loaded_down_jitter_ms,
             loaded_up_ms,
             loaded_up_jitter_ms,
+            probe: LatencyProbe::Http,
+            load_streams: None,
         }
     }
 
+    /// Record the probe that measured idle latency.
+    pub fn with_probe(mut self, probe: LatencyProbe) -> Self {
+        self.probe = probe;
+        self
+    }
+
+    /// Record the synthetic load level; zero streams is left unset.
+    pub fn with_load_streams(mut self, load_streams: usize) -> Self {
+        self.load_streams = (load_streams > 0).then_some(load_streams);
+        self
+    }
+
     /// Create LatencyResults from engine output.
     pub fn from_engine(engine: &EngineLatencyResults) -> Self {
         Self {
@@ -228,7 +1479,10 @@ impl LatencyResults {
             loaded_down_jitter_ms: engine.loaded_down_jitter_ms,
             loaded_up_ms: engine.loaded_up_ms,
             loaded_up_jitter_ms: engine.loaded_up_jitter_ms,
+            probe: engine.probe,
+            load_streams: None,
         }
+        .with_load_streams(engine.load_streams)
     }
 
     /// Create LatencyResults with only idle measurements.
@@ -240,6 +1494,8 @@ impl LatencyResults {
             loaded_down_jitter_ms: None,
             loaded_up_ms: None,
             loaded_up_jitter_ms: None,
+            probe: LatencyProbe::Http,
+            load_streams: None,
         }
     }
 }
@@ -251,14 +1507,37 @@ impl LatencyResults {
 /// # Requirements
 /// - Include final speed and per-size measurements
 /// - _Requirements: 4.7_
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BandwidthResults {
-    /// Final speed in Mbps (90th percentile of all measurements)
+    /// Final speed in Mbps (`percentile` of all measurements)
     pub speed_mbps: f64,
+    /// Mean speed in Mbps across all measurements
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean_mbps: Option<f64>,
+    /// Fastest single measurement in Mbps, comparable to the peak other
+    /// tools report
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_mbps: Option<f64>,
+    /// Percentile `speed_mbps` was taken at (0.0 to 1.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percentile: Option<f64>,
     /// Per-size measurement results
     pub measurements: Vec<SizeMeasurement>,
     /// Whether early termination was applied
     pub early_terminated: bool,
+    /// Byte pattern of the uploaded payload (uploads only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<PayloadPattern>,
+    /// Goodput against wire throughput, where TCP statistics allow
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retransmission: Option<RetransmissionResults>,
+    /// CPU use of the transfers, where measurable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<CpuResults>,
+    /// Background load streams sharing the link with the measured
+    /// requests, which lower the speed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub load_streams: Option<usize>,
 }
 
 impl BandwidthResults {
@@ -268,25 +1547,64 @@ impl BandwidthResults {
         measurements: Vec<SizeMeasurement>,
         early_terminated: bool,
     ) -> Self {
-        Self { speed_mbps, measurements, early_terminated }
+        Self {
+            speed_mbps,
+            mean_mbps: None,
+            max_mbps: None,
+            percentile: None,
+            measurements,
+            early_terminated,
+            payload: None,
+            retransmission: None,
+            cpu: None,
+            load_streams: None,
+        }
+    }
+
+    /// Label for how `speed_mbps` was aggregated, e.g. "p90".
+    pub fn aggregation(&self) -> Option<String> {
+        self.percentile.map(|p| format!("p{}", (p * 100.0).round()))
+    }
+
+    /// Record the synthetic load level; zero streams is left unset.
+    pub fn with_load_streams(mut self, load_streams: usize) -> Self {
+        self.load_streams = (load_streams > 0).then_some(load_streams);
+        self
+    }
+
+    /// Record the payload pattern used for the upload.
+    pub fn with_payload(mut self, payload: Option<PayloadPattern>) -> Self {
+        self.payload = payload;
+        self
     }
 
     /// Create BandwidthResults from engine output.
     pub fn from_engine(engine: &EngineBandwidthResults) -> Self {
+        let measurements: Vec<SizeMeasurement> = engine
+            .measurements
+            .iter()
+            .map(SizeMeasurement::from_engine)
+            .collect();
         Self {
             speed_mbps: engine.speed_mbps,
-            measurements: engine
-                .measurements
-                .iter()
-                .map(SizeMeasurement::from_engine)
-                .collect(),
+            mean_mbps: engine.mean_mbps,
+            max_mbps: engine.max_mbps,
+            percentile: Some(engine.percentile),
+            retransmission: RetransmissionResults::from_measurements(
+                &measurements,
+                engine.speed_mbps,
+            ),
+            cpu: CpuResults::from_measurements(&measurements),
+            measurements,
             early_terminated: engine.early_terminated,
+            payload: engine.payload,
+            load_streams: None,
         }
     }
 }
 
 /// Results from a single bandwidth measurement set (one file size).
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SizeMeasurement {
     /// Size of the data block in bytes
     pub bytes: u64,
@@ -294,12 +1612,72 @@ pub struct SizeMeasurement {
     pub speed_mbps: f64,
     /// Number of measurements performed
     pub count: usize,
+    /// Rule that ended this size block early, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub terminated_by: Option<TerminationRule>,
+    /// Kernel TCP statistics for this size, where supported
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp: Option<TcpResults>,
+    /// Effective socket buffer sizes for this size
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socket_buffers: Option<SocketBufferResults>,
+    /// Iterations that needed retries, recorded with `--detailed`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<MeasurementFailure>,
+    /// Cloudflare headers of each response, recorded with `--detailed`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edge_headers: Vec<EdgeHeaders>,
+    /// Median latency under load while this size ran, in ms
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loaded_latency_ms: Option<f64>,
+    /// Jitter under load while this size ran, in ms
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loaded_jitter_ms: Option<f64>,
+    /// Redirects the measurements of this size followed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redirects: Option<RedirectResults>,
+    /// Median share of one core the measurements of this size kept busy
+    /// (0.0 to 1.0), where measurable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_utilization: Option<f64>,
+}
+
+/// Redirects followed by the measurements of one size.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RedirectResults {
+    /// Number of redirects followed
+    pub count: u32,
+    /// Total time spent following them in ms, left out of the speeds
+    pub time_ms: f64,
+}
+
+impl RedirectResults {
+    /// Create from engine output; `None` when nothing was redirected.
+    pub fn from_engine(engine: &EngineSizeMeasurement) -> Option<Self> {
+        (engine.redirects > 0).then_some(Self {
+            count: engine.redirects,
+            time_ms: engine.redirect_ms,
+        })
+    }
 }
 
 impl SizeMeasurement {
     /// Create a new SizeMeasurement.
     pub fn new(bytes: u64, speed_mbps: f64, count: usize) -> Self {
-        Self { bytes, speed_mbps, count }
+        Self {
+            bytes,
+            speed_mbps,
+            count,
+            terminated_by: None,
+            tcp: None,
+            socket_buffers: None,
+            failures: Vec::new(),
+            edge_headers: Vec::new(),
+            loaded_latency_ms: None,
+            loaded_jitter_ms: None,
+            redirects: None,
+            core_utilization: None,
+        }
     }
 
     /// Create SizeMeasurement from engine output.
@@ -308,12 +1686,25 @@ impl SizeMeasurement {
             bytes: engine.bytes,
             speed_mbps: engine.speed_mbps,
             count: engine.count,
+            terminated_by: engine.terminated_by,
+            tcp: TcpResults::from_engine(&engine.tcp_stats),
+            socket_buffers: SocketBufferResults::from_engine(
+                &engine.socket_buffers,
+            ),
+            failures: engine.failures.clone(),
+            edge_headers: engine.edge_headers.clone(),
+            loaded_latency_ms: median_f64(
+                &mut engine.loaded_latencies.clone(),
+            ),
+            loaded_jitter_ms: jitter_f64(&engine.loaded_latencies),
+            redirects: RedirectResults::from_engine(engine),
+            core_utilization: median_f64(&mut engine.core_utilization.clone()),
         }
     }
 }
 
 /// Packet loss measurement results.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PacketLossResults {
     /// Packet loss ratio (0.0 to 1.0)
     pub ratio: f64,
@@ -328,6 +1719,39 @@ pub struct PacketLossResults {
     /// Average round-trip time in milliseconds (if available)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub avg_rtt_ms: Option<f64>,
+    /// Kind of server the loss was measured against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<LossProvider>,
+    /// Number of packets answered after a later packet's answer
+    #[serde(default)]
+    pub reordered: usize,
+    /// Share of received packets that arrived out of order (0.0 to 1.0)
+    #[serde(default)]
+    pub reorder_ratio: f64,
+    /// Number of extra copies of answers received
+    #[serde(default)]
+    pub duplicates: usize,
+    /// Loss of each batch in order, recorded with `--detailed`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub batches: Vec<PacketLossBatch>,
+}
+
+/// Loss within one batch of a packet loss measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PacketLossBatch {
+    /// Packets sent in the batch
+    pub sent: usize,
+    /// Packets of the batch that went unanswered
+    pub lost: usize,
+    /// Loss ratio of the batch (0.0 to 1.0)
+    pub ratio: f64,
+}
+
+impl PacketLossBatch {
+    /// Create a PacketLossBatch from engine output.
+    pub fn from_engine(batch: &BatchLoss) -> Self {
+        Self { sent: batch.sent, lost: batch.lost(), ratio: batch.ratio() }
+    }
 }
 
 impl PacketLossResults {
@@ -346,9 +1770,39 @@ impl PacketLossResults {
             packets_lost,
             packets_received,
             avg_rtt_ms,
+            provider: None,
+            reordered: 0,
+            reorder_ratio: 0.0,
+            duplicates: 0,
+            batches: Vec::new(),
         }
     }
 
+    /// Record the kind of server the loss was measured against.
+    pub fn with_provider(mut self, provider: Option<LossProvider>) -> Self {
+        self.provider = provider;
+        self
+    }
+
+    /// Record how many answers arrived out of order or more than once.
+    pub fn with_order(mut self, reordered: usize, duplicates: usize) -> Self {
+        self.reordered = reordered;
+        self.reorder_ratio = if self.packets_received > 0 {
+            reordered as f64 / self.packets_received as f64
+        } else {
+            0.0
+        };
+        self.duplicates = duplicates;
+        self
+    }
+
+    /// Record the loss of each batch.
+    pub fn with_batches(mut self, batches: &[BatchLoss]) -> Self {
+        self.batches =
+            batches.iter().map(PacketLossBatch::from_engine).collect();
+        self
+    }
+
     /// Create PacketLossResults from engine output.
     pub fn from_engine(engine: &EnginePacketLossResult) -> Self {
         Self {
@@ -358,12 +1812,17 @@ impl PacketLossResults {
             packets_lost: engine.packets_lost,
             packets_received: engine.packets_received,
             avg_rtt_ms: engine.avg_rtt_ms,
+            provider: None,
+            reordered: engine.reordered,
+            reorder_ratio: engine.reorder_ratio(),
+            duplicates: engine.duplicates,
+            batches: Vec::new(),
         }
     }
 }
 
 /// AIM (Aggregated Internet Measurement) scores for JSON output.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AimScoresOutput {
     /// Quality score for video streaming
     pub streaming: String,
@@ -373,9 +1832,38 @@ pub struct AimScoresOutput {
     pub video_conferencing: String,
     /// Overall quality score (minimum of all)
     pub overall: String,
+    /// Per-factor breakdown behind each score
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<AimScoreDetails>,
+    /// Whether loaded latency stayed under the working latency targets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_latency: Option<WorkingLatencyCheck>,
+    /// Letter grade across the scores, bufferbloat and loss
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grade: Option<GradeSummary>,
 }
 
 impl AimScoresOutput {
+    /// Score `metrics` against `thresholds`, with the per-factor
+    /// breakdown, working latency check and grade.
+    pub fn score(
+        metrics: &ConnectionMetrics,
+        thresholds: &ScoringThresholds,
+    ) -> Self {
+        let details = crate::scoring::calculate_aim_score_details_with(
+            metrics, thresholds,
+        );
+        Self::from_details(&details)
+            .with_working_latency(crate::scoring::check_working_latency(
+                metrics,
+                &thresholds.working_latency,
+            ))
+            .with_grade(Some(crate::scoring::calculate_grade(
+                &details.scores(),
+                metrics,
+            )))
+    }
+
     /// Create AimScoresOutput from AimScores.
     pub fn from_aim_scores(scores: &AimScores) -> Self {
         Self {
@@ -385,8 +1873,125 @@ impl AimScoresOutput {
                 &scores.video_conferencing,
             ),
             overall: quality_score_to_string(&scores.overall()),
+            details: None,
+            working_latency: None,
+            grade: None,
+        }
+    }
+
+    /// Create AimScoresOutput from a per-factor breakdown, keeping the
+    /// breakdown as `details`.
+    pub fn from_details(details: &AimScoreDetails) -> Self {
+        Self {
+            details: Some(*details),
+            ..Self::from_aim_scores(&details.scores())
+        }
+    }
+
+    /// Record the working latency check.
+    pub fn with_working_latency(
+        mut self,
+        check: Option<WorkingLatencyCheck>,
+    ) -> Self {
+        self.working_latency = check;
+        self
+    }
+
+    /// Record the overall letter grade.
+    pub fn with_grade(mut self, grade: Option<GradeSummary>) -> Self {
+        self.grade = grade;
+        self
+    }
+}
+
+/// A threshold a metric was required to meet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssertionResult {
+    /// Metric checked: "download_mbps", "upload_mbps" or "latency_ms"
+    pub metric: String,
+    /// The minimum speed or maximum latency required
+    pub threshold: f64,
+    /// The measured value, if the metric was measured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+    /// Whether the value was measured and met the threshold
+    pub pass: bool,
+}
+
+impl AssertionResult {
+    /// Check each given threshold against `results`: speeds must reach
+    /// theirs and latency must stay at or under its own. A metric that
+    /// wasn't measured fails.
+    pub fn check_all(
+        results: &SpeedTestResults,
+        download_mbps: Option<f64>,
+        upload_mbps: Option<f64>,
+        latency_ms: Option<f64>,
+    ) -> Vec<Self> {
+        // (name, threshold, measured, higher is better)
+        let candidates = [
+            (
+                "download_mbps",
+                download_mbps,
+                results.download.as_ref().map(|d| d.speed_mbps),
+                true,
+            ),
+            (
+                "upload_mbps",
+                upload_mbps,
+                results.upload.as_ref().map(|u| u.speed_mbps),
+                true,
+            ),
+            ("latency_ms", latency_ms, Some(results.latency.idle_ms), false),
+        ];
+        candidates
+            .into_iter()
+            .filter_map(|(metric, threshold, value, higher_is_better)| {
+                let threshold = threshold?;
+                let pass = value.is_some_and(|value| {
+                    if higher_is_better {
+                        value >= threshold
+                    } else {
+                        value <= threshold
+                    }
+                });
+                Some(Self {
+                    metric: metric.to_string(),
+                    threshold,
+                    value,
+                    pass,
+                })
+            })
+            .collect()
+    }
+
+    /// Unit the metric is measured in.
+    pub fn unit(&self) -> &'static str {
+        if self.metric.ends_with("_ms") {
+            "ms"
+        } else {
+            "Mbps"
         }
     }
+
+    /// What the check found, e.g. "download_mbps 40.00 Mbps is below the
+    /// 50.00 Mbps threshold".
+    pub fn describe(&self) -> String {
+        let unit = self.unit();
+        let Some(value) = self.value else {
+            return format!("{} was not measured", self.metric);
+        };
+        let relation = match (self.pass, unit) {
+            (true, "ms") => "is within",
+            (false, "ms") => "exceeds",
+            (true, _) => "meets",
+            (false, _) => "is below",
+        };
+        format!(
+            "{} {:.2} {} {} the {:.2} {} threshold",
+            self.metric, value, unit, relation, self.threshold, unit
+        )
+    }
 }
 
 /// Convert QualityScore to a lowercase string for JSON output.
@@ -402,6 +2007,23 @@ fn quality_score_to_string(score: &QualityScore) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::errors::ErrorKind;
+    use crate::retry::FailedAttempt;
+
+    #[test]
+    fn test_estimates() {
+        assert_eq!(megabytes_per_sec(100.0), 12.5);
+
+        let times = estimates(100.0);
+        assert_eq!(times.len(), 2);
+        assert_eq!(times[0].label, "1 GB file");
+        assert_eq!(times[0].duration, Duration::from_secs(80));
+        assert_eq!(times[1].label, "50 GB game");
+        assert_eq!(times[1].duration, Duration::from_secs(4000));
+
+        assert!(estimates(0.0).is_empty());
+        assert!(estimates(f64::NAN).is_empty());
+    }
 
     #[test]
     fn test_server_location_new() {
@@ -423,8 +2045,417 @@ mod tests {
         );
         assert_eq!(meta.ip, "192.168.1.1");
         assert_eq!(meta.country, "US");
-        assert_eq!(meta.isp, "Example ISP");
-        assert_eq!(meta.asn, 12345);
+        assert_eq!(meta.isp.as_deref(), Some("Example ISP"));
+        assert_eq!(meta.asn, Some(12345));
+        assert!(meta.proxy.is_none());
+    }
+
+    #[test]
+    fn test_anonymize_ip() {
+        assert_eq!(anonymize_ip("203.0.113.77"), "203.0.113.0/24");
+        assert_eq!(
+            anonymize_ip("2001:db8:85a3:1234:5678:8a2e:370:7334"),
+            "2001:db8:85a3::/48"
+        );
+        assert_eq!(anonymize_ip("not an ip"), "");
+    }
+
+    #[test]
+    fn test_anonymized_results() {
+        let results = full_results().anonymized();
+        let json = serde_json::to_value(&results).unwrap();
+
+        assert_eq!(json["connection"]["ip"], "192.168.1.0/24");
+        assert!(json["connection"].get("isp").is_none());
+        assert!(json["connection"].get("asn").is_none());
+        assert!(json["connection"].get("proxy").is_none());
+        assert!(json.get("meas_id").is_none());
+        assert!(json["meta"].get("hostname").is_none());
+        assert!(json["meta"].get("vpn_signals").is_none());
+        let edge = &json["upload"]["measurements"][0]["edge_headers"][0];
+        assert!(edge.get("meta").is_none());
+        assert!(edge.get("ray").is_some());
+        assert_eq!(json["connection"]["country"], "US");
+    }
+
+    #[test]
+    fn test_anonymized_drops_route_addresses() {
+        let mut results = full_results();
+        results.meta =
+            Some(RunMeta::detect().with_network_changes(&[NetworkChange {
+                phase: TestPhase::Download,
+                from: Some("2001:db8::20".parse().unwrap()),
+                to: Some("192.168.1.20".parse().unwrap()),
+                restarted: true,
+            }]));
+        let json = serde_json::to_value(results.anonymized()).unwrap();
+
+        let change = &json["meta"]["network_changes"][0];
+        assert!(change.get("from").is_none());
+        assert!(change.get("to").is_none());
+        assert_eq!(change["phase"], "download");
+        assert_eq!(json["meta"]["network_changed"], true);
+    }
+
+    #[test]
+    fn test_anonymized_drops_network_comparison() {
+        let comparison = |scope, region: &str| {
+            Some(RegionalComparison {
+                scope,
+                region: region.to_string(),
+                source: "api.cloudflare.com".to_string(),
+                metrics: vec![],
+            })
+        };
+
+        let results = full_results()
+            .with_regional(comparison(RegionScope::Asn, "AS12345"))
+            .anonymized();
+        assert!(results.regional.is_none());
+
+        let results = full_results()
+            .with_regional(comparison(RegionScope::Country, "US"))
+            .anonymized();
+        assert_eq!(results.regional.unwrap().region, "US");
+    }
+
+    #[test]
+    fn test_connection_meta_proxy_serialization() {
+        let meta = ConnectionMeta::new(
+            "192.168.1.1".to_string(),
+            "US".to_string(),
+            "Example ISP".to_string(),
+            12345,
+        );
+        let json = serde_json::to_string(&meta).unwrap();
+        assert!(!json.contains("proxy"));
+
+        let meta = meta.with_proxy(Some("http://proxy:3128".to_string()));
+        let json = serde_json::to_string(&meta).unwrap();
+        assert!(json.contains("\"proxy\":\"http://proxy:3128\""));
+    }
+
+    #[test]
+    fn test_egress_results_serialization() {
+        let egress = EgressResults {
+            connection: ConnectionMeta::new(
+                "192.168.1.1".to_string(),
+                "US".to_string(),
+                "Example ISP".to_string(),
+                12345,
+            ),
+            city: "Austin".to_string(),
+            region: "Texas".to_string(),
+            server: ServerLocation::new(
+                "Dallas".to_string(),
+                "DFW".to_string(),
+            ),
+        };
+        let json: serde_json::Value = serde_json::to_value(&egress).unwrap();
+        assert_eq!(json["connection"]["asn"], 12345);
+        assert_eq!(json["server"]["iata"], "DFW");
+        assert_eq!(json["city"], "Austin");
+        assert!(json["server"].get("ip").is_none());
+    }
+
+    #[test]
+    fn test_latency_results_load_streams() {
+        let latency = LatencyResults::idle_only(15.5, None);
+        let json = serde_json::to_string(&latency).unwrap();
+        assert!(!json.contains("load_streams"));
+
+        let latency = latency.with_load_streams(0);
+        assert_eq!(latency.load_streams, None);
+
+        let latency = latency.with_load_streams(4);
+        let json = serde_json::to_string(&latency).unwrap();
+        assert!(json.contains("\"load_streams\":4"));
+
+        let download = BandwidthResults::new(80.0, vec![], false);
+        assert_eq!(download.clone().with_load_streams(0).load_streams, None);
+        let json =
+            serde_json::to_string(&download.with_load_streams(4)).unwrap();
+        assert!(json.contains("\"load_streams\":4"));
+    }
+
+    #[test]
+    fn test_aim_scores_output_details() {
+        use crate::scoring::calculate_aim_score_details_with;
+
+        let metrics = ConnectionMetrics::new(100.0, 50.0, 150.0, 5.0);
+        let output =
+            AimScoresOutput::from_details(&calculate_aim_score_details_with(
+                &metrics,
+                &ScoringThresholds::default(),
+            ));
+        assert_eq!(output.gaming, "poor");
+
+        let json = serde_json::to_value(&output).unwrap();
+        assert_eq!(json["details"]["gaming"]["latency"], "poor");
+
+        let output = AimScoresOutput::from_aim_scores(&AimScores::new(
+            QualityScore::Good,
+            QualityScore::Good,
+            QualityScore::Good,
+        ));
+        let json = serde_json::to_value(&output).unwrap();
+        assert!(json.get("details").is_none());
+    }
+
+    #[test]
+    fn test_run_meta_detect() {
+        let meta = RunMeta::detect();
+        assert_eq!(meta.os, std::env::consts::OS);
+        assert_eq!(meta.arch, std::env::consts::ARCH);
+        assert!(meta.tags.is_empty());
+        assert!(!meta.captive_portal_suspected);
+        assert!(
+            meta.with_captive_portal_suspected(true).captive_portal_suspected
+        );
+    }
+
+    #[test]
+    fn test_size_measurement_loaded_latency() {
+        let engine = EngineSizeMeasurement {
+            bytes: 1_000_000,
+            speed_mbps: 94.0,
+            count: 8,
+            measurements: Vec::new(),
+            triggered_early_termination: false,
+            terminated_by: None,
+            tcp_stats: Vec::new(),
+            socket_buffers: Vec::new(),
+            failures: Vec::new(),
+            edge_headers: Vec::new(),
+            loaded_latencies: vec![40.0, 50.0, 44.0],
+            redirects: 0,
+            redirect_ms: 0.0,
+            core_utilization: Vec::new(),
+        };
+        let size = SizeMeasurement::from_engine(&engine);
+        assert_eq!(size.loaded_latency_ms, Some(44.0));
+        assert_eq!(size.loaded_jitter_ms, Some(8.0));
+        assert_eq!(size.redirects, None);
+
+        let redirected =
+            SizeMeasurement::from_engine(&EngineSizeMeasurement {
+                redirects: 2,
+                redirect_ms: 31.5,
+                ..engine.clone()
+            });
+        assert_eq!(
+            redirected.redirects,
+            Some(RedirectResults { count: 2, time_ms: 31.5 })
+        );
+
+        let quiet = SizeMeasurement::from_engine(&EngineSizeMeasurement {
+            loaded_latencies: Vec::new(),
+            ..engine
+        });
+        assert_eq!(quiet.loaded_latency_ms, None);
+        let json = serde_json::to_value(&quiet).unwrap();
+        assert!(json.get("loaded_latency_ms").is_none());
+    }
+
+    #[test]
+    fn test_rescored_applies_new_thresholds() {
+        let results = SpeedTestResults::new(
+            ServerLocation::new("Dallas".to_string(), "DFW".to_string()),
+            ConnectionMeta::new(
+                "203.0.113.7".to_string(),
+                "US".to_string(),
+                "Example ISP".to_string(),
+                64500,
+            ),
+            LatencyResults::new(40.0, Some(2.0), None, None, None, None),
+            BandwidthResults::new(100.0, vec![], false),
+            BandwidthResults::new(50.0, vec![], false),
+            None,
+            AimScoresOutput::from_aim_scores(&AimScores::new(
+                QualityScore::Poor,
+                QualityScore::Poor,
+                QualityScore::Poor,
+            )),
+        );
+
+        let rescored = results.clone().rescored(&ScoringThresholds::default());
+        // 40ms idle latency is Good for gaming by default
+        assert_eq!(rescored.scores.as_ref().unwrap().gaming, "good");
+        assert!(rescored.scores.as_ref().unwrap().details.is_some());
+        assert!(rescored.scores.as_ref().unwrap().grade.is_some());
+
+        let mut strict = ScoringThresholds::default();
+        strict.gaming.latency = crate::scoring::Tiers::new(10.0, 20.0, 30.0);
+        let rescored = results.rescored(&strict);
+        assert_eq!(rescored.scores.as_ref().unwrap().gaming, "poor");
+    }
+
+    #[test]
+    fn test_with_phases_omits_skipped_sections() {
+        let results = SpeedTestResults::new(
+            ServerLocation::new("Dallas".to_string(), "DFW".to_string()),
+            ConnectionMeta::new(
+                "203.0.113.7".to_string(),
+                "US".to_string(),
+                "Example ISP".to_string(),
+                64500,
+            ),
+            LatencyResults::new(40.0, Some(2.0), None, None, None, None),
+            BandwidthResults::new(100.0, vec![], false),
+            BandwidthResults::new(50.0, vec![], false),
+            None,
+            AimScoresOutput::from_aim_scores(&AimScores::new(
+                QualityScore::Good,
+                QualityScore::Good,
+                QualityScore::Good,
+            )),
+        );
+
+        let full = results.clone().with_phases(PhaseSelection::All);
+        assert!(full.download.is_some() && full.upload.is_some());
+        assert!(full.scores.is_some());
+
+        let download = results.clone().with_phases(PhaseSelection::Download);
+        assert!(download.download.is_some());
+        assert!(download.upload.is_none());
+        assert!(download.scores.is_none());
+
+        let latency = results.with_phases(PhaseSelection::Latency);
+        let json = serde_json::to_value(&latency).unwrap();
+        assert!(json.get("download").is_none());
+        assert!(json.get("upload").is_none());
+        assert!(json.get("scores").is_none());
+
+        // Partial results read back, and rescoring leaves them unscored
+        let parsed = SpeedTestResults::from_value(json).unwrap();
+        assert!(parsed.download.is_none());
+        let rescored = parsed.rescored(&ScoringThresholds::default());
+        assert!(rescored.scores.is_none());
+    }
+
+    #[test]
+    fn test_assertion_results() {
+        let results = SpeedTestResults::new(
+            ServerLocation::new("Dallas".to_string(), "DFW".to_string()),
+            ConnectionMeta::new(
+                "203.0.113.7".to_string(),
+                "US".to_string(),
+                "Example ISP".to_string(),
+                64500,
+            ),
+            LatencyResults::new(40.0, Some(2.0), None, None, None, None),
+            BandwidthResults::new(100.0, vec![], false),
+            BandwidthResults::new(50.0, vec![], false),
+            None,
+            AimScoresOutput::from_aim_scores(&AimScores::new(
+                QualityScore::Good,
+                QualityScore::Good,
+                QualityScore::Good,
+            )),
+        );
+
+        let assertions = AssertionResult::check_all(
+            &results,
+            Some(100.0),
+            Some(60.0),
+            Some(30.0),
+        );
+        let passed: Vec<_> = assertions.iter().map(|a| a.pass).collect();
+        assert_eq!(passed, vec![true, false, false]);
+        assert_eq!(
+            assertions[1].describe(),
+            "upload_mbps 50.00 Mbps is below the 60.00 Mbps threshold"
+        );
+        assert_eq!(
+            assertions[2].describe(),
+            "latency_ms 40.00 ms exceeds the 30.00 ms threshold"
+        );
+        assert!(
+            AssertionResult::check_all(&results, None, None, None).is_empty()
+        );
+
+        // A direction that didn't run can't meet its threshold
+        let latency = results.with_phases(PhaseSelection::Latency);
+        let assertions =
+            AssertionResult::check_all(&latency, Some(1.0), None, None);
+        assert!(!assertions[0].pass);
+        assert_eq!(assertions[0].describe(), "download_mbps was not measured");
+    }
+
+    #[test]
+    fn test_run_meta_serialization() {
+        let meta = RunMeta {
+            hostname: Some("probe-1".to_string()),
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            tags: BTreeMap::new(),
+            captive_portal_suspected: false,
+            vpn_suspected: false,
+            vpn_signals: Vec::new(),
+            warp: false,
+            dscp: None,
+            order: None,
+            pacing_jitter: false,
+            clock_skew_secs: None,
+            clock_step_ms: None,
+            rate_limit: None,
+            network_changed: false,
+            network_changes: Vec::new(),
+            user_agent: None,
+            simulated: false,
+        };
+        let json = serde_json::to_value(&meta).unwrap();
+        assert_eq!(json["hostname"], "probe-1");
+        assert_eq!(json["captive_portal_suspected"], false);
+        assert_eq!(json["pacing_jitter"], false);
+        assert!(json.get("tags").is_none());
+        assert!(json.get("dscp").is_none());
+        assert!(json.get("user_agent").is_none());
+        assert!(json.get("order").is_none());
+        assert!(json.get("clock_skew_secs").is_none());
+        assert!(json.get("clock_step_ms").is_none());
+        assert!(json.get("rate_limit").is_none());
+        assert_eq!(json["network_changed"], false);
+        assert!(json.get("network_changes").is_none());
+        assert_eq!(json["vpn_suspected"], false);
+        assert!(json.get("vpn_signals").is_none());
+        assert_eq!(json["simulated"], false);
+
+        let mut tags = BTreeMap::new();
+        tags.insert("site".to_string(), "nyc".to_string());
+        let json = serde_json::to_value(
+            meta.with_tags(tags)
+                .with_dscp(Some(Dscp::EF))
+                .with_order(TestOrder::Concurrent)
+                .with_pacing_jitter(true)
+                .with_network_changes(&[NetworkChange {
+                    phase: TestPhase::Upload,
+                    from: Some("192.168.1.20".parse().unwrap()),
+                    to: None,
+                    restarted: true,
+                }]),
+        )
+        .unwrap();
+        assert_eq!(json["network_changed"], true);
+        assert_eq!(json["network_changes"][0]["phase"], "upload");
+        assert_eq!(json["network_changes"][0]["from"], "192.168.1.20");
+        assert!(json["network_changes"][0].get("to").is_none());
+        assert_eq!(json["network_changes"][0]["restarted"], true);
+        assert_eq!(json["tags"]["site"], "nyc");
+        assert_eq!(json["pacing_jitter"], true);
+        assert_eq!(json["dscp"], "ef");
+        assert_eq!(json["order"], "concurrent");
+    }
+
+    #[test]
+    fn test_latency_results_probe() {
+        let latency = LatencyResults::idle_only(15.5, None);
+        let json = serde_json::to_value(&latency).unwrap();
+        assert_eq!(json["probe"], "http");
+
+        let latency = latency.with_probe(LatencyProbe::Icmp);
+        let json = serde_json::to_value(&latency).unwrap();
+        assert_eq!(json["probe"], "icmp");
     }
 
     #[test]
@@ -460,6 +2491,19 @@ mod tests {
         assert!((bandwidth.speed_mbps - 80.0).abs() < 0.001);
         assert_eq!(bandwidth.measurements.len(), 2);
         assert!(!bandwidth.early_terminated);
+        assert!(bandwidth.payload.is_none());
+    }
+
+    #[test]
+    fn test_bandwidth_results_payload_serialization() {
+        let download = BandwidthResults::new(80.0, vec![], false);
+        let json = serde_json::to_value(&download).unwrap();
+        assert!(json.get("payload").is_none());
+
+        let upload = BandwidthResults::new(40.0, vec![], false)
+            .with_payload(Some(PayloadPattern::Random));
+        let json = serde_json::to_value(&upload).unwrap();
+        assert_eq!(json["payload"], "random");
     }
 
     #[test]
@@ -478,6 +2522,26 @@ mod tests {
         assert_eq!(pl.packets_sent, 1000);
         assert_eq!(pl.packets_lost, 50);
         assert_eq!(pl.packets_received, 950);
+
+        let pl = pl.with_order(19, 3);
+        assert!((pl.reorder_ratio - 0.02).abs() < 0.001);
+        assert_eq!(pl.duplicates, 3);
+    }
+
+    #[test]
+    fn test_packet_loss_batches() {
+        let pl = PacketLossResults::new(0.05, 200, 10, 190, None);
+        let json = serde_json::to_value(&pl).unwrap();
+        assert!(json.get("batches").is_none());
+
+        let pl = pl.with_batches(&[
+            BatchLoss { sent: 100, received: 100 },
+            BatchLoss { sent: 100, received: 90 },
+        ]);
+        let json = serde_json::to_value(&pl).unwrap();
+        assert_eq!(json["batches"][0]["lost"], 0);
+        assert_eq!(json["batches"][1]["lost"], 10);
+        assert_eq!(json["batches"][1]["ratio"], 0.1);
     }
 
     #[test]
@@ -522,6 +2586,9 @@ mod tests {
             gaming: "good".to_string(),
             video_conferencing: "good".to_string(),
             overall: "good".to_string(),
+            details: None,
+            working_latency: None,
+            grade: None,
         };
 
         let results = SpeedTestResults::new(
@@ -567,6 +2634,9 @@ mod tests {
             gaming: "great".to_string(),
             video_conferencing: "great".to_string(),
             overall: "great".to_string(),
+            details: None,
+            working_latency: None,
+            grade: None,
         };
 
         let results = SpeedTestResults::new(
@@ -585,4 +2655,586 @@ mod tests {
         assert!(json.contains("\"ratio\""));
         assert!(json.contains("\"percent\""));
     }
+
+    #[test]
+    fn test_tls_results_from_engine() {
+        use std::time::Duration;
+
+        let info = TlsInfo {
+            version: "TLSv1.3".to_string(),
+            cipher_suite: "TLS13_AES_128_GCM_SHA256".to_string(),
+            alpn: Some("http/1.1".to_string()),
+            cert_chain_len: 3,
+            cert_verify_duration: Some(Duration::from_micros(1500)),
+            handshake_duration: Duration::from_millis(25),
+            resumed: true,
+            backend: TlsBackend::Rustls,
+            trust: TrustSource::Bundled,
+        };
+        let tls = TlsResults::from_engine(&info);
+
+        assert_eq!(tls.version, "TLSv1.3");
+        assert_eq!(tls.cipher_suite, "TLS13_AES_128_GCM_SHA256");
+        assert_eq!(tls.alpn.as_deref(), Some("http/1.1"));
+        assert_eq!(tls.cert_chain_len, 3);
+        assert!((tls.cert_verify_ms.unwrap() - 1.5).abs() < 1e-9);
+        assert!((tls.handshake_ms - 25.0).abs() < 1e-9);
+        assert!(tls.resumed);
+
+        let json = serde_json::to_string(&tls).unwrap();
+        assert!(json.contains("\"version\":\"TLSv1.3\""));
+        assert!(json.contains("\"alpn\":\"http/1.1\""));
+        assert!(json.contains("\"backend\":\"rustls\""));
+        assert!(json.contains("\"trust\":\"bundled\""));
+
+        // Results saved before the backend was recorded
+        let old: TlsResults = serde_json::from_str(
+            r#"{"version":"TLSv1.3","cipher_suite":"","cert_chain_len":2,
+                "handshake_ms":20.0,"resumed":false}"#,
+        )
+        .unwrap();
+        assert_eq!(old.backend, TlsBackend::Rustls);
+        assert_eq!(old.trust, TrustSource::System);
+    }
+
+    #[test]
+    fn test_server_location_ip_serialization() {
+        let server = ServerLocation::new(
+            "San Francisco".to_string(),
+            "SFO".to_string(),
+        );
+        let json = serde_json::to_value(&server).unwrap();
+        assert!(json.get("ip").is_none());
+
+        let server = server.with_ip(Some("104.16.0.1".to_string()));
+        let json = serde_json::to_value(&server).unwrap();
+        assert_eq!(json["ip"], "104.16.0.1");
+    }
+
+    #[test]
+    fn test_speed_test_results_tls_skipped_when_none() {
+        let results = SpeedTestResults::new(
+            ServerLocation::new(
+                "San Francisco".to_string(),
+                "SFO".to_string(),
+            ),
+            ConnectionMeta::new(
+                "192.168.1.1".to_string(),
+                "US".to_string(),
+                "Example ISP".to_string(),
+                12345,
+            ),
+            LatencyResults::idle_only(15.5, Some(2.3)),
+            BandwidthResults::new(100.0, vec![], false),
+            BandwidthResults::new(50.0, vec![], false),
+            None,
+            AimScoresOutput {
+                streaming: "great".to_string(),
+                gaming: "great".to_string(),
+                video_conferencing: "great".to_string(),
+                overall: "great".to_string(),
+                details: None,
+                working_latency: None,
+                grade: None,
+            },
+        );
+
+        let json = serde_json::to_string(&results).unwrap();
+        assert!(!json.contains("\"tls\""));
+        assert!(!json.contains("\"regression\""));
+    }
+
+    #[test]
+    fn test_size_measurement_terminated_by_serialization() {
+        let mut size = SizeMeasurement::new(1_000_000, 95.0, 4);
+        let json = serde_json::to_string(&size).unwrap();
+        assert!(!json.contains("terminated_by"));
+
+        size.terminated_by = Some(TerminationRule::Convergence);
+        let json = serde_json::to_string(&size).unwrap();
+        assert!(json.contains("\"terminated_by\":\"convergence\""));
+    }
+
+    #[test]
+    fn test_tcp_results_from_engine() {
+        assert!(TcpResults::from_engine(&[]).is_none());
+
+        let stats = [
+            TcpStats {
+                retransmits: 2,
+                rtt_ms: 20.0,
+                min_rtt_ms: Some(12.0),
+                cwnd: 40,
+                delivery_rate_mbps: Some(90.0),
+                data_segs_out: Some(700),
+            },
+            TcpStats {
+                retransmits: 5,
+                rtt_ms: 25.0,
+                min_rtt_ms: Some(11.5),
+                cwnd: 64,
+                delivery_rate_mbps: Some(80.0),
+                data_segs_out: Some(800),
+            },
+            TcpStats {
+                retransmits: 0,
+                rtt_ms: 18.0,
+                min_rtt_ms: None,
+                cwnd: 10,
+                delivery_rate_mbps: None,
+                data_segs_out: Some(300),
+            },
+        ];
+        let tcp = TcpResults::from_engine(&stats).unwrap();
+
+        assert_eq!(tcp.samples, 3);
+        assert_eq!(tcp.retransmits, 7);
+        assert_eq!(tcp.min_rtt_ms, Some(11.5));
+        assert_eq!(tcp.delivery_rate_mbps, Some(85.0));
+        assert_eq!(tcp.max_cwnd, 64);
+        assert_eq!(tcp.data_segments, Some(1800));
+
+        let mut size = SizeMeasurement::new(1_000_000, 95.0, 3);
+        let json = serde_json::to_value(&size).unwrap();
+        assert!(json.get("tcp").is_none());
+
+        size.tcp = Some(tcp);
+        let json = serde_json::to_value(&size).unwrap();
+        assert_eq!(json["tcp"]["retransmits"], 7);
+    }
+
+    #[test]
+    fn test_retransmission_results_from_measurements() {
+        let tcp = |retransmits, data_segments| TcpResults {
+            samples: 4,
+            retransmits,
+            min_rtt_ms: None,
+            delivery_rate_mbps: None,
+            max_cwnd: 10,
+            data_segments,
+        };
+        let size = |tcp| SizeMeasurement {
+            tcp: Some(tcp),
+            ..SizeMeasurement::new(1_000_000, 45.0, 4)
+        };
+
+        // A download's requests are too few segments to judge by
+        let download = [size(tcp(0, Some(12)))];
+        assert!(RetransmissionResults::from_measurements(&download, 90.0)
+            .is_none());
+
+        let upload = [
+            size(tcp(10, Some(1_000))),
+            size(tcp(90, Some(1_000))),
+            size(tcp(50, None)),
+        ];
+        let retransmission =
+            RetransmissionResults::from_measurements(&upload, 47.5).unwrap();
+        assert!((retransmission.ratio - 0.05).abs() < 1e-9);
+        assert!((retransmission.percent() - 5.0).abs() < 1e-9);
+        assert_eq!(retransmission.goodput_mbps, 47.5);
+        assert!((retransmission.wire_mbps - 50.0).abs() < 1e-9);
+        assert!(retransmission.high);
+
+        let clean = [size(tcp(2, Some(2_000)))];
+        let retransmission =
+            RetransmissionResults::from_measurements(&clean, 50.0).unwrap();
+        assert!(!retransmission.high);
+    }
+
+    #[test]
+    fn test_cpu_results_from_largest_size() {
+        let size = |bytes, core_utilization| SizeMeasurement {
+            core_utilization,
+            ..SizeMeasurement::new(bytes, 900.0, 4)
+        };
+
+        assert_eq!(
+            CpuResults::from_measurements(&[size(100_000, None)]),
+            None
+        );
+
+        let sizes = [
+            size(100_000, Some(0.3)),
+            size(100_000_000, Some(0.95)),
+            size(250_000_000, None),
+        ];
+        let cpu = CpuResults::from_measurements(&sizes).unwrap();
+        assert_eq!(cpu.core_utilization, 0.95);
+        assert!(cpu.bound);
+        assert!((cpu.percent() - 95.0).abs() < 1e-9);
+
+        let cpu = CpuResults::from_measurements(&sizes[..1]).unwrap();
+        assert!(!cpu.bound);
+    }
+
+    #[test]
+    fn test_connection_metrics_use_retransmit_ratio() {
+        let latency = LatencyResults::idle_only(15.0, Some(2.0));
+        let download = BandwidthResults::new(100.0, vec![], false);
+        let mut upload = BandwidthResults::new(20.0, vec![], false);
+        assert_eq!(
+            connection_metrics(&latency, &download, &upload, None)
+                .retransmit_ratio,
+            None
+        );
+
+        upload.retransmission = Some(RetransmissionResults {
+            ratio: 0.04,
+            goodput_mbps: 20.0,
+            wire_mbps: 20.8,
+            high: true,
+        });
+        let metrics = connection_metrics(&latency, &download, &upload, None);
+        assert_eq!(metrics.retransmit_ratio, Some(0.04));
+    }
+
+    #[test]
+    fn test_socket_buffer_results_from_engine() {
+        assert!(SocketBufferResults::from_engine(&[]).is_none());
+
+        let buffers = [
+            SocketBuffers { send_bytes: 16_384, recv_bytes: 131_072 },
+            SocketBuffers { send_bytes: 87_040, recv_bytes: 65_536 },
+        ];
+        assert_eq!(
+            SocketBufferResults::from_engine(&buffers),
+            Some(SocketBufferResults {
+                send_bytes: 87_040,
+                recv_bytes: 131_072
+            })
+        );
+    }
+
+    /// Results with every optional section filled in.
+    fn full_results() -> SpeedTestResults {
+        use crate::history::regression::MetricBaseline;
+        use crate::scoring::FactorScores;
+        use ring::rand::SystemRandom;
+        use ring::signature::Ed25519KeyPair;
+
+        let factors = FactorScores {
+            download: Some(QualityScore::Great),
+            upload: Some(QualityScore::Good),
+            latency: Some(QualityScore::Average),
+            jitter: Some(QualityScore::Poor),
+            packet_loss: Some(QualityScore::Great),
+        };
+        let size = SizeMeasurement {
+            terminated_by: Some(TerminationRule::Convergence),
+            tcp: Some(TcpResults {
+                samples: 2,
+                retransmits: 3,
+                min_rtt_ms: Some(11.0),
+                delivery_rate_mbps: Some(90.0),
+                max_cwnd: 40,
+                data_segments: Some(2_000),
+            }),
+            socket_buffers: Some(SocketBufferResults {
+                send_bytes: 16_384,
+                recv_bytes: 131_072,
+            }),
+            failures: vec![MeasurementFailure {
+                iteration: 2,
+                attempts: 2,
+                recovered: true,
+                errors: vec![FailedAttempt {
+                    attempt: 1,
+                    kind: ErrorKind::Tls,
+                    status: None,
+                    error: "connection reset".to_string(),
+                    diagnostic: None,
+                }],
+            }],
+            edge_headers: vec![EdgeHeaders {
+                ray: Some("8a1b2c3d4e5f6789-DFW".to_string()),
+                cache_status: Some("DYNAMIC".to_string()),
+                server: Some("cloudflare".to_string()),
+                meta: BTreeMap::from([(
+                    "colo".to_string(),
+                    "DFW".to_string(),
+                )]),
+            }],
+            loaded_latency_ms: Some(48.0),
+            loaded_jitter_ms: Some(6.5),
+            ..SizeMeasurement::new(1_000_000, 94.0, 8)
+        };
+        let mut upload = BandwidthResults::new(20.0, vec![size.clone()], true);
+        upload.payload = Some(PayloadPattern::Zeros);
+        upload.mean_mbps = Some(18.0);
+        upload.max_mbps = Some(22.0);
+        upload.percentile = Some(0.9);
+        upload.retransmission = Some(RetransmissionResults {
+            ratio: 0.03,
+            goodput_mbps: 20.0,
+            wire_mbps: 20.6,
+            high: true,
+        });
+
+        let pkcs8 =
+            Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key = SigningKey::from_pkcs8(pkcs8.as_ref()).unwrap();
+
+        SpeedTestResults::new(
+            ServerLocation::new("Dallas".to_string(), "DFW".to_string())
+                .with_ip(Some("104.16.0.1".to_string())),
+            ConnectionMeta::new(
+                "192.168.1.1".to_string(),
+                "US".to_string(),
+                "Example ISP".to_string(),
+                12345,
+            )
+            .with_proxy(Some("http://proxy:3128".to_string())),
+            LatencyResults::new(
+                12.0,
+                Some(1.0),
+                Some(40.0),
+                Some(4.0),
+                Some(60.0),
+                Some(6.0),
+            )
+            .with_load_streams(4),
+            BandwidthResults::new(95.0, vec![size], false),
+            upload,
+            Some(PacketLossResults::new(0.01, 100, 1, 99, Some(15.0))),
+            AimScoresOutput::from_details(&AimScoreDetails {
+                streaming: factors,
+                gaming: factors,
+                video_conferencing: FactorScores::default(),
+            }),
+        )
+        .with_meas_id(Some("4815162342000001".to_string()))
+        .with_dns(Some(DnsResults {
+            host: "speed.cloudflare.com".to_string(),
+            addresses: vec![
+                "104.16.0.1".parse().unwrap(),
+                "2606:4700::6810:1".parse().unwrap(),
+            ],
+            resolver: Some("1.1.1.1:53".to_string()),
+            lookup_ms: 8.0,
+        }))
+        .with_dual_stack(Some(DualStackResults {
+            winner: "ipv4".to_string(),
+            ipv6_connect_ms: Some(85.0),
+            ipv4_connect_ms: Some(12.0),
+            races: 3,
+            ipv6_wins: 0,
+            ipv6_failures: 1,
+            ipv4_failures: 0,
+            advisory: Some(Advisory::Ipv6Slower),
+        }))
+        .with_tls(Some(TlsResults {
+            version: "TLSv1.3".to_string(),
+            cipher_suite: "TLS13_AES_128_GCM_SHA256".to_string(),
+            alpn: Some("http/1.1".to_string()),
+            cert_chain_len: 3,
+            cert_verify_ms: Some(2.0),
+            handshake_ms: 25.0,
+            resumed: false,
+            backend: TlsBackend::Rustls,
+            trust: TrustSource::System,
+        }))
+        .with_pacing(Some(PacingResults { pauses: 4, paused_ms: 1500.0 }))
+        .with_path_mtu(Some(PathMtuResults { bytes: 1492, probes: 12 }))
+        .with_handshake(Some(HandshakeResults {
+            cold: SetupResults {
+                dns_ms: 21.0,
+                tcp_ms: 14.0,
+                tls_ms: 45.0,
+                total_ms: 80.0,
+                resumed: false,
+            },
+            warm: SetupResults {
+                dns_ms: 0.1,
+                tcp_ms: 14.0,
+                tls_ms: 15.9,
+                total_ms: 30.0,
+                resumed: true,
+            },
+            warm_rounds: 3,
+            saved_ms: 50.0,
+        }))
+        .with_duplex(Some(DuplexResults {
+            download_mbps: 80.0,
+            upload_mbps: 15.0,
+            latency_ms: Some(70.0),
+            jitter_ms: Some(8.0),
+            efficiency: 0.83,
+        }))
+        .with_responsiveness(Some(ResponsivenessResults {
+            ttfb_median_ms: 30.0,
+            ttfb_p95_ms: 55.0,
+            samples: 40,
+            breakdown: Some(RttBreakdownResults {
+                network_median_ms: 24.0,
+                server_median_ms: 4.5,
+                samples: 20,
+            }),
+        }))
+        .with_meta(Some(RunMeta {
+            hostname: Some("probe-1".to_string()),
+            tags: BTreeMap::from([("site".to_string(), "nyc".to_string())]),
+            vpn_suspected: true,
+            vpn_signals: vec!["interface tun0".to_string()],
+            dscp: Some("ef".to_string()),
+            order: Some(TestOrder::Concurrent),
+            clock_skew_secs: Some(-2),
+            clock_step_ms: Some(3_600_000),
+            rate_limit: Some(RateLimitResults {
+                responses: 2,
+                waited_ms: 12_000.0,
+            }),
+            user_agent: Some("Mozilla/5.0".to_string()),
+            ..RunMeta::detect()
+        }))
+        .with_regression(Some(RegressionResults {
+            window_days: 30,
+            baseline_runs: 12,
+            metrics: vec![MetricBaseline {
+                metric: "download_mbps".into(),
+                value: 95.0,
+                median: 94.0,
+                iqr: 3.0,
+                samples: 12,
+                anomalous: false,
+            }],
+        }))
+        .signed(&key)
+        .unwrap()
+    }
+
+    /// Paths in `value` that `schema` doesn't declare, or that are
+    /// required and missing.
+    fn schema_mismatches(
+        value: &serde_json::Value,
+        schema: &serde_json::Value,
+        root: &serde_json::Value,
+        path: &str,
+    ) -> Vec<String> {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/$defs/");
+            return schema_mismatches(value, &root["$defs"][name], root, path);
+        }
+
+        let mut mismatches = Vec::new();
+        match value {
+            serde_json::Value::Object(object) => {
+                let properties = schema["properties"].as_object();
+                let additional = &schema["additionalProperties"];
+                for (key, field) in object {
+                    let path = format!("{}.{}", path, key);
+                    match properties.and_then(|p| p.get(key)) {
+                        Some(field_schema) => {
+                            mismatches.extend(schema_mismatches(
+                                field,
+                                field_schema,
+                                root,
+                                &path,
+                            ))
+                        }
+                        None if additional.is_object() => mismatches.extend(
+                            schema_mismatches(field, additional, root, &path),
+                        ),
+                        None => mismatches.push(path),
+                    }
+                }
+                for key in schema["required"].as_array().into_iter().flatten()
+                {
+                    let key = key.as_str().unwrap();
+                    if !object.contains_key(key) {
+                        mismatches.push(format!("{}.{} (missing)", path, key));
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    let path = format!("{}[{}]", path, i);
+                    mismatches.extend(schema_mismatches(
+                        item,
+                        &schema["items"],
+                        root,
+                        &path,
+                    ));
+                }
+            }
+            _ => {
+                if let Some(allowed) = schema["enum"].as_array() {
+                    if !allowed.contains(value) {
+                        mismatches.push(format!("{} = {}", path, value));
+                    }
+                }
+            }
+        }
+        mismatches
+    }
+
+    #[test]
+    fn test_schema_describes_output() {
+        let schema: serde_json::Value =
+            serde_json::from_str(JSON_SCHEMA).unwrap();
+        assert_eq!(
+            schema["properties"]["schema_version"]["const"],
+            SCHEMA_VERSION
+        );
+
+        for results in [full_results(), minimal_results()] {
+            let value = serde_json::to_value(&results).unwrap();
+            assert_eq!(value["schema_version"], SCHEMA_VERSION);
+            let mismatches = schema_mismatches(&value, &schema, &schema, "");
+            assert!(mismatches.is_empty(), "{:?}", mismatches);
+        }
+    }
+
+    #[test]
+    fn test_results_round_trip() {
+        let results = full_results();
+        let json = serde_json::to_string(&results).unwrap();
+        let parsed: SpeedTestResults = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, results);
+
+        let minimal = serde_json::to_string(&minimal_results()).unwrap();
+        let parsed: SpeedTestResults = serde_json::from_str(&minimal).unwrap();
+        assert!(parsed.meta.is_none());
+        assert!(parsed.scores.as_ref().unwrap().details.is_none());
+    }
+
+    #[test]
+    fn test_from_value_checks_schema_version() {
+        let results = full_results();
+        let mut value = serde_json::to_value(&results).unwrap();
+        assert_eq!(
+            SpeedTestResults::from_value(value.clone()).unwrap(),
+            results
+        );
+
+        value["schema_version"] = (SCHEMA_VERSION + 1).into();
+        let error = SpeedTestResults::from_value(value).unwrap_err();
+        assert!(error.to_string().contains("newer"), "{}", error);
+
+        let error = SpeedTestResults::from_value(serde_json::json!({"a": 1}))
+            .unwrap_err();
+        assert!(error.to_string().contains("missing field"), "{}", error);
+    }
+
+    /// Results with no optional sections.
+    fn minimal_results() -> SpeedTestResults {
+        SpeedTestResults::new(
+            ServerLocation::new("Dallas".to_string(), "DFW".to_string()),
+            ConnectionMeta::new(
+                "192.168.1.1".to_string(),
+                "US".to_string(),
+                "Example ISP".to_string(),
+                12345,
+            ),
+            LatencyResults::idle_only(12.0, None),
+            BandwidthResults::new(95.0, vec![], false),
+            BandwidthResults::new(20.0, vec![], false),
+            None,
+            AimScoresOutput::from_aim_scores(&AimScores {
+                streaming: QualityScore::Good,
+                gaming: QualityScore::Good,
+                video_conferencing: QualityScore::Good,
+            }),
+        )
+    }
 }
@@ -3,7 +3,10 @@
 //! This module provides utilities for retrying failed network operations
 //! with configurable retry counts and exponential backoff delays.
 
+use crate::cloudflare::tests::response::{BodyDiagnostic, BodyMismatch};
+use crate::errors::{classify_error, http_status, retry_after, ErrorKind};
 use log::{debug, warn};
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt;
 use std::future::Future;
@@ -19,6 +22,10 @@ pub const DEFAULT_BASE_DELAY_MS: u64 = 100;
 /// Maximum delay cap for exponential backoff (in milliseconds).
 pub const DEFAULT_MAX_DELAY_MS: u64 = 5000;
 
+/// Longest wait before retrying a rate limited request (in
+/// milliseconds), however long the server asked for.
+pub const MAX_RATE_LIMIT_DELAY_MS: u64 = 30_000;
+
 /// Configuration for retry behavior.
 #[derive(Debug, Clone)]
 pub struct RetryConfig {
@@ -59,6 +66,27 @@ impl RetryConfig {
         let capped_delay_ms = delay_ms.min(self.max_delay_ms);
         Duration::from_millis(capped_delay_ms)
     }
+
+    /// Calculate the delay before retrying after `failure`.
+    ///
+    /// Rate limited requests wait as long as the server's `Retry-After`
+    /// asked, or otherwise the maximum delay, since retrying sooner only
+    /// extends the limit. Either way the wait is capped at
+    /// [`MAX_RATE_LIMIT_DELAY_MS`].
+    pub fn delay_after(
+        &self,
+        attempt: u32,
+        failure: &FailedAttempt,
+    ) -> Duration {
+        let backoff = self.delay_for_attempt(attempt);
+        if failure.kind != ErrorKind::RateLimited {
+            return backoff;
+        }
+        retry_after(&failure.error)
+            .unwrap_or(Duration::from_millis(self.max_delay_ms))
+            .max(backoff)
+            .min(Duration::from_millis(MAX_RATE_LIMIT_DELAY_MS))
+    }
 }
 
 /// Error that wraps the last error from a series of retry attempts.
@@ -134,6 +162,24 @@ impl<T> RetryResult<T> {
     }
 }
 
+/// One failed attempt of a retried operation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailedAttempt {
+    /// Attempt number, starting at 1
+    pub attempt: u32,
+    /// What kind of failure it was
+    pub kind: ErrorKind,
+    /// HTTP status the server rejected the request with, if it did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// The error message
+    pub error: String,
+    /// What the response looked like, when its body didn't match its
+    /// size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnostic: Option<BodyDiagnostic>,
+}
+
 /// Execute an async operation with retry logic and exponential backoff.
 ///
 /// # Arguments
@@ -159,19 +205,35 @@ impl<T> RetryResult<T> {
 pub async fn retry_async<T, E, F, Fut>(
     config: &RetryConfig,
     operation_name: &str,
-    mut f: F,
+    f: F,
 ) -> RetryResult<T>
+where
+    E: Error + Send + Sync + 'static,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    retry_async_recording(config, operation_name, f).await.0
+}
+
+/// Like [`retry_async`], but also returns every failed attempt, including
+/// those followed by a successful retry.
+pub async fn retry_async_recording<T, E, F, Fut>(
+    config: &RetryConfig,
+    operation_name: &str,
+    mut f: F,
+) -> (RetryResult<T>, Vec<FailedAttempt>)
 where
     E: Error + Send + Sync + 'static,
     F: FnMut() -> Fut,
     Fut: Future<Output = Result<T, E>>,
 {
     let mut last_error: Option<Box<dyn Error + Send + Sync>> = None;
+    let mut failures = Vec::new();
     let total_attempts = config.max_retries + 1;
 
     for attempt in 0..total_attempts {
-        if attempt > 0 {
-            let delay = config.delay_for_attempt(attempt - 1);
+        if let Some(failure) = failures.last() {
+            let delay = config.delay_after(attempt - 1, failure);
             debug!(
                 "{}: Retry attempt {}/{} after {:?} delay",
                 operation_name, attempt, config.max_retries, delay
@@ -188,10 +250,17 @@ where
                         attempt + 1
                     );
                 }
-                return RetryResult::Success(result);
+                return (RetryResult::Success(result), failures);
             }
             Err(e) => {
                 let error_msg = e.to_string();
+                failures.push(FailedAttempt {
+                    attempt: attempt + 1,
+                    kind: classify_error(&e),
+                    status: http_status(&error_msg),
+                    error: error_msg.clone(),
+                    diagnostic: BodyMismatch::diagnostic_of(&e).cloned(),
+                });
                 last_error = Some(Box::new(e));
 
                 if attempt < config.max_retries {
@@ -211,10 +280,11 @@ where
         }
     }
 
-    RetryResult::Failed {
+    let result = RetryResult::Failed {
         last_error: last_error.unwrap(),
         attempts: total_attempts,
-    }
+    };
+    (result, failures)
 }
 
 /// Check if an error is retryable (network-related).
@@ -283,6 +353,35 @@ mod tests {
         assert_eq!(config.delay_for_attempt(5), Duration::from_millis(500));
     }
 
+    #[test]
+    fn test_rate_limited_retries_wait_longer() {
+        let config = RetryConfig::new(3, 100, 5000);
+        let failure = |error: &str| FailedAttempt {
+            attempt: 1,
+            kind: classify_error(&std::io::Error::other(error.to_string())),
+            status: http_status(error),
+            error: error.to_string(),
+            diagnostic: None,
+        };
+
+        let reset = failure("connection reset");
+        assert_eq!(config.delay_after(0, &reset), Duration::from_millis(100));
+
+        let limited = failure("HTTP 429 from speed test server");
+        assert_eq!(limited.status, Some(429));
+        assert_eq!(config.delay_after(0, &limited), Duration::from_secs(5));
+
+        let asked =
+            failure("HTTP 429 from speed test server (retry after 12s)");
+        assert_eq!(config.delay_after(0, &asked), Duration::from_secs(12));
+        let asked =
+            failure("HTTP 429 from speed test server (retry after 600s)");
+        assert_eq!(
+            config.delay_after(0, &asked),
+            Duration::from_millis(MAX_RATE_LIMIT_DELAY_MS)
+        );
+    }
+
     #[test]
     fn test_retry_result_is_success() {
         let success: RetryResult<i32> = RetryResult::Success(42);
@@ -293,10 +392,7 @@ mod tests {
     #[test]
     fn test_retry_result_is_failed() {
         let failed: RetryResult<i32> = RetryResult::Failed {
-            last_error: Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "test error",
-            )),
+            last_error: Box::new(std::io::Error::other("test error")),
             attempts: 3,
         };
         assert!(!failed.is_success());
@@ -309,10 +405,7 @@ mod tests {
         assert_eq!(success.ok(), Some(42));
 
         let failed: RetryResult<i32> = RetryResult::Failed {
-            last_error: Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "test error",
-            )),
+            last_error: Box::new(std::io::Error::other("test error")),
             attempts: 3,
         };
         assert_eq!(failed.ok(), None);
@@ -389,10 +482,7 @@ mod tests {
             async move {
                 let attempt = counter.fetch_add(1, Ordering::SeqCst);
                 if attempt < 2 {
-                    Err(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        "temporary failure",
-                    ))
+                    Err(std::io::Error::other("temporary failure"))
                 } else {
                     Ok(42)
                 }
@@ -405,6 +495,34 @@ mod tests {
         assert_eq!(counter.load(Ordering::SeqCst), 3);
     }
 
+    #[tokio::test]
+    async fn test_retry_async_recording_keeps_recovered_failures() {
+        let config = RetryConfig::new(3, 10, 100);
+        let counter = Arc::new(AtomicU32::new(0));
+        let counter_clone = counter.clone();
+
+        let (result, failures) =
+            retry_async_recording(&config, "test op", || {
+                let counter = counter_clone.clone();
+                async move {
+                    if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                        Err(std::io::Error::new(
+                            std::io::ErrorKind::TimedOut,
+                            "operation timed out",
+                        ))
+                    } else {
+                        Ok(42)
+                    }
+                }
+            })
+            .await;
+
+        assert_eq!(result.ok(), Some(42));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].attempt, 1);
+        assert_eq!(failures[0].kind, ErrorKind::Timeout);
+    }
+
     #[tokio::test]
     async fn test_retry_async_all_attempts_fail() {
         let config = RetryConfig::new(2, 10, 100);
@@ -415,10 +533,7 @@ mod tests {
             let counter = counter_clone.clone();
             async move {
                 counter.fetch_add(1, Ordering::SeqCst);
-                Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "persistent failure",
-                ))
+                Err(std::io::Error::other("persistent failure"))
             }
         })
         .await;
@@ -6,7 +6,7 @@
 //! The scoring is based on the methodology used by Cloudflare's speed test at
 //! speed.cloudflare.com.
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 /// Quality score categories for network performance.
 ///
@@ -14,7 +14,9 @@ use serde::Serialize;
 /// based on the measured network metrics.
 ///
 /// Variants are ordered from worst to best for correct derived Ord behavior.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
 #[serde(rename_all = "lowercase")]
 pub enum QualityScore {
     /// Poor performance - likely to experience significant issues
@@ -96,6 +98,9 @@ pub struct ConnectionMetrics {
     pub loaded_latency_down_ms: Option<f64>,
     /// Loaded latency during uploads in milliseconds, if measured
     pub loaded_latency_up_ms: Option<f64>,
+    /// Share of TCP data segments retransmitted (0.0 to 1.0), if the
+    /// kernel reported it
+    pub retransmit_ratio: Option<f64>,
 }
 
 impl ConnectionMetrics {
@@ -114,6 +119,7 @@ impl ConnectionMetrics {
             packet_loss: None,
             loaded_latency_down_ms: None,
             loaded_latency_up_ms: None,
+            retransmit_ratio: None,
         }
     }
 
@@ -133,6 +139,28 @@ impl ConnectionMetrics {
         self.loaded_latency_up_ms = up_ms;
         self
     }
+
+    /// Sets the TCP retransmission ratio.
+    pub fn with_retransmit_ratio(mut self, ratio: Option<f64>) -> Self {
+        self.retransmit_ratio = ratio;
+        self
+    }
+
+    /// Latency added under load: the worse loaded latency minus idle
+    /// latency, if loaded latency was measured.
+    pub fn bufferbloat_ms(&self) -> Option<f64> {
+        self.loaded_latency_down_ms
+            .into_iter()
+            .chain(self.loaded_latency_up_ms)
+            .max_by(f64::total_cmp)
+            .map(|loaded| (loaded - self.latency_ms).max(0.0))
+    }
+
+    /// Loss ratio to score: measured packet loss, or else the TCP
+    /// retransmission ratio, which a lossy link also drives up.
+    fn effective_loss(&self) -> Option<f64> {
+        self.packet_loss.or(self.retransmit_ratio)
+    }
 }
 
 // ============================================================================
@@ -243,77 +271,580 @@ mod video_conferencing_thresholds {
     pub const PACKET_LOSS_AVERAGE: f64 = 0.05;
 }
 
-/// Calculates AIM (Aggregated Internet Measurement) scores based on connection
-/// metrics.
+/// Boundaries between quality levels for a single metric.
 ///
-/// This function evaluates the connection quality for three use cases:
-/// - Streaming: Primarily based on download speed and latency
-/// - Gaming: Highly sensitive to latency, jitter, and packet loss
-/// - Video Conferencing: Requires balanced upload/download and low latency
+/// For metrics where higher is better (speeds) each value is the minimum
+/// for that level; where lower is better (latency, jitter, loss) it is
+/// the maximum.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tiers {
+    /// Boundary for Great quality
+    pub great: f64,
+    /// Boundary for Good quality
+    pub good: f64,
+    /// Boundary for Average quality
+    pub average: f64,
+}
+
+impl Tiers {
+    /// Creates tiers from the Great, Good and Average boundaries.
+    pub const fn new(great: f64, good: f64, average: f64) -> Self {
+        Self { great, good, average }
+    }
+
+    /// Rate a metric where higher values are better (e.g. speed).
+    fn rate_at_least(&self, value: f64) -> QualityScore {
+        if value >= self.great {
+            QualityScore::Great
+        } else if value >= self.good {
+            QualityScore::Good
+        } else if value >= self.average {
+            QualityScore::Average
+        } else {
+            QualityScore::Poor
+        }
+    }
+
+    /// Rate a metric where lower values are better (e.g. latency).
+    fn rate_at_most(&self, value: f64) -> QualityScore {
+        if value <= self.great {
+            QualityScore::Great
+        } else if value <= self.good {
+            QualityScore::Good
+        } else if value <= self.average {
+            QualityScore::Average
+        } else {
+            QualityScore::Poor
+        }
+    }
+
+    /// Check that the boundaries are usable for a higher-is-better metric.
+    fn validate_at_least(&self, name: &str) -> Result<(), String> {
+        self.validate_values(name)?;
+        if self.great >= self.good && self.good >= self.average {
+            Ok(())
+        } else {
+            Err(format!(
+                "{}: thresholds must satisfy great >= good >= average \
+                 (got {}, {}, {})",
+                name, self.great, self.good, self.average
+            ))
+        }
+    }
+
+    /// Check that the boundaries are usable for a lower-is-better metric.
+    fn validate_at_most(&self, name: &str) -> Result<(), String> {
+        self.validate_values(name)?;
+        if self.great <= self.good && self.good <= self.average {
+            Ok(())
+        } else {
+            Err(format!(
+                "{}: thresholds must satisfy great <= good <= average \
+                 (got {}, {}, {})",
+                name, self.great, self.good, self.average
+            ))
+        }
+    }
+
+    fn validate_values(&self, name: &str) -> Result<(), String> {
+        if [self.great, self.good, self.average]
+            .iter()
+            .all(|v| v.is_finite() && *v >= 0.0)
+        {
+            Ok(())
+        } else {
+            Err(format!(
+                "{}: thresholds must be finite and non-negative",
+                name
+            ))
+        }
+    }
+}
+
+/// Streaming thresholds. Missing fields keep their defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamingThresholds {
+    /// Download speed in Mbps
+    pub download: Tiers,
+    /// Latency in milliseconds
+    pub latency: Tiers,
+}
+
+impl Default for StreamingThresholds {
+    fn default() -> Self {
+        use streaming_thresholds::*;
+        Self {
+            download: Tiers::new(
+                DOWNLOAD_GREAT,
+                DOWNLOAD_GOOD,
+                DOWNLOAD_AVERAGE,
+            ),
+            latency: Tiers::new(LATENCY_GREAT, LATENCY_GOOD, LATENCY_AVERAGE),
+        }
+    }
+}
+
+/// Gaming thresholds. Missing fields keep their defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GamingThresholds {
+    /// Latency in milliseconds
+    pub latency: Tiers,
+    /// Jitter in milliseconds
+    pub jitter: Tiers,
+    /// Packet loss ratio (0.0 to 1.0)
+    pub packet_loss: Tiers,
+    /// Download speed in Mbps
+    pub download: Tiers,
+}
+
+impl Default for GamingThresholds {
+    fn default() -> Self {
+        use gaming_thresholds::*;
+        Self {
+            latency: Tiers::new(LATENCY_GREAT, LATENCY_GOOD, LATENCY_AVERAGE),
+            jitter: Tiers::new(JITTER_GREAT, JITTER_GOOD, JITTER_AVERAGE),
+            packet_loss: Tiers::new(
+                PACKET_LOSS_GREAT,
+                PACKET_LOSS_GOOD,
+                PACKET_LOSS_AVERAGE,
+            ),
+            download: Tiers::new(
+                DOWNLOAD_GREAT,
+                DOWNLOAD_GOOD,
+                DOWNLOAD_AVERAGE,
+            ),
+        }
+    }
+}
+
+/// Video conferencing thresholds. Missing fields keep their defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VideoConferencingThresholds {
+    /// Download speed in Mbps
+    pub download: Tiers,
+    /// Upload speed in Mbps
+    pub upload: Tiers,
+    /// Latency in milliseconds
+    pub latency: Tiers,
+    /// Jitter in milliseconds
+    pub jitter: Tiers,
+    /// Packet loss ratio (0.0 to 1.0)
+    pub packet_loss: Tiers,
+}
+
+impl Default for VideoConferencingThresholds {
+    fn default() -> Self {
+        use video_conferencing_thresholds::*;
+        Self {
+            download: Tiers::new(
+                DOWNLOAD_GREAT,
+                DOWNLOAD_GOOD,
+                DOWNLOAD_AVERAGE,
+            ),
+            upload: Tiers::new(UPLOAD_GREAT, UPLOAD_GOOD, UPLOAD_AVERAGE),
+            latency: Tiers::new(LATENCY_GREAT, LATENCY_GOOD, LATENCY_AVERAGE),
+            jitter: Tiers::new(JITTER_GREAT, JITTER_GOOD, JITTER_AVERAGE),
+            packet_loss: Tiers::new(
+                PACKET_LOSS_GREAT,
+                PACKET_LOSS_GOOD,
+                PACKET_LOSS_AVERAGE,
+            ),
+        }
+    }
+}
+
+/// Working latency (latency under load) BITAG recommends staying under,
+/// in milliseconds.
+pub const WORKING_LATENCY_TARGET_MS: f64 = 100.0;
+
+/// Targets loaded latency must stay under for the working latency check.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkingLatencyTargets {
+    /// Maximum latency while downloading, in milliseconds
+    pub download_ms: f64,
+    /// Maximum latency while uploading, in milliseconds
+    pub upload_ms: f64,
+}
+
+impl Default for WorkingLatencyTargets {
+    fn default() -> Self {
+        Self {
+            download_ms: WORKING_LATENCY_TARGET_MS,
+            upload_ms: WORKING_LATENCY_TARGET_MS,
+        }
+    }
+}
+
+impl WorkingLatencyTargets {
+    fn validate(&self) -> Result<(), String> {
+        if [self.download_ms, self.upload_ms]
+            .iter()
+            .all(|v| v.is_finite() && *v > 0.0)
+        {
+            Ok(())
+        } else {
+            Err("working_latency: targets must be finite and positive".into())
+        }
+    }
+}
+
+/// Whether loaded latency stayed under the working latency targets.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WorkingLatencyCheck {
+    /// Target for latency while downloading, in milliseconds
+    pub download_target_ms: f64,
+    /// Target for latency while uploading, in milliseconds
+    pub upload_target_ms: f64,
+    /// Whether download latency was within its target, if measured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_pass: Option<bool>,
+    /// Whether upload latency was within its target, if measured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_pass: Option<bool>,
+    /// Whether every measured direction was within its target
+    pub pass: bool,
+}
+
+/// Check loaded latency against `targets`.
 ///
-/// # Arguments
-/// * `metrics` - The connection metrics to evaluate
+/// Returns `None` when no loaded latency was measured, since there is
+/// nothing to check.
+pub fn check_working_latency(
+    metrics: &ConnectionMetrics,
+    targets: &WorkingLatencyTargets,
+) -> Option<WorkingLatencyCheck> {
+    let download_pass =
+        metrics.loaded_latency_down_ms.map(|ms| ms <= targets.download_ms);
+    let upload_pass =
+        metrics.loaded_latency_up_ms.map(|ms| ms <= targets.upload_ms);
+    if download_pass.is_none() && upload_pass.is_none() {
+        return None;
+    }
+
+    Some(WorkingLatencyCheck {
+        download_target_ms: targets.download_ms,
+        upload_target_ms: targets.upload_ms,
+        download_pass,
+        upload_pass,
+        pass: download_pass != Some(false) && upload_pass != Some(false),
+    })
+}
+
+/// Letter grade summarising a whole test.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum Grade {
+    A,
+    B,
+    C,
+    D,
+    F,
+}
+
+impl Grade {
+    /// Grade for a combined score from 0 (worst) to 4 (best).
+    fn from_points(points: f64) -> Self {
+        match points {
+            p if p >= 3.5 => Grade::A,
+            p if p >= 2.75 => Grade::B,
+            p if p >= 2.0 => Grade::C,
+            p if p >= 1.0 => Grade::D,
+            _ => Grade::F,
+        }
+    }
+}
+
+impl std::fmt::Display for Grade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let letter = match self {
+            Grade::A => "A",
+            Grade::B => "B",
+            Grade::C => "C",
+            Grade::D => "D",
+            Grade::F => "F",
+        };
+        f.write_str(letter)
+    }
+}
+
+/// Latency added under load, in ms, above which the grade is marked down,
+/// with the points taken off. Roughly the bands bufferbloat tests grade
+/// on.
+const BUFFERBLOAT_PENALTIES: [(f64, f64); 3] =
+    [(200.0, 2.0), (60.0, 1.0), (30.0, 0.5)];
+
+/// Loss ratio above which the grade is marked down, with the points taken
+/// off.
+const LOSS_PENALTIES: [(f64, f64); 3] =
+    [(0.025, 2.0), (0.01, 1.0), (0.005, 0.5)];
+
+/// A single letter grade for a test, with what held it back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GradeSummary {
+    /// The grade, A (best) to F
+    pub grade: Grade,
+    /// Short explanation of the grade
+    pub explanation: String,
+}
+
+/// Grade a test from its AIM `scores`, bufferbloat and loss.
+///
+/// The three AIM scores are averaged on a 0-4 scale (Poor counts as 0),
+/// then marked down for latency added under load and for loss, which hurt
+/// every use case even where the AIM tables are lenient about them.
+pub fn calculate_grade(
+    scores: &AimScores,
+    metrics: &ConnectionMetrics,
+) -> GradeSummary {
+    let points = |score: QualityScore| match score {
+        QualityScore::Great => 4.0,
+        QualityScore::Good => 3.0,
+        QualityScore::Average => 2.0,
+        QualityScore::Poor => 0.0,
+    };
+    let use_cases = [
+        ("streaming", scores.streaming),
+        ("gaming", scores.gaming),
+        ("video calls", scores.video_conferencing),
+    ];
+    let penalty = |value: f64, bands: &[(f64, f64)]| {
+        bands
+            .iter()
+            .find(|(above, _)| value > *above)
+            .map_or(0.0, |(_, points)| *points)
+    };
+
+    let mut total =
+        use_cases.iter().map(|(_, score)| points(*score)).sum::<f64>()
+            / use_cases.len() as f64;
+    let mut held_back_by: Vec<String> = Vec::new();
+
+    let mut weak = use_cases
+        .iter()
+        .filter(|(_, score)| *score < QualityScore::Great)
+        .collect::<Vec<_>>();
+    weak.sort_by_key(|(_, score)| *score);
+    held_back_by.extend(weak.iter().map(|(name, score)| {
+        format!("{} ({})", name, score.description().to_lowercase())
+    }));
+
+    if let Some(bloat_ms) = metrics.bufferbloat_ms() {
+        let bloat_penalty = penalty(bloat_ms, &BUFFERBLOAT_PENALTIES);
+        if bloat_penalty > 0.0 {
+            total -= bloat_penalty;
+            held_back_by
+                .push(format!("+{:.0} ms latency under load", bloat_ms));
+        }
+    }
+
+    if let Some(loss) = metrics.effective_loss() {
+        let loss_penalty = penalty(loss, &LOSS_PENALTIES);
+        if loss_penalty > 0.0 {
+            total -= loss_penalty;
+            held_back_by.push(format!("{:.1}% loss", loss * 100.0));
+        }
+    }
+
+    let explanation = if held_back_by.is_empty() {
+        "Great for streaming, gaming and video calls".to_string()
+    } else {
+        format!("Held back by {}", held_back_by.join(", "))
+    };
+
+    GradeSummary { grade: Grade::from_points(total), explanation }
+}
+
+/// Threshold tables for every use case.
 ///
-/// # Returns
-/// An `AimScores` struct containing quality scores for each use case.
+/// Defaults match the constants above; any table or metric can be
+/// overridden from the config file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScoringThresholds {
+    /// Streaming thresholds
+    pub streaming: StreamingThresholds,
+    /// Gaming thresholds
+    pub gaming: GamingThresholds,
+    /// Video conferencing thresholds
+    pub video_conferencing: VideoConferencingThresholds,
+    /// Loaded latency targets for the working latency check
+    pub working_latency: WorkingLatencyTargets,
+}
+
+impl ScoringThresholds {
+    /// Check that every table is monotonic.
+    ///
+    /// Speed boundaries must not increase from Great to Average, and
+    /// latency, jitter and loss boundaries must not decrease, otherwise
+    /// some levels could never be reached.
+    pub fn validate(&self) -> Result<(), String> {
+        let streaming = &self.streaming;
+        streaming.download.validate_at_least("streaming.download")?;
+        streaming.latency.validate_at_most("streaming.latency")?;
+
+        let gaming = &self.gaming;
+        gaming.latency.validate_at_most("gaming.latency")?;
+        gaming.jitter.validate_at_most("gaming.jitter")?;
+        gaming.packet_loss.validate_at_most("gaming.packet_loss")?;
+        gaming.download.validate_at_least("gaming.download")?;
+
+        let video = &self.video_conferencing;
+        video.download.validate_at_least("video_conferencing.download")?;
+        video.upload.validate_at_least("video_conferencing.upload")?;
+        video.latency.validate_at_most("video_conferencing.latency")?;
+        video.jitter.validate_at_most("video_conferencing.jitter")?;
+        video
+            .packet_loss
+            .validate_at_most("video_conferencing.packet_loss")?;
+
+        self.working_latency.validate()?;
+
+        Ok(())
+    }
+}
+
+/// Calculates the per-factor breakdown behind each AIM score against
+/// `thresholds`, which should have passed [`ScoringThresholds::validate`].
 ///
-/// # Example
-/// ```
-/// let metrics = ConnectionMetrics::new(100.0, 50.0, 15.0, 2.0);
-/// let scores = calculate_aim_scores(&metrics);
-/// assert_eq!(scores.streaming, QualityScore::Great);
-/// ```
-pub fn calculate_aim_scores(metrics: &ConnectionMetrics) -> AimScores {
-    AimScores {
-        streaming: calculate_streaming_score(metrics),
-        gaming: calculate_gaming_score(metrics),
-        video_conferencing: calculate_video_conferencing_score(metrics),
+/// Each use case's score is the worst of its factor scores, so the
+/// breakdown shows which metric is holding a score down.
+pub fn calculate_aim_score_details_with(
+    metrics: &ConnectionMetrics,
+    thresholds: &ScoringThresholds,
+) -> AimScoreDetails {
+    AimScoreDetails {
+        streaming: calculate_streaming_factors(metrics, &thresholds.streaming),
+        gaming: calculate_gaming_factors(metrics, &thresholds.gaming),
+        video_conferencing: calculate_video_conferencing_factors(
+            metrics,
+            &thresholds.video_conferencing,
+        ),
     }
 }
 
-/// Calculates the streaming quality score.
+/// Scores the individual metrics that make up one use case's score.
+///
+/// Factors that do not apply to the use case, or were not measured, are
+/// `None` and do not affect the overall score.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct FactorScores {
+    /// Download speed score
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download: Option<QualityScore>,
+    /// Upload speed score
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload: Option<QualityScore>,
+    /// Latency score
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<QualityScore>,
+    /// Jitter score
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jitter: Option<QualityScore>,
+    /// Packet loss score
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packet_loss: Option<QualityScore>,
+}
+
+impl FactorScores {
+    /// Factor names paired with their scores, skipping unscored factors.
+    pub fn factors(&self) -> Vec<(&'static str, QualityScore)> {
+        [
+            ("download", self.download),
+            ("upload", self.upload),
+            ("latency", self.latency),
+            ("jitter", self.jitter),
+            ("packet_loss", self.packet_loss),
+        ]
+        .into_iter()
+        .filter_map(|(name, score)| score.map(|s| (name, s)))
+        .collect()
+    }
+
+    /// The use case score: the worst factor score, or Great if none apply.
+    pub fn overall(&self) -> QualityScore {
+        self.factors()
+            .into_iter()
+            .map(|(_, score)| score)
+            .min()
+            .unwrap_or(QualityScore::Great)
+    }
+
+    /// Names of the factors that set the overall score.
+    ///
+    /// Empty when every factor is Great, since nothing is holding the
+    /// score down.
+    pub fn limiting_factors(&self) -> Vec<&'static str> {
+        let overall = self.overall();
+        if overall == QualityScore::Great {
+            return Vec::new();
+        }
+        self.factors()
+            .into_iter()
+            .filter(|(_, score)| *score == overall)
+            .map(|(name, _)| name)
+            .collect()
+    }
+}
+
+/// Per-factor breakdown of the AIM scores for every use case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AimScoreDetails {
+    /// Factors behind the streaming score
+    pub streaming: FactorScores,
+    /// Factors behind the gaming score
+    pub gaming: FactorScores,
+    /// Factors behind the video conferencing score
+    pub video_conferencing: FactorScores,
+}
+
+impl AimScoreDetails {
+    /// Collapse the breakdown into one score per use case.
+    pub fn scores(&self) -> AimScores {
+        AimScores {
+            streaming: self.streaming.overall(),
+            gaming: self.gaming.overall(),
+            video_conferencing: self.video_conferencing.overall(),
+        }
+    }
+}
+
+/// Calculates the streaming factor scores.
 ///
 /// Streaming is primarily dependent on download speed, with latency being
 /// a secondary factor. Upload speed and jitter have minimal impact.
-fn calculate_streaming_score(metrics: &ConnectionMetrics) -> QualityScore {
-    use streaming_thresholds::*;
-
-    // Evaluate download speed
-    let download_score = if metrics.download_mbps >= DOWNLOAD_GREAT {
-        QualityScore::Great
-    } else if metrics.download_mbps >= DOWNLOAD_GOOD {
-        QualityScore::Good
-    } else if metrics.download_mbps >= DOWNLOAD_AVERAGE {
-        QualityScore::Average
-    } else {
-        QualityScore::Poor
-    };
-
+fn calculate_streaming_factors(
+    metrics: &ConnectionMetrics,
+    thresholds: &StreamingThresholds,
+) -> FactorScores {
     // Evaluate latency (use loaded latency if available, otherwise idle)
     let effective_latency =
         metrics.loaded_latency_down_ms.unwrap_or(metrics.latency_ms);
 
-    let latency_score = if effective_latency <= LATENCY_GREAT {
-        QualityScore::Great
-    } else if effective_latency <= LATENCY_GOOD {
-        QualityScore::Good
-    } else if effective_latency <= LATENCY_AVERAGE {
-        QualityScore::Average
-    } else {
-        QualityScore::Poor
-    };
-
-    // Return the minimum of the two scores
-    std::cmp::min(download_score, latency_score)
+    FactorScores {
+        download: Some(
+            thresholds.download.rate_at_least(metrics.download_mbps),
+        ),
+        latency: Some(thresholds.latency.rate_at_most(effective_latency)),
+        ..FactorScores::default()
+    }
 }
 
-/// Calculates the gaming quality score.
+/// Calculates the gaming factor scores.
 ///
 /// Gaming is highly sensitive to latency, jitter, and packet loss.
 /// Download speed is less critical but still considered.
-fn calculate_gaming_score(metrics: &ConnectionMetrics) -> QualityScore {
-    use gaming_thresholds::*;
-
+fn calculate_gaming_factors(
+    metrics: &ConnectionMetrics,
+    thresholds: &GamingThresholds,
+) -> FactorScores {
     // Evaluate latency (use loaded latency if available for more realistic gaming
     // scenario)
     let effective_latency = metrics
@@ -321,140 +852,65 @@ fn calculate_gaming_score(metrics: &ConnectionMetrics) -> QualityScore {
         .or(metrics.loaded_latency_up_ms)
         .unwrap_or(metrics.latency_ms);
 
-    let latency_score = if effective_latency <= LATENCY_GREAT {
-        QualityScore::Great
-    } else if effective_latency <= LATENCY_GOOD {
-        QualityScore::Good
-    } else if effective_latency <= LATENCY_AVERAGE {
-        QualityScore::Average
-    } else {
-        QualityScore::Poor
-    };
-
-    // Evaluate jitter
-    let jitter_score = if metrics.jitter_ms <= JITTER_GREAT {
-        QualityScore::Great
-    } else if metrics.jitter_ms <= JITTER_GOOD {
-        QualityScore::Good
-    } else if metrics.jitter_ms <= JITTER_AVERAGE {
-        QualityScore::Average
-    } else {
-        QualityScore::Poor
-    };
-
-    // Evaluate packet loss (if available)
-    let packet_loss_score = match metrics.packet_loss {
-        Some(loss) if loss <= PACKET_LOSS_GREAT => QualityScore::Great,
-        Some(loss) if loss <= PACKET_LOSS_GOOD => QualityScore::Good,
-        Some(loss) if loss <= PACKET_LOSS_AVERAGE => QualityScore::Average,
-        Some(_) => QualityScore::Poor,
-        // If packet loss is not measured, don't penalize
-        None => QualityScore::Great,
-    };
-
-    // Evaluate download speed
-    let download_score = if metrics.download_mbps >= DOWNLOAD_GREAT {
-        QualityScore::Great
-    } else if metrics.download_mbps >= DOWNLOAD_GOOD {
-        QualityScore::Good
-    } else if metrics.download_mbps >= DOWNLOAD_AVERAGE {
-        QualityScore::Average
-    } else {
-        QualityScore::Poor
-    };
-
-    // Return the minimum of all scores
-    [latency_score, jitter_score, packet_loss_score, download_score]
-        .into_iter()
-        .min()
-        .unwrap()
+    FactorScores {
+        download: Some(
+            thresholds.download.rate_at_least(metrics.download_mbps),
+        ),
+        upload: None,
+        latency: Some(thresholds.latency.rate_at_most(effective_latency)),
+        jitter: Some(thresholds.jitter.rate_at_most(metrics.jitter_ms)),
+        // If neither loss nor retransmissions were measured, don't penalize
+        packet_loss: metrics
+            .effective_loss()
+            .map(|loss| thresholds.packet_loss.rate_at_most(loss)),
+    }
 }
 
-/// Calculates the video conferencing quality score.
+/// Calculates the video conferencing factor scores.
 ///
 /// Video conferencing requires balanced upload and download speeds,
 /// low latency, and low jitter for smooth two-way communication.
-fn calculate_video_conferencing_score(
+fn calculate_video_conferencing_factors(
     metrics: &ConnectionMetrics,
-) -> QualityScore {
-    use video_conferencing_thresholds::*;
-
-    // Evaluate download speed
-    let download_score = if metrics.download_mbps >= DOWNLOAD_GREAT {
-        QualityScore::Great
-    } else if metrics.download_mbps >= DOWNLOAD_GOOD {
-        QualityScore::Good
-    } else if metrics.download_mbps >= DOWNLOAD_AVERAGE {
-        QualityScore::Average
-    } else {
-        QualityScore::Poor
-    };
-
-    // Evaluate upload speed
-    let upload_score = if metrics.upload_mbps >= UPLOAD_GREAT {
-        QualityScore::Great
-    } else if metrics.upload_mbps >= UPLOAD_GOOD {
-        QualityScore::Good
-    } else if metrics.upload_mbps >= UPLOAD_AVERAGE {
-        QualityScore::Average
-    } else {
-        QualityScore::Poor
-    };
-
+    thresholds: &VideoConferencingThresholds,
+) -> FactorScores {
     // Evaluate latency (use loaded latency if available)
     let effective_latency = metrics
         .loaded_latency_up_ms
         .or(metrics.loaded_latency_down_ms)
         .unwrap_or(metrics.latency_ms);
 
-    let latency_score = if effective_latency <= LATENCY_GREAT {
-        QualityScore::Great
-    } else if effective_latency <= LATENCY_GOOD {
-        QualityScore::Good
-    } else if effective_latency <= LATENCY_AVERAGE {
-        QualityScore::Average
-    } else {
-        QualityScore::Poor
-    };
-
-    // Evaluate jitter
-    let jitter_score = if metrics.jitter_ms <= JITTER_GREAT {
-        QualityScore::Great
-    } else if metrics.jitter_ms <= JITTER_GOOD {
-        QualityScore::Good
-    } else if metrics.jitter_ms <= JITTER_AVERAGE {
-        QualityScore::Average
-    } else {
-        QualityScore::Poor
-    };
-
-    // Evaluate packet loss (if available)
-    let packet_loss_score = match metrics.packet_loss {
-        Some(loss) if loss <= PACKET_LOSS_GREAT => QualityScore::Great,
-        Some(loss) if loss <= PACKET_LOSS_GOOD => QualityScore::Good,
-        Some(loss) if loss <= PACKET_LOSS_AVERAGE => QualityScore::Average,
-        Some(_) => QualityScore::Poor,
-        // If packet loss is not measured, don't penalize
-        None => QualityScore::Great,
-    };
-
-    // Return the minimum of all scores
-    [
-        download_score,
-        upload_score,
-        latency_score,
-        jitter_score,
-        packet_loss_score,
-    ]
-    .into_iter()
-    .min()
-    .unwrap()
+    FactorScores {
+        download: Some(
+            thresholds.download.rate_at_least(metrics.download_mbps),
+        ),
+        upload: Some(thresholds.upload.rate_at_least(metrics.upload_mbps)),
+        latency: Some(thresholds.latency.rate_at_most(effective_latency)),
+        jitter: Some(thresholds.jitter.rate_at_most(metrics.jitter_ms)),
+        // If neither loss nor retransmissions were measured, don't penalize
+        packet_loss: metrics
+            .effective_loss()
+            .map(|loss| thresholds.packet_loss.rate_at_most(loss)),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Factor scores against the default thresholds.
+    fn score_details(metrics: &ConnectionMetrics) -> AimScoreDetails {
+        calculate_aim_score_details_with(
+            metrics,
+            &ScoringThresholds::default(),
+        )
+    }
+
+    /// Scores against the default thresholds.
+    fn aim_scores(metrics: &ConnectionMetrics) -> AimScores {
+        score_details(metrics).scores()
+    }
+
     // ========================================================================
     // Unit tests for QualityScore
     // ========================================================================
@@ -515,7 +971,7 @@ mod tests {
     fn test_streaming_great_score() {
         // High download, low latency
         let metrics = ConnectionMetrics::new(100.0, 50.0, 20.0, 5.0);
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         assert_eq!(scores.streaming, QualityScore::Great);
     }
 
@@ -523,7 +979,7 @@ mod tests {
     fn test_streaming_good_score() {
         // Good download (10-25 Mbps), acceptable latency
         let metrics = ConnectionMetrics::new(15.0, 10.0, 50.0, 10.0);
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         assert_eq!(scores.streaming, QualityScore::Good);
     }
 
@@ -531,7 +987,7 @@ mod tests {
     fn test_streaming_average_score() {
         // Average download (5-10 Mbps)
         let metrics = ConnectionMetrics::new(7.0, 5.0, 100.0, 15.0);
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         assert_eq!(scores.streaming, QualityScore::Average);
     }
 
@@ -539,7 +995,7 @@ mod tests {
     fn test_streaming_poor_score() {
         // Low download (<5 Mbps)
         let metrics = ConnectionMetrics::new(3.0, 2.0, 50.0, 10.0);
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         assert_eq!(scores.streaming, QualityScore::Poor);
     }
 
@@ -547,7 +1003,7 @@ mod tests {
     fn test_streaming_limited_by_latency() {
         // Great download but poor latency
         let metrics = ConnectionMetrics::new(100.0, 50.0, 500.0, 5.0);
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         assert_eq!(scores.streaming, QualityScore::Poor);
     }
 
@@ -559,7 +1015,7 @@ mod tests {
     fn test_gaming_great_score() {
         // Low latency, low jitter, no packet loss
         let metrics = ConnectionMetrics::new(50.0, 20.0, 20.0, 5.0);
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         assert_eq!(scores.gaming, QualityScore::Great);
     }
 
@@ -567,7 +1023,7 @@ mod tests {
     fn test_gaming_good_score() {
         // Moderate latency and jitter
         let metrics = ConnectionMetrics::new(20.0, 10.0, 40.0, 15.0);
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         assert_eq!(scores.gaming, QualityScore::Good);
     }
 
@@ -575,7 +1031,7 @@ mod tests {
     fn test_gaming_poor_due_to_latency() {
         // High latency
         let metrics = ConnectionMetrics::new(100.0, 50.0, 150.0, 5.0);
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         assert_eq!(scores.gaming, QualityScore::Poor);
     }
 
@@ -583,7 +1039,7 @@ mod tests {
     fn test_gaming_poor_due_to_jitter() {
         // High jitter
         let metrics = ConnectionMetrics::new(100.0, 50.0, 20.0, 50.0);
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         assert_eq!(scores.gaming, QualityScore::Poor);
     }
 
@@ -592,16 +1048,33 @@ mod tests {
         // High packet loss
         let metrics = ConnectionMetrics::new(100.0, 50.0, 20.0, 5.0)
             .with_packet_loss(0.1);
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         assert_eq!(scores.gaming, QualityScore::Poor);
     }
 
+    #[test]
+    fn test_retransmits_stand_in_for_packet_loss() {
+        let metrics = ConnectionMetrics::new(100.0, 50.0, 20.0, 5.0)
+            .with_retransmit_ratio(Some(0.04));
+        let details = score_details(&metrics);
+        assert_eq!(details.gaming.packet_loss, Some(QualityScore::Average));
+        assert_eq!(
+            details.video_conferencing.packet_loss,
+            Some(QualityScore::Average)
+        );
+        assert_eq!(details.streaming.packet_loss, None);
+
+        // Measured packet loss takes precedence
+        let details = score_details(&metrics.with_packet_loss(0.0));
+        assert_eq!(details.gaming.packet_loss, Some(QualityScore::Great));
+    }
+
     #[test]
     fn test_gaming_with_acceptable_packet_loss() {
         // Low packet loss should still be great
         let metrics = ConnectionMetrics::new(50.0, 20.0, 20.0, 5.0)
             .with_packet_loss(0.005);
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         assert_eq!(scores.gaming, QualityScore::Great);
     }
 
@@ -613,7 +1086,7 @@ mod tests {
     fn test_video_conferencing_great_score() {
         // High upload/download, low latency and jitter
         let metrics = ConnectionMetrics::new(50.0, 30.0, 30.0, 10.0);
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         assert_eq!(scores.video_conferencing, QualityScore::Great);
     }
 
@@ -621,7 +1094,7 @@ mod tests {
     fn test_video_conferencing_good_score() {
         // Moderate speeds and latency
         let metrics = ConnectionMetrics::new(8.0, 6.0, 80.0, 20.0);
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         assert_eq!(scores.video_conferencing, QualityScore::Good);
     }
 
@@ -629,7 +1102,7 @@ mod tests {
     fn test_video_conferencing_limited_by_upload() {
         // Great download but poor upload
         let metrics = ConnectionMetrics::new(100.0, 1.0, 30.0, 10.0);
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         assert_eq!(scores.video_conferencing, QualityScore::Poor);
     }
 
@@ -637,7 +1110,7 @@ mod tests {
     fn test_video_conferencing_limited_by_jitter() {
         // Good speeds but high jitter
         let metrics = ConnectionMetrics::new(50.0, 30.0, 30.0, 60.0);
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         assert_eq!(scores.video_conferencing, QualityScore::Poor);
     }
 
@@ -646,7 +1119,7 @@ mod tests {
         // Use loaded latency when available
         let metrics = ConnectionMetrics::new(50.0, 30.0, 30.0, 10.0)
             .with_loaded_latency(None, Some(250.0));
-        let scores = calculate_aim_scores(&metrics);
+        let scores = aim_scores(&metrics);
         // Loaded latency of 250ms should result in Poor
         assert_eq!(scores.video_conferencing, QualityScore::Poor);
     }
@@ -701,9 +1174,10 @@ mod tests {
                 packet_loss,
                 loaded_latency_down_ms: loaded_latency_down,
                 loaded_latency_up_ms: loaded_latency_up,
+                retransmit_ratio: None,
             };
 
-            let scores = calculate_aim_scores(&metrics);
+            let scores = aim_scores(&metrics);
 
             // Verify each score is a valid category
             let valid_scores = [
@@ -730,7 +1204,7 @@ mod tests {
             );
 
             // Verify determinism: same inputs produce same outputs
-            let scores2 = calculate_aim_scores(&metrics);
+            let scores2 = aim_scores(&metrics);
             prop_assert_eq!(
                 scores.streaming, scores2.streaming,
                 "Streaming score is not deterministic"
@@ -769,8 +1243,8 @@ mod tests {
                 jitter_ms,
             );
 
-            let base_scores = calculate_aim_scores(&base_metrics);
-            let improved_scores = calculate_aim_scores(&improved_metrics);
+            let base_scores = aim_scores(&base_metrics);
+            let improved_scores = aim_scores(&improved_metrics);
 
             prop_assert!(
                 improved_scores.streaming >= base_scores.streaming,
@@ -807,8 +1281,8 @@ mod tests {
                 jitter_ms,
             );
 
-            let base_scores = calculate_aim_scores(&base_metrics);
-            let improved_scores = calculate_aim_scores(&improved_metrics);
+            let base_scores = aim_scores(&base_metrics);
+            let improved_scores = aim_scores(&improved_metrics);
 
             prop_assert!(
                 improved_scores.gaming >= base_scores.gaming,
@@ -842,8 +1316,8 @@ mod tests {
                 jitter_ms,
             );
 
-            let base_scores = calculate_aim_scores(&base_metrics);
-            let improved_scores = calculate_aim_scores(&improved_metrics);
+            let base_scores = aim_scores(&base_metrics);
+            let improved_scores = aim_scores(&improved_metrics);
 
             prop_assert!(
                 improved_scores.video_conferencing >= base_scores.video_conferencing,
@@ -880,8 +1354,8 @@ mod tests {
                 improved_jitter,
             );
 
-            let base_scores = calculate_aim_scores(&base_metrics);
-            let improved_scores = calculate_aim_scores(&improved_metrics);
+            let base_scores = aim_scores(&base_metrics);
+            let improved_scores = aim_scores(&improved_metrics);
 
             prop_assert!(
                 improved_scores.gaming >= base_scores.gaming,
@@ -926,8 +1400,8 @@ mod tests {
                 jitter_ms,
             ).with_packet_loss(improved_packet_loss);
 
-            let base_scores = calculate_aim_scores(&base_metrics);
-            let improved_scores = calculate_aim_scores(&improved_metrics);
+            let base_scores = aim_scores(&base_metrics);
+            let improved_scores = aim_scores(&improved_metrics);
 
             prop_assert!(
                 improved_scores.gaming >= base_scores.gaming,
@@ -959,7 +1433,7 @@ mod tests {
                 jitter_ms,
             );
 
-            let scores = calculate_aim_scores(&metrics);
+            let scores = aim_scores(&metrics);
             let overall = scores.overall();
 
             let min_score = [scores.streaming, scores.gaming, scores.video_conferencing]
@@ -974,4 +1448,225 @@ mod tests {
             );
         }
     }
+
+    // ========================================================================
+    // Unit tests for the per-factor breakdown
+    // ========================================================================
+
+    #[test]
+    fn test_details_agree_with_scores() {
+        let metrics = ConnectionMetrics::new(12.0, 3.0, 45.0, 25.0)
+            .with_packet_loss(0.02);
+        let details = score_details(&metrics);
+        assert_eq!(details.scores(), aim_scores(&metrics));
+    }
+
+    #[test]
+    fn test_gaming_details_explain_poor_score() {
+        let metrics = ConnectionMetrics::new(100.0, 50.0, 150.0, 5.0);
+        let details = score_details(&metrics);
+
+        assert_eq!(details.gaming.overall(), QualityScore::Poor);
+        assert_eq!(details.gaming.latency, Some(QualityScore::Poor));
+        assert_eq!(details.gaming.jitter, Some(QualityScore::Great));
+        assert_eq!(details.gaming.limiting_factors(), vec!["latency"]);
+    }
+
+    #[test]
+    fn test_unmeasured_packet_loss_is_omitted() {
+        let metrics = ConnectionMetrics::new(100.0, 50.0, 15.0, 2.0);
+        let details = score_details(&metrics);
+        assert_eq!(details.gaming.packet_loss, None);
+        assert!(details
+            .gaming
+            .factors()
+            .iter()
+            .all(|(n, _)| *n != "packet_loss"));
+
+        let details = score_details(&metrics.with_packet_loss(0.1));
+        assert_eq!(details.gaming.packet_loss, Some(QualityScore::Poor));
+    }
+
+    #[test]
+    fn test_streaming_details_factors() {
+        let metrics = ConnectionMetrics::new(100.0, 50.0, 15.0, 2.0);
+        let details = score_details(&metrics);
+        let names: Vec<_> =
+            details.streaming.factors().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(names, vec!["download", "latency"]);
+        assert!(details.streaming.limiting_factors().is_empty());
+    }
+
+    #[test]
+    fn test_details_serialization() {
+        let metrics = ConnectionMetrics::new(100.0, 50.0, 15.0, 2.0);
+        let details = score_details(&metrics);
+        let json = serde_json::to_value(details).unwrap();
+        assert_eq!(json["gaming"]["latency"], "great");
+        assert!(json["streaming"].get("upload").is_none());
+        assert!(json["gaming"].get("packet_loss").is_none());
+    }
+
+    // ========================================================================
+    // Unit tests for configurable thresholds
+    // ========================================================================
+
+    #[test]
+    fn test_default_thresholds_are_valid() {
+        assert!(ScoringThresholds::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_non_monotonic_thresholds_rejected() {
+        let mut thresholds = ScoringThresholds::default();
+        thresholds.gaming.latency = Tiers::new(50.0, 30.0, 100.0);
+        let err = thresholds.validate().unwrap_err();
+        assert!(err.contains("gaming.latency"));
+
+        let mut thresholds = ScoringThresholds::default();
+        thresholds.streaming.download = Tiers::new(5.0, 10.0, 25.0);
+        let err = thresholds.validate().unwrap_err();
+        assert!(err.contains("streaming.download"));
+    }
+
+    #[test]
+    fn test_negative_or_nan_thresholds_rejected() {
+        let mut thresholds = ScoringThresholds::default();
+        thresholds.video_conferencing.jitter = Tiers::new(-1.0, 30.0, 50.0);
+        assert!(thresholds.validate().is_err());
+
+        let mut thresholds = ScoringThresholds::default();
+        thresholds.gaming.packet_loss = Tiers::new(f64::NAN, 0.02, 0.05);
+        assert!(thresholds.validate().is_err());
+    }
+
+    #[test]
+    fn test_working_latency_check() {
+        let targets = WorkingLatencyTargets::default();
+        let metrics = ConnectionMetrics::new(100.0, 50.0, 15.0, 2.0);
+        assert_eq!(check_working_latency(&metrics, &targets), None);
+
+        let check = check_working_latency(
+            &metrics.clone().with_loaded_latency(Some(60.0), Some(100.0)),
+            &targets,
+        )
+        .unwrap();
+        assert_eq!(check.download_pass, Some(true));
+        assert_eq!(check.upload_pass, Some(true));
+        assert!(check.pass);
+
+        let check = check_working_latency(
+            &metrics.clone().with_loaded_latency(Some(60.0), Some(240.0)),
+            &targets,
+        )
+        .unwrap();
+        assert_eq!(check.upload_pass, Some(false));
+        assert!(!check.pass);
+
+        // Only the measured direction counts
+        let strict =
+            WorkingLatencyTargets { download_ms: 50.0, upload_ms: 50.0 };
+        let check = check_working_latency(
+            &metrics.with_loaded_latency(Some(40.0), None),
+            &strict,
+        )
+        .unwrap();
+        assert_eq!(check.upload_pass, None);
+        assert!(check.pass);
+    }
+
+    #[test]
+    fn test_bufferbloat_ms() {
+        let metrics = ConnectionMetrics::new(100.0, 50.0, 15.0, 2.0);
+        assert_eq!(metrics.bufferbloat_ms(), None);
+
+        let metrics = metrics.with_loaded_latency(Some(40.0), Some(95.0));
+        assert_eq!(metrics.bufferbloat_ms(), Some(80.0));
+
+        let metrics = metrics.with_loaded_latency(Some(10.0), None);
+        assert_eq!(metrics.bufferbloat_ms(), Some(0.0));
+    }
+
+    #[test]
+    fn test_grade_great_connection() {
+        let metrics = ConnectionMetrics::new(500.0, 100.0, 10.0, 1.0)
+            .with_loaded_latency(Some(20.0), Some(25.0))
+            .with_packet_loss(0.0);
+        let grade = calculate_grade(&aim_scores(&metrics), &metrics);
+        assert_eq!(grade.grade, Grade::A);
+        assert_eq!(
+            grade.explanation,
+            "Great for streaming, gaming and video calls"
+        );
+    }
+
+    #[test]
+    fn test_grade_marked_down_for_bufferbloat_and_loss() {
+        let great = AimScores::new(
+            QualityScore::Great,
+            QualityScore::Great,
+            QualityScore::Great,
+        );
+        let metrics = ConnectionMetrics::new(500.0, 100.0, 10.0, 1.0)
+            .with_loaded_latency(Some(110.0), None);
+        let grade = calculate_grade(&great, &metrics);
+        assert_eq!(grade.grade, Grade::B);
+        assert_eq!(
+            grade.explanation,
+            "Held back by +100 ms latency under load"
+        );
+
+        let grade = calculate_grade(&great, &metrics.with_packet_loss(0.03));
+        assert_eq!(grade.grade, Grade::D);
+        assert_eq!(
+            grade.explanation,
+            "Held back by +100 ms latency under load, 3.0% loss"
+        );
+    }
+
+    #[test]
+    fn test_grade_names_weakest_use_cases_first() {
+        let scores = AimScores::new(
+            QualityScore::Good,
+            QualityScore::Poor,
+            QualityScore::Great,
+        );
+        let metrics = ConnectionMetrics::new(50.0, 10.0, 80.0, 10.0);
+        let grade = calculate_grade(&scores, &metrics);
+        // (3 + 0 + 4) / 3
+        assert_eq!(grade.grade, Grade::C);
+        assert_eq!(
+            grade.explanation,
+            "Held back by gaming (poor), streaming (good)"
+        );
+    }
+
+    #[test]
+    fn test_grade_serialization() {
+        assert_eq!(serde_json::to_value(Grade::B).unwrap(), "B");
+        assert_eq!(Grade::F.to_string(), "F");
+    }
+
+    #[test]
+    fn test_invalid_working_latency_targets_rejected() {
+        let mut thresholds = ScoringThresholds::default();
+        thresholds.working_latency.upload_ms = 0.0;
+        let err = thresholds.validate().unwrap_err();
+        assert!(err.contains("working_latency"));
+    }
+
+    #[test]
+    fn test_custom_thresholds_change_scores() {
+        // 40ms idle latency is Good for gaming by default
+        let metrics = ConnectionMetrics::new(100.0, 50.0, 40.0, 2.0);
+        assert_eq!(
+            score_details(&metrics).gaming.latency,
+            Some(QualityScore::Good)
+        );
+
+        let mut thresholds = ScoringThresholds::default();
+        thresholds.gaming.latency = Tiers::new(45.0, 60.0, 120.0);
+        let details = calculate_aim_score_details_with(&metrics, &thresholds);
+        assert_eq!(details.gaming.latency, Some(QualityScore::Great));
+    }
 }
@@ -0,0 +1,483 @@
+//! Read-only history server for `cloud-speed history serve-json`.
+//!
+//! Charts the connection over time from the local history, with no
+//! database in between:
+//!
+//! - `GET /timeseries?metric=NAME&from=MS&to=MS` returns each metric as a
+//!   list of `{time, value}` points; `metric` may repeat and defaults to
+//!   all of them, `from` and `to` are Unix times in ms
+//!
+//! With `--grafana`, it also speaks the Grafana JSON datasource contract:
+//!
+//! - `GET /` answers the datasource's connection test
+//! - `POST /search` and `POST /metrics` list the metric names
+//! - `POST /query` returns `[value, time]` datapoints for the targets and
+//!   time range of a panel
+//!
+//! The history file is read again for every request, so new runs show up
+//! without restarting.
+
+use super::{read_request, write_json, write_response, Request};
+use crate::history::{HistoryEntry, HistoryStore};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+
+/// A history field that can be charted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Metric {
+    Download,
+    Upload,
+    Latency,
+    Jitter,
+    LoadedDown,
+    LoadedUp,
+    PacketLoss,
+}
+
+impl Metric {
+    const ALL: [Metric; 7] = [
+        Metric::Download,
+        Metric::Upload,
+        Metric::Latency,
+        Metric::Jitter,
+        Metric::LoadedDown,
+        Metric::LoadedUp,
+        Metric::PacketLoss,
+    ];
+
+    /// Name of the metric, as in the history file.
+    fn name(self) -> &'static str {
+        match self {
+            Metric::Download => "download_mbps",
+            Metric::Upload => "upload_mbps",
+            Metric::Latency => "latency_ms",
+            Metric::Jitter => "jitter_ms",
+            Metric::LoadedDown => "loaded_down_ms",
+            Metric::LoadedUp => "loaded_up_ms",
+            Metric::PacketLoss => "packet_loss_percent",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|metric| metric.name() == name)
+    }
+
+    /// The metric's value in `entry`, if the run measured it.
+    fn value(self, entry: &HistoryEntry) -> Option<f64> {
+        match self {
+            Metric::Download => Some(entry.download_mbps),
+            Metric::Upload => Some(entry.upload_mbps),
+            Metric::Latency => Some(entry.latency_ms),
+            Metric::Jitter => entry.jitter_ms,
+            Metric::LoadedDown => entry.loaded_down_ms,
+            Metric::LoadedUp => entry.loaded_up_ms,
+            Metric::PacketLoss => entry.packet_loss_percent,
+        }
+    }
+}
+
+/// Time range of a query; unset ends are open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+struct TimeRange {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+impl TimeRange {
+    fn contains(&self, time: DateTime<Utc>) -> bool {
+        self.from.is_none_or(|from| time >= from)
+            && self.to.is_none_or(|to| time <= to)
+    }
+}
+
+/// Body of a Grafana `POST /query`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct GrafanaQuery {
+    range: TimeRange,
+    targets: Vec<GrafanaTarget>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct GrafanaTarget {
+    target: Option<String>,
+}
+
+/// Body of a Grafana `POST /search`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct GrafanaSearch {
+    target: Option<String>,
+}
+
+/// The endpoints the server exposes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Route {
+    Timeseries,
+    Health,
+    Search,
+    Query,
+    MethodNotAllowed,
+    NotFound,
+}
+
+/// What each connection needs.
+struct Datasource {
+    store: HistoryStore,
+    grafana: bool,
+}
+
+/// Bind to `listen` and serve `store` until the process is stopped.
+pub async fn run(
+    listen: SocketAddr,
+    store: HistoryStore,
+    grafana: bool,
+) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .map_err(|e| format!("Failed to listen on {}: {}", listen, e))?;
+    log::info!("Listening on http://{}", listener.local_addr()?);
+    eprintln!(
+        "Serving {} on http://{}",
+        store.path().display(),
+        listener.local_addr()?
+    );
+
+    accept_loop(listener, Arc::new(Datasource { store, grafana })).await;
+    Ok(())
+}
+
+async fn accept_loop(listener: TcpListener, source: Arc<Datasource>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let source = Arc::clone(&source);
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream, &source).await {
+                        log::debug!("Connection from {} failed: {}", peer, e);
+                    }
+                });
+            }
+            Err(e) => log::warn!("Failed to accept connection: {}", e),
+        }
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    source: &Datasource,
+) -> Result<(), Box<dyn Error>> {
+    let Some(request) = read_request(&mut stream).await? else {
+        let body = json!({ "error": "Malformed request" });
+        return write_json(&mut stream, 400, &body).await;
+    };
+    log::debug!("{} {}", request.method, request.path);
+
+    let route = route(&request, source.grafana);
+    let entries = match route {
+        Route::Timeseries | Route::Query => match source.store.load() {
+            Ok(entries) => entries,
+            Err(e) => {
+                let body = json!({
+                    "error": format!("Failed to read history: {}", e),
+                });
+                return write_json(&mut stream, 500, &body).await;
+            }
+        },
+        _ => Vec::new(),
+    };
+
+    match route {
+        Route::Timeseries => {
+            match timeseries(&entries, request.query.as_deref()) {
+                Ok(body) => write_json(&mut stream, 200, &body).await,
+                Err(message) => {
+                    let body = json!({ "error": message });
+                    write_json(&mut stream, 400, &body).await
+                }
+            }
+        }
+        Route::Health => {
+            write_response(&mut stream, 200, "text/plain", "OK").await
+        }
+        Route::Search => {
+            let search: GrafanaSearch =
+                serde_json::from_slice(&request.body).unwrap_or_default();
+            let body = if request.path.trim_end_matches('/') == "/metrics" {
+                metric_options(search.target.as_deref())
+            } else {
+                metric_names(search.target.as_deref())
+            };
+            write_json(&mut stream, 200, &body).await
+        }
+        Route::Query => {
+            match serde_json::from_slice::<GrafanaQuery>(&request.body) {
+                Ok(query) => {
+                    write_json(
+                        &mut stream,
+                        200,
+                        &grafana_query(&entries, &query),
+                    )
+                    .await
+                }
+                Err(e) => {
+                    let body =
+                        json!({ "error": format!("Invalid query: {}", e) });
+                    write_json(&mut stream, 400, &body).await
+                }
+            }
+        }
+        Route::MethodNotAllowed => {
+            let body = json!({ "error": "Method not allowed" });
+            write_json(&mut stream, 405, &body).await
+        }
+        Route::NotFound => {
+            write_json(&mut stream, 404, &json!({ "error": "Not found" }))
+                .await
+        }
+    }
+}
+
+fn route(request: &Request, grafana: bool) -> Route {
+    let (route, allowed) = match request.path.trim_end_matches('/') {
+        "/timeseries" => (Route::Timeseries, "GET"),
+        "" if grafana => (Route::Health, "GET"),
+        "/search" | "/metrics" if grafana => (Route::Search, "POST"),
+        "/query" if grafana => (Route::Query, "POST"),
+        _ => return Route::NotFound,
+    };
+
+    if request.method == allowed {
+        route
+    } else {
+        Route::MethodNotAllowed
+    }
+}
+
+/// Points of `metric` within `range`, oldest first.
+fn points(
+    entries: &[HistoryEntry],
+    metric: Metric,
+    range: TimeRange,
+) -> Vec<(DateTime<Utc>, f64)> {
+    entries
+        .iter()
+        .filter(|entry| range.contains(entry.timestamp))
+        .filter_map(|entry| Some((entry.timestamp, metric.value(entry)?)))
+        .collect()
+}
+
+/// Body of `GET /timeseries` for the query string `query`.
+fn timeseries(
+    entries: &[HistoryEntry],
+    query: Option<&str>,
+) -> Result<Value, String> {
+    let mut metrics = Vec::new();
+    let mut range = TimeRange::default();
+    for (key, value) in query
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+    {
+        match key {
+            "metric" => metrics.push(
+                Metric::from_name(value)
+                    .ok_or_else(|| format!("unknown metric '{}'", value))?,
+            ),
+            "from" => range.from = Some(parse_millis(value)?),
+            "to" => range.to = Some(parse_millis(value)?),
+            _ => {}
+        }
+    }
+    if metrics.is_empty() {
+        metrics = Metric::ALL.to_vec();
+    }
+
+    let series: Vec<Value> = metrics
+        .into_iter()
+        .map(|metric| {
+            let points: Vec<Value> = points(entries, metric, range)
+                .into_iter()
+                .map(|(time, value)| {
+                    json!({ "time": time.to_rfc3339(), "value": value })
+                })
+                .collect();
+            json!({ "metric": metric.name(), "points": points })
+        })
+        .collect();
+    Ok(json!(series))
+}
+
+/// Parse a Unix time in ms.
+fn parse_millis(value: &str) -> Result<DateTime<Utc>, String> {
+    value
+        .parse::<i64>()
+        .ok()
+        .and_then(DateTime::from_timestamp_millis)
+        .ok_or_else(|| format!("invalid time '{}' (expected Unix ms)", value))
+}
+
+/// Metric names matching `filter`, for Grafana's `POST /search`.
+fn metric_names(filter: Option<&str>) -> Value {
+    let names: Vec<&str> = Metric::ALL
+        .into_iter()
+        .map(Metric::name)
+        .filter(|name| name.contains(filter.unwrap_or_default()))
+        .collect();
+    json!(names)
+}
+
+/// Metric names as options, for Grafana's `POST /metrics`.
+fn metric_options(filter: Option<&str>) -> Value {
+    let names = metric_names(filter);
+    let options: Vec<Value> = names
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|name| json!({ "label": name, "value": name }))
+        .collect();
+    json!(options)
+}
+
+/// Response to Grafana's `POST /query`; unknown targets are left out.
+fn grafana_query(entries: &[HistoryEntry], query: &GrafanaQuery) -> Value {
+    let series: Vec<Value> = query
+        .targets
+        .iter()
+        .filter_map(|target| Metric::from_name(target.target.as_deref()?))
+        .map(|metric| {
+            let datapoints: Vec<Value> = points(entries, metric, query.range)
+                .into_iter()
+                .map(|(time, value)| json!([value, time.timestamp_millis()]))
+                .collect();
+            json!({ "target": metric.name(), "datapoints": datapoints })
+        })
+        .collect();
+    json!(series)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entries() -> Vec<HistoryEntry> {
+        [(1, 90.0, None), (2, 95.0, Some(1.5)), (3, 80.0, Some(2.0))]
+            .into_iter()
+            .map(|(day, download_mbps, jitter_ms)| HistoryEntry {
+                timestamp: Utc
+                    .with_ymd_and_hms(2024, 5, day, 0, 0, 0)
+                    .unwrap(),
+                download_mbps,
+                upload_mbps: 20.0,
+                latency_ms: 12.0,
+                jitter_ms,
+                loaded_down_ms: None,
+                loaded_up_ms: None,
+                packet_loss_percent: None,
+                load_streams: None,
+                order: None,
+            })
+            .collect()
+    }
+
+    fn millis(day: u32) -> i64 {
+        Utc.with_ymd_and_hms(2024, 5, day, 0, 0, 0).unwrap().timestamp_millis()
+    }
+
+    fn request(method: &str, path: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: None,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_route() {
+        assert_eq!(
+            route(&request("GET", "/timeseries"), false),
+            Route::Timeseries
+        );
+        assert_eq!(route(&request("POST", "/query"), false), Route::NotFound);
+        assert_eq!(route(&request("GET", "/"), true), Route::Health);
+        assert_eq!(route(&request("POST", "/search"), true), Route::Search);
+        assert_eq!(route(&request("POST", "/metrics"), true), Route::Search);
+        assert_eq!(route(&request("POST", "/query"), true), Route::Query);
+        assert_eq!(
+            route(&request("GET", "/query"), true),
+            Route::MethodNotAllowed
+        );
+    }
+
+    #[test]
+    fn test_timeseries() {
+        let query = format!("metric=jitter_ms&from={}", millis(2));
+        let body = timeseries(&entries(), Some(&query)).unwrap();
+        assert_eq!(
+            body,
+            json!([{
+                "metric": "jitter_ms",
+                "points": [
+                    { "time": "2024-05-02T00:00:00+00:00", "value": 1.5 },
+                    { "time": "2024-05-03T00:00:00+00:00", "value": 2.0 },
+                ],
+            }])
+        );
+
+        let all = timeseries(&entries(), None).unwrap();
+        assert_eq!(all.as_array().unwrap().len(), Metric::ALL.len());
+        assert_eq!(all[0]["points"].as_array().unwrap().len(), 3);
+
+        assert!(timeseries(&entries(), Some("metric=speed")).is_err());
+        assert!(timeseries(&entries(), Some("from=yesterday")).is_err());
+    }
+
+    #[test]
+    fn test_grafana_query() {
+        let query: GrafanaQuery = serde_json::from_value(json!({
+            "range": {
+                "from": "2024-05-01T12:00:00.000Z",
+                "to": "2024-05-03T00:00:00.000Z",
+            },
+            "targets": [
+                { "target": "download_mbps", "refId": "A" },
+                { "target": "nope", "refId": "B" },
+            ],
+            "maxDataPoints": 500,
+        }))
+        .unwrap();
+
+        assert_eq!(
+            grafana_query(&entries(), &query),
+            json!([{
+                "target": "download_mbps",
+                "datapoints": [[95.0, millis(2)], [80.0, millis(3)]],
+            }])
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        assert_eq!(
+            metric_names(Some("loaded")),
+            json!(["loaded_down_ms", "loaded_up_ms"])
+        );
+        assert_eq!(
+            metric_options(Some("upload"))[0],
+            json!({ "label": "upload_mbps", "value": "upload_mbps" })
+        );
+        assert_eq!(
+            metric_names(None).as_array().unwrap().len(),
+            Metric::ALL.len()
+        );
+    }
+}
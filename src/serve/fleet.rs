@@ -0,0 +1,607 @@
+//! Multi-probe orchestration for `cloud-speed fleet`.
+//!
+//! Agents are `cloud-speed serve` instances started with `--coordinator`,
+//! which register themselves with the coordinator's `POST /agents`. Once
+//! enough agents have registered, the coordinator opens every agent's
+//! event stream, starts a test on all of them at the same moment, and
+//! merges the outcomes into one report keyed by hostname and tags.
+
+use super::{read_request, write_json};
+use crate::reporters::CommandReport;
+use crate::retry::{retry_async, RetryConfig};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use reqwest::{Client as ReqwestClient, Response, StatusCode};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Barrier;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+/// How long a registration request to the coordinator may take.
+const REGISTER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Settings for a coordinator run.
+pub struct FleetConfig {
+    /// Number of agents to wait for before testing
+    pub agents: usize,
+    /// How long to wait for agents to register
+    pub wait: Duration,
+    /// How long each agent's test may take
+    pub timeout: Duration,
+}
+
+/// Merged outcome of one test round across the fleet.
+#[derive(Debug, Clone, Serialize)]
+pub struct FleetReport {
+    /// When the round started
+    pub timestamp: DateTime<Utc>,
+    /// One entry per agent, sorted by name
+    pub probes: Vec<ProbeReport>,
+}
+
+impl FleetReport {
+    /// Number of probes whose test failed.
+    pub fn failures(&self) -> usize {
+        self.probes.iter().filter(|probe| probe.error.is_some()).count()
+    }
+}
+
+/// Outcome of one agent's test.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeReport {
+    /// The agent's hostname, or its URL if the test failed
+    pub name: String,
+    /// Base URL of the agent's API
+    pub url: String,
+    /// The agent's `--tag` values
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// The agent's full results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub results: Option<Value>,
+    /// Why the test failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ProbeReport {
+    fn new(url: String, outcome: Result<Value, String>) -> Self {
+        match outcome {
+            Ok(results) => {
+                let meta = &results["meta"];
+                let name = meta["hostname"]
+                    .as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| url.clone());
+                let tags = meta["tags"]
+                    .as_object()
+                    .map(|tags| {
+                        tags.iter()
+                            .map(|(key, value)| {
+                                let value = match value.as_str() {
+                                    Some(value) => value.to_string(),
+                                    None => value.to_string(),
+                                };
+                                (key.clone(), value)
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                Self { name, url, tags, results: Some(results), error: None }
+            }
+            Err(error) => Self {
+                name: url.clone(),
+                url,
+                tags: BTreeMap::new(),
+                results: None,
+                error: Some(error),
+            },
+        }
+    }
+
+    /// Name and tags, as shown in the report.
+    fn label(&self) -> String {
+        if self.tags.is_empty() {
+            return self.name.clone();
+        }
+
+        let tags: Vec<String> = self
+            .tags
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        format!("{} [{}]", self.name, tags.join(","))
+    }
+}
+
+/// Register this agent with `coordinator`, retrying while it starts up.
+///
+/// The coordinator reaches the agent at `advertise` if given, otherwise
+/// at the address the registration came from and `port`.
+pub(super) async fn register(
+    coordinator: String,
+    advertise: Option<String>,
+    port: u16,
+) {
+    let client = match ReqwestClient::builder()
+        .no_proxy()
+        .timeout(REGISTER_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            log::warn!("Failed to create registration client: {}", e);
+            return;
+        }
+    };
+    let url = format!("{}/agents", coordinator.trim_end_matches('/'));
+    let body = json!({ "url": advertise, "port": port });
+
+    let result = retry_async(&RetryConfig::default(), "register", || async {
+        client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(Response::error_for_status)
+    })
+    .await;
+
+    match result.into_result("Registration") {
+        Ok(_) => eprintln!("Registered with coordinator {}", coordinator),
+        Err(e) => log::warn!("Failed to register with {}: {}", coordinator, e),
+    }
+}
+
+/// Bind to `listen`, wait for agents and run one test round on them.
+pub async fn run(
+    listen: SocketAddr,
+    config: FleetConfig,
+) -> Result<FleetReport, Box<dyn Error>> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .map_err(|e| format!("Failed to listen on {}: {}", listen, e))?;
+    eprintln!(
+        "Waiting for {} agent(s) on http://{}",
+        config.agents,
+        listener.local_addr()?
+    );
+
+    let agents = collect_agents(&listener, config.agents, config.wait).await;
+    if agents.is_empty() {
+        return Err(format!(
+            "No agents registered within {}s",
+            config.wait.as_secs()
+        )
+        .into());
+    }
+    if agents.len() < config.agents {
+        log::warn!(
+            "Only {} of {} agents registered; testing those",
+            agents.len(),
+            config.agents
+        );
+    }
+
+    eprintln!("Testing from {} agent(s)", agents.len());
+    run_round(agents, config.timeout).await
+}
+
+/// Accept registrations until `target` agents are known or `wait` runs
+/// out. Returns the agents' base URLs in registration order.
+async fn collect_agents(
+    listener: &TcpListener,
+    target: usize,
+    wait: Duration,
+) -> Vec<String> {
+    let deadline = Instant::now() + wait;
+    let mut agents = Vec::new();
+
+    while agents.len() < target {
+        let (mut stream, peer) =
+            match tokio::time::timeout_at(deadline, listener.accept()).await {
+                Ok(Ok(accepted)) => accepted,
+                Ok(Err(e)) => {
+                    log::warn!("Failed to accept connection: {}", e);
+                    continue;
+                }
+                Err(_) => break,
+            };
+
+        match handle_registration(&mut stream, peer).await {
+            Ok(Some(url)) => {
+                if !agents.contains(&url) {
+                    eprintln!("Agent registered: {}", url);
+                    agents.push(url);
+                }
+                let body = json!({ "agents": agents.len() });
+                let _ = write_json(&mut stream, 201, &body).await;
+            }
+            Ok(None) => {}
+            Err(e) => log::debug!("Registration from {} failed: {}", peer, e),
+        }
+    }
+
+    agents
+}
+
+/// Handle one request to the coordinator, returning the agent's URL if
+/// it was a valid registration. Other requests are answered here.
+async fn handle_registration(
+    stream: &mut TcpStream,
+    peer: SocketAddr,
+) -> Result<Option<String>, Box<dyn Error>> {
+    let Some(request) = read_request(stream).await? else {
+        let body = json!({ "error": "Malformed request" });
+        write_json(stream, 400, &body).await?;
+        return Ok(None);
+    };
+
+    if request.path.trim_end_matches('/') != "/agents" {
+        write_json(stream, 404, &json!({ "error": "Not found" })).await?;
+        return Ok(None);
+    }
+    if request.method != "POST" {
+        let body = json!({ "error": "Method not allowed" });
+        write_json(stream, 405, &body).await?;
+        return Ok(None);
+    }
+
+    match agent_url(&request.body, peer) {
+        Ok(url) => Ok(Some(url)),
+        Err(message) => {
+            write_json(stream, 400, &json!({ "error": message })).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// The base URL an agent registered with, from its `url`, or else from
+/// the connection's address and its `port`.
+fn agent_url(body: &[u8], peer: SocketAddr) -> Result<String, String> {
+    let body: Value = serde_json::from_slice(body)
+        .map_err(|e| format!("invalid registration: {}", e))?;
+
+    if let Some(url) = body["url"].as_str() {
+        return Ok(url.trim_end_matches('/').to_string());
+    }
+
+    match body["port"].as_u64().and_then(|port| u16::try_from(port).ok()) {
+        Some(port) => {
+            Ok(format!("http://{}", SocketAddr::new(peer.ip(), port)))
+        }
+        None => Err("registration needs a url or port".to_string()),
+    }
+}
+
+/// Start a test on every agent at once and collect the outcomes.
+async fn run_round(
+    agents: Vec<String>,
+    timeout: Duration,
+) -> Result<FleetReport, Box<dyn Error>> {
+    let client = ReqwestClient::builder().no_proxy().build()?;
+    let timestamp = Utc::now();
+    let barrier = Arc::new(Barrier::new(agents.len()));
+
+    let mut tasks = JoinSet::new();
+    for url in agents {
+        let client = client.clone();
+        let barrier = Arc::clone(&barrier);
+        tasks.spawn(async move {
+            let outcome = test_agent(&client, &url, &barrier, timeout).await;
+            ProbeReport::new(url, outcome)
+        });
+    }
+
+    let mut probes = Vec::new();
+    while let Some(probe) = tasks.join_next().await {
+        probes.push(probe?);
+    }
+    probes.sort_by(|a, b| a.name.cmp(&b.name).then(a.url.cmp(&b.url)));
+
+    Ok(FleetReport { timestamp, probes })
+}
+
+/// Run a test on the agent at `url` and wait for its results.
+///
+/// Every agent's event stream is opened before any test starts, so the
+/// tests begin together and no agent's outcome is missed.
+async fn test_agent(
+    client: &ReqwestClient,
+    url: &str,
+    barrier: &Barrier,
+    timeout: Duration,
+) -> Result<Value, String> {
+    let events = client
+        .get(format!("{}/events", url))
+        .send()
+        .await
+        .and_then(Response::error_for_status)
+        .map_err(|e| format!("Failed to open event stream: {}", e));
+    barrier.wait().await;
+    let events = events?;
+
+    let response = client
+        .post(format!("{}/tests", url))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to start test: {}", e))?;
+    let status = response.status();
+    let body: Value = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to start test: {}", e))?;
+    if status != StatusCode::ACCEPTED {
+        return Err(format!(
+            "Failed to start test: {}",
+            body["error"].as_str().unwrap_or(status.as_str())
+        ));
+    }
+    let id = body["id"].as_u64();
+
+    tokio::time::timeout(timeout, wait_for_outcome(events, id))
+        .await
+        .map_err(|_| format!("Timed out after {}s", timeout.as_secs()))?
+}
+
+/// Follow an agent's event stream until test `id` finishes.
+async fn wait_for_outcome(
+    mut events: Response,
+    id: Option<u64>,
+) -> Result<Value, String> {
+    let mut buffer = String::new();
+    loop {
+        let chunk = events
+            .chunk()
+            .await
+            .map_err(|e| format!("Event stream failed: {}", e))?
+            .ok_or("Event stream closed before the test finished")?;
+        buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+        while let Some(end) = buffer.find("\n\n") {
+            let frame: String = buffer.drain(..end + 2).collect();
+            let Some((event, mut data)) = parse_sse_frame(&frame) else {
+                continue;
+            };
+            if data["id"].as_u64() != id {
+                continue;
+            }
+
+            match event.as_str() {
+                "result" => return Ok(data["results"].take()),
+                "error" => {
+                    return Err(data["message"]
+                        .as_str()
+                        .unwrap_or("Test failed")
+                        .to_string())
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Parse a server-sent event into its name and JSON data.
+///
+/// Comments and events without JSON data are skipped.
+fn parse_sse_frame(frame: &str) -> Option<(String, Value)> {
+    let mut event = None;
+    let mut data = String::new();
+    for line in frame.lines() {
+        if let Some(name) = line.strip_prefix("event:") {
+            event = Some(name.trim().to_string());
+        } else if let Some(line) = line.strip_prefix("data:") {
+            data.push_str(line.trim());
+        }
+    }
+
+    Some((event?, serde_json::from_str(&data).ok()?))
+}
+
+/// Print the report as a comparison table.
+pub fn print_report(
+    report: &FleetReport,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let width = report
+        .probes
+        .iter()
+        .map(|probe| probe.label().len())
+        .max()
+        .unwrap_or(0)
+        .max("Probe".len());
+
+    writeln!(
+        out,
+        "{:<width$}  {:>14}  {:>14}  {:>10}  {:>10}",
+        "Probe", "Download", "Upload", "Latency", "Jitter"
+    )?;
+
+    for probe in &report.probes {
+        let label = probe.label();
+        let Some(ref results) = probe.results else {
+            let error = probe.error.as_deref().unwrap_or("no results");
+            writeln!(
+                out,
+                "{:<width$}  {}",
+                label,
+                format!("failed: {}", error).red()
+            )?;
+            continue;
+        };
+
+        let value = |pointer: &str, unit: &str| match results
+            .pointer(pointer)
+            .and_then(Value::as_f64)
+        {
+            Some(value) => format!("{:.2} {}", value, unit),
+            None => "-".to_string(),
+        };
+        writeln!(
+            out,
+            "{:<width$}  {:>14}  {:>14}  {:>10}  {:>10}",
+            label,
+            value("/download/speed_mbps", "Mbps"),
+            value("/upload/speed_mbps", "Mbps"),
+            value("/latency/idle_ms", "ms"),
+            value("/latency/idle_jitter_ms", "ms"),
+        )?;
+    }
+
+    Ok(())
+}
+
+impl CommandReport for FleetReport {
+    fn write_human(&self, out: &mut dyn Write) -> io::Result<()> {
+        print_report(self, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn sample_results(hostname: &str, download: f64) -> Value {
+        json!({
+            "download": { "speed_mbps": download },
+            "upload": { "speed_mbps": 20.0 },
+            "latency": { "idle_ms": 12.5, "idle_jitter_ms": 1.25 },
+            "meta": { "hostname": hostname, "tags": { "site": "nyc" } },
+        })
+    }
+
+    #[test]
+    fn test_agent_url() {
+        let peer: SocketAddr = "10.0.0.5:50123".parse().unwrap();
+        assert_eq!(
+            agent_url(br#"{"port": 8080}"#, peer),
+            Ok("http://10.0.0.5:8080".to_string())
+        );
+        assert_eq!(
+            agent_url(br#"{"url": "http://probe:9000/", "port": 8080}"#, peer),
+            Ok("http://probe:9000".to_string())
+        );
+
+        let peer: SocketAddr = "[::1]:50123".parse().unwrap();
+        assert_eq!(
+            agent_url(br#"{"port": 8080}"#, peer),
+            Ok("http://[::1]:8080".to_string())
+        );
+
+        assert!(agent_url(br#"{"port": 70000}"#, peer).is_err());
+        assert!(agent_url(b"not json", peer).is_err());
+    }
+
+    #[test]
+    fn test_parse_sse_frame() {
+        let (event, data) =
+            parse_sse_frame("event: result\ndata: {\"id\":3}\n\n").unwrap();
+        assert_eq!(event, "result");
+        assert_eq!(data["id"], 3);
+
+        assert_eq!(parse_sse_frame(": keep-alive\n\n"), None);
+    }
+
+    #[test]
+    fn test_probe_report_keys_by_hostname_and_tags() {
+        let probe = ProbeReport::new(
+            "http://10.0.0.5:8080".to_string(),
+            Ok(sample_results("nyc-1", 95.5)),
+        );
+        assert_eq!(probe.name, "nyc-1");
+        assert_eq!(probe.tags.get("site").map(String::as_str), Some("nyc"));
+        assert_eq!(probe.label(), "nyc-1 [site=nyc]");
+
+        let probe = ProbeReport::new(
+            "http://10.0.0.6:8080".to_string(),
+            Err("Timed out after 300s".to_string()),
+        );
+        assert_eq!(probe.name, "http://10.0.0.6:8080");
+        assert_eq!(probe.label(), "http://10.0.0.6:8080");
+    }
+
+    #[test]
+    fn test_print_report() {
+        colored::control::set_override(false);
+        let report = FleetReport {
+            timestamp: Utc::now(),
+            probes: vec![
+                ProbeReport::new(
+                    "http://a:8080".to_string(),
+                    Ok(sample_results("nyc-1", 95.5)),
+                ),
+                ProbeReport::new(
+                    "http://b:8080".to_string(),
+                    Err("Failed to start test: busy".to_string()),
+                ),
+            ],
+        };
+        assert_eq!(report.failures(), 1);
+
+        let mut out = Vec::new();
+        print_report(&report, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.starts_with("Probe"));
+        assert!(text.contains("nyc-1 [site=nyc]"));
+        assert!(text.contains("95.50 Mbps"));
+        assert!(text.contains("12.50 ms"));
+        assert!(text.contains("failed: Failed to start test: busy"));
+    }
+
+    #[tokio::test]
+    async fn test_collect_agents_registers_and_dedupes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let register = |body: &'static str| async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let request = format!(
+                "POST /agents HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let agents = async {
+            let response = register(r#"{"port": 8080}"#).await;
+            assert!(response.starts_with("HTTP/1.1 201"));
+            register(r#"{"port": 8080}"#).await;
+            register(r#"{"url": "http://probe:9000"}"#).await;
+        };
+
+        let (collected, _) = tokio::join!(
+            collect_agents(&listener, 2, Duration::from_secs(5)),
+            agents
+        );
+        assert_eq!(
+            collected,
+            vec![
+                "http://127.0.0.1:8080".to_string(),
+                "http://probe:9000".to_string()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collect_agents_gives_up_after_wait() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let agents =
+            collect_agents(&listener, 1, Duration::from_millis(50)).await;
+        assert!(agents.is_empty());
+    }
+}
@@ -0,0 +1,1157 @@
+//! Local HTTP API for `cloud-speed serve`.
+//!
+//! Lets dashboards and remote probes orchestrate tests over HTTP:
+//!
+//! - `POST /tests` starts a test (one at a time)
+//! - `GET /events` streams progress as server-sent events
+//! - `GET /ws/progress` streams the same events over a WebSocket
+//! - `GET /results?limit=N` returns the last N runs from history
+//! - `GET /results/latest` returns the full results of the last run
+//! - `GET /health` reports whether a test is running
+//! - `GET /` shows the last run and recent history as an HTML page
+//!
+//! Requests are parsed by hand, like the raw HTTP the tests themselves
+//! send; every response closes the connection except the event streams.
+
+use crate::clock::skew_secs;
+use crate::cloudflare::client::Client;
+use crate::cloudflare::requests::{locations::Locations, meta::MetaRequest};
+use crate::cloudflare::tests::engine::{TestConfig, TestEngine};
+use crate::errors::classify_error;
+use crate::history::{HistoryEntry, HistoryStore};
+use crate::results::{
+    ConnectionMeta, RunMeta, ServerLocation, SpeedTestResults,
+};
+use crate::scoring::ScoringThresholds;
+use crate::tui::progress::{ProgressCallback, ProgressEvent};
+use chrono::Utc;
+use serde_json::{json, Value};
+use sink::{LogSink, Record, SinkWriter};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+
+pub mod datasource;
+pub mod fleet;
+pub mod sink;
+mod status_page;
+mod systemd;
+mod websocket;
+
+/// Default number of history entries returned by `GET /results`.
+const DEFAULT_RESULTS_LIMIT: usize = 10;
+
+/// Largest number of history entries returned by `GET /results`.
+const MAX_RESULTS_LIMIT: usize = 1000;
+
+/// Largest request head accepted, in bytes.
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+/// Events buffered per subscriber before slow ones start missing events.
+const EVENT_BUFFER: usize = 256;
+
+/// Interval between keep-alive comments or pings on idle event streams.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Everything needed to run a test on request.
+pub struct ServeConfig {
+    /// Engine configuration for each test
+    pub test_config: TestConfig,
+    /// Thresholds for the quality scores
+    pub scoring: ScoringThresholds,
+    /// Tags attached to every result
+    pub tags: BTreeMap<String, String>,
+    /// Where completed runs are recorded and read back from
+    pub history: Option<HistoryStore>,
+    /// Fleet coordinator to register with as an agent
+    pub coordinator: Option<String>,
+    /// URL the coordinator should reach this agent at
+    pub advertise: Option<String>,
+    /// Remove the client IP, ISP and hostname from published results
+    pub anonymize: bool,
+    /// Where result summaries and errors are logged, besides stderr
+    pub log_sink: Option<LogSink>,
+}
+
+/// State shared between connections and the test runner.
+struct ServerState {
+    /// Events fanned out to every event stream client
+    events: broadcast::Sender<ServerEvent>,
+    /// Queue of test ids to run
+    trigger: mpsc::Sender<u64>,
+    /// Whether a test is queued or running
+    running: AtomicBool,
+    /// Id of the most recently started test
+    last_id: AtomicU64,
+    /// Results of the last successful test
+    latest: Mutex<Option<SpeedTestResults>>,
+    /// History store for `GET /results`
+    history: Option<HistoryStore>,
+}
+
+/// An event published to event stream clients.
+#[derive(Debug, Clone)]
+struct ServerEvent {
+    /// Event name: `started`, `progress`, `result` or `error`
+    name: &'static str,
+    data: Value,
+}
+
+/// A parsed request head.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Request {
+    method: String,
+    path: String,
+    query: Option<String>,
+    /// Header names are lowercased
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// The first value of header `name` (lowercase).
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The endpoints the server exposes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Route {
+    StatusPage,
+    Health,
+    StartTest,
+    Events,
+    ProgressSocket,
+    Results,
+    LatestResult,
+    MethodNotAllowed,
+    NotFound,
+}
+
+/// Bind to `listen` and serve until SIGTERM.
+pub async fn run(
+    listen: SocketAddr,
+    config: ServeConfig,
+) -> Result<(), Box<dyn Error>> {
+    // Installed before reporting ready so an early SIGTERM isn't lost
+    run_until(listen, config, systemd::sigterm()).await
+}
+
+/// Bind to `listen` and serve until `stop` resolves, finishing any test
+/// that is already running.
+pub async fn run_until(
+    listen: SocketAddr,
+    config: ServeConfig,
+    stop: impl std::future::Future<Output = ()>,
+) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .map_err(|e| format!("Failed to listen on {}: {}", listen, e))?;
+    log::info!("Listening on http://{}", listener.local_addr()?);
+    eprintln!("Serving on http://{}", listener.local_addr()?);
+
+    let sink = config.log_sink.map(SinkWriter::open).transpose()?;
+
+    let notifier = systemd::Notifier::from_env().map(Arc::new);
+    if let Some(ref notifier) = notifier {
+        notifier.notify("READY=1");
+        if let Some(interval) = systemd::watchdog_interval() {
+            tokio::spawn(ping_watchdog(Arc::clone(notifier), interval));
+        }
+    }
+    let shutdown = async move {
+        stop.await;
+        log::info!("Stopping");
+        if let Some(notifier) = notifier {
+            notifier.notify("STOPPING=1");
+        }
+    };
+
+    if let Some(ref coordinator) = config.coordinator {
+        tokio::spawn(fleet::register(
+            coordinator.clone(),
+            config.advertise.clone(),
+            listener.local_addr()?.port(),
+        ));
+    }
+
+    serve(listener, config, sink, shutdown).await;
+    Ok(())
+}
+
+/// Tell systemd's watchdog the server is alive every `interval`.
+async fn ping_watchdog(notifier: Arc<systemd::Notifier>, interval: Duration) {
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        notifier.notify("WATCHDOG=1");
+    }
+}
+
+/// Accept connections on `listener` and run requested tests.
+///
+/// Tests run on this future rather than a spawned task, one at a time,
+/// since concurrent tests would compete for the same link. Each outcome
+/// is also logged to `sink`, when given.
+///
+/// Returns once `shutdown` resolves, after finishing any test that is
+/// already running.
+async fn serve(
+    listener: TcpListener,
+    config: ServeConfig,
+    sink: Option<SinkWriter>,
+    shutdown: impl std::future::Future<Output = ()>,
+) {
+    let (state, mut queue) = ServerState::new(config.history.clone());
+    tokio::spawn(accept_loop(listener, Arc::clone(&state)));
+
+    // One engine serves every test; each run keeps its own state
+    let progress = Arc::new(EventProgress { state: Arc::clone(&state) });
+    let engine = TestEngine::new(config.test_config.clone(), Some(progress));
+
+    // Shutdown is only checked between tests, so one in flight completes
+    tokio::pin!(shutdown);
+    loop {
+        let id = tokio::select! {
+            biased;
+            _ = &mut shutdown => break,
+            id = queue.recv() => match id {
+                Some(id) => id,
+                None => break,
+            },
+        };
+        run_requested_test(id, &config, &engine, &state, sink.as_ref()).await;
+        state.running.store(false, Ordering::Release);
+    }
+}
+
+impl ServerState {
+    /// Create the shared state and the queue of requested test ids.
+    fn new(history: Option<HistoryStore>) -> (Arc<Self>, mpsc::Receiver<u64>) {
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        let (trigger, queue) = mpsc::channel(1);
+        let state = Arc::new(ServerState {
+            events,
+            trigger,
+            running: AtomicBool::new(false),
+            last_id: AtomicU64::new(0),
+            latest: Mutex::new(None),
+            history,
+        });
+        (state, queue)
+    }
+}
+
+async fn accept_loop(listener: TcpListener, state: Arc<ServerState>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let state = Arc::clone(&state);
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream, &state).await {
+                        log::debug!("Connection from {} failed: {}", peer, e);
+                    }
+                });
+            }
+            Err(e) => log::warn!("Failed to accept connection: {}", e),
+        }
+    }
+}
+
+/// Run test `id`, publishing its progress and outcome as events.
+async fn run_requested_test(
+    id: u64,
+    config: &ServeConfig,
+    engine: &TestEngine,
+    state: &Arc<ServerState>,
+    sink: Option<&SinkWriter>,
+) {
+    log::info!("Starting test {}", id);
+    publish(state, "started", json!({ "id": id }));
+
+    match run_test(config, engine).await {
+        Ok(results) => {
+            if let Some((store, entry)) = config
+                .history
+                .as_ref()
+                .zip(HistoryEntry::from_results(&results))
+            {
+                if let Err(e) = store.append(&entry) {
+                    log::warn!(
+                        "Failed to write history {}: {}",
+                        store.path().display(),
+                        e
+                    );
+                }
+            }
+            // History keeps everything, like the CLI's
+            let results =
+                if config.anonymize { results.anonymized() } else { results };
+            log_to_sink(sink, &Record::result(id, &results));
+
+            let json = serde_json::to_value(&results).unwrap_or(Value::Null);
+            if let Ok(mut latest) = state.latest.lock() {
+                *latest = Some(results);
+            }
+            publish(state, "result", json!({ "id": id, "results": json }));
+        }
+        Err(e) => {
+            log::warn!("Test {} failed: {}", id, e);
+            let kind = classify_error(e.as_ref());
+            log_to_sink(sink, &Record::error(id, kind, &e.to_string()));
+            publish(
+                state,
+                "error",
+                json!({
+                    "id": id,
+                    "kind": format!("{:?}", kind),
+                    "message": e.to_string(),
+                    "suggestion": kind.suggestion(),
+                }),
+            );
+        }
+    }
+}
+
+/// Send `record` to `sink`, if there is one.
+fn log_to_sink(sink: Option<&SinkWriter>, record: &Record) {
+    if let Some(sink) = sink {
+        if let Err(e) = sink.send(record) {
+            log::warn!("Failed to write to the log sink: {}", e);
+        }
+    }
+}
+
+/// Run one complete test.
+async fn run_test(
+    config: &ServeConfig,
+    engine: &TestEngine,
+) -> Result<SpeedTestResults, Box<dyn Error>> {
+    let client = Client::from_headers(config.test_config.headers.clone());
+    let proxy = client.active_proxy();
+
+    let (meta, server_date) = client
+        .send_dated(MetaRequest {})
+        .await
+        .map_err(|e| format!("Failed to fetch connection metadata: {}", e))?;
+    let clock_skew = server_date.map(|date| skew_secs(Utc::now(), date));
+    let location = client
+        .send(Locations {})
+        .await
+        .map_err(|e| format!("Failed to fetch server locations: {}", e))?
+        .get(&meta.colo.iata);
+
+    let output = engine.run().await?;
+
+    let server =
+        ServerLocation::new(location.city.clone(), location.iata.clone())
+            .with_ip(output.endpoint_ip().map(|ip| ip.to_string()));
+    let connection = ConnectionMeta::new(
+        meta.client_ip.clone(),
+        meta.country.clone(),
+        meta.as_organization.clone(),
+        meta.asn,
+    )
+    .with_proxy(proxy);
+
+    let results = SpeedTestResults::from_engine_output(
+        &output,
+        server,
+        connection,
+        None,
+        &config.scoring,
+    )
+    .with_meta(Some(
+        RunMeta::detect()
+            .with_tags(config.tags.clone())
+            .with_vpn(crate::vpn::detect(
+                Some(&meta.as_organization),
+                output.path_mtu.map(|path_mtu| path_mtu.bytes),
+            ))
+            .with_dscp(config.test_config.socket_options.dscp)
+            .with_order(config.test_config.bandwidth_order())
+            .with_pacing_jitter(config.test_config.pacing_jitter.is_some())
+            .with_clock(clock_skew, output.clock_step)
+            .with_rate_limit(output.rate_limit.as_ref())
+            .with_network_changes(&output.network_changes),
+    ));
+    Ok(results)
+}
+
+/// Forwards engine progress to event stream subscribers.
+struct EventProgress {
+    state: Arc<ServerState>,
+}
+
+impl ProgressCallback for EventProgress {
+    fn on_progress(&self, event: ProgressEvent) {
+        publish(&self.state, "progress", progress_json(&event));
+    }
+}
+
+/// Send an event to every event stream subscriber.
+fn publish(state: &ServerState, name: &'static str, data: Value) {
+    // No subscribers is not an error
+    let _ = state.events.send(ServerEvent { name, data });
+}
+
+/// Format a server-sent event.
+fn sse_frame(event: &ServerEvent) -> String {
+    format!("event: {}\ndata: {}\n\n", event.name, event.data)
+}
+
+/// Format an event as a WebSocket text message.
+fn socket_message(event: &ServerEvent) -> String {
+    json!({ "event": event.name, "data": event.data }).to_string()
+}
+
+/// JSON form of a progress event.
+fn progress_json(event: &ProgressEvent) -> Value {
+    match event {
+        ProgressEvent::PhaseChange(phase) => {
+            json!({ "type": "phase_change", "phase": phase })
+        }
+        ProgressEvent::PhaseComplete(phase) => {
+            json!({ "type": "phase_complete", "phase": phase })
+        }
+        ProgressEvent::LatencyMeasurement { value_ms, current, total } => {
+            json!({
+                "type": "latency",
+                "value_ms": value_ms,
+                "current": current,
+                "total": total,
+            })
+        }
+        ProgressEvent::BandwidthMeasurement {
+            direction,
+            speed_mbps,
+            bytes,
+            current,
+            total,
+        } => json!({
+            "type": "bandwidth",
+            "direction": direction,
+            "speed_mbps": speed_mbps,
+            "bytes": bytes,
+            "current": current,
+            "total": total,
+        }),
+        ProgressEvent::LatencyComplete { idle_ms, jitter_ms } => json!({
+            "type": "latency_complete",
+            "idle_ms": idle_ms,
+            "jitter_ms": jitter_ms,
+        }),
+        ProgressEvent::SizeBlockComplete {
+            direction,
+            bytes,
+            speed_mbps,
+            count,
+        } => json!({
+            "type": "size_block",
+            "direction": direction,
+            "bytes": bytes,
+            "speed_mbps": speed_mbps,
+            "count": count,
+        }),
+        ProgressEvent::Stalled { direction, bytes, idle_secs } => json!({
+            "type": "stalled",
+            "direction": direction,
+            "bytes": bytes,
+            "idle_secs": idle_secs,
+        }),
+        ProgressEvent::Paused => json!({ "type": "paused" }),
+        ProgressEvent::Resumed => json!({ "type": "resumed" }),
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    state: &Arc<ServerState>,
+) -> Result<(), Box<dyn Error>> {
+    let Some(request) = read_request(&mut stream).await? else {
+        return write_json(
+            &mut stream,
+            400,
+            &json!({ "error": "Malformed request" }),
+        )
+        .await;
+    };
+    log::debug!("{} {}", request.method, request.path);
+
+    match route(&request) {
+        Route::Health => {
+            let body = json!({
+                "status": "ok",
+                "running": state.running.load(Ordering::Acquire),
+            });
+            write_json(&mut stream, 200, &body).await
+        }
+        Route::StartTest => start_test(&mut stream, state).await,
+        Route::Events => stream_events(stream, state).await,
+        Route::ProgressSocket => {
+            stream_progress_socket(stream, &request, state).await
+        }
+        Route::Results => {
+            let limit = match parse_limit(request.query.as_deref()) {
+                Ok(limit) => limit,
+                Err(message) => {
+                    let body = json!({ "error": message });
+                    return write_json(&mut stream, 400, &body).await;
+                }
+            };
+            recent_results(&mut stream, state, limit).await
+        }
+        Route::LatestResult => {
+            let latest =
+                state.latest.lock().ok().and_then(|latest| latest.clone());
+            match latest {
+                Some(results) => {
+                    let body = serde_json::to_string(&results)?;
+                    write_response(&mut stream, 200, "application/json", &body)
+                        .await
+                }
+                None => {
+                    let body = json!({ "error": "No test has completed yet" });
+                    write_json(&mut stream, 404, &body).await
+                }
+            }
+        }
+        Route::StatusPage => {
+            let latest =
+                state.latest.lock().ok().and_then(|latest| latest.clone());
+            let history = match state.history {
+                Some(ref store) => match store.load() {
+                    Ok(entries) => Some(entries),
+                    Err(e) => {
+                        log::warn!("Failed to read history: {}", e);
+                        None
+                    }
+                },
+                None => None,
+            };
+            let page = status_page::render(
+                latest.as_ref(),
+                history.as_deref(),
+                state.running.load(Ordering::Acquire),
+            );
+            write_response(&mut stream, 200, "text/html; charset=utf-8", &page)
+                .await
+        }
+        Route::MethodNotAllowed => {
+            let body = json!({ "error": "Method not allowed" });
+            write_json(&mut stream, 405, &body).await
+        }
+        Route::NotFound => {
+            write_json(&mut stream, 404, &json!({ "error": "Not found" }))
+                .await
+        }
+    }
+}
+
+async fn start_test(
+    stream: &mut TcpStream,
+    state: &ServerState,
+) -> Result<(), Box<dyn Error>> {
+    if state
+        .running
+        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        let body = json!({
+            "error": "A test is already running",
+            "id": state.last_id.load(Ordering::Acquire),
+        });
+        return write_json(stream, 409, &body).await;
+    }
+
+    let id = state.last_id.fetch_add(1, Ordering::AcqRel) + 1;
+    if state.trigger.send(id).await.is_err() {
+        state.running.store(false, Ordering::Release);
+        let body = json!({ "error": "Test runner stopped" });
+        return write_json(stream, 503, &body).await;
+    }
+
+    write_json(stream, 202, &json!({ "id": id, "events": "/events" })).await
+}
+
+/// Stream events to the client until it disconnects.
+async fn stream_events(
+    mut stream: TcpStream,
+    state: &ServerState,
+) -> Result<(), Box<dyn Error>> {
+    let mut events = state.events.subscribe();
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\n\
+              Content-Type: text/event-stream\r\n\
+              Cache-Control: no-cache\r\n\
+              Connection: keep-alive\r\n\
+              \r\n",
+        )
+        .await?;
+
+    let mut keep_alive = tokio::time::interval(KEEP_ALIVE_INTERVAL);
+    loop {
+        let frame = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => sse_frame(&event),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    format!(": missed {} events\n\n", missed)
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+            _ = keep_alive.tick() => ": keep-alive\n\n".to_string(),
+        };
+        // A failed write means the client went away
+        stream.write_all(frame.as_bytes()).await?;
+    }
+}
+
+/// Upgrade to a WebSocket and stream events as JSON text messages until
+/// the client closes the socket or disconnects.
+async fn stream_progress_socket(
+    mut stream: TcpStream,
+    request: &Request,
+    state: &ServerState,
+) -> Result<(), Box<dyn Error>> {
+    let upgrade = request
+        .header("upgrade")
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+    let key = match request.header("sec-websocket-key") {
+        Some(key) if upgrade => key,
+        _ => {
+            let body = json!({ "error": "Expected a WebSocket upgrade" });
+            return write_json(&mut stream, 400, &body).await;
+        }
+    };
+    if request.header("sec-websocket-version") != Some(websocket::VERSION) {
+        let response = format!(
+            "HTTP/1.1 426 Upgrade Required\r\n\
+            Sec-WebSocket-Version: {}\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n",
+            websocket::VERSION
+        );
+        stream.write_all(response.as_bytes()).await?;
+        return Ok(());
+    }
+
+    // Subscribe before completing the handshake so no event published
+    // after the client sees the upgrade is missed
+    let mut events = state.events.subscribe();
+    stream.write_all(websocket::handshake_response(key).as_bytes()).await?;
+
+    let (mut reader, mut writer) = stream.into_split();
+
+    // Frames are read on their own task because a partially read frame
+    // would be lost if `select!` cancelled the read
+    let (frames_tx, mut frames) = mpsc::channel(8);
+    let read_task = tokio::spawn(async move {
+        while let Ok(frame) = websocket::read_frame(&mut reader).await {
+            if frames_tx.send(frame).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut keep_alive = tokio::time::interval(KEEP_ALIVE_INTERVAL);
+    let result = loop {
+        let frame = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => websocket::encode_frame(
+                    websocket::OP_TEXT,
+                    socket_message(&event).as_bytes(),
+                ),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    log::debug!("WebSocket client missed {} events", missed);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    let _ = writer
+                        .write_all(&websocket::encode_frame(
+                            websocket::OP_CLOSE,
+                            &[],
+                        ))
+                        .await;
+                    break Ok(());
+                }
+            },
+            frame = frames.recv() => match frame {
+                Some(frame) if frame.opcode == websocket::OP_PING => {
+                    websocket::encode_frame(
+                        websocket::OP_PONG,
+                        &frame.payload,
+                    )
+                }
+                Some(frame) if frame.opcode == websocket::OP_CLOSE => {
+                    // Echo the status code back, as RFC 6455 asks
+                    let status = frame.payload.get(..2).unwrap_or(&[]);
+                    let _ = writer
+                        .write_all(&websocket::encode_frame(
+                            websocket::OP_CLOSE,
+                            status,
+                        ))
+                        .await;
+                    break Ok(());
+                }
+                Some(_) => continue,
+                // Disconnected or sent an invalid frame
+                None => break Ok(()),
+            },
+            _ = keep_alive.tick() => {
+                websocket::encode_frame(websocket::OP_PING, &[])
+            }
+        };
+
+        // A failed write means the client went away
+        if let Err(e) = writer.write_all(&frame).await {
+            break Err(e.into());
+        }
+    };
+
+    read_task.abort();
+    result
+}
+
+async fn recent_results(
+    stream: &mut TcpStream,
+    state: &ServerState,
+    limit: usize,
+) -> Result<(), Box<dyn Error>> {
+    let Some(ref store) = state.history else {
+        let body = json!({ "error": "History is unavailable" });
+        return write_json(stream, 503, &body).await;
+    };
+
+    match store.load() {
+        Ok(entries) => {
+            let recent: Vec<_> = entries.iter().rev().take(limit).collect();
+            write_json(stream, 200, &json!(recent)).await
+        }
+        Err(e) => {
+            let body = json!({
+                "error": format!("Failed to read history: {}", e),
+            });
+            write_json(stream, 500, &body).await
+        }
+    }
+}
+
+/// Read a request head and any body announced by `Content-Length`.
+///
+/// Returns `None` if the request is malformed.
+async fn read_request(
+    stream: &mut TcpStream,
+) -> Result<Option<Request>, Box<dyn Error>> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 1024];
+    let head_end = loop {
+        if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if data.len() > MAX_REQUEST_BYTES {
+            return Err("Request head too large".into());
+        }
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            break data.len();
+        }
+        data.extend_from_slice(&buf[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&data[..head_end]).into_owned();
+    let Some(mut request) = parse_request(&head) else {
+        return Ok(None);
+    };
+
+    let length = match request.header("content-length") {
+        None => 0,
+        Some(value) => match value.parse::<usize>() {
+            Ok(length) if length <= MAX_REQUEST_BYTES => length,
+            _ => return Ok(None),
+        },
+    };
+    let mut body = data.split_off(head_end);
+    while body.len() < length {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        body.extend_from_slice(&buf[..read]);
+    }
+    body.truncate(length);
+    request.body = body;
+
+    Ok(Some(request))
+}
+
+/// Parse the request line and headers of an HTTP/1.x request head.
+fn parse_request(head: &str) -> Option<Request> {
+    let mut lines = head.lines();
+    let mut parts = lines.next()?.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?;
+    if !parts.next()?.starts_with("HTTP/1.") {
+        return None;
+    }
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query.to_string())),
+        None => (target, None),
+    };
+
+    let headers = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| {
+            (name.trim().to_ascii_lowercase(), value.trim().to_string())
+        })
+        .collect();
+
+    Some(Request {
+        method,
+        path: path.to_string(),
+        query,
+        headers,
+        body: Vec::new(),
+    })
+}
+
+fn route(request: &Request) -> Route {
+    let method = request.method.as_str();
+    let (route, allowed) = match request.path.trim_end_matches('/') {
+        "" => (Route::StatusPage, "GET"),
+        "/health" => (Route::Health, "GET"),
+        "/tests" => (Route::StartTest, "POST"),
+        "/events" => (Route::Events, "GET"),
+        "/ws/progress" => (Route::ProgressSocket, "GET"),
+        "/results" => (Route::Results, "GET"),
+        "/results/latest" => (Route::LatestResult, "GET"),
+        _ => return Route::NotFound,
+    };
+
+    if method == allowed {
+        route
+    } else {
+        Route::MethodNotAllowed
+    }
+}
+
+/// Parse the `limit` query parameter of `GET /results`.
+fn parse_limit(query: Option<&str>) -> Result<usize, String> {
+    let value = query.and_then(|query| {
+        query.split('&').find_map(|pair| pair.strip_prefix("limit="))
+    });
+
+    match value {
+        None => Ok(DEFAULT_RESULTS_LIMIT),
+        Some(value) => match value.parse::<usize>() {
+            Ok(limit) if limit > 0 => Ok(limit.min(MAX_RESULTS_LIMIT)),
+            _ => Err(format!(
+                "invalid limit '{}' (expected a positive integer)",
+                value
+            )),
+        },
+    }
+}
+
+async fn write_json(
+    stream: &mut TcpStream,
+    status: u16,
+    body: &Value,
+) -> Result<(), Box<dyn Error>> {
+    write_response(stream, status, "application/json", &body.to_string()).await
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    status: u16,
+    content_type: &str,
+    body: &str,
+) -> Result<(), Box<dyn Error>> {
+    let response = format!(
+        "HTTP/1.1 {} {}\r\n\
+        Content-Type: {}\r\n\
+        Content-Length: {}\r\n\
+        Connection: close\r\n\
+        \r\n\
+        {}",
+        status,
+        reason_phrase(status),
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn request(method: &str, path: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: None,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let head = "GET /results?limit=5 HTTP/1.1\r\nHost: x\r\n\
+                    Upgrade:  WebSocket \r\n\r\n";
+        let request = parse_request(head).unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/results");
+        assert_eq!(request.query.as_deref(), Some("limit=5"));
+        assert_eq!(request.header("host"), Some("x"));
+        assert_eq!(request.header("upgrade"), Some("WebSocket"));
+        assert_eq!(request.header("origin"), None);
+
+        assert_eq!(parse_request(""), None);
+        assert_eq!(parse_request("GET /\r\n\r\n"), None);
+        assert_eq!(parse_request("GET / SPDY/3\r\n\r\n"), None);
+    }
+
+    #[test]
+    fn test_route() {
+        assert_eq!(route(&request("GET", "/")), Route::StatusPage);
+        assert_eq!(route(&request("GET", "/health")), Route::Health);
+        assert_eq!(route(&request("POST", "/tests")), Route::StartTest);
+        assert_eq!(route(&request("GET", "/events")), Route::Events);
+        assert_eq!(
+            route(&request("GET", "/ws/progress")),
+            Route::ProgressSocket
+        );
+        assert_eq!(route(&request("GET", "/results/")), Route::Results);
+        assert_eq!(
+            route(&request("GET", "/results/latest")),
+            Route::LatestResult
+        );
+        assert_eq!(route(&request("GET", "/tests")), Route::MethodNotAllowed);
+        assert_eq!(route(&request("GET", "/nope")), Route::NotFound);
+    }
+
+    #[test]
+    fn test_parse_limit() {
+        assert_eq!(parse_limit(None), Ok(DEFAULT_RESULTS_LIMIT));
+        assert_eq!(parse_limit(Some("foo=1&limit=3")), Ok(3));
+        assert_eq!(parse_limit(Some("limit=100000")), Ok(MAX_RESULTS_LIMIT));
+        assert!(parse_limit(Some("limit=0")).is_err());
+        assert!(parse_limit(Some("limit=abc")).is_err());
+    }
+
+    #[test]
+    fn test_progress_json() {
+        use crate::tui::progress::{BandwidthDirection, TestPhase};
+
+        let json =
+            progress_json(&ProgressEvent::PhaseChange(TestPhase::Download));
+        assert_eq!(
+            json,
+            json!({ "type": "phase_change", "phase": "download" })
+        );
+
+        let json = progress_json(&ProgressEvent::BandwidthMeasurement {
+            direction: BandwidthDirection::Upload,
+            speed_mbps: 42.0,
+            bytes: 1_000_000,
+            current: 2,
+            total: 10,
+        });
+        assert_eq!(json["type"], "bandwidth");
+        assert_eq!(json["direction"], "upload");
+        assert_eq!(json["bytes"], 1_000_000);
+
+        let json = progress_json(&ProgressEvent::Stalled {
+            direction: BandwidthDirection::Download,
+            bytes: 25_000_000,
+            idle_secs: 12,
+        });
+        assert_eq!(json["type"], "stalled");
+        assert_eq!(json["idle_secs"], 12);
+    }
+
+    #[test]
+    fn test_event_formats() {
+        let event = ServerEvent { name: "started", data: json!({ "id": 1 }) };
+        assert_eq!(sse_frame(&event), "event: started\ndata: {\"id\":1}\n\n");
+        assert_eq!(
+            socket_message(&event),
+            r#"{"data":{"id":1},"event":"started"}"#
+        );
+    }
+
+    async fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET {} HTTP/1.1\r\n\r\n", path).as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_progress_socket_streams_events() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (state, _queue) = ServerState::new(None);
+        tokio::spawn(accept_loop(listener, Arc::clone(&state)));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(
+                b"GET /ws/progress HTTP/1.1\r\n\
+                  Upgrade: websocket\r\n\
+                  Connection: Upgrade\r\n\
+                  Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                  Sec-WebSocket-Version: 13\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        let head = String::from_utf8(head).unwrap();
+        assert!(head.starts_with("HTTP/1.1 101 Switching Protocols"));
+        assert!(head.contains("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+
+        publish(
+            &state,
+            "progress",
+            progress_json(&ProgressEvent::LatencyMeasurement {
+                value_ms: 12.5,
+                current: 1,
+                total: 20,
+            }),
+        );
+
+        // Skip the keep-alive ping sent on connect
+        let mut opcode = 0;
+        let mut payload = Vec::new();
+        while opcode != websocket::OP_TEXT {
+            opcode = stream.read_u8().await.unwrap() & 0x0f;
+            let len = stream.read_u8().await.unwrap() as usize;
+            payload = vec![0u8; len];
+            stream.read_exact(&mut payload).await.unwrap();
+        }
+        let message: Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(message["event"], "progress");
+        assert_eq!(message["data"]["type"], "latency");
+        assert_eq!(message["data"]["value_ms"], 12.5);
+    }
+
+    #[tokio::test]
+    async fn test_progress_socket_requires_upgrade() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (state, _queue) = ServerState::new(None);
+        tokio::spawn(accept_loop(listener, state));
+
+        let response = get(addr, "/ws/progress").await;
+        assert!(response.starts_with("HTTP/1.1 400"));
+    }
+
+    #[tokio::test]
+    async fn test_serves_health_and_history() {
+        let dir = std::env::temp_dir()
+            .join(format!("cloud-speed-serve-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let store = HistoryStore::new(dir.join("history.jsonl"));
+        for download_mbps in [10.0, 20.0, 30.0] {
+            store
+                .append(&HistoryEntry {
+                    timestamp: chrono::Utc::now(),
+                    download_mbps,
+                    upload_mbps: 5.0,
+                    latency_ms: 12.0,
+                    jitter_ms: None,
+                    loaded_down_ms: None,
+                    loaded_up_ms: None,
+                    packet_loss_percent: None,
+                    load_streams: None,
+                    order: None,
+                })
+                .unwrap();
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = ServeConfig {
+            test_config: TestConfig::default(),
+            scoring: ScoringThresholds::default(),
+            tags: BTreeMap::new(),
+            history: Some(store),
+            coordinator: None,
+            advertise: None,
+            anonymize: false,
+            log_sink: None,
+        };
+        let requests = async {
+            let response = get(addr, "/health").await;
+            assert!(response.starts_with("HTTP/1.1 200 OK"));
+            assert!(response.ends_with(r#"{"running":false,"status":"ok"}"#));
+
+            let response = get(addr, "/results?limit=2").await;
+            let body = response.split("\r\n\r\n").nth(1).unwrap();
+            let entries: Vec<Value> = serde_json::from_str(body).unwrap();
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0]["download_mbps"], 30.0);
+
+            let response = get(addr, "/results/latest").await;
+            assert!(response.starts_with("HTTP/1.1 404"));
+
+            let response = get(addr, "/").await;
+            assert!(response.contains("Content-Type: text/html"));
+            assert!(response.contains("<h2>Last 3 tests</h2>"));
+        };
+
+        // The server runs tests on its own future, which isn't `Send`
+        tokio::select! {
+            _ = serve(
+                listener,
+                config,
+                None,
+                std::future::pending(),
+            ) => unreachable!(),
+            _ = requests => {}
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
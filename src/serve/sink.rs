@@ -0,0 +1,363 @@
+//! Result summaries and errors for syslog, the systemd journal or the
+//! Windows event log (`serve --log-sink`).
+//!
+//! Records are sent as datagrams to the local socket of each service, so
+//! nothing beyond the standard library is needed: syslog gets RFC 5424
+//! messages with the fields as structured data, and the journal gets its
+//! native `KEY=value` format so each field can be matched with
+//! `journalctl CLOUDSPEED_DOWNLOAD_MBPS=...`. Event log entries list the
+//! fields below the message.
+
+use crate::errors::ErrorKind;
+use crate::results::SpeedTestResults;
+#[cfg(unix)]
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+#[cfg(all(windows, feature = "service"))]
+mod event_log;
+
+/// Socket syslog daemons listen on.
+#[cfg(unix)]
+const SYSLOG_SOCKET: &str = "/dev/log";
+
+/// Socket of the journal's native protocol.
+#[cfg(unix)]
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Name records are logged under.
+const IDENTIFIER: &str = "cloud-speed";
+
+/// Syslog facility for system daemons.
+#[cfg(unix)]
+const FACILITY_DAEMON: u8 = 3;
+
+/// Structured data ID for syslog fields. 32473 is the private enterprise
+/// number reserved for documentation (RFC 5612).
+#[cfg(unix)]
+const SD_ID: &str = "cloudspeed@32473";
+
+/// Where `serve` sends result summaries and errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogSink {
+    /// The local syslog daemon
+    Syslog,
+    /// The systemd journal
+    Journald,
+    /// The Windows event log (with the `service` feature)
+    EventLog,
+}
+
+impl fmt::Display for LogSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogSink::Syslog => write!(f, "syslog"),
+            LogSink::Journald => write!(f, "journald"),
+            LogSink::EventLog => write!(f, "eventlog"),
+        }
+    }
+}
+
+impl FromStr for LogSink {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "syslog" => Ok(LogSink::Syslog),
+            "journald" | "journal" => Ok(LogSink::Journald),
+            "eventlog" => Ok(LogSink::EventLog),
+            other => Err(format!(
+                "unknown log sink '{}' (expected syslog, journald or \
+                 eventlog)",
+                other
+            )),
+        }
+    }
+}
+
+/// Syslog severity of a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error = 3,
+    Info = 6,
+}
+
+/// A log message with structured fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub severity: Severity,
+    pub message: String,
+    /// Field names in the journal's form: uppercase, digits and `_`
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl Record {
+    /// Summary of a completed test.
+    pub fn result(id: u64, results: &SpeedTestResults) -> Self {
+        let mut fields = vec![("TEST_ID", id.to_string())];
+        if let Some(ref download) = results.download {
+            fields.push((
+                "DOWNLOAD_MBPS",
+                format!("{:.2}", download.speed_mbps),
+            ));
+        }
+        if let Some(ref upload) = results.upload {
+            fields.push(("UPLOAD_MBPS", format!("{:.2}", upload.speed_mbps)));
+        }
+        fields.push(("LATENCY_MS", format!("{:.2}", results.latency.idle_ms)));
+        if let Some(jitter) = results.latency.idle_jitter_ms {
+            fields.push(("JITTER_MS", format!("{:.2}", jitter)));
+        }
+        if let Some(ref loss) = results.packet_loss {
+            fields.push(("LOSS_PERCENT", format!("{:.2}", loss.percent)));
+        }
+        fields.push(("SERVER", results.server.iata.clone()));
+        if let Some(ref scores) = results.scores {
+            fields.push(("SCORE", scores.overall.clone()));
+        }
+
+        Self {
+            severity: Severity::Info,
+            message: format!(
+                "Test {} finished: {}",
+                id,
+                crate::reporters::summary_line(results)
+            ),
+            fields,
+        }
+    }
+
+    /// A test that failed.
+    pub fn error(id: u64, kind: ErrorKind, message: &str) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: format!("Test {} failed: {}", id, message),
+            fields: vec![
+                ("TEST_ID", id.to_string()),
+                ("ERROR_KIND", format!("{:?}", kind)),
+            ],
+        }
+    }
+}
+
+/// An open connection to a log sink.
+pub struct SinkWriter {
+    sink: LogSink,
+    target: Target,
+}
+
+/// Where an open sink's records go.
+enum Target {
+    /// The local socket of syslog or the journal
+    #[cfg(unix)]
+    Socket(std::os::unix::net::UnixDatagram),
+    /// The Windows event log
+    #[cfg(all(windows, feature = "service"))]
+    EventLog(event_log::EventSource),
+}
+
+impl SinkWriter {
+    /// Connect to `sink`.
+    pub fn open(sink: LogSink) -> io::Result<Self> {
+        let target = match sink {
+            #[cfg(unix)]
+            LogSink::Syslog | LogSink::Journald => {
+                let path = match sink {
+                    LogSink::Journald => JOURNAL_SOCKET,
+                    _ => SYSLOG_SOCKET,
+                };
+                let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                socket.connect(path).map_err(|e| {
+                    io::Error::new(
+                        e.kind(),
+                        format!(
+                            "Failed to connect to {} at {}: {}",
+                            sink, path, e
+                        ),
+                    )
+                })?;
+                Target::Socket(socket)
+            }
+            #[cfg(all(windows, feature = "service"))]
+            LogSink::EventLog => {
+                Target::EventLog(event_log::EventSource::register(IDENTIFIER)?)
+            }
+            #[allow(unreachable_patterns)]
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("{} is not available on this platform", sink),
+                ))
+            }
+        };
+        Ok(Self { sink, target })
+    }
+
+    /// Send `record` to the sink.
+    pub fn send(&self, record: &Record) -> io::Result<()> {
+        match self.target {
+            #[cfg(unix)]
+            Target::Socket(ref socket) => {
+                let datagram = match self.sink {
+                    LogSink::Journald => journal_datagram(record),
+                    _ => syslog_datagram(
+                        record,
+                        Utc::now(),
+                        hostname::get()
+                            .ok()
+                            .and_then(|h| h.into_string().ok())
+                            .as_deref(),
+                        std::process::id(),
+                    )
+                    .into_bytes(),
+                };
+                socket.send(&datagram).map(|_| ())
+            }
+            #[cfg(all(windows, feature = "service"))]
+            Target::EventLog(ref source) => source.report(record),
+        }
+    }
+}
+
+/// Format `record` as an RFC 5424 syslog message with its fields as
+/// structured data.
+#[cfg(unix)]
+fn syslog_datagram(
+    record: &Record,
+    timestamp: DateTime<Utc>,
+    hostname: Option<&str>,
+    pid: u32,
+) -> String {
+    let params: String = record
+        .fields
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                " {}=\"{}\"",
+                name.to_ascii_lowercase(),
+                escape_param(value)
+            )
+        })
+        .collect();
+    format!(
+        "<{}>1 {} {} {} {} - [{}{}] {}",
+        FACILITY_DAEMON * 8 + record.severity as u8,
+        timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+        hostname.filter(|h| !h.is_empty()).unwrap_or("-"),
+        IDENTIFIER,
+        pid,
+        SD_ID,
+        params,
+        record.message
+    )
+}
+
+/// Escape `"`, `\` and `]` in a structured data value.
+#[cfg(unix)]
+fn escape_param(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Encode `record` in the journal's native protocol, prefixing custom
+/// fields with `CLOUDSPEED_`.
+#[cfg(unix)]
+fn journal_datagram(record: &Record) -> Vec<u8> {
+    let mut datagram = Vec::new();
+    let mut field = |name: &str, value: &str| {
+        datagram.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            // Multi-line values carry their length instead of `=`
+            datagram.push(b'\n');
+            datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            datagram.push(b'=');
+        }
+        datagram.extend_from_slice(value.as_bytes());
+        datagram.push(b'\n');
+    };
+
+    field("MESSAGE", &record.message);
+    field("PRIORITY", &(record.severity as u8).to_string());
+    field("SYSLOG_IDENTIFIER", IDENTIFIER);
+    for (name, value) in &record.fields {
+        field(&format!("CLOUDSPEED_{}", name), value);
+    }
+    datagram
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn record() -> Record {
+        Record {
+            severity: Severity::Info,
+            message: "Test 1 finished".to_string(),
+            fields: vec![
+                ("TEST_ID", "1".to_string()),
+                ("SERVER", "a\"b]".to_string()),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_parse_log_sink() {
+        assert_eq!("syslog".parse(), Ok(LogSink::Syslog));
+        assert_eq!("Journald".parse(), Ok(LogSink::Journald));
+        assert_eq!("journal".parse(), Ok(LogSink::Journald));
+        assert_eq!("eventlog".parse(), Ok(LogSink::EventLog));
+        assert!("file".parse::<LogSink>().is_err());
+        assert_eq!(LogSink::Journald.to_string(), "journald");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_syslog_datagram() {
+        let timestamp = "2024-05-01T12:00:00Z".parse().unwrap();
+        assert_eq!(
+            syslog_datagram(&record(), timestamp, Some("probe"), 42),
+            "<30>1 2024-05-01T12:00:00.000Z probe cloud-speed 42 - \
+             [cloudspeed@32473 test_id=\"1\" server=\"a\\\"b\\]\"] \
+             Test 1 finished"
+        );
+
+        let error = Record { severity: Severity::Error, ..record() };
+        assert!(syslog_datagram(&error, timestamp, None, 42)
+            .starts_with("<27>1 2024-05-01T12:00:00.000Z - cloud-speed"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_journal_datagram() {
+        let datagram = journal_datagram(&record());
+        assert_eq!(
+            String::from_utf8(datagram).unwrap(),
+            "MESSAGE=Test 1 finished\nPRIORITY=6\n\
+             SYSLOG_IDENTIFIER=cloud-speed\nCLOUDSPEED_TEST_ID=1\n\
+             CLOUDSPEED_SERVER=a\"b]\n"
+        );
+
+        let multiline =
+            Record { message: "a\nb".to_string(), fields: vec![], ..record() };
+        let datagram = journal_datagram(&multiline);
+        assert!(datagram.starts_with(b"MESSAGE\n\x03\0\0\0\0\0\0\0a\nb\n"));
+    }
+
+    #[test]
+    fn test_error_record() {
+        let record = Record::error(3, ErrorKind::Network, "timed out");
+        assert_eq!(record.severity, Severity::Error);
+        assert_eq!(record.message, "Test 3 failed: timed out");
+        assert_eq!(record.fields[1], ("ERROR_KIND", "Network".to_string()));
+    }
+}
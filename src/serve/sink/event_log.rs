@@ -0,0 +1,82 @@
+//! Windows event log entries for `--log-sink eventlog`.
+
+use super::{Record, Severity};
+use std::io;
+use std::iter;
+use std::ptr;
+use windows_sys::Win32::Foundation::HANDLE;
+use windows_sys::Win32::System::EventLog::{
+    DeregisterEventSource, RegisterEventSourceW, ReportEventW,
+    EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE,
+};
+
+/// A registered event source in the Application log.
+pub struct EventSource(HANDLE);
+
+impl EventSource {
+    /// Register `name` as an event source on this machine.
+    pub fn register(name: &str) -> io::Result<Self> {
+        let name = wide(name);
+        // SAFETY: `name` is NUL terminated and outlives the call; a null
+        // server name means the local machine.
+        let handle =
+            unsafe { RegisterEventSourceW(ptr::null(), name.as_ptr()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self(handle))
+    }
+
+    /// Write `record` to the log, with its fields below the message.
+    pub fn report(&self, record: &Record) -> io::Result<()> {
+        let mut text = record.message.clone();
+        for (name, value) in &record.fields {
+            text.push_str(&format!(
+                "\r\n{}={}",
+                name.to_ascii_lowercase(),
+                value
+            ));
+        }
+        let text = wide(&text);
+        let strings = [text.as_ptr()];
+        let kind = match record.severity {
+            Severity::Error => EVENTLOG_ERROR_TYPE,
+            Severity::Info => EVENTLOG_INFORMATION_TYPE,
+        };
+
+        // SAFETY: the handle is open until drop, and `strings` holds one
+        // NUL terminated string that outlives the call.
+        let reported = unsafe {
+            ReportEventW(
+                self.0,
+                kind,
+                0,
+                0,
+                ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                ptr::null(),
+            )
+        };
+        if reported == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for EventSource {
+    fn drop(&mut self) {
+        // SAFETY: the handle came from `RegisterEventSourceW` and is only
+        // closed here.
+        unsafe {
+            DeregisterEventSource(self.0);
+        }
+    }
+}
+
+/// `s` as a NUL terminated UTF-16 string.
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(iter::once(0)).collect()
+}
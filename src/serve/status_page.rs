@@ -0,0 +1,244 @@
+//! HTML status page served at `GET /`.
+//!
+//! A single self-contained page (no scripts or external assets) showing
+//! the last run and sparklines of recent history, for checking on a
+//! connection from a browser without setting up a dashboard.
+
+use crate::history::HistoryEntry;
+use crate::reporters::escape;
+use crate::results::SpeedTestResults;
+use std::fmt::Write;
+
+/// History entries plotted in the sparklines.
+const SPARKLINE_RUNS: usize = 48;
+
+/// Sparkline size in CSS pixels.
+const SPARKLINE_WIDTH: f64 = 240.0;
+const SPARKLINE_HEIGHT: f64 = 40.0;
+
+/// A sparkline's label, unit, color and the value it plots.
+type Series =
+    (&'static str, &'static str, &'static str, fn(&HistoryEntry) -> f64);
+
+/// Metrics plotted in the history sparklines.
+const SERIES: [Series; 3] = [
+    ("Download", "Mbps", "#0a7ea4", |e| e.download_mbps),
+    ("Upload", "Mbps", "#7a4fd0", |e| e.upload_mbps),
+    ("Latency", "ms", "#c0392b", |e| e.latency_ms),
+];
+
+/// Seconds between automatic page reloads.
+const REFRESH_SECS: u32 = 60;
+
+const STYLE: &str = "\
+body{font-family:system-ui,sans-serif;max-width:40rem;margin:2rem auto;\
+padding:0 1rem;color:#222;background:#fafafa}\
+h1{font-size:1.4rem}h2{font-size:1.1rem;margin-top:2rem}\
+table{border-collapse:collapse}td{padding:.2rem 1rem .2rem 0}\
+td.value{font-weight:600}.muted{color:#777}\
+svg{vertical-align:middle}polyline{fill:none;stroke-width:1.5}";
+
+/// Render the status page.
+///
+/// # Arguments
+/// * `latest` - Results of the last test the server ran, if any
+/// * `history` - Past runs, oldest first; `None` if history is disabled
+/// * `running` - Whether a test is running now
+pub fn render(
+    latest: Option<&SpeedTestResults>,
+    history: Option<&[HistoryEntry]>,
+    running: bool,
+) -> String {
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\"><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width\">\
+         <meta http-equiv=\"refresh\" content=\"{}\">\
+         <title>cloud-speed</title><style>{}</style></head><body>\
+         <h1>cloud-speed</h1><p class=\"muted\">{}</p>",
+        REFRESH_SECS,
+        STYLE,
+        if running { "A test is running" } else { "Idle" }
+    );
+
+    // The last run this server measured, or else the newest in history
+    let last = history.and_then(|history| history.last());
+    match (latest, last) {
+        (Some(results), _) => latest_section(&mut html, results),
+        (None, Some(entry)) => entry_section(&mut html, entry),
+        (None, None) => html.push_str("<p>No test has completed yet.</p>"),
+    }
+
+    if let Some(history) = history.filter(|history| history.len() > 1) {
+        history_section(&mut html, history);
+    }
+
+    html.push_str("</body></html>\n");
+    html
+}
+
+/// The full results of the last run.
+fn latest_section(html: &mut String, results: &SpeedTestResults) {
+    let _ = write!(
+        html,
+        "<h2>Last test</h2><p class=\"muted\">{} via {} ({})</p><table>",
+        results.timestamp.format("%Y-%m-%d %H:%M UTC"),
+        escape(&results.server.city),
+        escape(&results.server.iata)
+    );
+    if let Some(ref download) = results.download {
+        row(html, "Download", format!("{:.2} Mbps", download.speed_mbps));
+    }
+    if let Some(ref upload) = results.upload {
+        row(html, "Upload", format!("{:.2} Mbps", upload.speed_mbps));
+    }
+    row(html, "Latency", format!("{:.2} ms", results.latency.idle_ms));
+    if let Some(jitter) = results.latency.idle_jitter_ms {
+        row(html, "Jitter", format!("{:.2} ms", jitter));
+    }
+    if let Some(ref loss) = results.packet_loss {
+        row(html, "Packet loss", format!("{:.2}%", loss.percent));
+    }
+    if let Some(ref scores) = results.scores {
+        row(html, "Overall", escape(&scores.overall));
+    }
+    html.push_str("</table>");
+}
+
+/// The summary of a run recorded in history.
+fn entry_section(html: &mut String, entry: &HistoryEntry) {
+    let _ = write!(
+        html,
+        "<h2>Last test</h2><p class=\"muted\">{}</p><table>",
+        entry.timestamp.format("%Y-%m-%d %H:%M UTC")
+    );
+    row(html, "Download", format!("{:.2} Mbps", entry.download_mbps));
+    row(html, "Upload", format!("{:.2} Mbps", entry.upload_mbps));
+    row(html, "Latency", format!("{:.2} ms", entry.latency_ms));
+    if let Some(jitter) = entry.jitter_ms {
+        row(html, "Jitter", format!("{:.2} ms", jitter));
+    }
+    if let Some(loss) = entry.packet_loss_percent {
+        row(html, "Packet loss", format!("{:.2}%", loss));
+    }
+    html.push_str("</table>");
+}
+
+/// Sparklines of the most recent runs in `history`.
+fn history_section(html: &mut String, history: &[HistoryEntry]) {
+    let recent = &history[history.len().saturating_sub(SPARKLINE_RUNS)..];
+    let _ = write!(html, "<h2>Last {} tests</h2><table>", recent.len());
+    for (label, unit, color, metric) in SERIES {
+        let values: Vec<f64> = recent.iter().map(metric).collect();
+        let (min, max) = bounds(&values);
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td>\
+             <td class=\"muted\">{:.1}&ndash;{:.1} {}</td></tr>",
+            label,
+            sparkline(&values, color),
+            min,
+            max,
+            unit
+        );
+    }
+    html.push_str("</table>");
+}
+
+/// A table row with a label and a value.
+fn row(html: &mut String, label: &str, value: String) {
+    let _ = write!(
+        html,
+        "<tr><td>{}</td><td class=\"value\">{}</td></tr>",
+        label, value
+    );
+}
+
+/// Smallest and largest of `values`.
+fn bounds(values: &[f64]) -> (f64, f64) {
+    values.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+        (min.min(v), max.max(v))
+    })
+}
+
+/// An inline SVG line of `values`, scaled to fill the sparkline box.
+fn sparkline(values: &[f64], color: &str) -> String {
+    let (min, max) = bounds(values);
+    let range = if max > min { max - min } else { 1.0 };
+    let step = SPARKLINE_WIDTH / (values.len().max(2) - 1) as f64;
+    let points: Vec<String> = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let y =
+                SPARKLINE_HEIGHT - (value - min) / range * SPARKLINE_HEIGHT;
+            format!("{:.1},{:.1}", i as f64 * step, y)
+        })
+        .collect();
+    format!(
+        "<svg width=\"{w}\" height=\"{h}\" viewBox=\"0 -1 {w} {vh}\">\
+         <polyline stroke=\"{}\" points=\"{}\"/></svg>",
+        color,
+        points.join(" "),
+        w = SPARKLINE_WIDTH,
+        h = SPARKLINE_HEIGHT,
+        vh = SPARKLINE_HEIGHT + 2.0
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn entry(hour: u32, download_mbps: f64) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: Utc.with_ymd_and_hms(2024, 5, 1, hour, 0, 0).unwrap(),
+            download_mbps,
+            upload_mbps: 10.0,
+            latency_ms: 12.0,
+            jitter_ms: Some(1.5),
+            loaded_down_ms: None,
+            loaded_up_ms: None,
+            packet_loss_percent: None,
+            load_streams: None,
+            order: None,
+        }
+    }
+
+    #[test]
+    fn test_render_without_runs() {
+        let html = render(None, None, true);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("A test is running"));
+        assert!(html.contains("No test has completed yet"));
+        assert!(!html.contains("<svg"));
+    }
+
+    #[test]
+    fn test_render_history() {
+        let history = [entry(1, 50.0), entry(2, 100.0)];
+        let html = render(None, Some(&history), false);
+        assert!(html.contains("Idle"));
+        assert!(html.contains("2024-05-01 02:00 UTC"));
+        assert!(html.contains("<td class=\"value\">100.00 Mbps</td>"));
+        assert!(html.contains("<h2>Last 2 tests</h2>"));
+        assert!(html.contains("50.0&ndash;100.0 Mbps"));
+        assert!(html.contains("points=\"0.0,40.0 240.0,0.0\""));
+    }
+
+    #[test]
+    fn test_sparkline_of_constant_values() {
+        let svg = sparkline(&[5.0, 5.0, 5.0], "red");
+        assert!(svg.contains("points=\"0.0,40.0 120.0,40.0 240.0,40.0\""));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("<a href=\"x\">&'"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;"
+        );
+    }
+}
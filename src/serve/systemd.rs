@@ -0,0 +1,132 @@
+//! systemd service integration for `serve`.
+//!
+//! With `Type=notify` the server reports `READY=1` once it is listening
+//! and `STOPPING=1` on SIGTERM, and with `WatchdogSec=` it pings the
+//! watchdog at half the configured interval. Outside systemd,
+//! `NOTIFY_SOCKET` is unset and all of this is skipped.
+
+use std::time::Duration;
+
+/// A connection to systemd's notification socket.
+pub struct Notifier {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+}
+
+impl Notifier {
+    /// Connect to the socket in `NOTIFY_SOCKET`, if systemd set one.
+    #[cfg(unix)]
+    pub fn from_env() -> Option<Self> {
+        use std::os::unix::net::UnixDatagram;
+
+        let path = std::env::var("NOTIFY_SOCKET").ok()?;
+        let socket = UnixDatagram::unbound().ok()?;
+        let connected = match path.strip_prefix('@') {
+            // Names starting with @ are in the abstract namespace
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                std::os::unix::net::SocketAddr::from_abstract_name(name)
+                    .and_then(|addr| socket.connect_addr(&addr))
+            }
+            _ => socket.connect(&path),
+        };
+        match connected {
+            Ok(()) => Some(Self { socket }),
+            Err(e) => {
+                log::warn!(
+                    "Failed to connect to NOTIFY_SOCKET {}: {}",
+                    path,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// systemd notification is Unix only.
+    #[cfg(not(unix))]
+    pub fn from_env() -> Option<Self> {
+        None
+    }
+
+    /// Send a state such as `READY=1` to systemd.
+    pub fn notify(&self, state: &str) {
+        #[cfg(unix)]
+        if let Err(e) = self.socket.send(state.as_bytes()) {
+            log::debug!("Failed to notify systemd of {}: {}", state, e);
+        }
+        #[cfg(not(unix))]
+        let _ = state;
+    }
+}
+
+/// How often to ping the watchdog, if systemd enabled it for this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    parse_watchdog(
+        std::env::var("WATCHDOG_USEC").ok().as_deref(),
+        std::env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+/// Half the watchdog timeout in `usec`, as systemd recommends, unless
+/// `watchdog_pid` names another process.
+fn parse_watchdog(
+    usec: Option<&str>,
+    watchdog_pid: Option<&str>,
+    pid: u32,
+) -> Option<Duration> {
+    if let Some(watchdog_pid) = watchdog_pid {
+        if watchdog_pid.trim().parse::<u32>().ok()? != pid {
+            return None;
+        }
+    }
+    match usec?.trim().parse::<u64>().ok()? {
+        0 => None,
+        usec => Some(Duration::from_micros(usec) / 2),
+    }
+}
+
+/// Resolve once SIGTERM arrives; never resolves where there is no SIGTERM.
+///
+/// The handler is installed when this is called, so a SIGTERM that arrives
+/// before the future is first polled is not lost.
+pub fn sigterm() -> impl std::future::Future<Output = ()> {
+    #[cfg(unix)]
+    let mut signal = tokio::signal::unix::signal(
+        tokio::signal::unix::SignalKind::terminate(),
+    )
+    .map_err(|e| log::warn!("Failed to set up SIGTERM handler: {}", e))
+    .ok();
+
+    async move {
+        #[cfg(unix)]
+        if let Some(ref mut signal) = signal {
+            signal.recv().await;
+            return;
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_watchdog() {
+        assert_eq!(
+            parse_watchdog(Some("30000000"), None, 7),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            parse_watchdog(Some("30000000"), Some("7"), 7),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(parse_watchdog(Some("30000000"), Some("8"), 7), None);
+        assert_eq!(parse_watchdog(Some("0"), None, 7), None);
+        assert_eq!(parse_watchdog(Some("soon"), None, 7), None);
+        assert_eq!(parse_watchdog(None, None, 7), None);
+    }
+}
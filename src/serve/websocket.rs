@@ -0,0 +1,177 @@
+//! Minimal server side of the WebSocket protocol (RFC 6455).
+//!
+//! Only what `/ws/progress` needs: the opening handshake, unmasked
+//! server frames, and reading the client's masked control frames so
+//! pings are answered and closes are honoured. Messages are never
+//! fragmented by the server, and fragmented client messages are ignored.
+
+use base64::Engine;
+use sha1::{Digest, Sha1};
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Appended to the client's key before hashing, per RFC 6455 §1.3.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The only protocol version the handshake accepts.
+pub(super) const VERSION: &str = "13";
+
+/// Largest client frame payload accepted, in bytes.
+///
+/// Clients only send control frames, which are capped at 125 bytes, but
+/// browsers may send a text message too; anything larger is abuse.
+const MAX_PAYLOAD: u64 = 64 * 1024;
+
+pub(super) const OP_TEXT: u8 = 0x1;
+pub(super) const OP_CLOSE: u8 = 0x8;
+pub(super) const OP_PING: u8 = 0x9;
+pub(super) const OP_PONG: u8 = 0xA;
+
+/// A frame received from the client, with its payload unmasked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Frame {
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+/// The `Sec-WebSocket-Accept` value for a client's `Sec-WebSocket-Key`.
+pub(super) fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.trim().as_bytes());
+    hasher.update(HANDSHAKE_GUID.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(hasher.finalize())
+}
+
+/// The `101 Switching Protocols` response completing the handshake.
+pub(super) fn handshake_response(key: &str) -> String {
+    format!(
+        "HTTP/1.1 101 Switching Protocols\r\n\
+        Upgrade: websocket\r\n\
+        Connection: Upgrade\r\n\
+        Sec-WebSocket-Accept: {}\r\n\
+        \r\n",
+        accept_key(key)
+    )
+}
+
+/// Encode a single unfragmented, unmasked server frame.
+pub(super) fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Read one frame from the client.
+///
+/// Fails if the frame is unmasked, which RFC 6455 requires servers to
+/// treat as a protocol error, or larger than [`MAX_PAYLOAD`].
+pub(super) async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> io::Result<Frame> {
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head).await?;
+
+    let opcode = head[0] & 0x0f;
+    if head[1] & 0x80 == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "client frame is not masked",
+        ));
+    }
+
+    let len = match head[1] & 0x7f {
+        126 => reader.read_u16().await? as u64,
+        127 => reader.read_u64().await?,
+        len => len as u64,
+    };
+    if len > MAX_PAYLOAD {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("client frame of {} bytes is too large", len),
+        ));
+    }
+
+    let mut mask = [0u8; 4];
+    reader.read_exact(&mut mask).await?;
+
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload).await?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+
+    Ok(Frame { opcode, payload })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mask `payload` the way a client would.
+    fn client_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut frame = encode_frame(opcode, payload);
+        let offset = frame.len() - payload.len();
+        frame[1] |= 0x80;
+
+        let mut masked = frame[..offset].to_vec();
+        masked.extend_from_slice(&mask);
+        masked
+            .extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        masked
+    }
+
+    #[test]
+    fn test_accept_key_matches_rfc_example() {
+        // RFC 6455 §1.3
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn test_encode_frame_lengths() {
+        let frame = encode_frame(OP_TEXT, b"Hello");
+        assert_eq!(frame, [0x81, 0x05, b'H', b'e', b'l', b'l', b'o']);
+
+        let frame = encode_frame(OP_TEXT, &[0u8; 300]);
+        assert_eq!(&frame[..4], &[0x81, 126, 0x01, 0x2c]);
+        assert_eq!(frame.len(), 4 + 300);
+
+        let frame = encode_frame(OP_TEXT, &[0u8; 70_000]);
+        assert_eq!(frame[1], 127);
+        assert_eq!(&frame[2..10], &70_000u64.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_unmasks_payload() {
+        let bytes = client_frame(OP_PING, b"are you there");
+        let frame = read_frame(&mut bytes.as_slice()).await.unwrap();
+        assert_eq!(
+            frame,
+            Frame { opcode: OP_PING, payload: b"are you there".to_vec() }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_rejects_unmasked() {
+        let bytes = encode_frame(OP_CLOSE, &[]);
+        let err = read_frame(&mut bytes.as_slice()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,178 @@
+//! Windows service support for `serve` (`cloud-speed service`, with the
+//! `service` feature).
+//!
+//! `service install` registers the executable with the service control
+//! manager to start at boot as `service run`, keeping the flags it was
+//! installed with. The service control manager then calls back into
+//! [`run`], which serves the API until the service is stopped, logging
+//! every test to the event log.
+
+use crate::serve::{self, ServeConfig};
+use std::error::Error;
+use std::ffi::OsString;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl,
+    ServiceExitCode, ServiceInfo, ServiceStartType, ServiceState,
+    ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{
+    self, ServiceControlHandlerResult, ServiceStatusHandle,
+};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+/// Name the service is registered under.
+const SERVICE_NAME: &str = "cloud-speed";
+
+/// Name shown in the Services console.
+const DISPLAY_NAME: &str = "cloud-speed";
+
+/// How long stopping may take; a running test is finished first.
+const STOP_WAIT_HINT: Duration = Duration::from_secs(120);
+
+/// What `serve` runs with, handed from `main` to the service thread.
+static SERVE: Mutex<Option<(SocketAddr, ServeConfig)>> = Mutex::new(None);
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Register the service to start at boot with `arguments`, the command
+/// line after the executable name.
+pub fn install(arguments: Vec<OsString>) -> windows_service::Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )?;
+    let info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from(DISPLAY_NAME),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()
+            .map_err(windows_service::Error::Winapi)?,
+        launch_arguments: arguments,
+        dependencies: vec![],
+        // LocalSystem
+        account_name: None,
+        account_password: None,
+    };
+    let service =
+        manager.create_service(&info, ServiceAccess::CHANGE_CONFIG)?;
+    service.set_description(
+        "Serves the cloud-speed API for running network speed tests",
+    )
+}
+
+/// Stop the service if it is running and remove it.
+pub fn uninstall() -> windows_service::Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT,
+    )?;
+    let service = manager.open_service(
+        SERVICE_NAME,
+        ServiceAccess::QUERY_STATUS
+            | ServiceAccess::STOP
+            | ServiceAccess::DELETE,
+    )?;
+    // Marked for deletion; removed once it has stopped
+    service.delete()?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+    Ok(())
+}
+
+/// Hand control to the service control manager and serve on `listen`
+/// until the service is stopped.
+///
+/// Fails when not started by the service control manager.
+pub fn run(
+    listen: SocketAddr,
+    config: ServeConfig,
+) -> windows_service::Result<()> {
+    if let Ok(mut serve) = SERVE.lock() {
+        *serve = Some((listen, config));
+    }
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+}
+
+/// Entry point the service control manager calls on its own thread.
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        log::error!("Service failed: {}", e);
+    }
+}
+
+fn run_service() -> Result<(), Box<dyn Error>> {
+    let (listen, config) = SERVE
+        .lock()
+        .ok()
+        .and_then(|mut serve| serve.take())
+        .ok_or("Service started without a configuration")?;
+
+    let (stop_tx, stop_rx) = oneshot::channel();
+    let mut stop_tx = Some(stop_tx);
+    let status =
+        service_control_handler::register(SERVICE_NAME, move |control| {
+            match control {
+                ServiceControl::Stop | ServiceControl::Shutdown => {
+                    if let Some(stop_tx) = stop_tx.take() {
+                        let _ = stop_tx.send(());
+                    }
+                    ServiceControlHandlerResult::NoError
+                }
+                ServiceControl::Interrogate => {
+                    ServiceControlHandlerResult::NoError
+                }
+                _ => ServiceControlHandlerResult::NotImplemented,
+            }
+        })?;
+    set_state(&status, ServiceState::Running, 0)?;
+
+    let stop = async move {
+        let _ = stop_rx.await;
+        let _ = set_state(&status, ServiceState::StopPending, 0);
+    };
+    let served = tokio::runtime::Runtime::new()?
+        .block_on(serve::run_until(listen, config, stop));
+    if let Err(ref e) = served {
+        log::error!("Failed to serve: {}", e);
+    }
+
+    set_state(&status, ServiceState::Stopped, u32::from(served.is_err()))?;
+    Ok(())
+}
+
+/// Report `state` to the service control manager, with a
+/// service-specific `exit_code` when stopped.
+fn set_state(
+    status: &ServiceStatusHandle,
+    state: ServiceState,
+    exit_code: u32,
+) -> windows_service::Result<()> {
+    status.set_service_status(ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted: match state {
+            ServiceState::Running => {
+                ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+            }
+            _ => ServiceControlAccept::empty(),
+        },
+        exit_code: match exit_code {
+            0 => ServiceExitCode::Win32(0),
+            code => ServiceExitCode::ServiceSpecific(code),
+        },
+        checkpoint: 0,
+        wait_hint: match state {
+            ServiceState::StopPending => STOP_WAIT_HINT,
+            _ => Duration::default(),
+        },
+        process_id: None,
+    })
+}
@@ -0,0 +1,220 @@
+//! Shareable result summaries.
+//!
+//! `--share` condenses results into a [`ShareSummary`] holding only the
+//! headline numbers, with nothing that identifies the client beyond its
+//! country, and encodes it as a short snippet: `cs1.` followed by
+//! unpadded base64url JSON. With a `[share] endpoint` configured the
+//! snippet is posted there and the link the endpoint answers with is
+//! printed instead. `cloud-speed decode` renders a received snippet.
+
+use crate::cloudflare::proxy::env_proxy;
+use crate::results::SpeedTestResults;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use reqwest::{Client as ReqwestClient, Proxy, Response};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::time::Duration;
+
+/// Prefix identifying a snippet and the version of its encoding.
+pub const SNIPPET_PREFIX: &str = "cs1.";
+
+/// How long the paste endpoint has to answer.
+const SHARE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The headline numbers of a run, as shared.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ShareSummary {
+    /// When the test completed
+    pub timestamp: DateTime<Utc>,
+    /// IATA code of the Cloudflare location tested against
+    pub server: String,
+    /// Country code of the client
+    pub country: String,
+    /// Download speed in Mbps
+    pub download_mbps: f64,
+    /// Upload speed in Mbps
+    pub upload_mbps: f64,
+    /// Idle latency in milliseconds
+    pub latency_ms: f64,
+    /// Idle jitter in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jitter_ms: Option<f64>,
+    /// Packet loss as a percentage, when measured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packet_loss_percent: Option<f64>,
+    /// Overall quality score
+    pub score: String,
+}
+
+/// Snippet form of a [`ShareSummary`], with short keys to keep it short.
+#[derive(Serialize, Deserialize)]
+struct Wire {
+    t: i64,
+    s: String,
+    c: String,
+    d: f64,
+    u: f64,
+    l: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    j: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    p: Option<f64>,
+    q: String,
+}
+
+impl ShareSummary {
+    /// Summarize `results`, rounding to the precision reports show.
+    ///
+    /// Returns `None` when a direction was skipped, leaving nothing to
+    /// score.
+    pub fn from_results(results: &SpeedTestResults) -> Option<Self> {
+        Some(Self {
+            timestamp: results.timestamp,
+            server: results.server.iata.clone(),
+            country: results.connection.country.clone(),
+            download_mbps: round2(results.download.as_ref()?.speed_mbps),
+            upload_mbps: round2(results.upload.as_ref()?.speed_mbps),
+            latency_ms: round2(results.latency.idle_ms),
+            jitter_ms: results.latency.idle_jitter_ms.map(round2),
+            packet_loss_percent: results
+                .packet_loss
+                .as_ref()
+                .map(|loss| round2(loss.percent)),
+            score: results.scores.as_ref()?.overall.clone(),
+        })
+    }
+
+    /// Encode as a snippet.
+    pub fn encode(&self) -> String {
+        let wire = Wire {
+            t: self.timestamp.timestamp(),
+            s: self.server.clone(),
+            c: self.country.clone(),
+            d: self.download_mbps,
+            u: self.upload_mbps,
+            l: self.latency_ms,
+            j: self.jitter_ms,
+            p: self.packet_loss_percent,
+            q: self.score.clone(),
+        };
+        // Serializing plain numbers and strings can't fail
+        let json = serde_json::to_vec(&wire).unwrap_or_default();
+        format!("{}{}", SNIPPET_PREFIX, BASE64.encode(json))
+    }
+
+    /// Decode the first snippet found in `text`, which may be a bare
+    /// snippet or a link or message containing one.
+    pub fn decode(text: &str) -> Result<Self, Box<dyn Error>> {
+        let start = text
+            .find(SNIPPET_PREFIX)
+            .ok_or("no cloud-speed snippet found (expected \"cs1.…\")")?;
+        let encoded: String = text[start + SNIPPET_PREFIX.len()..]
+            .chars()
+            .take_while(|c| {
+                c.is_ascii_alphanumeric() || *c == '-' || *c == '_'
+            })
+            .collect();
+        let json = BASE64
+            .decode(encoded)
+            .map_err(|e| format!("snippet is not valid base64: {}", e))?;
+        let wire: Wire = serde_json::from_slice(&json)
+            .map_err(|e| format!("snippet is not a result summary: {}", e))?;
+
+        Ok(Self {
+            timestamp: DateTime::from_timestamp(wire.t, 0)
+                .ok_or("snippet has an invalid timestamp")?,
+            server: wire.s,
+            country: wire.c,
+            download_mbps: wire.d,
+            upload_mbps: wire.u,
+            latency_ms: wire.l,
+            jitter_ms: wire.j,
+            packet_loss_percent: wire.p,
+            score: wire.q,
+        })
+    }
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Post `snippet` to a paste `endpoint` as plain text, with `headers`.
+///
+/// # Returns
+/// The endpoint's response body, trimmed, which should be the link to
+/// the paste.
+pub async fn upload(
+    endpoint: &str,
+    snippet: &str,
+    headers: HeaderMap,
+) -> Result<String, Box<dyn Error>> {
+    let client = ReqwestClient::builder()
+        .no_proxy()
+        .proxy(Proxy::custom(|url| env_proxy().proxy_for(url).cloned()))
+        .timeout(SHARE_TIMEOUT)
+        .build()?;
+    let link = client
+        .post(endpoint)
+        .headers(headers)
+        .header(CONTENT_TYPE, "text/plain")
+        .body(snippet.to_string())
+        .send()
+        .await
+        .and_then(Response::error_for_status)?
+        .text()
+        .await?;
+
+    let link = link.trim();
+    if link.is_empty() {
+        return Err(format!("{} returned an empty response", endpoint).into());
+    }
+    Ok(link.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary() -> ShareSummary {
+        ShareSummary {
+            timestamp: DateTime::from_timestamp(1_790_000_000, 0).unwrap(),
+            server: "DFW".to_string(),
+            country: "US".to_string(),
+            download_mbps: 95.12,
+            upload_mbps: 20.5,
+            latency_ms: 12.0,
+            jitter_ms: Some(1.25),
+            packet_loss_percent: None,
+            score: "great".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_snippet_round_trip() {
+        let snippet = summary().encode();
+        assert!(snippet.starts_with(SNIPPET_PREFIX));
+        assert!(snippet.len() < 160);
+        assert_eq!(ShareSummary::decode(&snippet).unwrap(), summary());
+    }
+
+    #[test]
+    fn test_decode_finds_snippet_in_text() {
+        let text = format!(
+            "my speed: https://paste.example/#{} !",
+            summary().encode()
+        );
+        assert_eq!(ShareSummary::decode(&text).unwrap(), summary());
+    }
+
+    #[test]
+    fn test_decode_rejects_other_text() {
+        assert!(ShareSummary::decode("hello").is_err());
+        assert!(ShareSummary::decode("cs1.!!!").is_err());
+        let not_a_summary = format!("cs1.{}", BASE64.encode(b"{\"a\":1}"));
+        assert!(ShareSummary::decode(&not_a_summary).is_err());
+    }
+}
@@ -0,0 +1,458 @@
+//! Simulated speed tests, for working on the TUI and for demos without a
+//! network.
+//!
+//! A simulation sends the same progress events a real run does, spread
+//! over the requested duration, then fabricates results shaped like a
+//! connection of the chosen profile: speeds ramp up with the transfer
+//! size as TCP would, and latency grows under load. Nothing is sent over
+//! the network, and the results say they were simulated.
+
+use crate::cloudflare::tests::engine::{DataBlock, TestConfig};
+use crate::measurements::{jitter_f64, latency_f64};
+use crate::results::{
+    connection_metrics, AimScoresOutput, BandwidthResults, ConnectionMeta,
+    LatencyResults, ServerLocation, SizeMeasurement, SpeedTestResults,
+};
+use crate::scoring::ScoringThresholds;
+use crate::stats::{median_f64, percentile_f64};
+use crate::tui::progress::{
+    BandwidthDirection, ProgressCallback, ProgressEvent, TestPhase,
+};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Seconds a simulated run takes unless told otherwise.
+pub const DEFAULT_SIMULATION_SECS: u64 = 20;
+
+/// Kind of connection a simulation imitates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpeedProfile {
+    /// Symmetric gigabit fiber with little queueing.
+    Fiber,
+    /// Fast downloads, slow uploads and a buffer that bloats under load.
+    #[default]
+    Cable,
+    /// Tens of megabits with a long, bloated queue.
+    Dsl,
+    /// Cellular: middling speeds and jittery latency.
+    Mobile,
+}
+
+impl SpeedProfile {
+    /// Download speed in Mbps the profile settles at.
+    fn download_mbps(self) -> f64 {
+        match self {
+            SpeedProfile::Fiber => 940.0,
+            SpeedProfile::Cable => 450.0,
+            SpeedProfile::Dsl => 45.0,
+            SpeedProfile::Mobile => 120.0,
+        }
+    }
+
+    /// Upload speed in Mbps the profile settles at.
+    fn upload_mbps(self) -> f64 {
+        match self {
+            SpeedProfile::Fiber => 880.0,
+            SpeedProfile::Cable => 22.0,
+            SpeedProfile::Dsl => 9.0,
+            SpeedProfile::Mobile => 25.0,
+        }
+    }
+
+    /// Idle latency in ms.
+    fn latency_ms(self) -> f64 {
+        match self {
+            SpeedProfile::Fiber => 4.0,
+            SpeedProfile::Cable => 16.0,
+            SpeedProfile::Dsl => 28.0,
+            SpeedProfile::Mobile => 38.0,
+        }
+    }
+
+    /// How far either side of the typical value samples stray, as a
+    /// fraction of it.
+    fn spread(self) -> f64 {
+        match self {
+            SpeedProfile::Fiber => 0.05,
+            SpeedProfile::Cable => 0.12,
+            SpeedProfile::Dsl => 0.15,
+            SpeedProfile::Mobile => 0.35,
+        }
+    }
+
+    /// Latency added by queueing while downloading, in ms; uploads add
+    /// twice as much.
+    fn bufferbloat_ms(self) -> f64 {
+        match self {
+            SpeedProfile::Fiber => 3.0,
+            SpeedProfile::Cable => 60.0,
+            SpeedProfile::Dsl => 90.0,
+            SpeedProfile::Mobile => 140.0,
+        }
+    }
+}
+
+impl fmt::Display for SpeedProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpeedProfile::Fiber => write!(f, "fiber"),
+            SpeedProfile::Cable => write!(f, "cable"),
+            SpeedProfile::Dsl => write!(f, "dsl"),
+            SpeedProfile::Mobile => write!(f, "mobile"),
+        }
+    }
+}
+
+impl FromStr for SpeedProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fiber" => Ok(SpeedProfile::Fiber),
+            "cable" => Ok(SpeedProfile::Cable),
+            "dsl" => Ok(SpeedProfile::Dsl),
+            "mobile" => Ok(SpeedProfile::Mobile),
+            other => Err(format!(
+                "unknown speed profile '{}' (expected fiber, cable, dsl or \
+                 mobile)",
+                other
+            )),
+        }
+    }
+}
+
+/// Deterministic noise for the fabricated samples, so a simulation looks
+/// the same every time it's run.
+struct Noise(u64);
+
+impl Noise {
+    /// A value in [0, 1).
+    fn unit(&mut self) -> f64 {
+        // xorshift64
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    /// `center`, moved up or down by up to `spread` of itself.
+    fn around(&mut self, center: f64, spread: f64) -> f64 {
+        center * (1.0 + spread * (2.0 * self.unit() - 1.0))
+    }
+}
+
+/// A simulated run: what to imitate and how long to take over it.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    /// Connection to imitate
+    profile: SpeedProfile,
+    /// Time the run is spread over
+    duration: Duration,
+    /// Idle latency samples to send
+    latency_packets: usize,
+    /// Download sizes and counts, as a real run would use
+    download_sizes: Vec<DataBlock>,
+    /// Upload sizes and counts, as a real run would use
+    upload_sizes: Vec<DataBlock>,
+}
+
+/// What a simulated run measured.
+#[derive(Debug, Clone)]
+pub struct SimulatedRun {
+    /// Idle and loaded latency
+    pub latency: LatencyResults,
+    /// Download speeds
+    pub download: BandwidthResults,
+    /// Upload speeds
+    pub upload: BandwidthResults,
+}
+
+impl Simulation {
+    /// Simulate `profile` over `duration`, with the default sizes.
+    pub fn new(profile: SpeedProfile, duration: Duration) -> Self {
+        let defaults = TestConfig::default();
+        Self {
+            profile,
+            duration,
+            latency_packets: defaults.latency_packets,
+            download_sizes: defaults.download_sizes,
+            upload_sizes: defaults.upload_sizes,
+        }
+    }
+
+    /// Use these download and upload sizes instead of the defaults.
+    pub fn with_sizes(
+        mut self,
+        download_sizes: Vec<DataBlock>,
+        upload_sizes: Vec<DataBlock>,
+    ) -> Self {
+        self.download_sizes = download_sizes;
+        self.upload_sizes = upload_sizes;
+        self
+    }
+
+    /// Send the events of a full run to `progress`, pausing between them
+    /// so the run takes about the configured duration.
+    pub async fn run(&self, progress: &dyn ProgressCallback) -> SimulatedRun {
+        let profile = self.profile;
+        let mut noise = Noise(0x2545_f491_4f6c_dd1d);
+        let events = self.latency_packets
+            + [&self.download_sizes, &self.upload_sizes]
+                .iter()
+                .flat_map(|sizes| sizes.iter())
+                .map(|block| block.count)
+                .sum::<usize>();
+        let gap = self.duration / events.max(1) as u32;
+
+        progress.on_progress(ProgressEvent::PhaseChange(TestPhase::Latency));
+        let mut idle = Vec::with_capacity(self.latency_packets);
+        for current in 1..=self.latency_packets {
+            tokio::time::sleep(gap).await;
+            let value_ms =
+                noise.around(profile.latency_ms(), profile.spread());
+            idle.push(value_ms);
+            progress.on_progress(ProgressEvent::LatencyMeasurement {
+                value_ms,
+                current,
+                total: self.latency_packets,
+            });
+        }
+        let idle_ms = latency_f64(&idle).unwrap_or_default();
+        let idle_jitter_ms = jitter_f64(&idle);
+        progress.on_progress(ProgressEvent::LatencyComplete {
+            idle_ms,
+            jitter_ms: idle_jitter_ms,
+        });
+        progress.on_progress(ProgressEvent::PhaseComplete(TestPhase::Latency));
+
+        let directions = [
+            (
+                BandwidthDirection::Download,
+                TestPhase::Download,
+                &self.download_sizes,
+                profile.download_mbps(),
+                profile.bufferbloat_ms(),
+            ),
+            (
+                BandwidthDirection::Upload,
+                TestPhase::Upload,
+                &self.upload_sizes,
+                profile.upload_mbps(),
+                profile.bufferbloat_ms() * 2.0,
+            ),
+        ];
+        let mut results = Vec::with_capacity(2);
+        for (direction, phase, sizes, target_mbps, bloat_ms) in directions {
+            progress.on_progress(ProgressEvent::PhaseChange(phase));
+            let total = sizes.iter().map(|block| block.count).sum();
+            let mut current = 0;
+            let mut speeds = Vec::with_capacity(total);
+            let mut loaded = Vec::new();
+            let mut measurements = Vec::with_capacity(sizes.len());
+            for block in sizes {
+                let mut block_speeds = Vec::with_capacity(block.count);
+                for _ in 0..block.count {
+                    tokio::time::sleep(gap).await;
+                    current += 1;
+                    let speed_mbps = noise.around(
+                        target_mbps * ramp(block.bytes, target_mbps),
+                        profile.spread(),
+                    );
+                    block_speeds.push(speed_mbps);
+                    loaded.push(noise.around(
+                        profile.latency_ms() + bloat_ms,
+                        profile.spread(),
+                    ));
+                    progress.on_progress(
+                        ProgressEvent::BandwidthMeasurement {
+                            direction,
+                            speed_mbps,
+                            bytes: block.bytes,
+                            current,
+                            total,
+                        },
+                    );
+                }
+                let speed_mbps =
+                    median_f64(&mut block_speeds.clone()).unwrap_or_default();
+                progress.on_progress(ProgressEvent::SizeBlockComplete {
+                    direction,
+                    bytes: block.bytes,
+                    speed_mbps,
+                    count: block.count,
+                });
+                measurements.push(SizeMeasurement::new(
+                    block.bytes,
+                    speed_mbps,
+                    block.count,
+                ));
+                speeds.extend(block_speeds);
+            }
+            progress.on_progress(ProgressEvent::PhaseComplete(phase));
+
+            let speed_mbps =
+                percentile_f64(&mut speeds, 0.9).unwrap_or_default();
+            results.push((
+                BandwidthResults::new(speed_mbps, measurements, false),
+                latency_f64(&loaded),
+                jitter_f64(&loaded),
+            ));
+        }
+        progress.on_progress(ProgressEvent::PhaseChange(TestPhase::Complete));
+
+        let (upload, loaded_up_ms, loaded_up_jitter_ms) =
+            results.pop().expect("two directions");
+        let (download, loaded_down_ms, loaded_down_jitter_ms) =
+            results.pop().expect("two directions");
+        SimulatedRun {
+            latency: LatencyResults::new(
+                idle_ms,
+                idle_jitter_ms,
+                loaded_down_ms,
+                loaded_down_jitter_ms,
+                loaded_up_ms,
+                loaded_up_jitter_ms,
+            ),
+            download,
+            upload,
+        }
+    }
+
+    /// The made-up server the simulation pretends to test against.
+    pub fn server(&self) -> ServerLocation {
+        ServerLocation::new("Simulation".to_string(), "SIM".to_string())
+    }
+
+    /// The made-up client, on a documentation address and ASN.
+    pub fn connection(&self) -> ConnectionMeta {
+        ConnectionMeta::new(
+            "192.0.2.1".to_string(),
+            "ZZ".to_string(),
+            format!("Simulated {}", self.profile),
+            64496,
+        )
+    }
+
+    /// Results of `run`, scored against `thresholds`.
+    pub fn results(
+        &self,
+        run: &SimulatedRun,
+        thresholds: &ScoringThresholds,
+    ) -> SpeedTestResults {
+        let metrics =
+            connection_metrics(&run.latency, &run.download, &run.upload, None);
+        SpeedTestResults::new(
+            self.server(),
+            self.connection(),
+            run.latency.clone(),
+            run.download.clone(),
+            run.upload.clone(),
+            None,
+            AimScoresOutput::score(&metrics, thresholds),
+        )
+    }
+}
+
+/// Fraction of the link rate a transfer of `bytes` reaches, with TCP
+/// still ramping up for the smallest sizes.
+fn ramp(bytes: u64, link_mbps: f64) -> f64 {
+    // About 50 ms worth of data at the link rate goes on slow start
+    let ramp_bytes = link_mbps * 1e6 / 8.0 * 0.05;
+    1.0 - (-(bytes as f64) / ramp_bytes).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Records every event it's sent.
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<ProgressEvent>>);
+
+    impl ProgressCallback for Recorder {
+        fn on_progress(&self, event: ProgressEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    #[test]
+    fn test_speed_profile_parse() {
+        for profile in [
+            SpeedProfile::Fiber,
+            SpeedProfile::Cable,
+            SpeedProfile::Dsl,
+            SpeedProfile::Mobile,
+        ] {
+            assert_eq!(
+                profile.to_string().parse::<SpeedProfile>(),
+                Ok(profile)
+            );
+        }
+        assert_eq!("DSL".parse::<SpeedProfile>(), Ok(SpeedProfile::Dsl));
+        assert!("dialup".parse::<SpeedProfile>().is_err());
+    }
+
+    #[test]
+    fn test_ramp() {
+        assert!(ramp(100_000, 450.0) < 0.1);
+        assert!(ramp(25_000_000, 450.0) > 0.99);
+        assert!(ramp(100_000, 45.0) > ramp(100_000, 450.0));
+    }
+
+    #[tokio::test]
+    async fn test_simulation_events_and_results() {
+        let simulation =
+            Simulation::new(SpeedProfile::Cable, Duration::from_millis(20))
+                .with_sizes(
+                    vec![DataBlock::new(1_000_000, 4)],
+                    vec![DataBlock::new(1_000_000, 2)],
+                );
+        let recorder = Recorder::default();
+        let run = simulation.run(&recorder).await;
+        let events = recorder.0.into_inner().unwrap();
+
+        let phases: Vec<TestPhase> = events
+            .iter()
+            .filter_map(|event| match event {
+                ProgressEvent::PhaseChange(phase) => Some(*phase),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            phases,
+            [
+                TestPhase::Latency,
+                TestPhase::Download,
+                TestPhase::Upload,
+                TestPhase::Complete
+            ]
+        );
+        let uploads: Vec<(usize, usize)> = events
+            .iter()
+            .filter_map(|event| match event {
+                ProgressEvent::BandwidthMeasurement {
+                    direction: BandwidthDirection::Upload,
+                    current,
+                    total,
+                    ..
+                } => Some((*current, *total)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(uploads, [(1, 2), (2, 2)]);
+
+        // Cable: fast down, slow up, and bloated under load
+        assert!(run.download.speed_mbps > 100.0);
+        assert!(run.upload.speed_mbps < 30.0);
+        assert_eq!(run.download.measurements.len(), 1);
+        assert!(run.latency.loaded_up_ms > run.latency.loaded_down_ms);
+        assert!(run.latency.loaded_down_ms.unwrap() > run.latency.idle_ms);
+
+        let results = simulation.results(&run, &ScoringThresholds::default());
+        assert_eq!(results.server.iata, "SIM");
+        assert_eq!(results.connection.isp.as_deref(), Some("Simulated cable"));
+        assert!(results.scores.is_some());
+    }
+}
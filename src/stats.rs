@@ -89,6 +89,121 @@ pub fn percentile_f64(values: &mut [f64], p: f64) -> Option<f64> {
     Some(lower_val + fraction * (upper_val - lower_val))
 }
 
+/// Largest combined sample size for which [`mann_whitney_p`] computes the
+/// exact distribution of U rather than its normal approximation.
+const EXACT_MANN_WHITNEY_MAX: usize = 40;
+
+/// Two-sided p-value of the Mann-Whitney U test that samples `a` and `b`
+/// come from the same distribution.
+///
+/// Makes no assumption about the shape of the distribution, which suits
+/// bandwidth and latency samples. Small samples without ties use the
+/// exact distribution of U; otherwise a normal approximation with tie
+/// and continuity corrections is used.
+///
+/// # Returns
+/// * `Some(p)` - The p-value in [0.0, 1.0]
+/// * `None` - If either sample is empty
+pub fn mann_whitney_p(a: &[f64], b: &[f64]) -> Option<f64> {
+    let (m, n) = (a.len(), b.len());
+    if m == 0 || n == 0 {
+        return None;
+    }
+
+    // Rank the pooled samples, averaging the ranks of ties
+    let mut pooled: Vec<(f64, bool)> = a
+        .iter()
+        .map(|&v| (v, true))
+        .chain(b.iter().map(|&v| (v, false)))
+        .collect();
+    pooled.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    let mut rank_sum_a = 0.0;
+    let mut tie_term = 0.0;
+    let mut i = 0;
+    while i < pooled.len() {
+        let mut j = i;
+        while j + 1 < pooled.len() && pooled[j + 1].0 == pooled[i].0 {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        let in_a = pooled[i..=j].iter().filter(|(_, in_a)| *in_a).count();
+        rank_sum_a += rank * in_a as f64;
+
+        let t = (j - i + 1) as f64;
+        tie_term += t * t * t - t;
+        i = j + 1;
+    }
+
+    let u = rank_sum_a - (m * (m + 1)) as f64 / 2.0;
+
+    if tie_term == 0.0 && m + n <= EXACT_MANN_WHITNEY_MAX {
+        let counts = u_distribution(m, n);
+        let total: f64 = counts.iter().sum();
+        let u = u as usize;
+        let lower: f64 = counts[..=u].iter().sum();
+        let upper: f64 = counts[u..].iter().sum();
+        return Some((2.0 * lower.min(upper) / total).min(1.0));
+    }
+
+    let (m, n) = (m as f64, n as f64);
+    let total = m + n;
+    let mean = m * n / 2.0;
+    let variance =
+        m * n / 12.0 * ((total + 1.0) - tie_term / (total * (total - 1.0)));
+    if variance <= 0.0 {
+        // Every value is identical
+        return Some(1.0);
+    }
+
+    let z = (((u - mean).abs() - 0.5).max(0.0)) / variance.sqrt();
+    Some((2.0 * (1.0 - normal_cdf(z))).clamp(0.0, 1.0))
+}
+
+/// Number of orderings of samples of size `m` and `n` giving each value
+/// of U, from 0 to `m * n`.
+fn u_distribution(m: usize, n: usize) -> Vec<f64> {
+    // counts[i][j] is the distribution for sizes i and j, built from
+    // whether the largest value belongs to the first sample (adding j
+    // to U) or the second (adding nothing)
+    let mut counts = vec![vec![Vec::new(); n + 1]; m + 1];
+    for i in 0..=m {
+        for j in 0..=n {
+            counts[i][j] = if i == 0 || j == 0 {
+                vec![1.0]
+            } else {
+                let mut dist = vec![0.0; i * j + 1];
+                for (u, c) in counts[i - 1][j].iter().enumerate() {
+                    dist[u + j] += c;
+                }
+                for (u, c) in counts[i][j - 1].iter().enumerate() {
+                    dist[u] += c;
+                }
+                dist
+            };
+        }
+    }
+
+    counts.swap_remove(m).swap_remove(n)
+}
+
+/// Standard normal cumulative distribution function.
+fn normal_cdf(z: f64) -> f64 {
+    0.5 * (1.0 + erf(z / std::f64::consts::SQRT_2))
+}
+
+/// Error function, accurate to about 1.5e-7 (Abramowitz and Stegun
+/// 7.1.26).
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736
+                + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let y = 1.0 - poly * (-x * x).exp();
+    y.copysign(x)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -139,7 +254,7 @@ mod tests {
         // Median should always be between min and max
         let mut values = vec![10.0, 50.0, 30.0, 20.0, 40.0];
         let result = median_f64(&mut values).unwrap();
-        assert!(result >= 10.0 && result <= 50.0);
+        assert!((10.0..=50.0).contains(&result));
     }
 
     // Tests for percentile_f64
@@ -205,10 +320,67 @@ mod tests {
         let values = vec![10.0, 20.0, 30.0, 40.0, 50.0];
         for p in [0.0, 0.25, 0.5, 0.75, 1.0] {
             let result = percentile_f64(&mut values.clone(), p).unwrap();
-            assert!(result >= 10.0 && result <= 50.0);
+            assert!((10.0..=50.0).contains(&result));
         }
     }
 
+    // Tests for mann_whitney_p
+    #[test]
+    fn test_mann_whitney_p_empty_sample() {
+        assert_eq!(mann_whitney_p(&[], &[1.0]), None);
+        assert_eq!(mann_whitney_p(&[1.0], &[]), None);
+    }
+
+    #[test]
+    fn test_mann_whitney_p_exact_separated_samples() {
+        // Complete separation of 5 vs 5 is the most extreme of
+        // C(10, 5) = 252 orderings, in either direction
+        let a = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let b = [6.0, 7.0, 8.0, 9.0, 10.0];
+        let p = mann_whitney_p(&a, &b).unwrap();
+        assert!((p - 2.0 / 252.0).abs() < 1e-12, "p = {}", p);
+        assert_eq!(mann_whitney_p(&b, &a), Some(p));
+    }
+
+    #[test]
+    fn test_mann_whitney_p_interleaved_samples() {
+        let a = [1.0, 4.0, 5.0, 8.0, 9.0];
+        let b = [2.0, 3.0, 6.0, 7.0, 10.0];
+        assert!(mann_whitney_p(&a, &b).unwrap() > 0.5);
+    }
+
+    #[test]
+    fn test_mann_whitney_p_identical_samples() {
+        assert_eq!(mann_whitney_p(&[3.0; 4], &[3.0; 4]), Some(1.0));
+    }
+
+    #[test]
+    fn test_mann_whitney_p_normal_approximation() {
+        // Ties force the approximation; well separated samples are still
+        // significant
+        let a = [10.0, 10.0, 11.0, 12.0, 12.0, 13.0];
+        let b = [20.0, 20.0, 21.0, 22.0, 22.0, 23.0];
+        let p = mann_whitney_p(&a, &b).unwrap();
+        assert!(p < 0.01, "p = {}", p);
+    }
+
+    #[test]
+    fn test_u_distribution_is_symmetric() {
+        let dist = u_distribution(3, 4);
+        assert_eq!(dist.len(), 13);
+        assert_eq!(dist.iter().sum::<f64>(), 35.0);
+        let reversed: Vec<f64> = dist.iter().rev().copied().collect();
+        assert_eq!(dist, reversed);
+    }
+
+    #[test]
+    fn test_erf_known_values() {
+        assert!(erf(0.0).abs() < 1e-7);
+        assert!((erf(1.0) - 0.8427007929).abs() < 1e-6);
+        assert!((erf(-1.0) + 0.8427007929).abs() < 1e-6);
+        assert!((normal_cdf(1.959964) - 0.975).abs() < 1e-6);
+    }
+
     // Property-based tests for median_f64
     // Feature: cloudflare-speedtest-parity, Property 1: Median Calculation Correctness
     // Validates: Requirements 2.4
@@ -0,0 +1,251 @@
+//! Terminal capability detection.
+//!
+//! Not every terminal the TUI lands in is a modern local emulator: over
+//! SSH the locale often isn't forwarded, plain `screen` and `xterm`
+//! terminal types only promise eight colors, and the Linux console
+//! and old VT terminals have neither the alternate screen nor the
+//! glyphs the renderer draws. Capabilities are guessed from the
+//! environment (`TERM`, `COLORTERM`, the locale, and the variables SSH,
+//! tmux and screen set), and `--ascii` forces the limited set.
+
+use ratatui::style::Color;
+
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSupport {
+    /// The eight standard ANSI colors
+    Basic,
+    /// The xterm 256-color palette
+    Ansi256,
+    /// 24-bit RGB
+    TrueColor,
+}
+
+impl ColorSupport {
+    /// The closest color to `color` the terminal can show.
+    pub fn adapt(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorSupport::TrueColor, color) => color,
+            (ColorSupport::Ansi256, Color::Rgb(r, g, b)) => {
+                Color::Indexed(cube_index(r, g, b))
+            }
+            (ColorSupport::Ansi256, color) => color,
+            (ColorSupport::Basic, Color::Rgb(r, g, b)) => {
+                nearest_basic(r, g, b)
+            }
+            (ColorSupport::Basic, Color::Indexed(_)) => Color::Reset,
+            (ColorSupport::Basic, Color::LightRed) => Color::Red,
+            (ColorSupport::Basic, Color::LightGreen) => Color::Green,
+            (ColorSupport::Basic, Color::LightYellow) => Color::Yellow,
+            (ColorSupport::Basic, Color::LightBlue) => Color::Blue,
+            (ColorSupport::Basic, Color::LightMagenta) => Color::Magenta,
+            (ColorSupport::Basic, Color::LightCyan) => Color::Cyan,
+            (ColorSupport::Basic, Color::White) => Color::Gray,
+            (ColorSupport::Basic, Color::DarkGray) => Color::Reset,
+            (ColorSupport::Basic, color) => color,
+        }
+    }
+}
+
+/// Index of the 256-color cube entry closest to an RGB color.
+fn cube_index(r: u8, g: u8, b: u8) -> u8 {
+    // The cube's levels are 0, 95, 135, 175, 215 and 255
+    let level = |v: u8| match v {
+        0..48 => 0,
+        48..115 => 1,
+        _ => (v - 35) / 40,
+    };
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+/// The standard ANSI color closest to an RGB color.
+fn nearest_basic(r: u8, g: u8, b: u8) -> Color {
+    match (r >= 128, g >= 128, b >= 128) {
+        (false, false, false) => Color::Black,
+        (true, false, false) => Color::Red,
+        (false, true, false) => Color::Green,
+        (true, true, false) => Color::Yellow,
+        (false, false, true) => Color::Blue,
+        (true, false, true) => Color::Magenta,
+        (false, true, true) => Color::Cyan,
+        (true, true, true) => Color::Gray,
+    }
+}
+
+/// What the terminal can display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalCapabilities {
+    /// Whether Unicode symbols, box drawing and block glyphs show up
+    pub unicode: bool,
+    /// Colors available
+    pub colors: ColorSupport,
+    /// Whether the TUI can draw on the alternate screen
+    pub alternate_screen: bool,
+    /// Whether to capture the mouse. Nothing in the TUI uses it, and over
+    /// SSH or inside tmux and screen capturing it takes away their own
+    /// scrolling and selection.
+    pub mouse: bool,
+}
+
+impl Default for TerminalCapabilities {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+impl TerminalCapabilities {
+    /// A modern local terminal.
+    pub const FULL: Self = Self {
+        unicode: true,
+        colors: ColorSupport::TrueColor,
+        alternate_screen: true,
+        mouse: true,
+    };
+
+    /// Capabilities of the terminal the process runs in.
+    pub fn detect() -> Self {
+        if cfg!(windows) {
+            return Self::FULL;
+        }
+        Self::from_env(|name| std::env::var(name).ok())
+    }
+
+    /// Capabilities described by the environment variables `var` reads.
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        let is_set = |name: &str| var(name).is_some_and(|v| !v.is_empty());
+        let term = var("TERM").unwrap_or_default().to_ascii_lowercase();
+        // Old VT terminals, the Linux console and `dumb` lack the
+        // alternate screen, and their fonts most of the symbols
+        let legacy = matches!(term.as_str(), "" | "dumb" | "ansi" | "linux")
+            || term.starts_with("vt");
+
+        let colors = if matches!(
+            var("COLORTERM").as_deref(),
+            Some("truecolor" | "24bit")
+        ) || term.ends_with("-direct")
+        {
+            ColorSupport::TrueColor
+        } else if term.contains("256color") {
+            ColorSupport::Ansi256
+        } else {
+            ColorSupport::Basic
+        };
+
+        let remote = ["SSH_CONNECTION", "SSH_CLIENT", "SSH_TTY"]
+            .into_iter()
+            .any(is_set);
+        let multiplexed = is_set("TMUX") || is_set("STY");
+
+        Self {
+            unicode: !legacy && locale_is_utf8(&var),
+            colors,
+            alternate_screen: !legacy,
+            mouse: !legacy && !remote && !multiplexed,
+        }
+    }
+
+    /// These capabilities limited to plain ASCII and the basic colors,
+    /// without mouse capture, as `--ascii` asks for.
+    pub fn ascii(self) -> Self {
+        Self {
+            unicode: false,
+            colors: ColorSupport::Basic,
+            mouse: false,
+            ..self
+        }
+    }
+}
+
+/// Whether the locale uses UTF-8. An unset locale says nothing about the
+/// terminal, so it counts as UTF-8.
+fn locale_is_utf8(var: &impl Fn(&str) -> Option<String>) -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .into_iter()
+        .find_map(|name| var(name).filter(|v| !v.is_empty()));
+    match locale {
+        Some(locale) => {
+            let locale = locale.to_ascii_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        }
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn detect(vars: &[(&str, &str)]) -> TerminalCapabilities {
+        let vars: HashMap<_, _> = vars.iter().copied().collect();
+        TerminalCapabilities::from_env(|name| {
+            vars.get(name).map(|v| v.to_string())
+        })
+    }
+
+    #[test]
+    fn test_detect_local_terminal() {
+        let caps = detect(&[
+            ("TERM", "xterm-256color"),
+            ("COLORTERM", "truecolor"),
+            ("LANG", "en_US.UTF-8"),
+        ]);
+        assert_eq!(caps, TerminalCapabilities::FULL);
+        assert_eq!(
+            detect(&[("TERM", "xterm-256color")]).colors,
+            ColorSupport::Ansi256
+        );
+    }
+
+    #[test]
+    fn test_detect_limited_terminals() {
+        let ssh_in_screen = detect(&[
+            ("TERM", "screen"),
+            ("STY", "1234.pts-0.host"),
+            ("SSH_CONNECTION", "192.0.2.1 51234 192.0.2.2 22"),
+            ("LANG", "C"),
+        ]);
+        assert!(!ssh_in_screen.unicode);
+        assert_eq!(ssh_in_screen.colors, ColorSupport::Basic);
+        assert!(ssh_in_screen.alternate_screen);
+        assert!(!ssh_in_screen.mouse);
+
+        let console = detect(&[("TERM", "linux"), ("LANG", "en_US.UTF-8")]);
+        assert!(!console.unicode);
+        assert!(!console.alternate_screen);
+        assert!(!detect(&[("TERM", "vt220")]).alternate_screen);
+
+        // LC_ALL wins over LANG
+        let caps = detect(&[
+            ("TERM", "tmux-256color"),
+            ("TMUX", "/tmp/tmux-1000/default,1,0"),
+            ("LANG", "en_US.UTF-8"),
+            ("LC_ALL", "en_US.ISO-8859-1"),
+        ]);
+        assert!(!caps.unicode);
+        assert!(!caps.mouse);
+    }
+
+    #[test]
+    fn test_ascii_keeps_alternate_screen() {
+        let caps = TerminalCapabilities::FULL.ascii();
+        assert!(!caps.unicode);
+        assert_eq!(caps.colors, ColorSupport::Basic);
+        assert!(caps.alternate_screen);
+        assert!(!caps.mouse);
+    }
+
+    #[test]
+    fn test_adapt_colors() {
+        let orange = Color::Rgb(255, 165, 0);
+        assert_eq!(ColorSupport::TrueColor.adapt(orange), orange);
+        assert_eq!(ColorSupport::Ansi256.adapt(orange), Color::Indexed(214));
+        assert_eq!(ColorSupport::Basic.adapt(orange), Color::Yellow);
+        assert_eq!(ColorSupport::Basic.adapt(Color::LightGreen), Color::Green);
+        assert_eq!(ColorSupport::Basic.adapt(Color::DarkGray), Color::Reset);
+        assert_eq!(
+            ColorSupport::Ansi256.adapt(Color::DarkGray),
+            Color::DarkGray
+        );
+    }
+}
@@ -0,0 +1,40 @@
+//! System clipboard access, for copying results from the TUI.
+//!
+//! Behind the `clipboard` feature (on by default). On X11 and Wayland
+//! the copied text is served by this process, so the clipboard is kept
+//! open while the TUI runs and handed to a clipboard manager, if one is
+//! running, when it closes.
+
+/// An open system clipboard.
+pub struct Clipboard {
+    #[cfg(feature = "clipboard")]
+    inner: arboard::Clipboard,
+}
+
+impl Clipboard {
+    /// Open the system clipboard.
+    #[cfg(feature = "clipboard")]
+    pub fn open() -> Result<Self, String> {
+        arboard::Clipboard::new()
+            .map(|inner| Self { inner })
+            .map_err(|e| e.to_string())
+    }
+
+    /// Open the system clipboard.
+    #[cfg(not(feature = "clipboard"))]
+    pub fn open() -> Result<Self, String> {
+        Err("built without clipboard support".to_string())
+    }
+
+    /// Replace the clipboard's contents with `text`.
+    #[cfg(feature = "clipboard")]
+    pub fn set_text(&mut self, text: &str) -> Result<(), String> {
+        self.inner.set_text(text).map_err(|e| e.to_string())
+    }
+
+    /// Replace the clipboard's contents with `text`.
+    #[cfg(not(feature = "clipboard"))]
+    pub fn set_text(&mut self, _text: &str) -> Result<(), String> {
+        Err("built without clipboard support".to_string())
+    }
+}
@@ -3,8 +3,23 @@
 //! The TuiController manages the TUI lifecycle, including initialization,
 //! rendering, and cleanup. It also provides a progress callback for
 //! the test engine to emit events.
-
+//!
+//! The callback never waits on the renderer: events go through a channel
+//! and are applied in one batch before each frame. Only measurement
+//! events can be dropped, when the renderer falls far behind; phase and
+//! size block events always arrive.
+//!
+//! A frame is only drawn when it would differ from the last one: the
+//! renderer draws from nothing but the state, so an unchanged state hash
+//! means an unchanged screen. This keeps idle redraws off slow terminals
+//! and SSH sessions.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::{self, Write as _};
+use std::hash::Hasher;
 use std::io::{self, Stdout};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -12,21 +27,35 @@ use crossterm::{
     cursor,
     event::{
         self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode,
-        KeyEventKind,
+        KeyEventKind, KeyModifiers,
     },
     execute,
     terminal::{
-        disable_raw_mode, enable_raw_mode, EnterAlternateScreen,
-        LeaveAlternateScreen,
+        disable_raw_mode, enable_raw_mode, Clear, ClearType,
+        EnterAlternateScreen, LeaveAlternateScreen,
     },
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
 
+use super::capabilities::TerminalCapabilities;
+use super::clipboard::Clipboard;
 use super::display_mode::DisplayMode;
+use super::panic_guard;
 use super::progress::{ProgressCallback, ProgressEvent};
 use super::renderer::render_frame;
-use super::state::{ConnectionInfo, ServerInfo, TuiState};
+use super::state::{ConnectionInfo, PingView, ServerInfo, TuiState};
+use crate::cloudflare::tests::engine::{PauseFlag, PhaseSelection};
+use crate::exit::{bounded, CLEANUP_TIMEOUT};
+use crate::ping::PingStats;
 use crate::results::SpeedTestResults;
+use crate::scoring::{AimScoreDetails, GradeSummary};
+
+/// Measurement events that may wait for the renderer at once; further
+/// ones are dropped until it catches up.
+const MEASUREMENT_BACKLOG: usize = 256;
+
+/// How often to redraw while a test runs, unless configured otherwise.
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_millis(100);
 
 /// Result of waiting for user input after test completion.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -47,19 +76,72 @@ pub struct TuiController {
     terminal: Option<Terminal<CrosstermBackend<Stdout>>>,
     /// Whether the terminal has been initialized
     initialized: bool,
+    /// Pause switch shared with the test engine, toggled with 'p'
+    pause: PauseFlag,
+    /// Progress events not yet applied to `state`
+    events: Receiver<ProgressEvent>,
+    /// Sending end handed to progress callbacks
+    event_sender: Sender<ProgressEvent>,
+    /// Measurement events sent but not yet applied
+    backlog: Arc<AtomicUsize>,
+    /// The final results as JSON, copied with 'c'
+    results_json: Option<String>,
+    /// Clipboard opened by the first copy, kept open so what was copied
+    /// stays available while the TUI runs
+    clipboard: Option<Clipboard>,
+    /// How often to redraw while a test runs
+    refresh_interval: Duration,
+    /// Hash of the state the last frame was drawn from
+    last_frame: Option<u64>,
 }
 
 impl TuiController {
     /// Create a new TUI controller.
     pub fn new(mode: DisplayMode) -> Result<Self, Box<dyn std::error::Error>> {
+        let (event_sender, events) = mpsc::channel();
         Ok(Self {
             mode,
             state: Arc::new(Mutex::new(TuiState::new())),
             terminal: None,
             initialized: false,
+            pause: PauseFlag::default(),
+            events,
+            event_sender,
+            backlog: Arc::new(AtomicUsize::new(0)),
+            results_json: None,
+            clipboard: None,
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            last_frame: None,
         })
     }
 
+    /// Redraw every `interval` while a test runs.
+    pub fn with_refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    /// How often to redraw while a test runs.
+    pub fn refresh_interval(&self) -> Duration {
+        self.refresh_interval
+    }
+
+    /// Draw for a terminal with `capabilities`.
+    pub fn with_capabilities(
+        self,
+        capabilities: TerminalCapabilities,
+    ) -> Self {
+        if let Ok(mut state) = self.state.lock() {
+            state.capabilities = capabilities;
+        }
+        self
+    }
+
+    /// What the terminal is taken to display.
+    pub fn capabilities(&self) -> TerminalCapabilities {
+        self.state.lock().map(|state| state.capabilities).unwrap_or_default()
+    }
+
     /// Get current display mode.
     pub fn mode(&self) -> DisplayMode {
         self.mode
@@ -71,21 +153,29 @@ impl TuiController {
             return Ok(());
         }
 
+        let capabilities = self.capabilities();
         enable_raw_mode()?;
+        panic_guard::mark_taken();
 
         let mut stdout = io::stdout();
-        execute!(
-            stdout,
-            EnterAlternateScreen,
-            EnableMouseCapture,
-            cursor::Hide
-        )?;
+        if capabilities.alternate_screen {
+            execute!(stdout, EnterAlternateScreen)?;
+        }
+        if capabilities.mouse {
+            execute!(stdout, EnableMouseCapture)?;
+        }
+        execute!(stdout, cursor::Hide)?;
 
         let backend = CrosstermBackend::new(stdout);
-        let terminal = Terminal::new(backend)?;
+        let mut terminal = Terminal::new(backend)?;
+        if !capabilities.alternate_screen {
+            // Draw over a blank screen rather than the shell's output
+            terminal.clear()?;
+        }
 
         self.terminal = Some(terminal);
         self.initialized = true;
+        self.last_frame = None;
 
         if let Some(ref terminal) = self.terminal {
             let size = terminal.size()?;
@@ -98,25 +188,40 @@ impl TuiController {
         Ok(())
     }
 
-    /// Clean up and restore terminal state.
+    /// Clean up and restore terminal state, giving up after
+    /// [`CLEANUP_TIMEOUT`] when the terminal doesn't take the writes. It
+    /// isn't retried either way.
     pub fn cleanup(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         if !self.initialized {
             return Ok(());
         }
+        self.initialized = false;
 
-        if let Some(ref mut terminal) = self.terminal {
-            execute!(
-                terminal.backend_mut(),
-                LeaveAlternateScreen,
-                DisableMouseCapture,
-                cursor::Show
-            )?;
+        let capabilities = self.capabilities();
+        let terminal = self.terminal.take();
+        if !panic_guard::mark_released() {
+            // A panic already put the terminal back
+            return Ok(());
         }
-
-        disable_raw_mode()?;
-
-        self.initialized = false;
-        self.terminal = None;
+        bounded("restoring the terminal", CLEANUP_TIMEOUT, move || {
+            if let Some(mut terminal) = terminal {
+                let backend = terminal.backend_mut();
+                if capabilities.alternate_screen {
+                    execute!(backend, LeaveAlternateScreen)?;
+                } else {
+                    execute!(
+                        backend,
+                        Clear(ClearType::All),
+                        cursor::MoveTo(0, 0)
+                    )?;
+                }
+                if capabilities.mouse {
+                    execute!(backend, DisableMouseCapture)?;
+                }
+                execute!(backend, cursor::Show)?;
+            }
+            disable_raw_mode()
+        })?;
 
         Ok(())
     }
@@ -139,6 +244,13 @@ impl TuiController {
         }
     }
 
+    /// Set a non-fatal warning for display.
+    pub fn set_warning(&mut self, warning: String) {
+        if let Ok(mut state) = self.state.lock() {
+            state.set_warning(warning);
+        }
+    }
+
     /// Set quality scores for display.
     pub fn set_quality_scores(
         &mut self,
@@ -151,6 +263,51 @@ impl TuiController {
         }
     }
 
+    /// Set the per-factor breakdown behind the quality scores.
+    pub fn set_score_details(&mut self, details: AimScoreDetails) {
+        if let Ok(mut state) = self.state.lock() {
+            state.set_score_details(details);
+        }
+    }
+
+    /// Set the phases the test runs, hiding the rest.
+    pub fn set_phases(&mut self, phases: PhaseSelection) {
+        if let Ok(mut state) = self.state.lock() {
+            state.set_phases(phases);
+        }
+    }
+
+    /// Show the latency monitor, probing every `interval`, instead of the
+    /// speed test.
+    pub fn start_ping(&mut self, interval: Duration) {
+        if let Ok(mut state) = self.state.lock() {
+            state.ping =
+                Some(PingView { stats: PingStats::default(), interval });
+        }
+    }
+
+    /// Update the latency monitor's statistics.
+    pub fn set_ping_stats(&mut self, stats: &PingStats) {
+        if let Ok(mut state) = self.state.lock() {
+            if let Some(ref mut ping) = state.ping {
+                ping.stats = stats.clone();
+            }
+        }
+    }
+
+    /// Whether the user pressed 'q', Esc or Ctrl+C since the last render.
+    /// Raw mode swallows Ctrl+C, so it arrives as a key press.
+    pub fn stop_requested(&self) -> bool {
+        self.state.lock().map(|state| state.stop_requested).unwrap_or(false)
+    }
+
+    /// Set the letter grade for the whole test.
+    pub fn set_grade(&mut self, grade: GradeSummary) {
+        if let Ok(mut state) = self.state.lock() {
+            state.set_grade(grade);
+        }
+    }
+
     /// Set loaded latency values.
     pub fn set_loaded_latency(
         &mut self,
@@ -167,12 +324,46 @@ impl TuiController {
         }
     }
 
+    /// Set the server time to first byte figures.
+    pub fn set_ttfb(&mut self, median_ms: Option<f64>, p95_ms: Option<f64>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.latency.ttfb_median_ms = median_ms;
+            state.latency.ttfb_p95_ms = p95_ms;
+        }
+    }
+
+    /// Apply the progress events received since the last call.
+    ///
+    /// However many events piled up, the state is locked once for all of
+    /// them, so a burst of fast transfers costs the renderer one frame.
+    pub fn apply_progress(&mut self) {
+        let events: Vec<ProgressEvent> = self.events.try_iter().collect();
+        if events.is_empty() {
+            return;
+        }
+        let measurements =
+            events.iter().filter(|event| is_measurement(event)).count();
+        self.backlog.fetch_sub(measurements, Ordering::AcqRel);
+
+        if let Ok(mut state) = self.state.lock() {
+            for event in &events {
+                state.update_from_event(event);
+            }
+        }
+    }
+
     /// Render the current state to the terminal.
     pub fn render(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.apply_progress();
         if self.mode != DisplayMode::Tui {
             return Ok(());
         }
 
+        // Don't draw over a panic message
+        if !panic_guard::is_taken() {
+            return Ok(());
+        }
+
         self.handle_pending_events()?;
 
         if let Some(ref mut terminal) = self.terminal {
@@ -192,9 +383,14 @@ impl TuiController {
                 state_guard.clone()
             };
 
+            let fingerprint = frame_fingerprint(&state);
+            if self.last_frame == Some(fingerprint) {
+                return Ok(());
+            }
             terminal.draw(|frame| {
                 render_frame(frame, &state);
             })?;
+            self.last_frame = Some(fingerprint);
         }
 
         Ok(())
@@ -216,14 +412,24 @@ impl TuiController {
                         state.terminal_height = height;
                     }
                 }
-                Event::Key(key_event) => {
-                    if key_event.kind == KeyEventKind::Press {
-                        match key_event.code {
-                            KeyCode::Char('q') | KeyCode::Esc => {
-                                // Handled by wait_for_exit
-                            }
-                            _ => {}
+                Event::Key(key_event)
+                    if key_event.kind == KeyEventKind::Press =>
+                {
+                    match key_event.code {
+                        KeyCode::Char('c')
+                            if key_event
+                                .modifiers
+                                .contains(KeyModifiers::CONTROL) =>
+                        {
+                            self.request_stop();
+                        }
+                        KeyCode::Char('q') | KeyCode::Esc => {
+                            // Ends the latency monitor; after a test,
+                            // handled by wait_for_exit
+                            self.request_stop();
                         }
+                        KeyCode::Char('p') => self.toggle_pause(),
+                        _ => {}
                     }
                 }
                 _ => {}
@@ -233,7 +439,51 @@ impl TuiController {
         Ok(())
     }
 
+    /// Record that the user asked to stop.
+    fn request_stop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.stop_requested = true;
+        }
+    }
+
+    /// Pause or resume the running test.
+    fn toggle_pause(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            if state.waiting_for_exit {
+                return;
+            }
+            state.request_pause(self.pause.toggle());
+        }
+    }
+
+    /// Copy the final results to the system clipboard as JSON, noting
+    /// how it went in the status bar.
+    fn copy_results(&mut self) {
+        let Some(json) = self.results_json.as_deref() else {
+            return;
+        };
+        let copied = match self.clipboard.as_mut() {
+            Some(clipboard) => clipboard.set_text(json),
+            None => Clipboard::open().and_then(|mut clipboard| {
+                clipboard.set_text(json)?;
+                self.clipboard = Some(clipboard);
+                Ok(())
+            }),
+        };
+        let notice = match copied {
+            Ok(()) => {
+                "Copied the results to the clipboard as JSON".to_string()
+            }
+            Err(e) => format!("Couldn't copy the results: {}", e),
+        };
+        if let Ok(mut state) = self.state.lock() {
+            state.notice = Some(notice);
+        }
+    }
+
     /// Wait for user to press 'q' or Esc to exit, or 'r' to retest.
+    /// 'd' expands or collapses the quality score breakdown, and 'c'
+    /// copies the results.
     /// Returns Ok(WaitResult::Exit) if user wants to exit,
     /// Ok(WaitResult::Retest) if user wants to retest,
     /// or Err if interrupted.
@@ -272,8 +522,15 @@ impl TuiController {
                                 if let Ok(mut state) = self.state.lock() {
                                     state.reset_for_retest();
                                 }
+                                self.pause.clear();
                                 return Ok(WaitResult::Retest);
                             }
+                            KeyCode::Char('d') => {
+                                if let Ok(mut state) = self.state.lock() {
+                                    state.toggle_score_details();
+                                }
+                            }
+                            KeyCode::Char('c') => self.copy_results(),
                             _ => {}
                         }
                     }
@@ -290,6 +547,10 @@ impl TuiController {
         &mut self,
         results: &SpeedTestResults,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        self.results_json = serde_json::to_string_pretty(results).ok();
+
+        // Events still queued mustn't overwrite the final results
+        self.apply_progress();
         if let Ok(mut state) = self.state.lock() {
             state.latency.median_ms = Some(results.latency.idle_ms);
             state.latency.jitter_ms = results.latency.idle_jitter_ms;
@@ -301,10 +562,11 @@ impl TuiController {
                 results.latency.loaded_up_jitter_ms;
 
             state.download.final_speed_mbps =
-                Some(results.download.speed_mbps);
+                results.download.as_ref().map(|d| d.speed_mbps);
             state.download.completed = true;
 
-            state.upload.final_speed_mbps = Some(results.upload.speed_mbps);
+            state.upload.final_speed_mbps =
+                results.upload.as_ref().map(|u| u.speed_mbps);
             state.upload.completed = true;
 
             state.phase = super::progress::TestPhase::Complete;
@@ -317,11 +579,20 @@ impl TuiController {
 
     /// Get a progress callback for the test engine.
     pub fn progress_callback(&self) -> Arc<dyn ProgressCallback> {
-        Arc::new(TuiProgressCallback { state: Arc::clone(&self.state) })
+        Arc::new(TuiProgressCallback {
+            sender: self.event_sender.clone(),
+            backlog: Arc::clone(&self.backlog),
+        })
+    }
+
+    /// Get the pause switch to hand to the test engine.
+    pub fn pause_flag(&self) -> PauseFlag {
+        self.pause.clone()
     }
 
     /// Get partial results collected so far.
-    pub fn get_partial_results(&self) -> Option<PartialResults> {
+    pub fn get_partial_results(&mut self) -> Option<PartialResults> {
+        self.apply_progress();
         let state = self.state.lock().ok()?;
 
         if state.latency.measurements.is_empty()
@@ -375,16 +646,55 @@ impl Drop for TuiController {
 }
 
 /// Progress callback implementation for the TUI.
+///
+/// Queues events for [`TuiController::apply_progress`] without locking,
+/// so the engine never waits on a frame being drawn.
 struct TuiProgressCallback {
-    state: Arc<Mutex<TuiState>>,
+    sender: Sender<ProgressEvent>,
+    /// Measurement events queued and not yet applied
+    backlog: Arc<AtomicUsize>,
 }
 
 impl ProgressCallback for TuiProgressCallback {
     fn on_progress(&self, event: ProgressEvent) {
-        if let Ok(mut state) = self.state.try_lock() {
-            state.update_from_event(&event);
+        if is_measurement(&event)
+            && self.backlog.fetch_add(1, Ordering::AcqRel)
+                >= MEASUREMENT_BACKLOG
+        {
+            // The renderer is far behind; the next one will do
+            self.backlog.fetch_sub(1, Ordering::AcqRel);
+            return;
+        }
+        // Fails only once the controller is gone
+        let _ = self.sender.send(event);
+    }
+}
+
+/// Whether `event` is one of the frequent per-measurement updates, as
+/// opposed to a phase or block boundary that must not be lost.
+fn is_measurement(event: &ProgressEvent) -> bool {
+    matches!(
+        event,
+        ProgressEvent::LatencyMeasurement { .. }
+            | ProgressEvent::BandwidthMeasurement { .. }
+    )
+}
+
+/// A hash of everything a frame is drawn from.
+fn frame_fingerprint(state: &TuiState) -> u64 {
+    /// Feeds formatted output straight into a hasher.
+    struct HashWriter(DefaultHasher);
+
+    impl fmt::Write for HashWriter {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0.write(s.as_bytes());
+            Ok(())
         }
     }
+
+    let mut writer = HashWriter(DefaultHasher::new());
+    let _ = write!(writer, "{:?}", state);
+    writer.0.finish()
 }
 
 #[cfg(test)]
@@ -438,18 +748,20 @@ mod tests {
 
     #[test]
     fn test_progress_callback_updates_state() {
-        let controller = TuiController::new(DisplayMode::Silent).unwrap();
+        let mut controller = TuiController::new(DisplayMode::Silent).unwrap();
         let callback = controller.progress_callback();
 
         callback.on_progress(ProgressEvent::PhaseChange(TestPhase::Latency));
 
+        controller.apply_progress();
+
         let state = controller.state.lock().unwrap();
         assert_eq!(state.phase, TestPhase::Latency);
     }
 
     #[test]
     fn test_progress_callback_latency_measurement() {
-        let controller = TuiController::new(DisplayMode::Silent).unwrap();
+        let mut controller = TuiController::new(DisplayMode::Silent).unwrap();
         let callback = controller.progress_callback();
 
         callback.on_progress(ProgressEvent::LatencyMeasurement {
@@ -458,6 +770,8 @@ mod tests {
             total: 10,
         });
 
+        controller.apply_progress();
+
         let state = controller.state.lock().unwrap();
         assert_eq!(state.latency.measurements.len(), 1);
         assert_eq!(state.latency.measurements[0], 15.5);
@@ -467,7 +781,7 @@ mod tests {
 
     #[test]
     fn test_progress_callback_bandwidth_measurement() {
-        let controller = TuiController::new(DisplayMode::Silent).unwrap();
+        let mut controller = TuiController::new(DisplayMode::Silent).unwrap();
         let callback = controller.progress_callback();
 
         callback.on_progress(ProgressEvent::BandwidthMeasurement {
@@ -478,6 +792,8 @@ mod tests {
             total: 8,
         });
 
+        controller.apply_progress();
+
         let state = controller.state.lock().unwrap();
         assert_eq!(state.download.current_speed_mbps, Some(95.5));
         assert_eq!(state.download.current_bytes, 10_000_000);
@@ -485,6 +801,64 @@ mod tests {
         assert_eq!(state.download.total_measurements, 8);
     }
 
+    #[test]
+    fn test_progress_events_survive_a_locked_state() {
+        let mut controller = TuiController::new(DisplayMode::Silent).unwrap();
+        let callback = controller.progress_callback();
+
+        // Events sent while a frame holds the state still arrive
+        {
+            let _frame = controller.state.lock().unwrap();
+            callback.on_progress(ProgressEvent::BandwidthMeasurement {
+                direction: BandwidthDirection::Upload,
+                speed_mbps: 40.0,
+                bytes: 1_000_000,
+                current: 1,
+                total: 6,
+            });
+            callback
+                .on_progress(ProgressEvent::PhaseComplete(TestPhase::Upload));
+        }
+        controller.apply_progress();
+
+        let state = controller.state.lock().unwrap();
+        assert_eq!(state.upload.speed_history.len(), 1);
+        assert!(state.upload.completed);
+        assert_eq!(state.upload.final_speed_mbps, Some(40.0));
+    }
+
+    #[test]
+    fn test_measurement_backlog_never_drops_block_events() {
+        let mut controller = TuiController::new(DisplayMode::Silent).unwrap();
+        let callback = controller.progress_callback();
+
+        for i in 0..MEASUREMENT_BACKLOG + 50 {
+            callback.on_progress(ProgressEvent::LatencyMeasurement {
+                value_ms: 10.0,
+                current: i + 1,
+                total: MEASUREMENT_BACKLOG + 50,
+            });
+        }
+        callback.on_progress(ProgressEvent::PhaseComplete(TestPhase::Latency));
+        controller.apply_progress();
+
+        {
+            let state = controller.state.lock().unwrap();
+            assert_eq!(state.latency.measurements.len(), MEASUREMENT_BACKLOG);
+            assert_eq!(state.latency.median_ms, Some(10.0));
+        }
+
+        // Once applied, the backlog has room again
+        callback.on_progress(ProgressEvent::LatencyMeasurement {
+            value_ms: 12.0,
+            current: 1,
+            total: 1,
+        });
+        controller.apply_progress();
+        let state = controller.state.lock().unwrap();
+        assert_eq!(state.latency.measurements.len(), MEASUREMENT_BACKLOG + 1);
+    }
+
     #[test]
     fn test_init_noop_for_non_tui_modes() {
         let mut controller = TuiController::new(DisplayMode::Silent).unwrap();
@@ -554,4 +928,37 @@ mod tests {
         assert_eq!(state.latency.loaded_up_ms, Some(30.0));
         assert_eq!(state.latency.loaded_up_jitter_ms, Some(6.0));
     }
+
+    #[test]
+    fn test_set_ttfb() {
+        let mut controller = TuiController::new(DisplayMode::Silent).unwrap();
+        controller.set_ttfb(Some(40.0), Some(120.0));
+
+        let state = controller.state.lock().unwrap();
+        assert_eq!(state.latency.ttfb_median_ms, Some(40.0));
+        assert_eq!(state.latency.ttfb_p95_ms, Some(120.0));
+    }
+
+    #[test]
+    fn test_copy_results_needs_results() {
+        let mut controller = TuiController::new(DisplayMode::Silent).unwrap();
+        controller.copy_results();
+
+        assert!(controller.clipboard.is_none());
+        assert!(controller.state.lock().unwrap().notice.is_none());
+    }
+
+    #[test]
+    fn test_frame_fingerprint() {
+        let mut state = TuiState::new();
+        let idle = frame_fingerprint(&state);
+        assert_eq!(frame_fingerprint(&state.clone()), idle);
+
+        state.terminal_width += 1;
+        let resized = frame_fingerprint(&state);
+        assert_ne!(resized, idle);
+
+        state.notice = Some("Copied".to_string());
+        assert_ne!(frame_fingerprint(&state), resized);
+    }
 }
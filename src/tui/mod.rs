@@ -4,15 +4,20 @@
 //! including progress indicators, live measurements, and animated
 //! visualizations.
 
+pub mod capabilities;
+pub mod clipboard;
 pub mod controller;
 pub mod display_mode;
+pub mod panic_guard;
 pub mod progress;
 pub mod renderer;
 pub mod state;
 
+pub use capabilities::TerminalCapabilities;
 pub use controller::PartialResults;
 pub use controller::TuiController;
 pub use controller::WaitResult;
+pub use controller::DEFAULT_REFRESH_INTERVAL;
 pub use display_mode::DisplayMode;
 pub use progress::{
     BandwidthDirection, ProgressCallback, ProgressEvent, TestPhase,
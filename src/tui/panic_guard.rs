@@ -0,0 +1,98 @@
+//! Terminal restoration when a panic interrupts the TUI.
+//!
+//! While the TUI runs, the terminal is in raw mode on the alternate
+//! screen. A panic message written then is lost with the alternate
+//! screen, and a panic that aborts (as `release-lto` builds do) never
+//! reaches [`TuiController`](super::TuiController)'s `Drop`, leaving the
+//! shell without echo or line editing. The panic hook installed here puts
+//! the terminal back first, then lets the usual hook print the message.
+
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crossterm::{
+    cursor,
+    event::DisableMouseCapture,
+    execute,
+    terminal::{disable_raw_mode, LeaveAlternateScreen},
+};
+
+use crate::exit::{bounded, CLEANUP_TIMEOUT};
+
+/// Whether the TUI has the terminal in raw mode on the alternate screen.
+static TERMINAL_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Note that the TUI has taken over the terminal.
+pub fn mark_taken() {
+    TERMINAL_TAKEN.store(true, Ordering::SeqCst);
+}
+
+/// Note that the terminal is being given back.
+///
+/// # Returns
+/// Whether it was still taken, i.e. no panic has restored it already.
+pub fn mark_released() -> bool {
+    TERMINAL_TAKEN.swap(false, Ordering::SeqCst)
+}
+
+/// Whether the TUI still has the terminal; false once a panic restored
+/// it, so nothing more is drawn over the panic message.
+pub fn is_taken() -> bool {
+    TERMINAL_TAKEN.load(Ordering::SeqCst)
+}
+
+/// Put the terminal back if the TUI has it, giving up after
+/// [`CLEANUP_TIMEOUT`] when the terminal doesn't take the writes.
+pub fn restore_terminal() {
+    if !mark_released() {
+        return;
+    }
+    let _ = bounded("restoring the terminal", CLEANUP_TIMEOUT, || {
+        execute!(
+            io::stdout(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            cursor::Show
+        )?;
+        disable_raw_mode()
+    });
+}
+
+/// Restore the terminal before any panic message is printed.
+pub fn install_panic_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        previous(info);
+    }));
+}
+
+/// Run `f`, restoring the terminal if it panics before passing the panic
+/// on; a backstop for when another hook replaced ours.
+pub fn restore_on_panic<T>(f: impl FnOnce() -> T) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => value,
+        Err(payload) => {
+            restore_terminal();
+            panic::resume_unwind(payload)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restore_on_panic() {
+        assert_eq!(restore_on_panic(|| 42), 42);
+
+        let result = panic::catch_unwind(|| {
+            restore_on_panic(|| panic!("renderer bug"));
+        });
+        let payload = result.unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"renderer bug"));
+        assert!(!is_taken());
+    }
+}
@@ -3,8 +3,11 @@
 //! Defines the events emitted by the test engine to update the TUI
 //! and the callback trait for receiving these events.
 
+use serde::{Deserialize, Serialize};
+
 /// Test phases during speed test execution.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TestPhase {
     /// Initializing the test
     Initializing,
@@ -14,12 +17,15 @@ pub enum TestPhase {
     Download,
     /// Running upload tests
     Upload,
+    /// Saturating both directions at once
+    Duplex,
     /// All tests complete
     Complete,
 }
 
 /// Direction of bandwidth measurement.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum BandwidthDirection {
     /// Download test
     Download,
@@ -54,6 +60,37 @@ pub enum ProgressEvent {
         /// Total number of measurements
         total: usize,
     },
+    /// Idle latency measurement finished
+    LatencyComplete {
+        /// Median idle latency in milliseconds
+        idle_ms: f64,
+        /// Idle jitter in milliseconds
+        jitter_ms: Option<f64>,
+    },
+    /// All measurements of one transfer size finished
+    SizeBlockComplete {
+        /// Direction of the measurements
+        direction: BandwidthDirection,
+        /// Bytes per transfer
+        bytes: u64,
+        /// Speed across the block's measurements in Mbps
+        speed_mbps: f64,
+        /// Measurements taken
+        count: usize,
+    },
+    /// A transfer has received no data for a while
+    Stalled {
+        /// Direction of the transfer
+        direction: BandwidthDirection,
+        /// Bytes the transfer was requested to move
+        bytes: u64,
+        /// Seconds since the transfer last moved data
+        idle_secs: u64,
+    },
+    /// The test is holding between measurements until resumed
+    Paused,
+    /// The test has resumed after a pause
+    Resumed,
     /// Phase completed with results
     PhaseComplete(TestPhase),
 }
@@ -3,8 +3,13 @@
 //! Handles the actual rendering of the TUI using ratatui widgets,
 //! including layout, formatting, and color coding. Designed to match
 //! the Cloudflare speed test dashboard style.
+//!
+//! Frames are drawn for a modern terminal, then fitted to the one at
+//! hand: symbols outside ASCII and colors it lacks are swapped for the
+//! closest it has.
 
 use ratatui::{
+    buffer::Buffer,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
@@ -12,8 +17,35 @@ use ratatui::{
     Frame,
 };
 
-use super::progress::TestPhase;
-use super::state::{QualityRating, TuiState};
+use super::capabilities::TerminalCapabilities;
+use super::progress::{BandwidthDirection, TestPhase};
+use super::state::{
+    BandwidthState, PauseState, PingView, QualityRating, TuiState,
+};
+use crate::cloudflare::tests::engine::PhaseSelection;
+use crate::scoring::{FactorScores, Grade};
+
+/// Get color for idle latency based on thresholds.
+fn latency_color(latency_ms: f64) -> Color {
+    if latency_ms <= 30.0 {
+        Color::Green
+    } else if latency_ms <= 100.0 {
+        Color::Yellow
+    } else {
+        Color::Red
+    }
+}
+
+/// Get color for jitter based on thresholds.
+fn jitter_color(jitter_ms: f64) -> Color {
+    if jitter_ms <= 10.0 {
+        Color::Green
+    } else if jitter_ms <= 30.0 {
+        Color::Yellow
+    } else {
+        Color::Red
+    }
+}
 
 /// Get color for speed value based on thresholds.
 pub fn speed_color(speed_mbps: f64) -> Color {
@@ -26,6 +58,17 @@ pub fn speed_color(speed_mbps: f64) -> Color {
     }
 }
 
+/// Get color for a letter grade.
+pub fn grade_color(grade: Grade) -> Color {
+    match grade {
+        Grade::A => Color::Green,
+        Grade::B => Color::LightGreen,
+        Grade::C => Color::Yellow,
+        Grade::D => Color::LightRed,
+        Grade::F => Color::Red,
+    }
+}
+
 /// Get color for quality rating.
 pub fn quality_color(rating: &QualityRating) -> Color {
     match rating {
@@ -56,11 +99,53 @@ pub fn is_minimal_mode(width: u16) -> bool {
 
 /// Render the TUI to the terminal.
 pub fn render_frame(frame: &mut Frame, state: &TuiState) {
-    if is_minimal_mode(frame.area().width) {
+    if let Some(ref ping) = state.ping {
+        render_ping_frame(frame, state, ping);
+    } else if is_minimal_mode(frame.area().width) {
         render_minimal_frame(frame, state);
     } else {
         render_dashboard_frame(frame, state);
     }
+    fit_to_terminal(frame.buffer_mut(), state.capabilities);
+}
+
+/// Swap what the terminal can't show for the closest it can: symbols for
+/// ASCII without Unicode, and colors for ones in its palette.
+pub fn fit_to_terminal(
+    buffer: &mut Buffer,
+    capabilities: TerminalCapabilities,
+) {
+    for cell in &mut buffer.content {
+        if !capabilities.unicode && !cell.symbol().is_ascii() {
+            let symbol = ascii_symbol(cell.symbol());
+            cell.set_symbol(symbol);
+        }
+        cell.fg = capabilities.colors.adapt(cell.fg);
+        cell.bg = capabilities.colors.adapt(cell.bg);
+    }
+}
+
+/// ASCII stand-in for a symbol the renderer draws; `?` for anything else,
+/// e.g. accented letters in an ISP's name.
+fn ascii_symbol(symbol: &str) -> &'static str {
+    match symbol {
+        "─" => "-",
+        "│" => "|",
+        "┌" | "┐" | "└" | "┘" => "+",
+        // Sparkline bars, lowest to highest
+        "▁" | "▂" => "_",
+        "▃" | "▄" => "-",
+        "▅" | "▆" => "=",
+        "▇" | "█" => "#",
+        "☁" | "⚡" | "⊙" | "⊡" => "*",
+        "⚠" => "!",
+        "—" | "·" => "-",
+        "•" => "|",
+        "◐" => "o",
+        "▶" => ">",
+        "✓" => "+",
+        _ => "?",
+    }
 }
 
 /// Render the dashboard-style TUI layout (like Cloudflare's speed test).
@@ -96,14 +181,39 @@ fn render_header(frame: &mut Frame, area: Rect, state: &TuiState) {
         .constraints([Constraint::Min(20), Constraint::Length(40)])
         .split(inner);
 
-    // Title
-    let title = Paragraph::new(Line::from(vec![
+    // Title, followed by any warning
+    let mut title_spans = vec![
         Span::styled("☁ ", Style::default().fg(Color::Cyan)),
         Span::styled(
             "Speed Test",
             Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
         ),
-    ]));
+    ];
+    if let Some(ref warning) = state.warning {
+        title_spans.push(Span::styled(
+            format!("  ⚠ {}", warning),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    let mut title_lines = vec![Line::from(title_spans)];
+
+    // Grade for the whole test once it is scored
+    if let Some(ref grade) = state.quality_scores.grade {
+        title_lines.push(Line::from(vec![
+            Span::styled("Grade ", Style::default().fg(Color::DarkGray)),
+            Span::styled(
+                grade.grade.to_string(),
+                Style::default()
+                    .fg(grade_color(grade.grade))
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(
+                format!("  {}", grade.explanation),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]));
+    }
+    let title = Paragraph::new(title_lines);
     frame.render_widget(title, title_chunks[0]);
 
     // Server info on the right
@@ -128,14 +238,24 @@ fn render_main_content(frame: &mut Frame, area: Rect, state: &TuiState) {
         return;
     }
 
-    // Layout: connection info, speeds, graphs, quality/latency
+    // The quality panel grows by one line per use case when expanded
+    let bottom_height = if state.show_score_details
+        && state.quality_scores.details.is_some()
+    {
+        9
+    } else {
+        6
+    };
+
+    // Layout: connection info, speeds, graphs (left empty when only
+    // latency runs), quality/latency
     let content_chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(4), // Connection info
-            Constraint::Length(5), // Speed displays
-            Constraint::Min(6),    // Graphs
-            Constraint::Length(6), // Quality scores and latency
+            Constraint::Length(4),             // Connection info
+            Constraint::Length(5),             // Speed displays
+            Constraint::Min(6),                // Graphs
+            Constraint::Length(bottom_height), // Quality scores and latency
         ])
         .split(area);
 
@@ -194,77 +314,61 @@ fn render_connection_info(frame: &mut Frame, area: Rect, state: &TuiState) {
     frame.render_widget(paragraph, inner);
 }
 
-/// Render the large speed displays (Download, Upload, Latency, Jitter).
-fn render_speed_displays(frame: &mut Frame, area: Rect, state: &TuiState) {
-    let chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage(25),
-            Constraint::Percentage(25),
-            Constraint::Percentage(25),
-            Constraint::Percentage(25),
-        ])
-        .split(area);
-
-    // Download speed
-    render_metric_box(
-        frame,
-        chunks[0],
-        "Download",
-        state.download.final_speed_mbps.or(state.download.current_speed_mbps),
-        "Mbps",
-        state.phase == TestPhase::Download,
-        speed_color,
-    );
+/// A large metric display: label, value, unit, whether its phase is
+/// running, and how to color the value.
+type MetricBox<'a> = (&'a str, Option<f64>, &'a str, bool, fn(f64) -> Color);
 
-    // Upload speed
-    render_metric_box(
-        frame,
-        chunks[1],
-        "Upload",
-        state.upload.final_speed_mbps.or(state.upload.current_speed_mbps),
-        "Mbps",
-        state.phase == TestPhase::Upload,
-        speed_color,
-    );
-
-    // Latency
-    render_metric_box(
-        frame,
-        chunks[2],
+/// Render the large speed displays (Download, Upload, Latency, Jitter),
+/// leaving out directions the test skips.
+fn render_speed_displays(frame: &mut Frame, area: Rect, state: &TuiState) {
+    let mut metrics: Vec<MetricBox> = Vec::with_capacity(4);
+    if state.phases.downloads() {
+        metrics.push((
+            "Download",
+            state
+                .download
+                .final_speed_mbps
+                .or(state.download.current_speed_mbps),
+            "Mbps",
+            state.phase == TestPhase::Download,
+            speed_color,
+        ));
+    }
+    if state.phases.uploads() {
+        metrics.push((
+            "Upload",
+            state.upload.final_speed_mbps.or(state.upload.current_speed_mbps),
+            "Mbps",
+            state.phase == TestPhase::Upload,
+            speed_color,
+        ));
+    }
+    metrics.push((
         "Latency",
         state.latency.median_ms,
         "ms",
         state.phase == TestPhase::Latency,
-        |v| {
-            if v <= 30.0 {
-                Color::Green
-            } else if v <= 100.0 {
-                Color::Yellow
-            } else {
-                Color::Red
-            }
-        },
-    );
-
-    // Jitter
-    render_metric_box(
-        frame,
-        chunks[3],
+        latency_color,
+    ));
+    metrics.push((
         "Jitter",
         state.latency.jitter_ms,
         "ms",
         false,
-        |v| {
-            if v <= 10.0 {
-                Color::Green
-            } else if v <= 30.0 {
-                Color::Yellow
-            } else {
-                Color::Red
-            }
-        },
-    );
+        jitter_color,
+    ));
+
+    let count = metrics.len() as u32;
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Constraint::Ratio(1, count); metrics.len()])
+        .split(area);
+
+    for (chunk, (label, value, unit, active, color)) in
+        chunks.iter().zip(metrics)
+    {
+        render_metric_box(frame, *chunk, label, value, unit, active, color);
+    }
 }
 
 /// Render a single metric box with large value display.
@@ -321,27 +425,29 @@ fn render_metric_box<F>(
     frame.render_widget(paragraph, inner);
 }
 
-/// Render speed graphs for download and upload.
+/// Render speed graphs for the directions the test runs.
 fn render_speed_graphs(frame: &mut Frame, area: Rect, state: &TuiState) {
+    let mut graphs: Vec<(&str, &BandwidthState, Color)> =
+        Vec::with_capacity(2);
+    if state.phases.downloads() {
+        graphs.push(("Download", &state.download, Color::Rgb(255, 165, 0)));
+    }
+    if state.phases.uploads() {
+        graphs.push(("Upload", &state.upload, Color::Magenta));
+    }
+    if graphs.is_empty() {
+        return;
+    }
+
+    let count = graphs.len() as u32;
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .constraints(vec![Constraint::Ratio(1, count); graphs.len()])
         .split(area);
 
-    render_speed_graph(
-        frame,
-        chunks[0],
-        "Download",
-        &state.download,
-        Color::Rgb(255, 165, 0),
-    );
-    render_speed_graph(
-        frame,
-        chunks[1],
-        "Upload",
-        &state.upload,
-        Color::Magenta,
-    );
+    for (chunk, (label, bandwidth, color)) in chunks.iter().zip(graphs) {
+        render_speed_graph(frame, *chunk, label, bandwidth, color);
+    }
 }
 
 /// Render a single speed graph using sparkline.
@@ -349,7 +455,7 @@ fn render_speed_graph(
     frame: &mut Frame,
     area: Rect,
     label: &str,
-    bandwidth: &super::state::BandwidthState,
+    bandwidth: &BandwidthState,
     color: Color,
 ) {
     let block = Block::default()
@@ -423,6 +529,12 @@ fn render_speed_graph(
 
 /// Render the bottom section with quality scores and latency details.
 fn render_bottom_section(frame: &mut Frame, area: Rect, state: &TuiState) {
+    // Scores need both directions, so partial runs only show latency
+    if state.phases != PhaseSelection::All {
+        render_latency_details(frame, area, state);
+        return;
+    }
+
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
@@ -445,23 +557,34 @@ fn render_quality_scores(frame: &mut Frame, area: Rect, state: &TuiState) {
     let inner = block.inner(area);
     frame.render_widget(block, area);
 
-    let lines = vec![
-        // Video Streaming
-        render_quality_line(
-            "Video Streaming:",
-            state.quality_scores.streaming.as_ref(),
-        ),
-        // Online Gaming
-        render_quality_line(
-            "Online Gaming:",
-            state.quality_scores.gaming.as_ref(),
-        ),
-        // Video Chatting
-        render_quality_line(
-            "Video Chatting:",
-            state.quality_scores.video_conferencing.as_ref(),
-        ),
-    ];
+    let scores = &state.quality_scores;
+    let details = scores.details.filter(|_| state.show_score_details);
+
+    let mut lines = Vec::new();
+
+    // Video Streaming
+    lines.push(render_quality_line(
+        "Video Streaming:",
+        scores.streaming.as_ref(),
+    ));
+    if let Some(ref details) = details {
+        lines.push(render_factor_line(&details.streaming));
+    }
+
+    // Online Gaming
+    lines.push(render_quality_line("Online Gaming:", scores.gaming.as_ref()));
+    if let Some(ref details) = details {
+        lines.push(render_factor_line(&details.gaming));
+    }
+
+    // Video Chatting
+    lines.push(render_quality_line(
+        "Video Chatting:",
+        scores.video_conferencing.as_ref(),
+    ));
+    if let Some(ref details) = details {
+        lines.push(render_factor_line(&details.video_conferencing));
+    }
 
     let paragraph = Paragraph::new(lines);
     frame.render_widget(paragraph, inner);
@@ -488,6 +611,31 @@ fn render_quality_line<'a>(
     ])
 }
 
+/// Render the factor scores behind one use case's quality score.
+fn render_factor_line(factors: &FactorScores) -> Line<'static> {
+    let mut spans = vec![Span::raw("  ")];
+
+    for (i, (name, score)) in factors.factors().into_iter().enumerate() {
+        if i > 0 {
+            spans.push(Span::styled(
+                " · ",
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        let rating = QualityRating::from(score);
+        spans.push(Span::styled(
+            format!("{} ", name.replace('_', " ")),
+            Style::default().fg(Color::DarkGray),
+        ));
+        spans.push(Span::styled(
+            rating.as_str(),
+            Style::default().fg(quality_color(&rating)),
+        ));
+    }
+
+    Line::from(spans)
+}
+
 /// Render latency measurement details.
 fn render_latency_details(frame: &mut Frame, area: Rect, state: &TuiState) {
     let block = Block::default()
@@ -515,25 +663,48 @@ fn render_latency_details(frame: &mut Frame, area: Rect, state: &TuiState) {
     ]));
 
     // Latency during download
-    let down_text = if let Some(ms) = state.latency.loaded_down_ms {
-        format!("{:.1} ms", ms)
-    } else {
-        "—".to_string()
-    };
-    lines.push(Line::from(vec![
-        Span::styled("During download: ", Style::default().fg(Color::White)),
-        Span::styled(down_text, Style::default().fg(Color::Rgb(255, 165, 0))),
-    ]));
+    if state.phases.downloads() {
+        let down_text = if let Some(ms) = state.latency.loaded_down_ms {
+            format!("{:.1} ms", ms)
+        } else {
+            "—".to_string()
+        };
+        lines.push(Line::from(vec![
+            Span::styled(
+                "During download: ",
+                Style::default().fg(Color::White),
+            ),
+            Span::styled(
+                down_text,
+                Style::default().fg(Color::Rgb(255, 165, 0)),
+            ),
+        ]));
+    }
 
     // Latency during upload
-    let up_text = if let Some(ms) = state.latency.loaded_up_ms {
-        format!("{:.1} ms", ms)
-    } else {
-        "—".to_string()
-    };
+    if state.phases.uploads() {
+        let up_text = if let Some(ms) = state.latency.loaded_up_ms {
+            format!("{:.1} ms", ms)
+        } else {
+            "—".to_string()
+        };
+        lines.push(Line::from(vec![
+            Span::styled("During upload: ", Style::default().fg(Color::White)),
+            Span::styled(up_text, Style::default().fg(Color::Magenta)),
+        ]));
+    }
+
+    // Server responsiveness
+    let ttfb_text =
+        match (state.latency.ttfb_median_ms, state.latency.ttfb_p95_ms) {
+            (Some(median), Some(p95)) => {
+                format!("{:.1} ms (p95 {:.1} ms)", median, p95)
+            }
+            _ => "—".to_string(),
+        };
     lines.push(Line::from(vec![
-        Span::styled("During upload: ", Style::default().fg(Color::White)),
-        Span::styled(up_text, Style::default().fg(Color::Magenta)),
+        Span::styled("Server TTFB: ", Style::default().fg(Color::White)),
+        Span::styled(ttfb_text, Style::default().fg(Color::Yellow)),
     ]));
 
     let paragraph = Paragraph::new(lines);
@@ -543,15 +714,26 @@ fn render_latency_details(frame: &mut Frame, area: Rect, state: &TuiState) {
 /// Render the status bar at the bottom.
 pub fn render_status_bar(frame: &mut Frame, area: Rect, state: &TuiState) {
     let status_text = if state.waiting_for_exit {
-        "Press 'r' to retest • 'q' or Esc to exit"
+        let prompt = if state.quality_scores.details.is_some() {
+            "Press 'd' for score details • 'c' to copy • 'r' to retest • \
+             'q' or Esc to exit"
+        } else {
+            "Press 'c' to copy • 'r' to retest • 'q' or Esc to exit"
+        };
+        match &state.notice {
+            Some(notice) => format!("{} • {}", notice, prompt),
+            None => prompt.to_string(),
+        }
     } else {
         match state.phase {
             TestPhase::Initializing => "Connecting to Cloudflare...",
-            TestPhase::Latency => "Measuring latency...",
-            TestPhase::Download => "Testing download speed...",
-            TestPhase::Upload => "Testing upload speed...",
+            TestPhase::Latency => "Measuring latency... • 'p' to pause",
+            TestPhase::Download => "Testing download speed... • 'p' to pause",
+            TestPhase::Upload => "Testing upload speed... • 'p' to pause",
+            TestPhase::Duplex => "Testing both directions at once...",
             TestPhase::Complete => "Speed test complete",
         }
+        .to_string()
     };
 
     let style = if state.waiting_for_exit {
@@ -560,7 +742,31 @@ pub fn render_status_bar(frame: &mut Frame, area: Rect, state: &TuiState) {
         Style::default().fg(Color::DarkGray)
     };
 
-    let paragraph = Paragraph::new(status_text).style(style);
+    let paused = match state.pause {
+        _ if state.waiting_for_exit => None,
+        PauseState::Running => None,
+        PauseState::Pausing => Some("Pausing after this measurement..."),
+        PauseState::Paused => Some("Paused • 'p' to resume"),
+    };
+
+    let stall = state.stall.filter(|_| !state.waiting_for_exit);
+    let paragraph = match (paused, stall) {
+        (Some(paused), _) => {
+            Paragraph::new(paused).style(Style::default().fg(Color::Yellow))
+        }
+        (None, Some(stall)) => {
+            let direction = match stall.direction {
+                BandwidthDirection::Download => "Download",
+                BandwidthDirection::Upload => "Upload",
+            };
+            Paragraph::new(format!(
+                "{} stalled: no data for {}s...",
+                direction, stall.idle_secs
+            ))
+            .style(Style::default().fg(Color::Yellow))
+        }
+        (None, None) => Paragraph::new(status_text).style(style),
+    };
     frame.render_widget(paragraph, area);
 }
 
@@ -642,31 +848,24 @@ fn render_minimal_phase(frame: &mut Frame, area: Rect, state: &TuiState) {
     let (phase_text, progress) = match state.phase {
         TestPhase::Initializing => ("◐ Init".to_string(), 0),
         TestPhase::Latency => {
-            let pct = if state.latency.total > 0 {
-                (state.latency.current * 100) / state.latency.total
-            } else {
-                0
-            };
+            let pct = (state.latency.current * 100)
+                .checked_div(state.latency.total)
+                .unwrap_or(0);
             (format!("▶ Latency {}%", pct), pct)
         }
         TestPhase::Download => {
-            let pct = if state.download.total_measurements > 0 {
-                (state.download.current_measurement * 100)
-                    / state.download.total_measurements
-            } else {
-                0
-            };
+            let pct = (state.download.current_measurement * 100)
+                .checked_div(state.download.total_measurements)
+                .unwrap_or(0);
             (format!("▶ Download {}%", pct), pct)
         }
         TestPhase::Upload => {
-            let pct = if state.upload.total_measurements > 0 {
-                (state.upload.current_measurement * 100)
-                    / state.upload.total_measurements
-            } else {
-                0
-            };
+            let pct = (state.upload.current_measurement * 100)
+                .checked_div(state.upload.total_measurements)
+                .unwrap_or(0);
             (format!("▶ Upload {}%", pct), pct)
         }
+        TestPhase::Duplex => ("▶ Duplex".to_string(), 0),
         TestPhase::Complete => ("✓ Done".to_string(), 100),
     };
 
@@ -732,6 +931,146 @@ fn render_minimal_results(frame: &mut Frame, area: Rect, state: &TuiState) {
     frame.render_widget(paragraph, area);
 }
 
+/// Render the latency monitor: the latest figures, a sparkline of recent
+/// probes and totals for the session.
+fn render_ping_frame(frame: &mut Frame, state: &TuiState, ping: &PingView) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1), // Title
+            Constraint::Length(5), // Latest figures
+            Constraint::Min(4),    // Sparkline
+            Constraint::Length(1), // Session totals
+            Constraint::Length(1), // Status bar
+        ])
+        .split(frame.area());
+
+    let mut title = vec![
+        Span::styled("☁ ", Style::default().fg(Color::Cyan)),
+        Span::styled(
+            "Latency Monitor",
+            Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
+        ),
+    ];
+    if let Some(ref server) = state.server {
+        title.push(Span::styled(
+            format!("  {} ({})", server.city, server.iata),
+            Style::default().fg(Color::Cyan),
+        ));
+    }
+    frame.render_widget(Paragraph::new(Line::from(title)), chunks[0]);
+
+    let stats = &ping.stats;
+    let boxes = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Ratio(1, 4); 4])
+        .split(chunks[1]);
+    render_metric_box(
+        frame,
+        boxes[0],
+        "Latest",
+        stats.last_ms(),
+        "ms",
+        true,
+        latency_color,
+    );
+    render_metric_box(
+        frame,
+        boxes[1],
+        "Median",
+        stats.window_median_ms(),
+        "ms",
+        false,
+        latency_color,
+    );
+    render_metric_box(
+        frame,
+        boxes[2],
+        "Jitter",
+        stats.window_jitter_ms(),
+        "ms",
+        false,
+        jitter_color,
+    );
+    render_metric_box(
+        frame,
+        boxes[3],
+        "Loss",
+        (stats.sent() > 0).then(|| stats.loss_ratio() * 100.0),
+        "%",
+        false,
+        |loss| if loss > 0.0 { Color::Red } else { Color::Green },
+    );
+
+    render_ping_graph(frame, chunks[2], ping);
+
+    let summary = stats.summary();
+    let totals = match (summary.min_ms, summary.avg_ms, summary.max_ms) {
+        (Some(min), Some(avg), Some(max)) => format!(
+            "{} sent, {} received • min/avg/max {:.1}/{:.1}/{:.1} ms",
+            summary.sent, summary.received, min, avg, max
+        ),
+        _ => format!("{} sent, {} received", summary.sent, summary.received),
+    };
+    frame.render_widget(
+        Paragraph::new(totals).style(Style::default().fg(Color::White)),
+        chunks[3],
+    );
+
+    let status = format!(
+        "Probing every {:.1}s • 'q' or Ctrl+C to stop",
+        ping.interval.as_secs_f64()
+    );
+    frame.render_widget(
+        Paragraph::new(status).style(Style::default().fg(Color::DarkGray)),
+        chunks[4],
+    );
+}
+
+/// Render a sparkline of the latency monitor's recent probes.
+fn render_ping_graph(frame: &mut Frame, area: Rect, ping: &PingView) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::DarkGray))
+        .title(Span::styled(
+            format!(" Latency (last {} probes) ", ping.stats.recent().len()),
+            Style::default().fg(Color::White),
+        ));
+
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let recent = ping.stats.recent();
+    if recent.is_empty() {
+        let placeholder = Paragraph::new("Waiting for data...")
+            .style(Style::default().fg(Color::DarkGray))
+            .alignment(ratatui::layout::Alignment::Center);
+        frame.render_widget(placeholder, inner);
+        return;
+    }
+
+    // Newest probes on the right, as many as fit
+    let max_ms = recent.iter().fold(0.0f64, |a, &b| a.max(b));
+    let data: Vec<u64> = recent
+        .iter()
+        .skip(recent.len().saturating_sub(inner.width as usize))
+        .map(
+            |&ms| {
+                if max_ms > 0.0 {
+                    ((ms / max_ms) * 100.0) as u64
+                } else {
+                    0
+                }
+            },
+        )
+        .collect();
+
+    let sparkline = Sparkline::default()
+        .data(&data)
+        .style(Style::default().fg(Color::Cyan));
+    frame.render_widget(sparkline, inner);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -824,4 +1163,31 @@ mod tests {
         assert_eq!(quality_color(&QualityRating::Average), Color::Yellow);
         assert_eq!(quality_color(&QualityRating::Poor), Color::Red);
     }
+
+    #[test]
+    fn test_fit_to_ascii_terminal() {
+        use ratatui::{backend::TestBackend, Terminal};
+
+        let mut state = TuiState::new();
+        state.capabilities = TerminalCapabilities::FULL.ascii();
+        state.set_warning("Captive portal?".to_string());
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| render_frame(frame, &state)).unwrap();
+        let buffer = terminal.backend().buffer();
+
+        assert!(buffer.content.iter().all(|cell| cell.symbol().is_ascii()));
+        assert!(buffer.content.iter().all(|cell| cell.fg != Color::DarkGray));
+        let top: String =
+            buffer.content[..100].iter().map(|cell| cell.symbol()).collect();
+        assert!(top.starts_with("* Speed Test  ! Captive portal?"));
+        assert_eq!(ascii_symbol("▇"), "#");
+        assert_eq!(ascii_symbol("é"), "?");
+    }
+
+    #[test]
+    fn test_grade_color() {
+        assert_eq!(grade_color(Grade::A), Color::Green);
+        assert_eq!(grade_color(Grade::F), Color::Red);
+    }
 }
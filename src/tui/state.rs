@@ -3,7 +3,11 @@
 //! Holds all state needed for rendering the TUI, including
 //! connection metadata, test progress, and results.
 
+use super::capabilities::TerminalCapabilities;
 use super::progress::{BandwidthDirection, ProgressEvent, TestPhase};
+use crate::cloudflare::tests::engine::PhaseSelection;
+use crate::ping::PingStats;
+use crate::scoring::{AimScoreDetails, GradeSummary, QualityScore};
 use crate::stats::median_f64;
 
 /// Server location information.
@@ -37,6 +41,30 @@ pub struct ErrorInfo {
     pub suggestion: Option<String>,
 }
 
+/// A transfer that has stopped moving data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StallInfo {
+    /// Direction of the transfer
+    pub direction: BandwidthDirection,
+    /// Bytes the transfer was requested to move
+    pub bytes: u64,
+    /// Seconds since the transfer last moved data
+    pub idle_secs: u64,
+}
+
+/// Whether the user has paused the test.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PauseState {
+    /// The test is running
+    #[default]
+    Running,
+    /// A pause was requested and takes effect after the current
+    /// measurement
+    Pausing,
+    /// The test is holding between measurements
+    Paused,
+}
+
 /// Latency measurement state.
 #[derive(Debug, Clone, Default)]
 pub struct LatencyState {
@@ -58,6 +86,10 @@ pub struct LatencyState {
     pub loaded_up_ms: Option<f64>,
     /// Loaded jitter during upload (ms)
     pub loaded_up_jitter_ms: Option<f64>,
+    /// Median server time to first byte (ms)
+    pub ttfb_median_ms: Option<f64>,
+    /// 95th percentile server time to first byte (ms)
+    pub ttfb_p95_ms: Option<f64>,
 }
 
 impl LatencyState {
@@ -128,12 +160,27 @@ impl QualityRating {
     }
 }
 
+impl From<QualityScore> for QualityRating {
+    fn from(score: QualityScore) -> Self {
+        match score {
+            QualityScore::Great => QualityRating::Great,
+            QualityScore::Good => QualityRating::Good,
+            QualityScore::Average => QualityRating::Average,
+            QualityScore::Poor => QualityRating::Poor,
+        }
+    }
+}
+
 /// Network quality scores for different use cases.
 #[derive(Debug, Clone, Default)]
 pub struct QualityScores {
     pub streaming: Option<QualityRating>,
     pub gaming: Option<QualityRating>,
     pub video_conferencing: Option<QualityRating>,
+    /// Per-factor breakdown behind each score
+    pub details: Option<AimScoreDetails>,
+    /// Letter grade for the whole test
+    pub grade: Option<GradeSummary>,
 }
 
 /// State for the TUI display.
@@ -155,16 +202,45 @@ pub struct TuiState {
     pub quality_scores: QualityScores,
     /// Error message if any
     pub error: Option<ErrorInfo>,
+    /// Non-fatal warning shown in the header, if any
+    pub warning: Option<String>,
+    /// Transfer currently stalled, shown in the status bar
+    pub stall: Option<StallInfo>,
+    /// Whether the user has paused the test
+    pub pause: PauseState,
     /// Terminal width for layout
     pub terminal_width: u16,
     /// Terminal height for layout
     pub terminal_height: u16,
+    /// What the terminal can display
+    pub capabilities: TerminalCapabilities,
     /// Whether the test is complete and waiting for user to exit
     pub waiting_for_exit: bool,
     /// Timestamp when test started (for graph x-axis)
     pub test_start_time: std::time::Instant,
     /// Whether a retest has been requested
     pub retest_requested: bool,
+    /// Whether the quality score panel shows the per-factor breakdown
+    pub show_score_details: bool,
+    /// Phases the test runs; skipped directions and the scores they
+    /// feed are hidden
+    pub phases: PhaseSelection,
+    /// Latency monitor shown instead of the speed test, with `ping`
+    pub ping: Option<PingView>,
+    /// Whether the user asked to stop with 'q', Esc or Ctrl+C
+    pub stop_requested: bool,
+    /// Outcome of the last action on the results, e.g. copying them,
+    /// shown in the status bar
+    pub notice: Option<String>,
+}
+
+/// Live state of the latency monitor.
+#[derive(Debug, Clone)]
+pub struct PingView {
+    /// Statistics so far
+    pub stats: PingStats,
+    /// Time between probes
+    pub interval: std::time::Duration,
 }
 
 impl Default for TuiState {
@@ -178,11 +254,20 @@ impl Default for TuiState {
             upload: BandwidthState::default(),
             quality_scores: QualityScores::default(),
             error: None,
+            warning: None,
+            stall: None,
+            pause: PauseState::Running,
             terminal_width: 80,
             terminal_height: 24,
+            capabilities: TerminalCapabilities::FULL,
             waiting_for_exit: false,
             test_start_time: std::time::Instant::now(),
             retest_requested: false,
+            show_score_details: false,
+            phases: PhaseSelection::default(),
+            ping: None,
+            stop_requested: false,
+            notice: None,
         }
     }
 }
@@ -208,6 +293,11 @@ impl TuiState {
         self.error = Some(ErrorInfo { message, suggestion });
     }
 
+    /// Set a non-fatal warning, e.g. a suspected captive portal.
+    pub fn set_warning(&mut self, warning: String) {
+        self.warning = Some(warning);
+    }
+
     /// Set quality scores from scoring results.
     pub fn set_quality_scores(
         &mut self,
@@ -221,11 +311,45 @@ impl TuiState {
             Some(parse_quality_rating(video_conferencing));
     }
 
+    /// Set the per-factor breakdown behind the quality scores.
+    pub fn set_score_details(&mut self, details: AimScoreDetails) {
+        self.quality_scores.details = Some(details);
+    }
+
+    /// Set the phases the test runs.
+    pub fn set_phases(&mut self, phases: PhaseSelection) {
+        self.phases = phases;
+    }
+
+    /// Set the letter grade shown in the header.
+    pub fn set_grade(&mut self, grade: GradeSummary) {
+        self.quality_scores.grade = Some(grade);
+    }
+
+    /// Record that the user turned the pause switch on or off.
+    pub fn request_pause(&mut self, paused: bool) {
+        self.pause = match (paused, self.pause) {
+            (true, PauseState::Running) => PauseState::Pausing,
+            (true, pause) => pause,
+            (false, _) => PauseState::Running,
+        };
+    }
+
+    /// Expand or collapse the quality score breakdown.
+    ///
+    /// Does nothing until a breakdown is available.
+    pub fn toggle_score_details(&mut self) {
+        if self.quality_scores.details.is_some() {
+            self.show_score_details = !self.show_score_details;
+        }
+    }
+
     /// Update state from a progress event.
     pub fn update_from_event(&mut self, event: &ProgressEvent) {
         match event {
             ProgressEvent::PhaseChange(phase) => {
                 self.phase = *phase;
+                self.stall = None;
             }
             ProgressEvent::LatencyMeasurement { value_ms, current, total } => {
                 self.latency.measurements.push(*value_ms);
@@ -243,16 +367,29 @@ impl TuiState {
                     BandwidthDirection::Download => &mut self.download,
                     BandwidthDirection::Upload => &mut self.upload,
                 };
+                self.stall = None;
                 state.current_speed_mbps = Some(*speed_mbps);
                 state.current_bytes = *bytes;
                 state.current_measurement = *current;
                 state.total_measurements = *total;
 
                 // Add to speed history for graph
-                state.speed_history.push(SpeedSample {
-                    speed_mbps: *speed_mbps,
+                state
+                    .speed_history
+                    .push(SpeedSample { speed_mbps: *speed_mbps });
+            }
+            // Summaries of what the measurement events already showed
+            ProgressEvent::LatencyComplete { .. }
+            | ProgressEvent::SizeBlockComplete { .. } => {}
+            ProgressEvent::Stalled { direction, bytes, idle_secs } => {
+                self.stall = Some(StallInfo {
+                    direction: *direction,
+                    bytes: *bytes,
+                    idle_secs: *idle_secs,
                 });
             }
+            ProgressEvent::Paused => self.pause = PauseState::Paused,
+            ProgressEvent::Resumed => self.pause = PauseState::Running,
             ProgressEvent::PhaseComplete(phase) => {
                 match phase {
                     TestPhase::Latency => {
@@ -275,11 +412,14 @@ impl TuiState {
                                 .map(|s| s.speed_mbps)
                                 .collect();
                             speeds.sort_by(|a, b| a.total_cmp(b));
-                            let idx = ((speeds.len() as f64 * 0.9).ceil() as usize)
+                            let idx = ((speeds.len() as f64 * 0.9).ceil()
+                                as usize)
                                 .saturating_sub(1)
                                 .min(speeds.len() - 1);
                             self.download.percentile_90 = Some(speeds[idx]);
-                        } else if let Some(speed) = self.download.final_speed_mbps {
+                        } else if let Some(speed) =
+                            self.download.final_speed_mbps
+                        {
                             // Fallback to final speed if no history
                             self.download.percentile_90 = Some(speed);
                         }
@@ -297,11 +437,14 @@ impl TuiState {
                                 .map(|s| s.speed_mbps)
                                 .collect();
                             speeds.sort_by(|a, b| a.total_cmp(b));
-                            let idx = ((speeds.len() as f64 * 0.9).ceil() as usize)
+                            let idx = ((speeds.len() as f64 * 0.9).ceil()
+                                as usize)
                                 .saturating_sub(1)
                                 .min(speeds.len() - 1);
                             self.upload.percentile_90 = Some(speeds[idx]);
-                        } else if let Some(speed) = self.upload.final_speed_mbps {
+                        } else if let Some(speed) =
+                            self.upload.final_speed_mbps
+                        {
                             // Fallback to final speed if no history
                             self.upload.percentile_90 = Some(speed);
                         }
@@ -322,6 +465,27 @@ fn parse_quality_rating(s: &str) -> QualityRating {
     }
 }
 
+impl TuiState {
+    /// Reset state for a retest, preserving server/connection info and
+    /// the selected phases.
+    pub fn reset_for_retest(&mut self) {
+        self.phase = TestPhase::Initializing;
+        self.latency = LatencyState::default();
+        self.download = BandwidthState::default();
+        self.upload = BandwidthState::default();
+        self.quality_scores = QualityScores::default();
+        self.error = None;
+        self.warning = None;
+        self.stall = None;
+        self.pause = PauseState::Running;
+        self.waiting_for_exit = false;
+        self.test_start_time = std::time::Instant::now();
+        self.retest_requested = false;
+        self.show_score_details = false;
+        self.notice = None;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -368,6 +532,63 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_set_warning_cleared_on_retest() {
+        let mut state = TuiState::new();
+        state.set_warning("Captive portal suspected".to_string());
+        assert_eq!(state.warning.as_deref(), Some("Captive portal suspected"));
+
+        state.reset_for_retest();
+        assert!(state.warning.is_none());
+    }
+
+    #[test]
+    fn test_grade_cleared_on_retest() {
+        use crate::scoring::Grade;
+
+        let mut state = TuiState::new();
+        state.set_grade(GradeSummary {
+            grade: Grade::B,
+            explanation: "Held back by gaming (good)".to_string(),
+        });
+        assert_eq!(
+            state.quality_scores.grade.as_ref().map(|g| g.grade),
+            Some(Grade::B)
+        );
+
+        state.reset_for_retest();
+        assert!(state.quality_scores.grade.is_none());
+    }
+
+    #[test]
+    fn test_toggle_score_details() {
+        use crate::scoring::{
+            calculate_aim_score_details_with, ConnectionMetrics,
+            ScoringThresholds,
+        };
+
+        let mut state = TuiState::new();
+
+        // Nothing to expand before scores are known
+        state.toggle_score_details();
+        assert!(!state.show_score_details);
+
+        let metrics = ConnectionMetrics::new(100.0, 50.0, 15.0, 2.0);
+        state.set_score_details(calculate_aim_score_details_with(
+            &metrics,
+            &ScoringThresholds::default(),
+        ));
+        state.toggle_score_details();
+        assert!(state.show_score_details);
+        state.toggle_score_details();
+        assert!(!state.show_score_details);
+
+        state.toggle_score_details();
+        state.reset_for_retest();
+        assert!(!state.show_score_details);
+        assert!(state.quality_scores.details.is_none());
+    }
+
     #[test]
     fn test_update_from_phase_change() {
         let mut state = TuiState::new();
@@ -384,6 +605,48 @@ mod tests {
         assert_eq!(state.phase, TestPhase::Download);
     }
 
+    #[test]
+    fn test_stall_shown_until_next_measurement() {
+        let mut state = TuiState::new();
+
+        state.update_from_event(&ProgressEvent::Stalled {
+            direction: BandwidthDirection::Download,
+            bytes: 25_000_000,
+            idle_secs: 11,
+        });
+        assert_eq!(state.stall.map(|stall| stall.idle_secs), Some(11));
+
+        state.update_from_event(&ProgressEvent::BandwidthMeasurement {
+            direction: BandwidthDirection::Download,
+            speed_mbps: 95.5,
+            bytes: 25_000_000,
+            current: 3,
+            total: 8,
+        });
+        assert!(state.stall.is_none());
+    }
+
+    #[test]
+    fn test_pause_requested_then_confirmed() {
+        let mut state = TuiState::new();
+
+        state.request_pause(true);
+        assert_eq!(state.pause, PauseState::Pausing);
+        state.update_from_event(&ProgressEvent::Paused);
+        assert_eq!(state.pause, PauseState::Paused);
+        state.request_pause(true);
+        assert_eq!(state.pause, PauseState::Paused);
+
+        state.request_pause(false);
+        assert_eq!(state.pause, PauseState::Running);
+        state.update_from_event(&ProgressEvent::Resumed);
+        assert_eq!(state.pause, PauseState::Running);
+
+        state.request_pause(true);
+        state.reset_for_retest();
+        assert_eq!(state.pause, PauseState::Running);
+    }
+
     #[test]
     fn test_update_from_latency_measurement() {
         let mut state = TuiState::new();
@@ -528,18 +791,3 @@ mod tests {
         }
     }
 }
-
-impl TuiState {
-    /// Reset state for a retest, preserving server/connection info.
-    pub fn reset_for_retest(&mut self) {
-        self.phase = TestPhase::Initializing;
-        self.latency = LatencyState::default();
-        self.download = BandwidthState::default();
-        self.upload = BandwidthState::default();
-        self.quality_scores = QualityScores::default();
-        self.error = None;
-        self.waiting_for_exit = false;
-        self.test_start_time = std::time::Instant::now();
-        self.retest_requested = false;
-    }
-}
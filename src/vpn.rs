@@ -0,0 +1,308 @@
+//! Detection of VPNs and tunnels in the test path.
+//!
+//! A speed test through a VPN measures the tunnel and its exit server, not
+//! the local connection, which is worth flagging next to the results. No
+//! single signal is conclusive, so several are combined:
+//!
+//! - the default route leaves through a tunnel-style interface (`wg0`,
+//!   `tun0`, `utun3`, ...), read from `/proc/net/route` on Linux
+//! - the egress network reported by Cloudflare belongs to a VPN provider
+//! - a reduced MTU (on the route's interface, or the discovered path MTU)
+//!   from a hosting network, as tunnels carry their own headers
+//!
+//! Cloudflare WARP is recognized for certain: its traffic reaches the
+//! speed test from Cloudflare's own network.
+
+use serde::Serialize;
+
+/// Cloudflare's network, which WARP traffic leaves from.
+pub const WARP_ASN: i64 = 13335;
+
+/// MTU of an untunnelled Ethernet path.
+const ETHERNET_MTU: usize = 1500;
+
+/// Interface name prefixes used by tunnel drivers and VPN clients.
+const TUNNEL_PREFIXES: &[&str] = &[
+    "tun",
+    "tap",
+    "wg",
+    "utun",
+    "ipsec",
+    "vti",
+    "tailscale",
+    "zt",
+    "nordlynx",
+    "proton",
+    "mullvad",
+    "cscotun",
+    "gpd",
+];
+
+/// Fragments of network names belonging to consumer VPN services, or to
+/// the hosting companies their exit servers commonly run in.
+const VPN_NETWORKS: &[&str] = &[
+    "mullvad",
+    "nordvpn",
+    "expressvpn",
+    "proton",
+    "private internet access",
+    "surfshark",
+    "cyberghost",
+    "ipvanish",
+    "windscribe",
+    "m247",
+    "datacamp",
+    "cloudflare",
+];
+
+/// Fragments of network names belonging to cloud and hosting providers.
+const HOSTING_NETWORKS: &[&str] = &[
+    "amazon",
+    "google",
+    "microsoft",
+    "digitalocean",
+    "linode",
+    "akamai",
+    "hetzner",
+    "ovh",
+    "vultr",
+    "choopa",
+    "leaseweb",
+    "oracle",
+];
+
+/// Whether a VPN or tunnel looks to be in use, and why.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VpnDetection {
+    /// Whether the signals add up to a likely VPN
+    pub suspected: bool,
+    /// Human-readable reasons, including ones too weak on their own
+    pub signals: Vec<String>,
+}
+
+/// Look for signs of a VPN using local routing information, the egress
+/// network Cloudflare reported (`as_organization`), and the path MTU if
+/// one was discovered.
+pub fn detect(
+    as_organization: Option<&str>,
+    path_mtu: Option<usize>,
+) -> VpnDetection {
+    let interface = default_route_interface();
+    let interface_mtu = interface.as_deref().and_then(interface_mtu);
+    let mtu = match (interface_mtu, path_mtu) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+
+    assess(interface.as_deref(), mtu, as_organization)
+}
+
+/// Whether the egress network Cloudflare reported (`asn`) is its own,
+/// i.e. the test would measure WARP rather than the ISP.
+pub fn is_warp(asn: i64) -> bool {
+    asn == WARP_ASN
+}
+
+/// Combine the individual signals into a verdict.
+fn assess(
+    interface: Option<&str>,
+    mtu: Option<usize>,
+    as_organization: Option<&str>,
+) -> VpnDetection {
+    let mut detection = VpnDetection::default();
+
+    if let Some(interface) = interface.filter(|i| is_tunnel_interface(i)) {
+        detection.suspected = true;
+        detection.signals.push(format!(
+            "default route uses tunnel interface {}",
+            interface
+        ));
+    }
+
+    let network = as_organization.map(|org| (org, org.to_lowercase()));
+    if let Some((org, _)) =
+        network.as_ref().filter(|(_, lower)| matches_any(lower, VPN_NETWORKS))
+    {
+        detection.suspected = true;
+        detection
+            .signals
+            .push(format!("egress network {} runs VPN exits", org));
+    }
+
+    let reduced_mtu = mtu.filter(|&mtu| mtu < ETHERNET_MTU);
+    if let Some(mtu) = reduced_mtu {
+        detection.signals.push(format!("MTU reduced to {} bytes", mtu));
+    }
+
+    if let Some((org, _)) = network
+        .as_ref()
+        .filter(|(_, lower)| matches_any(lower, HOSTING_NETWORKS))
+    {
+        detection
+            .signals
+            .push(format!("egress network {} is a hosting provider", org));
+        // A cloud VM tests from a hosting network too; only a tunnel's
+        // MTU overhead makes that look like a VPN exit
+        if reduced_mtu.is_some() {
+            detection.suspected = true;
+        }
+    }
+
+    detection
+}
+
+fn matches_any(name: &str, fragments: &[&str]) -> bool {
+    fragments.iter().any(|fragment| name.contains(fragment))
+}
+
+/// Whether `name` looks like a tunnel or VPN client interface.
+fn is_tunnel_interface(name: &str) -> bool {
+    let name = name.to_lowercase();
+    TUNNEL_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+/// Interface of the IPv4 default route with the lowest metric.
+#[cfg(target_os = "linux")]
+fn default_route_interface() -> Option<String> {
+    let table = std::fs::read_to_string("/proc/net/route").ok()?;
+    parse_default_route(&table)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn default_route_interface() -> Option<String> {
+    None
+}
+
+/// MTU of the interface `name`.
+#[cfg(target_os = "linux")]
+fn interface_mtu(name: &str) -> Option<usize> {
+    std::fs::read_to_string(format!("/sys/class/net/{}/mtu", name))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn interface_mtu(_name: &str) -> Option<usize> {
+    None
+}
+
+/// Find the default route's interface in `/proc/net/route` contents.
+///
+/// WireGuard and OpenVPN often install two /1 routes instead of
+/// replacing the default route, so those count as default too.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_default_route(table: &str) -> Option<String> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (interface, mask, metric) =
+                (fields.first()?, fields.get(7)?, fields.get(6)?);
+            let mask = u32::from_str_radix(mask, 16).ok()?;
+            // Masks are little-endian; 0 is /0 and 0x80 is /1
+            (mask == 0 || mask == 0x80).then(|| {
+                (mask, metric.parse::<u32>().unwrap_or(0), *interface)
+            })
+        })
+        // Longer (/1) routes win over the default, then lower metrics
+        .min_by_key(|&(mask, metric, _)| (u32::MAX - mask, metric))
+        .map(|(_, _, interface)| interface.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTE_HEADER: &str = "Iface\tDestination\tGateway \tFlags\t\
+        RefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n";
+
+    #[test]
+    fn test_parse_default_route() {
+        let table = format!(
+            "{}eth0\t00000000\t010200C0\t0003\t0\t0\t100\t00000000\t0\t0\t0\n\
+             eth0\t000200C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0\n\
+             wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0\n",
+            ROUTE_HEADER
+        );
+        assert_eq!(parse_default_route(&table).as_deref(), Some("eth0"));
+
+        assert_eq!(parse_default_route(ROUTE_HEADER), None);
+    }
+
+    #[test]
+    fn test_parse_default_route_prefers_split_routes() {
+        // OpenVPN's def1: 0.0.0.0/1 and 128.0.0.0/1 via the tunnel
+        let table = format!(
+            "{}eth0\t00000000\t010200C0\t0003\t0\t0\t0\t00000000\t0\t0\t0\n\
+             tun0\t00000000\t0100080A\t0003\t0\t0\t0\t00000080\t0\t0\t0\n\
+             tun0\t00000080\t0100080A\t0003\t0\t0\t0\t00000080\t0\t0\t0\n",
+            ROUTE_HEADER
+        );
+        assert_eq!(parse_default_route(&table).as_deref(), Some("tun0"));
+    }
+
+    #[test]
+    fn test_is_tunnel_interface() {
+        for name in ["wg0", "tun0", "utun3", "tailscale0", "WG1"] {
+            assert!(is_tunnel_interface(name), "{}", name);
+        }
+        // ppp0 is usually PPPoE rather than a VPN
+        for name in ["eth0", "enp3s0", "wlan0", "en0", "bond0", "ppp0"] {
+            assert!(!is_tunnel_interface(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_assess_tunnel_interface() {
+        let detection = assess(Some("wg0"), Some(1420), Some("Comcast"));
+        assert!(detection.suspected);
+        assert_eq!(
+            detection.signals,
+            [
+                "default route uses tunnel interface wg0",
+                "MTU reduced to 1420 bytes"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assess_vpn_network() {
+        let detection = assess(Some("eth0"), Some(1500), Some("M247 Ltd"));
+        assert!(detection.suspected);
+        assert_eq!(
+            detection.signals,
+            ["egress network M247 Ltd runs VPN exits"]
+        );
+    }
+
+    #[test]
+    fn test_assess_hosting_network_needs_reduced_mtu() {
+        // A cloud VM is not a VPN
+        let vm = assess(Some("ens5"), Some(1500), Some("Amazon.com, Inc."));
+        assert!(!vm.suspected);
+        assert_eq!(vm.signals.len(), 1);
+
+        let exit = assess(Some("eth0"), Some(1420), Some("DigitalOcean, LLC"));
+        assert!(exit.suspected);
+    }
+
+    #[test]
+    fn test_assess_plain_connection() {
+        let detection = assess(Some("eth0"), Some(1500), Some("Comcast"));
+        assert_eq!(detection, VpnDetection::default());
+
+        // PPPoE lowers the MTU without a VPN
+        let pppoe = assess(Some("eth0"), Some(1492), Some("Deutsche Telekom"));
+        assert!(!pppoe.suspected);
+        assert_eq!(pppoe.signals, ["MTU reduced to 1492 bytes"]);
+    }
+
+    #[test]
+    fn test_is_warp() {
+        assert!(is_warp(13335));
+        assert!(!is_warp(7922));
+    }
+}